@@ -2,11 +2,12 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt;
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct QualifiedId {
     pub base_id: Identifier,
     pub quals: Vec<String>,
@@ -66,19 +67,23 @@ impl fmt::Display for QualifiedId {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AttributeValue {
     Identifier(Identifier),
     String(String),
+    Integer(u32),
     None,
 }
 
 pub type Attributes = HashMap<String, (Location, AttributeValue)>;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct TypeSpec {
     pub loc: Location,
     pub spec: String,
+    // The arguments of a C++ template instance, like `A' and `B' in
+    // `Variant<A, B>'. The spec includes them too.
+    pub template_args: Vec<TypeSpec>,
     pub array: bool,
     pub maybe: bool,
     pub nullable: bool,
@@ -90,6 +95,7 @@ impl TypeSpec {
         TypeSpec {
             loc: id.loc,
             spec: id.id,
+            template_args: Vec::new(),
             array: false,
             maybe: false,
             nullable: false,
@@ -99,6 +105,11 @@ impl TypeSpec {
 
     // XXX Get rid of these setters if the fields are just public anyways?
 
+    pub fn set_template_args(mut self, template_args: Vec<TypeSpec>) -> TypeSpec {
+        self.template_args = template_args;
+        self
+    }
+
     pub fn set_array(mut self, is_array: bool) -> TypeSpec {
         self.array = is_array;
         self
@@ -124,7 +135,30 @@ impl TypeSpec {
     }
 }
 
-#[derive(Debug)]
+// How the type is written in IPDL, like `nullable PFoo[]' or
+// `UniquePtr<Bar>?'. `nullable' always comes first, because it applies to
+// the elements of an array.
+impl fmt::Display for TypeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.nullable {
+            write!(f, "nullable ")?;
+        }
+        if self.uniqueptr {
+            write!(f, "UniquePtr<{}>", self.spec)?;
+        } else {
+            write!(f, "{}", self.spec)?;
+        }
+        if self.array {
+            write!(f, "[]")?;
+        }
+        if self.maybe {
+            write!(f, "?")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Param {
     pub attributes: Attributes,
     pub name: Identifier,
@@ -141,7 +175,7 @@ impl Param {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct StructField {
     pub type_spec: TypeSpec,
     pub name: Identifier,
@@ -189,6 +223,8 @@ pub enum Compress {
     All,
 }
 
+// The parser doesn't produce these any more: `verify' was removed from the
+// language, and compression is given by the [Compress] attribute.
 #[derive(Debug)]
 pub enum MessageModifier {
     Verify,
@@ -237,7 +273,7 @@ impl Nesting {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Priority {
     Normal,
     Input,
@@ -327,7 +363,7 @@ impl fmt::Display for Identifier {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct MessageDecl {
     pub name: Identifier,
     pub attributes: Attributes,
@@ -357,7 +393,7 @@ impl MessageDecl {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Protocol {
     pub attributes: Attributes,
     pub send_semantics: SendSemantics,
@@ -384,16 +420,29 @@ impl Protocol {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CxxTypeKind {
     Struct,
     Class,
 }
 
-#[derive(Debug)]
+impl fmt::Display for CxxTypeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CxxTypeKind::Struct => write!(f, "struct"),
+            CxxTypeKind::Class => write!(f, "class"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct UsingStmt {
     pub cxx_type: QualifiedId,
+    // The template arguments of the type, if it is a template instance.
+    pub template_args: Vec<TypeSpec>,
     pub header: String,
+    // Whether the type was imported with `using class' or `using struct',
+    // which determines how it is forward declared. None for a bare `using'.
     pub kind: Option<CxxTypeKind>,
     pub attributes: Attributes,
 }
@@ -418,18 +467,228 @@ impl FileType {
     }
 }
 
-// Translation unit identifier.
+// Translation unit identifier. These are handed out counting up from 0, in
+// the order that the files are found, so they can index a TUMap.
 pub type TUId = i32;
 
-#[derive(Debug)]
+// Something for each translation unit, like the unit itself or its types.
+// This is a Vec indexed by TUId, rather than a HashMap, so lookups don't
+// hash, and iteration is in the order that the files were found. Each entry
+// keeps its TUId, so that iterating gives references to them, as with a
+// HashMap.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TUMap<T> {
+    entries: Vec<Option<(TUId, T)>>,
+    len: usize,
+}
+
+impl<T> TUMap<T> {
+    pub fn new() -> TUMap<T> {
+        TUMap {
+            entries: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, tuid: TUId, value: T) -> Option<T> {
+        let index = usize::try_from(tuid).expect("TUIds are never negative");
+        if index >= self.entries.len() {
+            self.entries.resize_with(index + 1, || None);
+        }
+        let old = self.entries[index].replace((tuid, value)).map(|(_, v)| v);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn remove(&mut self, tuid: &TUId) -> Option<T> {
+        let index = usize::try_from(*tuid).ok()?;
+        let old = self.entries.get_mut(index)?.take().map(|(_, v)| v);
+        if old.is_some() {
+            self.len -= 1;
+        }
+        old
+    }
+
+    pub fn get(&self, tuid: &TUId) -> Option<&T> {
+        let index = usize::try_from(*tuid).ok()?;
+        self.entries.get(index)?.as_ref().map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, tuid: &TUId) -> Option<&mut T> {
+        let index = usize::try_from(*tuid).ok()?;
+        self.entries.get_mut(index)?.as_mut().map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, tuid: &TUId) -> bool {
+        self.get(tuid).is_some()
+    }
+
+    pub fn retain<F: FnMut(&TUId, &mut T) -> bool>(&mut self, mut keep: F) {
+        for entry in &mut self.entries {
+            if let Some((tuid, value)) = entry {
+                if !keep(tuid, value) {
+                    *entry = None;
+                    self.len -= 1;
+                }
+            }
+        }
+    }
+
+    pub fn iter(&self) -> TUMapIter<'_, T> {
+        fn split<T>(entry: &(TUId, T)) -> (&TUId, &T) {
+            (&entry.0, &entry.1)
+        }
+        self.entries.iter().flatten().map(split)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&TUId, &mut T)> {
+        self.entries
+            .iter_mut()
+            .flatten()
+            .map(|(tuid, v)| (&*tuid, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &TUId> {
+        self.iter().map(|(tuid, _)| tuid)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.iter_mut().map(|(_, v)| v)
+    }
+
+    pub fn into_values(self) -> impl Iterator<Item = T> {
+        self.into_iter().map(|(_, v)| v)
+    }
+}
+
+impl<T> Default for TUMap<T> {
+    fn default() -> TUMap<T> {
+        TUMap::new()
+    }
+}
+
+impl<T> std::ops::Index<&TUId> for TUMap<T> {
+    type Output = T;
+
+    fn index(&self, tuid: &TUId) -> &T {
+        self.get(tuid).expect("no entry for TUId")
+    }
+}
+
+impl<T> std::ops::IndexMut<&TUId> for TUMap<T> {
+    fn index_mut(&mut self, tuid: &TUId) -> &mut T {
+        self.get_mut(tuid).expect("no entry for TUId")
+    }
+}
+
+impl<T> std::iter::FromIterator<(TUId, T)> for TUMap<T> {
+    fn from_iter<I: IntoIterator<Item = (TUId, T)>>(iter: I) -> TUMap<T> {
+        let mut map = TUMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<T> Extend<(TUId, T)> for TUMap<T> {
+    fn extend<I: IntoIterator<Item = (TUId, T)>>(&mut self, iter: I) {
+        for (tuid, value) in iter {
+            self.insert(tuid, value);
+        }
+    }
+}
+
+impl<T> IntoIterator for TUMap<T> {
+    type Item = (TUId, T);
+    type IntoIter = std::iter::Flatten<std::vec::IntoIter<Option<(TUId, T)>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter().flatten()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a TUMap<T> {
+    type Item = (&'a TUId, &'a T);
+    type IntoIter = TUMapIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub type TUMapIter<'a, T> = std::iter::Map<
+    std::iter::Flatten<std::slice::Iter<'a, Option<(TUId, T)>>>,
+    fn(&'a (TUId, T)) -> (&'a TUId, &'a T),
+>;
+
+#[derive(Clone, Debug)]
 pub struct TranslationUnit {
     pub namespace: Namespace,
     pub file_type: FileType,
     pub file_name: PathBuf,
-    pub cxx_includes: Vec<String>,
+    // The C++ headers from `include "foo.h";' statements, with the quotes.
+    pub cxx_includes: Vec<Identifier>,
     pub includes: Vec<TUId>,
+    // The file names from the include statements, like `PFoo.ipdl`, in the
+    // same order as |includes|.
+    pub include_files: Vec<Identifier>,
+    // The `header' of a `header;' statement, which says that the file is
+    // meant to be a header, so that a header that is given a protocol
+    // file's name by mistake is caught.
+    pub header_marker: Option<Identifier>,
     pub using: Vec<UsingStmt>,
     pub structs: Vec<(Namespace, Attributes, Vec<StructField>)>,
     pub unions: Vec<(Namespace, Attributes, Vec<TypeSpec>)>,
     pub protocol: Option<(Namespace, Protocol)>,
+    // The `#if' sections of the file. Everything in the file is parsed,
+    // whatever the conditions are.
+    pub conditions: Vec<ConditionalSection>,
+}
+
+// The condition of an `#if', `#ifdef' or `#ifndef' directive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Defined(String),
+    // A number, like in `#if 0'.
+    Constant(bool),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    // Whether the condition holds when exactly `defines' are defined.
+    pub fn eval(&self, defines: &HashSet<String>) -> bool {
+        match self {
+            Condition::Defined(name) => defines.contains(name),
+            Condition::Constant(b) => *b,
+            Condition::Not(c) => !c.eval(defines),
+            Condition::And(a, b) => a.eval(defines) && b.eval(defines),
+            Condition::Or(a, b) => a.eval(defines) || b.eval(defines),
+        }
+    }
+}
+
+// The lines of a file, from first_line to last_line inclusive, that are
+// only used when a condition holds. For an `#else' or `#elif' branch, the
+// condition includes the negation of the earlier branches. Sections can be
+// nested, so a line may be in more than one section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionalSection {
+    pub condition: Condition,
+    pub first_line: usize,
+    pub last_line: usize,
 }
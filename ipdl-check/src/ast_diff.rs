@@ -0,0 +1,419 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// A structural diff of two versions of a file, for code review tools.
+//
+// The files are compared declaration by declaration, so changes to
+// whitespace, comments and the order of attributes don't show up. Structs,
+// unions, messages and imported types are matched up by name, so a renamed
+// one is reported as removed and added. The fields of structs and the
+// parameters of messages are compared in order, because the order is part
+// of how they are serialized.
+//
+// The uses of `[Deprecated]' protocols that the new version adds can be
+// found too, once the new version has been checked.
+
+use ast::{
+    AttributeValue, Attributes, Direction, Identifier, MessageDecl, Param, ProtocolSide,
+    QualifiedId, SendSemantics, StructField, TUId, TranslationUnit, TypeSpec,
+};
+use errors::Diagnostic;
+use ir::TypedProgram;
+use std::fmt;
+use type_check::{IPDLType, MessageType};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstChange {
+    pub kind: ChangeKind,
+    // What changed, like `message PFoo::Bar'.
+    pub item: String,
+    // How it changed, for a ChangeKind::Changed.
+    pub detail: Option<String>,
+}
+
+impl fmt::Display for AstChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mark = match self.kind {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Changed => '~',
+        };
+        write!(f, "{} {}", mark, self.item)?;
+        if let Some(detail) = &self.detail {
+            write!(f, ": {}", detail)?;
+        }
+        Ok(())
+    }
+}
+
+// The attributes, like `[Compress, Priority=input]', or an empty string.
+fn attributes_text(attributes: &Attributes) -> String {
+    let mut attributes = attributes
+        .iter()
+        .map(|(name, (_, value))| match value {
+            AttributeValue::Identifier(id) => format!("{}={}", name, id.id),
+            AttributeValue::String(s) => format!("{}=\"{}\"", name, s),
+            AttributeValue::Integer(n) => format!("{}={}", name, n),
+            AttributeValue::None => name.clone(),
+        })
+        .collect::<Vec<_>>();
+    if attributes.is_empty() {
+        return String::new();
+    }
+    attributes.sort();
+    format!("[{}]", attributes.join(", "))
+}
+
+// A qualified name without the leading `::'.
+fn name_text(qid: &QualifiedId) -> String {
+    qid.to_string().trim_start_matches("::").to_string()
+}
+
+fn semantics_text(s: SendSemantics) -> &'static str {
+    match s {
+        SendSemantics::Async => "async",
+        SendSemantics::Sync => "sync",
+        SendSemantics::Intr => "intr",
+    }
+}
+
+fn direction_text(d: Direction) -> &'static str {
+    match d {
+        Direction::To(ProtocolSide::Parent) => "parent",
+        Direction::To(ProtocolSide::Child) => "child",
+        Direction::Both => "both",
+    }
+}
+
+struct Differ {
+    changes: Vec<AstChange>,
+}
+
+impl Differ {
+    fn push(&mut self, kind: ChangeKind, item: &str, detail: Option<String>) {
+        self.changes.push(AstChange {
+            kind,
+            item: String::from(item),
+            detail,
+        });
+    }
+
+    fn changed<T: PartialEq + fmt::Display>(&mut self, item: &str, what: &str, old: T, new: T) {
+        if old != new {
+            let detail = format!("{} changed from `{}' to `{}'", what, old, new);
+            self.push(ChangeKind::Changed, item, Some(detail));
+        }
+    }
+
+    // Match up the items of |old| and |new| by |key|, report the ones that
+    // were added or removed, and call |compare| on the rest.
+    fn keyed<T, K, C>(&mut self, what: &str, old: &[T], new: &[T], key: K, mut compare: C)
+    where
+        K: Fn(&T) -> String,
+        C: FnMut(&mut Differ, &str, &T, &T),
+    {
+        for o in old {
+            let item = format!("{} {}", what, key(o));
+            match new.iter().find(|n| key(n) == key(o)) {
+                Some(n) => compare(self, &item, o, n),
+                None => self.push(ChangeKind::Removed, &item, None),
+            }
+        }
+        for n in new {
+            if !old.iter().any(|o| key(o) == key(n)) {
+                self.push(ChangeKind::Added, &format!("{} {}", what, key(n)), None);
+            }
+        }
+    }
+
+    // Compare named, typed things in order, like struct fields or message
+    // parameters.
+    fn ordered(
+        &mut self,
+        item: &str,
+        what: &str,
+        old: &[(String, String)],
+        new: &[(String, String)],
+    ) {
+        for (i, (o, n)) in old.iter().zip(new).enumerate() {
+            if o.0 != n.0 {
+                self.changed(item, &format!("the name of {} {}", what, i + 1), &o.0, &n.0);
+            }
+            if o.1 != n.1 {
+                self.changed(item, &format!("the type of {} `{}'", what, n.0), &o.1, &n.1);
+            }
+        }
+        for (name, t) in old.iter().skip(new.len()) {
+            let detail = format!("{} `{} {}' removed", what, t, name);
+            self.push(ChangeKind::Changed, item, Some(detail));
+        }
+        for (name, t) in new.iter().skip(old.len()) {
+            let detail = format!("{} `{} {}' added", what, t, name);
+            self.push(ChangeKind::Changed, item, Some(detail));
+        }
+    }
+
+    // Report the names that are only in one of |old| and |new|.
+    fn names(&mut self, item: &str, what: &str, old: &[String], new: &[String]) {
+        for o in old.iter().filter(|o| !new.contains(o)) {
+            self.push(
+                ChangeKind::Changed,
+                item,
+                Some(format!("{} `{}' removed", what, o)),
+            );
+        }
+        for n in new.iter().filter(|n| !old.contains(n)) {
+            self.push(
+                ChangeKind::Changed,
+                item,
+                Some(format!("{} `{}' added", what, n)),
+            );
+        }
+    }
+
+    fn message(&mut self, item: &str, old: &MessageDecl, new: &MessageDecl) {
+        let params = |params: &[Param]| {
+            params
+                .iter()
+                .map(|p| (p.name.id.clone(), p.type_spec.to_string()))
+                .collect::<Vec<_>>()
+        };
+        self.changed(
+            item,
+            "the send semantics",
+            semantics_text(old.send_semantics),
+            semantics_text(new.send_semantics),
+        );
+        self.changed(
+            item,
+            "the direction",
+            direction_text(old.direction),
+            direction_text(new.direction),
+        );
+        self.changed(
+            item,
+            "the attributes",
+            attributes_text(&old.attributes),
+            attributes_text(&new.attributes),
+        );
+        self.ordered(
+            item,
+            "parameter",
+            &params(&old.in_params),
+            &params(&new.in_params),
+        );
+        self.ordered(
+            item,
+            "return value",
+            &params(&old.out_params),
+            &params(&new.out_params),
+        );
+    }
+}
+
+// The changes from |old| to |new|, which are two versions of the same file,
+// in the order of the declarations in the file.
+pub fn diff_translation_units(old: &TranslationUnit, new: &TranslationUnit) -> Vec<AstChange> {
+    let mut d = Differ {
+        changes: Vec::new(),
+    };
+    let ids = |ids: &[ast::Identifier]| ids.iter().map(|i| i.id.clone()).collect::<Vec<_>>();
+
+    d.keyed(
+        "include",
+        &ids(&old.include_files),
+        &ids(&new.include_files),
+        |i| i.clone(),
+        |_, _, _, _| (),
+    );
+    d.keyed(
+        "C++ include",
+        &ids(&old.cxx_includes),
+        &ids(&new.cxx_includes),
+        |i| i.clone(),
+        |_, _, _, _| (),
+    );
+    d.keyed(
+        "using",
+        &old.using,
+        &new.using,
+        |u| name_text(&u.cxx_type),
+        |d, item, o, n| {
+            d.changed(item, "the header", &o.header, &n.header);
+            let kind = |u: &ast::UsingStmt| u.kind.map_or(String::new(), |k| k.to_string());
+            d.changed(item, "the kind", kind(o), kind(n));
+            d.changed(
+                item,
+                "the attributes",
+                attributes_text(&o.attributes),
+                attributes_text(&n.attributes),
+            );
+        },
+    );
+    d.keyed(
+        "struct",
+        &old.structs,
+        &new.structs,
+        |s| name_text(&s.0.qname()),
+        |d, item, o, n| {
+            let fields = |fields: &[StructField]| {
+                fields
+                    .iter()
+                    .map(|f| (f.name.id.clone(), f.type_spec.to_string()))
+                    .collect::<Vec<_>>()
+            };
+            d.changed(
+                item,
+                "the attributes",
+                attributes_text(&o.1),
+                attributes_text(&n.1),
+            );
+            d.ordered(item, "field", &fields(&o.2), &fields(&n.2));
+        },
+    );
+    d.keyed(
+        "union",
+        &old.unions,
+        &new.unions,
+        |u| name_text(&u.0.qname()),
+        |d, item, o, n| {
+            let types = |ts: &[TypeSpec]| ts.iter().map(TypeSpec::to_string).collect::<Vec<_>>();
+            d.changed(
+                item,
+                "the attributes",
+                attributes_text(&o.1),
+                attributes_text(&n.1),
+            );
+            d.names(item, "component type", &types(&o.2), &types(&n.2));
+        },
+    );
+    d.keyed(
+        "protocol",
+        old.protocol.as_slice(),
+        new.protocol.as_slice(),
+        |p| name_text(&p.0.qname()),
+        |d, item, o, n| {
+            d.changed(
+                item,
+                "the send semantics",
+                semantics_text(o.1.send_semantics),
+                semantics_text(n.1.send_semantics),
+            );
+            d.changed(
+                item,
+                "the attributes",
+                attributes_text(&o.1.attributes),
+                attributes_text(&n.1.attributes),
+            );
+            d.names(item, "manager", &ids(&o.1.managers), &ids(&n.1.managers));
+            d.names(
+                item,
+                "managed protocol",
+                &ids(&o.1.manages),
+                &ids(&n.1.manages),
+            );
+            let protocol = o.0.qname().short_name();
+            d.keyed(
+                "message",
+                &o.1.messages,
+                &n.1.messages,
+                |m| format!("{}::{}", protocol, m.name.id),
+                |d, item, o, n| d.message(item, o, n),
+            );
+        },
+    );
+    d.changes
+}
+
+// The protocol of an actor type, or of an array or Maybe of one.
+fn actor_protocol(t: &IPDLType) -> Option<TUId> {
+    match t {
+        IPDLType::ActorType(tuid) => Some(*tuid),
+        IPDLType::ArrayType(inner)
+        | IPDLType::MaybeType(inner)
+        | IPDLType::UniquePtrType(inner)
+        | IPDLType::NotNullType(inner) => actor_protocol(inner),
+        _ => None,
+    }
+}
+
+// The uses of `[Deprecated]' protocols in the protocol of |tuid| that
+// aren't in |old|, an older version of its file: new managers, managed
+// protocols and constructors, and new message parameters and return values
+// that are actors of them. |program| must have been checked from the new
+// version. A deprecated protocol can keep using other deprecated ones.
+pub fn new_deprecated_uses(
+    old: &TranslationUnit,
+    program: &TypedProgram,
+    tuid: &TUId,
+) -> Vec<Diagnostic> {
+    let (p, ptype) = match (&program.tus[tuid].protocol, &program.tuts[tuid].protocol) {
+        (Some((_, p)), Some(ptype)) if ptype.deprecated.is_none() => (p, ptype),
+        _ => return Vec::new(),
+    };
+    let old = old.protocol.as_ref().map(|(_, p)| p);
+    let mut warnings = Vec::new();
+    let mut warn = |loc: &ast::Location, used: &TUId| {
+        let used = program.protocol(used);
+        if let Some(hint) = &used.deprecated {
+            warnings.push(Diagnostic::from_catalog(
+                loc,
+                "deprecated",
+                &[("protocol", &used.qname.short_name()), ("hint", hint)],
+            ));
+        }
+    };
+
+    let names = |ids: &[Identifier], tuids: &[TUId]| {
+        ids.iter()
+            .filter_map(|id| {
+                let tuid = tuids
+                    .iter()
+                    .find(|t| program.protocol(t).qname.short_name() == id.id)?;
+                Some((id.clone(), *tuid))
+            })
+            .collect::<Vec<_>>()
+    };
+    let old_managers = old.map_or(&[][..], |o| &o.managers);
+    for (id, used) in names(&p.managers, &ptype.managers) {
+        if !old_managers.iter().any(|m| m.id == id.id) {
+            warn(&id.loc, &used);
+        }
+    }
+    let old_manages = old.map_or(&[][..], |o| &o.manages);
+    for (id, used) in names(&p.manages, &ptype.manages) {
+        if !old_manages.iter().any(|m| m.id == id.id) {
+            warn(&id.loc, &used);
+        }
+    }
+
+    // A constructor's typed message is named after the constructor, rather
+    // than the protocol, so they are matched up by their declarations.
+    for (decl, md) in p.messages.iter().zip(&ptype.messages) {
+        let old_md = old.and_then(|o| o.messages.iter().find(|m| m.name.id == decl.name.id));
+        if let (MessageType::Ctor(used), None) = (&md.mtype, old_md) {
+            warn(&md.name.loc, used);
+        }
+        for param in md.params.iter().chain(&md.returns) {
+            let is_new = old_md.is_none_or(|o| {
+                !o.in_params
+                    .iter()
+                    .chain(&o.out_params)
+                    .any(|op| op.name.id == param.name.id)
+            });
+            match actor_protocol(param.param_type.lookup(&program.tuts)) {
+                Some(used) if is_new => warn(&param.name.loc, &used),
+                _ => (),
+            }
+        }
+    }
+    warnings
+}
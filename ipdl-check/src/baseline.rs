@@ -0,0 +1,142 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Warning baselines, which let strict lints be turned on for a tree that
+// already has many warnings.
+//
+// A baseline records the warnings of one run, keyed by file, warning code
+// and message, but not by line, so that the entries still match after
+// the code around them has been edited. Messages name the symbol that a
+// warning is about, so the key identifies the symbol as well. Later runs
+// only report the warnings that aren't in the baseline. Keys are counted,
+// so a second copy of a warning in the same file is still new.
+
+use errors::Diagnostic;
+use json::{json_string, parse_json, JsonValue};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BaselineEntry {
+    // The file the warning is in, relative to the root of the baseline if
+    // it is under it.
+    pub file: String,
+    pub code: Option<String>,
+    pub message: String,
+}
+
+impl BaselineEntry {
+    pub fn new(d: &Diagnostic, root: &Path) -> BaselineEntry {
+        let file = match &d.loc {
+            Some(loc) => {
+                let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+                let file = loc.file_name.strip_prefix(&root).unwrap_or(&loc.file_name);
+                file.to_string_lossy().replace('\\', "/")
+            }
+            None => String::new(),
+        };
+        BaselineEntry {
+            file,
+            code: d.code.map(String::from),
+            message: d.message.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Baseline {
+    // Sorted, so that the file doesn't depend on the order warnings are
+    // found in.
+    entries: Vec<BaselineEntry>,
+}
+
+// The result of comparing the warnings of a run against a baseline.
+pub struct Comparison {
+    // The warnings that aren't in the baseline, in their original order.
+    pub new_warnings: Vec<Diagnostic>,
+    // The entries of the baseline that no longer occur, because the
+    // warnings have been fixed.
+    pub fixed: Vec<BaselineEntry>,
+}
+
+impl Baseline {
+    // |root| is the directory that file names are made relative to, which
+    // is normally the directory that the baseline file is in.
+    pub fn from_warnings(warnings: &[Diagnostic], root: &Path) -> Baseline {
+        let mut entries = warnings
+            .iter()
+            .map(|d| BaselineEntry::new(d, root))
+            .collect::<Vec<_>>();
+        entries.sort();
+        Baseline { entries }
+    }
+
+    pub fn entries(&self) -> &[BaselineEntry] {
+        &self.entries
+    }
+
+    pub fn parse(text: &str) -> Result<Baseline, String> {
+        let value = parse_json(text)?;
+        let items = value
+            .get("warnings")
+            .and_then(JsonValue::as_array)
+            .ok_or("expected an object with a `warnings' list")?;
+        let mut entries = Vec::new();
+        for item in items {
+            let field = |name| item.get(name).and_then(JsonValue::as_str).map(String::from);
+            let (file, message) = match (field("file"), field("message")) {
+                (Some(file), Some(message)) => (file, message),
+                _ => return Err(String::from("every warning needs a `file' and a `message'")),
+            };
+            entries.push(BaselineEntry {
+                file,
+                code: field("code"),
+                message,
+            });
+        }
+        entries.sort();
+        Ok(Baseline { entries })
+    }
+
+    pub fn to_json(&self) -> String {
+        let entries = self
+            .entries
+            .iter()
+            .map(|e| {
+                let code = match &e.code {
+                    Some(code) => json_string(code),
+                    None => String::from("null"),
+                };
+                format!(
+                    "    {{\"file\": {}, \"code\": {}, \"message\": {}}}",
+                    json_string(&e.file),
+                    code,
+                    json_string(&e.message)
+                )
+            })
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            String::from("{\n  \"warnings\": []\n}\n")
+        } else {
+            format!("{{\n  \"warnings\": [\n{}\n  ]\n}}\n", entries.join(",\n"))
+        }
+    }
+
+    pub fn compare(&self, warnings: &[Diagnostic], root: &Path) -> Comparison {
+        let mut unmatched = self.entries.clone();
+        let mut new_warnings = Vec::new();
+        for d in warnings {
+            let entry = BaselineEntry::new(d, root);
+            match unmatched.iter().position(|e| *e == entry) {
+                Some(i) => {
+                    unmatched.remove(i);
+                }
+                None => new_warnings.push(d.clone()),
+            }
+        }
+        Comparison {
+            new_warnings,
+            fixed: unmatched,
+        }
+    }
+}
@@ -0,0 +1,168 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Checking a big set of files in several processes, for `--jobs'. The
+// driver splits the files given on the command line into partitions, and
+// runs a copy of itself on each one, which only loads the files that its
+// partition includes. Each worker writes its diagnostics with a JsonSink,
+// and the driver reads them back and merges them.
+
+use ast::Location;
+use catalog;
+use errors::{sort_diagnostics, Diagnostic, IpdlError, Note, Severity};
+use json::{parse_json, JsonValue};
+use sinks::diagnostic_json;
+use stable_hash::StableHasher;
+use std::collections::HashSet;
+use std::hash::Hasher;
+use std::path::PathBuf;
+
+// Split |files| into |jobs| partitions by a stable hash of their paths, so
+// that a file stays in the same partition from one run to the next. The
+// files keep their order within each partition. Empty partitions are left
+// out.
+pub fn partition_by_hash(files: &[PathBuf], jobs: usize) -> Vec<Vec<PathBuf>> {
+    let jobs = jobs.max(1);
+    let mut partitions = vec![Vec::new(); jobs];
+    for file in files {
+        let mut hasher = StableHasher::new();
+        hasher.write_str(&file.to_string_lossy());
+        partitions[(hasher.finish() % jobs as u64) as usize].push(file.clone());
+    }
+    partitions.retain(|p| !p.is_empty());
+    partitions
+}
+
+fn location(v: &JsonValue) -> Result<Option<Location>, String> {
+    let number = |key: &str| match v.get(key) {
+        Some(JsonValue::Number(n)) => Ok(*n as usize),
+        _ => Err(format!("expected a number for `{}'", key)),
+    };
+    match v.get("file") {
+        Some(JsonValue::Null) => Ok(None),
+        Some(JsonValue::String(file)) => Ok(Some(Location {
+            file_name: PathBuf::from(file),
+            lineno: number("line")?,
+            colno: number("column")?,
+        })),
+        _ => Err(String::from("expected a file name or null for `file'")),
+    }
+}
+
+fn message(v: &JsonValue) -> Result<String, String> {
+    match v.get("message").and_then(JsonValue::as_str) {
+        Some(m) => Ok(String::from(m)),
+        None => Err(String::from("expected a string for `message'")),
+    }
+}
+
+fn diagnostic(v: &JsonValue) -> Result<Diagnostic, String> {
+    let severity = match v.get("severity").and_then(JsonValue::as_str) {
+        Some("error") => Severity::Error,
+        Some("warning") => Severity::Warning,
+        _ => return Err(String::from("expected `error' or `warning' for `severity'")),
+    };
+    // Every code comes from the catalog, which also gives the &'static str.
+    let code = match v.get("code") {
+        Some(JsonValue::Null) => None,
+        Some(JsonValue::String(code)) => match catalog::entries().iter().find(|e| e.code() == code)
+        {
+            Some(entry) => Some(entry.code()),
+            None => return Err(format!("unknown diagnostic code `{}'", code)),
+        },
+        _ => return Err(String::from("expected a string or null for `code'")),
+    };
+    let mut notes = Vec::new();
+    for n in v.get("notes").and_then(JsonValue::as_array).unwrap_or(&[]) {
+        match location(n)? {
+            Some(loc) => notes.push(Note {
+                loc,
+                message: message(n)?,
+            }),
+            None => return Err(String::from("a note has no location")),
+        }
+    }
+    let mut d = Diagnostic::without_location(&message(v)?);
+    d.severity = severity;
+    d.code = code;
+    d.loc = location(v)?;
+    d.notes = notes;
+    Ok(d)
+}
+
+// Read back the diagnostics that a JsonSink wrote, from all of the checks
+// in |text|. The snippets and include chains of the diagnostics aren't
+// written, so they don't have any.
+pub fn read_diagnostics_json(text: &str) -> Result<Vec<Diagnostic>, String> {
+    let mut diagnostics = Vec::new();
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        let check = parse_json(line)?;
+        let list = match check.get("diagnostics").and_then(JsonValue::as_array) {
+            Some(list) => list,
+            None => return Err(String::from("expected a list of `diagnostics'")),
+        };
+        for v in list {
+            diagnostics.push(diagnostic(v)?);
+        }
+    }
+    Ok(diagnostics)
+}
+
+// The diagnostics of all of the workers, sorted by location. A header that
+// is included by files in several partitions is checked by each of their
+// workers, so a diagnostic that more than one of them reports is only
+// kept once.
+pub fn merge_diagnostics(parts: Vec<Vec<Diagnostic>>) -> Vec<Diagnostic> {
+    let mut seen = HashSet::new();
+    let mut merged = parts
+        .into_iter()
+        .flatten()
+        .filter(|d| seen.insert(diagnostic_json(d)))
+        .collect::<Vec<_>>();
+    sort_diagnostics(&mut merged);
+    merged
+}
+
+// The outcome of a run from the exit codes of its workers, which are the
+// ones of IpdlError::exit_code, or None for a worker that was killed by a
+// signal, and their merged diagnostics: the warnings if they all
+// succeeded, or the most basic of their failures, so that a file that
+// can't be read wins over a type error in another partition. A worker that
+// failed in any other way, like by crashing, wins over all of them, because
+// the files it had to check may not have been checked at all.
+pub fn merged_result(
+    exit_codes: &[Option<i32>],
+    diagnostics: Vec<Diagnostic>,
+) -> Result<Vec<Diagnostic>, IpdlError> {
+    for code in exit_codes {
+        match code {
+            Some(0) | Some(2..=5) => (),
+            Some(c) => {
+                return Err(IpdlError::Io(format!(
+                    "a worker process failed with exit code {}",
+                    c
+                )))
+            }
+            None => {
+                return Err(IpdlError::Io(String::from(
+                    "a worker process was killed by a signal",
+                )))
+            }
+        }
+    }
+    match exit_codes.iter().flatten().filter(|&&c| c != 0).min() {
+        None => Ok(diagnostics),
+        Some(2) => Err(IpdlError::Io(
+            diagnostics
+                .iter()
+                .filter(|d| d.loc.is_none())
+                .map(|d| d.message.clone())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )),
+        Some(3) => Err(IpdlError::Parse(diagnostics)),
+        Some(4) => Err(IpdlError::TypeCheck(diagnostics)),
+        _ => Err(IpdlError::Lint(diagnostics)),
+    }
+}
@@ -0,0 +1,634 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// The wording of the diagnostics that have codes.
+//
+// Each message has an id, which is the code of the diagnostic, or the code
+// followed by a dot and a variant when one code has several messages, like
+// `unknown_type.field'. The text has named placeholders, like `{message}',
+// which are filled in with Diagnostic::from_catalog. Keeping the text here,
+// rather than in format! calls next to the checks, keeps the wording of
+// related messages consistent, and means that a translation only has to
+// replace this table.
+
+use errors::Severity;
+use std::fmt;
+
+pub struct Entry {
+    pub id: &'static str,
+    pub severity: Severity,
+    pub text: &'static str,
+}
+
+impl Entry {
+    // The code of the diagnostics that use this message, which is the id
+    // without the variant.
+    pub fn code(&self) -> &'static str {
+        self.id.split('.').next().unwrap()
+    }
+}
+
+const fn warning(id: &'static str, text: &'static str) -> Entry {
+    Entry {
+        id,
+        severity: Severity::Warning,
+        text,
+    }
+}
+
+const fn error(id: &'static str, text: &'static str) -> Entry {
+    Entry {
+        id,
+        severity: Severity::Error,
+        text,
+    }
+}
+
+// Sorted by id.
+const ENTRIES: &[Entry] = &[
+    error(
+        "alloc_shmem",
+        "message `{message}' has the {side} side allocate a Shmem for its {what}, but protocol \
+         `{protocol}' is [AllocShmem={allowed}]",
+    ),
+    error(
+        "attribute.allow_sync_from",
+        "{attribute} on message `{message}', which isn't sync",
+    ),
+    error(
+        "attribute.allow_sync_from_sender",
+        "{attribute} on message `{message}', which only the {side} side sends",
+    ),
+    error(
+        "attribute.async_only",
+        "non-async message `{message}' cannot specify [{attribute}]",
+    ),
+    error(
+        "attribute.intr_nested_up_to",
+        "intr protocol `{protocol}' cannot specify [NestedUpTo]",
+    ),
+    error(
+        "attribute.intr_priority",
+        "intr message `{message}' cannot specify a priority",
+    ),
+    error(
+        "attribute.manual_dealloc_manager",
+        "[ManualDealloc] protocols cannot be managers",
+    ),
+    error(
+        "attribute.manual_dealloc_toplevel",
+        "Toplevel protocols cannot be [ManualDealloc]",
+    ),
+    error("attribute.max_size", "[MaxSize] must be positive"),
+    error(
+        "attribute.max_size_exceeded",
+        "message `{message}' always has at least {size} bytes of {what}, more than its \
+         [MaxSize={max_size}]",
+    ),
+    error(
+        "attribute.missing_child_proc",
+        "Toplevel protocols must specify [ChildProc]",
+    ),
+    error(
+        "attribute.needs_other_pid",
+        "[NeedsOtherPid] only applies to toplevel protocols",
+    ),
+    error(
+        "attribute.nested_priority",
+        "nested message `{message}' cannot specify a [Priority] other than normal",
+    ),
+    error(
+        "attribute.nested_protocol",
+        "[Nested] only applies to messages; a protocol declares how deeply its messages can be \
+         nested with [NestedUpTo]",
+    ),
+    error(
+        "attribute.no_logging",
+        "message `{message}' has no parameters or return values, so [NoLogging] has nothing to \
+         hide",
+    ),
+    error(
+        "attribute.no_taint",
+        "argument typename `{type}' of message `{message}' has a NoTaint attribute, but the \
+         message lacks the Tainted attribute",
+    ),
+    error(
+        "attribute.reply_priority",
+        "non-returns message `{message}' cannot specify [ReplyPriority]",
+    ),
+    error("attribute.unknown", "unknown attribute `{attribute}'"),
+    error(
+        "attribute.value",
+        "invalid value for attribute `{attribute}', expected one of: {options}",
+    ),
+    error(
+        "attribute.valueless",
+        "unexpected value for valueless attribute `{attribute}'",
+    ),
+    error(
+        "bidirectional_actor_union",
+        "union `{union}' contains actor type `{type}', so it can't be sent in both directions",
+    ),
+    error(
+        "case_collision",
+        "{kind} `{name}' differs only in case from {other_kind} `{other}'",
+    ),
+    error(
+        "case_collision.header",
+        "generated header `{header}' differs only in case from `{other}', so they would clash on \
+         case-insensitive file systems",
+    ),
+    error(
+        "compress.coalesced",
+        "message `{message}' in protocol `{protocol}' requests compression, but carries {kind}, \
+         which would be lost if the message were coalesced with a later one",
+    ),
+    error(
+        "compress.ctor",
+        "{kind} messages can't use compression (here, in protocol `{protocol}')",
+    ),
+    error(
+        "compress.not_async",
+        "message `{message}' in protocol `{protocol}' requests compression but is not async",
+    ),
+    error(
+        "ctor.async_returns",
+        "asynchronous ctor/dtor message `{message}' declares return values",
+    ),
+    error(
+        "ctor.not_managed",
+        "ctor for protocol `{managee}', which is not managed by protocol `{protocol}'",
+    ),
+    error(
+        "ctor.own_actor",
+        "constructor of protocol `{protocol}' can't take parameter `{param}', which is an actor \
+         of the protocol it constructs",
+    ),
+    error(
+        "ctor.sync_returns",
+        "synchronous ctor message `{message}' declares return values",
+    ),
+    warning(
+        "ctor_direction",
+        "constructor `{message}' is sent to the {receiver}, but every message of `{protocol}' \
+         is sent to the {creator}, so the {creator} side never sends anything on the actors \
+         it creates",
+    ),
+    warning("deprecated", "protocol `{protocol}' is deprecated: {hint}"),
+    error(
+        "dtor.actor_param",
+        "destructor of protocol `{protocol}' can't take actor parameter `{param}'",
+    ),
+    error("dtor.async", "destructor must be async"),
+    error(
+        "dtor.both_directions",
+        "destructor of protocol `{protocol}' can't be sent in both directions",
+    ),
+    error(
+        "dtor.missing",
+        "destructor declaration `{dtor}(...)' required for managed protocol `{protocol}'",
+    ),
+    error("dtor.returns", "destructors cannot return values"),
+    error(
+        "duplicate_component",
+        "duplicate component type `{type}' of union `{union}'",
+    ),
+    error(
+        "duplicate_cxx_include",
+        "C++ header {header} is included more than once",
+    ),
+    error(
+        "duplicate_definition",
+        "{kind} `{name}' is also defined in `{file}'",
+    ),
+    error(
+        "duplicate_definition.protocol",
+        "protocol `{protocol}' is also defined in `{file}'",
+    ),
+    warning(
+        "duplicate_protocol_name",
+        "protocol `{protocol}' has the same name as `{other}' in `{file}', so their message IDs \
+         and IPC log names clash",
+    ),
+    warning(
+        "duplicate_struct",
+        "struct `{struct}' has the same fields as `{other}' in `{file}'; they could be one \
+         struct in a shared .ipdlh",
+    ),
+    warning("empty_struct", "struct `{struct}' has no fields"),
+    error(
+        "endpoint.managed",
+        "endpoint `{endpoint}' is for protocol `{protocol}', which is managed, so it needs to be \
+         a ManagedEndpoint",
+    ),
+    error(
+        "endpoint.not_async",
+        "endpoint `{endpoint}' is sent in message `{message}', which is not async",
+    ),
+    error(
+        "endpoint.not_managed",
+        "managed endpoint `{endpoint}' is sent in protocol `{protocol}', which does not manage \
+         protocol `{managed}'",
+    ),
+    warning(
+        "endpoint_process",
+        "endpoint `{endpoint}' is bound by the {receiver} side of `{protocol}', in a `{process}' \
+         process, but the {side} side of `{opened}' is in a `{opened_process}' process",
+    ),
+    error(
+        "file_name",
+        "expected file for translation unit `{name}' to be named `{expected}'; instead it's named \
+         `{actual}'.",
+    ),
+    error(
+        "file_name.header",
+        "header file name `{stem}.ipdlh' can't be used in an include statement, because `{stem}' \
+         isn't an identifier",
+    ),
+    error(
+        "file_name.header_marker",
+        "`header;' says that `{file}' is a header, but the names of headers end with `.ipdlh'",
+    ),
+    error(
+        "generated_name_clash",
+        "`{name}' clashes with the name of a C++ {what} generated for protocol `{protocol}'",
+    ),
+    error(
+        "include_namespace",
+        "`include protocol {dirs}::{protocol}' names namespace `{dirs}', but protocol \
+         `{protocol}' is in namespace `{namespace}'",
+    ),
+    error(
+        "inconsistent_cxx_type.moveonly",
+        "inconsistent moveonly status of type `{type}`",
+    ),
+    error(
+        "inconsistent_cxx_type.refcounted",
+        "inconsistent refcounted status of type `{type}`",
+    ),
+    warning(
+        "intr",
+        "protocol `{protocol}' is intr, which has been removed from IPDL; use sync or async \
+         messages instead",
+    ),
+    warning(
+        "intr_nesting",
+        "[NestedUpTo=not] has no effect on intr protocol `{protocol}'",
+    ),
+    warning(
+        "large_message",
+        "the {what} of message `{message}' can be {size} bytes, more than the limit of {limit}",
+    ),
+    warning(
+        "large_message.unbounded",
+        "the size of the {what} of message `{message}' has no limit, but the limit is {limit} \
+         bytes; declare a [MaxSize] if it is never larger",
+    ),
+    error(
+        "manager_cycle",
+        "cycle(s) detected in manager/manages hierarchy: {cycles}",
+    ),
+    error(
+        "manager_cycle.mutual",
+        "protocol `{protocol}' lists `{managee}' both as its manager and as a protocol it \
+         manages, so each would manage the other",
+    ),
+    error(
+        "manager_cycle.self",
+        "top-level protocol `{protocol}' cannot manage itself",
+    ),
+    warning(
+        "manager_depth",
+        "the manager chain of protocol `{protocol}' is {depth} deep, more than the limit of \
+         {limit}",
+    ),
+    error(
+        "manager_mismatch.managee",
+        "|manages| declaration in protocol `{protocol}' does not match any |manager| declaration \
+         in protocol `{managee}'",
+    ),
+    error(
+        "manager_mismatch.manager",
+        "|manager| declaration in protocol `{protocol}' does not match any |manages| declaration \
+         in protocol `{manager}'",
+    ),
+    warning(
+        "manager_order",
+        "the managers of protocol `{protocol}' are not in alphabetical order",
+    ),
+    warning(
+        "message_direction",
+        "message `{message}' is in a `{section}:' section, but its name matches `{pattern}', \
+         which is for messages in a `{rule_section}:' section",
+    ),
+    warning(
+        "mirrored_messages",
+        "message `{message}' is the same as `{other}' except for its direction; they could be \
+         one message in a `both:' section",
+    ),
+    warning(
+        "missing_ctor",
+        "protocol `{manager}' manages `{protocol}', but has no ctor message for it",
+    ),
+    warning(
+        "namespace_dirs",
+        "`{name}' is in namespace `{namespace}', so it should be in a directory ending with \
+         `{dir}'",
+    ),
+    error(
+        "nesting.inside_cpow",
+        "inside_cpow nested parent-to-child messages are verboten (here, message `{message}' in \
+         protocol `{protocol}')",
+    ),
+    error(
+        "nesting.inside_sync",
+        "inside_sync nested messages must be sync (here, message `{message}' in protocol \
+         `{protocol}')",
+    ),
+    error(
+        "nesting.manager",
+        "message `{message}' is nested {nesting}, but manager `{manager}' of protocol \
+         `{protocol}' doesn't allow that",
+    ),
+    warning(
+        "never_constructed",
+        "managed protocol `{protocol}' is never constructed by any of its managers",
+    ),
+    error(
+        "not_a_protocol.managee",
+        "{protocol} declares itself managing a non-`protocol' entity `{managee}' that is a {kind}",
+    ),
+    error(
+        "not_a_protocol.manager",
+        "entity `{manager}' referenced as |manager| of `{protocol}' is not of `protocol' type; \
+         instead it is a {kind}",
+    ),
+    error(
+        "not_exported",
+        "type `{type}' is declared in header `{header}', but not exported from it; add \
+         [Export] to its declaration",
+    ),
+    error(
+        "not_included",
+        "type `{type}' is declared in `{file}', which isn't included here; add `{include}'",
+    ),
+    error(
+        "partially_defined",
+        "{kind} `{name}' is only partially defined",
+    ),
+    error(
+        "partially_defined.self_component",
+        "union `{union}' is only partially defined, because it contains itself as a component and \
+         none of its other components are fully defined",
+    ),
+    error(
+        "plain_old_data",
+        "field `{field}' of [PlainOldData] struct `{struct}' contains refcounted type `{type}', \
+         which can't be copied as raw memory",
+    ),
+    warning("policy", "{what} {violation}, because of policy `{policy}'"),
+    error("redeclaration", "redeclaration of symbol `{name}'"),
+    error(
+        "redeclaration.ctor",
+        "message `{message}' has the same name that the ctor message `{ctor}' is declared with",
+    ),
+    error(
+        "redeclaration.ctor_name",
+        "ctor message `{ctor}' is declared as `{message}', which is already the name of a message",
+    ),
+    error(
+        "redeclaration.field",
+        "redeclaration of symbol `{field}': struct `{struct}' has two fields named `{field}'",
+    ),
+    error(
+        "redeclaration.manager",
+        "manager `{manager}' appears multiple times",
+    ),
+    error(
+        "redeclaration.message",
+        "message name `{message}' already declared as `{kind}'",
+    ),
+    error(
+        "redeclaration.param",
+        "redeclaration of symbol `{param}': message `{message}' has {what} named `{param}'",
+    ),
+    warning(
+        "redundant_using",
+        "type `{type}' is imported more than once",
+    ),
+    warning(
+        "redundant_using.alias",
+        "type `{type}' is already imported as `{first}', which is the same type",
+    ),
+    error("reserved_name", "{what} name `{name}' is {reason}"),
+    error(
+        "send_semantics",
+        "message `{message}' requires more powerful send semantics than its protocol `{protocol}' \
+         provides: {mismatch}",
+    ),
+    error(
+        "send_semantics.actor",
+        "message `{message}' can't carry an actor of protocol `{protocol}' in `{param}': \
+         {mismatch}",
+    ),
+    error(
+        "send_semantics.manager",
+        "protocol `{protocol}' requires more powerful send semantics than its manager `{manager}' \
+         provides: {mismatch}",
+    ),
+    warning(
+        "shmem_in_sync_reply",
+        "sync message `{message}' returns a Shmem",
+    ),
+    warning(
+        "shmem_without_attribute",
+        "message `{message}' carries a Shmem, but doesn't have an `[{attribute}]' attribute",
+    ),
+    warning(
+        "single_component_union",
+        "union `{union}' has only one component type, so it could be replaced by that type",
+    ),
+    warning(
+        "sync_both_directions",
+        "sync message `{message}' is declared in a `both:' section, so either side can block \
+         on the other; declare a message for each direction instead",
+    ),
+    error(
+        "sync_moveonly_return",
+        "moveonly type `{type}' can't be returned from sync message `{message}'",
+    ),
+    error(
+        "sync_to_child",
+        "sync parent-to-child messages are verboten (here, message `{message}' in protocol \
+         `{protocol}')",
+    ),
+    warning(
+        "sync_without_returns",
+        "sync message `{message}' has no return values, so it could be async",
+    ),
+    warning(
+        "too_many_fds",
+        "the {what} of message `{message}' can carry {count} file descriptors, more than the \
+         limit of {limit}",
+    ),
+    warning(
+        "too_many_fds.unbounded",
+        "the {what} of message `{message}' can carry any number of file descriptors, but the \
+         limit is {limit}",
+    ),
+    warning(
+        "too_many_managees",
+        "protocol `{protocol}' manages {count} protocols, more than the limit of {limit}",
+    ),
+    warning(
+        "too_many_params",
+        "message `{message}' has {count} parameters, more than the limit of {limit}; consider \
+         putting them in a struct",
+    ),
+    warning(
+        "toplevel_process",
+        "toplevel protocol `{protocol}' doesn't say which process its parent side is in; add \
+         a [ParentProc]",
+    ),
+    error(
+        "type_constructor",
+        "{what} `{type}' is not allowed, because {reason}",
+    ),
+    error(
+        "type_depth",
+        "type `{type}' has more than {limit} levels of type constructors",
+    ),
+    error(
+        "undeclared_protocol.managee",
+        "protocol `{managee}', managed by `{protocol}', has not been declared",
+    ),
+    error(
+        "undeclared_protocol.manager",
+        "protocol `{manager}' referenced as |manager| of `{protocol}' has not been declared",
+    ),
+    error("unknown_type", "unknown type `{type}'"),
+    error(
+        "unknown_type.component",
+        "unknown component type `{type}' of union `{union}'",
+    ),
+    error(
+        "unknown_type.field",
+        "field `{field}' of struct `{struct}' has unknown type `{type}'",
+    ),
+    error(
+        "unknown_type.param",
+        "argument typename `{type}' of message `{message}' has not been declared",
+    ),
+    error(
+        "unknown_type.template_arg",
+        "unknown type `{type}' in the template arguments of `{instance}'",
+    ),
+    warning(
+        "unlisted_sync_message",
+        "sync message `{message}' is not in the list of allowed sync messages",
+    ),
+    error(
+        "unsupported_nullable",
+        "`nullable' qualifier for type `{type}' is unsupported",
+    ),
+    error(
+        "unsupported_nullable.array",
+        "`nullable' qualifier for type `{type}' is unsupported; in `{spec}', it applies to the \
+         elements of the array, and arrays themselves can't be null",
+    ),
+    warning(
+        "unused_include",
+        "nothing that header `{header}' declares is used here, so the include can be removed",
+    ),
+    warning(
+        "unused_sync_return",
+        "sync message `{message}' only returns `bool {return}', and its name suggests that \
+         nothing waits for the result, so it could be async",
+    ),
+    error(
+        "using_kind",
+        "type `{type}' is imported as a {kind} here, but as a {other_kind} elsewhere",
+    ),
+    error(
+        "version.order",
+        "message `{message}' is from version {since}, but comes after a message from version \
+         {version}",
+    ),
+    error("version.protocol", "[ProtocolVersion] must be at least 1"),
+    error("version.since", "[Since] must be at least 1"),
+    error(
+        "version.too_late",
+        "message `{message}' is [Since={since}], which is later than version {version} of \
+         protocol `{protocol}'",
+    ),
+    error(
+        "version.unversioned_protocol",
+        "message `{message}' has [Since], but protocol `{protocol}' has no [ProtocolVersion]",
+    ),
+];
+
+pub fn entries() -> &'static [Entry] {
+    ENTRIES
+}
+
+pub fn entry(id: &str) -> Option<&'static Entry> {
+    ENTRIES.iter().find(|e| e.id == id)
+}
+
+// Replace the placeholders in |text| with the values of |args|. A
+// placeholder without a value is left as it is.
+pub fn fill(text: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        match args.iter().find(|(name, _)| *name == &rest[1..end]) {
+            Some((_, value)) => out.push_str(&value.to_string()),
+            None => out.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[test]
+fn catalog_tests() {
+    for pair in ENTRIES.windows(2) {
+        assert!(pair[0].id < pair[1].id, "`{}' is out of order", pair[1].id);
+    }
+    for e in ENTRIES {
+        // Every placeholder is a name.
+        let placeholders = e
+            .text
+            .split('{')
+            .skip(1)
+            .map(|p| p.split('}').next().unwrap());
+        for p in placeholders {
+            assert!(
+                !p.is_empty() && p.chars().all(|c| c.is_ascii_lowercase() || c == '_'),
+                "bad placeholder `{}' in `{}'",
+                p,
+                e.id
+            );
+        }
+        // All of the messages for a code have the same severity.
+        let first = ENTRIES.iter().find(|f| f.code() == e.code()).unwrap();
+        assert_eq!(first.severity, e.severity);
+    }
+
+    assert_eq!(entry("unknown_type.field").unwrap().code(), "unknown_type");
+    assert_eq!(
+        fill(
+            "sync message `{message}' has {count} {count}{x}",
+            &[("message", &"PFoo::Msg"), ("count", &2)]
+        ),
+        "sync message `PFoo::Msg' has 2 2{x}"
+    );
+}
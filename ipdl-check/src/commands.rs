@@ -0,0 +1,84 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// A compilation database, like the compile_commands.json files that C++
+// tools use, so that editors and indexers can check a single IPDL file the
+// same way the build does.
+//
+// There is an entry for each file given on the command line, with the
+// include search path, the files its include statements resolved to, and
+// the arguments to check just that file, and the files that were
+// generated from it, where the output layout put them.
+
+use ir::TypedProgram;
+use json::{json_list, json_path, json_string};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// |program| must have been checked from |file_names|, with |include_dirs|
+// as the search path.
+pub fn compile_commands(
+    program: &TypedProgram,
+    include_dirs: &[PathBuf],
+    file_names: &[PathBuf],
+) -> String {
+    compile_commands_with_outputs(program, include_dirs, file_names, &HashMap::new())
+}
+
+// Like compile_commands, with the files in |outputs| that were generated
+// from each of |file_names|.
+pub fn compile_commands_with_outputs(
+    program: &TypedProgram,
+    include_dirs: &[PathBuf],
+    file_names: &[PathBuf],
+    outputs: &HashMap<PathBuf, Vec<PathBuf>>,
+) -> String {
+    let include_dirs = include_dirs
+        .iter()
+        .map(|d| d.canonicalize().unwrap_or_else(|_| d.clone()))
+        .collect::<Vec<_>>();
+    let directory = std::env::current_dir().unwrap_or_default();
+
+    let mut entries = Vec::new();
+    for file in file_names {
+        let tuid = program.tu_for_file(file).unwrap();
+        let tu = &program.tus[&tuid];
+
+        let mut arguments = vec![json_string("ipdl_parser")];
+        for d in &include_dirs {
+            arguments.push(json_string("-I"));
+            arguments.push(json_path(d));
+        }
+        arguments.push(json_path(&tu.file_name));
+
+        let includes = tu
+            .includes
+            .iter()
+            .map(|i| json_path(&program.tus[i].file_name));
+
+        entries.push(format!(
+            "  {{\n    \"directory\": {},\n    \"file\": {},\n    \
+             \"include_dirs\": {},\n    \"includes\": {},\n    \
+             \"arguments\": {},\n    \"outputs\": {}\n  }}",
+            json_path(&directory),
+            json_path(&tu.file_name),
+            json_list(include_dirs.iter().map(|d| json_path(d))),
+            json_list(includes),
+            json_list(arguments),
+            json_list(
+                outputs
+                    .get(file)
+                    .into_iter()
+                    .flatten()
+                    .map(|o| json_path(o))
+            ),
+        ));
+    }
+
+    if entries.is_empty() {
+        String::from("[]\n")
+    } else {
+        format!("[\n{}\n]\n", entries.join(",\n"))
+    }
+}
@@ -0,0 +1,275 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use conditions;
+use config::{self, ConfigLoader};
+use errors::{Diagnostic, IpdlError};
+use events::{CompilerEvents, NoEvents, Phase};
+use ir::TypedProgram;
+use parser;
+use sinks::DiagnosticSink;
+use snapshot::Snapshot;
+use sources::SourceManager;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use type_check::{self, CheckOptions, GatherCache};
+
+// Parse and type check the files, and return the typed IR for them.
+pub fn check(
+    include_dirs: &Vec<PathBuf>,
+    file_names: Vec<PathBuf>,
+) -> Result<TypedProgram, IpdlError> {
+    check_with_options(include_dirs, file_names, &CheckOptions::default())
+}
+
+pub fn check_with_options(
+    include_dirs: &Vec<PathBuf>,
+    file_names: Vec<PathBuf>,
+    options: &CheckOptions,
+) -> Result<TypedProgram, IpdlError> {
+    check_with_sources(include_dirs, file_names, options, &mut SourceManager::new())
+}
+
+// Like check_with_options, but the text of the files is kept in |sources|,
+// so that it can be used afterwards even if checking failed.
+pub fn check_with_sources(
+    include_dirs: &Vec<PathBuf>,
+    file_names: Vec<PathBuf>,
+    options: &CheckOptions,
+    sources: &mut SourceManager,
+) -> Result<TypedProgram, IpdlError> {
+    let context = CheckContext {
+        cache: &mut GatherCache::new(),
+        configs: &mut ConfigLoader::new(),
+        snapshot: None,
+        events: &mut NoEvents,
+    };
+    check_files(include_dirs, file_names, options, sources, context).0
+}
+
+// How long each phase of a check took.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhaseTimes {
+    pub parse: Duration,
+    pub type_check: Duration,
+    // Applying the lint levels of the options and of the config files.
+    pub lint_levels: Duration,
+}
+
+// What a check reuses from earlier checks, and where it reports its
+// progress.
+struct CheckContext<'a> {
+    cache: &'a mut GatherCache,
+    configs: &'a mut ConfigLoader,
+    snapshot: Option<&'a Snapshot>,
+    events: &'a mut dyn CompilerEvents,
+}
+
+// Report to |events| that |phase| finished, and record how long it took in
+// |times|.
+fn finish_phase(
+    events: &mut dyn CompilerEvents,
+    times: &mut PhaseTimes,
+    phase: Phase,
+    start: Instant,
+) {
+    let time = start.elapsed();
+    match phase {
+        Phase::Parse => times.parse = time,
+        Phase::TypeCheck => times.type_check = time,
+        Phase::LintLevels => times.lint_levels = time,
+    }
+    events.phase_finished(phase, time);
+}
+
+// The result of checking the files, and how long each phase took. The
+// phases after one that failed take no time.
+fn check_files(
+    include_dirs: &Vec<PathBuf>,
+    file_names: Vec<PathBuf>,
+    options: &CheckOptions,
+    sources: &mut SourceManager,
+    context: CheckContext,
+) -> (Result<TypedProgram, IpdlError>, PhaseTimes) {
+    let events = context.events;
+    let mut times = PhaseTimes::default();
+
+    events.phase_started(Phase::Parse);
+    let start = Instant::now();
+    let tus = parser::parse_with_events(
+        include_dirs,
+        file_names,
+        context.snapshot,
+        sources,
+        &options.limits,
+        events,
+    );
+    finish_phase(events, &mut times, Phase::Parse, start);
+    let mut tus = match tus {
+        Ok(tus) => tus,
+        Err(e) => return (Err(e), times),
+    };
+    for tu in tus.values_mut() {
+        conditions::remove_inactive(tu, &options.defines);
+    }
+
+    events.phase_started(Phase::TypeCheck);
+    let start = Instant::now();
+    let checked = type_check::check_with_events(&tus, options, context.cache, events);
+    finish_phase(events, &mut times, Phase::TypeCheck, start);
+    let (tuts, warnings) = match checked {
+        Ok(checked) => checked,
+        Err(e) => return (Err(e), times),
+    };
+    let mut program = TypedProgram::new(tus, tuts);
+
+    events.phase_started(Phase::LintLevels);
+    let start = Instant::now();
+    let loader = if options.use_config_files {
+        Some(context.configs)
+    } else {
+        None
+    };
+    let warnings = config::apply_lint_levels(&program, warnings, loader, options);
+    finish_phase(events, &mut times, Phase::LintLevels, start);
+    let result = warnings.map(|warnings| {
+        program.warnings = warnings;
+        program
+    });
+    (result, times)
+}
+
+// Everything that is kept from one check of a set of files to the next,
+// for tools like editors that check the same files over and over.
+pub struct Session {
+    pub include_dirs: Vec<PathBuf>,
+    pub options: CheckOptions,
+    // Whether diagnostics quote the line they are about.
+    pub show_source: bool,
+    pub sources: SourceManager,
+    // Included files that haven't changed since the snapshot was written
+    // are taken from it instead of being parsed.
+    pub snapshot: Option<Snapshot>,
+    configs: ConfigLoader,
+    cache: GatherCache,
+    phase_times: PhaseTimes,
+    program: Option<TypedProgram>,
+    // The errors or warnings from the last check.
+    diagnostics: Vec<Diagnostic>,
+    sinks: Vec<Box<dyn DiagnosticSink>>,
+    events: Vec<Box<dyn CompilerEvents>>,
+}
+
+impl Session {
+    pub fn new(include_dirs: Vec<PathBuf>, options: CheckOptions) -> Session {
+        Session {
+            include_dirs,
+            options,
+            show_source: false,
+            sources: SourceManager::new(),
+            snapshot: None,
+            configs: ConfigLoader::new(),
+            cache: GatherCache::new(),
+            phase_times: PhaseTimes::default(),
+            program: None,
+            diagnostics: Vec::new(),
+            sinks: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    // Send the diagnostics of every check from now on to |sink| too.
+    pub fn add_sink(&mut self, sink: Box<dyn DiagnosticSink>) {
+        self.sinks.push(sink);
+    }
+
+    // Report the events of every check from now on to |events| too.
+    pub fn add_events(&mut self, events: Box<dyn CompilerEvents>) {
+        self.events.push(events);
+    }
+
+    fn publish(&mut self, io_error: Option<&str>) {
+        let io_diagnostic = io_error.map(Diagnostic::without_location);
+        let diagnostics = match &io_diagnostic {
+            Some(d) => std::slice::from_ref(d),
+            None => &self.diagnostics[..],
+        };
+        for sink in &mut self.sinks {
+            sink.publish(diagnostics);
+        }
+        self.events.diagnostics_emitted(diagnostics);
+    }
+
+    pub fn check(&mut self, file_names: Vec<PathBuf>) -> Result<&TypedProgram, IpdlError> {
+        let context = CheckContext {
+            cache: &mut self.cache,
+            configs: &mut self.configs,
+            snapshot: self.snapshot.as_ref(),
+            events: &mut self.events,
+        };
+        let (result, phase_times) = check_files(
+            &self.include_dirs,
+            file_names,
+            &self.options,
+            &mut self.sources,
+            context,
+        );
+        self.phase_times = phase_times;
+        self.program = None;
+        match result {
+            Ok(mut program) => {
+                if self.show_source {
+                    self.sources.add_snippets(&mut program.warnings);
+                }
+                self.diagnostics = program.warnings.clone();
+                self.publish(None);
+                Ok(self.program.get_or_insert(program))
+            }
+            Err(mut e) => {
+                if self.show_source {
+                    self.sources.add_snippets(e.diagnostics_mut());
+                }
+                self.diagnostics = e.diagnostics().to_vec();
+                match &e {
+                    IpdlError::Io(message) => self.publish(Some(message)),
+                    _ => self.publish(None),
+                }
+                Err(e)
+            }
+        }
+    }
+
+    // The program from the last check, if it succeeded.
+    pub fn program(&self) -> Option<&TypedProgram> {
+        self.program.as_ref()
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn cache(&self) -> &GatherCache {
+        &self.cache
+    }
+
+    // How long the phases of the last check took.
+    pub fn phase_times(&self) -> PhaseTimes {
+        self.phase_times
+    }
+
+    // Forget the text of the files and the configs, so that the next check
+    // sees any edits. The declarations of files that haven't changed are
+    // still reused.
+    pub fn reset(&mut self) {
+        self.sources = SourceManager::new();
+        self.configs = ConfigLoader::new();
+        self.program = None;
+        self.diagnostics.clear();
+    }
+}
+
+pub fn compile(include_dirs: &Vec<PathBuf>, file_names: Vec<PathBuf>) -> Result<(), IpdlError> {
+    check(include_dirs, file_names)?;
+    Ok(())
+}
@@ -0,0 +1,306 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Conditional sections, like `#if defined(MOZ_WIDGET_ANDROID)' ... `#endif'.
+//
+// The parser treats preprocessor lines like comments, so both branches of a
+// condition are always parsed. The sections are recorded in the translation
+// unit, and remove_inactive() drops the declarations in sections whose
+// condition doesn't hold for a set of defines, before type checking, so
+// that only one consistent set of branches is checked. Only `defined', `!', `&&',
+// `||', parentheses and numbers are supported in conditions. A bare name is
+// treated like `defined(name)', because defines don't have values.
+
+use ast::{Condition, ConditionalSection, Location, TranslationUnit};
+use errors::Errors;
+use lexer::{self, Token};
+use std::collections::HashSet;
+use std::path::Path;
+
+struct ConditionParser<'a> {
+    tokens: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> ConditionParser<'a> {
+    fn new(text: &'a str) -> ConditionParser<'a> {
+        let mut tokens = Vec::new();
+        let mut rest = text.trim_start();
+        while !rest.is_empty() {
+            let len = if rest.starts_with("&&") || rest.starts_with("||") {
+                2
+            } else if rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+                rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len())
+            } else {
+                rest.chars().next().unwrap().len_utf8()
+            };
+            tokens.push(&rest[..len]);
+            rest = rest[len..].trim_start();
+        }
+        ConditionParser { tokens, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).cloned()
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let t = self.peek()?;
+        if t.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            self.pos += 1;
+            Some(String::from(t))
+        } else {
+            None
+        }
+    }
+
+    fn parse(mut self) -> Option<Condition> {
+        let c = self.or()?;
+        if self.peek().is_some() {
+            return None;
+        }
+        Some(c)
+    }
+
+    fn or(&mut self) -> Option<Condition> {
+        let mut c = self.and()?;
+        while self.eat("||") {
+            c = Condition::Or(Box::new(c), Box::new(self.and()?));
+        }
+        Some(c)
+    }
+
+    fn and(&mut self) -> Option<Condition> {
+        let mut c = self.unary()?;
+        while self.eat("&&") {
+            c = Condition::And(Box::new(c), Box::new(self.unary()?));
+        }
+        Some(c)
+    }
+
+    fn unary(&mut self) -> Option<Condition> {
+        if self.eat("!") {
+            return Some(Condition::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let c = self.or()?;
+            return if self.eat(")") { Some(c) } else { None };
+        }
+        if self.eat("defined") {
+            let name = if self.eat("(") {
+                let name = self.name()?;
+                if !self.eat(")") {
+                    return None;
+                }
+                name
+            } else {
+                self.name()?
+            };
+            return Some(Condition::Defined(name));
+        }
+        let t = self.peek()?;
+        if let Ok(n) = t.parse::<u64>() {
+            self.pos += 1;
+            return Some(Condition::Constant(n != 0));
+        }
+        self.name().map(Condition::Defined)
+    }
+}
+
+pub fn parse_condition(text: &str) -> Option<Condition> {
+    ConditionParser::new(text).parse()
+}
+
+// An `#if' whose `#endif' hasn't been found yet.
+struct OpenSection {
+    loc: Location,
+    // The conditions of the earlier branches, which must all be false for
+    // the current branch to be used.
+    earlier: Vec<Condition>,
+    condition: Condition,
+    first_line: usize,
+    seen_else: bool,
+}
+
+impl OpenSection {
+    fn close(&self, last_line: usize) -> ConditionalSection {
+        let condition = self.earlier.iter().fold(self.condition.clone(), |c, e| {
+            Condition::And(Box::new(Condition::Not(Box::new(e.clone()))), Box::new(c))
+        });
+        ConditionalSection {
+            condition,
+            first_line: self.first_line,
+            last_line,
+        }
+    }
+}
+
+// Find the conditional sections of a file. Preprocessor lines other than
+// the conditional ones are ignored.
+pub fn scan(file_name: &Path, text: &str) -> (Vec<ConditionalSection>, Errors) {
+    let mut errors = Errors::none();
+    let mut sections = Vec::new();
+    let mut open: Vec<OpenSection> = Vec::new();
+
+    let mut line_starts = vec![0];
+    line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+
+    for (token, span) in lexer::tokenize(text) {
+        let directive = match token {
+            Token::Preprocessor(d) => d,
+            _ => continue,
+        };
+        let line = match line_starts.binary_search(&span.start) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let loc = Location {
+            file_name: file_name.to_path_buf(),
+            lineno: line + 1,
+            colno: span.start - line_starts[line],
+        };
+        let body = directive[1..].trim_start();
+        let word_len = body
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(body.len());
+        let (word, rest) = body.split_at(word_len);
+
+        let condition = match word {
+            "if" | "elif" => parse_condition(rest),
+            "ifdef" | "ifndef" => {
+                let mut p = ConditionParser::new(rest);
+                p.name().filter(|_| p.peek().is_none()).map(|name| {
+                    if word == "ifdef" {
+                        Condition::Defined(name)
+                    } else {
+                        Condition::Not(Box::new(Condition::Defined(name)))
+                    }
+                })
+            }
+            "else" | "endif" => Some(Condition::Constant(true)),
+            _ => continue,
+        };
+        let condition = match condition {
+            Some(c) => c,
+            None => {
+                errors.append_one(
+                    &loc,
+                    &format!("can't parse the condition of `{}'", directive.trim_end()),
+                );
+                Condition::Constant(false)
+            }
+        };
+
+        match word {
+            "if" | "ifdef" | "ifndef" => open.push(OpenSection {
+                loc,
+                earlier: Vec::new(),
+                condition,
+                first_line: line + 2,
+                seen_else: false,
+            }),
+            _ => {
+                let section = match open.last_mut() {
+                    Some(section) => section,
+                    None => {
+                        errors.append_one(&loc, &format!("`#{}' without `#if'", word));
+                        continue;
+                    }
+                };
+                if section.seen_else && word != "endif" {
+                    errors.append_one(&loc, &format!("`#{}' after `#else'", word));
+                }
+                sections.push(section.close(line));
+                if word == "endif" {
+                    open.pop();
+                } else {
+                    let previous = std::mem::replace(&mut section.condition, condition);
+                    section.earlier.push(previous);
+                    section.first_line = line + 2;
+                    section.seen_else = word == "else";
+                }
+            }
+        }
+    }
+
+    for section in open {
+        errors.append_one(&section.loc, "`#if' without `#endif'");
+    }
+
+    (sections, errors)
+}
+
+// Remove the declarations that are in sections whose condition doesn't
+// hold. Included files are always kept, because they have already been
+// parsed.
+pub fn remove_inactive(tu: &mut TranslationUnit, defines: &HashSet<String>) {
+    let conditions = std::mem::take(&mut tu.conditions);
+    let active = |loc: &Location| {
+        conditions.iter().all(|s| {
+            loc.lineno < s.first_line || loc.lineno > s.last_line || s.condition.eval(defines)
+        })
+    };
+
+    tu.using.retain(|u| active(u.cxx_type.loc()));
+    tu.structs.retain(|s| active(&s.0.name.loc));
+    for s in &mut tu.structs {
+        s.2.retain(|f| active(&f.name.loc));
+    }
+    tu.unions.retain(|u| active(&u.0.name.loc));
+    for u in &mut tu.unions {
+        u.2.retain(|c| active(c.loc()));
+    }
+    if let Some((_, p)) = &mut tu.protocol {
+        p.messages.retain(|md| active(&md.name.loc));
+        for md in &mut p.messages {
+            md.in_params.retain(|param| active(&param.name.loc));
+            md.out_params.retain(|param| active(&param.name.loc));
+        }
+    }
+
+    tu.conditions = conditions;
+}
+
+#[test]
+fn condition_tests() {
+    let defines = HashSet::from([String::from("A")]);
+    let eval = |text| parse_condition(text).unwrap().eval(&defines);
+    assert!(eval("defined(A)"));
+    assert!(eval("defined A"));
+    assert!(!eval("B"));
+    assert!(eval("!defined(B) && (A || B)"));
+    assert!(!eval("0"));
+    assert!(parse_condition("defined(A").is_none());
+    assert!(parse_condition("A B").is_none());
+
+    let text = "#ifdef A\na\n#elif B\nb\n#else\nc\n#endif\n// #if x\n#if\n";
+    let (sections, errors) = scan(Path::new("f.ipdl"), text);
+    let lines = sections
+        .iter()
+        .map(|s| (s.first_line, s.last_line, s.condition.eval(&defines)))
+        .collect::<Vec<_>>();
+    assert_eq!(lines, vec![(2, 2, true), (4, 4, false), (6, 6, false)]);
+    let messages = errors
+        .diagnostics()
+        .iter()
+        .map(|d| d.message.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            "can't parse the condition of `#if'",
+            "`#if' without `#endif'"
+        ]
+    );
+}
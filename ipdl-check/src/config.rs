@@ -0,0 +1,390 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Configuration files, which are called `ipdl.toml'.
+//
+// The settings for a file come from the config files in its directory and
+// every directory above it, up to a config that sets `root = true'. Nearer
+// files take precedence, so a team can make the files in its own directory
+// more or less strict than the rest of the tree:
+//
+//     root = true
+//
+//     [lints]
+//     empty_struct = "allow"
+//     sync_without_returns = "deny"
+//
+//     [sync]
+//     allowed = ["PContent::SyncMsg"]
+//
+// Lint levels are merged one code at a time, and the levels in
+// CheckOptions::lint_levels, like `-D redundant_using', take precedence over
+// all of them. A list of allowed sync
+// messages replaces the list from the directories above, rather than adding
+// to it. When there is a list, sync messages that aren't in it get an
+// `unlisted_sync_message' warning. CheckOptions::sync_messages, from a
+// list in ipdl.py's format, takes precedence over the lists of all of the
+// configs. Configs can also declare policies, which
+// are described in policy.rs, direction rules, which are described in
+// direction_rules.rs, and Shmem restrictions, which are described in
+// shmem.rs.
+
+use direction_rules::{self, DirectionRule};
+use errors::{sort_diagnostics, Diagnostic, Errors, IpdlError, Severity};
+use ir::TypedProgram;
+use policy::{self, Policy};
+use shmem;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{parse_toml, TomlEntry, TomlValue};
+use type_check::CheckOptions;
+
+pub const CONFIG_FILE_NAME: &str = "ipdl.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    // Report the warning as an error.
+    Deny,
+}
+
+impl LintLevel {
+    pub fn from_name(name: &str) -> Option<LintLevel> {
+        match name {
+            "allow" => Some(LintLevel::Allow),
+            "warn" => Some(LintLevel::Warn),
+            "deny" => Some(LintLevel::Deny),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    // Whether the config files in the directories above are ignored.
+    pub root: bool,
+    pub lints: HashMap<String, LintLevel>,
+    // The sync messages that may be declared, like `PContent::SyncMsg'. If
+    // this is None, any sync message may be.
+    pub allowed_sync_messages: Option<Vec<String>>,
+    pub policies: Vec<Policy>,
+    pub direction_rules: Vec<DirectionRule>,
+    pub shmem_require_attribute: Option<bool>,
+    pub shmem_forbid_in_sync_replies: Option<bool>,
+}
+
+fn expect_string<'a>(entry: &'a TomlEntry, errors: &mut Errors) -> Option<&'a str> {
+    match &entry.value {
+        TomlValue::String(s) => Some(s),
+        v => {
+            errors.append_one(
+                &entry.loc,
+                &format!("`{}' must be a string, not {}", entry.key, v.type_name()),
+            );
+            None
+        }
+    }
+}
+
+fn expect_bool(entry: &TomlEntry, errors: &mut Errors) -> Option<bool> {
+    match entry.value {
+        TomlValue::Bool(b) => Some(b),
+        ref v => {
+            errors.append_one(
+                &entry.loc,
+                &format!("`{}' must be a boolean, not {}", entry.key, v.type_name()),
+            );
+            None
+        }
+    }
+}
+
+fn expect_strings(entry: &TomlEntry, errors: &mut Errors) -> Option<Vec<String>> {
+    if let TomlValue::Array(items) = &entry.value {
+        let strings = items
+            .iter()
+            .filter_map(|v| match v {
+                TomlValue::String(s) => Some(s.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if strings.len() == items.len() {
+            return Some(strings);
+        }
+    }
+    errors.append_one(
+        &entry.loc,
+        &format!("`{}' must be an array of strings", entry.key),
+    );
+    None
+}
+
+impl Config {
+    pub fn parse(file_name: &Path, text: &str) -> Result<Config, Errors> {
+        let mut config = Config::default();
+        let mut errors = Errors::none();
+        for table in parse_toml(file_name, text)? {
+            if table.name == "policy" {
+                config
+                    .policies
+                    .extend(Policy::from_table(&table, &mut errors));
+                continue;
+            }
+            if table.name == "direction-rule" {
+                config
+                    .direction_rules
+                    .extend(DirectionRule::from_table(&table, &mut errors));
+                continue;
+            }
+            for entry in &table.entries {
+                match (table.name.as_str(), entry.key.as_str()) {
+                    ("", "root") => {
+                        if let Some(b) = expect_bool(entry, &mut errors) {
+                            config.root = b;
+                        }
+                    }
+                    ("lints", code) => {
+                        let name = match expect_string(entry, &mut errors) {
+                            Some(name) => name,
+                            None => continue,
+                        };
+                        let level = match LintLevel::from_name(name) {
+                            Some(level) => level,
+                            None => {
+                                errors.append_one(
+                                    &entry.loc,
+                                    &format!(
+                                        "unknown lint level `{}', expected `allow', `warn' or `deny'",
+                                        name
+                                    ),
+                                );
+                                continue;
+                            }
+                        };
+                        config.lints.insert(String::from(code), level);
+                    }
+                    ("sync", "allowed") => {
+                        config.allowed_sync_messages = expect_strings(entry, &mut errors);
+                    }
+                    ("shmem", "require-attribute") => {
+                        config.shmem_require_attribute = expect_bool(entry, &mut errors);
+                    }
+                    ("shmem", "forbid-in-sync-replies") => {
+                        config.shmem_forbid_in_sync_replies = expect_bool(entry, &mut errors);
+                    }
+                    (table, key) => {
+                        let name = if table.is_empty() {
+                            String::from(key)
+                        } else {
+                            format!("{}.{}", table, key)
+                        };
+                        errors.append_one(&entry.loc, &format!("unknown setting `{}'", name));
+                    }
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Apply the settings of a config that is nearer to the file.
+    pub fn merge(&mut self, nearer: &Config) {
+        for (code, level) in &nearer.lints {
+            self.lints.insert(code.clone(), *level);
+        }
+        if let Some(allowed) = &nearer.allowed_sync_messages {
+            self.allowed_sync_messages = Some(allowed.clone());
+        }
+        if nearer.shmem_require_attribute.is_some() {
+            self.shmem_require_attribute = nearer.shmem_require_attribute;
+        }
+        if nearer.shmem_forbid_in_sync_replies.is_some() {
+            self.shmem_forbid_in_sync_replies = nearer.shmem_forbid_in_sync_replies;
+        }
+        for p in &nearer.policies {
+            match self.policies.iter_mut().find(|q| q.name == p.name) {
+                Some(q) => *q = p.clone(),
+                None => self.policies.push(p.clone()),
+            }
+        }
+        for r in &nearer.direction_rules {
+            let pattern = r.pattern.as_str();
+            match self
+                .direction_rules
+                .iter_mut()
+                .find(|q| q.pattern.as_str() == pattern)
+            {
+                Some(q) => *q = r.clone(),
+                None => self.direction_rules.push(r.clone()),
+            }
+        }
+    }
+
+    pub fn level(&self, code: &str) -> LintLevel {
+        self.lints.get(code).cloned().unwrap_or(LintLevel::Warn)
+    }
+}
+
+// Finds and merges the config files for the directories that IPDL files
+// are in. Each directory is only looked at once.
+#[derive(Default)]
+pub struct ConfigLoader {
+    configs: HashMap<PathBuf, Config>,
+}
+
+impl ConfigLoader {
+    pub fn new() -> ConfigLoader {
+        ConfigLoader::default()
+    }
+
+    // The merged config for the files in a directory, which must be
+    // canonical.
+    pub fn config_for_dir(&mut self, dir: &Path) -> Result<Config, IpdlError> {
+        if let Some(config) = self.configs.get(dir) {
+            return Ok(config.clone());
+        }
+        let file = dir.join(CONFIG_FILE_NAME);
+        let own = if file.is_file() {
+            let text = fs::read_to_string(&file)
+                .map_err(|e| IpdlError::Io(format!("Can't read `{}': {}", file.display(), e)))?;
+            let config = Config::parse(&file, &text).map_err(|e| {
+                let mut diagnostics = e.into_diagnostics();
+                sort_diagnostics(&mut diagnostics);
+                IpdlError::Parse(diagnostics)
+            })?;
+            Some(config)
+        } else {
+            None
+        };
+        let mut config = match (&own, dir.parent()) {
+            (Some(own), _) if own.root => Config::default(),
+            (_, Some(parent)) => self.config_for_dir(parent)?,
+            (_, None) => Config::default(),
+        };
+        if let Some(own) = &own {
+            config.merge(own);
+        }
+        self.configs.insert(dir.to_path_buf(), config.clone());
+        Ok(config)
+    }
+
+    pub fn config_for_file(&mut self, file: &Path) -> Result<Config, IpdlError> {
+        let file = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+        match file.parent() {
+            Some(dir) => self.config_for_dir(dir),
+            None => Ok(Config::default()),
+        }
+    }
+}
+
+// The messages in a sync message list of ipdl.py, which is an INI file
+// with a section for each message, like `[PContent::SyncMsg]'.
+pub fn parse_sync_message_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('[') && line.ends_with(']'))
+        .map(|line| String::from(line[1..line.len() - 1].trim()))
+        .collect()
+}
+
+fn check_sync_messages(
+    program: &TypedProgram,
+    mut loader: Option<&mut ConfigLoader>,
+    listed: Option<&Vec<String>>,
+) -> Result<Vec<Diagnostic>, IpdlError> {
+    let mut warnings = Vec::new();
+    for tuid in program.tu_ids() {
+        let ptype = match &program.tuts[&tuid].protocol {
+            Some(ptype) => ptype,
+            None => continue,
+        };
+        let allowed = match (listed, loader.as_mut()) {
+            (Some(listed), _) => listed.clone(),
+            (None, Some(loader)) => {
+                let config = loader.config_for_file(&program.tus[&tuid].file_name)?;
+                match config.allowed_sync_messages {
+                    Some(allowed) => allowed,
+                    None => continue,
+                }
+            }
+            (None, None) => continue,
+        };
+        for md in ptype.messages.iter().filter(|md| md.is_sync()) {
+            let name = format!("{}::{}", ptype.qname.short_name(), md.name.id);
+            if !allowed.contains(&name) {
+                warnings.push(Diagnostic::from_catalog(
+                    &md.name.loc,
+                    "unlisted_sync_message",
+                    &[("message", &name)],
+                ));
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+// Check the program against the config files for its files, and apply
+// their lint levels to the warnings. If any warning is denied, all of the
+// diagnostics are returned as an IpdlError::Lint.
+pub fn apply_configs(
+    program: &TypedProgram,
+    warnings: Vec<Diagnostic>,
+) -> Result<Vec<Diagnostic>, IpdlError> {
+    apply_configs_with(program, warnings, &mut ConfigLoader::new())
+}
+
+// Like apply_configs, but use the configs that |loader| has already read.
+pub fn apply_configs_with(
+    program: &TypedProgram,
+    warnings: Vec<Diagnostic>,
+    loader: &mut ConfigLoader,
+) -> Result<Vec<Diagnostic>, IpdlError> {
+    apply_lint_levels(program, warnings, Some(loader), &CheckOptions::default())
+}
+
+// Like apply_configs_with, but the levels in CheckOptions::lint_levels,
+// which usually come from the command line, take precedence over the ones
+// in the config files. Without a |loader|, the config files aren't used at
+// all, and only the options are applied. Repeated diagnostics are only
+// dropped if CheckOptions::limit_repeats is set.
+pub fn apply_lint_levels(
+    program: &TypedProgram,
+    warnings: Vec<Diagnostic>,
+    mut loader: Option<&mut ConfigLoader>,
+    options: &CheckOptions,
+) -> Result<Vec<Diagnostic>, IpdlError> {
+    let overrides = &options.lint_levels;
+    let mut errors = Errors::none();
+    let mut config_warnings = check_sync_messages(
+        program,
+        loader.as_deref_mut(),
+        options.sync_messages.as_ref(),
+    )?;
+    if let Some(loader) = loader.as_mut() {
+        config_warnings.extend(policy::check_policies(program, loader)?);
+        config_warnings.extend(direction_rules::check_direction_rules(program, loader)?);
+        config_warnings.extend(shmem::check_shmem(program, loader)?);
+    }
+    for mut d in warnings.into_iter().chain(config_warnings) {
+        let level = match (&d.loc, d.code, loader.as_mut()) {
+            (_, Some(code), _) if overrides.contains_key(code) => overrides[code],
+            (Some(loc), Some(code), Some(loader)) => {
+                loader.config_for_file(&loc.file_name)?.level(code)
+            }
+            _ => LintLevel::Warn,
+        };
+        match level {
+            LintLevel::Allow => continue,
+            LintLevel::Warn => (),
+            LintLevel::Deny => d.severity = Severity::Error,
+        }
+        errors.push(d);
+    }
+    errors.into_warnings_with_limit(IpdlError::Lint, options.limit_repeats)
+}
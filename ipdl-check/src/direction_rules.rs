@@ -0,0 +1,177 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Direction rules, which say which way messages with certain names should
+// be sent. They are declared in `[[direction-rule]]' tables of ipdl.toml
+// files:
+//
+//     [[direction-rule]]
+//     pattern = "^Notify"
+//     direction = "child"
+//
+// The direction is the section that a matching message should be in, so
+// here every message whose name starts with `Notify' must be in a `child:'
+// section, or in a `both:' one. A message whose declared direction doesn't
+// allow the rule's direction gets a `message_direction' warning. Nothing is
+// checked unless a config declares a rule. A nearer config replaces a rule
+// with the same pattern.
+
+use ast::{Direction, Location, ProtocolSide};
+use config::ConfigLoader;
+use errors::{Diagnostic, Errors, IpdlError};
+use ir::TypedProgram;
+use regex::Regex;
+use toml::{TomlTable, TomlValue};
+
+#[derive(Debug, Clone)]
+pub struct DirectionRule {
+    pub pattern: Regex,
+    pub direction: Direction,
+    // Where the rule is declared.
+    pub loc: Location,
+}
+
+// Regexes can't be compared, so rules are the same if their patterns are.
+impl PartialEq for DirectionRule {
+    fn eq(&self, other: &DirectionRule) -> bool {
+        self.pattern.as_str() == other.pattern.as_str()
+            && self.direction == other.direction
+            && self.loc == other.loc
+    }
+}
+
+fn section_name(d: Direction) -> &'static str {
+    match d {
+        Direction::To(ProtocolSide::Parent) => "parent",
+        Direction::To(ProtocolSide::Child) => "child",
+        Direction::Both => "both",
+    }
+}
+
+impl DirectionRule {
+    pub fn from_table(table: &TomlTable, errors: &mut Errors) -> Option<DirectionRule> {
+        let mut pattern = None;
+        let mut direction = None;
+        let mut ok = true;
+        for entry in &table.entries {
+            let value = match &entry.value {
+                TomlValue::String(s) => s.as_str(),
+                v => {
+                    errors.append_one(
+                        &entry.loc,
+                        &format!("`{}' must be a string, not {}", entry.key, v.type_name()),
+                    );
+                    ok = false;
+                    continue;
+                }
+            };
+            match entry.key.as_str() {
+                "pattern" => match Regex::new(value) {
+                    Ok(r) => pattern = Some(r),
+                    Err(e) => {
+                        // The syntax errors quote the pattern with a caret
+                        // under the problem, and end with what it is.
+                        let e = e.to_string();
+                        let reason = e.lines().last().unwrap_or("");
+                        errors.append_one(
+                            &entry.loc,
+                            &format!(
+                                "invalid pattern `{}': {}",
+                                value,
+                                reason.trim_start_matches("error: ")
+                            ),
+                        );
+                        ok = false;
+                    }
+                },
+                "direction" => match value {
+                    "parent" => direction = Some(Direction::To(ProtocolSide::Parent)),
+                    "child" => direction = Some(Direction::To(ProtocolSide::Child)),
+                    "both" => direction = Some(Direction::Both),
+                    _ => {
+                        errors.append_one(
+                            &entry.loc,
+                            &format!(
+                                "unknown value `{}' for `direction', expected `parent', `child' or `both'",
+                                value
+                            ),
+                        );
+                        ok = false;
+                    }
+                },
+                key => {
+                    errors.append_one(
+                        &entry.loc,
+                        &format!("unknown direction rule setting `{}'", key),
+                    );
+                    ok = false;
+                }
+            }
+        }
+        if !ok {
+            return None;
+        }
+        match (pattern, direction) {
+            (Some(pattern), Some(direction)) => Some(DirectionRule {
+                pattern,
+                direction,
+                loc: table.loc.clone(),
+            }),
+            _ => {
+                errors.append_one(
+                    &table.loc,
+                    "a direction rule needs a `pattern' and a `direction'",
+                );
+                None
+            }
+        }
+    }
+
+    // Whether a message declared with direction |d| follows the rule. A
+    // `both' message can be sent either way, so it satisfies a rule for
+    // either side, but a rule for `both' needs a `both' message.
+    fn allows(&self, d: Direction) -> bool {
+        d == self.direction || d == Direction::Both
+    }
+}
+
+// Check every message against the direction rules in the config for its
+// file.
+pub fn check_direction_rules(
+    program: &TypedProgram,
+    loader: &mut ConfigLoader,
+) -> Result<Vec<Diagnostic>, IpdlError> {
+    let mut diagnostics = Vec::new();
+    for tuid in program.tu_ids() {
+        let ptype = match &program.tuts[&tuid].protocol {
+            Some(ptype) => ptype,
+            None => continue,
+        };
+        let config = loader.config_for_file(&program.tus[&tuid].file_name)?;
+        for md in &ptype.messages {
+            for rule in &config.direction_rules {
+                if !rule.pattern.is_match(&md.name.id) || rule.allows(md.direction) {
+                    continue;
+                }
+                diagnostics.push(
+                    Diagnostic::from_catalog(
+                        &md.name.loc,
+                        "message_direction",
+                        &[
+                            (
+                                "message",
+                                &format!("{}::{}", ptype.qname.short_name(), md.name.id),
+                            ),
+                            ("section", &section_name(md.direction)),
+                            ("pattern", &rule.pattern.as_str()),
+                            ("rule_section", &section_name(rule.direction)),
+                        ],
+                    )
+                    .with_note(&rule.loc, "the rule is declared here"),
+                );
+            }
+        }
+    }
+    Ok(diagnostics)
+}
@@ -0,0 +1,109 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// The graph of which protocols can open which. A message that carries an
+// Endpoint or a ManagedEndpoint of a protocol, as a parameter or in its
+// reply, lets the side that gets it bind an actor of that protocol, so it
+// is an edge from the protocol of the message to the protocol of the
+// endpoint. The type checker checks that the side that binds an endpoint
+// can be in the same process as the side of the protocol that it is for.
+//
+// endpoint_graph_dot() writes the graph for Graphviz, with managed
+// endpoints as dashed edges.
+
+use ast::{Direction, Location, ProtocolSide, TUId};
+use ir::TypedProgram;
+use type_check::{endpoint_target, endpoint_type, IPDLType};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndpointEdge {
+    // The protocol of the message that carries the endpoint.
+    pub protocol: TUId,
+    pub message: String,
+    // The parameter or return value with the endpoint.
+    pub loc: Location,
+    // The side of |protocol| that binds the endpoint, or None for a message
+    // that either side can send.
+    pub bound_by: Option<ProtocolSide>,
+    // The protocol that the endpoint opens, and the side of it.
+    pub opens: TUId,
+    pub side: ProtocolSide,
+    pub managed: bool,
+}
+
+// Every endpoint sent in a message, with the protocols in the order of
+// their names and the endpoints in the order they are declared.
+pub fn endpoint_edges(program: &TypedProgram) -> Vec<EndpointEdge> {
+    let mut edges = Vec::new();
+    for tuid in program.decl_order().protocols() {
+        for md in &program.protocol(tuid).messages {
+            let params = md.params.iter().map(|p| (p, false));
+            for (param, is_return) in params.chain(md.returns.iter().map(|p| (p, true))) {
+                let endpoint = match endpoint_type(param.param_type.lookup(&program.tuts)) {
+                    Some(endpoint) => endpoint,
+                    None => continue,
+                };
+                let (opens, side) = match endpoint_target(&program.tuts, endpoint) {
+                    Some(target) => target,
+                    None => continue,
+                };
+                let bound_by = match (md.direction, is_return) {
+                    (Direction::To(side), false) => Some(side),
+                    (Direction::To(ProtocolSide::Parent), true) => Some(ProtocolSide::Child),
+                    (Direction::To(ProtocolSide::Child), true) => Some(ProtocolSide::Parent),
+                    (Direction::Both, _) => None,
+                };
+                edges.push(EndpointEdge {
+                    protocol: *tuid,
+                    message: md.name.id.clone(),
+                    loc: param.name.loc.clone(),
+                    bound_by,
+                    opens,
+                    side,
+                    managed: matches!(endpoint, IPDLType::ManagedEndpointType(_, _)),
+                });
+            }
+        }
+    }
+    edges
+}
+
+pub fn side_name(side: Option<ProtocolSide>) -> &'static str {
+    match side {
+        Some(ProtocolSide::Parent) => "parent",
+        Some(ProtocolSide::Child) => "child",
+        None => "either",
+    }
+}
+
+// The graph in the Graphviz dot language. Each protocol that sends or gets
+// an endpoint is a node, and each edge is labeled with the message, the
+// side of the protocol that binds the endpoint, and the side it opens.
+pub fn endpoint_graph_dot(program: &TypedProgram) -> String {
+    let edges = endpoint_edges(program);
+    let name = |tuid: &TUId| program.protocol(tuid).qname.to_string();
+    let mut out = String::from("digraph endpoints {\n");
+    let mut nodes = Vec::new();
+    for e in &edges {
+        for tuid in &[e.protocol, e.opens] {
+            if !nodes.contains(tuid) {
+                nodes.push(*tuid);
+                out.push_str(&format!("  \"{}\";\n", name(tuid)));
+            }
+        }
+    }
+    for e in &edges {
+        out.push_str(&format!(
+            "  \"{}\" -> \"{}\" [label=\"{} ({} opens {})\"{}];\n",
+            name(&e.protocol),
+            name(&e.opens),
+            e.message,
+            side_name(e.bound_by),
+            side_name(Some(e.side)),
+            if e.managed { ", style=dashed" } else { "" }
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
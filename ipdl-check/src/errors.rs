@@ -0,0 +1,561 @@
+use ast::Location;
+use catalog;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// A secondary location that is related to an error, like the first
+// declaration of a symbol that has been redeclared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub loc: Location,
+    pub message: String,
+}
+
+// A change to a file: the |len| bytes at |loc| are replaced with
+// |replacement|.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub loc: Location,
+    pub len: usize,
+    pub replacement: String,
+}
+
+// The byte offset of the start of each line of |text|.
+pub fn line_starts(text: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+    starts
+}
+
+impl Edit {
+    // The byte range of |text| that the edit replaces, given the
+    // line_starts of |text|, or None if the location isn't in |text|, like
+    // when the file changed after it was checked.
+    pub fn range(&self, text: &str, line_starts: &[usize]) -> Option<(usize, usize)> {
+        let line_start = *line_starts.get(self.loc.lineno.checked_sub(1)?)?;
+        let start = line_start + self.loc.colno;
+        let end = start + self.len;
+        if end <= text.len() && text.is_char_boundary(start) && text.is_char_boundary(end) {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+}
+
+// A fix-it: edits to one file that together fix the problem that a
+// diagnostic is about, and what they do.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    pub message: String,
+    pub edits: Vec<Edit>,
+}
+
+impl Fix {
+    // The text of the file with the edits applied, where |text| is the text
+    // that the locations of the edits refer to. Edits whose locations
+    // aren't in |text|, or that overlap an earlier edit, are left out.
+    pub fn apply(&self, text: &str) -> String {
+        let line_starts = line_starts(text);
+        let mut edits = self
+            .edits
+            .iter()
+            .filter_map(|e| Some((e.range(text, &line_starts)?, e)))
+            .collect::<Vec<_>>();
+        edits.sort_by_key(|((start, _), _)| *start);
+        let mut out = String::new();
+        let mut end = 0;
+        for ((start, edit_end), e) in edits {
+            if start < end {
+                continue;
+            }
+            out.push_str(&text[end..start]);
+            out.push_str(&e.replacement);
+            end = edit_end;
+        }
+        out.push_str(&text[end..]);
+        out
+    }
+
+    // Whether the fix can be applied as it is: it has edits, and they are
+    // all in the same file.
+    pub fn is_machine_applicable(&self) -> bool {
+        match self.edits.first() {
+            Some(first) => self
+                .edits
+                .iter()
+                .all(|e| e.loc.file_name == first.loc.file_name),
+            None => false,
+        }
+    }
+}
+
+// Whether a diagnostic has a fix, and whether a tool can apply it without
+// anyone looking at it first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FixAvailability {
+    NoFix,
+    MachineApplicable,
+    // The fix can only be shown, because it has no edits or changes
+    // several files.
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    // A warning doesn't stop the compilation.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // A name for the kind of warning, like `sync_without_returns', which
+    // can be used to suppress it.
+    pub code: Option<&'static str>,
+    // The name that the diagnostic is about, like the unknown type in an
+    // `unknown type' error. Only the first few diagnostics with the same
+    // code and symbol are reported; see limit_diagnostics.
+    pub symbol: Option<String>,
+    // Some errors, like failing to read a file, have no location.
+    pub loc: Option<Location>,
+    pub message: String,
+    pub notes: Vec<Note>,
+    // The chain of files that included the file the error is in, outermost
+    // first.
+    pub included_from: Vec<PathBuf>,
+    // The source line that the error is on, with a caret under the
+    // location. See SourceManager::add_snippets.
+    pub snippet: Option<String>,
+    pub fix: Option<Fix>,
+}
+
+impl Diagnostic {
+    pub fn new(loc: &Location, message: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            symbol: None,
+            loc: Some(loc.clone()),
+            message: String::from(message),
+            notes: Vec::new(),
+            included_from: Vec::new(),
+            snippet: None,
+            fix: None,
+        }
+    }
+
+    pub fn warning(loc: &Location, message: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::new(loc, message)
+        }
+    }
+
+    // A diagnostic with a message from the catalog, which also gives its
+    // code and severity. |id| has to be in the catalog.
+    pub fn from_catalog(
+        loc: &Location,
+        id: &str,
+        args: &[(&str, &dyn fmt::Display)],
+    ) -> Diagnostic {
+        let entry = catalog::entry(id).expect("diagnostic id missing from the catalog");
+        Diagnostic {
+            severity: entry.severity,
+            code: Some(entry.code()),
+            ..Diagnostic::new(loc, &catalog::fill(entry.text, args))
+        }
+    }
+
+    // An error for a situation that the checker doesn't expect to be
+    // possible, like a missing declaration that an earlier pass should have
+    // made. It is reported instead of panicking, so that a bad file can't
+    // crash the tool.
+    pub fn internal(loc: &Location, message: &str) -> Diagnostic {
+        Diagnostic::new(loc, &format!("internal error: {}", message))
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Diagnostic {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_symbol(mut self, symbol: &str) -> Diagnostic {
+        self.symbol = Some(String::from(symbol));
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn fix_availability(&self) -> FixAvailability {
+        match &self.fix {
+            Some(fix) if fix.is_machine_applicable() => FixAvailability::MachineApplicable,
+            Some(_) => FixAvailability::Manual,
+            None => FixAvailability::NoFix,
+        }
+    }
+
+    // The fix, if it can be applied as it is.
+    pub fn applicable_fix(&self) -> Option<&Fix> {
+        self.fix.as_ref().filter(|fix| fix.is_machine_applicable())
+    }
+
+    pub fn with_fix(mut self, message: &str, edits: Vec<Edit>) -> Diagnostic {
+        self.fix = Some(Fix {
+            message: String::from(message),
+            edits,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, loc: &Location, message: &str) -> Diagnostic {
+        self.notes.push(Note {
+            loc: loc.clone(),
+            message: String::from(message),
+        });
+        self
+    }
+
+    pub fn without_location(message: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            symbol: None,
+            loc: None,
+            message: String::from(message),
+            notes: Vec::new(),
+            included_from: Vec::new(),
+            snippet: None,
+            fix: None,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for file in &self.included_from {
+            writeln!(f, "  in file included from `{}':", file.display())?;
+        }
+        match &self.loc {
+            Some(loc) => write!(f, "{}: {}: {}", loc, self.severity, self.message)?,
+            None => write!(f, "{}: {}", self.severity, self.message)?,
+        }
+        if let Some(code) = self.code {
+            write!(f, " [{}]", code)?;
+        }
+        if let Some(snippet) = &self.snippet {
+            write!(f, "\n{}", snippet)?;
+        }
+        for note in &self.notes {
+            write!(f, "\n{}: note: {}", note.loc, note.message)?;
+        }
+        if let Some(fix) = &self.fix {
+            match &self.loc {
+                Some(loc) => write!(f, "\n{}: fix: {}", loc, fix.message)?,
+                None => write!(f, "\nfix: {}", fix.message)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[must_use]
+pub struct Errors {
+    errors: Vec<Diagnostic>,
+}
+
+impl Errors {
+    pub fn none() -> Errors {
+        Errors { errors: Vec::new() }
+    }
+
+    pub fn one(loc: &Location, err: &str) -> Errors {
+        Errors {
+            errors: vec![Diagnostic::new(loc, err)],
+        }
+    }
+
+    pub fn from_diagnostic(diagnostic: Diagnostic) -> Errors {
+        Errors {
+            errors: vec![diagnostic],
+        }
+    }
+
+    pub fn append(&mut self, mut other: Errors) {
+        self.errors.append(&mut other.errors);
+    }
+
+    pub fn append_one(&mut self, loc: &Location, other: &str) {
+        self.errors.push(Diagnostic::new(loc, other));
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.errors.push(diagnostic);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    // Whether there is anything worse than a warning.
+    pub fn has_errors(&self) -> bool {
+        self.errors.iter().any(Diagnostic::is_error)
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.errors
+    }
+
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.errors
+    }
+
+    // Turn any errors into an error of the given kind, like
+    // IpdlError::TypeCheck. The errors are sorted by location, because the
+    // order the checker finds them in depends on hash map iteration order.
+    // Warnings are dropped if there are no errors.
+    pub fn into_result<F>(self, kind: F) -> Result<(), IpdlError>
+    where
+        F: FnOnce(Vec<Diagnostic>) -> IpdlError,
+    {
+        self.into_warnings(kind).map(|_| ())
+    }
+
+    // Like into_result, but return the sorted warnings if there are no
+    // errors. If there are errors, the warnings are returned along with
+    // them.
+    pub fn into_warnings<F>(self, kind: F) -> Result<Vec<Diagnostic>, IpdlError>
+    where
+        F: FnOnce(Vec<Diagnostic>) -> IpdlError,
+    {
+        self.into_warnings_with_limit(kind, true)
+    }
+
+    // Like into_warnings, but the diagnostics that repeat the code and
+    // symbol of earlier ones are only dropped if |limit| is set; see
+    // limit_diagnostics.
+    pub fn into_warnings_with_limit<F>(
+        mut self,
+        kind: F,
+        limit: bool,
+    ) -> Result<Vec<Diagnostic>, IpdlError>
+    where
+        F: FnOnce(Vec<Diagnostic>) -> IpdlError,
+    {
+        sort_diagnostics(&mut self.errors);
+        if limit {
+            limit_diagnostics(&mut self.errors);
+        }
+        if self.has_errors() {
+            Err(kind(self.errors))
+        } else {
+            Ok(self.errors)
+        }
+    }
+}
+
+// The diagnostics of a check, narrowed down by severity, code or file, for
+// the tools that show or apply them, so that `--apply-fixes' and an
+// editor's language server agree about which fixes can be applied.
+#[derive(Debug, Clone)]
+pub struct Diagnostics<'a> {
+    diagnostics: Vec<&'a Diagnostic>,
+}
+
+impl<'a> Diagnostics<'a> {
+    pub fn new(diagnostics: &'a [Diagnostic]) -> Diagnostics<'a> {
+        Diagnostics {
+            diagnostics: diagnostics.iter().collect(),
+        }
+    }
+
+    fn filter<F>(mut self, keep: F) -> Diagnostics<'a>
+    where
+        F: Fn(&Diagnostic) -> bool,
+    {
+        self.diagnostics.retain(|d| keep(d));
+        self
+    }
+
+    pub fn with_severity(self, severity: Severity) -> Diagnostics<'a> {
+        self.filter(|d| d.severity == severity)
+    }
+
+    pub fn with_code(self, code: &str) -> Diagnostics<'a> {
+        self.filter(|d| d.code == Some(code))
+    }
+
+    // The diagnostics whose location is in |file|. Diagnostics without a
+    // location aren't in any file.
+    pub fn in_file(self, file: &Path) -> Diagnostics<'a> {
+        self.filter(|d| d.loc.as_ref().is_some_and(|loc| loc.file_name == file))
+    }
+
+    // The diagnostics with a fix that can be applied as it is.
+    pub fn fixable(self) -> Diagnostics<'a> {
+        self.filter(|d| d.fix_availability() == FixAvailability::MachineApplicable)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a Diagnostic> + '_ {
+        self.diagnostics.iter().cloned()
+    }
+
+    // The fixes that can be applied as they are, in the order of their
+    // diagnostics.
+    pub fn fixes(&self) -> impl Iterator<Item = &'a Fix> + '_ {
+        self.iter().filter_map(Diagnostic::applicable_fix)
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+// Sort diagnostics by file, then line and column. Diagnostics without a
+// location come first. The sort is stable, so diagnostics at the same
+// location stay in the order they were reported in.
+pub fn sort_diagnostics(diagnostics: &mut [Diagnostic]) {
+    diagnostics.sort_by(|a, b| match (&a.loc, &b.loc) {
+        (Some(a), Some(b)) => {
+            (&a.file_name, a.lineno, a.colno).cmp(&(&b.file_name, b.lineno, b.colno))
+        }
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    });
+}
+
+// How many diagnostics with the same code and symbol are reported. The
+// rest are almost always the same problem showing up again, like a type
+// with a missing `using' in every message that uses it.
+const SYMBOL_LIMIT: usize = 3;
+
+// Drop the diagnostics after the first SYMBOL_LIMIT ones with the same code
+// and symbol. The last one that is kept gets a note about how many were
+// dropped, at the first one that was.
+pub fn limit_diagnostics(diagnostics: &mut Vec<Diagnostic>) {
+    let mut groups: HashMap<(&'static str, &str), Vec<usize>> = HashMap::new();
+    for (i, d) in diagnostics.iter().enumerate() {
+        if let (Some(code), Some(symbol)) = (d.code, &d.symbol) {
+            groups.entry((code, symbol)).or_default().push(i);
+        }
+    }
+    let mut notes = Vec::new();
+    let mut dropped = HashSet::new();
+    for ((_, symbol), indices) in groups {
+        if indices.len() <= SYMBOL_LIMIT {
+            continue;
+        }
+        let last = indices[SYMBOL_LIMIT - 1];
+        let d = &diagnostics[last];
+        if let Some(loc) = diagnostics[indices[SYMBOL_LIMIT]]
+            .loc
+            .as_ref()
+            .or(d.loc.as_ref())
+        {
+            let message = format!(
+                "{} limit reached for `{}': {} more like this are not reported",
+                d.severity,
+                symbol,
+                indices.len() - SYMBOL_LIMIT
+            );
+            notes.push((last, loc.clone(), message));
+        }
+        dropped.extend(indices[SYMBOL_LIMIT..].iter().cloned());
+    }
+    for (i, loc, message) in notes {
+        diagnostics[i].notes.push(Note { loc, message });
+    }
+    let mut i = 0;
+    diagnostics.retain(|_| {
+        i += 1;
+        !dropped.contains(&(i - 1))
+    });
+}
+
+// Format diagnostics, with a header before the diagnostics for each file.
+// Diagnostics for the same file must be next to each other, as they are
+// after sort_diagnostics.
+fn format_diagnostics(f: &mut fmt::Formatter, diagnostics: &[Diagnostic]) -> fmt::Result {
+    let mut curr_file = None;
+    for (i, d) in diagnostics.iter().enumerate() {
+        if i > 0 {
+            writeln!(f)?;
+        }
+        if let Some(loc) = &d.loc {
+            if curr_file != Some(&loc.file_name) {
+                writeln!(f, "In file `{}':", loc.file_name.display())?;
+                curr_file = Some(&loc.file_name);
+            }
+        }
+        write!(f, "{}", d)?;
+    }
+    Ok(())
+}
+
+// The kind of failure that stopped a compilation. Each kind is reported
+// with its own process exit code, so that build wrappers can tell them
+// apart without having to look at the error text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IpdlError {
+    // A file could not be read, or an include could not be resolved.
+    Io(String),
+    Parse(Vec<Diagnostic>),
+    TypeCheck(Vec<Diagnostic>),
+    // Only lints failed.
+    Lint(Vec<Diagnostic>),
+}
+
+impl IpdlError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            IpdlError::Io(_) => 2,
+            IpdlError::Parse(_) => 3,
+            IpdlError::TypeCheck(_) => 4,
+            IpdlError::Lint(_) => 5,
+        }
+    }
+
+    // The diagnostics for the failure. An I/O error has none.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            IpdlError::Io(_) => &[],
+            IpdlError::Parse(ds) | IpdlError::TypeCheck(ds) | IpdlError::Lint(ds) => ds,
+        }
+    }
+    pub fn diagnostics_mut(&mut self) -> &mut [Diagnostic] {
+        match self {
+            IpdlError::Io(_) => &mut [],
+            IpdlError::Parse(ds) | IpdlError::TypeCheck(ds) | IpdlError::Lint(ds) => ds,
+        }
+    }
+}
+
+impl fmt::Display for IpdlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IpdlError::Io(m) => write!(f, "{}", m),
+            IpdlError::Parse(ds) | IpdlError::TypeCheck(ds) | IpdlError::Lint(ds) => {
+                format_diagnostics(f, ds)
+            }
+        }
+    }
+}
+
+impl Error for IpdlError {}
@@ -0,0 +1,88 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Hooks into a check, for the programs that embed the checker, like mach,
+// an editor's language server or a daemon, to show progress and collect
+// telemetry. Every method does nothing by default, so an implementation
+// only has to have the ones it wants. A Session passes the events of each
+// check to all of the CompilerEvents that were added to it, in the order
+// they were added, while the diagnostics still go to its sinks.
+
+use errors::Diagnostic;
+use std::path::Path;
+use std::time::Duration;
+
+// The phases of a check, in the order they run. A phase that fails ends
+// the check, so the later ones don't start.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Parse,
+    TypeCheck,
+    // Applying the lint levels of the options and of the config files.
+    LintLevels,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Parse => "parse",
+            Phase::TypeCheck => "type_check",
+            Phase::LintLevels => "lint_levels",
+        }
+    }
+}
+
+pub trait CompilerEvents {
+    fn phase_started(&mut self, _phase: Phase) {}
+
+    fn phase_finished(&mut self, _phase: Phase, _time: Duration) {}
+
+    // A file was parsed, whether or not it had errors, in |time|, or was
+    // taken from the snapshot, with no time.
+    fn file_parsed(&mut self, _file: &Path, _time: Option<Duration>) {}
+
+    // The declarations of a file were taken from the gather cache, or
+    // weren't there and were gathered again.
+    fn cache_lookup(&mut self, _file: &Path, _hit: bool) {}
+
+    // The errors or warnings of a check, the same ones that its sinks get.
+    fn diagnostics_emitted(&mut self, _diagnostics: &[Diagnostic]) {}
+}
+
+// For checks that nobody is watching.
+pub struct NoEvents;
+
+impl CompilerEvents for NoEvents {}
+
+impl CompilerEvents for Vec<Box<dyn CompilerEvents>> {
+    fn phase_started(&mut self, phase: Phase) {
+        for e in self.iter_mut() {
+            e.phase_started(phase);
+        }
+    }
+
+    fn phase_finished(&mut self, phase: Phase, time: Duration) {
+        for e in self.iter_mut() {
+            e.phase_finished(phase, time);
+        }
+    }
+
+    fn file_parsed(&mut self, file: &Path, time: Option<Duration>) {
+        for e in self.iter_mut() {
+            e.file_parsed(file, time);
+        }
+    }
+
+    fn cache_lookup(&mut self, file: &Path, hit: bool) {
+        for e in self.iter_mut() {
+            e.cache_lookup(file, hit);
+        }
+    }
+
+    fn diagnostics_emitted(&mut self, diagnostics: &[Diagnostic]) {
+        for e in self.iter_mut() {
+            e.diagnostics_emitted(diagnostics);
+        }
+    }
+}
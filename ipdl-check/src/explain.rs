@@ -0,0 +1,326 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// A summary of a protocol for people to read, made from the typed IR: what
+// it manages and is managed by, each message with its parameter types
+// resolved, and the files that the types it needs come from.
+//
+// Also a table of which side can construct and delete each actor that a
+// protocol manages. An actor that the child can construct is state that a
+// compromised child can make the parent allocate, so those are flagged.
+
+use ast::{Direction, Nesting, Priority, ProtocolSide, SendSemantics, TUId};
+use ir::TypedProgram;
+use type_check::{IPDLType, MessageType};
+
+fn semantics_name(s: SendSemantics) -> &'static str {
+    match s {
+        SendSemantics::Async => "async",
+        SendSemantics::Sync => "sync",
+        SendSemantics::Intr => "intr",
+    }
+}
+
+// The names are the ones that the attributes use.
+fn nesting_name(n: Nesting) -> &'static str {
+    match n {
+        Nesting::None => "none",
+        Nesting::InsideSync => "inside_sync",
+        Nesting::InsideCpow => "inside_cpow",
+    }
+}
+
+fn priority_name(p: Priority) -> &'static str {
+    match p {
+        Priority::Normal => "normal",
+        Priority::Input => "input",
+        Priority::Vsync => "vsync",
+        Priority::Mediumhigh => "mediumhigh",
+        Priority::Control => "control",
+    }
+}
+
+fn direction_name(d: Direction) -> &'static str {
+    match d {
+        Direction::To(ProtocolSide::Parent) => "parent",
+        Direction::To(ProtocolSide::Child) => "child",
+        Direction::Both => "both",
+    }
+}
+
+// The sides that can send a message in a direction.
+fn senders(d: Direction) -> &'static [ProtocolSide] {
+    match d {
+        Direction::To(ProtocolSide::Parent) => &[ProtocolSide::Child],
+        Direction::To(ProtocolSide::Child) => &[ProtocolSide::Parent],
+        Direction::Both => &[ProtocolSide::Parent, ProtocolSide::Child],
+    }
+}
+
+fn side_names(sides: &[ProtocolSide]) -> &'static str {
+    match sides {
+        [] => "nobody",
+        [ProtocolSide::Parent] => "parent",
+        [ProtocolSide::Child] => "child",
+        _ => "both",
+    }
+}
+
+fn protocol_names(program: &TypedProgram, tuids: &[TUId]) -> String {
+    tuids
+        .iter()
+        .map(|tuid| program.protocol(tuid).qname.short_name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// The file that provides a type to a protocol, which is the file that
+// declares a struct or union, or the first of the protocol's own file and
+// its includes with a `using' for an imported type. Builtin types have no
+// file.
+fn contributing_tu(program: &TypedProgram, protocol: &TUId, t: &IPDLType) -> Option<TUId> {
+    if let IPDLType::StructType(tr) | IPDLType::UnionType(tr) = t {
+        return Some(tr.tu);
+    }
+    let name = program.qualified_name(t)?;
+    let tu = &program.tus[protocol];
+    Some(*protocol)
+        .iter()
+        .chain(&tu.includes)
+        .find(|tuid| {
+            program.tus[tuid]
+                .using
+                .iter()
+                .any(|u| u.cxx_type.to_string() == name)
+        })
+        .cloned()
+}
+
+pub fn explain_protocol(program: &TypedProgram, tuid: &TUId) -> String {
+    let ptype = program.protocol(tuid);
+    let mut lines = vec![format!(
+        "protocol {}, declared at {}",
+        ptype.qname,
+        ptype.qname.loc()
+    )];
+    if ptype.nested.is_none() {
+        lines.push(format!(
+            "  {}, not nested",
+            semantics_name(ptype.send_semantics)
+        ));
+    } else {
+        lines.push(format!(
+            "  {}, nested up to {}",
+            semantics_name(ptype.send_semantics),
+            nesting_name(ptype.nested)
+        ));
+    }
+    let process = |p: &Option<String>| p.clone().unwrap_or_else(|| String::from("unspecified"));
+    lines.push(format!(
+        "  parent process: {}, child process: {}",
+        process(&ptype.proc_parent),
+        process(&ptype.proc_child)
+    ));
+    if ptype.managers.is_empty() {
+        lines.push(String::from("  top level"));
+    } else {
+        lines.push(format!(
+            "  managed by: {}",
+            protocol_names(program, &ptype.managers)
+        ));
+    }
+    if !ptype.manages.is_empty() {
+        lines.push(format!(
+            "  manages: {}",
+            protocol_names(program, &ptype.manages)
+        ));
+    }
+
+    lines.push(String::from("Messages:"));
+    for md in &ptype.messages {
+        let mut notes = Vec::new();
+        match &md.mtype {
+            MessageType::Ctor(t) => notes.push(format!(
+                "constructs {}",
+                program.protocol(t).qname.short_name()
+            )),
+            MessageType::Dtor(_) => notes.push(String::from("destructor")),
+            MessageType::Other => (),
+        }
+        if !md.nested.is_none() {
+            notes.push(format!("nested: {}", nesting_name(md.nested)));
+        }
+        if md.prio != Priority::Normal {
+            notes.push(format!("priority: {}", priority_name(md.prio)));
+        }
+        if md.no_logging {
+            notes.push(String::from("no logging"));
+        }
+        let mut line = format!(
+            "  {}: {}",
+            direction_name(md.direction),
+            md.signature(&program.tuts)
+        );
+        if !notes.is_empty() {
+            line.push_str(&format!(" [{}]", notes.join(", ")));
+        }
+        lines.push(line);
+    }
+
+    // Group the types by the file they come from, in the order of the
+    // protocol's file and then its includes.
+    let closure = program.type_closure(tuid);
+    let types = closure
+        .structs
+        .iter()
+        .cloned()
+        .map(IPDLType::StructType)
+        .chain(closure.unions.iter().cloned().map(IPDLType::UnionType))
+        .chain(closure.imported.iter().cloned())
+        .collect::<Vec<_>>();
+    let mut files = vec![*tuid];
+    files.extend(program.tus[tuid].includes.iter().cloned());
+    for t in &types {
+        if let Some(file) = contributing_tu(program, tuid, t) {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    lines.push(String::from("Types, by the file that provides them:"));
+    let describe = |t: &IPDLType| {
+        let name = program
+            .qualified_name(t)
+            .unwrap_or_else(|| t.name(&program.tuts));
+        match t {
+            IPDLType::StructType(_) => format!("struct {}", name),
+            IPDLType::UnionType(_) => format!("union {}", name),
+            _ => match program.header_of(t) {
+                Some(header) => format!("{}, from \"{}\"", name, header),
+                None => name,
+            },
+        }
+    };
+    for file in &files {
+        let provided = types
+            .iter()
+            .filter(|t| contributing_tu(program, tuid, t) == Some(*file))
+            .collect::<Vec<_>>();
+        if provided.is_empty() {
+            continue;
+        }
+        let file_name = &program.tus[file].file_name;
+        let base_name = file_name.file_name().unwrap_or(file_name.as_os_str());
+        lines.push(format!("  {}:", base_name.to_string_lossy()));
+        for t in provided {
+            lines.push(format!("    {}", describe(t)));
+        }
+    }
+    let builtins = types
+        .iter()
+        .filter(|t| contributing_tu(program, tuid, t).is_none())
+        .map(describe)
+        .collect::<Vec<_>>();
+    if !builtins.is_empty() {
+        lines.push(format!("  builtin: {}", builtins.join(", ")));
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+// Who can create and destroy an actor that a protocol manages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActorLifetime {
+    pub actor: TUId,
+    // The sides that can send one of the manager's constructors for the
+    // actor, and the sides that can send the actor's destructor.
+    pub constructed_by: Vec<ProtocolSide>,
+    pub deleted_by: Vec<ProtocolSide>,
+}
+
+impl ActorLifetime {
+    pub fn child_constructs(&self) -> bool {
+        self.constructed_by.contains(&ProtocolSide::Child)
+    }
+}
+
+fn add_senders(sides: &mut Vec<ProtocolSide>, d: Direction) {
+    for side in senders(d) {
+        if !sides.contains(side) {
+            sides.push(*side);
+        }
+    }
+}
+
+// The lifetimes of the actors that a protocol manages, in the order of its
+// `manages' statements.
+pub fn actor_lifetimes(program: &TypedProgram, tuid: &TUId) -> Vec<ActorLifetime> {
+    let ptype = program.protocol(tuid);
+    ptype
+        .manages
+        .iter()
+        .map(|actor| {
+            let mut lifetime = ActorLifetime {
+                actor: *actor,
+                constructed_by: Vec::new(),
+                deleted_by: Vec::new(),
+            };
+            for md in &ptype.messages {
+                if md.ctor_type() == Some(actor) {
+                    add_senders(&mut lifetime.constructed_by, md.direction);
+                }
+            }
+            for md in &program.protocol(actor).messages {
+                if md.is_dtor() {
+                    add_senders(&mut lifetime.deleted_by, md.direction);
+                }
+            }
+            lifetime
+        })
+        .collect()
+}
+
+pub fn lifetime_table(program: &TypedProgram, tuid: &TUId) -> String {
+    let ptype = program.protocol(tuid);
+    let lifetimes = actor_lifetimes(program, tuid);
+    let mut text = format!("protocol {}\n", ptype.qname);
+    if lifetimes.is_empty() {
+        text.push_str("  manages nothing\n");
+        return text;
+    }
+    let names = lifetimes
+        .iter()
+        .map(|l| program.protocol(&l.actor).qname.short_name())
+        .collect::<Vec<_>>();
+    let width = names
+        .iter()
+        .map(|n| n.len())
+        .max()
+        .unwrap()
+        .max("actor".len());
+    text.push_str(&format!(
+        "  {:width$}  {:14}  {}\n",
+        "actor",
+        "constructed by",
+        "deleted by",
+        width = width
+    ));
+    for (l, name) in lifetimes.iter().zip(&names) {
+        let mut line = format!(
+            "  {:width$}  {:14}  {}",
+            name,
+            side_names(&l.constructed_by),
+            side_names(&l.deleted_by),
+            width = width
+        );
+        if l.child_constructs() {
+            line.push_str("  (child can construct parent-side state)");
+        }
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,253 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// A JSON description of the protocols in the files given on the command
+// line, for tools that want to query the typed IR without linking against
+// this crate.
+//
+// Each message has its direction, as "toParent", "toChild" or "both" like
+// the TypeScript declarations, and the sides that can send it. Each
+// protocol also lists the names of the messages that each side can send,
+// so that a question like "what can the child send to the parent" doesn't
+// need the directions to be decoded again.
+//
+// Messages also say whether their reply looks like it is never used, which
+// is what the `unused_sync_return' warning is about, so that other tools
+// can combine that guess with the returns in their own lints.
+//
+// Protocols have their `[ProtocolVersion]' and messages their `[Since]', or
+// null, so that a description saved from an older tree can be compared
+// against a newer one. See versions.rs.
+//
+// Each protocol lists the endpoints that its messages carry under "opens",
+// with the side of the protocol that binds each one. See endpoints.rs.
+//
+// Messages with `[NoLogging]' have "no_logging": true, for tools that
+// record IPC traffic and must leave out the data of those messages.
+//
+// manager_graph_json() is a smaller description of every protocol in the
+// program, with only which protocols it manages and is managed by, and the
+// constructors of each managed protocol. See managers.rs.
+//
+// Messages also have the names of the C++ methods and types that are
+// generated for them under "cxx_names", like "SendFoo" and "RecvFoo", and
+// "FooPromise" and "FooResolver" or null.
+//
+// Protocols have the sides that their `[AllocShmem]' lets allocate Shmems
+// under "alloc_shmem", or null, and messages the sides that allocate the
+// Shmems they carry under "shmem_allocated_by". See shmem.rs.
+
+use ast::{Direction, ProtocolSide, SendSemantics};
+use endpoints::{endpoint_edges, side_name};
+use ir::TypedProgram;
+use json::{json_list, json_path, json_string};
+use managers::manager_edges;
+use std::path::PathBuf;
+use type_check::{shmem_allocators, MessageType, MessageTypeDef, ParamTypeDef};
+
+pub fn direction_name(d: Direction) -> &'static str {
+    match d {
+        Direction::To(ProtocolSide::Parent) => "toParent",
+        Direction::To(ProtocolSide::Child) => "toChild",
+        Direction::Both => "both",
+    }
+}
+
+pub fn semantics_name(s: SendSemantics) -> &'static str {
+    match s {
+        SendSemantics::Async => "async",
+        SendSemantics::Sync => "sync",
+        SendSemantics::Intr => "intr",
+    }
+}
+
+fn optional_number(n: Option<u32>) -> String {
+    n.map_or(String::from("null"), |n| n.to_string())
+}
+
+fn params_json(program: &TypedProgram, params: &[ParamTypeDef]) -> String {
+    json_list(params.iter().map(|p| {
+        format!(
+            "{{\"name\": {}, \"type\": {}}}",
+            json_string(&p.name.id),
+            json_string(&p.param_type.lookup(&program.tuts).name(&program.tuts))
+        )
+    }))
+}
+
+fn sides_json(sides: &[ProtocolSide]) -> String {
+    json_list(sides.iter().map(|side| json_string(side_name(Some(*side)))))
+}
+
+// The sides that can send a message in a direction.
+fn senders_json(d: Direction) -> String {
+    let mut senders = Vec::new();
+    if d.is_to_child() {
+        senders.push(json_string("parent"));
+    }
+    if d.is_to_parent() {
+        senders.push(json_string("child"));
+    }
+    json_list(senders)
+}
+
+fn message_json(program: &TypedProgram, md: &MessageTypeDef) -> String {
+    let kind = match &md.mtype {
+        MessageType::Ctor(tuid) => format!(
+            "\"constructor\", \"constructs\": {}",
+            json_string(&program.protocol(tuid).qname.to_string())
+        ),
+        MessageType::Dtor(_) => String::from("\"destructor\""),
+        MessageType::Other => String::from("\"normal\""),
+    };
+    let cxx_names = md.cxx_names();
+    let optional_name = |name: &Option<String>| {
+        name.as_ref()
+            .map_or(String::from("null"), |name| json_string(name))
+    };
+    format!(
+        "{{\"name\": {}, \"direction\": {}, \"senders\": {}, \"send_semantics\": {}, \
+         \"kind\": {}, \"params\": {}, \"returns\": {}, \"unused_sync_return\": {}, \
+         \"since\": {}, \"no_logging\": {}, \"max_size\": {}, \"shmem_allocated_by\": {}, \
+         \"cxx_names\": {{\"send\": {}, \"recv\": {}, \"promise\": {}, \"resolver\": {}}}}}",
+        json_string(&md.name.id),
+        json_string(direction_name(md.direction)),
+        senders_json(md.direction),
+        json_string(semantics_name(md.send_semantics)),
+        kind,
+        params_json(program, &md.params),
+        params_json(program, &md.returns),
+        md.unused_sync_return(&program.tuts).is_some(),
+        optional_number(md.since),
+        md.no_logging,
+        optional_number(md.max_size),
+        sides_json(&shmem_allocators(md, &program.tuts)),
+        json_string(&cxx_names.send),
+        json_string(&cxx_names.recv),
+        optional_name(&cxx_names.promise),
+        optional_name(&cxx_names.resolver)
+    )
+}
+
+// |program| must have been checked from |file_names|.
+pub fn export_json(program: &TypedProgram, file_names: &[PathBuf]) -> String {
+    let edges = endpoint_edges(program);
+    let mut protocols = Vec::new();
+    for file in file_names {
+        let tuid = program.tu_for_file(file).unwrap();
+        let ptype = match &program.tuts[&tuid].protocol {
+            Some(ptype) => ptype,
+            None => continue,
+        };
+        let names = |tuids: &[_]| {
+            json_list(
+                tuids
+                    .iter()
+                    .map(|t| json_string(&program.protocol(t).qname.to_string())),
+            )
+        };
+        let sent_by = |side| {
+            json_list(
+                program
+                    .messages_sent_by(&tuid, side)
+                    .iter()
+                    .map(|md| json_string(&md.name.id)),
+            )
+        };
+        let opens = json_list(edges.iter().filter(|e| e.protocol == tuid).map(|e| {
+            format!(
+                "{{\"message\": {}, \"bound_by\": {}, \"protocol\": {}, \"side\": {}, \
+                 \"managed\": {}}}",
+                json_string(&e.message),
+                json_string(side_name(e.bound_by)),
+                json_string(&program.protocol(&e.opens).qname.to_string()),
+                json_string(side_name(Some(e.side))),
+                e.managed
+            )
+        }));
+        let messages = ptype
+            .messages
+            .iter()
+            .map(|md| format!("      {}", message_json(program, md)))
+            .collect::<Vec<_>>();
+        let messages = if messages.is_empty() {
+            String::from("[]")
+        } else {
+            format!("[\n{}\n    ]", messages.join(",\n"))
+        };
+        protocols.push(format!(
+            "  {{\n    \"name\": {},\n    \"file\": {},\n    \"version\": {},\n    \
+             \"send_semantics\": {},\n    \
+             \"managers\": {},\n    \"manages\": {},\n    \"parent_sends\": {},\n    \
+             \"child_sends\": {},\n    \"alloc_shmem\": {},\n    \"opens\": {},\n    \
+             \"messages\": {}\n  }}",
+            json_string(&ptype.qname.to_string()),
+            json_path(&program.tus[&tuid].file_name),
+            optional_number(ptype.version),
+            json_string(semantics_name(ptype.send_semantics)),
+            names(&ptype.managers),
+            names(&ptype.manages),
+            sent_by(ProtocolSide::Parent),
+            sent_by(ProtocolSide::Child),
+            ptype
+                .alloc_shmem
+                .as_ref()
+                .map_or(String::from("null"), |sides| sides_json(sides)),
+            opens,
+            messages
+        ));
+    }
+
+    if protocols.is_empty() {
+        String::from("{\"protocols\": []}\n")
+    } else {
+        format!("{{\"protocols\": [\n{}\n]}}\n", protocols.join(",\n"))
+    }
+}
+
+// The manager graph of every protocol in |program| as adjacency lists, with
+// the protocols in the order of their names.
+pub fn manager_graph_json(program: &TypedProgram) -> String {
+    let edges = manager_edges(program);
+    let name = |tuid| json_string(&program.protocol(tuid).qname.to_string());
+    let protocols = program
+        .decl_order()
+        .protocols()
+        .iter()
+        .map(|tuid| {
+            let ptype = program.protocol(tuid);
+            let manages = json_list(edges.iter().filter(|e| e.manager == *tuid).map(|e| {
+                let constructors = json_list(e.constructors.iter().map(|c| {
+                    format!(
+                        "{{\"message\": {}, \"send_semantics\": {}, \"direction\": {}, \
+                         \"senders\": {}}}",
+                        json_string(&c.message),
+                        json_string(semantics_name(c.send_semantics)),
+                        json_string(direction_name(c.direction)),
+                        senders_json(c.direction)
+                    )
+                }));
+                format!(
+                    "{{\"protocol\": {}, \"constructors\": {}}}",
+                    name(&e.managee),
+                    constructors
+                )
+            }));
+            format!(
+                "  {{\"name\": {}, \"file\": {}, \"send_semantics\": {}, \"managers\": {}, \
+                 \"manages\": {}}}",
+                name(tuid),
+                json_path(&program.tus[tuid].file_name),
+                json_string(semantics_name(ptype.send_semantics)),
+                json_list(ptype.managers.iter().map(name)),
+                manages
+            )
+        })
+        .collect::<Vec<_>>();
+    if protocols.is_empty() {
+        String::from("{\"protocols\": []}\n")
+    } else {
+        format!("{{\"protocols\": [\n{}\n]}}\n", protocols.join(",\n"))
+    }
+}
@@ -0,0 +1,214 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Structural hashing of translation units.
+//
+// The fingerprint of a translation unit only depends on its structure, so
+// it doesn't change when whitespace or comments are edited. Locations are
+// not hashed, and neither are the ids of included translation units, which
+// depend on the order files are parsed in. Included files are hashed by
+// name instead.
+//
+// The located fingerprint hashes the locations as well, for caching
+// results that contain locations, like diagnostics.
+
+use ast::{
+    AttributeValue, Attributes, Identifier, Location, MessageDecl, Namespace, Param,
+    TranslationUnit, TypeSpec,
+};
+use stable_hash::StableHasher;
+use std::hash::Hasher;
+
+struct Fingerprinter {
+    h: StableHasher,
+    locations: bool,
+}
+
+impl Fingerprinter {
+    fn loc(&mut self, loc: &Location) {
+        if self.locations {
+            self.h.write_usize(loc.lineno);
+            self.h.write_usize(loc.colno);
+        }
+    }
+
+    fn identifier(&mut self, id: &Identifier) {
+        self.h.write_str(&id.id);
+        self.loc(&id.loc);
+    }
+
+    fn namespace(&mut self, ns: &Namespace) {
+        self.h.write_str(&ns.qname().to_string());
+        self.loc(&ns.name.loc);
+    }
+
+    fn attributes(&mut self, attributes: &Attributes) {
+        // Attributes are a HashMap, so sort them to get a stable order.
+        let mut attributes = attributes
+            .iter()
+            .map(|(name, (loc, value))| match value {
+                AttributeValue::Identifier(id) => (name, 0, id.id.clone(), loc),
+                AttributeValue::String(s) => (name, 1, s.clone(), loc),
+                AttributeValue::None => (name, 2, String::new(), loc),
+                AttributeValue::Integer(n) => (name, 3, n.to_string(), loc),
+            })
+            .collect::<Vec<_>>();
+        attributes.sort_by(|a, b| (a.0, a.1, &a.2).cmp(&(b.0, b.1, &b.2)));
+        self.h.write_usize(attributes.len());
+        for (name, kind, value, loc) in attributes {
+            self.h.write_str(name);
+            self.h.write_u8(kind);
+            self.h.write_str(&value);
+            self.loc(loc);
+        }
+    }
+
+    fn type_spec(&mut self, t: &TypeSpec) {
+        self.h.write_str(&t.spec);
+        self.h.write(&[
+            t.array as u8,
+            t.maybe as u8,
+            t.nullable as u8,
+            t.uniqueptr as u8,
+        ]);
+        self.loc(&t.loc);
+    }
+
+    fn params(&mut self, params: &[Param]) {
+        self.h.write_usize(params.len());
+        for p in params {
+            self.attributes(&p.attributes);
+            self.type_spec(&p.type_spec);
+            self.identifier(&p.name);
+        }
+    }
+
+    fn message(&mut self, md: &MessageDecl) {
+        self.identifier(&md.name);
+        self.attributes(&md.attributes);
+        self.h.write_str(&format!("{:?}", md.send_semantics));
+        self.h.write_str(&format!("{:?}", md.direction));
+        self.params(&md.in_params);
+        self.params(&md.out_params);
+    }
+
+    fn translation_unit(mut self, tu: &TranslationUnit) -> u64 {
+        if self.locations {
+            self.h.write_str(&tu.file_name.to_string_lossy());
+        }
+        self.h.write_str(&format!("{:?}", tu.file_type));
+        self.namespace(&tu.namespace);
+        match &tu.header_marker {
+            Some(marker) => {
+                self.h.write_u8(1);
+                self.identifier(marker);
+            }
+            None => self.h.write_u8(0),
+        }
+
+        self.h.write_usize(tu.cxx_includes.len());
+        for i in &tu.cxx_includes {
+            self.identifier(i);
+        }
+        self.h.write_usize(tu.include_files.len());
+        for i in &tu.include_files {
+            self.identifier(i);
+        }
+
+        self.h.write_usize(tu.using.len());
+        for u in &tu.using {
+            self.h.write_str(&u.cxx_type.to_string());
+            self.loc(u.cxx_type.loc());
+            self.h.write_usize(u.template_args.len());
+            for arg in &u.template_args {
+                self.type_spec(arg);
+            }
+            self.h.write_str(&u.header);
+            self.h.write_str(&format!("{:?}", u.kind));
+            self.attributes(&u.attributes);
+        }
+
+        self.h.write_usize(tu.structs.len());
+        for (ns, attributes, fields) in &tu.structs {
+            self.namespace(ns);
+            self.attributes(attributes);
+            self.h.write_usize(fields.len());
+            for f in fields {
+                self.type_spec(&f.type_spec);
+                self.identifier(&f.name);
+            }
+        }
+
+        self.h.write_usize(tu.unions.len());
+        for (ns, attributes, components) in &tu.unions {
+            self.namespace(ns);
+            self.attributes(attributes);
+            self.h.write_usize(components.len());
+            for c in components {
+                self.type_spec(c);
+            }
+        }
+
+        match &tu.protocol {
+            Some((ns, p)) => {
+                self.h.write_u8(1);
+                self.namespace(ns);
+                self.attributes(&p.attributes);
+                self.h.write_str(&format!("{:?}", p.send_semantics));
+                self.h.write_usize(p.managers.len());
+                for m in &p.managers {
+                    self.identifier(m);
+                }
+                self.h.write_usize(p.manages.len());
+                for m in &p.manages {
+                    self.identifier(m);
+                }
+                self.h.write_usize(p.messages.len());
+                for md in &p.messages {
+                    self.message(md);
+                }
+            }
+            None => self.h.write_u8(0),
+        }
+
+        // Which lines a section covers only matters with locations, since
+        // without them adding a line to a section looks like an edit of
+        // whitespace.
+        self.h.write_usize(tu.conditions.len());
+        for section in &tu.conditions {
+            self.h.write_str(&format!("{:?}", section.condition));
+            if self.locations {
+                self.h.write_usize(section.first_line);
+                self.h.write_usize(section.last_line);
+            }
+        }
+
+        self.h.finish()
+    }
+}
+
+pub trait Fingerprint {
+    fn fingerprint(&self) -> u64;
+    fn located_fingerprint(&self) -> u64;
+}
+
+impl Fingerprint for TranslationUnit {
+    fn fingerprint(&self) -> u64 {
+        Fingerprinter {
+            h: StableHasher::new(),
+            locations: false,
+        }
+        .translation_unit(self)
+    }
+
+    // Like fingerprint, but it also changes when anything in the file
+    // moves, or the file is renamed.
+    fn located_fingerprint(&self) -> u64 {
+        Fingerprinter {
+            h: StableHasher::new(),
+            locations: true,
+        }
+        .translation_unit(self)
+    }
+}
@@ -0,0 +1,238 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Applying the fixes of diagnostics to the files they are about, for
+// `--apply-fixes', and showing the changes as unified diffs instead of
+// writing them, for `--dry-run'.
+
+use errors::{line_starts, Diagnostic, Diagnostics, Fix};
+use sources::SourceManager;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// The lines of context around each change in a diff, as in `diff -u'.
+const CONTEXT: usize = 3;
+
+// A file with the fixes for it applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixedFile {
+    pub file_name: PathBuf,
+    pub old: String,
+    pub new: String,
+    // The number of fixes that were applied, and the number that were left
+    // out because they change the same text as one that was applied. A
+    // fix whose edits were all applied already, by another diagnostic with
+    // the same fix, is in neither.
+    pub applied: usize,
+    pub skipped: usize,
+    // The number of fixes that were left out because their locations
+    // aren't in the file, which happens when it changed after the check.
+    pub stale: usize,
+}
+
+// The files that the fixes of |diagnostics| change, sorted by name, with
+// the fixes applied to the text of each file in |sources|. The fixes are
+// applied in the order of the diagnostics, and a fix that overlaps one
+// that is already applied is skipped, so running the check again after
+// writing the files gives the next round of fixes. Fixes that aren't
+// machine applicable are left out.
+pub fn fixed_files(diagnostics: &[Diagnostic], sources: &SourceManager) -> Vec<FixedFile> {
+    let mut by_file: BTreeMap<PathBuf, Vec<&Fix>> = BTreeMap::new();
+    for fix in Diagnostics::new(diagnostics).fixes() {
+        if let Some(e) = fix.edits.first() {
+            by_file
+                .entry(e.loc.file_name.clone())
+                .or_default()
+                .push(fix);
+        }
+    }
+
+    let mut files = Vec::new();
+    for (file_name, fixes) in by_file {
+        let old = match sources.text(&file_name) {
+            Some(text) => text,
+            None => continue,
+        };
+        let line_starts = line_starts(old);
+
+        let mut taken: Vec<(usize, usize)> = Vec::new();
+        let mut combined = Fix {
+            message: String::new(),
+            edits: Vec::new(),
+        };
+        let (mut applied, mut skipped, mut stale) = (0, 0, 0);
+        for fix in &fixes {
+            // The same fix can come from several diagnostics, and an edit
+            // that is already applied doesn't change anything, so it
+            // doesn't overlap itself.
+            let edits = fix
+                .edits
+                .iter()
+                .filter(|e| !combined.edits.contains(e))
+                .collect::<Vec<_>>();
+            if edits.is_empty() {
+                continue;
+            }
+            let ranges = match edits
+                .iter()
+                .map(|e| e.range(old, &line_starts))
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(ranges) => ranges,
+                None => {
+                    stale += 1;
+                    continue;
+                }
+            };
+            let overlaps = ranges.iter().any(|&(start, end)| {
+                taken
+                    .iter()
+                    .any(|&(s, e)| (start < e && s < end) || start == s)
+            });
+            if overlaps {
+                skipped += 1;
+                continue;
+            }
+            taken.extend(ranges);
+            combined.edits.extend(edits.into_iter().cloned());
+            applied += 1;
+        }
+
+        files.push(FixedFile {
+            new: combined.apply(old),
+            old: String::from(old),
+            applied,
+            skipped,
+            stale,
+            file_name,
+        });
+    }
+    files
+}
+
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+// The lines of |old| and |new|, with the lines that are only in one of
+// them marked, from the longest common subsequence of their lines.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // common[i][j] is the length of the longest common subsequence of
+    // old[i..] and new[j..].
+    let mut common = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+// The range of a hunk header, like `12,7'. An empty range starts at the
+// line before it, as in `diff -u'.
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+// A unified diff from |old| to |new|, which are the text of |file_name|
+// before and after a change, with the `a/' and `b/' prefixes that patch
+// -p1 and code review tools expect. The diff is empty if the texts are the
+// same.
+pub fn unified_diff(file_name: &str, old: &str, new: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    // The lines keep their newlines, so that a last line without one is
+    // different from the same line with one.
+    let old_lines = old.split_inclusive('\n').collect::<Vec<_>>();
+    let new_lines = new.split_inclusive('\n').collect::<Vec<_>>();
+    let lines = diff_lines(&old_lines, &new_lines);
+
+    // The indexes of the changed lines, grouped into hunks whose context
+    // would touch.
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| !matches!(l, Line::Same(_)))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for i in changed {
+        let start = i.saturating_sub(CONTEXT);
+        let end = (i + CONTEXT + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- a/{0}\n+++ b/{0}\n", file_name);
+    // The number of old and new lines before each line of |lines|.
+    let (mut old_line, mut new_line, mut next) = (0, 0, 0);
+    for (start, end) in hunks {
+        for l in &lines[next..start] {
+            match l {
+                Line::Same(_) => {
+                    old_line += 1;
+                    new_line += 1;
+                }
+                Line::Removed(_) => old_line += 1,
+                Line::Added(_) => new_line += 1,
+            }
+        }
+        let hunk = &lines[start..end];
+        let old_count = hunk.iter().filter(|l| !matches!(l, Line::Added(_))).count();
+        let new_count = hunk
+            .iter()
+            .filter(|l| !matches!(l, Line::Removed(_)))
+            .count();
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_line, old_count),
+            hunk_range(new_line, new_count)
+        ));
+        for l in hunk {
+            let (prefix, text) = match l {
+                Line::Same(text) => (' ', text),
+                Line::Removed(text) => ('-', text),
+                Line::Added(text) => ('+', text),
+            };
+            out.push(prefix);
+            out.push_str(text);
+            if !text.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+        old_line += old_count;
+        new_line += new_count;
+        next = end;
+    }
+    out
+}
@@ -0,0 +1,197 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// The graph of which translation units include which, for the checker and
+// for build tools that need to know what to check again after a file
+// changes.
+//
+// Protocols often include each other, like a manager and its managees, so
+// the graph can have cycles. The files in a cycle form a strongly
+// connected component, and the order of the graph is a topological order
+// of those components, in which a file comes after the files it includes,
+// unless they are in the same component. Within a component, files are in
+// the order of their TUIds, so the order is the same every time the same
+// files are parsed in the same order.
+
+use ast::{TUId, TUMap, TranslationUnit};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+// A translation unit in topological order, with the files it includes
+// directly, for build tools that split the checking up themselves. The
+// files of one component have to be checked together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderedFile {
+    // The index of the strongly connected component of the file, which
+    // goes up in topological order.
+    pub component: usize,
+    pub file_name: PathBuf,
+    pub includes: Vec<PathBuf>,
+}
+
+// Like `3 /src/PFoo.ipdl: /src/PBar.ipdl /src/Shared.ipdlh'.
+impl fmt::Display for OrderedFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}:", self.component, self.file_name.display())?;
+        for include in &self.includes {
+            write!(f, " {}", include.display())?;
+        }
+        Ok(())
+    }
+}
+
+pub struct IncludeGraph {
+    // The includes of each translation unit, in the order of the include
+    // statements.
+    includes: BTreeMap<TUId, Vec<TUId>>,
+}
+
+// The state of Tarjan's algorithm for strongly connected components.
+struct Tarjan<'a> {
+    graph: &'a IncludeGraph,
+    index: BTreeMap<TUId, usize>,
+    low_link: BTreeMap<TUId, usize>,
+    stack: Vec<TUId>,
+    components: Vec<Vec<TUId>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, tuid: TUId) {
+        let index = self.index.len();
+        self.index.insert(tuid, index);
+        self.low_link.insert(tuid, index);
+        self.stack.push(tuid);
+
+        for &include in self.graph.includes(&tuid) {
+            if !self.index.contains_key(&include) {
+                self.visit(include);
+                let low = self.low_link[&tuid].min(self.low_link[&include]);
+                self.low_link.insert(tuid, low);
+            } else if self.stack.contains(&include) {
+                let low = self.low_link[&tuid].min(self.index[&include]);
+                self.low_link.insert(tuid, low);
+            }
+        }
+
+        // A component is finished after every component that it includes,
+        // so they come out in topological order.
+        if self.low_link[&tuid] == index {
+            let start = self.stack.iter().rposition(|&t| t == tuid).unwrap();
+            let mut component = self.stack.split_off(start);
+            component.sort();
+            self.components.push(component);
+        }
+    }
+}
+
+impl IncludeGraph {
+    // The graph of |tus|. Includes of translation units that aren't in
+    // |tus| are left out.
+    pub fn new(tus: &TUMap<TranslationUnit>) -> IncludeGraph {
+        let includes = tus
+            .iter()
+            .map(|(tuid, tu)| {
+                let includes = tu
+                    .includes
+                    .iter()
+                    .filter(|include| tus.contains_key(include))
+                    .cloned()
+                    .collect();
+                (*tuid, includes)
+            })
+            .collect();
+        IncludeGraph { includes }
+    }
+
+    // All of the translation units, sorted by TUId.
+    pub fn nodes(&self) -> Vec<TUId> {
+        self.includes.keys().cloned().collect()
+    }
+
+    // The translation units that |tuid| includes directly.
+    pub fn includes(&self, tuid: &TUId) -> &[TUId] {
+        self.includes.get(tuid).map_or(&[], |includes| includes)
+    }
+
+    // The translation units that include |tuid| directly, sorted by TUId.
+    pub fn included_by(&self, tuid: &TUId) -> Vec<TUId> {
+        self.includes
+            .iter()
+            .filter(|(_, includes)| includes.contains(tuid))
+            .map(|(includer, _)| *includer)
+            .collect()
+    }
+
+    // The strongly connected components of the graph, in topological
+    // order, each sorted by TUId. A file that isn't in a cycle is a
+    // component of its own.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<TUId>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: BTreeMap::new(),
+            low_link: BTreeMap::new(),
+            stack: Vec::new(),
+            components: Vec::new(),
+        };
+        for tuid in self.nodes() {
+            if !tarjan.index.contains_key(&tuid) {
+                tarjan.visit(tuid);
+            }
+        }
+        tarjan.components
+    }
+
+    // All of the translation units, each after the ones it includes,
+    // except for those in the same cycle.
+    pub fn topological_order(&self) -> Vec<TUId> {
+        self.strongly_connected_components()
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    // The files of |tus|, which the graph is of, in topological order.
+    pub fn ordered_files(&self, tus: &TUMap<TranslationUnit>) -> Vec<OrderedFile> {
+        let mut files = Vec::new();
+        for (component, tuids) in self.strongly_connected_components().into_iter().enumerate() {
+            for tuid in tuids {
+                files.push(OrderedFile {
+                    component,
+                    file_name: tus[&tuid].file_name.clone(),
+                    includes: self
+                        .includes(&tuid)
+                        .iter()
+                        .map(|include| tus[include].file_name.clone())
+                        .collect(),
+                });
+            }
+        }
+        files
+    }
+
+    // The translation units whose checks can be different after the files
+    // in |changed| change: those files, and every file that includes one
+    // of them, directly or not. They are in topological order.
+    pub fn dependents(&self, changed: &[TUId]) -> Vec<TUId> {
+        let mut affected = changed
+            .iter()
+            .filter(|tuid| self.includes.contains_key(tuid))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut i = 0;
+        while i < affected.len() {
+            for includer in self.included_by(&affected[i]) {
+                if !affected.contains(&includer) {
+                    affected.push(includer);
+                }
+            }
+            i += 1;
+        }
+        self.topological_order()
+            .into_iter()
+            .filter(|tuid| affected.contains(tuid))
+            .collect()
+    }
+}
@@ -0,0 +1,265 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Expansion of the inputs given on the command line into IPDL files.
+//
+// An input can be a file, a directory, which is searched recursively for
+// .ipdl and .ipdlh files, or a glob pattern. Glob patterns support `*` and
+// `?` within a path component and `**` for any number of directories.
+
+use errors::IpdlError;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+fn is_glob(s: &str) -> bool {
+    s.contains('*') || s.contains('?')
+}
+
+fn is_ipdl_file(p: &Path) -> bool {
+    match p.extension() {
+        Some(e) => e == "ipdl" || e == "ipdlh",
+        None => false,
+    }
+}
+
+// The entries of |dir|, with an empty |dir| meaning the current directory.
+fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let read_dir = if dir.as_os_str().is_empty() {
+        fs::read_dir(".")
+    } else {
+        fs::read_dir(dir)
+    };
+    let mut entries = match read_dir {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| dir.join(e.file_name()))
+            .collect::<Vec<_>>(),
+        Err(_) => Vec::new(),
+    };
+    entries.sort();
+    entries
+}
+
+// Whether |dir| hasn't been searched yet. Directories are identified by
+// their canonical paths, so that a symlink back to a directory that
+// contains it doesn't make the search go on forever.
+fn first_visit(dir: &Path, visited: &mut HashSet<PathBuf>) -> bool {
+    visited.insert(dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf()))
+}
+
+fn find_ipdl_files(dir: &Path, files: &mut Vec<PathBuf>, visited: &mut HashSet<PathBuf>) {
+    if !first_visit(dir, visited) {
+        return;
+    }
+    for p in sorted_entries(dir) {
+        if p.is_dir() {
+            find_ipdl_files(&p, files, visited);
+        } else if is_ipdl_file(&p) {
+            files.push(p);
+        }
+    }
+}
+
+// Match a single path component against a pattern containing `*` and `?`.
+fn matches_component(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| matches_component(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && matches_component(&pattern[1..], &name[1..]),
+        Some(c) => name.first() == Some(c) && matches_component(&pattern[1..], &name[1..]),
+    }
+}
+
+// |visited| holds the directories that a `**` has already matched, for
+// each number of pattern components that are left after it.
+fn expand_glob(
+    base: &Path,
+    pattern: &[String],
+    files: &mut Vec<PathBuf>,
+    visited: &mut HashSet<(PathBuf, usize)>,
+) {
+    let (first, rest) = match pattern.split_first() {
+        Some(split) => split,
+        None => {
+            if base.is_file() {
+                files.push(base.to_path_buf());
+            }
+            return;
+        }
+    };
+
+    if first == "**" {
+        let canonical = base.canonicalize().unwrap_or_else(|_| base.to_path_buf());
+        if !visited.insert((canonical, pattern.len())) {
+            return;
+        }
+        // Match zero directories, then one or more.
+        expand_glob(base, rest, files, visited);
+        for p in sorted_entries(base) {
+            if p.is_dir() {
+                expand_glob(&p, pattern, files, visited);
+            }
+        }
+        return;
+    }
+
+    if !is_glob(first) {
+        expand_glob(&base.join(first), rest, files, visited);
+        return;
+    }
+
+    let first_chars = first.chars().collect::<Vec<_>>();
+    for p in sorted_entries(base) {
+        let name = match p.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.chars().collect::<Vec<_>>(),
+            None => continue,
+        };
+        if matches_component(&first_chars, &name) {
+            expand_glob(&p, rest, files, visited);
+        }
+    }
+}
+
+fn expand_one(input: &str) -> Result<Vec<PathBuf>, IpdlError> {
+    let path = Path::new(input);
+    let mut files = Vec::new();
+
+    if is_glob(input) {
+        let mut base = PathBuf::new();
+        let mut pattern = Vec::new();
+        for c in path.components() {
+            match c {
+                Component::Normal(s) => pattern.push(s.to_string_lossy().into_owned()),
+                _ if pattern.is_empty() => base.push(c.as_os_str()),
+                _ => pattern.push(c.as_os_str().to_string_lossy().into_owned()),
+            }
+        }
+        expand_glob(&base, &pattern, &mut files, &mut HashSet::new());
+        files.sort();
+        files.dedup();
+        if files.is_empty() {
+            return Err(IpdlError::Io(format!(
+                "No files match the pattern `{}'",
+                input
+            )));
+        }
+    } else if path.is_dir() {
+        find_ipdl_files(path, &mut files, &mut HashSet::new());
+        if files.is_empty() {
+            return Err(IpdlError::Io(format!(
+                "No .ipdl or .ipdlh files in the directory `{}'",
+                input
+            )));
+        }
+    } else {
+        // Missing files are reported by the parser.
+        files.push(path.to_path_buf());
+    }
+
+    Ok(files)
+}
+
+// Expand every input in order. Each directory or glob expands to its
+// matching files in sorted order, so the result is deterministic. A
+// directory without any IPDL files in it is an error, like a glob that
+// doesn't match anything.
+//
+// Files that exist are given by their canonical paths, which are the paths
+// that the parser gives their translation units, so a file is spelled the
+// same way however the inputs named it. A file that more than one input
+// names is only kept where it is named first. Missing files are kept as
+// they were given, for the parser to report.
+pub fn expand_inputs(inputs: &[String]) -> Result<Vec<PathBuf>, IpdlError> {
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+    for i in inputs {
+        for file in expand_one(i)? {
+            let file = file.canonicalize().unwrap_or(file);
+            if seen.insert(file.clone()) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[test]
+fn glob_tests() {
+    let m = |p: &str, n: &str| {
+        matches_component(
+            &p.chars().collect::<Vec<_>>(),
+            &n.chars().collect::<Vec<_>>(),
+        )
+    };
+    assert!(m("*.ipdl", "PFoo.ipdl"));
+    assert!(!m("*.ipdl", "PFoo.ipdlh"));
+    assert!(m("*.ipdl*", "PFoo.ipdlh"));
+    assert!(m("P?oo.ipdl", "PFoo.ipdl"));
+    assert!(!m("P?oo.ipdl", "Poo.ipdl"));
+    assert!(m("*", ""));
+    assert!(m("P*o*.ipdl", "PFooBar.ipdl"));
+
+    let ok_files = expand_inputs(&["../tests/ok".to_string()]).unwrap();
+    let ok_glob_files = expand_inputs(&["../tests/ok/*.ipdl*".to_string()]).unwrap();
+    assert_eq!(ok_files, ok_glob_files);
+    let mut sorted = ok_files.clone();
+    sorted.sort();
+    assert_eq!(ok_files, sorted);
+
+    let extra_files = expand_inputs(&["../tests/**/extra/P*.ipdl".to_string()]).unwrap();
+    assert!(!extra_files.is_empty());
+    let extra_dir = Path::new("../tests/error/extra").canonicalize().unwrap();
+    assert!(extra_files.iter().all(|f| f.starts_with(&extra_dir)));
+
+    assert!(expand_inputs(&["../tests/ok/*.nothing".to_string()]).is_err());
+
+    let twice = expand_inputs(&[
+        "../tests/ok/PStruct.ipdl".to_string(),
+        "../tests/ok/*.ipdl".to_string(),
+        "../tests/ok/../ok/PStruct.ipdl".to_string(),
+    ])
+    .unwrap();
+    let pstruct = Path::new("../tests/ok/PStruct.ipdl")
+        .canonicalize()
+        .unwrap();
+    assert_eq!(twice[0], pstruct);
+    let reordered = expand_inputs(&[
+        "../tests/ok/../ok/PStruct.ipdl".to_string(),
+        "../tests/ok/PStruct.ipdl".to_string(),
+    ])
+    .unwrap();
+    assert_eq!(reordered, vec![pstruct]);
+    let protocols = expand_inputs(&["../tests/ok/*.ipdl".to_string()]).unwrap();
+    assert_eq!(twice.len(), protocols.len());
+}
+
+#[cfg(unix)]
+#[test]
+fn directory_tests() {
+    use std::os::unix::fs::symlink;
+
+    let root = ::std::env::temp_dir().join(format!("ipdl_inputs_{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("protocols/sub")).unwrap();
+    let root = root.canonicalize().unwrap();
+    fs::create_dir_all(root.join("empty/sub")).unwrap();
+    fs::write(root.join("protocols/sub/PFoo.ipdl"), "").unwrap();
+    symlink(&root, root.join("protocols/sub/loop")).unwrap();
+    let input = |p: &str| vec![root.join(p).to_string_lossy().into_owned()];
+
+    // A symlink back up the tree is only followed as far as a directory
+    // that has already been searched.
+    let files = expand_inputs(&input("protocols")).unwrap();
+    assert_eq!(files, vec![root.join("protocols/sub/PFoo.ipdl")]);
+    let files = expand_inputs(&input("protocols/**/*.ipdl")).unwrap();
+    assert_eq!(files, vec![root.join("protocols/sub/PFoo.ipdl")]);
+
+    match expand_inputs(&input("empty")) {
+        Err(IpdlError::Io(message)) => assert!(message.contains("No .ipdl or .ipdlh files")),
+        _ => panic!("expected an error for a directory without IPDL files"),
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,833 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// The typed IR for a whole compilation, with a visitor and some queries
+// over it.
+
+use ast::{
+    CxxTypeKind, Identifier, Location, Priority, ProtocolSide, TUId, TUMap, TranslationUnit,
+};
+use errors::Diagnostic;
+use include_graph::IncludeGraph;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use type_check::{
+    get_protocol_type, CompoundType, IPDLType, MessageTypeDef, ProtocolTypeDef, StrengthMismatch,
+    StructTypeDef, TranslationUnitType, TypeRef, TypeTable, UnionTypeDef,
+};
+
+pub struct TypedProgram {
+    pub tus: TUMap<TranslationUnit>,
+    pub tuts: TUMap<TranslationUnitType>,
+    // Warnings from checking the program, sorted by location.
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl TypedProgram {
+    pub fn new(tus: TUMap<TranslationUnit>, tuts: TUMap<TranslationUnitType>) -> TypedProgram {
+        TypedProgram {
+            tus,
+            tuts,
+            warnings: Vec::new(),
+        }
+    }
+
+    // The ids of all translation units, in a deterministic order.
+    pub fn tu_ids(&self) -> Vec<TUId> {
+        let mut ids = self.tuts.keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    // The ids of all translation units, sorted by the names of their files
+    // and then by their paths. Unlike tu_ids(), this doesn't depend on the
+    // order the files were parsed in.
+    pub fn tu_ids_by_file(&self) -> Vec<TUId> {
+        let mut ids = self.tu_ids();
+        ids.sort_by(|a, b| {
+            let (a, b) = (&self.tus[a].file_name, &self.tus[b].file_name);
+            (a.file_name(), a).cmp(&(b.file_name(), b))
+        });
+        ids
+    }
+
+    // The stable numbering of the protocols, structs and unions of the
+    // program.
+    pub fn decl_order(&self) -> DeclOrder {
+        let mut protocols = self
+            .tu_ids()
+            .into_iter()
+            .filter(|tuid| self.tuts[tuid].protocol.is_some())
+            .collect::<Vec<_>>();
+        protocols.sort_by_key(|tuid| self.protocol(tuid).qname.to_string());
+        let mut structs = Vec::new();
+        let mut unions = Vec::new();
+        for tuid in self.tu_ids_by_file() {
+            let tut = &self.tuts[&tuid];
+            structs.extend((0..tut.structs.len()).map(|i| TypeRef::new(&tuid, i)));
+            unions.extend((0..tut.unions.len()).map(|i| TypeRef::new(&tuid, i)));
+        }
+        DeclOrder {
+            protocols,
+            structs,
+            unions,
+        }
+    }
+
+    // Which translation units of the program include which.
+    pub fn include_graph(&self) -> IncludeGraph {
+        IncludeGraph::new(&self.tus)
+    }
+
+    // The translation unit parsed from a file, which may be given by any
+    // path that leads to it.
+    pub fn tu_for_file(&self, file: &Path) -> Option<TUId> {
+        let file = file.canonicalize().ok()?;
+        self.tu_ids()
+            .into_iter()
+            .find(|tuid| self.tus[tuid].file_name == file)
+    }
+
+    // The type of a protocol of the program. A checked program has one for
+    // every actor and managed protocol that its types refer to.
+    pub fn protocol(&self, tuid: &TUId) -> &ProtocolTypeDef {
+        get_protocol_type(&self.tuts, tuid).expect("translation unit is not a protocol")
+    }
+
+    // Find a protocol by its short name (`PFoo`) or its fully qualified
+    // name (`mozilla::PFoo`, with or without a leading `::`).
+    pub fn protocol_by_name(&self, name: &str) -> Option<TUId> {
+        let full_name = if name.starts_with("::") {
+            name.to_string()
+        } else {
+            format!("::{}", name)
+        };
+        self.tu_ids()
+            .into_iter()
+            .find(|tuid| match &self.tuts.get(tuid).unwrap().protocol {
+                Some(p) => p.qname.short_name() == name || p.qname.to_string() == full_name,
+                None => false,
+            })
+    }
+
+    // Where every protocol, struct, union and message of the program is
+    // declared, by fully qualified name.
+    pub fn definition_index(&self) -> DefinitionIndex {
+        let mut definitions: HashMap<String, Definition> = HashMap::new();
+        let mut add = |name: String, kind: DefinitionKind, id: &Identifier| {
+            definitions.entry(name.clone()).or_insert(Definition {
+                name,
+                kind,
+                loc: id.loc.clone(),
+                len: id.id.chars().count(),
+            });
+        };
+        for tuid in self.tu_ids_by_file() {
+            let tut = &self.tuts[&tuid];
+            for sdef in &tut.structs {
+                add(
+                    sdef.qname.to_string(),
+                    DefinitionKind::Struct,
+                    &sdef.qname.base_id,
+                );
+            }
+            for udef in &tut.unions {
+                add(
+                    udef.qname.to_string(),
+                    DefinitionKind::Union,
+                    &udef.qname.base_id,
+                );
+            }
+            if let Some(ptype) = &tut.protocol {
+                add(
+                    ptype.qname.to_string(),
+                    DefinitionKind::Protocol,
+                    &ptype.qname.base_id,
+                );
+                for md in &ptype.messages {
+                    let name = format!("{}::{}", ptype.qname, md.name);
+                    add(name, DefinitionKind::Message, &md.name);
+                }
+            }
+        }
+        DefinitionIndex { definitions }
+    }
+
+    // The C++ headers that the code generated for a translation unit has to
+    // include, without the quotes, in the order they were included in.
+    pub fn cxx_includes_of(&self, tuid: &TUId) -> Vec<&str> {
+        self.tus[tuid]
+            .cxx_includes
+            .iter()
+            .map(|i| i.id.trim_matches('"'))
+            .collect()
+    }
+
+    // Whether an imported C++ type was declared with `using class' or
+    // `using struct'. This is None for other types, and for types that were
+    // only imported with a bare `using'.
+    pub fn cxx_type_kind(&self, t: &IPDLType) -> Option<CxxTypeKind> {
+        let qid = match t {
+            IPDLType::ImportedCxxType(qid, _, _, _) => qid,
+            _ => return None,
+        };
+        let name = qid.to_string();
+        self.tu_ids_by_file()
+            .into_iter()
+            .flat_map(|tuid| self.tus[&tuid].using.iter())
+            .filter(|u| u.cxx_type.to_string() == name)
+            .find_map(|u| u.kind)
+    }
+
+    pub fn messages_of(&self, protocol: &TUId) -> &[MessageTypeDef] {
+        &self.protocol(protocol).messages
+    }
+
+    // The messages of a protocol that |side| can send, which includes the
+    // messages declared in `both:' sections.
+    pub fn messages_sent_by(&self, protocol: &TUId, side: ProtocolSide) -> Vec<&MessageTypeDef> {
+        self.messages_of(protocol)
+            .iter()
+            .filter(|md| match side {
+                ProtocolSide::Parent => md.direction.is_to_child(),
+                ProtocolSide::Child => md.direction.is_to_parent(),
+            })
+            .collect()
+    }
+
+    // The messages of a protocol that |side| receives.
+    pub fn messages_received_by(
+        &self,
+        protocol: &TUId,
+        side: ProtocolSide,
+    ) -> Vec<&MessageTypeDef> {
+        let other = match side {
+            ProtocolSide::Parent => ProtocolSide::Child,
+            ProtocolSide::Child => ProtocolSide::Parent,
+        };
+        self.messages_sent_by(protocol, other)
+    }
+
+    pub fn managers_of(&self, protocol: &TUId) -> &[TUId] {
+        &self.protocol(protocol).managers
+    }
+
+    pub fn managees_of(&self, protocol: &TUId) -> &[TUId] {
+        &self.protocol(protocol).manages
+    }
+
+    // The protocols that can't be reached from any of |roots| by following
+    // |manages| edges, in a deterministic order. These are IPC interfaces
+    // that nothing, starting from the roots, can ever construct.
+    pub fn unreachable_protocols(&self, roots: &[TUId]) -> Vec<TUId> {
+        let mut reachable = HashSet::new();
+        let mut stack = roots.to_vec();
+        while let Some(tuid) = stack.pop() {
+            if reachable.insert(tuid) {
+                stack.extend(self.managees_of(&tuid));
+            }
+        }
+        self.tu_ids()
+            .into_iter()
+            .filter(|tuid| self.tuts[tuid].protocol.is_some() && !reachable.contains(tuid))
+            .collect()
+    }
+
+    // Whether |message| can be declared in the first protocol of
+    // |protocol_chain| and sent through each of the others, which are
+    // usually its managers up to a top level protocol. If it can't, this is
+    // the first protocol that rejects it, and why. These are the rules that
+    // the type checker applies to each message.
+    pub fn can_send(
+        &self,
+        message: &MessageTypeDef,
+        protocol_chain: &[TUId],
+    ) -> Result<(), (TUId, StrengthMismatch)> {
+        if let Some(first) = protocol_chain.first() {
+            if !message.nested.is_none() && message.prio != Priority::Normal {
+                return Err((*first, StrengthMismatch::NestedPriority(message.prio)));
+            }
+        }
+        let strength = message.message_strength();
+        for tuid in protocol_chain {
+            strength
+                .check_conversion(&self.protocol(tuid).message_strength())
+                .map_err(|mismatch| (*tuid, mismatch))?;
+        }
+        Ok(())
+    }
+
+    // The top level protocols with a side that can live in a process of
+    // type |process|, each followed by the protocols it manages, directly
+    // or not, which live in the same processes. The top level protocols are
+    // sorted by name, and each protocol is only listed once.
+    pub fn protocols_hosted_by(&self, process: &str) -> Vec<TUId> {
+        let mut top_level = self
+            .tu_ids()
+            .into_iter()
+            .filter(|tuid| match &self.tuts[tuid].protocol {
+                Some(p) => p.is_top_level() && !p.sides_in_process(process).is_empty(),
+                None => false,
+            })
+            .collect::<Vec<_>>();
+        top_level.sort_by_key(|tuid| self.protocol(tuid).qname.to_string());
+        let mut hosted = Vec::new();
+        for root in top_level {
+            let mut stack = vec![root];
+            while let Some(tuid) = stack.pop() {
+                if !hosted.contains(&tuid) {
+                    hosted.push(tuid);
+                    stack.extend(self.managees_of(&tuid).iter().rev());
+                }
+            }
+        }
+        hosted
+    }
+
+    // Every type that appears in the parameters or return values of the
+    // messages of a protocol, including the types wrapped by arrays, Maybe,
+    // UniquePtr and NotNull, identified by name. This does not look inside
+    // of structs and unions.
+    pub fn types_used_by(&self, protocol: &TUId) -> Vec<IPDLType> {
+        struct TypeCollector<'a> {
+            tuts: &'a TUMap<TranslationUnitType>,
+            types: Vec<(String, IPDLType)>,
+        }
+        impl<'a> IrVisitor for TypeCollector<'a> {
+            fn visit_type(&mut self, t: &IPDLType) {
+                let name = t.name(self.tuts);
+                if !self.types.iter().any(|(n, _)| n == &name) {
+                    self.types.push((name, t.clone()));
+                }
+                walk_type(self, t);
+            }
+        }
+
+        let mut collector = TypeCollector {
+            tuts: &self.tuts,
+            types: Vec::new(),
+        };
+        collector.visit_protocol(
+            protocol,
+            &self.tuts[protocol].types,
+            self.protocol(protocol),
+        );
+        collector.types.into_iter().map(|(_, t)| t).collect()
+    }
+
+    // The header that an imported C++ type was declared in by a `using',
+    // without the quotes.
+    pub fn header_of(&self, t: &IPDLType) -> Option<&str> {
+        let qid = match t {
+            IPDLType::ImportedCxxType(qid, _, _, _) => qid,
+            _ => return None,
+        };
+        let name = qid.to_string();
+        self.tu_ids_by_file()
+            .into_iter()
+            .flat_map(|tuid| self.tus[&tuid].using.iter())
+            .find(|u| u.cxx_type.to_string() == name)
+            .map(|u| u.header.trim_matches('"'))
+    }
+
+    // Every struct, union, builtin and imported type that the messages of
+    // a protocol need, either directly or through the fields and components
+    // of the structs and unions they use.
+    pub fn type_closure(&self, protocol: &TUId) -> TypeClosure {
+        let mut closure = TypeClosure {
+            structs: Vec::new(),
+            unions: Vec::new(),
+            imported: Vec::new(),
+            headers: Vec::new(),
+        };
+        let mut pending = self.types_used_by(protocol);
+        while let Some(t) = pending.pop() {
+            match &t {
+                IPDLType::ArrayType(inner)
+                | IPDLType::MaybeType(inner)
+                | IPDLType::UniquePtrType(inner)
+                | IPDLType::NotNullType(inner) => pending.push((**inner).clone()),
+                IPDLType::StructType(tr) if !closure.structs.contains(tr) => {
+                    closure.structs.push(tr.clone());
+                    pending.extend(
+                        tr.lookup_struct(&self.tuts)
+                            .field_types(&self.tuts)
+                            .cloned(),
+                    );
+                }
+                IPDLType::UnionType(tr) if !closure.unions.contains(tr) => {
+                    closure.unions.push(tr.clone());
+                    pending.extend(
+                        tr.lookup_union(&self.tuts)
+                            .component_types(&self.tuts)
+                            .cloned(),
+                    );
+                }
+                IPDLType::BuiltinCType(_)
+                | IPDLType::ImportedCxxType(..)
+                | IPDLType::ShmemType(_)
+                | IPDLType::ByteBufType(_)
+                | IPDLType::FDType(_) => {
+                    let name = self.qualified_name(&t);
+                    if !closure
+                        .imported
+                        .iter()
+                        .any(|i| self.qualified_name(i) == name)
+                    {
+                        if let Some(header) = self.header_of(&t) {
+                            if !closure.headers.iter().any(|h| h == header) {
+                                closure.headers.push(String::from(header));
+                            }
+                        }
+                        closure.imported.push(t);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let name = |t: &IPDLType| self.qualified_name(t);
+        closure
+            .structs
+            .sort_by_key(|tr| name(&IPDLType::StructType(tr.clone())));
+        closure
+            .unions
+            .sort_by_key(|tr| name(&IPDLType::UnionType(tr.clone())));
+        closure.imported.sort_by_key(name);
+        closure.headers.sort();
+        closure
+    }
+
+    // The fully qualified name of a named type, like `::mozilla::ipc::Shmem'.
+    // Arrays and the other wrapper types have no name of their own.
+    pub fn qualified_name(&self, t: &IPDLType) -> Option<String> {
+        Some(match t {
+            IPDLType::BuiltinCType(n) => String::from(*n),
+            IPDLType::ImportedCxxType(qid, _, _, _)
+            | IPDLType::ShmemType(qid)
+            | IPDLType::ByteBufType(qid)
+            | IPDLType::FDType(qid) => qid.to_string(),
+            IPDLType::StructType(tr) => tr.lookup_struct(&self.tuts).qname.to_string(),
+            IPDLType::UnionType(tr) => tr.lookup_union(&self.tuts).qname.to_string(),
+            IPDLType::ActorType(tuid) => self.protocol(tuid).qname.to_string(),
+            _ => return None,
+        })
+    }
+
+    // Whether |t| is the type called |name|, which is either a fully
+    // qualified name, with or without a leading `::', or a short name.
+    fn is_named(&self, t: &IPDLType, name: &str) -> bool {
+        if let IPDLType::BuiltinCType(n) = t {
+            return *n == name;
+        }
+        let full_name = match self.qualified_name(t) {
+            Some(full_name) => full_name,
+            None => return false,
+        };
+        if name.contains("::") {
+            full_name.trim_start_matches("::") == name.trim_start_matches("::")
+        } else {
+            full_name.rsplit("::").next() == Some(name)
+        }
+    }
+
+    fn find_uses(
+        &self,
+        t: &IPDLType,
+        name: &str,
+        path: &mut Vec<String>,
+        // The structs and unions that |path| goes through, so that
+        // recursive types are only followed once.
+        on_path: &mut Vec<(CompoundType, TypeRef)>,
+        found: &mut dyn FnMut(&[String]),
+    ) {
+        if self.is_named(t, name) {
+            found(path);
+            return;
+        }
+        self.find_uses_inside(t, name, path, on_path, found)
+    }
+
+    // Like find_uses, but only the uses inside of |t| are found, even if
+    // |t| is the type itself.
+    fn find_uses_inside(
+        &self,
+        t: &IPDLType,
+        name: &str,
+        path: &mut Vec<String>,
+        on_path: &mut Vec<(CompoundType, TypeRef)>,
+        found: &mut dyn FnMut(&[String]),
+    ) {
+        match t {
+            IPDLType::ArrayType(inner)
+            | IPDLType::MaybeType(inner)
+            | IPDLType::UniquePtrType(inner)
+            | IPDLType::NotNullType(inner) => self.find_uses(inner, name, path, on_path, found),
+            IPDLType::StructType(tr) if !on_path.contains(&(CompoundType::Struct, tr.clone())) => {
+                let sdef = tr.lookup_struct(&self.tuts);
+                let fields = &self.tus[&tr.tu].structs[tr.index].2;
+                on_path.push((CompoundType::Struct, tr.clone()));
+                path.push(format!("struct {}", sdef.qname.short_name()));
+                for (f, ft) in fields.iter().zip(sdef.field_types(&self.tuts)) {
+                    path.push(format!("field {}", f.name));
+                    self.find_uses(ft, name, path, on_path, found);
+                    path.pop();
+                }
+                path.pop();
+                on_path.pop();
+            }
+            IPDLType::UnionType(tr) if !on_path.contains(&(CompoundType::Union, tr.clone())) => {
+                let udef = tr.lookup_union(&self.tuts);
+                on_path.push((CompoundType::Union, tr.clone()));
+                path.push(format!("union {}", udef.qname.short_name()));
+                for c in udef.component_types(&self.tuts) {
+                    path.push(format!("variant {}", c.name(&self.tuts)));
+                    self.find_uses(c, name, path, on_path, found);
+                    path.pop();
+                }
+                path.pop();
+                on_path.pop();
+            }
+            _ => (),
+        }
+    }
+
+    // Whether |t| is the type called |name|, or contains it through arrays,
+    // structs, unions and the like.
+    pub fn contains_type(&self, t: &IPDLType, name: &str) -> bool {
+        let mut found = false;
+        self.find_uses(t, name, &mut Vec::new(), &mut Vec::new(), &mut |_| {
+            found = true
+        });
+        found
+    }
+
+    // Every place in the program that uses the declaration at |decl|, by
+    // file and then in the order the checker looked them up. This is only
+    // filled in when the program was checked with
+    // CheckOptions::track_references.
+    pub fn references_to(&self, decl: &Location) -> Vec<Location> {
+        let mut references = Vec::new();
+        for tuid in self.tu_ids_by_file() {
+            for symbol in self.tuts[&tuid].symbols.iter().filter(|s| &s.loc == decl) {
+                for r in &symbol.references {
+                    if !references.contains(r) {
+                        references.push(r.clone());
+                    }
+                }
+            }
+        }
+        references
+    }
+
+    // Every way that a message, struct or union contains the type called
+    // |name|, directly or through other structs and unions. The uses are
+    // grouped by translation unit, in a deterministic order.
+    pub fn uses_of_type(&self, name: &str) -> Vec<TypeUse> {
+        let mut uses = Vec::new();
+        for tuid in self.tu_ids() {
+            let tut = &self.tuts[&tuid];
+            let mut roots = Vec::new();
+            for (i, sdef) in tut.structs.iter().enumerate() {
+                roots.push((
+                    sdef.qname.loc().clone(),
+                    String::from(""),
+                    IPDLType::StructType(TypeRef::new(&tuid, i)),
+                ));
+            }
+            for (i, udef) in tut.unions.iter().enumerate() {
+                roots.push((
+                    udef.qname.loc().clone(),
+                    String::from(""),
+                    IPDLType::UnionType(TypeRef::new(&tuid, i)),
+                ));
+            }
+            if let Some(ptype) = &tut.protocol {
+                for md in &ptype.messages {
+                    let message = format!("{}::{}", ptype.qname.short_name(), md.name);
+                    let params = md.params.iter().map(|p| ("arg", p));
+                    let returns = md.returns.iter().map(|p| ("return", p));
+                    let mut counts = HashMap::new();
+                    for (kind, p) in params.chain(returns) {
+                        let count = counts.entry(kind).or_insert(0);
+                        *count += 1;
+                        roots.push((
+                            md.name.loc.clone(),
+                            format!("{} → {} {} ({})", message, kind, count, p.name),
+                            p.param_type.lookup(&self.tuts).clone(),
+                        ));
+                    }
+                }
+            }
+
+            for (loc, root, t) in roots {
+                // A struct or union that is the type isn't a use of it.
+                if root.is_empty() && self.is_named(&t, name) {
+                    continue;
+                }
+                let mut path = if root.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![root]
+                };
+                self.find_uses(&t, name, &mut path, &mut Vec::new(), &mut |p| {
+                    uses.push(TypeUse {
+                        loc: loc.clone(),
+                        path: p.to_vec(),
+                    })
+                });
+            }
+        }
+        uses
+    }
+
+    // Whether a struct or union contains itself, and if so the first way
+    // that it does. Arrays and the like are looked through, and other
+    // types are never recursive. A program that type checked has no types
+    // that are only partially defined, so every recursion goes through a
+    // union, an array, a Maybe or a UniquePtr, and a serializer has to
+    // stop there.
+    pub fn completeness(&self, t: &IPDLType) -> TypeCompleteness {
+        let t = match t {
+            IPDLType::ArrayType(inner)
+            | IPDLType::MaybeType(inner)
+            | IPDLType::UniquePtrType(inner)
+            | IPDLType::NotNullType(inner) => return self.completeness(inner),
+            IPDLType::StructType(_) | IPDLType::UnionType(_) => t,
+            _ => return TypeCompleteness::Defined,
+        };
+        let name = self.qualified_name(t).unwrap();
+        let mut first = None;
+        self.find_uses_inside(t, &name, &mut Vec::new(), &mut Vec::new(), &mut |p| {
+            if first.is_none() {
+                first = Some(p.to_vec());
+            }
+        });
+        match first {
+            Some(path) => TypeCompleteness::Recursive(path),
+            None => TypeCompleteness::Defined,
+        }
+    }
+
+    pub fn visit<V: IrVisitor>(&self, v: &mut V) {
+        for tuid in self.tu_ids() {
+            v.visit_translation_unit(&tuid, self.tuts.get(&tuid).unwrap());
+        }
+    }
+}
+
+// The types that a protocol needs, sorted by name. |imported| also holds
+// the builtin types. The headers are the ones that the imported types were
+// declared in, so builtin types like int32_t and special types like Shmem
+// don't add any.
+pub struct TypeClosure {
+    pub structs: Vec<TypeRef>,
+    pub unions: Vec<TypeRef>,
+    pub imported: Vec<IPDLType>,
+    pub headers: Vec<String>,
+}
+
+// Stable ordinals for the declarations of a program. Generated code that
+// numbers declarations, or lists them, uses this order, so that it only
+// depends on the IPDL, and not on the order the files were given or parsed
+// in, or on the iteration order of a HashMap:
+//
+// * Protocols are numbered in the order of their fully qualified names,
+//   which is the order of the IPCMessageStart enum.
+// * The structs and unions of a file are numbered in declaration order,
+//   after those of the files that come before it in tu_ids_by_file().
+// * Messages don't need anything here: the index of a message in
+//   ProtocolTypeDef::messages is its declaration order, which is the order
+//   of the MessageType enum.
+//
+// Adding a declaration can change the ordinals of the ones after it, but
+// checking the same files twice always gives the same ordinals.
+pub struct DeclOrder {
+    protocols: Vec<TUId>,
+    structs: Vec<TypeRef>,
+    unions: Vec<TypeRef>,
+}
+
+impl DeclOrder {
+    pub fn protocols(&self) -> &[TUId] {
+        &self.protocols
+    }
+
+    pub fn structs(&self) -> &[TypeRef] {
+        &self.structs
+    }
+
+    pub fn unions(&self) -> &[TypeRef] {
+        &self.unions
+    }
+
+    pub fn protocol_ordinal(&self, tuid: &TUId) -> Option<usize> {
+        self.protocols.iter().position(|p| p == tuid)
+    }
+
+    pub fn struct_ordinal(&self, tr: &TypeRef) -> Option<usize> {
+        self.structs.iter().position(|s| s == tr)
+    }
+
+    pub fn union_ordinal(&self, tr: &TypeRef) -> Option<usize> {
+        self.unions.iter().position(|u| u == tr)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DefinitionKind {
+    Protocol,
+    Struct,
+    Union,
+    Message,
+}
+
+// The declaration of a name. The name is spelled out from |loc| to |len|
+// characters after it, on the same line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    // The fully qualified name, like `::mozilla::dom::PContent::LoadURI'.
+    pub name: String,
+    pub kind: DefinitionKind,
+    pub loc: Location,
+    pub len: usize,
+}
+
+// A map from the fully qualified names of the protocols, structs, unions
+// and messages of a program to their declarations. Messages are named
+// after their protocol, like `mozilla::PFoo::Msg'. If two files declare
+// the same name, the one that comes first in tu_ids_by_file() wins.
+pub struct DefinitionIndex {
+    definitions: HashMap<String, Definition>,
+}
+
+impl DefinitionIndex {
+    // Find a definition by its fully qualified name, with or without a
+    // leading `::'.
+    pub fn lookup(&self, name: &str) -> Option<&Definition> {
+        if name.starts_with("::") {
+            self.definitions.get(name)
+        } else {
+            self.definitions.get(&format!("::{}", name))
+        }
+    }
+
+    // All of the definitions, sorted by name.
+    pub fn definitions(&self) -> Vec<&Definition> {
+        let mut definitions = self.definitions.values().collect::<Vec<_>>();
+        definitions.sort_by(|a, b| a.name.cmp(&b.name));
+        definitions
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeCompleteness {
+    // The type doesn't contain itself.
+    Defined,
+    // The fields and variants that lead from the type back to itself, in
+    // the same form as TypeUse, like `union U → variant S → struct S →
+    // field u'.
+    Recursive(Vec<String>),
+}
+
+// A way that a message, struct or union contains a type, like
+// `PContent::LoadURI → arg 3 (aLoadInfo) → struct LoadInfo → field fd'.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeUse {
+    // Where the message, struct or union is declared.
+    pub loc: Location,
+    pub path: Vec<String>,
+}
+
+impl fmt::Display for TypeUse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.join(" → "))
+    }
+}
+
+// A visitor over the typed IR, in the same style as visitor::Visitor.
+pub trait IrVisitor {
+    fn visit_translation_unit(&mut self, tuid: &TUId, tut: &TranslationUnitType) {
+        walk_translation_unit(self, tuid, tut)
+    }
+
+    // |types| is the type table of the translation unit, which the types
+    // of the fields, components and parameters are looked up in.
+    fn visit_struct(&mut self, _tr: &TypeRef, types: &TypeTable, sdef: &StructTypeDef) {
+        walk_struct(self, types, sdef)
+    }
+
+    fn visit_union(&mut self, _tr: &TypeRef, types: &TypeTable, udef: &UnionTypeDef) {
+        walk_union(self, types, udef)
+    }
+
+    fn visit_protocol(&mut self, _tuid: &TUId, types: &TypeTable, ptype: &ProtocolTypeDef) {
+        walk_protocol(self, types, ptype)
+    }
+
+    fn visit_message(
+        &mut self,
+        _ptype: &ProtocolTypeDef,
+        types: &TypeTable,
+        mtype: &MessageTypeDef,
+    ) {
+        walk_message(self, types, mtype)
+    }
+
+    // Called for every type, including the types wrapped by other types.
+    fn visit_type(&mut self, t: &IPDLType) {
+        walk_type(self, t)
+    }
+}
+
+pub fn walk_translation_unit<V: IrVisitor + ?Sized>(
+    v: &mut V,
+    tuid: &TUId,
+    tut: &TranslationUnitType,
+) {
+    for (i, s) in tut.structs.iter().enumerate() {
+        v.visit_struct(&TypeRef::new(tuid, i), &tut.types, s);
+    }
+    for (i, u) in tut.unions.iter().enumerate() {
+        v.visit_union(&TypeRef::new(tuid, i), &tut.types, u);
+    }
+    if let Some(p) = &tut.protocol {
+        v.visit_protocol(tuid, &tut.types, p);
+    }
+}
+
+pub fn walk_struct<V: IrVisitor + ?Sized>(v: &mut V, types: &TypeTable, sdef: &StructTypeDef) {
+    for f in &sdef.fields {
+        v.visit_type(types.get(f.index));
+    }
+}
+
+pub fn walk_union<V: IrVisitor + ?Sized>(v: &mut V, types: &TypeTable, udef: &UnionTypeDef) {
+    for c in &udef.components {
+        v.visit_type(types.get(c.index));
+    }
+}
+
+pub fn walk_protocol<V: IrVisitor + ?Sized>(v: &mut V, types: &TypeTable, ptype: &ProtocolTypeDef) {
+    for m in &ptype.messages {
+        v.visit_message(ptype, types, m);
+    }
+}
+
+pub fn walk_message<V: IrVisitor + ?Sized>(v: &mut V, types: &TypeTable, mtype: &MessageTypeDef) {
+    for p in mtype.params.iter().chain(mtype.returns.iter()) {
+        v.visit_type(types.get(p.param_type.index));
+    }
+}
+
+pub fn walk_type<V: IrVisitor + ?Sized>(v: &mut V, t: &IPDLType) {
+    match t {
+        IPDLType::ArrayType(inner)
+        | IPDLType::MaybeType(inner)
+        | IPDLType::UniquePtrType(inner)
+        | IPDLType::NotNullType(inner) => v.visit_type(inner),
+        _ => (),
+    }
+}
@@ -0,0 +1,229 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Just enough JSON for the files that other tools read, like the
+// compilation database and source maps, and for reading back the files
+// that we write ourselves, like warning baselines.
+
+use std::path::Path;
+
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn json_path(p: &Path) -> String {
+    json_string(&p.to_string_lossy())
+}
+
+pub fn json_list<I: IntoIterator<Item = String>>(items: I) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<_>>().join(", "))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    // The members of an object, in the order they appear.
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn error<T>(&self, what: &str) -> Result<T, String> {
+        Err(format!("{} at offset {}", what, self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        self.skip_whitespace();
+        if self.text[self.pos..].starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), String> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}'", s))
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue, String> {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        if self.eat("null") {
+            Ok(JsonValue::Null)
+        } else if self.eat("true") {
+            Ok(JsonValue::Bool(true))
+        } else if self.eat("false") {
+            Ok(JsonValue::Bool(false))
+        } else if rest.starts_with('"') {
+            self.string().map(JsonValue::String)
+        } else if self.eat("[") {
+            let mut items = Vec::new();
+            if !self.eat("]") {
+                loop {
+                    items.push(self.value()?);
+                    if self.eat("]") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            Ok(JsonValue::Array(items))
+        } else if self.eat("{") {
+            let mut members = Vec::new();
+            if !self.eat("}") {
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(":")?;
+                    members.push((key, self.value()?));
+                    if self.eat("}") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            Ok(JsonValue::Object(members))
+        } else {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+                .unwrap_or(rest.len());
+            match rest[..len].parse() {
+                Ok(n) if len > 0 => {
+                    self.pos += len;
+                    Ok(JsonValue::Number(n))
+                }
+                _ => self.error("expected a value"),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if !self.text[self.pos..].starts_with('"') {
+            return self.error("expected a string");
+        }
+        self.pos += 1;
+        let mut s = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(s);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('u') => {
+                        let hex = chars.by_ref().take(4).map(|(_, c)| c).collect::<String>();
+                        match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                            Some(c) => s.push(c),
+                            None => return self.error("bad `\\u' escape"),
+                        }
+                    }
+                    Some(c) => s.push(c),
+                    None => break,
+                },
+                c => s.push(c),
+            }
+        }
+        self.error("unterminated string")
+    }
+}
+
+pub fn parse_json(text: &str) -> Result<JsonValue, String> {
+    let mut parser = JsonParser { text, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != text.len() {
+        return parser.error("unexpected text after the value");
+    }
+    Ok(value)
+}
+
+#[test]
+fn json_string_tests() {
+    assert_eq!(json_string("a/b.ipdl"), "\"a/b.ipdl\"");
+    assert_eq!(json_string("C:\\x \"y\"\n"), "\"C:\\\\x \\\"y\\\"\\n\"");
+    assert_eq!(json_string("\u{1}"), "\"\\u0001\"");
+}
+
+#[test]
+fn parse_json_tests() {
+    let v = parse_json("{\"a\": [1, -2.5e1, true, null], \"b\": \"x\\\"\\u0041\\n\"}").unwrap();
+    assert_eq!(
+        v.get("a"),
+        Some(&JsonValue::Array(vec![
+            JsonValue::Number(1.0),
+            JsonValue::Number(-25.0),
+            JsonValue::Bool(true),
+            JsonValue::Null
+        ]))
+    );
+    assert_eq!(v.get("b").and_then(JsonValue::as_str), Some("x\"A\n"));
+    assert_eq!(
+        parse_json(&json_string("C:\\x \"y\"\n")).unwrap().as_str(),
+        Some("C:\\x \"y\"\n")
+    );
+    assert!(parse_json("[1,]").is_err());
+    assert!(parse_json("{} x").is_err());
+    assert!(parse_json("\"abc").is_err());
+}
@@ -0,0 +1,234 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// A lexer for IPDL that produces a stream of tokens with their spans.
+//
+// The parser itself uses the lexer generated by lalrpop, which is not
+// public. This lexer accepts the same tokens as the grammar in
+// ipdl.lalrpop, so the two must be kept in sync. Unlike the parser, it
+// works on the original text of a file: comments and preprocessor lines are
+// returned as tokens rather than being stripped, and characters that can't
+// start any token are returned as Token::Unknown rather than being an error.
+
+use std::fmt;
+
+// Keywords, which are never identifiers.
+pub const KEYWORDS: &[&str] = &[
+    "UniquePtr",
+    "async",
+    "both",
+    "child",
+    "class",
+    "from",
+    "include",
+    "intr",
+    "manager",
+    "manages",
+    "namespace",
+    "nullable",
+    "or",
+    "parent",
+    "protocol",
+    "returns",
+    "struct",
+    "sync",
+    "union",
+    "using",
+];
+
+// Punctuation, longest first.
+const PUNCTUATION: &[&str] = &[
+    "::", "(", ")", ",", ":", ";", "<", "=", ">", "?", "[", "]", "{", "}",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token<'a> {
+    Keyword(&'a str),
+    Identifier(&'a str),
+    // A decimal integer, like the value of `[ProtocolVersion=2]`.
+    Integer(&'a str),
+    // A string literal, including the quotes.
+    String(&'a str),
+    Punctuation(&'a str),
+    // A `//` or `/* */` comment, including the delimiters.
+    Comment(&'a str),
+    // A line starting with `#`, which is treated like a comment.
+    Preprocessor(&'a str),
+    Unknown(char),
+}
+
+// A range of byte offsets into the text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+pub struct Lexer<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(text: &'a str) -> Lexer<'a> {
+        Lexer { text, offset: 0 }
+    }
+
+    // The token starting at the current offset, and its length.
+    fn next_token(&self) -> (Token<'a>, usize) {
+        let rest: &'a str = &self.text[self.offset..];
+        let c = rest.chars().next().unwrap();
+
+        if rest.starts_with("//") || c == '#' {
+            let len = rest.find('\n').unwrap_or(rest.len());
+            let t = &rest[..len];
+            return if c == '#' {
+                (Token::Preprocessor(t), len)
+            } else {
+                (Token::Comment(t), len)
+            };
+        }
+
+        if let Some(body) = rest.strip_prefix("/*") {
+            let len = body.find("*/").map(|i| i + 4).unwrap_or(rest.len());
+            return (Token::Comment(&rest[..len]), len);
+        }
+
+        if let Some(body) = rest.strip_prefix('"') {
+            if let Some(i) = body.find(['"', '\n']) {
+                if body.as_bytes()[i] == b'"' {
+                    return (Token::String(&rest[..i + 2]), i + 2);
+                }
+            }
+            return (Token::Unknown(c), 1);
+        }
+
+        if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            return if KEYWORDS.contains(&word) {
+                (Token::Keyword(word), len)
+            } else {
+                (Token::Identifier(word), len)
+            };
+        }
+
+        if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            return (Token::Integer(&rest[..len]), len);
+        }
+
+        for p in PUNCTUATION {
+            if rest.starts_with(p) {
+                return (Token::Punctuation(&rest[..p.len()]), p.len());
+            }
+        }
+
+        (Token::Unknown(c), c.len_utf8())
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = (Token<'a>, Span);
+
+    fn next(&mut self) -> Option<(Token<'a>, Span)> {
+        let rest = &self.text[self.offset..];
+        let trimmed = rest.trim_start();
+        if trimmed.is_empty() {
+            self.offset = self.text.len();
+            return None;
+        }
+        self.offset += rest.len() - trimmed.len();
+
+        let (token, len) = self.next_token();
+        let span = Span {
+            start: self.offset,
+            end: self.offset + len,
+        };
+        self.offset += len;
+        Some((token, span))
+    }
+}
+
+pub fn tokenize(text: &str) -> Lexer<'_> {
+    Lexer::new(text)
+}
+
+#[test]
+fn lexer_tests() {
+    let tokens = |text| tokenize(text).map(|(t, _)| t).collect::<Vec<_>>();
+
+    assert_eq!(
+        tokens("async Foo(nsString a) returns (int b);"),
+        vec![
+            Token::Keyword("async"),
+            Token::Identifier("Foo"),
+            Token::Punctuation("("),
+            Token::Identifier("nsString"),
+            Token::Identifier("a"),
+            Token::Punctuation(")"),
+            Token::Keyword("returns"),
+            Token::Punctuation("("),
+            Token::Identifier("int"),
+            Token::Identifier("b"),
+            Token::Punctuation(")"),
+            Token::Punctuation(";"),
+        ]
+    );
+    assert_eq!(
+        tokens("using a::b from \"c.h\""),
+        vec![
+            Token::Keyword("using"),
+            Token::Identifier("a"),
+            Token::Punctuation("::"),
+            Token::Identifier("b"),
+            Token::Keyword("from"),
+            Token::String("\"c.h\""),
+        ]
+    );
+    assert_eq!(
+        tokens("// x\n#if y\n/* z\n */ asyncx"),
+        vec![
+            Token::Comment("// x"),
+            Token::Preprocessor("#if y"),
+            Token::Comment("/* z\n */"),
+            Token::Identifier("asyncx"),
+        ]
+    );
+    assert_eq!(
+        tokens("[Since=12]"),
+        vec![
+            Token::Punctuation("["),
+            Token::Identifier("Since"),
+            Token::Punctuation("="),
+            Token::Integer("12"),
+            Token::Punctuation("]"),
+        ]
+    );
+    assert_eq!(
+        tokens("\u{201c}a\" $"),
+        vec![
+            Token::Unknown('\u{201c}'),
+            Token::Identifier("a"),
+            Token::Unknown('"'),
+            Token::Unknown('$'),
+        ]
+    );
+
+    let spans = tokenize("  foo  (").map(|(_, s)| s).collect::<Vec<_>>();
+    assert_eq!(
+        spans,
+        vec![Span { start: 2, end: 5 }, Span { start: 7, end: 8 }]
+    );
+}
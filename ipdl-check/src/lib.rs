@@ -0,0 +1,59 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+pub extern crate ipdl_ast as ast;
+extern crate regex;
+
+// The parser and the type checker, which everything else is built on.
+pub mod batch;
+pub mod catalog;
+pub mod compiler;
+pub mod conditions;
+pub mod errors;
+pub mod events;
+pub mod fingerprint;
+pub mod fixes;
+pub mod include_graph;
+pub mod inputs;
+mod ipdl;
+pub mod ir;
+pub mod json;
+pub mod lexer;
+pub mod node_id;
+pub mod parser;
+pub mod progress;
+pub mod sinks;
+pub mod snapshot;
+pub mod sources;
+pub mod stable_hash;
+pub mod trivia;
+pub mod type_check;
+mod uncommenter;
+pub mod visitor;
+
+// Lints and queries on the checked program, and comparing versions of a
+// file.
+pub mod ast_diff;
+pub mod config;
+pub mod direction_rules;
+pub mod endpoints;
+pub mod explain;
+pub mod lifetime_diagrams;
+pub mod managers;
+pub mod policy;
+pub mod shmem;
+mod toml;
+
+// JSON descriptions for other tools, and the files that the command line
+// driver reads back.
+#[cfg(feature = "json")]
+pub mod baseline;
+#[cfg(feature = "json")]
+pub mod commands;
+#[cfg(feature = "json")]
+pub mod export;
+#[cfg(feature = "json")]
+pub mod metrics;
+#[cfg(feature = "json")]
+pub mod versions;
@@ -0,0 +1,267 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Mermaid sequence diagrams of how the actors of a managed protocol are
+// constructed and deleted, made from the typed IR, for documentation.
+//
+// A diagram shows each constructor that a manager declares for the
+// protocol, sent by each side that can send it, with the empty reply that
+// a sync one waits for, and then `__delete__', with the ActorDestroy calls that the
+// generated code makes on both sides. Top level protocols have no
+// constructors, so they have no diagram.
+
+use ast::{Direction, ProtocolSide, SendSemantics, TUId};
+use ir::TypedProgram;
+use std::path::PathBuf;
+use type_check::{Lifetime, MessageTypeDef, ParamTypeDef};
+
+fn semantics_name(s: SendSemantics) -> &'static str {
+    match s {
+        SendSemantics::Async => "async",
+        SendSemantics::Sync => "sync",
+        SendSemantics::Intr => "intr",
+    }
+}
+
+fn side_name(side: ProtocolSide) -> &'static str {
+    match side {
+        ProtocolSide::Parent => "Parent",
+        ProtocolSide::Child => "Child",
+    }
+}
+
+fn other_side(side: ProtocolSide) -> ProtocolSide {
+    match side {
+        ProtocolSide::Parent => ProtocolSide::Child,
+        ProtocolSide::Child => ProtocolSide::Parent,
+    }
+}
+
+// The sides that can send a message in a direction, parent first.
+fn senders(d: Direction) -> &'static [ProtocolSide] {
+    match d {
+        Direction::To(ProtocolSide::Parent) => &[ProtocolSide::Child],
+        Direction::To(ProtocolSide::Child) => &[ProtocolSide::Parent],
+        Direction::Both => &[ProtocolSide::Parent, ProtocolSide::Child],
+    }
+}
+
+// Mermaid ends a statement at `;', and reads `#' as the start of an entity
+// code and `<' as the start of HTML, so those are written as entity codes.
+fn escape(text: &str) -> String {
+    text.replace('#', "#35;")
+        .replace(';', "#59;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+}
+
+fn params(program: &TypedProgram, params: &[ParamTypeDef]) -> String {
+    params
+        .iter()
+        .map(|p| {
+            format!(
+                "{} {}",
+                p.param_type.lookup(&program.tuts).display(&program.tuts),
+                p.name
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// The arrows of one message, sent by |sender|, and of the reply to it if
+// it is sync. Constructors and destructors can't return values, but the
+// sender of a sync one still waits for the empty reply.
+fn message_lines(
+    program: &TypedProgram,
+    lines: &mut Vec<String>,
+    indent: &str,
+    sender: ProtocolSide,
+    name: &str,
+    md: &MessageTypeDef,
+) {
+    let (s, r) = (side_name(sender), side_name(other_side(sender)));
+    lines.push(format!(
+        "{}{}->>{}: {}",
+        indent,
+        s,
+        r,
+        escape(&format!(
+            "{} {}({})",
+            semantics_name(md.send_semantics),
+            name,
+            params(program, &md.params)
+        ))
+    ));
+    if md.send_semantics.is_async() {
+        return;
+    }
+    lines.push(format!(
+        "{}{}-->>{}: {}",
+        indent,
+        r,
+        s,
+        escape(&format!("reply ({})", params(program, &md.returns)))
+    ));
+    lines.push(format!(
+        "{}Note over {}: Send{} waits for the reply",
+        indent, s, md.name
+    ));
+}
+
+// The diagram of the constructors and destructors of the actors of
+// protocol |tuid|, or None if it is top level.
+pub fn lifetime_diagram(program: &TypedProgram, tuid: &TUId) -> Option<String> {
+    let ptype = program.protocol(tuid);
+    if ptype.managers.is_empty() {
+        return None;
+    }
+    let name = ptype.qname.short_name();
+    let mut lines = vec![
+        String::from("sequenceDiagram"),
+        format!("    participant Parent as {}Parent", name),
+        format!("    participant Child as {}Child", name),
+    ];
+
+    // Each way of constructing an actor is an alternative, labeled with
+    // the manager and the side that sends the constructor.
+    let mut constructions = Vec::new();
+    for manager in &ptype.managers {
+        let manager_name = program.protocol(manager).qname.short_name();
+        for md in &program.protocol(manager).messages {
+            if md.ctor_type() == Some(tuid) {
+                for sender in senders(md.direction) {
+                    constructions.push((*sender, manager_name.clone(), md));
+                }
+            }
+        }
+    }
+    if constructions.is_empty() {
+        lines.push(format!(
+            "    Note over Parent,Child: {} has no constructors, so its actors are only bound from \
+             ManagedEndpoints",
+            name
+        ));
+    }
+    for (i, (sender, manager_name, md)) in constructions.iter().enumerate() {
+        let label = format!(
+            "the {} sends {}::{}",
+            side_name(*sender).to_lowercase(),
+            manager_name,
+            md.name
+        );
+        let indent = if constructions.len() == 1 {
+            lines.push(format!("    Note over Parent,Child: {}", label));
+            "    "
+        } else {
+            let keyword = if i == 0 { "alt" } else { "else" };
+            lines.push(format!("    {} {}", keyword, label));
+            "        "
+        };
+        let receiver = other_side(*sender);
+        lines.push(format!(
+            "{}Note over {}: Send{} binds the new {}{}",
+            indent,
+            side_name(*sender),
+            md.name,
+            name,
+            side_name(*sender)
+        ));
+        message_lines(
+            program,
+            &mut lines,
+            indent,
+            *sender,
+            &format!("{}::{}", manager_name, md.name),
+            md,
+        );
+        lines.push(format!(
+            "{}Note over {}: the new {}{} is bound before Recv{}",
+            indent,
+            side_name(receiver),
+            name,
+            side_name(receiver),
+            md.name
+        ));
+    }
+    if constructions.len() > 1 {
+        lines.push(String::from("    end"));
+    }
+
+    let dtors = ptype
+        .messages
+        .iter()
+        .filter(|md| md.is_dtor())
+        .flat_map(|md| senders(md.direction).iter().map(move |s| (*s, md)))
+        .collect::<Vec<_>>();
+    for (i, (sender, md)) in dtors.iter().enumerate() {
+        let indent = if dtors.len() == 1 {
+            "    "
+        } else {
+            let keyword = if i == 0 { "alt" } else { "else" };
+            lines.push(format!(
+                "    {} the {} deletes",
+                keyword,
+                side_name(*sender).to_lowercase()
+            ));
+            "        "
+        };
+        message_lines(program, &mut lines, indent, *sender, &md.name.id, md);
+        lines.push(format!(
+            "{}Note over {}: Send{} calls ActorDestroy(Deletion)",
+            indent,
+            side_name(*sender),
+            md.name
+        ));
+        lines.push(format!(
+            "{}Note over {}: Recv{} is called, then ActorDestroy(Deletion)",
+            indent,
+            side_name(other_side(*sender)),
+            md.name
+        ));
+    }
+    if dtors.len() > 1 {
+        lines.push(String::from("    end"));
+    }
+    if !ptype.manages.is_empty() {
+        lines.push(format!(
+            "    Note over Parent,Child: the actors that {} manages get \
+             ActorDestroy(AncestorDeletion) first",
+            name
+        ));
+    }
+    lines.push(match ptype.lifetime {
+        Lifetime::ManualDealloc => format!(
+            "    Note over Parent,Child: Dealloc{0}Parent and Dealloc{0}Child free the actors",
+            name
+        ),
+        Lifetime::RefCounted => String::from(
+            "    Note over Parent,Child: IPC releases its reference to the actor on each side",
+        ),
+    });
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    Some(text)
+}
+
+// The diagrams of the managed protocols in |file_names|, as a Markdown
+// section for each, for `--lifetime-diagrams'.
+pub fn lifetime_diagrams_markdown(program: &TypedProgram, file_names: &[PathBuf]) -> String {
+    let mut sections = Vec::new();
+    for file in file_names {
+        let tuid = program.tu_for_file(file).unwrap();
+        if program.tuts[&tuid].protocol.is_none() {
+            continue;
+        }
+        if let Some(diagram) = lifetime_diagram(program, &tuid) {
+            sections.push(format!(
+                "## {}\n\n```mermaid\n{}```\n",
+                program.protocol(&tuid).qname,
+                diagram
+            ));
+        }
+    }
+    sections.join("\n")
+}
@@ -0,0 +1,90 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// The graph of which protocols manage which. Each edge goes from a manager
+// to a protocol that it manages, with the constructor messages that the
+// manager declares for it, so that tools can tell which side creates the
+// actors. A managed protocol that is only ever bound from a
+// ManagedEndpoint has no constructors.
+//
+// manager_graph_dot() writes the graph for Graphviz, with the edges
+// without constructors dashed. export::manager_graph_json() writes the
+// same edges as adjacency lists, for tools that want to read them back.
+
+use ast::{Direction, SendSemantics, TUId};
+use ir::TypedProgram;
+use type_check::MessageType;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constructor {
+    pub message: String,
+    pub send_semantics: SendSemantics,
+    pub direction: Direction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManagerEdge {
+    pub manager: TUId,
+    pub managee: TUId,
+    pub constructors: Vec<Constructor>,
+}
+
+// Every manager and managee pair, with the managers in the order of their
+// names and the managees in the order of the `manages' statements.
+pub fn manager_edges(program: &TypedProgram) -> Vec<ManagerEdge> {
+    let mut edges = Vec::new();
+    for tuid in program.decl_order().protocols() {
+        let ptype = program.protocol(tuid);
+        for managee in &ptype.manages {
+            let constructors = ptype
+                .messages
+                .iter()
+                .filter(|md| matches!(&md.mtype, MessageType::Ctor(t) if t == managee))
+                .map(|md| Constructor {
+                    message: md.name.id.clone(),
+                    send_semantics: md.send_semantics,
+                    direction: md.direction,
+                })
+                .collect();
+            edges.push(ManagerEdge {
+                manager: *tuid,
+                managee: *managee,
+                constructors,
+            });
+        }
+    }
+    edges
+}
+
+// The graph in the Graphviz dot language. Every protocol is a node, so top
+// level protocols that manage nothing show up too, and each edge is
+// labeled with its constructors.
+pub fn manager_graph_dot(program: &TypedProgram) -> String {
+    let name = |tuid: &TUId| program.protocol(tuid).qname.to_string();
+    let mut out = String::from("digraph managers {\n");
+    for tuid in program.decl_order().protocols() {
+        out.push_str(&format!("  \"{}\";\n", name(tuid)));
+    }
+    for e in manager_edges(program) {
+        let label = e
+            .constructors
+            .iter()
+            .map(|c| c.message.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        out.push_str(&format!(
+            "  \"{}\" -> \"{}\" [label=\"{}\"{}];\n",
+            name(&e.manager),
+            name(&e.managee),
+            label,
+            if e.constructors.is_empty() {
+                ", style=dashed"
+            } else {
+                ""
+            }
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
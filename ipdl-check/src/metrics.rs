@@ -0,0 +1,201 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Per-file numbers from a check, for build telemetry to find the IPDL
+// files that are slow or unusually big.
+//
+// Also a summary of a whole run, for CI to keep and track the health of
+// the IPDL files over time without parsing the log: how each file fared,
+// how many diagnostics of each code there were, how long each phase took
+// and how much the caches saved.
+//
+// There is an entry for every file that was read, including the included
+// ones. The tokens are the ones the grammar sees, so comments and
+// preprocessor lines aren't counted. The nodes are the ones that have a
+// NodeId, and are only known if the check succeeded.
+
+use compiler::Session;
+use errors::{Diagnostic, Severity};
+use ir::TypedProgram;
+use json::{json_list, json_path, json_string};
+use lexer::{tokenize, Token};
+use node_id::NodeMap;
+use sources::SourceManager;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileMetrics {
+    pub file: PathBuf,
+    // None if the file was taken from a snapshot instead of being parsed.
+    pub parse_time: Option<Duration>,
+    pub tokens: usize,
+    pub nodes: Option<usize>,
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+// The metrics of the files in |sources|, sorted by file name. |program|
+// is the result of the check, if it succeeded, and |diagnostics| are its
+// errors or warnings.
+pub fn file_metrics(
+    sources: &SourceManager,
+    program: Option<&TypedProgram>,
+    diagnostics: &[Diagnostic],
+) -> Vec<FileMetrics> {
+    let mut files = sources.files().collect::<Vec<_>>();
+    files.sort();
+    files
+        .into_iter()
+        .map(|file| {
+            let tokens = tokenize(sources.text(file).unwrap())
+                .filter(|(t, _)| !matches!(t, Token::Comment(_) | Token::Preprocessor(_)))
+                .count();
+            let nodes = program.and_then(|program| {
+                let tuid = program.tu_for_file(file)?;
+                Some(NodeMap::new(&program.tus[&tuid]).ids().len())
+            });
+            FileMetrics {
+                file: file.to_path_buf(),
+                parse_time: sources.parse_time(file),
+                tokens,
+                nodes,
+                errors: count_in_file(diagnostics, file, Severity::Error),
+                warnings: count_in_file(diagnostics, file, Severity::Warning),
+            }
+        })
+        .collect()
+}
+
+fn count_in_file(diagnostics: &[Diagnostic], file: &Path, severity: Severity) -> usize {
+    diagnostics
+        .iter()
+        .filter(|d| d.severity == severity)
+        .filter(|d| d.loc.as_ref().is_some_and(|loc| loc.file_name == file))
+        .count()
+}
+
+pub fn metrics_json(metrics: &[FileMetrics]) -> String {
+    let null_or = |n: Option<u128>| n.map_or(String::from("null"), |n| n.to_string());
+    let files = metrics
+        .iter()
+        .map(|m| {
+            format!(
+                "  {{\"file\": {}, \"parse_time_us\": {}, \"tokens\": {}, \"nodes\": {}, \
+                 \"errors\": {}, \"warnings\": {}}}",
+                json_path(&m.file),
+                null_or(m.parse_time.map(|t| t.as_micros())),
+                m.tokens,
+                null_or(m.nodes.map(|n| n as u128)),
+                m.errors,
+                m.warnings
+            )
+        })
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        String::from("{\"files\": []}\n")
+    } else {
+        format!("{{\"files\": [\n{}\n]}}\n", files.join(",\n"))
+    }
+}
+
+// The summary of the last check of |session|, which was given
+// |file_names|. Every file that was read has a status, which is `errors'
+// or `warnings' if it has any, or else `ok'. The cache numbers count every
+// check of the session, and a file is taken from the snapshot when it has
+// no parse time.
+pub fn summary_json(session: &Session, file_names: &[PathBuf]) -> String {
+    let diagnostics = session.diagnostics();
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let mut codes = BTreeMap::new();
+    for d in diagnostics {
+        if let Some(code) = d.code {
+            *codes.entry(code).or_insert(0) += 1;
+        }
+    }
+
+    let mut files = session.sources.files().collect::<Vec<_>>();
+    files.sort();
+    let file_lines = files
+        .iter()
+        .map(|file| {
+            let errors = count_in_file(diagnostics, file, Severity::Error);
+            let warnings = count_in_file(diagnostics, file, Severity::Warning);
+            let status = if errors > 0 {
+                "errors"
+            } else if warnings > 0 {
+                "warnings"
+            } else {
+                "ok"
+            };
+            format!(
+                "    {{\"file\": {}, \"status\": \"{}\", \"errors\": {}, \"warnings\": {}}}",
+                json_path(file),
+                status,
+                errors,
+                warnings
+            )
+        })
+        .collect::<Vec<_>>();
+    let code_lines = codes
+        .iter()
+        .map(|(code, count)| format!("{}: {}", json_string(code), count))
+        .collect::<Vec<_>>();
+    let times = session.phase_times();
+    let from_snapshot = if session.snapshot.is_some() {
+        files
+            .iter()
+            .filter(|file| session.sources.parse_time(file).is_none())
+            .count()
+    } else {
+        0
+    };
+
+    let mut out = String::from("{\n");
+    out.push_str(&format!(
+        "  \"result\": \"{}\",\n",
+        if errors > 0 { "errors" } else { "ok" }
+    ));
+    out.push_str(&format!(
+        "  \"files_checked\": {},\n",
+        json_list(file_names.iter().map(|f| json_path(f)))
+    ));
+    if file_lines.is_empty() {
+        out.push_str("  \"files\": [],\n");
+    } else {
+        out.push_str(&format!(
+            "  \"files\": [\n{}\n  ],\n",
+            file_lines.join(",\n")
+        ));
+    }
+    out.push_str(&format!(
+        "  \"errors\": {},\n  \"warnings\": {},\n",
+        errors,
+        diagnostics.len() - errors
+    ));
+    out.push_str(&format!("  \"codes\": {{{}}},\n", code_lines.join(", ")));
+    out.push_str(&format!(
+        "  \"phase_times_us\": {{\"parse\": {}, \"type_check\": {}, \"lint_levels\": {}}},\n",
+        times.parse.as_micros(),
+        times.type_check.as_micros(),
+        times.lint_levels.as_micros()
+    ));
+    out.push_str(&format!(
+        "  \"gather_cache\": {{\"hits\": {}, \"lookups\": {}}},\n",
+        session.cache().hits(),
+        session.cache().lookups()
+    ));
+    out.push_str(&format!(
+        "  \"snapshot\": {{\"hits\": {}, \"lookups\": {}}}\n",
+        from_snapshot,
+        if session.snapshot.is_some() {
+            files.len()
+        } else {
+            0
+        }
+    ));
+    out.push_str("}\n");
+    out
+}
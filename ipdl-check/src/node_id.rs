@@ -0,0 +1,139 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Stable identifiers for AST nodes.
+//
+// A NodeId is a hash of the base name of the file that contains the node
+// and of the structural position of the node inside the file, like "the
+// second parameter of the third message of the protocol". Unlike a
+// Location, it does not change when whitespace or comments are edited, so
+// it can be used to match up nodes across runs.
+
+use ast::{
+    Attributes, Location, MessageDecl, Namespace, Param, Protocol, StructField, TranslationUnit,
+    TypeSpec, UsingStmt,
+};
+use stable_hash::StableHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hasher;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub u64);
+
+impl NodeId {
+    fn new(file: &str, path: &str) -> NodeId {
+        let mut h = StableHasher::new();
+        h.write_str(file);
+        h.write_str(path);
+        NodeId(h.finish())
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Node<'a> {
+    Using(&'a UsingStmt),
+    Struct(&'a (Namespace, Attributes, Vec<StructField>)),
+    StructField(&'a StructField),
+    Union(&'a (Namespace, Attributes, Vec<TypeSpec>)),
+    UnionComponent(&'a TypeSpec),
+    Protocol(&'a (Namespace, Protocol)),
+    Message(&'a MessageDecl),
+    Param(&'a Param),
+}
+
+impl<'a> Node<'a> {
+    pub fn loc(&self) -> &'a Location {
+        match *self {
+            Node::Using(u) => u.cxx_type.loc(),
+            Node::Struct(s) => &s.0.name.loc,
+            Node::StructField(f) => &f.name.loc,
+            Node::Union(u) => &u.0.name.loc,
+            Node::UnionComponent(c) => c.loc(),
+            Node::Protocol(p) => &p.0.name.loc,
+            Node::Message(md) => &md.name.loc,
+            Node::Param(p) => &p.name.loc,
+        }
+    }
+}
+
+// The NodeIds of all of the nodes of a translation unit.
+pub struct NodeMap<'a> {
+    ids: Vec<NodeId>,
+    nodes: HashMap<NodeId, Node<'a>>,
+}
+
+impl<'a> NodeMap<'a> {
+    pub fn new(tu: &'a TranslationUnit) -> NodeMap<'a> {
+        let file = tu
+            .file_name
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut map = NodeMap {
+            ids: Vec::new(),
+            nodes: HashMap::new(),
+        };
+        let mut add = |path: &str, node: Node<'a>| {
+            let id = NodeId::new(&file, path);
+            map.ids.push(id);
+            map.nodes.insert(id, node);
+        };
+
+        for (i, u) in tu.using.iter().enumerate() {
+            add(&format!("using[{}]", i), Node::Using(u));
+        }
+        for (i, s) in tu.structs.iter().enumerate() {
+            let path = format!("struct[{}]", i);
+            add(&path, Node::Struct(s));
+            for (j, f) in s.2.iter().enumerate() {
+                add(&format!("{}/field[{}]", path, j), Node::StructField(f));
+            }
+        }
+        for (i, u) in tu.unions.iter().enumerate() {
+            let path = format!("union[{}]", i);
+            add(&path, Node::Union(u));
+            for (j, c) in u.2.iter().enumerate() {
+                add(
+                    &format!("{}/component[{}]", path, j),
+                    Node::UnionComponent(c),
+                );
+            }
+        }
+        if let Some(p) = &tu.protocol {
+            add("protocol", Node::Protocol(p));
+            for (i, md) in p.1.messages.iter().enumerate() {
+                let path = format!("protocol/message[{}]", i);
+                add(&path, Node::Message(md));
+                for (j, param) in md.in_params.iter().enumerate() {
+                    add(&format!("{}/param[{}]", path, j), Node::Param(param));
+                }
+                for (j, param) in md.out_params.iter().enumerate() {
+                    add(&format!("{}/returns[{}]", path, j), Node::Param(param));
+                }
+            }
+        }
+
+        map
+    }
+
+    // All ids, in the order the nodes appear in the translation unit.
+    pub fn ids(&self) -> &[NodeId] {
+        &self.ids
+    }
+
+    pub fn node(&self, id: &NodeId) -> Option<&Node<'a>> {
+        self.nodes.get(id)
+    }
+
+    pub fn location(&self, id: &NodeId) -> Option<&'a Location> {
+        self.nodes.get(id).map(|n| n.loc())
+    }
+}
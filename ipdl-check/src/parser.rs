@@ -0,0 +1,838 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+extern crate lalrpop_util as __lalrpop_util;
+use self::__lalrpop_util::lexer::Token;
+use self::__lalrpop_util::ParseError;
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use ast::{
+    Attributes, Direction, FileType, Identifier, Location, Protocol, StructField, TUId, TUMap,
+    TranslationUnit, TypeSpec, UsingStmt,
+};
+use conditions;
+use errors::{Diagnostic, Errors, IpdlError};
+use events::{CompilerEvents, NoEvents};
+use ipdl::TranslationUnitParser;
+use lexer;
+use snapshot::Snapshot;
+use sources::{quote_line, SourceManager};
+
+use uncommenter::uncomment_checked;
+
+pub struct TUIdFileMap {
+    next_id: TUId,
+    file_ids: HashMap<PathBuf, TUId>,
+    id_files: TUMap<PathBuf>,
+}
+
+impl TUIdFileMap {
+    fn new() -> TUIdFileMap {
+        TUIdFileMap {
+            next_id: 0,
+            file_ids: HashMap::new(),
+            id_files: TUMap::new(),
+        }
+    }
+
+    fn resolve_file_name(&mut self, pb: &PathBuf) -> TUId {
+        if let Some(id) = self.file_ids.get(pb).cloned() {
+            return id;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.file_ids.insert(pb.clone(), id);
+        self.id_files.insert(id, pb.clone());
+        id
+    }
+
+    fn id_file_name(&self, tuid: &TUId) -> &PathBuf {
+        self.id_files.get(tuid).unwrap()
+    }
+}
+
+pub struct IncludeResolver {
+    include_dirs: Vec<PathBuf>,
+    include_files: HashMap<String, PathBuf>,
+    id_file_map: TUIdFileMap,
+}
+
+impl IncludeResolver {
+    fn new(include_dirs: Vec<PathBuf>) -> IncludeResolver {
+        IncludeResolver {
+            include_dirs: include_dirs,
+            include_files: HashMap::new(),
+            id_file_map: TUIdFileMap::new(),
+        }
+    }
+
+    fn resolve_include(&mut self, include: &str) -> Option<TUId> {
+        if let Some(ref pb) = self.include_files.get(include) {
+            return Some(self.id_file_map.resolve_file_name(&pb));
+        }
+
+        let pb = self.find_include(include)?;
+        self.include_files.insert(String::from(include), pb.clone());
+        Some(self.id_file_map.resolve_file_name(&pb))
+    }
+
+    // The path of |include| in the first include directory that has it.
+    fn find_include(&self, include: &str) -> Option<PathBuf> {
+        // XXX The Python parser also checks '' for some reason.
+        let file_path = Path::new(&include);
+        for d in &self.include_dirs {
+            let mut p = d.clone();
+            p.push(file_path);
+
+            if p.exists() {
+                if let Ok(pb) = p.canonicalize() {
+                    return Some(pb);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+pub struct ParserState<'a> {
+    include_resolver: &'a RefCell<IncludeResolver>,
+    // The text being parsed, for quoting in errors.
+    text: &'a str,
+    pub file_type: FileType,
+    pub file_name: PathBuf,
+    pub direction: Cell<Option<Direction>>,
+    errors: RefCell<Errors>,
+    missing_include: Cell<bool>,
+    newline_offsets: Vec<usize>,
+}
+
+impl<'a> ParserState<'a> {
+    pub fn new(
+        include_resolver: &'a RefCell<IncludeResolver>,
+        text: &'a str,
+        file_type: FileType,
+        file_name: &Path,
+        newline_offsets: Vec<usize>,
+    ) -> ParserState<'a> {
+        ParserState {
+            include_resolver: include_resolver,
+            text,
+            file_type: file_type,
+            file_name: PathBuf::from(file_name),
+            direction: Cell::new(None),
+            errors: RefCell::new(Errors::none()),
+            missing_include: Cell::new(false),
+            newline_offsets: newline_offsets,
+        }
+    }
+
+    pub fn resolve_include_path(&self, loc: &Location, file: &str) -> TUId {
+        if let Some(tuid) = self.include_resolver.borrow_mut().resolve_include(&file) {
+            return tuid;
+        }
+
+        // The kind of the include picks the extension, so a protocol that
+        // is included as a header, or the other way around, would only be
+        // reported as missing.
+        let (path, is_protocol) = match file.strip_suffix(".ipdlh") {
+            Some(path) => (path, false),
+            None => (file.trim_end_matches(".ipdl"), true),
+        };
+        let name = path.replace('/', "::");
+        let other = if is_protocol {
+            format!("{}.ipdlh", path)
+        } else {
+            format!("{}.ipdl", path)
+        };
+        if let Some(path) = self.include_resolver.borrow().find_include(&other) {
+            let error = if is_protocol {
+                format!(
+                    "`include protocol {}' found `{}', which is a header, not a protocol; \
+                     use `include {}' instead",
+                    name,
+                    path.display(),
+                    name
+                )
+            } else {
+                format!(
+                    "`include {}' found `{}', which is a protocol, not a header; \
+                     use `include protocol {}' instead",
+                    name,
+                    path.display(),
+                    name
+                )
+            };
+            self.add_error(loc, &error);
+            self.missing_include.set(true);
+            return -1; // Dummy id
+        }
+
+        self.add_error(&loc, &format!("can't locate include file `{}'", &file));
+        self.missing_include.set(true);
+        -1 // Dummy id
+    }
+
+    pub fn resolve_location(&self, byte_offset: usize) -> Location {
+        match self.newline_offsets.binary_search(&byte_offset) {
+            Ok(r) => panic!(
+                "Token should not start or end on a newline: {}, {}",
+                byte_offset, r
+            ),
+            Err(index) => {
+                let file_name = self.file_name.clone();
+                if index == 0 {
+                    Location {
+                        file_name: file_name,
+                        lineno: 1,
+                        colno: byte_offset,
+                    }
+                } else {
+                    let line_start_offset = self.newline_offsets[index - 1] + 1;
+                    Location {
+                        file_name: file_name,
+                        lineno: index + 1,
+                        colno: byte_offset - line_start_offset,
+                    }
+                }
+            }
+        }
+    }
+
+    // Like resolve_location, but for the end of the last token in the file,
+    // which may be right before a newline.
+    fn resolve_eof_location(&self, byte_offset: usize) -> Location {
+        let index = match self.newline_offsets.binary_search(&byte_offset) {
+            Ok(index) | Err(index) => index,
+        };
+        let line_start_offset = if index == 0 {
+            0
+        } else {
+            self.newline_offsets[index - 1] + 1
+        };
+        Location {
+            file_name: self.file_name.clone(),
+            lineno: index + 1,
+            colno: byte_offset - line_start_offset,
+        }
+    }
+
+    pub fn add_error(&self, loc: &Location, error: &str) {
+        self.errors.borrow_mut().append_one(&loc, error);
+    }
+
+    // Describe a character that can't start any token, and quote the line
+    // it is on with a caret under it.
+    fn unknown_character_message(&self, c: char, loc: &Location) -> String {
+        let mut message = format!(
+            "Unexpected token: the character `{}' (U+{:04X}) can't start any token",
+            c, c as u32
+        );
+        let hint = match c {
+            '\u{201c}' | '\u{201d}' | '\u{201e}' => Some("use a plain `\"' instead"),
+            '\u{2018}' | '\u{2019}' => Some("IPDL has no character literals"),
+            '\u{a0}' | '\u{2009}' | '\u{200b}' | '\u{feff}' => {
+                Some("this is an invisible space; replace it with a plain space")
+            }
+            '\u{2013}' | '\u{2014}' => Some("this is a dash, not a `-'"),
+            '"' => Some("strings must end on the line they start on"),
+            _ => None,
+        };
+        if let Some(hint) = hint {
+            message.push_str("; ");
+            message.push_str(hint);
+        }
+        if let Some(line) = self.text.lines().nth(loc.lineno - 1) {
+            message.push('\n');
+            message.push_str(&quote_line(line, loc.colno));
+        }
+        message
+    }
+
+    pub fn add_syntax_error(&self, error: ParseError<usize, Token, &'static str>) {
+        let diagnostic = match error {
+            ParseError::InvalidToken { location } => {
+                let loc = self.resolve_location(location);
+                Diagnostic::new(&loc, "Unexpected token.")
+                // XXX This does not include a token, so we can't precisely
+                // match the Python compiler's error.
+            }
+            ParseError::UnrecognizedToken { token, expected: _ } => {
+                let (start, t, _) = token;
+                let loc = self.resolve_location(start);
+                // Characters that can't start any token are lexed as a
+                // token that no rule accepts. See ipdl.lalrpop.
+                if let Some((lexer::Token::Unknown(c), _)) = lexer::tokenize(t.1).next() {
+                    Diagnostic::new(&loc, &self.unknown_character_message(c, &loc))
+                } else if let Some(hint) = legacy_construct_hint(t.1) {
+                    Diagnostic::new(&loc, &format!("bad syntax near `{}': {}", t.1, hint))
+                } else {
+                    Diagnostic::new(&loc, &format!("bad syntax near `{}'", t.1))
+                    // XXX Can anything useful be reported about |expected|?
+                }
+            }
+            ParseError::UnrecognizedEof {
+                location,
+                expected: _,
+            } => {
+                let loc = self.resolve_eof_location(location);
+                Diagnostic::new(&loc, "bad syntax near `???'")
+            }
+            ParseError::ExtraToken { token } => {
+                let (start, t, _) = token;
+                let loc = self.resolve_location(start);
+                Diagnostic::new(&loc, &format!("Extra token `{}'.", t.1))
+            }
+            ParseError::User { error: _ } => {
+                panic!("Unexpected user error.");
+            }
+        };
+        self.errors.borrow_mut().push(diagnostic);
+    }
+}
+
+// Words that started constructs that have been removed from IPDL, with a
+// hint about what to do instead. These are only used when the parser
+// fails at the word, so they can still be used as identifiers.
+fn legacy_construct_hint(word: &str) -> Option<&'static str> {
+    match word {
+        "state" | "start" => Some(
+            "protocol state machines have been removed from IPDL; \
+             check the order of messages in the C++ implementation instead",
+        ),
+        "bridges" | "opens" => Some(
+            "`bridges' and `opens' have been removed from IPDL; \
+             create the endpoints in C++ and send one of them in a message instead",
+        ),
+        "spawns" => Some(
+            "`spawns' has been removed from IPDL; \
+             launch the process in C++ and bind a top-level actor to it",
+        ),
+        "rpc" => Some("`rpc' has been renamed to `intr'"),
+        "answer" | "call" => Some(
+            "the direction of an intr message is given by the \
+             `parent:', `child:' or `both:' section it is in",
+        ),
+        _ => None,
+    }
+}
+
+// What a file has to define depends on its extension. These are checked
+// again for translation units that weren't parsed.
+pub const NO_PROTOCOL: &str = "Protocol file must contain a protocol. A file with only structs \
+                               and unions is a header, and its name has to end with `.ipdlh'";
+pub const PROTOCOL_IN_HEADER: &str =
+    "can't define a protocol in a header.  Do it in a protocol spec instead.";
+
+// The error for something that can only be in a protocol, like |what| =
+// `a `manager' statement', outside of one.
+pub fn outside_protocol(parser_state: &ParserState, what: &str) -> String {
+    match parser_state.file_type {
+        FileType::Header => format!(
+            "{} can only be in a protocol, and headers can't define protocols",
+            what
+        ),
+        FileType::Protocol => format!("{} can only be inside of the protocol", what),
+    }
+}
+
+// The error for a struct or union that is declared in a protocol's body.
+pub fn inside_protocol(kind: &str, name: &str) -> String {
+    format!(
+        "{} `{}' is declared inside of a protocol, but structs and unions can only be declared \
+         outside of protocols; move it before the protocol",
+        kind, name
+    )
+}
+
+pub enum PreambleStmt {
+    CxxInclude(Identifier),
+    Include(TUId, Identifier),
+    Using(UsingStmt),
+    // `header;', which says that the file is a header.
+    HeaderMarker(Identifier),
+}
+
+pub enum TopLevelDecl {
+    Struct(Attributes, Vec<StructField>),
+    Union(Attributes, Vec<TypeSpec>),
+    Protocol(Protocol),
+}
+
+// Limits on the input, so that a corrupted or generated file can't make
+// the parser and the checker use unbounded time and memory. None means
+// that there is no limit. The defaults are far above what any real IPDL
+// file needs.
+#[derive(Debug, Clone)]
+pub struct InputLimits {
+    // The size of a file, in bytes.
+    pub max_file_size: Option<u64>,
+    // How many levels deep files can be included. The files given to the
+    // parser are at level 0.
+    pub max_include_depth: Option<usize>,
+    // How many things a file can declare: imported types, structs and
+    // their fields, unions and their components, and messages and their
+    // parameters.
+    pub max_declarations: Option<usize>,
+}
+
+impl Default for InputLimits {
+    fn default() -> InputLimits {
+        InputLimits {
+            max_file_size: Some(16 * 1024 * 1024),
+            max_include_depth: Some(64),
+            max_declarations: Some(100_000),
+        }
+    }
+}
+
+// The locations of everything that a translation unit declares, in the
+// order they are counted against InputLimits::max_declarations.
+fn declaration_locations(tu: &TranslationUnit) -> impl Iterator<Item = &Location> {
+    let usings = tu.using.iter().map(|u| u.cxx_type.loc());
+    let structs = tu.structs.iter().flat_map(|(ns, _, fields)| {
+        std::iter::once(&ns.name.loc).chain(fields.iter().map(|f| &f.name.loc))
+    });
+    let unions = tu.unions.iter().flat_map(|(ns, _, components)| {
+        std::iter::once(&ns.name.loc).chain(components.iter().map(|c| c.loc()))
+    });
+    let messages = tu.protocol.iter().flat_map(|(_, p)| {
+        p.messages.iter().flat_map(|md| {
+            std::iter::once(&md.name.loc).chain(
+                md.in_params
+                    .iter()
+                    .chain(&md.out_params)
+                    .map(|p| &p.name.loc),
+            )
+        })
+    });
+    usings.chain(structs).chain(unions).chain(messages)
+}
+
+fn check_declaration_limit(tu: &TranslationUnit, limits: &InputLimits) -> Result<(), IpdlError> {
+    let max = match limits.max_declarations {
+        Some(max) => max,
+        None => return Ok(()),
+    };
+    let loc = match declaration_locations(tu).nth(max) {
+        Some(loc) => loc,
+        None => return Ok(()),
+    };
+    let file_name = tu.file_name.file_name().unwrap_or_default();
+    let message = format!(
+        "`{}' declares more than the limit of {} types, fields, messages and parameters",
+        file_name.to_string_lossy(),
+        max
+    );
+    Err(IpdlError::Parse(vec![Diagnostic::new(loc, &message)]))
+}
+
+fn check_file_size(file_name: &Path, limits: &InputLimits) -> Result<(), IpdlError> {
+    let size = match (limits.max_file_size, fs::metadata(file_name)) {
+        (Some(max), Ok(metadata)) if metadata.len() > max => (metadata.len(), max),
+        // If the file can't be read, that is reported when it is read.
+        _ => return Ok(()),
+    };
+    Err(IpdlError::Io(format!(
+        "{}: error: the file is {} bytes, more than the limit of {} bytes",
+        file_name.display(),
+        size.0,
+        size.1
+    )))
+}
+
+// The result of parsing a single file, along with any errors.
+struct ParsedFile {
+    tu: Option<TranslationUnit>,
+    errors: Errors,
+    missing_include: bool,
+}
+
+// Read a source file. A UTF-8 byte order mark is dropped and Windows line
+// endings are turned into `\n', so that locations are the same however the
+// file was checked out. Anything that uses the text of a file along with
+// its parsed translation unit, like a TriviaMap, should read it with this.
+pub fn read_file(file_name: &Path) -> Result<String, String> {
+    let mut bytes = Vec::new();
+    File::open(file_name)
+        .and_then(|mut f| f.read_to_end(&mut bytes))
+        .map_err(|e| format!("can't read file: {}", e))?;
+    let text = String::from_utf8(bytes).map_err(|e| {
+        let bytes = e.as_bytes();
+        let offset = e.utf8_error().valid_up_to();
+        let before = &bytes[..offset];
+        let lineno = before.iter().filter(|&&b| b == b'\n').count() + 1;
+        let line_start = before
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        format!(
+            "invalid UTF-8 at line {}, column {} (byte offset {})",
+            lineno,
+            offset - line_start,
+            offset
+        )
+    })?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+    Ok(text.replace("\r\n", "\n"))
+}
+
+fn parse_text(
+    include_resolver: &RefCell<IncludeResolver>,
+    file_name: &Path,
+    original_text: &str,
+) -> ParsedFile {
+    // The file type and name are later enforced by the type checker.
+    // This is just a hint to the parser.
+    let file_type = FileType::from_file_path(&file_name).unwrap();
+
+    let (text, unterminated_comment) = uncomment_checked(original_text);
+
+    // Create a vector of byte offsets of all the newlines in the input.
+    // We'll use this to resolve (lineno, colno) pairs.
+    let mut newline_offsets = Vec::new();
+    let mut offset = 0;
+    for line in text.split('\n') {
+        offset += line.len();
+        newline_offsets.push(offset);
+        offset += 1;
+    }
+
+    let parser_state = ParserState::new(
+        include_resolver,
+        &text,
+        file_type,
+        file_name,
+        newline_offsets,
+    );
+    if let Some(offset) = unterminated_comment {
+        let loc = parser_state.resolve_location(offset);
+        parser_state.add_error(&loc, "unterminated comment, which starts here");
+    }
+    let tu = match TranslationUnitParser::new().parse(&parser_state, &text) {
+        Ok(tu) => Some(tu),
+        // Running into the end of the file is caused by the comment.
+        Err(ParseError::UnrecognizedEof { .. }) if unterminated_comment.is_some() => None,
+        Err(e) => {
+            parser_state.add_syntax_error(e);
+            None
+        }
+    };
+
+    let (sections, condition_errors) = conditions::scan(file_name, original_text);
+    parser_state.errors.borrow_mut().append(condition_errors);
+    let tu = tu.map(|mut tu| {
+        tu.conditions = sections;
+        tu
+    });
+
+    ParsedFile {
+        tu,
+        errors: parser_state.errors.replace(Errors::none()),
+        missing_include: parser_state.missing_include.get(),
+    }
+}
+
+pub fn parse_file(
+    include_resolver: &RefCell<IncludeResolver>,
+    file_name: &PathBuf,
+) -> Result<TranslationUnit, IpdlError> {
+    parse_source(include_resolver, file_name, read_file(file_name).as_deref())
+}
+
+fn parse_source(
+    include_resolver: &RefCell<IncludeResolver>,
+    file_name: &Path,
+    text: Result<&str, &String>,
+) -> Result<TranslationUnit, IpdlError> {
+    let text = text.map_err(|e| IpdlError::Io(format!("{}: error: {}", file_name.display(), e)))?;
+    let parsed = parse_text(include_resolver, file_name, text);
+    if parsed.missing_include {
+        // The other errors are probably caused by the missing include.
+        let errors = IpdlError::Parse(parsed.errors.into_diagnostics());
+        return Err(IpdlError::Io(errors.to_string()));
+    }
+    parsed.errors.into_result(IpdlError::Parse)?;
+    Ok(parsed.tu.unwrap())
+}
+
+// Parse a single file, without parsing the files it includes, and return as
+// much of the translation unit as could be parsed along with all of the
+// errors. This never fails outright, so that tools like editors can make
+// use of files with syntax errors. The resulting translation unit may be
+// missing some messages or struct fields, and is None if the syntax errors
+// were too bad to recover from.
+pub fn parse_partial(
+    include_dirs: &[PathBuf],
+    file_name: &Path,
+) -> (Option<TranslationUnit>, Vec<Diagnostic>) {
+    let text = match read_file(file_name) {
+        Ok(text) => text,
+        Err(e) => return (None, vec![Diagnostic::without_location(&e)]),
+    };
+    let include_resolver = RefCell::new(IncludeResolver::new(include_dirs.to_vec()));
+    let parsed = parse_text(&include_resolver, file_name, &text);
+    (parsed.tu, parsed.errors.into_diagnostics())
+}
+
+fn include_context_to_string(include_context: &Vec<PathBuf>) -> String {
+    let mut context = String::new();
+    for pb in include_context {
+        context.push_str(&format!("  in file included from `{}':\n", pb.display()));
+    }
+    context
+}
+
+fn add_include_context(e: IpdlError, include_context: &[PathBuf]) -> IpdlError {
+    match e {
+        IpdlError::Io(m) => {
+            IpdlError::Io(include_context_to_string(&include_context.to_vec()) + &m)
+        }
+        IpdlError::Parse(mut ds) => {
+            for d in &mut ds {
+                d.included_from = include_context.to_vec();
+            }
+            IpdlError::Parse(ds)
+        }
+        e => e,
+    }
+}
+
+fn parse_internal(
+    include_dirs: &Vec<PathBuf>,
+    file_names: Vec<PathBuf>,
+    ignore_errors: bool,
+    sources: &mut SourceManager,
+    snapshot: Option<&Snapshot>,
+    limits: &InputLimits,
+    events: &mut dyn CompilerEvents,
+) -> Result<TUMap<TranslationUnit>, IpdlError> {
+    let mut work_list: Vec<(PathBuf, Vec<PathBuf>)> = Vec::new();
+    let mut parsed = TUMap::new();
+    let mut visited = HashSet::new();
+
+    let mut include_resolver = IncludeResolver::new(include_dirs.clone());
+    for f in file_names {
+        let fc = match f.canonicalize() {
+            Ok(fc) => fc,
+            Err(_) => {
+                if ignore_errors {
+                    continue;
+                } else {
+                    return Err(IpdlError::Io(format!(
+                        "error: can't locate file specified on the command line `{}'",
+                        f.display()
+                    )));
+                }
+            }
+        };
+
+        // Generated file lists often name a file twice, or by two paths
+        // that canonicalize to the same one, which is parsed once, where
+        // it is named first. Its translation unit has the canonical path
+        // whichever path named it first, like inputs::expand_inputs.
+        let fid = include_resolver.id_file_map.resolve_file_name(&fc);
+        if visited.insert(fid) {
+            work_list.push((fc, Vec::new()));
+        }
+    }
+
+    let include_resolver_cell = RefCell::new(include_resolver);
+
+    while !work_list.is_empty() {
+        let mut new_work_list = Vec::new();
+        for (curr_file, include_context) in work_list {
+            if let Err(e) = check_file_size(&curr_file, limits) {
+                if ignore_errors {
+                    continue;
+                }
+                return Err(add_include_context(e, &include_context));
+            }
+            let text = sources.load(&curr_file);
+            // Only included files are taken from the snapshot. The ones on
+            // the command line are the ones being edited.
+            let unchanged = match (snapshot, &text) {
+                (Some(snapshot), Ok(text)) if !include_context.is_empty() => {
+                    snapshot.translation_unit(&curr_file, text)
+                }
+                _ => None,
+            };
+            let tu = match unchanged {
+                Some((mut tu, include_files)) => {
+                    events.file_parsed(&curr_file, None);
+                    let mut include_resolver = include_resolver_cell.borrow_mut();
+                    tu.includes = include_files
+                        .iter()
+                        .map(|f| include_resolver.id_file_map.resolve_file_name(f))
+                        .collect();
+                    tu
+                }
+                None => {
+                    // XXX In the long run, we probably don't want to output this.
+                    println!("Parsing file {}", curr_file.display());
+                    let start = Instant::now();
+                    let parsed = parse_source(&include_resolver_cell, &curr_file, text.as_deref())
+                        .and_then(|tu| check_declaration_limit(&tu, limits).map(|_| tu));
+                    sources.record_parse_time(&curr_file, start.elapsed());
+                    events.file_parsed(&curr_file, Some(start.elapsed()));
+                    match parsed {
+                        Ok(tu) => tu,
+                        Err(e) => {
+                            if ignore_errors {
+                                continue;
+                            } else {
+                                return Err(add_include_context(e, &include_context));
+                            }
+                        }
+                    }
+                }
+            };
+
+            for i in &tu.includes {
+                if visited.contains(i) {
+                    continue;
+                }
+                if limits
+                    .max_include_depth
+                    .is_some_and(|max| include_context.len() >= max)
+                {
+                    if ignore_errors {
+                        continue;
+                    }
+                    let e = IpdlError::Io(format!(
+                        "{}: error: includes are nested more than the limit of {} levels deep",
+                        curr_file.display(),
+                        include_context.len()
+                    ));
+                    return Err(add_include_context(e, &include_context));
+                }
+                let mut new_context = include_context.clone();
+                new_context.push(curr_file.clone());
+                visited.insert(i.clone());
+                new_work_list.push((
+                    include_resolver_cell
+                        .borrow()
+                        .id_file_map
+                        .id_file_name(i)
+                        .clone(),
+                    new_context,
+                ));
+            }
+
+            let curr_id = include_resolver_cell
+                .borrow_mut()
+                .id_file_map
+                .resolve_file_name(&curr_file);
+            parsed.insert(curr_id, tu);
+        }
+
+        work_list = new_work_list;
+    }
+
+    Ok(parsed)
+}
+
+pub fn parse_with_errors(
+    include_dirs: &Vec<PathBuf>,
+    file_names: Vec<PathBuf>,
+) -> Result<TUMap<TranslationUnit>, IpdlError> {
+    parse_with_sources(include_dirs, file_names, &mut SourceManager::new())
+}
+
+// Like parse_with_errors, but the text of every file that is parsed is
+// kept in |sources|, for quoting in diagnostics and for later passes.
+pub fn parse_with_sources(
+    include_dirs: &Vec<PathBuf>,
+    file_names: Vec<PathBuf>,
+    sources: &mut SourceManager,
+) -> Result<TUMap<TranslationUnit>, IpdlError> {
+    parse_with_limits(
+        include_dirs,
+        file_names,
+        None,
+        sources,
+        &InputLimits::default(),
+    )
+}
+
+// Like parse_with_sources, but included files that haven't changed since
+// |snapshot| was written are taken from it instead of being parsed.
+pub fn parse_with_snapshot(
+    include_dirs: &Vec<PathBuf>,
+    file_names: Vec<PathBuf>,
+    snapshot: &Snapshot,
+    sources: &mut SourceManager,
+) -> Result<TUMap<TranslationUnit>, IpdlError> {
+    parse_with_limits(
+        include_dirs,
+        file_names,
+        Some(snapshot),
+        sources,
+        &InputLimits::default(),
+    )
+}
+
+// Like parse_with_snapshot, but the snapshot is optional, and the input is
+// held to |limits| instead of the default limits.
+pub fn parse_with_limits(
+    include_dirs: &Vec<PathBuf>,
+    file_names: Vec<PathBuf>,
+    snapshot: Option<&Snapshot>,
+    sources: &mut SourceManager,
+    limits: &InputLimits,
+) -> Result<TUMap<TranslationUnit>, IpdlError> {
+    parse_with_events(
+        include_dirs,
+        file_names,
+        snapshot,
+        sources,
+        limits,
+        &mut NoEvents,
+    )
+}
+
+// Like parse_with_limits, but each file that is parsed or taken from the
+// snapshot is reported to |events|.
+pub fn parse_with_events(
+    include_dirs: &Vec<PathBuf>,
+    file_names: Vec<PathBuf>,
+    snapshot: Option<&Snapshot>,
+    sources: &mut SourceManager,
+    limits: &InputLimits,
+    events: &mut dyn CompilerEvents,
+) -> Result<TUMap<TranslationUnit>, IpdlError> {
+    parse_internal(
+        include_dirs,
+        file_names,
+        /* ignore_errors = */ false,
+        sources,
+        snapshot,
+        limits,
+        events,
+    )
+}
+
+pub fn parse(
+    include_dirs: &Vec<PathBuf>,
+    file_names: Vec<PathBuf>,
+) -> Option<TUMap<TranslationUnit>> {
+    parse_internal(
+        include_dirs,
+        file_names,
+        /* ignore_errors = */ true,
+        &mut SourceManager::new(),
+        None,
+        &InputLimits::default(),
+        &mut NoEvents,
+    )
+    .ok()
+}
@@ -0,0 +1,300 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Policies, which are rules about protocols and messages that are declared
+// in `[[policy]]' tables of ipdl.toml files:
+//
+//     [[policy]]
+//     name = "toplevel-parent-proc"
+//     protocols = "toplevel"
+//     require-attribute = "ParentProc"
+//
+//     [[policy]]
+//     name = "async-fds"
+//     messages = "all"
+//     carrying = "mozilla::ipc::FileDescriptor"
+//     require = "async"
+//     level = "deny"
+//
+// A policy selects either protocols (`all', `toplevel' or `managed') or
+// messages (`all', `async', `sync' or `intr'), optionally only the
+// messages whose parameters or return values carry a type. Each selected
+// protocol or message must then satisfy every requirement of the policy.
+// A config in a subdirectory can replace a policy by using the same name,
+// or turn it off with `level = "allow"'.
+
+use ast::{Attributes, Location, SendSemantics};
+use config::{ConfigLoader, LintLevel};
+use errors::{Diagnostic, Errors, IpdlError, Severity};
+use ir::TypedProgram;
+use toml::{TomlTable, TomlValue};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProtocolSelector {
+    All,
+    Toplevel,
+    Managed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageSelector {
+    All,
+    Semantics(SendSemantics),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyTarget {
+    Protocols(ProtocolSelector),
+    // The messages with the given semantics, and that carry a type if one
+    // is given.
+    Messages(MessageSelector, Option<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    pub name: String,
+    // Where the policy is declared.
+    pub loc: Location,
+    pub target: PolicyTarget,
+    pub require_attribute: Option<String>,
+    pub forbid_attribute: Option<String>,
+    pub require_semantics: Option<SendSemantics>,
+    pub level: LintLevel,
+}
+
+fn semantics_from_name(name: &str) -> Option<SendSemantics> {
+    match name {
+        "async" => Some(SendSemantics::Async),
+        "sync" => Some(SendSemantics::Sync),
+        "intr" => Some(SendSemantics::Intr),
+        _ => None,
+    }
+}
+
+fn semantics_name(s: SendSemantics) -> &'static str {
+    match s {
+        SendSemantics::Async => "async",
+        SendSemantics::Sync => "sync",
+        SendSemantics::Intr => "intr",
+    }
+}
+
+impl Policy {
+    pub fn from_table(table: &TomlTable, errors: &mut Errors) -> Option<Policy> {
+        let mut name = None;
+        let mut protocols = None;
+        let mut messages = None;
+        let mut carrying = None;
+        let mut policy = Policy {
+            name: String::new(),
+            loc: table.loc.clone(),
+            target: PolicyTarget::Protocols(ProtocolSelector::All),
+            require_attribute: None,
+            forbid_attribute: None,
+            require_semantics: None,
+            level: LintLevel::Warn,
+        };
+        let mut ok = true;
+        for entry in &table.entries {
+            let value = match &entry.value {
+                TomlValue::String(s) => s.as_str(),
+                v => {
+                    errors.append_one(
+                        &entry.loc,
+                        &format!("`{}' must be a string, not {}", entry.key, v.type_name()),
+                    );
+                    ok = false;
+                    continue;
+                }
+            };
+            let mut bad_value = |expected: &str| {
+                errors.append_one(
+                    &entry.loc,
+                    &format!(
+                        "unknown value `{}' for `{}', expected {}",
+                        value, entry.key, expected
+                    ),
+                );
+            };
+            match entry.key.as_str() {
+                "name" => name = Some(String::from(value)),
+                "protocols" => match value {
+                    "all" => protocols = Some(ProtocolSelector::All),
+                    "toplevel" => protocols = Some(ProtocolSelector::Toplevel),
+                    "managed" => protocols = Some(ProtocolSelector::Managed),
+                    _ => {
+                        bad_value("`all', `toplevel' or `managed'");
+                        ok = false;
+                    }
+                },
+                "messages" => match (value, semantics_from_name(value)) {
+                    ("all", _) => messages = Some(MessageSelector::All),
+                    (_, Some(s)) => messages = Some(MessageSelector::Semantics(s)),
+                    _ => {
+                        bad_value("`all', `async', `sync' or `intr'");
+                        ok = false;
+                    }
+                },
+                "carrying" => carrying = Some(String::from(value)),
+                "require-attribute" => policy.require_attribute = Some(String::from(value)),
+                "forbid-attribute" => policy.forbid_attribute = Some(String::from(value)),
+                "require" => match semantics_from_name(value) {
+                    Some(s) => policy.require_semantics = Some(s),
+                    None => {
+                        bad_value("`async', `sync' or `intr'");
+                        ok = false;
+                    }
+                },
+                "level" => match LintLevel::from_name(value) {
+                    Some(level) => policy.level = level,
+                    None => {
+                        bad_value("`allow', `warn' or `deny'");
+                        ok = false;
+                    }
+                },
+                key => {
+                    errors.append_one(&entry.loc, &format!("unknown policy setting `{}'", key));
+                    ok = false;
+                }
+            }
+        }
+
+        let mut error = |message: &str| {
+            errors.append_one(&table.loc, message);
+            None
+        };
+        policy.name = match name {
+            Some(name) => name,
+            None => return error("a policy needs a `name'"),
+        };
+        policy.target = match (protocols, messages, carrying) {
+            (Some(p), None, None) => PolicyTarget::Protocols(p),
+            (None, Some(m), carrying) => PolicyTarget::Messages(m, carrying),
+            (Some(_), None, Some(_)) => {
+                return error("`carrying' can only be used with `messages'");
+            }
+            _ => return error("a policy needs exactly one of `protocols' and `messages'"),
+        };
+        if policy.require_attribute.is_none()
+            && policy.forbid_attribute.is_none()
+            && policy.require_semantics.is_none()
+        {
+            return error(
+                "a policy needs at least one of `require-attribute', `forbid-attribute' and `require'",
+            );
+        }
+        if ok {
+            Some(policy)
+        } else {
+            None
+        }
+    }
+
+    // The ways that a protocol or message breaks the policy.
+    fn violations(&self, attributes: &Attributes, semantics: SendSemantics) -> Vec<String> {
+        let mut violations = Vec::new();
+        if let Some(a) = &self.require_attribute {
+            if !attributes.contains_key(a) {
+                violations.push(format!("must have a `[{}]' attribute", a));
+            }
+        }
+        if let Some(a) = &self.forbid_attribute {
+            if attributes.contains_key(a) {
+                violations.push(format!("must not have a `[{}]' attribute", a));
+            }
+        }
+        if let Some(s) = self.require_semantics {
+            if semantics != s {
+                violations.push(format!("must be {}", semantics_name(s)));
+            }
+        }
+        violations
+    }
+
+    fn diagnostics(&self, loc: &Location, what: &str, violations: Vec<String>) -> Vec<Diagnostic> {
+        violations
+            .into_iter()
+            .map(|v| {
+                let mut d = Diagnostic::from_catalog(
+                    loc,
+                    "policy",
+                    &[("what", &what), ("violation", &v), ("policy", &self.name)],
+                )
+                .with_note(&self.loc, "the policy is declared here");
+                if self.level == LintLevel::Deny {
+                    d.severity = Severity::Error;
+                }
+                d
+            })
+            .collect()
+    }
+}
+
+// Check every protocol and message against the policies in the configs
+// for their files.
+pub fn check_policies(
+    program: &TypedProgram,
+    loader: &mut ConfigLoader,
+) -> Result<Vec<Diagnostic>, IpdlError> {
+    let mut diagnostics = Vec::new();
+    for tuid in program.tu_ids() {
+        let ptype = match &program.tuts[&tuid].protocol {
+            Some(ptype) => ptype,
+            None => continue,
+        };
+        let tu = &program.tus[&tuid];
+        let protocol = &tu.protocol.as_ref().unwrap().1;
+        let config = loader.config_for_file(&tu.file_name)?;
+        for policy in &config.policies {
+            if policy.level == LintLevel::Allow {
+                continue;
+            }
+            match &policy.target {
+                PolicyTarget::Protocols(selector) => {
+                    let selected = match selector {
+                        ProtocolSelector::All => true,
+                        ProtocolSelector::Toplevel => ptype.managers.is_empty(),
+                        ProtocolSelector::Managed => !ptype.managers.is_empty(),
+                    };
+                    if selected {
+                        let what = format!("protocol `{}'", ptype.qname.short_name());
+                        let violations =
+                            policy.violations(&protocol.attributes, protocol.send_semantics);
+                        diagnostics.extend(policy.diagnostics(
+                            ptype.qname.loc(),
+                            &what,
+                            violations,
+                        ));
+                    }
+                }
+                PolicyTarget::Messages(selector, carrying) => {
+                    for md in &ptype.messages {
+                        let selected = match selector {
+                            MessageSelector::All => true,
+                            MessageSelector::Semantics(s) => md.send_semantics == *s,
+                        };
+                        let carries = |name: &str| {
+                            md.params.iter().chain(&md.returns).any(|p| {
+                                program.contains_type(p.param_type.lookup(&program.tuts), name)
+                            })
+                        };
+                        if !selected || !carrying.as_ref().is_none_or(|name| carries(name)) {
+                            continue;
+                        }
+                        let decl = protocol
+                            .messages
+                            .iter()
+                            .find(|m| m.name.id == md.name.id)
+                            .unwrap();
+                        let what =
+                            format!("message `{}::{}'", ptype.qname.short_name(), md.name.id);
+                        let violations = policy.violations(&decl.attributes, md.send_semantics);
+                        diagnostics.extend(policy.diagnostics(&md.name.loc, &what, violations));
+                    }
+                }
+            }
+        }
+    }
+    Ok(diagnostics)
+}
@@ -0,0 +1,92 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// A progress line for `--progress', so that checking the whole tree, which
+// takes a few seconds, doesn't look like it is hung. It is rewritten in
+// place, with a carriage return, each time a file is parsed or checked,
+// and ends with the number of errors and warnings once the check is done.
+
+use errors::Diagnostic;
+use events::{CompilerEvents, Phase};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+pub struct Progress<W: Write> {
+    out: W,
+    parsed: usize,
+    // The translation units that were looked up in the gather cache, which
+    // every one that is type checked is.
+    checked: usize,
+    errors: usize,
+    // The length of the last line, to blank out what a shorter one leaves.
+    width: usize,
+}
+
+impl<W: Write> Progress<W> {
+    pub fn new(out: W) -> Progress<W> {
+        Progress {
+            out,
+            parsed: 0,
+            checked: 0,
+            errors: 0,
+            width: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn line(&self) -> String {
+        format!(
+            "parsed {} files, checked {} of them, {} errors",
+            self.parsed, self.checked, self.errors
+        )
+    }
+
+    fn show(&mut self, line: &str, end: &str) {
+        let pad = self.width.saturating_sub(line.len());
+        self.width = if end.is_empty() { line.len() } else { 0 };
+        // Progress that can't be shown shouldn't stop the checking.
+        let _ = write!(self.out, "\r{}{:pad$}{}", line, "", end, pad = pad)
+            .and_then(|_| self.out.flush());
+    }
+}
+
+impl<W: Write> CompilerEvents for Progress<W> {
+    fn phase_started(&mut self, phase: Phase) {
+        // A Session can check again, so each check starts over.
+        if phase == Phase::Parse {
+            self.parsed = 0;
+            self.checked = 0;
+            self.errors = 0;
+        }
+    }
+
+    fn file_parsed(&mut self, _file: &Path, _time: Option<Duration>) {
+        self.parsed += 1;
+        let line = self.line();
+        self.show(&line, "");
+    }
+
+    fn cache_lookup(&mut self, _file: &Path, _hit: bool) {
+        self.checked += 1;
+        let line = self.line();
+        self.show(&line, "");
+    }
+
+    fn diagnostics_emitted(&mut self, diagnostics: &[Diagnostic]) {
+        self.errors = diagnostics.iter().filter(|d| d.is_error()).count();
+        let warnings = diagnostics.len() - self.errors;
+        let line = format!("{}, {} warnings", self.line(), warnings);
+        self.show(&line, "\n");
+    }
+}
+
+impl Progress<io::Stderr> {
+    pub fn stderr() -> Progress<io::Stderr> {
+        Progress::new(io::stderr())
+    }
+}
@@ -0,0 +1,134 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Auditing the messages that carry Shmem, directly or inside of arrays,
+// structs and unions. Shared memory that either side can ask for is easy
+// to grow without anyone noticing, so the ipdl.toml `[shmem]' table can
+// restrict it:
+//
+//     [shmem]
+//     require-attribute = true
+//     forbid-in-sync-replies = true
+//
+// With `require-attribute', each message that carries a Shmem needs an
+// `[AllowShmem]' attribute, or it gets a `shmem_without_attribute'
+// warning. With `forbid-in-sync-replies', a sync message that returns a
+// Shmem gets a `shmem_in_sync_reply' warning. Neither is checked by
+// default.
+//
+// A protocol can also say which of its sides may allocate Shmems at all,
+// with `[AllocShmem=Parent]', `Child', `Both' or `None'. The sender of a
+// message allocates the Shmems in its parameters, and the receiver the
+// ones in its reply, and the type checker reports the messages that make
+// any other side allocate one.
+
+use ast::{Direction, Location, MessageDecl, ProtocolSide, SendSemantics, TUId};
+use config::ConfigLoader;
+use errors::{Diagnostic, IpdlError};
+use ir::TypedProgram;
+use type_check::{shmem_allocators, MessageTypeDef, ParamTypeDef};
+
+const SHMEM_NAME: &str = "mozilla::ipc::Shmem";
+const ALLOW_ATTRIBUTE: &str = "AllowShmem";
+
+// A message that carries a Shmem.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShmemMessage {
+    pub protocol: TUId,
+    // Like `PFoo::Bar'.
+    pub name: String,
+    pub loc: Location,
+    pub direction: Direction,
+    pub send_semantics: SendSemantics,
+    pub in_params: bool,
+    pub in_returns: bool,
+    // Whether the message has an `[AllowShmem]' attribute.
+    pub allowed: bool,
+    // The sides that allocate the Shmems.
+    pub allocated_by: Vec<ProtocolSide>,
+}
+
+fn message_decl<'a>(program: &'a TypedProgram, tuid: &TUId, name: &str) -> &'a MessageDecl {
+    let protocol = &program.tus[tuid].protocol.as_ref().unwrap().1;
+    protocol
+        .messages
+        .iter()
+        .find(|m| m.name.id == name)
+        .unwrap()
+}
+
+fn shmem_message(program: &TypedProgram, tuid: &TUId, md: &MessageTypeDef) -> Option<ShmemMessage> {
+    let carries = |params: &[ParamTypeDef]| {
+        params
+            .iter()
+            .any(|p| program.contains_type(p.param_type.lookup(&program.tuts), SHMEM_NAME))
+    };
+    let in_params = carries(&md.params);
+    let in_returns = carries(&md.returns);
+    if !in_params && !in_returns {
+        return None;
+    }
+    let decl = message_decl(program, tuid, &md.name.id);
+    Some(ShmemMessage {
+        protocol: *tuid,
+        name: format!(
+            "{}::{}",
+            program.protocol(tuid).qname.short_name(),
+            md.name.id
+        ),
+        loc: md.name.loc.clone(),
+        direction: md.direction,
+        send_semantics: md.send_semantics,
+        in_params,
+        in_returns,
+        allowed: decl.attributes.contains_key(ALLOW_ATTRIBUTE),
+        allocated_by: shmem_allocators(md, &program.tuts),
+    })
+}
+
+// Every message that carries a Shmem, in a deterministic order.
+pub fn shmem_messages(program: &TypedProgram) -> Vec<ShmemMessage> {
+    let mut messages = Vec::new();
+    for tuid in program.tu_ids() {
+        if let Some(ptype) = &program.tuts[&tuid].protocol {
+            messages.extend(
+                ptype
+                    .messages
+                    .iter()
+                    .filter_map(|md| shmem_message(program, &tuid, md)),
+            );
+        }
+    }
+    messages
+}
+
+// Check the messages that carry a Shmem against the `[shmem]' settings in
+// the configs for their files.
+pub fn check_shmem(
+    program: &TypedProgram,
+    loader: &mut ConfigLoader,
+) -> Result<Vec<Diagnostic>, IpdlError> {
+    let mut diagnostics = Vec::new();
+    for m in shmem_messages(program) {
+        let config = loader.config_for_file(&program.tus[&m.protocol].file_name)?;
+        if config.shmem_require_attribute == Some(true) && !m.allowed {
+            diagnostics.push(Diagnostic::from_catalog(
+                &m.loc,
+                "shmem_without_attribute",
+                &[("message", &m.name), ("attribute", &ALLOW_ATTRIBUTE)],
+            ));
+        }
+        if config.shmem_forbid_in_sync_replies == Some(true)
+            && m.in_returns
+            && m.send_semantics != SendSemantics::Async
+        {
+            diagnostics.push(Diagnostic::from_catalog(
+                &m.loc,
+                "shmem_in_sync_reply",
+                &[("message", &m.name)],
+            ));
+        }
+    }
+    Ok(diagnostics)
+}
@@ -0,0 +1,127 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Where the diagnostics of a Session go. After each check, the Session
+// passes the errors, or the warnings if there were no errors, to each of
+// its sinks in the order they were added, so that one check can be printed,
+// written out for another tool, and published to an editor at once.
+//
+// An I/O error, like a missing include, is passed on as a diagnostic
+// without a location.
+
+use ast::Location;
+use errors::Diagnostic;
+use json::{json_list, json_path, json_string};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+pub trait DiagnosticSink {
+    // The diagnostics of one check, sorted by location. This is called even
+    // when there are none, so that a sink can clear what it published for
+    // the previous check.
+    fn publish(&mut self, diagnostics: &[Diagnostic]);
+}
+
+// Prints each diagnostic the way the command line does.
+pub struct TerminalSink;
+
+impl DiagnosticSink for TerminalSink {
+    fn publish(&mut self, diagnostics: &[Diagnostic]) {
+        for d in diagnostics {
+            println!("{}", d);
+        }
+    }
+}
+
+fn loc_json(loc: &Option<Location>) -> String {
+    match loc {
+        Some(loc) => format!(
+            "\"file\": {}, \"line\": {}, \"column\": {}",
+            json_path(&loc.file_name),
+            loc.lineno,
+            loc.colno
+        ),
+        None => String::from("\"file\": null, \"line\": null, \"column\": null"),
+    }
+}
+
+pub fn diagnostic_json(d: &Diagnostic) -> String {
+    let notes = json_list(d.notes.iter().map(|n| {
+        format!(
+            "{{{}, \"message\": {}}}",
+            loc_json(&Some(n.loc.clone())),
+            json_string(&n.message)
+        )
+    }));
+    format!(
+        "{{\"severity\": {}, \"code\": {}, {}, \"message\": {}, \"notes\": {}}}",
+        json_string(&d.severity.to_string()),
+        d.code.map_or(String::from("null"), json_string),
+        loc_json(&d.loc),
+        json_string(&d.message),
+        notes
+    )
+}
+
+// Writes each check as one line of JSON, like
+// `{"diagnostics": [{"severity": "warning", ...}]}', for tools that read
+// the results of a long running session as they come.
+pub struct JsonSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonSink<W> {
+    pub fn new(out: W) -> JsonSink<W> {
+        JsonSink { out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> DiagnosticSink for JsonSink<W> {
+    fn publish(&mut self, diagnostics: &[Diagnostic]) {
+        let line = format!(
+            "{{\"diagnostics\": {}}}\n",
+            json_list(diagnostics.iter().map(diagnostic_json))
+        );
+        // A reader that went away shouldn't stop the checking.
+        let _ = self
+            .out
+            .write_all(line.as_bytes())
+            .and_then(|_| self.out.flush());
+    }
+}
+
+impl JsonSink<io::Stdout> {
+    pub fn stdout() -> JsonSink<io::Stdout> {
+        JsonSink::new(io::stdout())
+    }
+}
+
+// Keeps the diagnostics of every check, for tests. Clones share the same
+// list, so a test can keep one and give another to the Session.
+#[derive(Clone, Default)]
+pub struct Collector {
+    diagnostics: Rc<RefCell<Vec<Vec<Diagnostic>>>>,
+}
+
+impl Collector {
+    pub fn new() -> Collector {
+        Collector::default()
+    }
+
+    // The diagnostics of each check so far, oldest first.
+    pub fn checks(&self) -> Vec<Vec<Diagnostic>> {
+        self.diagnostics.borrow().clone()
+    }
+}
+
+impl DiagnosticSink for Collector {
+    fn publish(&mut self, diagnostics: &[Diagnostic]) {
+        self.diagnostics.borrow_mut().push(diagnostics.to_vec());
+    }
+}
@@ -0,0 +1,653 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Snapshots of parsed translation units, so that a tool like an editor's
+// save hook can check one edited file without parsing every header and
+// protocol that it includes again.
+//
+// A snapshot is written after a successful check, and has the translation
+// unit of every file that was parsed, with a hash of the file's text and
+// the files it includes. When checking with a snapshot, a file that is
+// included and whose text still has the same hash is taken from the
+// snapshot instead of being parsed. The files given on the command line,
+// and any file that has been edited since, are always parsed. Files are
+// still type checked as usual.
+//
+// The includes are recorded as they were resolved when the snapshot was
+// written, and any `#if' sections that --define removed stay removed, so a
+// snapshot should be used with the same include path and defines.
+
+use ast::{
+    AttributeValue, Attributes, Condition, ConditionalSection, CxxTypeKind, Direction, FileType,
+    Identifier, Location, MessageDecl, Namespace, Param, Protocol, ProtocolSide, QualifiedId,
+    SendSemantics, StructField, TranslationUnit, TypeSpec, UsingStmt,
+};
+use errors::IpdlError;
+use ir::TypedProgram;
+use json::{json_list, json_path, json_string, parse_json, JsonValue};
+use sources::SourceManager;
+use stable_hash::StableHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+const VERSION: usize = 2;
+
+#[derive(Debug, Clone)]
+struct SnapshotFile {
+    hash: String,
+    // The files in the include statements, in order.
+    includes: Vec<PathBuf>,
+    tu: TranslationUnit,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    files: HashMap<PathBuf, SnapshotFile>,
+}
+
+fn text_hash(text: &str) -> String {
+    let mut h = StableHasher::new();
+    h.write_str(text);
+    format!("{:016x}", h.finish())
+}
+
+// Every location in a translation unit is in its own file, so only the
+// line and column are written.
+fn loc_json(loc: &Location) -> String {
+    format!("[{}, {}]", loc.lineno, loc.colno)
+}
+
+fn identifier_json(id: &Identifier) -> String {
+    format!(
+        "[{}, {}, {}]",
+        json_string(&id.id),
+        id.loc.lineno,
+        id.loc.colno
+    )
+}
+
+fn strings_json(strings: &[String]) -> String {
+    json_list(strings.iter().map(|s| json_string(s)))
+}
+
+fn qualified_id_json(qid: &QualifiedId) -> String {
+    format!(
+        "{{\"quals\": {}, \"base\": {}}}",
+        strings_json(&qid.quals),
+        identifier_json(&qid.base_id)
+    )
+}
+
+fn namespace_json(ns: &Namespace) -> String {
+    format!(
+        "{{\"name\": {}, \"namespaces\": {}}}",
+        identifier_json(&ns.name),
+        strings_json(&ns.namespaces)
+    )
+}
+
+fn type_spec_json(t: &TypeSpec) -> String {
+    format!(
+        "{{\"loc\": {}, \"spec\": {}, \"template_args\": {}, \"array\": {}, \"maybe\": {}, \
+         \"nullable\": {}, \"uniqueptr\": {}}}",
+        loc_json(&t.loc),
+        json_string(&t.spec),
+        json_list(t.template_args.iter().map(type_spec_json)),
+        t.array,
+        t.maybe,
+        t.nullable,
+        t.uniqueptr
+    )
+}
+
+// Sorted by name, so that the same file always gives the same snapshot.
+fn attributes_json(attributes: &Attributes) -> String {
+    let mut names = attributes.keys().collect::<Vec<_>>();
+    names.sort();
+    json_list(names.into_iter().map(|name| {
+        let (loc, value) = &attributes[name];
+        let value = match value {
+            AttributeValue::Identifier(id) => identifier_json(id),
+            AttributeValue::String(s) => json_string(s),
+            AttributeValue::Integer(n) => n.to_string(),
+            AttributeValue::None => String::from("null"),
+        };
+        format!(
+            "{{\"name\": {}, \"loc\": {}, \"value\": {}}}",
+            json_string(name),
+            loc_json(loc),
+            value
+        )
+    }))
+}
+
+fn semantics_name(s: SendSemantics) -> &'static str {
+    match s {
+        SendSemantics::Async => "async",
+        SendSemantics::Sync => "sync",
+        SendSemantics::Intr => "intr",
+    }
+}
+
+fn direction_name(d: Direction) -> &'static str {
+    match d {
+        Direction::To(ProtocolSide::Parent) => "parent",
+        Direction::To(ProtocolSide::Child) => "child",
+        Direction::Both => "both",
+    }
+}
+
+fn param_json(p: &Param) -> String {
+    format!(
+        "{{\"attributes\": {}, \"type\": {}, \"name\": {}}}",
+        attributes_json(&p.attributes),
+        type_spec_json(&p.type_spec),
+        identifier_json(&p.name)
+    )
+}
+
+fn message_json(md: &MessageDecl) -> String {
+    format!(
+        "{{\"name\": {}, \"attributes\": {}, \"send_semantics\": {}, \"direction\": {}, \
+         \"in_params\": {}, \"out_params\": {}}}",
+        identifier_json(&md.name),
+        attributes_json(&md.attributes),
+        json_string(semantics_name(md.send_semantics)),
+        json_string(direction_name(md.direction)),
+        json_list(md.in_params.iter().map(param_json)),
+        json_list(md.out_params.iter().map(param_json))
+    )
+}
+
+fn using_json(u: &UsingStmt) -> String {
+    let kind = match u.kind {
+        Some(kind) => json_string(&kind.to_string()),
+        None => String::from("null"),
+    };
+    format!(
+        "{{\"cxx_type\": {}, \"template_args\": {}, \"header\": {}, \"kind\": {}, \
+         \"attributes\": {}}}",
+        qualified_id_json(&u.cxx_type),
+        json_list(u.template_args.iter().map(type_spec_json)),
+        json_string(&u.header),
+        kind,
+        attributes_json(&u.attributes)
+    )
+}
+
+fn condition_json(c: &Condition) -> String {
+    match c {
+        Condition::Defined(name) => format!("{{\"defined\": {}}}", json_string(name)),
+        Condition::Constant(b) => format!("{{\"constant\": {}}}", b),
+        Condition::Not(c) => format!("{{\"not\": {}}}", condition_json(c)),
+        Condition::And(a, b) => format!(
+            "{{\"and\": [{}, {}]}}",
+            condition_json(a),
+            condition_json(b)
+        ),
+        Condition::Or(a, b) => {
+            format!("{{\"or\": [{}, {}]}}", condition_json(a), condition_json(b))
+        }
+    }
+}
+
+fn file_json(file: &SnapshotFile) -> String {
+    let tu = &file.tu;
+    let file_type = match tu.file_type {
+        FileType::Protocol => "protocol",
+        FileType::Header => "header",
+    };
+    let structs = tu.structs.iter().map(|(ns, attributes, fields)| {
+        format!(
+            "{{\"namespace\": {}, \"attributes\": {}, \"fields\": {}}}",
+            namespace_json(ns),
+            attributes_json(attributes),
+            json_list(fields.iter().map(|f| format!(
+                "{{\"type\": {}, \"name\": {}}}",
+                type_spec_json(&f.type_spec),
+                identifier_json(&f.name)
+            )))
+        )
+    });
+    let unions = tu.unions.iter().map(|(ns, attributes, types)| {
+        format!(
+            "{{\"namespace\": {}, \"attributes\": {}, \"types\": {}}}",
+            namespace_json(ns),
+            attributes_json(attributes),
+            json_list(types.iter().map(type_spec_json))
+        )
+    });
+    let protocol = match &tu.protocol {
+        Some((ns, p)) => format!(
+            "{{\"namespace\": {}, \"attributes\": {}, \"send_semantics\": {}, \"managers\": {}, \
+             \"manages\": {}, \"messages\": {}}}",
+            namespace_json(ns),
+            attributes_json(&p.attributes),
+            json_string(semantics_name(p.send_semantics)),
+            json_list(p.managers.iter().map(identifier_json)),
+            json_list(p.manages.iter().map(identifier_json)),
+            json_list(p.messages.iter().map(message_json))
+        ),
+        None => String::from("null"),
+    };
+    let conditions = tu.conditions.iter().map(|s| {
+        format!(
+            "{{\"condition\": {}, \"first_line\": {}, \"last_line\": {}}}",
+            condition_json(&s.condition),
+            s.first_line,
+            s.last_line
+        )
+    });
+    format!(
+        "{{\"file\": {}, \"hash\": {}, \"includes\": {}, \"file_type\": {}, \"namespace\": {}, \
+         \"cxx_includes\": {}, \"include_files\": {}, \"header_marker\": {}, \"using\": {}, \
+         \"structs\": {}, \"unions\": {}, \"protocol\": {}, \"conditions\": {}}}",
+        json_path(&tu.file_name),
+        json_string(&file.hash),
+        json_list(file.includes.iter().map(|p| json_path(p))),
+        json_string(file_type),
+        namespace_json(&tu.namespace),
+        json_list(tu.cxx_includes.iter().map(identifier_json)),
+        json_list(tu.include_files.iter().map(identifier_json)),
+        tu.header_marker
+            .as_ref()
+            .map_or(String::from("null"), identifier_json),
+        json_list(tu.using.iter().map(using_json)),
+        json_list(structs),
+        json_list(unions),
+        protocol,
+        json_list(conditions)
+    )
+}
+
+fn member<'a>(v: &'a JsonValue, key: &str) -> Result<&'a JsonValue, String> {
+    v.get(key).ok_or_else(|| format!("missing `{}'", key))
+}
+
+fn string(v: &JsonValue) -> Result<String, String> {
+    v.as_str()
+        .map(String::from)
+        .ok_or_else(|| String::from("expected a string"))
+}
+
+fn number(v: &JsonValue) -> Result<usize, String> {
+    match v {
+        JsonValue::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as usize),
+        _ => Err(String::from("expected a non-negative integer")),
+    }
+}
+
+fn boolean(v: &JsonValue) -> Result<bool, String> {
+    match v {
+        JsonValue::Bool(b) => Ok(*b),
+        _ => Err(String::from("expected true or false")),
+    }
+}
+
+fn array(v: &JsonValue) -> Result<&[JsonValue], String> {
+    v.as_array()
+        .ok_or_else(|| String::from("expected an array"))
+}
+
+fn strings(v: &JsonValue) -> Result<Vec<String>, String> {
+    array(v)?.iter().map(string).collect()
+}
+
+// Reads the parts of one file's translation unit, whose locations are all
+// in that file.
+struct Reader<'a> {
+    file_name: &'a Path,
+}
+
+impl<'a> Reader<'a> {
+    fn loc_at(&self, lineno: &JsonValue, colno: &JsonValue) -> Result<Location, String> {
+        Ok(Location {
+            file_name: self.file_name.to_path_buf(),
+            lineno: number(lineno)?,
+            colno: number(colno)?,
+        })
+    }
+
+    fn loc(&self, v: &JsonValue) -> Result<Location, String> {
+        match array(v)? {
+            [lineno, colno] => self.loc_at(lineno, colno),
+            _ => Err(String::from("expected a line and a column")),
+        }
+    }
+
+    fn identifier(&self, v: &JsonValue) -> Result<Identifier, String> {
+        match array(v)? {
+            [id, lineno, colno] => Ok(Identifier::new(string(id)?, self.loc_at(lineno, colno)?)),
+            _ => Err(String::from("expected a name, a line and a column")),
+        }
+    }
+
+    fn identifiers(&self, v: &JsonValue) -> Result<Vec<Identifier>, String> {
+        array(v)?.iter().map(|v| self.identifier(v)).collect()
+    }
+
+    fn namespace(&self, v: &JsonValue) -> Result<Namespace, String> {
+        Ok(Namespace {
+            name: self.identifier(member(v, "name")?)?,
+            namespaces: strings(member(v, "namespaces")?)?,
+        })
+    }
+
+    fn type_spec(&self, v: &JsonValue) -> Result<TypeSpec, String> {
+        Ok(TypeSpec {
+            loc: self.loc(member(v, "loc")?)?,
+            spec: string(member(v, "spec")?)?,
+            template_args: self.type_specs(member(v, "template_args")?)?,
+            array: boolean(member(v, "array")?)?,
+            maybe: boolean(member(v, "maybe")?)?,
+            nullable: boolean(member(v, "nullable")?)?,
+            uniqueptr: boolean(member(v, "uniqueptr")?)?,
+        })
+    }
+
+    fn type_specs(&self, v: &JsonValue) -> Result<Vec<TypeSpec>, String> {
+        array(v)?.iter().map(|v| self.type_spec(v)).collect()
+    }
+
+    fn attributes(&self, v: &JsonValue) -> Result<Attributes, String> {
+        let mut attributes = Attributes::new();
+        for a in array(v)? {
+            let value = match member(a, "value")? {
+                JsonValue::Null => AttributeValue::None,
+                JsonValue::String(s) => AttributeValue::String(s.clone()),
+                JsonValue::Number(n) => AttributeValue::Integer(*n as u32),
+                v => AttributeValue::Identifier(self.identifier(v)?),
+            };
+            attributes.insert(
+                string(member(a, "name")?)?,
+                (self.loc(member(a, "loc")?)?, value),
+            );
+        }
+        Ok(attributes)
+    }
+
+    fn params(&self, v: &JsonValue) -> Result<Vec<Param>, String> {
+        array(v)?
+            .iter()
+            .map(|p| {
+                Ok(Param::new(
+                    self.attributes(member(p, "attributes")?)?,
+                    self.type_spec(member(p, "type")?)?,
+                    self.identifier(member(p, "name")?)?,
+                ))
+            })
+            .collect()
+    }
+
+    fn message(&self, v: &JsonValue) -> Result<MessageDecl, String> {
+        Ok(MessageDecl::new(
+            self.identifier(member(v, "name")?)?,
+            self.attributes(member(v, "attributes")?)?,
+            send_semantics(member(v, "send_semantics")?)?,
+            direction(member(v, "direction")?)?,
+            self.params(member(v, "in_params")?)?,
+            self.params(member(v, "out_params")?)?,
+        ))
+    }
+
+    fn protocol(&self, v: &JsonValue) -> Result<Option<(Namespace, Protocol)>, String> {
+        if *v == JsonValue::Null {
+            return Ok(None);
+        }
+        let messages = array(member(v, "messages")?)?
+            .iter()
+            .map(|m| self.message(m))
+            .collect::<Result<_, _>>()?;
+        Ok(Some((
+            self.namespace(member(v, "namespace")?)?,
+            Protocol::new(
+                self.attributes(member(v, "attributes")?)?,
+                send_semantics(member(v, "send_semantics")?)?,
+                self.identifiers(member(v, "managers")?)?,
+                self.identifiers(member(v, "manages")?)?,
+                messages,
+            ),
+        )))
+    }
+
+    fn using(&self, v: &JsonValue) -> Result<UsingStmt, String> {
+        let cxx_type = member(v, "cxx_type")?;
+        let kind = match member(v, "kind")? {
+            JsonValue::Null => None,
+            k => match string(k)?.as_str() {
+                "struct" => Some(CxxTypeKind::Struct),
+                "class" => Some(CxxTypeKind::Class),
+                k => return Err(format!("unknown kind `{}'", k)),
+            },
+        };
+        Ok(UsingStmt {
+            cxx_type: QualifiedId {
+                base_id: self.identifier(member(cxx_type, "base")?)?,
+                quals: strings(member(cxx_type, "quals")?)?,
+            },
+            template_args: self.type_specs(member(v, "template_args")?)?,
+            header: string(member(v, "header")?)?,
+            kind,
+            attributes: self.attributes(member(v, "attributes")?)?,
+        })
+    }
+
+    fn translation_unit(&self, v: &JsonValue) -> Result<TranslationUnit, String> {
+        let file_type = match string(member(v, "file_type")?)?.as_str() {
+            "protocol" => FileType::Protocol,
+            "header" => FileType::Header,
+            t => return Err(format!("unknown file type `{}'", t)),
+        };
+        let structs = array(member(v, "structs")?)?
+            .iter()
+            .map(|s| {
+                let fields = array(member(s, "fields")?)?
+                    .iter()
+                    .map(|f| {
+                        Ok(StructField::new(
+                            self.type_spec(member(f, "type")?)?,
+                            self.identifier(member(f, "name")?)?,
+                        ))
+                    })
+                    .collect::<Result<_, String>>()?;
+                Ok((
+                    self.namespace(member(s, "namespace")?)?,
+                    self.attributes(member(s, "attributes")?)?,
+                    fields,
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+        let unions = array(member(v, "unions")?)?
+            .iter()
+            .map(|u| {
+                Ok((
+                    self.namespace(member(u, "namespace")?)?,
+                    self.attributes(member(u, "attributes")?)?,
+                    self.type_specs(member(u, "types")?)?,
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+        let conditions = array(member(v, "conditions")?)?
+            .iter()
+            .map(|s| {
+                Ok(ConditionalSection {
+                    condition: condition(member(s, "condition")?)?,
+                    first_line: number(member(s, "first_line")?)?,
+                    last_line: number(member(s, "last_line")?)?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(TranslationUnit {
+            namespace: self.namespace(member(v, "namespace")?)?,
+            file_type,
+            file_name: self.file_name.to_path_buf(),
+            cxx_includes: self.identifiers(member(v, "cxx_includes")?)?,
+            // These are filled in from the include paths when the
+            // translation unit is used.
+            includes: Vec::new(),
+            include_files: self.identifiers(member(v, "include_files")?)?,
+            header_marker: match member(v, "header_marker")? {
+                JsonValue::Null => None,
+                id => Some(self.identifier(id)?),
+            },
+            using: array(member(v, "using")?)?
+                .iter()
+                .map(|u| self.using(u))
+                .collect::<Result<_, _>>()?,
+            structs,
+            unions,
+            protocol: self.protocol(member(v, "protocol")?)?,
+            conditions,
+        })
+    }
+}
+
+fn send_semantics(v: &JsonValue) -> Result<SendSemantics, String> {
+    match string(v)?.as_str() {
+        "async" => Ok(SendSemantics::Async),
+        "sync" => Ok(SendSemantics::Sync),
+        "intr" => Ok(SendSemantics::Intr),
+        s => Err(format!("unknown send semantics `{}'", s)),
+    }
+}
+
+fn direction(v: &JsonValue) -> Result<Direction, String> {
+    match string(v)?.as_str() {
+        "parent" => Ok(Direction::new_parent()),
+        "child" => Ok(Direction::new_child()),
+        "both" => Ok(Direction::new_both()),
+        d => Err(format!("unknown direction `{}'", d)),
+    }
+}
+
+fn condition(v: &JsonValue) -> Result<Condition, String> {
+    let pair = |v: &JsonValue| match array(v)? {
+        [a, b] => Ok((Box::new(condition(a)?), Box::new(condition(b)?))),
+        _ => Err(String::from("expected two conditions")),
+    };
+    match v {
+        JsonValue::Object(members) if members.len() == 1 => {
+            let (key, value) = &members[0];
+            match key.as_str() {
+                "defined" => Ok(Condition::Defined(string(value)?)),
+                "constant" => Ok(Condition::Constant(boolean(value)?)),
+                "not" => Ok(Condition::Not(Box::new(condition(value)?))),
+                "and" => pair(value).map(|(a, b)| Condition::And(a, b)),
+                "or" => pair(value).map(|(a, b)| Condition::Or(a, b)),
+                k => Err(format!("unknown condition `{}'", k)),
+            }
+        }
+        _ => Err(String::from("expected a condition")),
+    }
+}
+
+impl Snapshot {
+    // A snapshot of every file in |program| whose text is in |sources|.
+    pub fn from_program(program: &TypedProgram, sources: &SourceManager) -> Snapshot {
+        let mut snapshot = Snapshot::default();
+        for tuid in program.tu_ids() {
+            let tu = &program.tus[&tuid];
+            let text = match sources.text(&tu.file_name) {
+                Some(text) => text,
+                None => continue,
+            };
+            let includes = tu
+                .includes
+                .iter()
+                .map(|i| program.tus[i].file_name.clone())
+                .collect();
+            snapshot.files.insert(
+                tu.file_name.clone(),
+                SnapshotFile {
+                    hash: text_hash(text),
+                    includes,
+                    tu: tu.clone(),
+                },
+            );
+        }
+        snapshot
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut names = self.files.keys().collect::<Vec<_>>();
+        names.sort();
+        let files = names
+            .into_iter()
+            .map(|name| format!("  {}", file_json(&self.files[name])))
+            .collect::<Vec<_>>();
+        if files.is_empty() {
+            format!("{{\"version\": {}, \"files\": []}}\n", VERSION)
+        } else {
+            format!(
+                "{{\"version\": {}, \"files\": [\n{}\n]}}\n",
+                VERSION,
+                files.join(",\n")
+            )
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<Snapshot, String> {
+        let root = parse_json(text)?;
+        let version = number(member(&root, "version")?)?;
+        if version != VERSION {
+            return Err(format!("unsupported snapshot version {}", version));
+        }
+        let mut snapshot = Snapshot::default();
+        for f in array(member(&root, "files")?)? {
+            let file_name = PathBuf::from(string(member(f, "file")?)?);
+            let reader = Reader {
+                file_name: &file_name,
+            };
+            let file = SnapshotFile {
+                hash: string(member(f, "hash")?)?,
+                includes: strings(member(f, "includes")?)?
+                    .into_iter()
+                    .map(PathBuf::from)
+                    .collect(),
+                tu: reader
+                    .translation_unit(f)
+                    .map_err(|e| format!("{}: {}", file_name.display(), e))?,
+            };
+            snapshot.files.insert(file_name, file);
+        }
+        Ok(snapshot)
+    }
+
+    pub fn read(file_name: &Path) -> Result<Snapshot, IpdlError> {
+        fs::read_to_string(file_name)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Snapshot::from_json(&text))
+            .map_err(|e| {
+                IpdlError::Io(format!(
+                    "error: can't read snapshot `{}': {}",
+                    file_name.display(),
+                    e
+                ))
+            })
+    }
+
+    // The files in the snapshot, sorted.
+    pub fn file_names(&self) -> Vec<&Path> {
+        let mut names = self.files.keys().map(|p| p.as_path()).collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    // The translation unit of a file and the files it includes, if the
+    // snapshot has the file and |text| is what it had when the snapshot was
+    // written. The translation unit's includes are left empty.
+    pub fn translation_unit(
+        &self,
+        file_name: &Path,
+        text: &str,
+    ) -> Option<(TranslationUnit, &[PathBuf])> {
+        let file = self.files.get(file_name)?;
+        if file.hash != text_hash(text) {
+            return None;
+        }
+        Some((file.tu.clone(), &file.includes))
+    }
+}
@@ -0,0 +1,100 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// The text of the files in a compilation.
+//
+// A SourceManager reads each file once, the first time it is needed, and
+// keeps the text until the compilation is over. The parser borrows the
+// text from it, and it is used afterwards to quote the lines that
+// diagnostics point at, so nothing has to read a file again. It also keeps
+// how long the parser took on each file, for build metrics.
+
+use ast::Location;
+use errors::Diagnostic;
+use parser::read_file;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+pub struct SourceManager {
+    files: HashMap<PathBuf, String>,
+    parse_times: HashMap<PathBuf, Duration>,
+}
+
+// Quote |line| with a caret under the byte offset |colno|. Tabs before the
+// caret are kept, so that it lines up however wide a tab is.
+pub fn quote_line(line: &str, colno: usize) -> String {
+    let indent = line
+        .char_indices()
+        .take_while(|&(i, _)| i < colno)
+        .map(|(_, c)| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    format!("    {}\n    {}^", line, indent)
+}
+
+impl SourceManager {
+    pub fn new() -> SourceManager {
+        SourceManager::default()
+    }
+
+    // The text of a file, which is read if it hasn't been yet. See
+    // parser::read_file for how the text is normalized.
+    pub fn load(&mut self, file_name: &Path) -> Result<&str, String> {
+        if !self.files.contains_key(file_name) {
+            let text = read_file(file_name)?;
+            self.files.insert(file_name.to_path_buf(), text);
+        }
+        Ok(&self.files[file_name])
+    }
+
+    // The text of a file that has already been loaded.
+    pub fn text(&self, file_name: &Path) -> Option<&str> {
+        self.files.get(file_name).map(|s| s.as_str())
+    }
+
+    // The files that have been loaded, in no particular order.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(|f| f.as_path())
+    }
+
+    pub fn record_parse_time(&mut self, file_name: &Path, time: Duration) {
+        self.parse_times.insert(file_name.to_path_buf(), time);
+    }
+
+    // How long the parser took on a file, if it parsed it rather than
+    // taking it from a snapshot.
+    pub fn parse_time(&self, file_name: &Path) -> Option<Duration> {
+        self.parse_times.get(file_name).copied()
+    }
+
+    // The line that a location is on, without its newline.
+    pub fn line(&self, loc: &Location) -> Option<&str> {
+        let lineno = loc.lineno.checked_sub(1)?;
+        self.text(&loc.file_name)?.split('\n').nth(lineno)
+    }
+
+    // The line that a location is on, with a caret under the location.
+    pub fn snippet(&self, loc: &Location) -> Option<String> {
+        self.line(loc).map(|line| quote_line(line, loc.colno))
+    }
+
+    // Attach a snippet to each diagnostic whose file has been loaded.
+    pub fn add_snippets(&self, diagnostics: &mut [Diagnostic]) {
+        for d in diagnostics {
+            if let Some(loc) = &d.loc {
+                d.snippet = self.snippet(loc);
+            }
+        }
+    }
+}
+
+#[test]
+fn quote_line_tests() {
+    assert_eq!(quote_line("struct X {", 7), "    struct X {\n           ^");
+    assert_eq!(quote_line("\tint x;", 5), "    \tint x;\n    \t    ^");
+    // Past the end, or inside a character.
+    assert_eq!(quote_line("ab", 9), "    ab\n      ^");
+    assert_eq!(quote_line("\u{e9}x", 1), "    \u{e9}x\n     ^");
+}
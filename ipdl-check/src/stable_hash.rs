@@ -0,0 +1,74 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// A hasher whose output is stable across runs, platforms and Rust versions,
+// unlike std's DefaultHasher. This is the 64-bit FNV-1a hash.
+//
+// Only write() is stable. The std Hash impls for integer types like usize
+// are platform dependent, so callers should hash integers with write_u64()
+// or similar fixed-width methods.
+
+use std::hash::Hasher;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+pub struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    pub fn new() -> StableHasher {
+        StableHasher {
+            state: FNV_OFFSET_BASIS,
+        }
+    }
+
+    // Write a string, followed by a terminator so that ("ab", "c") and
+    // ("a", "bc") hash differently.
+    pub fn write_str(&mut self, s: &str) {
+        self.write(s.as_bytes());
+        self.write_u8(0xff);
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> StableHasher {
+        StableHasher::new()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.state
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.state ^= *b as u64;
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+#[test]
+fn fnv_tests() {
+    let hash = |s: &str| {
+        let mut h = StableHasher::new();
+        h.write(s.as_bytes());
+        h.finish()
+    };
+    // Reference values for 64-bit FNV-1a.
+    assert_eq!(hash(""), 0xcbf29ce484222325);
+    assert_eq!(hash("a"), 0xaf63dc4c8601ec8c);
+    assert_eq!(hash("foobar"), 0x85944171f73967e8);
+}
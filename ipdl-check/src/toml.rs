@@ -0,0 +1,321 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Just enough TOML for configuration files: `[table]' and `[[table]]'
+// headers, and `key = value' pairs where a value is a string, an integer,
+// a boolean or an array of those. Dotted keys, inline tables, floats and
+// dates are not supported. The entries are returned in the order they
+// appear, with their locations, so that the config loader can report
+// unknown keys and bad values itself.
+
+use ast::Location;
+use errors::Errors;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TomlValue {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<TomlValue>),
+}
+
+impl TomlValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            TomlValue::String(_) => "a string",
+            TomlValue::Integer(_) => "an integer",
+            TomlValue::Bool(_) => "a boolean",
+            TomlValue::Array(_) => "an array",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TomlEntry {
+    pub key: String,
+    pub value: TomlValue,
+    pub loc: Location,
+}
+
+// A table, or one element of an array of tables. The entries before the
+// first header are in a table with an empty name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TomlTable {
+    pub name: String,
+    pub loc: Location,
+    pub entries: Vec<TomlEntry>,
+}
+
+struct TomlParser<'a> {
+    file_name: &'a Path,
+    text: &'a str,
+    pos: usize,
+    lineno: usize,
+    line_start: usize,
+}
+
+impl<'a> TomlParser<'a> {
+    fn loc(&self) -> Location {
+        Location {
+            file_name: self.file_name.to_path_buf(),
+            lineno: self.lineno,
+            colno: self.pos - self.line_start,
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, Errors> {
+        Err(Errors::one(&self.loc(), message))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.lineno += 1;
+            self.line_start = self.pos;
+        }
+        Some(c)
+    }
+
+    // Skip spaces and comments, and newlines too if |newlines| is set.
+    fn skip(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {
+                    self.bump();
+                }
+                '\n' if newlines => {
+                    self.bump();
+                }
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), Errors> {
+        self.skip(false);
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.bump();
+                Ok(())
+            }
+            Some(_) => self.error("expected the end of the line"),
+        }
+    }
+
+    fn key(&mut self) -> Result<String, Errors> {
+        match self.peek() {
+            Some('"') | Some('\'') => self.string(),
+            _ => {
+                let rest = &self.text[self.pos..];
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    return self.error("expected a key");
+                }
+                self.pos += len;
+                Ok(String::from(&rest[..len]))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Errors> {
+        let quote = self.bump().unwrap();
+        let mut s = String::new();
+        loop {
+            if self.peek().is_none_or(|c| c == '\n') {
+                return self.error("unterminated string");
+            }
+            match self.bump().unwrap() {
+                c if c == quote => return Ok(s),
+                '\\' if quote == '"' => match self.bump() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('u') => {
+                        let hex = self.text[self.pos..].chars().take(4).collect::<String>();
+                        match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                            Some(c) if hex.len() == 4 => {
+                                self.pos += 4;
+                                s.push(c);
+                            }
+                            _ => return self.error("bad `\\u' escape"),
+                        }
+                    }
+                    _ => return self.error("unknown escape in string"),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<TomlValue, Errors> {
+        match self.peek() {
+            Some('"') | Some('\'') => self.string().map(TomlValue::String),
+            Some('[') => {
+                self.bump();
+                let mut items = Vec::new();
+                loop {
+                    self.skip(true);
+                    if self.eat(']') {
+                        return Ok(TomlValue::Array(items));
+                    }
+                    items.push(self.value()?);
+                    self.skip(true);
+                    if !self.eat(',') {
+                        self.skip(true);
+                        if self.eat(']') {
+                            return Ok(TomlValue::Array(items));
+                        }
+                        return self.error("expected `,' or `]' in array");
+                    }
+                }
+            }
+            _ => {
+                let rest = &self.text[self.pos..];
+                let len = rest
+                    .find(|c: char| {
+                        !(c.is_ascii_alphanumeric() || c == '_' || c == '+' || c == '-')
+                    })
+                    .unwrap_or(rest.len());
+                let word = &rest[..len];
+                let value = match word {
+                    "true" => TomlValue::Bool(true),
+                    "false" => TomlValue::Bool(false),
+                    _ => match word.replace('_', "").parse() {
+                        Ok(n) if len > 0 => TomlValue::Integer(n),
+                        _ => return self.error("expected a string, integer, boolean or array"),
+                    },
+                };
+                self.pos += len;
+                Ok(value)
+            }
+        }
+    }
+
+    fn parse(mut self) -> Result<Vec<TomlTable>, Errors> {
+        let mut tables = vec![TomlTable {
+            name: String::new(),
+            loc: self.loc(),
+            entries: Vec::new(),
+        }];
+        loop {
+            self.skip(true);
+            if self.peek().is_none() {
+                return Ok(tables);
+            }
+            let loc = self.loc();
+            if self.eat('[') {
+                let array = self.eat('[');
+                self.skip(false);
+                let name = self.key()?;
+                self.skip(false);
+                if !self.eat(']') || (array && !self.eat(']')) {
+                    return self.error("expected `]' after the table name");
+                }
+                if !array && tables.iter().any(|t| t.name == name) {
+                    return Err(Errors::one(
+                        &loc,
+                        &format!("table `{}' is defined more than once", name),
+                    ));
+                }
+                tables.push(TomlTable {
+                    name,
+                    loc,
+                    entries: Vec::new(),
+                });
+            } else {
+                let key = self.key()?;
+                self.skip(false);
+                if !self.eat('=') {
+                    return self.error("expected `=' after the key");
+                }
+                self.skip(false);
+                let value = self.value()?;
+                let table = tables.last_mut().unwrap();
+                if table.entries.iter().any(|e| e.key == key) {
+                    return Err(Errors::one(
+                        &loc,
+                        &format!("key `{}' is defined more than once", key),
+                    ));
+                }
+                table.entries.push(TomlEntry { key, value, loc });
+            }
+            self.end_of_line()?;
+        }
+    }
+}
+
+pub fn parse_toml(file_name: &Path, text: &str) -> Result<Vec<TomlTable>, Errors> {
+    TomlParser {
+        file_name,
+        text,
+        pos: 0,
+        lineno: 1,
+        line_start: 0,
+    }
+    .parse()
+}
+
+#[test]
+fn parse_toml_tests() {
+    let text = "# comment\nroot = true\n\n[lints]\nempty_struct = \"allow\" # why\n\
+                \"quoted-key\" = -1_000\n[[rule]]\nlist = [\n  'a\\b', \"\\u0041\",\n]\n[[rule]]\n";
+    let tables = parse_toml(Path::new("ipdl.toml"), text).ok().unwrap();
+    let names = tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["", "lints", "rule", "rule"]);
+    assert_eq!(tables[0].entries[0].value, TomlValue::Bool(true));
+    assert_eq!(tables[1].entries[0].loc.lineno, 5);
+    assert_eq!(tables[1].entries[1].key, "quoted-key");
+    assert_eq!(tables[1].entries[1].value, TomlValue::Integer(-1000));
+    assert_eq!(
+        tables[2].entries[0].value,
+        TomlValue::Array(vec![
+            TomlValue::String(String::from("a\\b")),
+            TomlValue::String(String::from("A"))
+        ])
+    );
+
+    let error = |text| {
+        let e = parse_toml(Path::new("ipdl.toml"), text).err().unwrap();
+        let d = &e.diagnostics()[0];
+        (d.loc.as_ref().unwrap().lineno, d.message.clone())
+    };
+    assert_eq!(
+        error("a = 1\n[t]\n[t]\n"),
+        (3, String::from("table `t' is defined more than once"))
+    );
+    assert_eq!(
+        error("a = 1 2\n"),
+        (1, String::from("expected the end of the line"))
+    );
+    assert_eq!(
+        error("\na = \"b\n"),
+        (2, String::from("unterminated string"))
+    );
+    assert_eq!(error("a = 1.5\n").1, "expected the end of the line");
+}
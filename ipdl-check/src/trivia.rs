@@ -0,0 +1,179 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Comments and blank lines, which the parser throws away.
+//
+// Trivia is kept in a side table keyed by NodeId, so the AST itself doesn't
+// have to change. A comment is attached to a node if it is on the lines
+// directly before the node (or before the attributes of the node), with no
+// blank line in between, or if it comes after the node on the same line.
+// Comments that can't be attached to any node, like a license header that
+// is followed by a blank line, are detached. Preprocessor lines are treated
+// like comments, as they are by the parser.
+
+use lexer::{self, Token};
+use node_id::{NodeId, NodeMap};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    // The text of the comment, including the delimiters.
+    pub text: String,
+    // The line the comment starts on.
+    pub lineno: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trivia {
+    pub leading: Vec<Comment>,
+    pub trailing: Option<Comment>,
+    // Whether there is a blank line before the node and its leading
+    // comments.
+    pub blank_line_before: bool,
+}
+
+// What is on a line: the comments that start on it, with their columns,
+// whether it is inside a multi-line comment, and the first and last code
+// tokens on it.
+#[derive(Default)]
+struct Line<'a> {
+    comments: Vec<(usize, Token<'a>)>,
+    in_comment: bool,
+    first_code: Option<Token<'a>>,
+    last_code: Option<(usize, Token<'a>)>,
+}
+
+impl<'a> Line<'a> {
+    fn is_blank(&self) -> bool {
+        self.comments.is_empty() && !self.in_comment && self.first_code.is_none()
+    }
+
+    // Whether the line only holds an attribute list like `[Foo]`.
+    fn is_attributes(&self) -> bool {
+        matches!(
+            (self.first_code, self.last_code),
+            (
+                Some(Token::Punctuation("[")),
+                Some((_, Token::Punctuation("]")))
+            )
+        )
+    }
+}
+
+fn comment_text<'a>(t: &Token<'a>) -> &'a str {
+    match *t {
+        Token::Comment(s) | Token::Preprocessor(s) => s,
+        _ => panic!("Not a comment"),
+    }
+}
+
+pub struct TriviaMap {
+    trivia: HashMap<NodeId, Trivia>,
+    detached: Vec<Comment>,
+}
+
+impl TriviaMap {
+    // |text| must be the contents of the file that |nodes| was parsed from.
+    pub fn new(nodes: &NodeMap, text: &str) -> TriviaMap {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        let line_of = |offset: usize| match line_starts.binary_search(&offset) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+
+        // Lines are numbered from 1, like Location::lineno, so lines[0] is
+        // unused.
+        let mut lines: Vec<Line> = Vec::new();
+        lines.resize_with(line_starts.len() + 1, Default::default);
+        for (token, span) in lexer::tokenize(text) {
+            let start = line_of(span.start) + 1;
+            let col = span.start - line_starts[start - 1];
+            match token {
+                Token::Comment(_) | Token::Preprocessor(_) => {
+                    lines[start].comments.push((col, token));
+                    let end = line_of(span.end.saturating_sub(1)) + 1;
+                    for line in &mut lines[start + 1..=end] {
+                        line.in_comment = true;
+                    }
+                }
+                _ => {
+                    let line = &mut lines[start];
+                    line.first_code = line.first_code.or(Some(token));
+                    line.last_code = Some((col, token));
+                }
+            }
+        }
+
+        let mut positions = nodes
+            .ids()
+            .iter()
+            .map(|id| {
+                let loc = nodes.location(id).unwrap();
+                (loc.lineno, loc.colno, *id)
+            })
+            .collect::<Vec<_>>();
+        positions.sort();
+
+        let mut trivia: HashMap<NodeId, Trivia> = HashMap::new();
+        // The (lineno, column) of every comment attached to a node.
+        let mut attached = HashSet::new();
+        let comment = |lineno: usize, c: &Token| Comment {
+            text: String::from(comment_text(c)),
+            lineno,
+        };
+        for (i, &(lineno, _, id)) in positions.iter().enumerate() {
+            let first_on_line = i == 0 || positions[i - 1].0 != lineno;
+            let last_on_line = i + 1 == positions.len() || positions[i + 1].0 != lineno;
+            let entry = trivia.entry(id).or_default();
+
+            if first_on_line {
+                let mut start = lineno;
+                while start > 1 && lines[start - 1].is_attributes() {
+                    start -= 1;
+                }
+                let mut l = start - 1;
+                while l >= 1 && lines[l].first_code.is_none() && !lines[l].is_blank() {
+                    l -= 1;
+                }
+                for (j, line) in lines.iter().enumerate().take(start).skip(l + 1) {
+                    for (col, c) in &line.comments {
+                        entry.leading.push(comment(j, c));
+                        attached.insert((j, *col));
+                    }
+                }
+                entry.blank_line_before = l >= 1 && lines[l].is_blank();
+            }
+
+            if last_on_line {
+                let line = &lines[lineno];
+                let code_col = line.last_code.map_or(0, |(col, _)| col);
+                if let Some((col, c)) = line.comments.iter().find(|(col, _)| *col > code_col) {
+                    entry.trailing = Some(comment(lineno, c));
+                    attached.insert((lineno, *col));
+                }
+            }
+        }
+
+        let mut detached = Vec::new();
+        for (lineno, line) in lines.iter().enumerate() {
+            for (col, c) in &line.comments {
+                if !attached.contains(&(lineno, *col)) {
+                    detached.push(comment(lineno, c));
+                }
+            }
+        }
+
+        TriviaMap { trivia, detached }
+    }
+
+    pub fn get(&self, id: &NodeId) -> Option<&Trivia> {
+        self.trivia.get(id)
+    }
+
+    // Comments that aren't attached to any node, in the order they appear.
+    pub fn detached(&self) -> &[Comment] {
+        &self.detached
+    }
+}
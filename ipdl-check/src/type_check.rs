@@ -0,0 +1,6647 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use ast::*;
+use config::LintLevel;
+use errors::{Diagnostic, Edit, Errors, IpdlError};
+use events::{CompilerEvents, NoEvents};
+use fingerprint::Fingerprint;
+use include_graph::IncludeGraph;
+use parser::{InputLimits, NO_PROTOCOL, PROTOCOL_IN_HEADER};
+use stable_hash::StableHasher;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hasher;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::Once;
+
+// C types
+//
+// These types don't live in any namespace, so can't be imported with `using`
+// statements like normal C++ types.
+const BUILTIN_C_TYPES: &'static [&'static str] = &[
+    // C types
+    "bool", "char", "short", "int", "long", "float", "double",
+];
+
+// C++ keywords, which can't be used as the names of things that end up in
+// the generated C++ code.
+const CXX_KEYWORDS: &[&str] = &[
+    "alignas",
+    "alignof",
+    "and",
+    "and_eq",
+    "asm",
+    "auto",
+    "bitand",
+    "bitor",
+    "bool",
+    "break",
+    "case",
+    "catch",
+    "char",
+    "char8_t",
+    "char16_t",
+    "char32_t",
+    "class",
+    "compl",
+    "concept",
+    "const",
+    "consteval",
+    "constexpr",
+    "constinit",
+    "const_cast",
+    "continue",
+    "co_await",
+    "co_return",
+    "co_yield",
+    "decltype",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "dynamic_cast",
+    "else",
+    "enum",
+    "explicit",
+    "export",
+    "extern",
+    "false",
+    "float",
+    "for",
+    "friend",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "mutable",
+    "namespace",
+    "new",
+    "noexcept",
+    "not",
+    "not_eq",
+    "nullptr",
+    "operator",
+    "or",
+    "or_eq",
+    "private",
+    "protected",
+    "public",
+    "register",
+    "reinterpret_cast",
+    "requires",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "static_assert",
+    "static_cast",
+    "struct",
+    "switch",
+    "template",
+    "this",
+    "thread_local",
+    "throw",
+    "true",
+    "try",
+    "typedef",
+    "typeid",
+    "typename",
+    "union",
+    "unsigned",
+    "using",
+    "virtual",
+    "void",
+    "volatile",
+    "wchar_t",
+    "while",
+    "xor",
+    "xor_eq",
+];
+
+// C++ types
+//
+// These types must be fully qualified, and will be `typedef`-ed into IPDL
+// structs to make them readily available when used.
+const BUILTIN_TYPES: &'static [&'static str] = &[
+    // stdint types
+    "int8_t",
+    "uint8_t",
+    "int16_t",
+    "uint16_t",
+    "int32_t",
+    "uint32_t",
+    "int64_t",
+    "uint64_t",
+    "intptr_t",
+    "uintptr_t",
+    // stddef types
+    "size_t",
+    "ssize_t",
+    // Mozilla types: "less" standard things we know how serialize/deserialize
+    "nsresult",
+    "nsString",
+    "nsCString",
+    "nsDependentSubstring",
+    "nsDependentCSubstring",
+    "mozilla::ipc::Shmem",
+    "mozilla::ipc::ByteBuf",
+    "mozilla::ipc::FileDescriptor",
+];
+
+fn builtin_from_string(tname: &str) -> QualifiedId {
+    QualifiedId::new_from_iter(tname.split("::"))
+}
+
+const DELETE_MESSAGE_NAME: &'static str = "__delete__";
+const EXPORT: &str = "Export";
+const PLAIN_OLD_DATA: &str = "PlainOldData";
+const CONSTRUCTOR_SUFFIX: &'static str = "Constructor";
+
+// A reference to a struct or union, by its index in the list of structs or
+// unions of the translation unit that declares it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TypeRef {
+    pub tu: TUId,
+    pub index: usize,
+}
+
+impl TypeRef {
+    pub fn new(tu: &TUId, index: usize) -> TypeRef {
+        TypeRef {
+            tu: tu.clone(),
+            index: index,
+        }
+    }
+
+    pub fn lookup_struct<'a>(&self, tuts: &'a TUMap<TranslationUnitType>) -> &'a StructTypeDef {
+        &tuts.get(&self.tu).unwrap().structs[self.index]
+    }
+
+    pub fn lookup_union<'a>(&self, tuts: &'a TUMap<TranslationUnitType>) -> &'a UnionTypeDef {
+        &tuts.get(&self.tu).unwrap().unions[self.index]
+    }
+}
+
+// A type used by a struct field, a union component or a message parameter,
+// by its index in the type table of the translation unit that uses it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TypeId {
+    pub tu: TUId,
+    pub index: usize,
+}
+
+impl TypeId {
+    pub fn lookup<'a>(&self, tuts: &'a TUMap<TranslationUnitType>) -> &'a IPDLType {
+        tuts.get(&self.tu).unwrap().types.get(self.index)
+    }
+}
+
+// The types used by the structs, unions and messages of a translation unit.
+// Each distinct type is stored once, however many fields and parameters
+// use it, and they refer to it by its TypeId.
+#[derive(Clone, Default)]
+pub struct TypeTable {
+    types: Vec<IPDLType>,
+    ids: HashMap<IPDLType, usize>,
+}
+
+impl TypeTable {
+    pub fn get(&self, index: usize) -> &IPDLType {
+        &self.types[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, IPDLType> {
+        self.types.iter()
+    }
+
+    fn intern(&mut self, tuid: &TUId, t: IPDLType) -> TypeId {
+        let index = match self.ids.get(&t) {
+            Some(&index) => index,
+            None => {
+                self.types.push(t.clone());
+                self.ids.insert(t, self.types.len() - 1);
+                self.types.len() - 1
+            }
+        };
+        TypeId { tu: *tuid, index }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lifetime {
+    RefCounted,
+    ManualDealloc,
+}
+
+// XXX The Python compiler has "Type" and a subclass "IPDLType". I
+// don't know how useful it is to split them. Plus my notion of type
+// may be different.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IPDLType {
+    BuiltinCType(&'static str),
+    ImportedCxxType(
+        Rc<QualifiedId>,
+        Lifetime,
+        bool, /* send moveonly */
+        bool, /* data moveonly */
+    ),
+    MessageType(TypeRef),
+    ProtocolType(TUId),
+    ActorType(TUId),
+    StructType(TypeRef),
+    UnionType(TypeRef),
+    ArrayType(Box<IPDLType>),
+    MaybeType(Box<IPDLType>),
+    ShmemType(Rc<QualifiedId>),
+    ByteBufType(Rc<QualifiedId>),
+    FDType(Rc<QualifiedId>),
+    EndpointType(Rc<QualifiedId>),
+    // The protocol whose managed endpoint this is.
+    ManagedEndpointType(Rc<QualifiedId>, TUId),
+    UniquePtrType(Box<IPDLType>),
+    NotNullType(Box<IPDLType>),
+}
+
+// The grammar only allows a type spec to have a few type constructors, but
+// if that ever changes, this keeps the recursion in passes like
+// fully_defined bounded.
+const MAX_TYPE_CONSTRUCTOR_DEPTH: usize = 4;
+
+#[derive(Clone, Copy)]
+enum TypeConstructor {
+    UniquePtr,
+    Array,
+}
+
+// The inner types that a type constructor can't be applied to, and why. The
+// generated C++ for these would either not compile or not make sense.
+//
+// UniquePtr of an actor is allowed, as in the Python IPDL compiler, and is
+// just the actor. There is nothing that Maybe can't wrap: a protocol name is
+// always turned into an actor type, and the grammar doesn't allow arrays or
+// UniquePtr inside of a Maybe.
+fn type_constructor_error(constructor: TypeConstructor, inner: &IPDLType) -> Option<&'static str> {
+    use self::TypeConstructor::*;
+    match (constructor, inner) {
+        (UniquePtr, IPDLType::ShmemType(_)) => Some("a Shmem is already a handle to shared memory"),
+        (UniquePtr, IPDLType::FDType(_)) => Some("a FileDescriptor already owns its handle"),
+        (UniquePtr, IPDLType::ImportedCxxType(_, Lifetime::RefCounted, _, _)) => {
+            Some("refcounted types are held by a RefPtr")
+        }
+        (Array, IPDLType::ManagedEndpointType(_, _)) => {
+            Some("each managed endpoint has to be bound to its own actor")
+        }
+        _ => None,
+    }
+}
+
+fn check_type_constructor(
+    constructor: TypeConstructor,
+    inner: &IPDLType,
+    type_spec: &TypeSpec,
+) -> Errors {
+    match type_constructor_error(constructor, inner) {
+        Some(reason) => {
+            let what = match constructor {
+                TypeConstructor::UniquePtr => "`UniquePtr' of",
+                TypeConstructor::Array => "array of",
+            };
+            Errors::from_diagnostic(Diagnostic::from_catalog(
+                type_spec.loc(),
+                "type_constructor",
+                &[
+                    ("what", &what),
+                    ("type", &type_spec.spec),
+                    ("reason", &reason),
+                ],
+            ))
+        }
+        None => Errors::none(),
+    }
+}
+
+// The type of the protocol declared by a translation unit, or None if the
+// translation unit hasn't been checked, or isn't a protocol.
+pub fn get_protocol_type<'a>(
+    tuts: &'a TUMap<TranslationUnitType>,
+    tuid: &TUId,
+) -> Option<&'a ProtocolTypeDef> {
+    tuts.get(tuid).and_then(|tut| tut.protocol.as_ref())
+}
+
+// Like get_protocol_type, but for a translation unit that an earlier pass
+// has already found to be a protocol, so that anything else is an internal
+// error at |loc|.
+fn checked_protocol_type<'a>(
+    tuts: &'a TUMap<TranslationUnitType>,
+    tuid: &TUId,
+    loc: &Location,
+) -> Result<&'a ProtocolTypeDef, Diagnostic> {
+    get_protocol_type(tuts, tuid).ok_or_else(|| {
+        Diagnostic::internal(loc, &format!("translation unit {} is not a protocol", tuid))
+    })
+}
+
+// The stable string form of an IPDLType. Structs, unions and protocols
+// have their fully qualified names, like `::mozilla::dom::Foo', and other
+// named types their short names. An array is `T[]', a Maybe is `T?', and a
+// UniquePtr or NotNull is `UniquePtr<T>' or `NotNullPtr<T>', so an array of
+// UniquePtrs of strings is `UniquePtr<nsString>[]'. The names of structs
+// and unions need the TUMap they are in, so this is made with
+// IPDLType::display.
+pub struct DisplayType<'a> {
+    t: &'a IPDLType,
+    tuts: &'a TUMap<TranslationUnitType>,
+}
+
+impl<'a> fmt::Display for DisplayType<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tuts = self.tuts;
+        match self.t {
+            IPDLType::BuiltinCType(name) => write!(f, "{}", name),
+            IPDLType::ImportedCxxType(qid, _, _, _)
+            | IPDLType::ShmemType(qid)
+            | IPDLType::ByteBufType(qid)
+            | IPDLType::FDType(qid)
+            | IPDLType::EndpointType(qid)
+            | IPDLType::ManagedEndpointType(qid, _) => write!(f, "{}", qid.short_name()),
+            IPDLType::MessageType(_) => write!(f, "???"),
+            IPDLType::ProtocolType(p) | IPDLType::ActorType(p) => {
+                match get_protocol_type(tuts, p) {
+                    Some(ptype) => write!(f, "{}", ptype.qname),
+                    None => write!(f, "???"),
+                }
+            }
+            IPDLType::StructType(tr) => write!(f, "{}", tr.lookup_struct(tuts).qname),
+            IPDLType::UnionType(tr) => write!(f, "{}", tr.lookup_union(tuts).qname),
+            IPDLType::ArrayType(t) => write!(f, "{}[]", t.display(tuts)),
+            IPDLType::MaybeType(t) => write!(f, "{}?", t.display(tuts)),
+            IPDLType::UniquePtrType(t) => write!(f, "UniquePtr<{}>", t.display(tuts)),
+            IPDLType::NotNullType(t) => write!(f, "NotNullPtr<{}>", t.display(tuts)),
+        }
+    }
+}
+
+impl IPDLType {
+    // XXX This has to be the same as the IPDL Python compiler's class names,
+    // to perfectly match the error messages, because Type::typename is defined
+    // as self.__class__.__name__.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            &IPDLType::BuiltinCType(_) => "BuiltinCType",
+            &IPDLType::ImportedCxxType(_, _, _, _) => "ImportedCxxType",
+            &IPDLType::MessageType(_) => "MessageType",
+            &IPDLType::ProtocolType(_) => "ProtocolType",
+            &IPDLType::ActorType(_) => "ActorType",
+            &IPDLType::StructType(_) => "StructType",
+            &IPDLType::UnionType(_) => "UnionType",
+            &IPDLType::ArrayType(_) => "ArrayType",
+            &IPDLType::MaybeType(_) => "MaybeType",
+            &IPDLType::ShmemType(_) => "ShmemType",
+            &IPDLType::ByteBufType(_) => "ByteBufType",
+            &IPDLType::FDType(_) => "FDType",
+            &IPDLType::EndpointType(_) => "EndpointType",
+            &IPDLType::ManagedEndpointType(_, _) => "ManagedEndpointType",
+            &IPDLType::UniquePtrType(_) => "UniquePtrType",
+            &IPDLType::NotNullType(_) => "NotNullType",
+        }
+    }
+
+    // How the type is written in diagnostics, descriptions and exported
+    // JSON. See DisplayType.
+    pub fn name(&self, tuts: &TUMap<TranslationUnitType>) -> String {
+        self.display(tuts).to_string()
+    }
+
+    pub fn display<'a>(&'a self, tuts: &'a TUMap<TranslationUnitType>) -> DisplayType<'a> {
+        DisplayType { t: self, tuts }
+    }
+
+    // The type of a use of this type that is spelled |type_spec|, like a
+    // parameter or a field, with the UniquePtr, nullable, array and maybe
+    // of |type_spec| applied to it, and the errors about qualifiers that
+    // can't be applied to it. |self| is the type that the name of
+    // |type_spec| refers to, which TypeEnv::lookup finds. A protocol
+    // becomes an actor, which is NotNull unless |type_spec| is nullable.
+    // |tuts| is only used for the names of types in errors.
+    pub fn canonicalize(
+        &self,
+        tuts: &TUMap<TranslationUnitType>,
+        type_spec: &TypeSpec,
+    ) -> (Errors, IPDLType) {
+        let mut errors = Errors::none();
+        let mut itype = self.clone();
+
+        if type_spec.uniqueptr {
+            errors.append(check_type_constructor(
+                TypeConstructor::UniquePtr,
+                &itype,
+                type_spec,
+            ));
+            itype = IPDLType::UniquePtrType(Box::new(itype))
+        }
+
+        if let &IPDLType::ProtocolType(ref p) = self {
+            itype = IPDLType::ActorType(p.clone())
+        }
+
+        // `nullable' always applies to the element type, so `nullable P[]'
+        // is an array of nullable actors. There's no way to write a
+        // nullable array, because arrays are never null in C++.
+
+        match itype {
+            // This case covers when supportsNullable() from type.py is true.
+            IPDLType::ActorType(_) | IPDLType::ImportedCxxType(_, Lifetime::RefCounted, _, _) => {
+                if !type_spec.nullable {
+                    itype = IPDLType::NotNullType(Box::new(itype))
+                }
+            }
+            _ => {
+                if type_spec.nullable {
+                    let id = if type_spec.array {
+                        "unsupported_nullable.array"
+                    } else {
+                        "unsupported_nullable"
+                    };
+                    errors.push(Diagnostic::from_catalog(
+                        type_spec.loc(),
+                        id,
+                        &[("type", &itype.name(tuts)), ("spec", type_spec)],
+                    ));
+                }
+            }
+        }
+
+        if type_spec.array {
+            errors.append(check_type_constructor(
+                TypeConstructor::Array,
+                &itype,
+                type_spec,
+            ));
+            itype = IPDLType::ArrayType(Box::new(itype))
+        }
+
+        if type_spec.maybe {
+            itype = IPDLType::MaybeType(Box::new(itype))
+        }
+
+        if itype.constructor_depth() > MAX_TYPE_CONSTRUCTOR_DEPTH {
+            errors.push(Diagnostic::from_catalog(
+                type_spec.loc(),
+                "type_depth",
+                &[("type", type_spec), ("limit", &MAX_TYPE_CONSTRUCTOR_DEPTH)],
+            ));
+        }
+
+        (errors, itype)
+    }
+
+    // The number of type constructors, like UniquePtr or an array, that
+    // are wrapped around a named type.
+    fn constructor_depth(&self) -> usize {
+        match self {
+            IPDLType::ArrayType(t)
+            | IPDLType::MaybeType(t)
+            | IPDLType::UniquePtrType(t)
+            | IPDLType::NotNullType(t) => t.constructor_depth() + 1,
+            _ => 0,
+        }
+    }
+
+    // A string that is the same for two types exactly when they are the same
+    // type. Unlike name(), this works before the definitions of structs and
+    // unions have been gathered.
+    fn identity(&self) -> String {
+        match self {
+            IPDLType::BuiltinCType(name) => name.to_string(),
+            IPDLType::ImportedCxxType(qid, _, _, _)
+            | IPDLType::ShmemType(qid)
+            | IPDLType::ByteBufType(qid)
+            | IPDLType::FDType(qid)
+            | IPDLType::EndpointType(qid)
+            | IPDLType::ManagedEndpointType(qid, _) => qid.to_string(),
+            IPDLType::MessageType(tr) => format!("message {} {}", tr.tu, tr.index),
+            IPDLType::ProtocolType(tu) => format!("protocol {}", tu),
+            IPDLType::ActorType(tu) => format!("actor {}", tu),
+            IPDLType::StructType(tr) => format!("struct {} {}", tr.tu, tr.index),
+            IPDLType::UnionType(tr) => format!("union {} {}", tr.tu, tr.index),
+            IPDLType::ArrayType(t) => format!("{}[]", t.identity()),
+            IPDLType::MaybeType(t) => format!("{}?", t.identity()),
+            IPDLType::UniquePtrType(t) => format!("UniquePtr<{}>", t.identity()),
+            IPDLType::NotNullType(t) => format!("NotNull<{}>", t.identity()),
+        }
+    }
+
+    fn is_refcounted(&self, tuts: &Option<TUMap<TranslationUnitType>>) -> bool {
+        match self {
+            &IPDLType::ImportedCxxType(_, Lifetime::RefCounted, _, _) => true,
+            &IPDLType::ActorType(ref p) => tuts
+                .as_ref()
+                .and_then(|tuts| get_protocol_type(tuts, p))
+                .is_some_and(|ptype| ptype.lifetime == Lifetime::RefCounted),
+            _ => false,
+        }
+    }
+
+    fn is_send_moveonly(&self) -> bool {
+        match self {
+            &IPDLType::ImportedCxxType(_, _, send_moveonly, _) => send_moveonly,
+            _ => false,
+        }
+    }
+
+    fn is_data_moveonly(&self) -> bool {
+        match self {
+            &IPDLType::ImportedCxxType(_, _, _, data_moveonly) => data_moveonly,
+            _ => false,
+        }
+    }
+
+    // The moveonly imported type that this type is made of, looking through
+    // arrays and the like but not into structs and unions.
+    fn moveonly_base(&self) -> Option<&IPDLType> {
+        match self {
+            IPDLType::ImportedCxxType(_, _, send_moveonly, data_moveonly)
+                if *send_moveonly || *data_moveonly =>
+            {
+                Some(self)
+            }
+            IPDLType::ArrayType(t_inner)
+            | IPDLType::MaybeType(t_inner)
+            | IPDLType::UniquePtrType(t_inner)
+            | IPDLType::NotNullType(t_inner) => t_inner.moveonly_base(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StructTypeDef {
+    pub qname: QualifiedId,
+    pub comparable: bool,
+    pub fields: Vec<TypeId>,
+}
+
+impl StructTypeDef {
+    fn new(ns: &Namespace, comparable: bool) -> StructTypeDef {
+        StructTypeDef {
+            qname: ns.qname(),
+            comparable: comparable,
+            fields: Vec::new(),
+        }
+    }
+
+    fn append_field(&mut self, field_type: TypeId) {
+        self.fields.push(field_type)
+    }
+
+    pub fn field_types<'a>(
+        &'a self,
+        tuts: &'a TUMap<TranslationUnitType>,
+    ) -> impl Iterator<Item = &'a IPDLType> + 'a {
+        self.fields.iter().map(move |f| f.lookup(tuts))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UnionTypeDef {
+    pub qname: QualifiedId,
+    pub comparable: bool,
+    pub components: Vec<TypeId>,
+}
+
+impl UnionTypeDef {
+    fn new(ns: &Namespace, comparable: bool) -> UnionTypeDef {
+        UnionTypeDef {
+            qname: ns.qname(),
+            comparable: comparable,
+            components: Vec::new(),
+        }
+    }
+
+    fn append_component(&mut self, component_type: TypeId) {
+        self.components.push(component_type)
+    }
+
+    pub fn component_types<'a>(
+        &'a self,
+        tuts: &'a TUMap<TranslationUnitType>,
+    ) -> impl Iterator<Item = &'a IPDLType> + 'a {
+        self.components.iter().map(move |c| c.lookup(tuts))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MessageType {
+    Ctor(TUId),
+    Dtor(TUId),
+    Other,
+}
+
+impl MessageType {
+    pub fn is_ctor(&self) -> bool {
+        match self {
+            &MessageType::Ctor(_) => true,
+            _ => false,
+        }
+    }
+
+    // The protocol that a ctor constructs, or None for other messages.
+    pub fn ctor_type(&self) -> Option<&TUId> {
+        match self {
+            MessageType::Ctor(tuid) => Some(tuid),
+            _ => None,
+        }
+    }
+
+    pub fn is_dtor(&self) -> bool {
+        match self {
+            &MessageType::Dtor(_) => true,
+            _ => false,
+        }
+    }
+}
+
+// The send semantics of a message or protocol, and the range of nesting
+// that it uses or allows. A message can be declared in a protocol, and
+// sent through each of its managers, if its strength converts to theirs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MessageStrength {
+    pub send_semantics: SendSemantics,
+    pub nested_min: Nesting,
+    pub nested_max: Nesting,
+}
+
+// Why a message can't be sent in a protocol.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StrengthMismatch {
+    // The message is nested less than the protocol requires.
+    NestedBelow {
+        message: Nesting,
+        protocol: Nesting,
+    },
+    // The message is nested more than the protocol allows.
+    NestedAbove {
+        message: Nesting,
+        protocol: Nesting,
+    },
+    // Intr protocols don't allow nesting.
+    NestedInIntr,
+    // The message needs stronger send semantics than the protocol has.
+    SendSemantics {
+        message: SendSemantics,
+        protocol: SendSemantics,
+    },
+    // Nested messages can only have the normal priority.
+    NestedPriority(Priority),
+}
+
+fn semantics_name(s: SendSemantics) -> &'static str {
+    match s {
+        SendSemantics::Async => "async",
+        SendSemantics::Sync => "sync",
+        SendSemantics::Intr => "intr",
+    }
+}
+
+// The names are the ones that the attributes use.
+fn nesting_name(n: Nesting) -> &'static str {
+    match n {
+        Nesting::None => "not",
+        Nesting::InsideSync => "inside_sync",
+        Nesting::InsideCpow => "inside_cpow",
+    }
+}
+
+impl StrengthMismatch {
+    // The mismatch as a sentence about a |subject|, which is `message' or
+    // `protocol', and the |container| that it is checked against, which is
+    // `protocol' or `manager'. A protocol's nesting is the most that its
+    // messages can have, so it is written as its attribute.
+    pub fn describe(&self, subject: &str, container: &str) -> String {
+        let nested = |who: &str, n: Nesting| {
+            if who == "message" {
+                format!("nested {}", nesting_name(n))
+            } else {
+                format!("[NestedUpTo={}]", nesting_name(n))
+            }
+        };
+        match self {
+            StrengthMismatch::NestedBelow { message, protocol } => format!(
+                "the {} is {}, but the {} only has messages nested at least {}",
+                subject,
+                nested(subject, *message),
+                container,
+                nesting_name(*protocol)
+            ),
+            StrengthMismatch::NestedAbove { message, protocol } => format!(
+                "the {} is {}, but the {} is only {}",
+                subject,
+                nested(subject, *message),
+                container,
+                nested(container, *protocol)
+            ),
+            StrengthMismatch::NestedInIntr => format!(
+                "the {} is intr, and intr protocols can't nest messages",
+                container
+            ),
+            StrengthMismatch::SendSemantics { message, protocol } => format!(
+                "the {} is {}, but the {} is only {}",
+                subject,
+                semantics_name(*message),
+                container,
+                semantics_name(*protocol)
+            ),
+            StrengthMismatch::NestedPriority(_) => format!(
+                "the {} is nested, so its priority has to be normal",
+                subject
+            ),
+        }
+    }
+}
+
+impl fmt::Display for StrengthMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.describe("message", "protocol"))
+    }
+}
+
+impl MessageStrength {
+    // Ok if a message with this strength can be sent in a protocol with
+    // strength |other|, or the first rule that it breaks.
+    pub fn check_conversion(&self, other: &MessageStrength) -> Result<(), StrengthMismatch> {
+        if self.nested_min < other.nested_min {
+            return Err(StrengthMismatch::NestedBelow {
+                message: self.nested_min,
+                protocol: other.nested_min,
+            });
+        }
+
+        if self.nested_max > other.nested_max {
+            return Err(StrengthMismatch::NestedAbove {
+                message: self.nested_max,
+                protocol: other.nested_max,
+            });
+        }
+
+        // Protocols that use intr semantics are not allowed to use
+        // message nesting.
+        if other.send_semantics.is_intr()
+            && (!self.nested_min.is_none() || !self.nested_max.is_none())
+        {
+            return Err(StrengthMismatch::NestedInIntr);
+        }
+
+        if self.send_semantics.is_async() {
+            return Ok(());
+        } else if self.send_semantics.is_sync() && !other.send_semantics.is_async() {
+            return Ok(());
+        } else if other.send_semantics.is_intr() {
+            return Ok(());
+        }
+
+        Err(StrengthMismatch::SendSemantics {
+            message: self.send_semantics,
+            protocol: other.send_semantics,
+        })
+    }
+
+    pub fn converts_to(&self, other: &MessageStrength) -> bool {
+        self.check_conversion(other).is_ok()
+    }
+}
+
+// The names of the C++ methods and types that are generated for a message,
+// so that collisions and questions about them can be answered before there
+// is any C++.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageCxxNames {
+    // The method that sends the message, like `SendFoo', or `CallFoo' for an
+    // intr message.
+    pub send: String,
+    // The method that the receiving actor implements, like `RecvFoo', or
+    // `AnswerFoo' for an intr message.
+    pub recv: String,
+    // For an async message with returns, the MozPromise that the sender
+    // gets back, like `FooPromise'.
+    pub promise: Option<String>,
+    // For an async message with returns, the callback that the receiver
+    // resolves, like `FooResolver'.
+    pub resolver: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParamTypeDef {
+    pub name: Identifier,
+    pub param_type: TypeId,
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageTypeDef {
+    pub name: Identifier,
+    pub send_semantics: SendSemantics,
+    pub nested: Nesting,
+    pub prio: Priority,
+    pub reply_prio: Priority,
+    pub direction: Direction,
+    pub params: Vec<ParamTypeDef>,
+    pub returns: Vec<ParamTypeDef>,
+    pub mtype: MessageType,
+    pub compress: Compress,
+    pub lazy_send: bool,
+    pub virtual_send: bool,
+    // The protocol version that added the message, from `[Since=N]'.
+    pub since: Option<u32>,
+    // `[NoLogging]' messages carry data that must not end up in IPC logs
+    // or telemetry.
+    pub no_logging: bool,
+    // The most bytes the message may be, from `[MaxSize=N]'.
+    pub max_size: Option<u32>,
+    // What to use instead, from `[Deprecated="..."]'.
+    pub deprecated: Option<String>,
+    // The side that is allowed to send the message even though it is sync,
+    // from `[AllowSyncFrom=Parent]' or `Child'.
+    pub allow_sync_from: Option<ProtocolSide>,
+}
+// XXX Need to add Tainted.
+
+fn has_attribute(attributes: &Attributes, key: &str) -> bool {
+    attributes.contains_key(key)
+}
+
+// Whether a warning is suppressed by an `[Allow=code]` attribute.
+fn allows_warning(attributes: &Attributes, code: &str) -> bool {
+    match attributes.get("Allow") {
+        Some((_, AttributeValue::Identifier(id))) => id.id == code,
+        _ => false,
+    }
+}
+
+// The codes of the warnings that can be suppressed on a message.
+const MESSAGE_WARNINGS: &[&str] = &[
+    "mirrored_messages",
+    "sync_both_directions",
+    "sync_without_returns",
+    "too_many_params",
+    "too_many_fds",
+    "unused_sync_return",
+    "large_message",
+];
+
+// The beginnings of the names of messages that tell the other side about
+// something, rather than ask it for anything.
+const FIRE_AND_FORGET_PREFIXES: &[&str] = &["Notify", "Report", "Record", "Log", "Post", "Update"];
+
+fn get_attribute_value<A: Clone>(
+    attributes: &Attributes,
+    key: &str,
+    no_value: A,
+    identifier_map: HashMap<&'static str, A>,
+    string_map: fn(&String) -> A,
+) -> Option<A> {
+    let v: &AttributeValue = &attributes.get(key)?.1;
+    let v: A = match v {
+        AttributeValue::Identifier(i) => identifier_map.get(i.id.as_str())?.clone(),
+        AttributeValue::String(s) => string_map(&s),
+        AttributeValue::None => no_value,
+        // None of the attributes that are read this way take numbers.
+        AttributeValue::Integer(_) => return None,
+    };
+    Some(v)
+}
+
+fn get_nested(attributes: &Attributes, key: &str) -> Nesting {
+    get_attribute_value(
+        attributes,
+        key,
+        Nesting::None,
+        HashMap::from([
+            ("not", Nesting::None),
+            ("inside_sync", Nesting::InsideSync),
+            ("inside_cpow", Nesting::InsideCpow),
+        ]),
+        |_| Nesting::None,
+    )
+    .unwrap_or(Nesting::None)
+}
+
+struct HostProcesses {
+    parent: Option<String>,
+    child: Option<String>,
+}
+fn get_processes(attributes: &Attributes) -> HostProcesses {
+    fn get<'a>(attributes: &'a Attributes, key: &str) -> Option<String> {
+        match &attributes.get(key)?.1 {
+            AttributeValue::Identifier(s) => Some(s.id.to_string()),
+            // TODO: either emit an error here, or make this irrepresentable
+            _ => None,
+        }
+    }
+
+    HostProcesses {
+        parent: get(attributes, "ParentProc"),
+        child: get(attributes, "ChildProc"),
+    }
+}
+
+fn get_alloc_shmem(attributes: &Attributes) -> Option<Vec<ProtocolSide>> {
+    get_attribute_value(
+        attributes,
+        "AllocShmem",
+        Vec::new(),
+        HashMap::from([
+            ("Parent", vec![ProtocolSide::Parent]),
+            ("Child", vec![ProtocolSide::Child]),
+            ("Both", vec![ProtocolSide::Parent, ProtocolSide::Child]),
+            ("None", Vec::new()),
+        ]),
+        |_| Vec::new(),
+    )
+}
+
+fn get_prio_impl(attributes: &Attributes, key: &str) -> Option<Priority> {
+    get_attribute_value(
+        attributes,
+        key,
+        Priority::Normal,
+        HashMap::from([
+            ("normal", Priority::Normal),
+            ("input", Priority::Input),
+            ("vsync", Priority::Vsync),
+            ("mediumhigh", Priority::Mediumhigh),
+            ("control", Priority::Control),
+        ]),
+        |_| Priority::Normal,
+    )
+}
+
+fn get_prio(attributes: &Attributes) -> Priority {
+    get_prio_impl(attributes, "Priority").unwrap_or(Priority::Normal)
+}
+
+fn get_reply_prio(attributes: &Attributes) -> Priority {
+    get_prio_impl(attributes, "ReplyPriority").unwrap_or_else(|| get_prio(attributes))
+}
+
+fn get_integer(attributes: &Attributes, key: &str) -> Option<u32> {
+    match attributes.get(key)?.1 {
+        AttributeValue::Integer(n) => Some(n),
+        _ => None,
+    }
+}
+
+// The value of a string attribute, without the quotes.
+fn get_string(attributes: &Attributes, key: &str) -> Option<String> {
+    match &attributes.get(key)?.1 {
+        AttributeValue::String(s) => Some(s.trim_matches('"').to_string()),
+        _ => None,
+    }
+}
+
+fn get_allow_sync_from(attributes: &Attributes) -> Option<ProtocolSide> {
+    get_attribute_value(
+        attributes,
+        "AllowSyncFrom",
+        None,
+        HashMap::from([
+            ("Parent", Some(ProtocolSide::Parent)),
+            ("Child", Some(ProtocolSide::Child)),
+        ]),
+        |_| None,
+    )
+    .flatten()
+}
+
+fn get_compress(attributes: &Attributes) -> Compress {
+    get_attribute_value(
+        attributes,
+        "Compress",
+        Compress::Enabled,
+        HashMap::from([("all", Compress::All)]),
+        |_| Compress::None,
+    )
+    .unwrap_or(Compress::None)
+}
+
+impl MessageTypeDef {
+    fn new(md: &MessageDecl, name: &str, mtype: MessageType) -> MessageTypeDef {
+        debug_assert!(!mtype.is_ctor() || name.ends_with(CONSTRUCTOR_SUFFIX));
+        MessageTypeDef {
+            name: Identifier::new(String::from(name), md.name.loc.clone()),
+            send_semantics: md.send_semantics,
+            nested: get_nested(&md.attributes, "Nested"),
+            prio: get_prio(&md.attributes),
+            reply_prio: get_reply_prio(&md.attributes),
+            direction: md.direction,
+            params: Vec::new(),
+            returns: Vec::new(),
+            mtype,
+            compress: get_compress(&md.attributes),
+            lazy_send: has_attribute(&md.attributes, "LazySend"),
+            virtual_send: has_attribute(&md.attributes, "VirtualSendImpl"),
+            since: get_integer(&md.attributes, "Since"),
+            no_logging: has_attribute(&md.attributes, "NoLogging"),
+            max_size: get_integer(&md.attributes, "MaxSize"),
+            deprecated: get_string(&md.attributes, "Deprecated"),
+            allow_sync_from: get_allow_sync_from(&md.attributes),
+        }
+    }
+
+    pub fn is_ctor(&self) -> bool {
+        self.mtype.is_ctor()
+    }
+
+    pub fn ctor_type(&self) -> Option<&TUId> {
+        self.mtype.ctor_type()
+    }
+
+    pub fn is_dtor(&self) -> bool {
+        self.mtype.is_dtor()
+    }
+
+    // The message as it is declared, without its attributes, like
+    // `async Foo(int a, ::Bar b) returns (bool ok)'. The types are written
+    // with IPDLType::display.
+    pub fn signature(&self, tuts: &TUMap<TranslationUnitType>) -> String {
+        let params = |params: &[ParamTypeDef]| {
+            params
+                .iter()
+                .map(|p| format!("{} {}", p.param_type.lookup(tuts).display(tuts), p.name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut s = format!(
+            "{} {}({})",
+            semantics_name(self.send_semantics),
+            self.name,
+            params(&self.params)
+        );
+        if !self.returns.is_empty() {
+            s.push_str(&format!(" returns ({})", params(&self.returns)));
+        }
+        s
+    }
+
+    // The return value of a sync message that probably nobody looks at: a
+    // single bool, on a message whose name sounds like a notification, like
+    // `NotifyFoo'. This is only a guess, so it is public for tools that
+    // want to make their own judgement with the returns.
+    pub fn unused_sync_return(&self, tuts: &TUMap<TranslationUnitType>) -> Option<&ParamTypeDef> {
+        let ret = match self.returns.as_slice() {
+            [ret] if matches!(ret.param_type.lookup(tuts), IPDLType::BuiltinCType("bool")) => ret,
+            _ => return None,
+        };
+        if self.send_semantics.is_async() {
+            return None;
+        }
+        let name = &self.name.id;
+        let notification = FIRE_AND_FORGET_PREFIXES.iter().any(|prefix| {
+            name.strip_prefix(prefix)
+                .is_some_and(|rest| rest.chars().next().is_none_or(|c| c.is_ascii_uppercase()))
+        });
+        if notification {
+            Some(ret)
+        } else {
+            None
+        }
+    }
+
+    pub fn message_strength(&self) -> MessageStrength {
+        MessageStrength {
+            send_semantics: self.send_semantics,
+            nested_min: self.nested,
+            nested_max: self.nested,
+        }
+    }
+
+    fn converts_to(&self, protocol: &ProtocolTypeDef) -> bool {
+        self.message_strength()
+            .converts_to(&protocol.message_strength())
+    }
+
+    pub fn is_async(&self) -> bool {
+        self.send_semantics.is_async()
+    }
+
+    pub fn is_sync(&self) -> bool {
+        self.send_semantics.is_sync()
+    }
+
+    // Whether the message has a reply message, which sync and intr
+    // messages always do, and async messages do when they return something.
+    pub fn has_reply(&self) -> bool {
+        !self.is_async() || !self.returns.is_empty()
+    }
+
+    // The constants of the MessageType enum that the message gets, like
+    // `Msg_Foo__ID', and `Reply_Foo__ID' after it if it has a reply.
+    pub fn message_ids(&self) -> Vec<String> {
+        let mut ids = vec![format!("Msg_{}__ID", self.name.id)];
+        if self.has_reply() {
+            ids.push(format!("Reply_{}__ID", self.name.id));
+        }
+        ids
+    }
+
+    pub fn cxx_names(&self) -> MessageCxxNames {
+        let name = &self.name.id;
+        let (send, recv) = if self.send_semantics.is_intr() {
+            ("Call", "Answer")
+        } else {
+            ("Send", "Recv")
+        };
+        let async_reply = self.is_async() && !self.returns.is_empty();
+        MessageCxxNames {
+            send: format!("{}{}", send, name),
+            recv: format!("{}{}", recv, name),
+            promise: Some(format!("{}Promise", name)).filter(|_| async_reply),
+            resolver: Some(format!("{}Resolver", name)).filter(|_| async_reply),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProtocolTypeDef {
+    pub qname: QualifiedId,
+    pub send_semantics: SendSemantics,
+    pub nested: Nesting,
+    pub managers: Vec<TUId>,
+    pub manages: Vec<TUId>,
+    pub messages: Vec<MessageTypeDef>,
+    pub has_delete: bool,
+    pub proc_parent: Option<String>,
+    pub proc_child: Option<String>,
+    pub lifetime: Lifetime,
+    pub needs_other_pid: bool,
+    // From `[ProtocolVersion=N]'.
+    pub version: Option<u32>,
+    // What to use instead, from `[Deprecated="..."]'.
+    pub deprecated: Option<String>,
+    // The sides that may allocate the Shmems that its messages carry, from
+    // `[AllocShmem=...]', or None if it doesn't say.
+    pub alloc_shmem: Option<Vec<ProtocolSide>>,
+}
+
+impl ProtocolTypeDef {
+    fn new(&(ref ns, ref p): &(Namespace, Protocol)) -> ProtocolTypeDef {
+        let processes = get_processes(&p.attributes);
+        ProtocolTypeDef {
+            qname: ns.qname(),
+            send_semantics: p.send_semantics,
+            nested: get_nested(&p.attributes, "NestedUpTo"),
+            managers: Vec::new(),
+            manages: Vec::new(),
+            messages: Vec::new(),
+            proc_parent: processes.parent,
+            proc_child: processes.child,
+            has_delete: false,
+            lifetime: if p.attributes.contains_key("ManualDealloc") {
+                Lifetime::ManualDealloc
+            } else {
+                Lifetime::RefCounted
+            },
+            needs_other_pid: p.attributes.contains_key("NeedsOtherPid"),
+            version: get_integer(&p.attributes, "ProtocolVersion"),
+            deprecated: get_string(&p.attributes, "Deprecated"),
+            alloc_shmem: get_alloc_shmem(&p.attributes),
+        }
+    }
+
+    pub fn is_top_level(&self) -> bool {
+        self.managers.len() == 0
+    }
+
+    pub fn message_strength(&self) -> MessageStrength {
+        MessageStrength {
+            send_semantics: self.send_semantics,
+            nested_min: Nesting::None,
+            nested_max: self.nested,
+        }
+    }
+
+    pub fn process_for(&self, side: ProtocolSide) -> Option<&str> {
+        match side {
+            ProtocolSide::Parent => &self.proc_parent,
+            ProtocolSide::Child => &self.proc_child,
+        }
+        .as_ref()
+        .map(|u| u.as_str())
+    }
+
+    // The sides of this protocol that can live in a process of type
+    // |process|, like `GPU', going by its [ParentProc] and [ChildProc]. A
+    // side with no declared process isn't in any process.
+    pub fn sides_in_process(&self, process: &str) -> Vec<ProtocolSide> {
+        [ProtocolSide::Parent, ProtocolSide::Child]
+            .iter()
+            .cloned()
+            .filter(|side| {
+                self.process_for(*side)
+                    .is_some_and(|declared| process_allows(declared, process))
+            })
+            .collect()
+    }
+}
+
+// The types of processes that a [ParentProc] or [ChildProc] can name,
+// besides the wildcards that process_allows understands.
+const PROCESS_TYPES: &[&str] = &[
+    "Parent",
+    "Content",
+    "IPDLUnitTest",
+    "GMPlugin",
+    "GPU",
+    "VR",
+    "RDD",
+    "Socket",
+    "RemoteSandboxBroker",
+    "ForkServer",
+    "Utility",
+];
+
+// Whether a side declared with the process keyword |declared| can live in
+// a process of type |process|. `any' is every process, `anychild' is every
+// process but the parent, `anydom' is the parent or a content process, and
+// `compositor' is the parent or the GPU process.
+fn process_allows(declared: &str, process: &str) -> bool {
+    let is = |name: &str| process.eq_ignore_ascii_case(name);
+    match declared {
+        "any" => true,
+        "anychild" => !is("Parent"),
+        "anydom" => is("Parent") || is("Content"),
+        "compositor" => is("Parent") || is("GPU"),
+        _ => is(declared),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Decl {
+    loc: Location,
+    decl_type: IPDLType,
+    short_name: String,
+    full_name: Option<String>,
+    // Where the declaration is used, if the symbol table tracks that.
+    references: RefCell<Vec<Location>>,
+}
+
+// The Python version also has a "progname" field, but I don't see any
+// reason to keep that separate from the short_name field.
+
+impl Decl {
+    fn new(loc: &Location, decl_type: IPDLType, short_name: String) -> Decl {
+        Decl {
+            loc: loc.clone(),
+            decl_type: decl_type,
+            short_name: short_name,
+            full_name: None,
+            references: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn new_from_qid(qid: &QualifiedId, decl_type: IPDLType) -> Decl {
+        Decl {
+            loc: qid.loc().clone(),
+            decl_type: decl_type,
+            short_name: qid.short_name(),
+            full_name: qid.full_name(),
+            references: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+// A declaration with a qualified name is bound under both of its names, so
+// the scopes share it rather than each having a copy.
+struct SymbolTable {
+    scopes: Vec<HashMap<String, Rc<Decl>>>,
+    // Whether lookup_at and lookup_type_at record where each declaration
+    // is used.
+    track_references: bool,
+}
+
+// A name that is visible in the scope of a translation unit, for tools
+// that explain name lookup. A declaration with a qualified name is visible
+// under both its short and its fully qualified names.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub symbol_type: IPDLType,
+    pub loc: Location,
+    // Where the symbol is used in the translation unit, in the order the
+    // checker looked it up, when CheckOptions::track_references is set.
+    pub references: Vec<Location>,
+}
+
+impl SymbolTable {
+    fn new(track_references: bool) -> SymbolTable {
+        SymbolTable {
+            scopes: vec![HashMap::new()],
+            track_references,
+        }
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(HashMap::new())
+    }
+
+    // Every visible symbol, sorted by name.
+    fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols = self
+            .scopes
+            .iter()
+            .flat_map(|s| s.iter())
+            .map(|(name, decl)| Symbol {
+                name: name.clone(),
+                symbol_type: decl.decl_type.clone(),
+                loc: decl.loc.clone(),
+                references: decl.references.borrow().clone(),
+            })
+            .collect::<Vec<_>>();
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        symbols
+    }
+
+    fn exit_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn lookup(&self, sym: &str) -> Option<Rc<Decl>> {
+        self.scopes.iter().find_map(|s| s.get(sym).cloned())
+    }
+
+    // Look up the name of a type that is used inside of |namespaces|. Like
+    // in C++, a partially qualified name, like `dom::Foo', is looked for in
+    // each enclosing namespace from the innermost out, so inside of
+    // `namespace mozilla' it can be `::mozilla::dom::Foo' or `::dom::Foo'.
+    fn lookup_type(&self, name: &str, namespaces: &[String]) -> Option<Rc<Decl>> {
+        // The template arguments of an instance are qualified on their own.
+        let base = name.split('<').next().unwrap_or(name);
+        if !base.contains("::") {
+            return self.lookup(name);
+        }
+        (0..=namespaces.len()).rev().find_map(|depth| {
+            let mut full_name = String::new();
+            for ns in &namespaces[..depth] {
+                full_name.push_str("::");
+                full_name.push_str(ns);
+            }
+            full_name.push_str("::");
+            full_name.push_str(name);
+            self.lookup(&full_name)
+        })
+    }
+
+    fn record_reference(&self, decl: Option<Rc<Decl>>, loc: &Location) -> Option<Rc<Decl>> {
+        if self.track_references {
+            if let Some(decl) = &decl {
+                decl.references.borrow_mut().push(loc.clone());
+            }
+        }
+        decl
+    }
+
+    // Like lookup, for a use of the name at |loc|.
+    fn lookup_at(&self, sym: &str, loc: &Location) -> Option<Rc<Decl>> {
+        self.record_reference(self.lookup(sym), loc)
+    }
+
+    // Like lookup_type, for a use of the name at |loc|.
+    fn lookup_type_at(
+        &self,
+        name: &str,
+        namespaces: &[String],
+        loc: &Location,
+    ) -> Option<Rc<Decl>> {
+        self.record_reference(self.lookup_type(name, namespaces), loc)
+    }
+
+    // Like the Python compiler, this also rejects a name that shadows a
+    // declaration in an enclosing scope, like a parameter or struct field
+    // with the same name as a type, so there's no need to warn about
+    // shadowing.
+    fn declare_inner(&mut self, name: &str, decl: Rc<Decl>) -> Errors {
+        if let Some(old_decl) = self.lookup(name) {
+            let mut diagnostic =
+                Diagnostic::from_catalog(&decl.loc, "redeclaration", &[("name", &name)]);
+            if old_decl.loc != Location::builtin() {
+                let note = if self.scopes.last().is_some_and(|s| s.contains_key(name)) {
+                    "first declared here"
+                } else {
+                    "first declared here, in an enclosing scope, and names can't be shadowed"
+                };
+                diagnostic = diagnostic.with_note(&old_decl.loc, note);
+            }
+            return Errors::from_diagnostic(diagnostic);
+        }
+
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(String::from(name), decl);
+        }
+        Errors::none()
+    }
+
+    // Bind |name| to the declaration that |target| is bound to, unless it
+    // already is.
+    fn declare_alias(&mut self, name: &str, target: &str) -> Errors {
+        let decl = match self.lookup(target) {
+            Some(decl) => decl,
+            None => return Errors::none(),
+        };
+        match self.lookup(name) {
+            Some(old_decl) if Rc::ptr_eq(&old_decl, &decl) => Errors::none(),
+            _ => self.declare_inner(name, decl),
+        }
+    }
+
+    fn declare(&mut self, decl: Decl) -> Errors {
+        let decl = Rc::new(decl);
+        let mut errors = self.declare_inner(&decl.short_name, decl.clone());
+        if let Some(ref full_name) = decl.full_name {
+            errors.append(self.declare_inner(full_name, decl.clone()));
+        }
+        errors
+    }
+}
+
+// The names that types can be looked up by, for tools that turn the
+// TypeSpecs of a translation unit, like ones read back from a JSON
+// description of it, into IPDLTypes the same way the checker does.
+pub struct TypeEnv {
+    sym_tab: SymbolTable,
+}
+
+impl TypeEnv {
+    // Only the builtin types, like `int32_t' and `Shmem'.
+    pub fn builtins() -> TypeEnv {
+        let mut sym_tab = SymbolTable::new(false);
+        let errors = declare_builtins(&mut sym_tab, &HashMap::new());
+        debug_assert!(errors.is_empty());
+        TypeEnv { sym_tab }
+    }
+
+    // The names that are visible in the scope of a checked translation
+    // unit, from the Symbols of its TranslationUnitType.
+    pub fn from_symbols(symbols: &[Symbol]) -> TypeEnv {
+        // The names of the Symbols are unique, and they already include
+        // both names of each declaration with a qualified name.
+        let mut sym_tab = SymbolTable::new(false);
+        for sym in symbols {
+            let decl = Decl::new(&sym.loc, sym.symbol_type.clone(), sym.name.clone());
+            sym_tab.scopes[0].insert(sym.name.clone(), Rc::new(decl));
+        }
+        TypeEnv { sym_tab }
+    }
+
+    // Make |name| refer to |t|, or return an error if it already refers to
+    // something.
+    pub fn declare(&mut self, name: &str, loc: &Location, t: IPDLType) -> Errors {
+        let decl = Rc::new(Decl::new(loc, t, String::from(name)));
+        self.sym_tab.declare_inner(name, decl)
+    }
+
+    // The type that |name| refers to when it is used inside of
+    // |namespaces|, which can be partially qualified like in the checker.
+    pub fn lookup(&self, name: &str, namespaces: &[String]) -> Option<IPDLType> {
+        self.sym_tab
+            .lookup_type(name, namespaces)
+            .map(|decl| decl.decl_type.clone())
+    }
+
+    // The canonical type of |type_spec| when it is used inside of
+    // |namespaces|, or the errors about it, including one if its name
+    // isn't declared.
+    pub fn resolve(
+        &self,
+        tuts: &TUMap<TranslationUnitType>,
+        type_spec: &TypeSpec,
+        namespaces: &[String],
+    ) -> Result<IPDLType, Vec<Diagnostic>> {
+        let name = type_spec.spec.to_string();
+        let t = match self.lookup(&name, namespaces) {
+            Some(t) => t,
+            None => {
+                return Err(vec![Diagnostic::from_catalog(
+                    type_spec.loc(),
+                    "unknown_type",
+                    &[("type", &name)],
+                )
+                .with_symbol(&name)]);
+            }
+        };
+        let (errors, itype) = t.canonicalize(tuts, type_spec);
+        if errors.is_empty() {
+            Ok(itype)
+        } else {
+            Err(errors.into_diagnostics())
+        }
+    }
+}
+
+// The spelling of an imported C++ type that the checker uses, which is the
+// type that |aliases| says it is another name for, if any. The locations
+// are the ones of |spec|.
+fn canonical_cxx_type(spec: &QualifiedId, aliases: &HashMap<String, String>) -> QualifiedId {
+    let name = spec.to_string();
+    let canonical = match aliases.get(name.strip_prefix("::").unwrap_or(&name)) {
+        Some(canonical) => canonical,
+        None => return spec.clone(),
+    };
+    let mut ids = canonical
+        .split("::")
+        .map(|id| Identifier::new(String::from(id), spec.loc().clone()));
+    match ids.next() {
+        Some(first) => ids.fold(QualifiedId::new(first), |qid, id| qid.qualify(id)),
+        None => spec.clone(),
+    }
+}
+
+// Declare an imported C++ type under its canonical name. A type that is
+// spelled with an alias is also visible under the alias's names.
+fn declare_cxx_type(
+    sym_tab: &mut SymbolTable,
+    spec: &QualifiedId,
+    aliases: &HashMap<String, String>,
+    refcounted: Lifetime,
+    send_moveonly: bool,
+    data_moveonly: bool,
+) -> Errors {
+    let canonical = canonical_cxx_type(spec, aliases);
+    let mut errors = declare_canonical_cxx_type(
+        sym_tab,
+        &canonical,
+        refcounted,
+        send_moveonly,
+        data_moveonly,
+    );
+    let full_name = canonical.to_string();
+    if full_name != spec.to_string() {
+        errors.append(sym_tab.declare_alias(&spec.short_name(), &full_name));
+        errors.append(sym_tab.declare_alias(&spec.to_string(), &full_name));
+    }
+    errors
+}
+
+fn declare_canonical_cxx_type(
+    sym_tab: &mut SymbolTable,
+    spec: &QualifiedId,
+    refcounted: Lifetime,
+    send_moveonly: bool,
+    data_moveonly: bool,
+) -> Errors {
+    // The type is shared by every use of it, so it is only allocated once.
+    let qid = Rc::new(spec.clone());
+    let ipdl_type = match spec.full_name() {
+        Some(ref n) if n == "::mozilla::ipc::Shmem" => IPDLType::ShmemType(qid),
+        Some(ref n) if n == "::mozilla::ipc::ByteBuf" => IPDLType::ByteBufType(qid),
+        Some(ref n) if n == "::mozilla::ipc::FileDescriptor" => IPDLType::FDType(qid),
+        _ => {
+            let ipdl_type =
+                IPDLType::ImportedCxxType(qid, refcounted, send_moveonly, data_moveonly);
+            let full_name = format!("{}", spec);
+            if let Some(decl) = sym_tab.lookup(&full_name) {
+                if let Some(existing_type) = &decl.full_name {
+                    if *existing_type == full_name {
+                        if (refcounted == Lifetime::RefCounted)
+                            != decl.decl_type.is_refcounted(&None)
+                        {
+                            return Errors::from_diagnostic(
+                                Diagnostic::from_catalog(
+                                    spec.loc(),
+                                    "inconsistent_cxx_type.refcounted",
+                                    &[("type", &full_name)],
+                                )
+                                .with_note(&decl.loc, "first declared here"),
+                            );
+                        }
+                        if send_moveonly != decl.decl_type.is_send_moveonly()
+                            || data_moveonly != decl.decl_type.is_data_moveonly()
+                        {
+                            return Errors::from_diagnostic(
+                                Diagnostic::from_catalog(
+                                    spec.loc(),
+                                    "inconsistent_cxx_type.moveonly",
+                                    &[("type", &full_name)],
+                                )
+                                .with_note(&decl.loc, "first declared here"),
+                            );
+                        }
+
+                        // This type has already been added, so don't do anything.
+                        return Errors::none();
+                    }
+                };
+            };
+            ipdl_type
+        }
+    };
+    sym_tab.declare(Decl::new_from_qid(&spec, ipdl_type))
+}
+
+// The typed IR of a single translation unit.
+#[derive(Clone)]
+pub struct TranslationUnitType {
+    pub structs: Vec<StructTypeDef>,
+    pub unions: Vec<UnionTypeDef>,
+    // The types of the fields, components and parameters above.
+    pub types: TypeTable,
+    pub protocol: Option<ProtocolTypeDef>,
+    // The symbols visible at the top level of the translation unit, or in
+    // the scope of its protocol, which includes the messages.
+    pub symbols: Vec<Symbol>,
+}
+
+impl TranslationUnitType {
+    fn new(maybe_protocol: &Option<(Namespace, Protocol)>) -> TranslationUnitType {
+        let protocol = maybe_protocol
+            .as_ref()
+            .map(|ref p| ProtocolTypeDef::new(&p));
+        TranslationUnitType {
+            structs: Vec::new(),
+            unions: Vec::new(),
+            types: TypeTable::default(),
+            protocol: protocol,
+            symbols: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AttributeSpecValue {
+    Valueless,
+    StringLiteral,
+    Keyword(&'static str),
+    Integer,
+}
+
+impl AttributeSpecValue {
+    pub fn check(&self, v: &AttributeValue) -> bool {
+        match (v, &self) {
+            (AttributeValue::Identifier(id1), AttributeSpecValue::Keyword(id2)) => &id1.id == id2,
+            (AttributeValue::String(_), AttributeSpecValue::StringLiteral) => true,
+            (AttributeValue::None, AttributeSpecValue::Valueless) => true,
+            (AttributeValue::Integer(_), AttributeSpecValue::Integer) => true,
+            (_, _) => false,
+        }
+    }
+}
+
+type AttributeSpec = HashMap<&'static str, Vec<AttributeSpecValue>>;
+
+fn check_attributes(attributes: &Attributes, specs: &AttributeSpec) -> Errors {
+    let mut errors = Errors::none();
+
+    for (name, (loc, value)) in attributes {
+        let spec = match specs.get(name as &str) {
+            Some(s) => s,
+            None => {
+                errors.push(Diagnostic::from_catalog(
+                    &loc,
+                    "attribute.unknown",
+                    &[("attribute", &name)],
+                ));
+                continue;
+            }
+        };
+
+        if spec.len() == 0 {
+            if value != &AttributeValue::None {
+                errors.push(Diagnostic::from_catalog(
+                    &loc,
+                    "attribute.valueless",
+                    &[("attribute", &name)],
+                ));
+            }
+            continue;
+        }
+
+        if !spec.iter().any(|s| s.check(&value)) {
+            let options = spec
+                .iter()
+                .map(|f| match f {
+                    AttributeSpecValue::Valueless => "None",
+                    AttributeSpecValue::StringLiteral => "StringLiteral",
+                    AttributeSpecValue::Keyword(k) => *k,
+                    AttributeSpecValue::Integer => "Integer",
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            errors.push(Diagnostic::from_catalog(
+                &loc,
+                "attribute.value",
+                &[("attribute", &name), ("options", &options)],
+            ));
+        }
+    }
+
+    errors
+}
+
+fn declare_protocol(
+    sym_tab: &mut SymbolTable,
+    tuid: &TUId,
+    ns: &Namespace,
+    p: &Protocol,
+) -> Errors {
+    let mut errors = Errors::none();
+
+    let protocol_attributes: AttributeSpec = {
+        let process_keywords = || {
+            ["any", "anychild", "anydom", "compositor"]
+                .iter()
+                .chain(PROCESS_TYPES)
+                .map(|k| AttributeSpecValue::Keyword(k))
+                .collect::<Vec<_>>()
+        };
+
+        HashMap::from([
+            ("Allow", vec![AttributeSpecValue::Keyword("manager_order")]),
+            (
+                "AllocShmem",
+                ["Parent", "Child", "Both", "None"]
+                    .iter()
+                    .map(|k| AttributeSpecValue::Keyword(k))
+                    .collect(),
+            ),
+            ("Deprecated", vec![AttributeSpecValue::StringLiteral]),
+            ("ManualDealloc", Vec::new()),
+            (
+                "NestedUpTo",
+                Vec::from([
+                    AttributeSpecValue::Keyword("not"),
+                    AttributeSpecValue::Keyword("inside_sync"),
+                    AttributeSpecValue::Keyword("inside_cpow"),
+                ]),
+            ),
+            ("NeedsOtherPid", Vec::new()),
+            (
+                "ChildImpl",
+                Vec::from([
+                    AttributeSpecValue::Keyword("virtual"),
+                    AttributeSpecValue::StringLiteral,
+                ]),
+            ),
+            (
+                "ParentImpl",
+                Vec::from([
+                    AttributeSpecValue::Keyword("virtual"),
+                    AttributeSpecValue::StringLiteral,
+                ]),
+            ),
+            ("ChildProc", process_keywords()),
+            ("ParentProc", process_keywords()),
+            ("ProtocolVersion", Vec::from([AttributeSpecValue::Integer])),
+        ])
+    };
+    // `[Nested]' is easy to write on a protocol by analogy with messages.
+    let mut attributes = p.attributes.clone();
+    if let Some((loc, _)) = attributes.remove("Nested") {
+        errors.push(Diagnostic::from_catalog(
+            &loc,
+            "attribute.nested_protocol",
+            &[],
+        ));
+    }
+    errors.append(check_attributes(&attributes, &protocol_attributes));
+
+    let p_type = IPDLType::ProtocolType(tuid.clone());
+    errors.append(sym_tab.declare(Decl::new_from_qid(&ns.qname(), p_type)));
+
+    let ref loc = ns.name.loc;
+    let mut declare_endpoint = |is_managed: bool, side: &str| {
+        let endpoint_str = if is_managed {
+            "ManagedEndpoint"
+        } else {
+            "Endpoint"
+        };
+        let full_id = Identifier::new(
+            format!("{}<{}{}>", endpoint_str, ns.qname(), side),
+            loc.clone(),
+        );
+        let namespaces = vec!["mozilla".to_string(), "ipc".to_string()];
+        let full_qid = QualifiedId {
+            base_id: full_id,
+            quals: namespaces,
+        };
+        let endpoint_type = if is_managed {
+            IPDLType::ManagedEndpointType(Rc::new(full_qid), *tuid)
+        } else {
+            IPDLType::EndpointType(Rc::new(full_qid))
+        };
+        let short_name = format!("{}<{}{}>", endpoint_str, ns.name.id, side);
+        sym_tab.declare(Decl::new(loc, endpoint_type, short_name))
+    };
+    errors.append(declare_endpoint(true, "Parent"));
+    errors.append(declare_endpoint(true, "Child"));
+    errors.append(declare_endpoint(false, "Parent"));
+    errors.append(declare_endpoint(false, "Child"));
+
+    errors
+}
+
+// Warn about a `using' for a type that the same file already imports,
+// possibly under another name.
+fn check_redundant_usings(tu: &TranslationUnit, aliases: &HashMap<String, String>) -> Errors {
+    let mut errors = Errors::none();
+    let mut imported: HashMap<String, &QualifiedId> = HashMap::new();
+    for u in &tu.using {
+        let canonical = canonical_cxx_type(&u.cxx_type, aliases).to_string();
+        let first = match imported.get(&canonical) {
+            Some(first) => first,
+            None => {
+                imported.insert(canonical, &u.cxx_type);
+                continue;
+            }
+        };
+        let spelled = u.cxx_type.to_string();
+        let first_spelled = first.to_string();
+        let diagnostic = if spelled == first_spelled {
+            Diagnostic::from_catalog(
+                u.cxx_type.loc(),
+                "redundant_using",
+                &[("type", &&spelled[2..])],
+            )
+        } else {
+            Diagnostic::from_catalog(
+                u.cxx_type.loc(),
+                "redundant_using.alias",
+                &[("type", &&spelled[2..]), ("first", &&first_spelled[2..])],
+            )
+        };
+        errors.push(diagnostic.with_note(first.loc(), "first imported here"));
+    }
+    errors
+}
+
+// With |exported_only|, only the usings with an `[Export]' attribute are
+// declared.
+fn declare_usings(
+    mut sym_tab: &mut SymbolTable,
+    tu: &TranslationUnit,
+    aliases: &HashMap<String, String>,
+    exported_only: bool,
+) -> Errors {
+    let mut errors = Errors::none();
+
+    let using_attributes: AttributeSpec = HashMap::from([
+        (
+            "MoveOnly",
+            Vec::from([
+                AttributeSpecValue::Valueless,
+                AttributeSpecValue::Keyword("send"),
+                AttributeSpecValue::Keyword("data"),
+            ]),
+        ),
+        ("RefCounted", Vec::new()),
+        (EXPORT, Vec::new()),
+    ]);
+
+    for u in &tu.using {
+        if exported_only && !u.attributes.contains_key(EXPORT) {
+            continue;
+        }
+        errors.append(check_attributes(&u.attributes, &using_attributes));
+
+        let (send, data) = u
+            .attributes
+            .get("MoveOnly")
+            .map(|(_, v)| match v {
+                AttributeValue::None => (true, true),
+                AttributeValue::Identifier(i) => match i.id.as_str() {
+                    "send" => (true, false),
+                    "data" => (false, true),
+                    _ => (false, false),
+                },
+                _ => (false, false),
+            })
+            .unwrap_or((false, false));
+
+        errors.append(declare_cxx_type(
+            &mut sym_tab,
+            &u.cxx_type,
+            aliases,
+            if u.attributes.contains_key("RefCounted") {
+                Lifetime::RefCounted
+            } else {
+                Lifetime::ManualDealloc
+            },
+            send,
+            data,
+        ));
+    }
+    errors
+}
+
+// With |exported_only|, only the structs and unions with an `[Export]'
+// attribute are declared, which is what an included protocol shares.
+fn declare_structs_and_unions(
+    sym_tab: &mut SymbolTable,
+    tuid: &TUId,
+    tu: &TranslationUnit,
+    exported_only: bool,
+) -> Errors {
+    let mut errors = Errors::none();
+    let declared = |attributes: &Attributes| !exported_only || attributes.contains_key(EXPORT);
+    let mut index = 0;
+
+    for s in &tu.structs {
+        if declared(&s.1) {
+            let s_type = IPDLType::StructType(TypeRef::new(tuid, index));
+            errors.append(sym_tab.declare(Decl::new_from_qid(&s.0.qname(), s_type)));
+        }
+        index += 1;
+    }
+
+    index = 0;
+    for u in &tu.unions {
+        if declared(&u.1) {
+            let u_type = IPDLType::UnionType(TypeRef::new(tuid, index));
+            errors.append(sym_tab.declare(Decl::new_from_qid(&u.0.qname(), u_type)));
+        }
+        index += 1;
+    }
+
+    errors
+}
+
+// `[Export]' is how a protocol file shares its imported types, structs and
+// unions with the files that include it. Everything in a header is shared,
+// unless the header marks some of its declarations with `[Export]', in
+// which case only those are.
+fn exports_only_marked(tu: &TranslationUnit) -> bool {
+    if tu.protocol.is_some() {
+        return true;
+    }
+    tu.using
+        .iter()
+        .map(|u| &u.attributes)
+        .chain(tu.structs.iter().map(|s| &s.1))
+        .chain(tu.unions.iter().map(|u| &u.1))
+        .any(|a| a.contains_key(EXPORT))
+}
+
+// The names of the declarations of a header that marks its exports but that
+// aren't marked, with the location of each, and both with and without its
+// namespaces.
+fn unexported_names(tu: &TranslationUnit) -> Vec<(String, &Location)> {
+    let mut names = Vec::new();
+    if tu.protocol.is_some() || !exports_only_marked(tu) {
+        return names;
+    }
+    let mut add = |qid: QualifiedId, attributes: &Attributes, loc| {
+        if !attributes.contains_key(EXPORT) {
+            names.push((qid.short_name(), loc));
+            names.push((qid.to_string().trim_start_matches("::").to_string(), loc));
+        }
+    };
+    for u in &tu.using {
+        add(u.cxx_type.clone(), &u.attributes, u.cxx_type.loc());
+    }
+    for s in &tu.structs {
+        add(s.0.qname(), &s.1, &s.0.name.loc);
+    }
+    for u in &tu.unions {
+        add(u.0.qname(), &u.1, &u.0.name.loc);
+    }
+    names
+}
+
+// A type that isn't declared might be declared in an included header but
+// not exported from it, which is reported instead.
+fn report_unexported(tus: &TUMap<TranslationUnit>, tu: &TranslationUnit, errors: Errors) -> Errors {
+    let unexported = tu
+        .includes
+        .iter()
+        .filter_map(|i| tus.get(i))
+        .flat_map(|include_tu| {
+            unexported_names(include_tu)
+                .into_iter()
+                .map(move |(name, loc)| (name, loc, include_tu))
+        })
+        .collect::<Vec<_>>();
+    if unexported.is_empty() {
+        return errors;
+    }
+    let mut result = Errors::none();
+    for d in errors.into_diagnostics() {
+        let found = match (d.code, &d.symbol) {
+            (Some("unknown_type"), Some(symbol)) => unexported
+                .iter()
+                .find(|(name, _, _)| name == symbol.trim_start_matches("::"))
+                .map(|found| (found, symbol)),
+            _ => None,
+        };
+        match (found, &d.loc) {
+            (Some(((_, decl_loc, include_tu), symbol)), Some(loc)) => {
+                let header = include_tu
+                    .file_name
+                    .file_name()
+                    .unwrap_or(include_tu.file_name.as_os_str())
+                    .to_string_lossy();
+                result.push(
+                    Diagnostic::from_catalog(
+                        loc,
+                        "not_exported",
+                        &[("type", symbol), ("header", &header)],
+                    )
+                    .with_note(decl_loc, "declared here without [Export]"),
+                );
+            }
+            _ => result.push(d),
+        }
+    }
+    result
+}
+
+// The names that |tu| shares with the files that include it, with the
+// location of each, both with and without its namespaces.
+fn shared_names(tu: &TranslationUnit) -> Vec<(String, &Location)> {
+    let mut names = Vec::new();
+    let exported_only = exports_only_marked(tu);
+    let mut add = |qid: QualifiedId, attributes: Option<&Attributes>, loc| {
+        if !exported_only || attributes.is_none_or(|a| a.contains_key(EXPORT)) {
+            names.push((qid.short_name(), loc));
+            names.push((qid.to_string().trim_start_matches("::").to_string(), loc));
+        }
+    };
+    if let Some(ref p) = tu.protocol {
+        add(p.0.qname(), None, &p.0.name.loc);
+    }
+    for u in &tu.using {
+        add(u.cxx_type.clone(), Some(&u.attributes), u.cxx_type.loc());
+    }
+    for s in &tu.structs {
+        add(s.0.qname(), Some(&s.1), &s.0.name.loc);
+    }
+    for u in &tu.unions {
+        add(u.0.qname(), Some(&u.1), &u.0.name.loc);
+    }
+    names
+}
+
+// The include statement for |tu|, and an edit that adds it to |tu| after
+// its last include, or at its start if it has none.
+fn include_edit(tu: &TranslationUnit, include_tu: &TranslationUnit) -> (String, Edit) {
+    let include = match include_tu.protocol {
+        Some(ref p) => format!("include protocol {};", p.0.name),
+        None => format!(
+            "include {};",
+            include_tu
+                .file_name
+                .file_stem()
+                .unwrap_or(include_tu.file_name.as_os_str())
+                .to_string_lossy()
+        ),
+    };
+    let lineno = tu.include_files.iter().map(|i| i.loc.lineno + 1).max();
+    let edit = Edit {
+        loc: Location {
+            file_name: tu.file_name.clone(),
+            lineno: lineno.unwrap_or(1),
+            colno: 0,
+        },
+        len: 0,
+        replacement: format!("{}\n", include),
+    };
+    (include, edit)
+}
+
+// A type that isn't declared might be shared by another file that was
+// loaded but that isn't included here, which is reported instead, with
+// the include that is missing.
+fn report_not_included(
+    tus: &TUMap<TranslationUnit>,
+    tuid: &TUId,
+    tu: &TranslationUnit,
+    errors: Errors,
+) -> Errors {
+    if !errors
+        .diagnostics()
+        .iter()
+        .any(|d| d.code == Some("unknown_type"))
+    {
+        return errors;
+    }
+    let shared = tus
+        .iter()
+        .filter(|(other, _)| *other != tuid && !tu.includes.contains(other))
+        .flat_map(|(_, other_tu)| {
+            shared_names(other_tu)
+                .into_iter()
+                .map(move |(name, loc)| (name, loc, other_tu))
+        })
+        .collect::<Vec<_>>();
+    let mut result = Errors::none();
+    for d in errors.into_diagnostics() {
+        let found = match (d.code, &d.symbol) {
+            (Some("unknown_type"), Some(symbol)) => shared
+                .iter()
+                .find(|(name, _, _)| name == symbol.trim_start_matches("::"))
+                .map(|found| (found, symbol)),
+            _ => None,
+        };
+        match (found, &d.loc) {
+            (Some(((_, decl_loc, other_tu), symbol)), Some(loc)) => {
+                let (include, edit) = include_edit(tu, other_tu);
+                result.push(
+                    Diagnostic::from_catalog(
+                        loc,
+                        "not_included",
+                        &[
+                            ("type", symbol),
+                            ("file", &other_tu.file_name.display()),
+                            ("include", &include),
+                        ],
+                    )
+                    .with_note(decl_loc, "declared here")
+                    .with_fix(&format!("add `{}'", include), vec![edit]),
+                );
+            }
+            _ => result.push(d),
+        }
+    }
+    result
+}
+
+fn gather_decls_struct(
+    sym_tab: &mut SymbolTable,
+    tuts: &TUMap<TranslationUnitType>,
+    tuid: &TUId,
+    (ns, attributes, sd): &(Namespace, Attributes, Vec<StructField>),
+    sdef: &mut StructTypeDef,
+    types: &mut TypeTable,
+) -> Errors {
+    let mut errors = Errors::none();
+
+    if sd.is_empty() && !allows_warning(attributes, "empty_struct") {
+        errors.push(Diagnostic::from_catalog(
+            &ns.name.loc,
+            "empty_struct",
+            &[("struct", &ns.qname().short_name())],
+        ));
+    }
+
+    sym_tab.enter_scope();
+
+    for (i, f) in sd.iter().enumerate() {
+        let fty_string = f.type_spec.spec.to_string();
+        let fty_decl = match sym_tab.lookup_type_at(&fty_string, &ns.namespaces, &f.type_spec.loc) {
+            Some(fty_decl) => fty_decl,
+            None => {
+                errors.push(
+                    Diagnostic::from_catalog(
+                        &f.name.loc,
+                        "unknown_type.field",
+                        &[
+                            ("field", &f.name),
+                            ("struct", &ns.qname().short_name()),
+                            ("type", &fty_string),
+                        ],
+                    )
+                    .with_symbol(&fty_string),
+                );
+                continue;
+            }
+        };
+        let (errors2, f_type) = fty_decl.decl_type.canonicalize(&tuts, &f.type_spec);
+        errors.append(errors2);
+
+        // Say which struct a repeated field is in, rather than only that
+        // the name is declared twice.
+        match sd[..i].iter().find(|g| g.name.id == f.name.id) {
+            Some(first) => errors.push(
+                Diagnostic::from_catalog(
+                    &f.name.loc,
+                    "redeclaration.field",
+                    &[("field", &f.name), ("struct", &ns.qname().short_name())],
+                )
+                .with_note(&first.name.loc, "first declared here"),
+            ),
+            None => errors.append(sym_tab.declare(Decl::new(
+                &f.name.loc,
+                f_type.clone(),
+                f.name.id.clone(),
+            ))),
+        }
+        sdef.append_field(types.intern(tuid, f_type));
+    }
+
+    sym_tab.exit_scope();
+
+    errors
+}
+
+fn gather_decls_union(
+    sym_tab: &mut SymbolTable,
+    tuts: &TUMap<TranslationUnitType>,
+    tuid: &TUId,
+    (ns, attributes, ud): &(Namespace, Attributes, Vec<TypeSpec>),
+    udef: &mut UnionTypeDef,
+    types: &mut TypeTable,
+) -> Errors {
+    let mut errors = Errors::none();
+
+    if ud.len() == 1 && !allows_warning(attributes, "single_component_union") {
+        errors.push(Diagnostic::from_catalog(
+            &ns.name.loc,
+            "single_component_union",
+            &[("union", &ns.qname().short_name())],
+        ));
+    }
+    let mut seen = HashMap::new();
+
+    for c in ud {
+        let c_string = c.spec.to_string();
+        let c_decl = match sym_tab.lookup_type_at(&c_string, &ns.namespaces, &c.loc) {
+            Some(c_decl) => c_decl,
+            None => {
+                errors.push(
+                    Diagnostic::from_catalog(
+                        c.loc(),
+                        "unknown_type.component",
+                        &[("type", &c_string), ("union", &ns.qname().short_name())],
+                    )
+                    .with_symbol(&c_string),
+                );
+                continue;
+            }
+        };
+        let (errors2, c_ty) = c_decl.decl_type.canonicalize(&tuts, &c);
+        errors.append(errors2);
+
+        let c_identity = c_ty.identity();
+        if let Some(first) = seen.get(&c_identity) {
+            errors.push(
+                Diagnostic::from_catalog(
+                    c.loc(),
+                    "duplicate_component",
+                    &[("type", &c), ("union", &ns.qname().short_name())],
+                )
+                .with_note(first, "first listed here"),
+            );
+        } else {
+            seen.insert(c_identity, c.loc().clone());
+        }
+
+        udef.append_component(types.intern(tuid, c_ty));
+    }
+
+    errors
+}
+
+fn gather_decls_manager(
+    sym_tab: &mut SymbolTable,
+    managee: &(Namespace, Protocol),
+    managee_type: &mut ProtocolTypeDef,
+    manager: &Identifier,
+) -> Errors {
+    let manager_decl = match sym_tab.lookup_at(&manager.id, &manager.loc) {
+        Some(decl) => decl,
+        None => {
+            return Errors::from_diagnostic(Diagnostic::from_catalog(
+                &manager.loc,
+                "undeclared_protocol.manager",
+                &[
+                    ("manager", &manager.id),
+                    ("protocol", &managee.0.qname().short_name()),
+                ],
+            ))
+        }
+    };
+
+    if let &IPDLType::ProtocolType(ref pt) = &manager_decl.decl_type {
+        managee_type.managers.push(pt.clone());
+        return Errors::none();
+    }
+
+    Errors::from_diagnostic(
+        Diagnostic::from_catalog(
+            &manager.loc,
+            "not_a_protocol.manager",
+            &[
+                ("manager", &manager.id),
+                ("protocol", &managee.0.qname().short_name()),
+                ("kind", &manager_decl.decl_type.type_name()),
+            ],
+        )
+        .with_note(
+            &manager_decl.loc,
+            &format!("`{}' declared here", manager.id),
+        ),
+    )
+}
+
+fn gather_decls_manages(
+    sym_tab: &mut SymbolTable,
+    manager: &(Namespace, Protocol),
+    manager_type: &mut ProtocolTypeDef,
+    managee: &Identifier,
+) -> Errors {
+    let managee_decl = match sym_tab.lookup_at(&managee.id, &managee.loc) {
+        Some(decl) => decl,
+        None => {
+            return Errors::from_diagnostic(Diagnostic::from_catalog(
+                &managee.loc,
+                "undeclared_protocol.managee",
+                &[
+                    ("managee", &managee.id),
+                    ("protocol", &manager.0.qname().short_name()),
+                ],
+            ))
+        }
+    };
+
+    if let &IPDLType::ProtocolType(ref pt) = &managee_decl.decl_type {
+        manager_type.manages.push(pt.clone());
+        return Errors::none();
+    }
+
+    Errors::from_diagnostic(
+        Diagnostic::from_catalog(
+            &managee.loc,
+            "not_a_protocol.managee",
+            &[
+                ("protocol", &manager.0.qname().short_name()),
+                ("managee", &managee.id),
+                ("kind", &managee_decl.decl_type.type_name()),
+            ],
+        )
+        .with_note(
+            &managee_decl.loc,
+            &format!("`{}' declared here", managee.id),
+        ),
+    )
+}
+
+fn gather_decls_message(
+    sym_tab: &mut SymbolTable,
+    tuts: &TUMap<TranslationUnitType>,
+    tuid: &TUId,
+    protocol_type: &mut ProtocolTypeDef,
+    types: &mut TypeTable,
+    md: &MessageDecl,
+) -> Errors {
+    let mut errors = Errors::none();
+    let mut message_name = md.name.id.clone();
+    let mut mtype = MessageType::Other;
+
+    if let Some(ref decl) = sym_tab.lookup(&message_name) {
+        if let IPDLType::ProtocolType(ref pt) = decl.decl_type {
+            // Probably a ctor. We'll check validity later.
+            message_name += CONSTRUCTOR_SUFFIX;
+            mtype = MessageType::Ctor(pt.clone());
+        } else if ctor_declared_as(tuid, protocol_type, &decl.decl_type).is_some() {
+            // This is reported as a collision with the ctor below.
+        } else {
+            errors.push(Diagnostic::from_catalog(
+                &md.name.loc,
+                "redeclaration.message",
+                &[("message", &md.name), ("kind", &decl.decl_type.type_name())],
+            ));
+            // If we error here, no big deal; move on to find more.
+        }
+    }
+
+    if DELETE_MESSAGE_NAME == message_name {
+        mtype = MessageType::Dtor(tuid.clone());
+    }
+
+    sym_tab.enter_scope();
+
+    let message_attributes: AttributeSpec = {
+        let priorities = || {
+            Vec::from([
+                AttributeSpecValue::Keyword("normal"),
+                AttributeSpecValue::Keyword("input"),
+                AttributeSpecValue::Keyword("vsync"),
+                AttributeSpecValue::Keyword("mediumhigh"),
+                AttributeSpecValue::Keyword("control"),
+            ])
+        };
+
+        HashMap::from([
+            ("Tainted", Vec::new()),
+            (
+                "Compress",
+                Vec::from([
+                    AttributeSpecValue::Valueless,
+                    AttributeSpecValue::Keyword("all"),
+                ]),
+            ),
+            ("Priority", priorities()),
+            ("ReplyPriority", priorities()),
+            (
+                "Nested",
+                Vec::from([
+                    AttributeSpecValue::Keyword("not"),
+                    AttributeSpecValue::Keyword("inside_sync"),
+                    AttributeSpecValue::Keyword("inside_cpow"),
+                ]),
+            ),
+            ("LegacyIntr", Vec::new()), // XXX LegacyIntr has been removed.
+            ("LazySend", Vec::new()),
+            ("VirtualSendImpl", Vec::new()),
+            ("AllowShmem", Vec::new()),
+            ("Since", Vec::from([AttributeSpecValue::Integer])),
+            ("NoLogging", Vec::new()),
+            ("MaxSize", Vec::from([AttributeSpecValue::Integer])),
+            ("Deprecated", Vec::from([AttributeSpecValue::StringLiteral])),
+            (
+                "AllowSyncFrom",
+                Vec::from([
+                    AttributeSpecValue::Keyword("Parent"),
+                    AttributeSpecValue::Keyword("Child"),
+                ]),
+            ),
+            (
+                "Allow",
+                MESSAGE_WARNINGS
+                    .iter()
+                    .map(|code| AttributeSpecValue::Keyword(code))
+                    .collect(),
+            ),
+        ])
+    };
+    errors.append(check_attributes(&md.attributes, &message_attributes));
+
+    let mut msg_type = MessageTypeDef::new(&md, &message_name, mtype);
+
+    if DELETE_MESSAGE_NAME == message_name {
+        if !msg_type.is_async() {
+            errors.push(Diagnostic::from_catalog(&md.name.loc, "dtor.async", &[]));
+        }
+        if md.out_params.len() > 0 {
+            errors.push(Diagnostic::from_catalog(&md.name.loc, "dtor.returns", &[]));
+        }
+    }
+
+    // Constructors can't have return values, so only other messages are
+    // checked.
+    if msg_type.is_sync()
+        && md.out_params.is_empty()
+        && !msg_type.is_ctor()
+        && !allows_warning(&md.attributes, "sync_without_returns")
+    {
+        errors.push(Diagnostic::from_catalog(
+            &md.name.loc,
+            "sync_without_returns",
+            &[("message", &message_name)],
+        ));
+    }
+
+    // A sync message that is declared in a `both:' section can block either
+    // side on the other. Unless it is nested, it is also a sync
+    // parent-to-child message, which is an error in check_types_message,
+    // but this says what is actually wrong with it.
+    if msg_type.is_sync()
+        && msg_type.direction == Direction::Both
+        && !allows_warning(&md.attributes, "sync_both_directions")
+    {
+        errors.push(Diagnostic::from_catalog(
+            &md.name.loc,
+            "sync_both_directions",
+            &[("message", &message_name)],
+        ));
+    }
+
+    // An exception to the sync rules has to be for a sync message, and for
+    // a side that actually sends it, so that it can't outlive a change to
+    // the message.
+    if let Some(side) = msg_type.allow_sync_from {
+        let attr_loc = md
+            .attributes
+            .get("AllowSyncFrom")
+            .map_or(&md.name.loc, |a| &a.0);
+        let attr = format!("[AllowSyncFrom={:?}]", side);
+        if !msg_type.is_sync() {
+            errors.push(Diagnostic::from_catalog(
+                attr_loc,
+                "attribute.allow_sync_from",
+                &[("attribute", &attr), ("message", &message_name)],
+            ));
+        } else if !senders(msg_type.direction).contains(&side) {
+            errors.push(Diagnostic::from_catalog(
+                attr_loc,
+                "attribute.allow_sync_from_sender",
+                &[
+                    ("attribute", &attr),
+                    ("message", &message_name),
+                    ("side", &side_name(senders(msg_type.direction)[0])),
+                ],
+            ));
+        }
+    }
+
+    if !msg_type.is_async() && msg_type.lazy_send {
+        errors.push(Diagnostic::from_catalog(
+            &md.name.loc,
+            "attribute.async_only",
+            &[("message", &message_name), ("attribute", &"LazySend")],
+        ));
+    }
+
+    if !msg_type.is_async() && has_attribute(&md.attributes, "ReplyPriority") {
+        errors.push(Diagnostic::from_catalog(
+            &md.name.loc,
+            "attribute.async_only",
+            &[("message", &message_name), ("attribute", &"ReplyPriority")],
+        ));
+    }
+
+    if md.out_params.len() == 0 && has_attribute(&md.attributes, "ReplyPriority") {
+        errors.push(Diagnostic::from_catalog(
+            &md.name.loc,
+            "attribute.reply_priority",
+            &[("message", &message_name)],
+        ));
+    }
+
+    // An intr message is handled while the sender waits for it, so there
+    // is no queue for a priority to reorder it in.
+    if msg_type.send_semantics.is_intr()
+        && (has_attribute(&md.attributes, "Priority")
+            || has_attribute(&md.attributes, "ReplyPriority"))
+    {
+        errors.push(Diagnostic::from_catalog(
+            &md.name.loc,
+            "attribute.intr_priority",
+            &[("message", &message_name)],
+        ));
+    }
+
+    if msg_type.max_size == Some(0) {
+        let loc = md.attributes.get("MaxSize").map_or(&md.name.loc, |a| &a.0);
+        errors.push(Diagnostic::from_catalog(loc, "attribute.max_size", &[]));
+    }
+
+    // Only the names of messages are logged without their data.
+    if msg_type.no_logging && md.in_params.is_empty() && md.out_params.is_empty() {
+        errors.push(Diagnostic::from_catalog(
+            &md.name.loc,
+            "attribute.no_logging",
+            &[("message", &message_name)],
+        ));
+    }
+
+    if !msg_type.nested.is_none() && msg_type.prio != Priority::Normal {
+        errors.push(Diagnostic::from_catalog(
+            &md.name.loc,
+            "attribute.nested_priority",
+            &[("message", &message_name)],
+        ));
+    }
+
+    {
+        // The Python version adds the parameter, just with a dummy
+        // type. Here I choose to be consistent with how we handle struct
+        // fields with invalid types and simply omit the parameter.
+        let mut param_to_decl = |param: &Param| {
+            let param_attributes: AttributeSpec = HashMap::from([(
+                "NoTaint",
+                Vec::from([
+                    AttributeSpecValue::Keyword("passback"),
+                    AttributeSpecValue::Keyword("allvalid"),
+                ]),
+            )]);
+            errors.append(check_attributes(&param.attributes, &param_attributes));
+
+            let pt_name = param.type_spec.spec.to_string();
+
+            if param.attributes.contains_key("NoTaint") && !md.attributes.contains_key("Tainted") {
+                errors.push(Diagnostic::from_catalog(
+                    param.type_spec.loc(),
+                    "attribute.no_taint",
+                    &[("type", &pt_name), ("message", &message_name)],
+                ));
+            }
+
+            match sym_tab.lookup_type_at(
+                &pt_name,
+                &protocol_type.qname.quals,
+                param.type_spec.loc(),
+            ) {
+                Some(p_type) => {
+                    let (errors2, t) = p_type.decl_type.canonicalize(&tuts, &param.type_spec);
+                    errors.append(errors2);
+                    // Say which message a repeated name is in, and whether
+                    // it is a parameter or a return value.
+                    let is_return = md.out_params.iter().any(|p| std::ptr::eq(p, param));
+                    let first = md
+                        .in_params
+                        .iter()
+                        .map(|p| (p, false))
+                        .chain(md.out_params.iter().map(|p| (p, true)))
+                        .take_while(|(p, _)| !std::ptr::eq(*p, param))
+                        .find(|(p, _)| p.name.id == param.name.id);
+                    match first {
+                        Some((first, first_is_return)) => {
+                            let what = match (first_is_return, is_return) {
+                                (false, false) => "two parameters",
+                                (true, true) => "two return values",
+                                _ => "a parameter and a return value",
+                            };
+                            errors.push(
+                                Diagnostic::from_catalog(
+                                    &param.name.loc,
+                                    "redeclaration.param",
+                                    &[
+                                        ("param", &param.name),
+                                        ("message", &message_name),
+                                        ("what", &what),
+                                    ],
+                                )
+                                .with_note(&first.name.loc, "first declared here"),
+                            );
+                        }
+                        None => {
+                            let decl =
+                                Decl::new(param.type_spec.loc(), t.clone(), param.name.id.clone());
+                            errors.append(sym_tab.declare(decl));
+                        }
+                    }
+                    Some(ParamTypeDef {
+                        name: param.name.clone(),
+                        param_type: types.intern(tuid, t),
+                    })
+                }
+                None => {
+                    errors.push(
+                        Diagnostic::from_catalog(
+                            param.type_spec.loc(),
+                            "unknown_type.param",
+                            &[("type", &pt_name), ("message", &message_name)],
+                        )
+                        .with_symbol(&pt_name),
+                    );
+                    None
+                }
+            }
+        };
+
+        for in_param in &md.in_params {
+            if let Some(t) = param_to_decl(&in_param) {
+                msg_type.params.push(t);
+            }
+        }
+
+        for out_param in &md.out_params {
+            if let Some(t) = param_to_decl(&out_param) {
+                msg_type.returns.push(t);
+            }
+        }
+    }
+
+    sym_tab.exit_scope();
+
+    // A ctor is declared with a `Constructor' suffix, so it can collide
+    // with a message that was given that name explicitly.
+    let collision = sym_tab.lookup(&message_name).and_then(|old| {
+        let old_ctor = ctor_declared_as(tuid, protocol_type, &old.decl_type);
+        match (&old_ctor, msg_type.is_ctor()) {
+            (None, false) => None,
+            _ => Some((old.loc.clone(), old_ctor)),
+        }
+    });
+    if let Some((old_loc, old_ctor)) = &collision {
+        let diagnostic = match old_ctor {
+            Some(ctor_name) => Diagnostic::from_catalog(
+                &md.name.loc,
+                "redeclaration.ctor",
+                &[("message", &message_name), ("ctor", ctor_name)],
+            )
+            .with_note(old_loc, "the ctor message is declared here"),
+            None => Diagnostic::from_catalog(
+                &md.name.loc,
+                "redeclaration.ctor_name",
+                &[("ctor", &md.name.id), ("message", &message_name)],
+            )
+            .with_note(old_loc, "the other message is declared here"),
+        };
+        errors.push(diagnostic);
+    }
+
+    let index = protocol_type.messages.len();
+    protocol_type.messages.push(msg_type);
+
+    if collision.is_none() {
+        let mt = IPDLType::MessageType(TypeRef::new(tuid, index));
+        errors.append(sym_tab.declare(Decl::new(&md.name.loc, mt, message_name)));
+    }
+
+    errors
+}
+
+// If |t| is a ctor message of the protocol, the name it was declared with,
+// without the `Constructor' suffix.
+fn ctor_declared_as(tuid: &TUId, protocol_type: &ProtocolTypeDef, t: &IPDLType) -> Option<String> {
+    match t {
+        IPDLType::MessageType(tr) if tr.tu == *tuid => protocol_type
+            .messages
+            .get(tr.index)
+            .filter(|m| m.is_ctor())
+            .map(|m| m.name.id.trim_end_matches(CONSTRUCTOR_SUFFIX).to_string()),
+        _ => None,
+    }
+}
+
+// A C++ identifier that is generated for a protocol.
+struct GeneratedName {
+    name: String,
+    what: &'static str,
+    // Whether the name is declared in the namespace of the protocol, rather
+    // than inside the actor classes.
+    in_namespace: bool,
+}
+
+fn generated_cxx_names(
+    tuts: &TUMap<TranslationUnitType>,
+    p_type: &ProtocolTypeDef,
+) -> Vec<GeneratedName> {
+    let mut names = Vec::new();
+    let mut add = |name: String, what, in_namespace| {
+        names.push(GeneratedName {
+            name,
+            what,
+            in_namespace,
+        })
+    };
+
+    let pname = p_type.qname.short_name();
+    for side in &["Parent", "Child"] {
+        add(format!("{}{}", pname, side), "actor class", true);
+    }
+    for managee in &p_type.manages {
+        // A managee that isn't a protocol is reported by check_types_protocol.
+        let managee_name = match get_protocol_type(tuts, managee) {
+            Some(managee_type) => managee_type.qname.short_name(),
+            None => continue,
+        };
+        for side in &["Parent", "Child"] {
+            add(format!("Alloc{}{}", managee_name, side), "method", false);
+            add(format!("Dealloc{}{}", managee_name, side), "method", false);
+        }
+    }
+    for m in &p_type.messages {
+        let cxx_names = m.cxx_names();
+        add(cxx_names.send, "method", false);
+        add(cxx_names.recv, "method", false);
+        for t in cxx_names.promise.into_iter().chain(cxx_names.resolver) {
+            add(t, "type", false);
+        }
+        for id in m.message_ids() {
+            add(id, "message ID", false);
+        }
+    }
+    names
+}
+
+// Check that no type or protocol visible in the protocol has the same name
+// as a C++ identifier generated for the protocol. Messages are not checked,
+// because message names never appear in C++ without a prefix.
+fn check_generated_cxx_names(
+    sym_tab: &SymbolTable,
+    tuts: &TUMap<TranslationUnitType>,
+    p: &(Namespace, Protocol),
+    p_type: &ProtocolTypeDef,
+) -> Errors {
+    let mut errors = Errors::none();
+    let namespace = p_type.qname.to_string();
+    let namespace = &namespace[..namespace.len() - p_type.qname.short_name().len()];
+
+    for g in generated_cxx_names(tuts, p_type) {
+        let decl = match sym_tab.lookup(&g.name) {
+            Some(decl) => decl,
+            None => continue,
+        };
+        if let IPDLType::MessageType(_) = decl.decl_type {
+            continue;
+        }
+        if g.in_namespace && decl.full_name != Some(format!("{}{}", namespace, g.name)) {
+            continue;
+        }
+        errors.push(
+            Diagnostic::from_catalog(
+                &decl.loc,
+                "generated_name_clash",
+                &[
+                    ("name", &g.name),
+                    ("what", &g.what),
+                    ("protocol", &p.0.qname().short_name()),
+                ],
+            )
+            .with_note(&p.0.name.loc, "protocol declared here"),
+        );
+    }
+
+    errors
+}
+
+// Nesting is how the sender of a sync message lets some messages through
+// while it waits for the reply. The sender of an intr message handles
+// incoming intr messages while it waits anyway, so intr protocols can't be
+// nested. This is reported here, at the protocol, before it shows up as
+// messages and managees that need more powerful send semantics.
+fn check_intr_nesting(p: &(Namespace, Protocol)) -> Errors {
+    let mut errors = Errors::none();
+    if !p.1.send_semantics.is_intr() {
+        return errors;
+    }
+    let loc = match p.1.attributes.get("NestedUpTo") {
+        Some((loc, _)) => loc,
+        None => return errors,
+    };
+    let name = p.0.qname().short_name();
+    let explanation = "intr messages are handled while their sender waits, so they are \
+                       never nested inside other messages";
+    if get_nested(&p.1.attributes, "NestedUpTo").is_none() {
+        errors.push(
+            Diagnostic::from_catalog(loc, "intr_nesting", &[("protocol", &name)])
+                .with_note(&p.0.name.loc, explanation),
+        );
+    } else {
+        errors.push(
+            Diagnostic::from_catalog(loc, "attribute.intr_nested_up_to", &[("protocol", &name)])
+                .with_note(&p.0.name.loc, explanation),
+        );
+    }
+    errors
+}
+
+// By convention, the managers of a protocol are listed in alphabetical
+// order, so that the same set of managers reads the same everywhere. The
+// fix-it swaps the names around in place.
+fn check_manager_order(p: &(Namespace, Protocol)) -> Errors {
+    let mut errors = Errors::none();
+    let managers = &p.1.managers;
+    if managers.windows(2).all(|w| w[0].id <= w[1].id)
+        || allows_warning(&p.1.attributes, "manager_order")
+    {
+        return errors;
+    }
+    let mut sorted = managers.iter().map(|m| m.id.as_str()).collect::<Vec<_>>();
+    sorted.sort();
+    let sorted_list = format!("manager {}", sorted.join(" or "));
+    let edits = managers
+        .iter()
+        .zip(&sorted)
+        .filter(|(m, s)| m.id != **s)
+        .map(|(m, s)| Edit {
+            loc: m.loc.clone(),
+            len: m.id.len(),
+            replacement: String::from(*s),
+        })
+        .collect();
+    errors.push(
+        Diagnostic::from_catalog(
+            &managers[0].loc,
+            "manager_order",
+            &[("protocol", &p.0.qname().short_name())],
+        )
+        .with_fix(&format!("list them as `{}'", sorted_list), edits),
+    );
+    errors
+}
+
+// Managing, being managed by or constructing a `[Deprecated]' protocol is
+// a new use of it, unless the protocol that does it is deprecated too.
+fn check_deprecated_protocols(
+    sym_tab: &SymbolTable,
+    tuts: &TUMap<TranslationUnitType>,
+    tuid: &TUId,
+    p: &(Namespace, Protocol),
+    p_type: &ProtocolTypeDef,
+) -> Errors {
+    let mut errors = Errors::none();
+    if p_type.deprecated.is_some() {
+        return errors;
+    }
+    let mut warn = |loc: &Location, used: &TUId| {
+        if used == tuid {
+            return;
+        }
+        let used = match get_protocol_type(tuts, used) {
+            Some(used) => used,
+            None => return,
+        };
+        if let Some(hint) = &used.deprecated {
+            errors.push(Diagnostic::from_catalog(
+                loc,
+                "deprecated",
+                &[("protocol", &used.qname.short_name()), ("hint", hint)],
+            ));
+        }
+    };
+    for id in p.1.managers.iter().chain(&p.1.manages) {
+        if let Some(decl) = sym_tab.lookup_at(&id.id, &id.loc) {
+            if let IPDLType::ProtocolType(used) = &decl.decl_type {
+                warn(&id.loc, used);
+            }
+        }
+    }
+    for md in &p_type.messages {
+        if let MessageType::Ctor(used) = &md.mtype {
+            warn(&md.name.loc, used);
+        }
+    }
+    errors
+}
+
+// Versions start at 1, and a message without `[Since]' has been there since
+// the first version. New messages go after the old ones, so the versions
+// of the messages must never go down.
+fn check_message_versions(p: &(Namespace, Protocol), p_type: &ProtocolTypeDef) -> Errors {
+    let mut errors = Errors::none();
+    let protocol_name = p.0.qname().short_name();
+
+    if p_type.version == Some(0) {
+        let loc =
+            p.1.attributes
+                .get("ProtocolVersion")
+                .map_or(&p.0.name.loc, |a| &a.0);
+        errors.push(Diagnostic::from_catalog(loc, "version.protocol", &[]));
+    }
+
+    let mut latest: Option<(u32, &Identifier)> = None;
+    for md in &p.1.messages {
+        let since = get_integer(&md.attributes, "Since");
+        if let Some(since) = since {
+            let loc = md.attributes.get("Since").map_or(&md.name.loc, |a| &a.0);
+            match p_type.version {
+                None => errors.push(Diagnostic::from_catalog(
+                    loc,
+                    "version.unversioned_protocol",
+                    &[("message", &md.name), ("protocol", &protocol_name)],
+                )),
+                Some(version) if since > version => errors.push(Diagnostic::from_catalog(
+                    loc,
+                    "version.too_late",
+                    &[
+                        ("message", &md.name),
+                        ("since", &since),
+                        ("version", &version),
+                        ("protocol", &protocol_name),
+                    ],
+                )),
+                _ => (),
+            }
+            if since == 0 {
+                errors.push(Diagnostic::from_catalog(loc, "version.since", &[]));
+            }
+        }
+
+        let since = since.unwrap_or(1);
+        match latest {
+            Some((version, name)) if since < version => errors.push(
+                Diagnostic::from_catalog(
+                    &md.name.loc,
+                    "version.order",
+                    &[
+                        ("message", &md.name),
+                        ("since", &since),
+                        ("version", &version),
+                    ],
+                )
+                .with_note(&name.loc, &format!("`{}' is [Since={}]", name, version)),
+            ),
+            Some((version, _)) if since == version => (),
+            _ => latest = Some((since, &md.name)),
+        }
+    }
+
+    errors
+}
+
+fn gather_decls_protocol(
+    mut sym_tab: &mut SymbolTable,
+    tuts: &TUMap<TranslationUnitType>,
+    tuid: &TUId,
+    p: &(Namespace, Protocol),
+    mut p_type: &mut ProtocolTypeDef,
+    types: &mut TypeTable,
+    symbols: &mut Vec<Symbol>,
+) -> Errors {
+    let mut errors = Errors::none();
+
+    sym_tab.enter_scope();
+
+    {
+        let mut seen_managers = HashSet::new();
+        for manager in &p.1.managers {
+            if seen_managers.contains(&manager.id) {
+                errors.push(Diagnostic::from_catalog(
+                    &manager.loc,
+                    "redeclaration.manager",
+                    &[("manager", &manager.id)],
+                ));
+                continue;
+            }
+
+            seen_managers.insert(manager.id.clone());
+
+            errors.append(gather_decls_manager(
+                &mut sym_tab,
+                &p,
+                &mut p_type,
+                &manager,
+            ));
+        }
+    }
+
+    for managee in &p.1.manages {
+        errors.append(gather_decls_manages(
+            &mut sym_tab,
+            &p,
+            &mut p_type,
+            &managee,
+        ));
+    }
+
+    for md in &p.1.messages {
+        errors.append(gather_decls_message(
+            &mut sym_tab,
+            &tuts,
+            &tuid,
+            &mut p_type,
+            types,
+            &md,
+        ));
+    }
+
+    errors.append(check_message_versions(p, p_type));
+    errors.append(check_intr_nesting(p));
+    errors.append(check_manager_order(p));
+    errors.append(check_deprecated_protocols(sym_tab, tuts, tuid, p, p_type));
+
+    let delete_type = sym_tab.lookup(DELETE_MESSAGE_NAME);
+    p_type.has_delete = delete_type.is_some();
+    if !(p_type.has_delete || p_type.is_top_level()) {
+        errors.push(Diagnostic::from_catalog(
+            &p.0.name.loc,
+            "dtor.missing",
+            &[
+                ("dtor", &DELETE_MESSAGE_NAME),
+                ("protocol", &p.0.qname().short_name()),
+            ],
+        ));
+    }
+
+    if !p_type.is_top_level() && p_type.needs_other_pid {
+        errors.push(Diagnostic::from_catalog(
+            &p.0.name.loc,
+            "attribute.needs_other_pid",
+            &[],
+        ));
+    }
+
+    if p_type.is_top_level() {
+        if p_type.lifetime == Lifetime::ManualDealloc {
+            errors.push(Diagnostic::from_catalog(
+                &p.0.name.loc,
+                "attribute.manual_dealloc_toplevel",
+                &[],
+            ));
+        }
+
+        if p_type.proc_child.is_none() {
+            errors.push(Diagnostic::from_catalog(
+                &p.0.name.loc,
+                "attribute.missing_child_proc",
+                &[],
+            ));
+        }
+    }
+
+    if p_type.manages.len() > 0 && p_type.lifetime == Lifetime::ManualDealloc {
+        errors.push(Diagnostic::from_catalog(
+            &p.0.name.loc,
+            "attribute.manual_dealloc_manager",
+            &[],
+        ));
+    }
+
+    // Check all the little C++ thingies that will be generated. They're
+    // not relevant to IPDL itself, but those ("invisible") symbols can
+    // clash with others in the IPDL spec, and we'd like to catch those
+    // before C++ compilers are allowed to obfuscate the error.
+    errors.append(check_generated_cxx_names(sym_tab, tuts, p, p_type));
+
+    *symbols = sym_tab.symbols();
+    sym_tab.exit_scope();
+
+    errors
+}
+
+// The template arguments of imported C++ types have to be types that are
+// known to IPDL, so that they can be serialized.
+fn check_template_args(sym_tab: &SymbolTable, tu: &TranslationUnit) -> Errors {
+    fn check_args(sym_tab: &SymbolTable, args: &[TypeSpec], instance: &str, errors: &mut Errors) {
+        for arg in args {
+            if arg.template_args.is_empty()
+                && sym_tab.lookup(&arg.spec).is_none()
+                && sym_tab.lookup(&format!("::{}", arg.spec)).is_none()
+            {
+                errors.push(
+                    Diagnostic::from_catalog(
+                        arg.loc(),
+                        "unknown_type.template_arg",
+                        &[("type", &arg.spec), ("instance", &instance)],
+                    )
+                    .with_symbol(&arg.spec),
+                );
+            }
+            check_args(sym_tab, &arg.template_args, instance, errors);
+        }
+    }
+
+    let mut errors = Errors::none();
+    for u in &tu.using {
+        check_args(
+            sym_tab,
+            &u.template_args,
+            &u.cxx_type.short_name(),
+            &mut errors,
+        );
+    }
+    errors
+}
+
+fn check_cxx_includes(tu: &TranslationUnit) -> Errors {
+    let mut errors = Errors::none();
+    let mut seen: HashMap<&str, &Location> = HashMap::new();
+    for i in &tu.cxx_includes {
+        if let Some(first) = seen.insert(&i.id, &i.loc) {
+            errors.push(
+                Diagnostic::from_catalog(&i.loc, "duplicate_cxx_include", &[("header", &i.id)])
+                    .with_note(first, "first included here"),
+            );
+        }
+    }
+    errors
+}
+
+// Declarations from included headers can conflict with each other, so for
+// each other file that a diagnostic points into, add a note with the
+// include statement that brought that file in.
+fn add_include_notes(tus: &TUMap<TranslationUnit>, tu: &TranslationUnit, errors: Errors) -> Errors {
+    let mut result = Errors::none();
+    for mut d in errors.into_diagnostics() {
+        let files = d
+            .loc
+            .iter()
+            .chain(d.notes.iter().map(|n| &n.loc))
+            .map(|loc| loc.file_name.clone())
+            .collect::<Vec<_>>();
+        let mut noted = HashSet::new();
+        for file in files {
+            if file == tu.file_name || !noted.insert(file.clone()) {
+                continue;
+            }
+            let include = tu
+                .includes
+                .iter()
+                .zip(tu.include_files.iter())
+                .find(|(include_tuid, _)| tus[include_tuid].file_name == file);
+            if let Some((_, name)) = include {
+                d = d.with_note(&name.loc, &format!("`{}' is included here", file.display()));
+            }
+        }
+        result.push(d);
+    }
+    result
+}
+
+fn declare_builtins(sym_tab: &mut SymbolTable, aliases: &HashMap<String, String>) -> Errors {
+    let mut errors = Errors::none();
+
+    // Declare builtin C types.
+    let builtin = Location::builtin();
+    for &t in BUILTIN_C_TYPES {
+        errors.append(sym_tab.declare(Decl::new(
+            &builtin,
+            IPDLType::BuiltinCType(t),
+            t.to_owned(),
+        )));
+    }
+
+    // Declare builtin C++ types.
+    for t in BUILTIN_TYPES {
+        let cxx_type = builtin_from_string(t);
+        errors.append(declare_cxx_type(
+            sym_tab,
+            &cxx_type,
+            aliases,
+            Lifetime::ManualDealloc, /* refcounted */
+            false,                   /* send moveonly */
+            false,                   /* data moveonly */
+        ));
+    }
+
+    errors
+}
+
+fn gather_decls_tu(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &mut TUMap<TranslationUnitType>,
+    tuid: &TUId,
+    tu: &TranslationUnit,
+    aliases: &HashMap<String, String>,
+    track_references: bool,
+) -> Errors {
+    let mut errors = check_cxx_includes(tu);
+    let mut sym_tab = SymbolTable::new(track_references);
+
+    if let &Some(ref p) = &tu.protocol {
+        errors.append(declare_protocol(&mut sym_tab, &tuid, &p.0, &p.1));
+    }
+
+    // Add the declarations from all the IPDL files we include.
+    for include_tuid in &tu.includes {
+        let include_tu = match tus.get(include_tuid) {
+            Some(include_tu) => include_tu,
+            None => {
+                errors.push(Diagnostic::internal(
+                    &tu.namespace.name.loc,
+                    &format!("included translation unit {} was not loaded", include_tuid),
+                ));
+                continue;
+            }
+        };
+        if let Some(ref p) = include_tu.protocol {
+            errors.append(declare_protocol(&mut sym_tab, include_tuid, &p.0, &p.1));
+        }
+        // A header's globals are all "exported" into our scope, unless it
+        // marks some with an `[Export]' attribute, but a protocol's are only
+        // if they have one.
+        let exported_only = exports_only_marked(include_tu);
+        errors.append(add_include_notes(
+            tus,
+            tu,
+            declare_usings(&mut sym_tab, include_tu, aliases, exported_only),
+        ));
+        errors.append(add_include_notes(
+            tus,
+            tu,
+            declare_structs_and_unions(&mut sym_tab, include_tuid, include_tu, exported_only),
+        ));
+    }
+
+    errors.append(declare_builtins(&mut sym_tab, aliases));
+
+    // Declare imported C++ types.
+    errors.append(declare_usings(&mut sym_tab, tu, aliases, false));
+    errors.append(check_redundant_usings(tu, aliases));
+
+    // Get a copy of the translation unit type so that we can still
+    // use |tuts| to look up things for error messages. An alternative
+    // would be to extract some kind of mapping from tuids to the name
+    // of protocols, structs and unions and use that.
+    let mut tut = match tuts.get(tuid) {
+        Some(tut) => tut.clone(),
+        None => {
+            errors.push(Diagnostic::internal(
+                &tu.namespace.name.loc,
+                &format!("translation unit {} was not declared", tuid),
+            ));
+            return errors;
+        }
+    };
+
+    let struct_union_attributes = |warning| -> AttributeSpec {
+        HashMap::from([
+            ("Comparable", Vec::new()),
+            ("Allow", vec![AttributeSpecValue::Keyword(warning)]),
+            (EXPORT, Vec::new()),
+        ])
+    };
+
+    // Create stubs for top level struct and union decls.
+    for s in &tu.structs {
+        let mut struct_attributes = struct_union_attributes("empty_struct");
+        struct_attributes.insert(PLAIN_OLD_DATA, Vec::new());
+        errors.append(check_attributes(&s.1, &struct_attributes));
+        tut.structs
+            .push(StructTypeDef::new(&s.0, s.1.contains_key("Comparable")));
+    }
+    for u in &tu.unions {
+        errors.append(check_attributes(
+            &u.1,
+            &struct_union_attributes("single_component_union"),
+        ));
+        tut.unions
+            .push(UnionTypeDef::new(&u.0, u.1.contains_key("Comparable")));
+    }
+
+    // Forward declare all structs and unions in order to support
+    // recursive definitions.
+    errors.append(declare_structs_and_unions(&mut sym_tab, tuid, tu, false));
+    errors.append(check_template_args(&sym_tab, tu));
+
+    // Check definitions of structs and unions.
+    // XXX It might be cleaner to do a zip iteration over {tu,tut}.structs
+    let mut index = 0;
+    for su in &tu.structs {
+        errors.append(gather_decls_struct(
+            &mut sym_tab,
+            &tuts,
+            tuid,
+            &su,
+            &mut tut.structs[index],
+            &mut tut.types,
+        ));
+        index += 1;
+    }
+    index = 0;
+    for u in &tu.unions {
+        errors.append(gather_decls_union(
+            &mut sym_tab,
+            &tuts,
+            tuid,
+            &u,
+            &mut tut.unions[index],
+            &mut tut.types,
+        ));
+        index += 1;
+    }
+
+    // The Python version type checks every struct and union included
+    // from an ipdlh file here, but I don't think that makes any
+    // sense.
+
+    if let &Some(ref p) = &tu.protocol {
+        match tut.protocol.as_mut() {
+            Some(ptype) => errors.append(gather_decls_protocol(
+                &mut sym_tab,
+                &tuts,
+                &tuid,
+                &p,
+                ptype,
+                &mut tut.types,
+                &mut tut.symbols,
+            )),
+            None => errors.push(Diagnostic::internal(
+                &p.0.name.loc,
+                &format!("protocol `{}' was not declared", p.0.name),
+            )),
+        }
+    } else {
+        tut.symbols = sym_tab.symbols();
+    }
+
+    // Now that we've updated |tut|, replace it in |tuts|.
+    tuts.insert(tuid.clone(), tut);
+
+    report_unexported(tus, tu, errors)
+}
+
+enum FullyDefinedState {
+    Visiting,
+    Defined(bool),
+}
+
+// Struct and union TypeRefs index different tables, so they need this to
+// tell them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompoundType {
+    Struct,
+    Union,
+}
+
+/* The rules for "full definition" of a type are
+    defined(atom)             := true
+    defined(array basetype)   := defined(basetype)
+    defined(struct f1 f2...)  := defined(f1) and defined(f2) and ...
+    defined(union c1 c2 ...)  := defined(c1) or defined(c2) or ...
+*/
+fn fully_defined(
+    tuts: &TUMap<TranslationUnitType>,
+    mut defined: &mut HashMap<(CompoundType, TypeRef), FullyDefinedState>,
+    t: &IPDLType,
+) -> bool {
+    let key = match t {
+        &IPDLType::StructType(ref tr) => (CompoundType::Struct, tr.clone()),
+        &IPDLType::UnionType(ref tr) => (CompoundType::Union, tr.clone()),
+        &IPDLType::ArrayType(ref t_inner) => return fully_defined(&tuts, &mut defined, &t_inner),
+        &IPDLType::MaybeType(ref t_inner) => return fully_defined(&tuts, &mut defined, &t_inner),
+        &IPDLType::UniquePtrType(ref t_inner) => {
+            return fully_defined(&tuts, &mut defined, &t_inner)
+        }
+        &IPDLType::NotNullType(ref t_inner) => return fully_defined(&tuts, &mut defined, &t_inner),
+
+        &IPDLType::BuiltinCType(_) => return true,
+        &IPDLType::ImportedCxxType(_, _, _, _) => return true,
+        &IPDLType::MessageType(_) => return true,
+        &IPDLType::ProtocolType(_) => return true,
+        &IPDLType::ActorType(_) => return true,
+        &IPDLType::ShmemType(_) => return true,
+        &IPDLType::ByteBufType(_) => return true,
+        &IPDLType::FDType(_) => return true,
+        &IPDLType::EndpointType(_) => return true,
+        &IPDLType::ManagedEndpointType(_, _) => return true,
+    };
+
+    // The Python version would repeatedly visit a type that was found
+    // to be not defined. I think that's unnecessary. Not doing it
+    // might save some time in the case of an error.
+
+    if let Some(state) = defined.get(&key) {
+        return match state {
+            &FullyDefinedState::Visiting => false,
+            &FullyDefinedState::Defined(is_defined) => is_defined,
+        };
+    }
+
+    defined.insert(key.clone(), FullyDefinedState::Visiting);
+
+    let mut is_defined;
+    match key.0 {
+        CompoundType::Struct => {
+            is_defined = true;
+            for f in key.1.lookup_struct(&tuts).field_types(tuts) {
+                if !fully_defined(&tuts, &mut defined, f) {
+                    is_defined = false;
+                    break;
+                }
+            }
+        }
+        CompoundType::Union => {
+            is_defined = false;
+            for f in key.1.lookup_union(&tuts).component_types(tuts) {
+                if fully_defined(&tuts, &mut defined, f) {
+                    is_defined = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    // XXX Don't need to insert here. get_mut should work.
+    defined.insert(key, FullyDefinedState::Defined(is_defined));
+
+    return is_defined;
+}
+
+// The struct or union that a type is, under any arrays and the like.
+fn compound_key(t: &IPDLType) -> Option<(CompoundType, TypeRef)> {
+    match t {
+        IPDLType::StructType(tr) => Some((CompoundType::Struct, tr.clone())),
+        IPDLType::UnionType(tr) => Some((CompoundType::Union, tr.clone())),
+        IPDLType::ArrayType(inner)
+        | IPDLType::MaybeType(inner)
+        | IPDLType::UniquePtrType(inner)
+        | IPDLType::NotNullType(inner) => compound_key(inner),
+        _ => None,
+    }
+}
+
+fn describe_compound(tuts: &TUMap<TranslationUnitType>, key: &(CompoundType, TypeRef)) -> String {
+    match key.0 {
+        CompoundType::Struct => format!("struct {}", key.1.lookup_struct(tuts).qname.short_name()),
+        CompoundType::Union => format!("union {}", key.1.lookup_union(tuts).qname.short_name()),
+    }
+}
+
+// Why a struct or union that isn't fully defined isn't: the chain of
+// fields and components, each of which is only partially defined, that
+// leads from it back to a type that is already on the chain, like
+// `struct A → field b: struct B → field a: struct A' or `union A →
+// component B: union B → component A: union A'. The location is the
+// field or component that closes the cycle.
+fn undefined_chain(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+    defined: &mut HashMap<(CompoundType, TypeRef), FullyDefinedState>,
+    root: (CompoundType, TypeRef),
+) -> Option<(String, Location)> {
+    let mut chain = vec![describe_compound(tuts, &root)];
+    let mut on_chain = vec![root];
+    loop {
+        let (kind, tr) = on_chain.last()?.clone();
+        let (step, key, loc) = match kind {
+            CompoundType::Struct => {
+                let fields = &tus.get(&tr.tu)?.structs.get(tr.index)?.2;
+                let (f, t) = fields
+                    .iter()
+                    .zip(tr.lookup_struct(tuts).field_types(tuts))
+                    .find(|(_, t)| !fully_defined(tuts, defined, t))?;
+                let key = compound_key(t)?;
+                let step = format!("field {}: {}", f.name.id, describe_compound(tuts, &key));
+                (step, key, f.name.loc.clone())
+            }
+            CompoundType::Union => {
+                let components = &tus.get(&tr.tu)?.unions.get(tr.index)?.2;
+                let (c, t) = components
+                    .iter()
+                    .zip(tr.lookup_union(tuts).component_types(tuts))
+                    .find(|(_, t)| !fully_defined(tuts, defined, t))?;
+                let key = compound_key(t)?;
+                let step = format!("component {}: {}", c, describe_compound(tuts, &key));
+                (step, key, c.loc.clone())
+            }
+        };
+        chain.push(step);
+        if on_chain.contains(&key) {
+            return Some((chain.join(" → "), loc));
+        }
+        on_chain.push(key);
+    }
+}
+
+enum ManagerCycleState {
+    Visiting,
+    Acyclic,
+}
+
+fn protocol_managers_cycles(
+    tuts: &TUMap<TranslationUnitType>,
+    mut visited: &mut TUMap<ManagerCycleState>,
+    mut stack: &mut Vec<TUId>,
+    tuid: &TUId,
+) -> Vec<String> {
+    if let Some(state) = visited.get(tuid) {
+        return match state {
+            &ManagerCycleState::Visiting => {
+                let cycle_names: Vec<String> = stack
+                    .iter()
+                    .chain([tuid.clone()].iter())
+                    .map(|p| {
+                        get_protocol_type(&tuts, &p)
+                            .map_or_else(|| String::from("???"), |pt| pt.qname.short_name())
+                    })
+                    .collect::<Vec<String>>();
+                vec![format!("`{}'", cycle_names.join(" -> "))]
+            }
+            &ManagerCycleState::Acyclic => Vec::new(),
+        };
+    }
+
+    // A managee that isn't a protocol is reported by check_types_protocol.
+    let pt = match get_protocol_type(&tuts, &tuid) {
+        Some(pt) => pt,
+        None => return Vec::new(),
+    };
+
+    let mut cycles = Vec::new();
+
+    visited.insert(tuid.clone(), ManagerCycleState::Visiting);
+
+    stack.push(tuid.clone());
+
+    for managee in &pt.manages {
+        // Self-managed protocols are allowed, except at the top level.
+        // The top level case is checked in protocol_managers_cycles.
+        if managee == tuid {
+            continue;
+        }
+        // A protocol that is both a manager and a managee of this one is
+        // reported on its own in check_types_protocol.
+        if pt.managers.contains(managee) {
+            continue;
+        }
+
+        cycles.append(&mut protocol_managers_cycles(
+            &tuts,
+            &mut visited,
+            &mut stack,
+            &managee,
+        ));
+    }
+
+    stack.pop();
+
+    visited.insert(tuid.clone(), ManagerCycleState::Acyclic);
+
+    cycles
+}
+
+fn protocols_managers_acyclic(tuts: &TUMap<TranslationUnitType>) -> Errors {
+    let mut errors = Errors::none();
+
+    for (tuid, tut) in tuts {
+        let pt = match &tut.protocol {
+            Some(pt) => pt,
+            None => continue,
+        };
+
+        // To match the behavior of the Python IPDL compiler in error cases, reset the
+        // visited stack after each protocol.
+        let mut visited = TUMap::new();
+        let mut stack = Vec::new();
+
+        let cycles = protocol_managers_cycles(&tuts, &mut visited, &mut stack, &tuid);
+        if cycles.len() > 0 {
+            errors.push(Diagnostic::from_catalog(
+                pt.qname.loc(),
+                "manager_cycle",
+                &[("cycles", &cycles.join(", "))],
+            ));
+        }
+
+        if pt.managers.len() == 1 && &pt.managers[0] == tuid {
+            errors.push(Diagnostic::from_catalog(
+                pt.qname.loc(),
+                "manager_cycle.self",
+                &[("protocol", &pt.qname.short_name())],
+            ));
+        }
+    }
+    errors
+}
+
+fn check_types_message(
+    tuts: &TUMap<TranslationUnitType>,
+    ptype: &ProtocolTypeDef,
+    mtype: &MessageTypeDef,
+) -> Errors {
+    let mut errors = Errors::none();
+    let mname = &mtype.name.id;
+
+    if mtype.nested.inside_sync() && !mtype.is_sync() {
+        errors.push(Diagnostic::from_catalog(
+            &mtype.name.loc,
+            "nesting.inside_sync",
+            &[("message", mname), ("protocol", &ptype.qname.short_name())],
+        ));
+    }
+
+    if mtype.nested.inside_cpow() && mtype.direction.is_to_child() {
+        errors.push(Diagnostic::from_catalog(
+            &mtype.name.loc,
+            "nesting.inside_cpow",
+            &[("message", mname), ("protocol", &ptype.qname.short_name())],
+        ));
+    }
+
+    // We allow inside_sync messages that are themselves sync to be sent from the
+    // parent. Normal and inside_cpow nested messages that are sync can only come from
+    // the child, unless the message has an [AllowSyncFrom=Parent] exception.
+    if mtype.is_sync()
+        && mtype.nested.is_none()
+        && mtype.direction.is_to_child()
+        && mtype.allow_sync_from != Some(ProtocolSide::Parent)
+    {
+        errors.push(Diagnostic::from_catalog(
+            &mtype.name.loc,
+            "sync_to_child",
+            &[("message", mname), ("protocol", &ptype.qname.short_name())],
+        ));
+    }
+
+    // Say whether the send semantics or the nesting is too much for the
+    // protocol.
+    if let Err(mismatch) = mtype
+        .message_strength()
+        .check_conversion(&ptype.message_strength())
+    {
+        errors.push(
+            Diagnostic::from_catalog(
+                &mtype.name.loc,
+                "send_semantics",
+                &[
+                    ("message", mname),
+                    ("protocol", &ptype.qname.short_name()),
+                    ("mismatch", &mismatch),
+                ],
+            )
+            .with_note(
+                ptype.qname.loc(),
+                &format!("protocol `{}' declared here", ptype.qname.short_name()),
+            ),
+        );
+    }
+
+    if (mtype.is_ctor() || mtype.is_dtor()) && mtype.is_async() && mtype.returns.len() > 0 {
+        errors.push(Diagnostic::from_catalog(
+            &mtype.name.loc,
+            "ctor.async_returns",
+            &[("message", mname)],
+        ));
+    }
+
+    // The reply to a sync message is copied out of the message into the
+    // caller's out-parameters.
+    if mtype.is_sync() {
+        for r in &mtype.returns {
+            if let Some(IPDLType::ImportedCxxType(qid, _, _, _)) =
+                r.param_type.lookup(tuts).moveonly_base()
+            {
+                errors.push(Diagnostic::from_catalog(
+                    &r.name.loc,
+                    "sync_moveonly_return",
+                    &[("type", &qid.short_name()), ("message", mname)],
+                ));
+            }
+        }
+    }
+
+    // A constructor's only result is the actor it constructs.
+    if mtype.is_ctor() && mtype.is_sync() && !mtype.returns.is_empty() {
+        errors.push(Diagnostic::from_catalog(
+            &mtype.name.loc,
+            "ctor.sync_returns",
+            &[("message", mname)],
+        ));
+    }
+
+    if mtype.compress != Compress::None && (!mtype.is_async() || mtype.is_ctor() || mtype.is_dtor())
+    {
+        let pname = ptype.qname.short_name();
+        let diagnostic = if mtype.is_ctor() || mtype.is_dtor() {
+            let message_type = if mtype.is_ctor() {
+                "constructor"
+            } else {
+                "destructor"
+            };
+            Diagnostic::from_catalog(
+                &mtype.name.loc,
+                "compress.ctor",
+                &[("kind", &message_type), ("protocol", &pname)],
+            )
+        } else {
+            Diagnostic::from_catalog(
+                &mtype.name.loc,
+                "compress.not_async",
+                &[("message", mname), ("protocol", &pname)],
+            )
+        };
+
+        errors.push(diagnostic);
+    }
+
+    if mtype.is_dtor() {
+        // Either side can destroy the actor, but it has to be clear which
+        // one does, or both could try to at the same time.
+        if mtype.direction == Direction::Both {
+            errors.push(Diagnostic::from_catalog(
+                &mtype.name.loc,
+                "dtor.both_directions",
+                &[("protocol", &ptype.qname.short_name())],
+            ));
+        }
+
+        // Actors in the destructor could be destroyed before the message
+        // is received.
+        for param in &mtype.params {
+            if contains_actor(param.param_type.lookup(tuts)) {
+                errors.push(
+                    Diagnostic::from_catalog(
+                        &param.name.loc,
+                        "dtor.actor_param",
+                        &[
+                            ("protocol", &ptype.qname.short_name()),
+                            ("param", &param.name.id),
+                        ],
+                    )
+                    .with_note(&mtype.name.loc, "in the destructor declared here"),
+                );
+            }
+        }
+    }
+
+    // The actor that a constructor creates doesn't exist on the other side
+    // until the constructor is received, so it can't be passed to it, and
+    // neither can any other actor of the same protocol.
+    if let Some(constructed) = mtype.ctor_type() {
+        let ctor_name = &mname[0..mname.len() - CONSTRUCTOR_SUFFIX.len()];
+        for param in &mtype.params {
+            if contains_actor_of(param.param_type.lookup(tuts), constructed) {
+                errors.push(
+                    Diagnostic::from_catalog(
+                        &param.name.loc,
+                        "ctor.own_actor",
+                        &[("protocol", &ctor_name), ("param", &param.name.id)],
+                    )
+                    .with_note(&mtype.name.loc, "in the constructor declared here"),
+                );
+            }
+        }
+    }
+
+    if mtype
+        .ctor_type()
+        .is_some_and(|t| !ptype.manages.contains(t))
+    {
+        let ctor_protocol_len = mname.len() - CONSTRUCTOR_SUFFIX.len();
+        errors.push(Diagnostic::from_catalog(
+            &mtype.name.loc,
+            "ctor.not_managed",
+            &[
+                ("managee", &&mname[0..ctor_protocol_len]),
+                ("protocol", &ptype.qname.short_name()),
+            ],
+        ));
+    }
+
+    errors
+}
+
+// The endpoint type inside of a parameter type, if there is one, looking
+// through arrays and the like but not into structs and unions.
+pub fn endpoint_type(t: &IPDLType) -> Option<&IPDLType> {
+    match t {
+        IPDLType::EndpointType(_) | IPDLType::ManagedEndpointType(_, _) => Some(t),
+        IPDLType::ArrayType(t_inner)
+        | IPDLType::MaybeType(t_inner)
+        | IPDLType::UniquePtrType(t_inner)
+        | IPDLType::NotNullType(t_inner) => endpoint_type(t_inner),
+        _ => None,
+    }
+}
+
+// The protocol that an endpoint type is for, and the side of it that the
+// endpoint binds. The endpoint types are named like
+// `Endpoint<::ns::PFooParent>'.
+pub fn endpoint_target(
+    tuts: &TUMap<TranslationUnitType>,
+    t: &IPDLType,
+) -> Option<(TUId, ProtocolSide)> {
+    let (qid, managed) = match t {
+        IPDLType::EndpointType(qid) => (qid, None),
+        IPDLType::ManagedEndpointType(qid, tuid) => (qid, Some(*tuid)),
+        _ => return None,
+    };
+    let name = &qid.base_id.id;
+    let inner = &name[name.find('<')? + 1..name.len() - 1];
+    let (protocol, side) = match inner.strip_suffix("Parent") {
+        Some(protocol) => (protocol, ProtocolSide::Parent),
+        None => (inner.strip_suffix("Child")?, ProtocolSide::Child),
+    };
+    let tuid = match managed {
+        Some(tuid) => tuid,
+        None => {
+            *tuts
+                .iter()
+                .find(|(_, tut)| {
+                    tut.protocol
+                        .as_ref()
+                        .is_some_and(|p| p.qname.to_string() == protocol)
+                })?
+                .0
+        }
+    };
+    Some((tuid, side))
+}
+
+// The process keywords of a side of a protocol. A side without a
+// [ParentProc] or [ChildProc] is in the same process as that side of its
+// managers.
+fn side_processes(
+    tuts: &TUMap<TranslationUnitType>,
+    tuid: &TUId,
+    side: ProtocolSide,
+) -> Vec<String> {
+    let mut processes = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![*tuid];
+    while let Some(tuid) = stack.pop() {
+        if !seen.insert(tuid) {
+            continue;
+        }
+        let ptype = match get_protocol_type(tuts, &tuid) {
+            Some(ptype) => ptype,
+            None => continue,
+        };
+        match ptype.process_for(side) {
+            Some(process) => {
+                if !processes.iter().any(|p| p == process) {
+                    processes.push(String::from(process));
+                }
+            }
+            None => stack.extend(&ptype.managers),
+        }
+    }
+    processes
+}
+
+// Whether some type of process matches both of the process keywords.
+fn processes_overlap(a: &str, b: &str) -> bool {
+    a == b
+        || PROCESS_TYPES
+            .iter()
+            .any(|p| process_allows(a, p) && process_allows(b, p))
+}
+
+fn side_name(side: ProtocolSide) -> &'static str {
+    match side {
+        ProtocolSide::Parent => "parent",
+        ProtocolSide::Child => "child",
+    }
+}
+
+// The sides that send a message in a direction.
+fn senders(d: Direction) -> &'static [ProtocolSide] {
+    match d {
+        Direction::To(ProtocolSide::Parent) => &[ProtocolSide::Child],
+        Direction::To(ProtocolSide::Child) => &[ProtocolSide::Parent],
+        Direction::Both => &[ProtocolSide::Parent, ProtocolSide::Child],
+    }
+}
+
+// The sides that receive a message in a direction.
+fn receivers(d: Direction) -> &'static [ProtocolSide] {
+    match d {
+        Direction::To(ProtocolSide::Parent) => &[ProtocolSide::Parent],
+        Direction::To(ProtocolSide::Child) => &[ProtocolSide::Child],
+        Direction::Both => &[ProtocolSide::Parent, ProtocolSide::Child],
+    }
+}
+
+// Checks for where endpoints can be sent. Endpoints are only sent in async
+// messages. A managed endpoint is bound to an actor of a managed protocol,
+// so it can only be sent in that protocol or in one of its managers. An
+// endpoint of a managed protocol has to be a managed endpoint, because the
+// actor needs a manager. The side that receives an endpoint should be in a
+// process where the side of the protocol that it binds can be.
+fn check_message_endpoints(
+    tuts: &TUMap<TranslationUnitType>,
+    tuid: &TUId,
+    ptype: &ProtocolTypeDef,
+    mtype: &MessageTypeDef,
+) -> Errors {
+    let mut errors = Errors::none();
+
+    let params = mtype.params.iter().map(|p| (p, false));
+    for (param, is_return) in params.chain(mtype.returns.iter().map(|p| (p, true))) {
+        let endpoint = match endpoint_type(param.param_type.lookup(tuts)) {
+            Some(endpoint) => endpoint,
+            None => continue,
+        };
+        let endpoint_name = endpoint.name(tuts);
+
+        if !mtype.is_async() {
+            errors.push(Diagnostic::from_catalog(
+                &param.name.loc,
+                "endpoint.not_async",
+                &[("endpoint", &endpoint_name), ("message", &mtype.name.id)],
+            ));
+        }
+
+        if let IPDLType::ManagedEndpointType(_, managed) = endpoint {
+            let managed_type = match checked_protocol_type(tuts, managed, &param.name.loc) {
+                Ok(managed_type) => managed_type,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            if managed != tuid && !managed_type.managers.contains(tuid) {
+                errors.push(
+                    Diagnostic::from_catalog(
+                        &param.name.loc,
+                        "endpoint.not_managed",
+                        &[
+                            ("endpoint", &endpoint_name),
+                            ("protocol", &ptype.qname.short_name()),
+                            ("managed", &managed_type.qname.short_name()),
+                        ],
+                    )
+                    .with_note(
+                        managed_type.qname.loc(),
+                        &format!(
+                            "protocol `{}' declared here",
+                            managed_type.qname.short_name()
+                        ),
+                    ),
+                );
+            }
+        }
+
+        let (opened, opened_side) = match endpoint_target(tuts, endpoint) {
+            Some(target) => target,
+            None => continue,
+        };
+        let opened_type = match checked_protocol_type(tuts, &opened, &param.name.loc) {
+            Ok(opened_type) => opened_type,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        if let IPDLType::EndpointType(_) = endpoint {
+            if !opened_type.is_top_level() {
+                errors.push(
+                    Diagnostic::from_catalog(
+                        &param.name.loc,
+                        "endpoint.managed",
+                        &[
+                            ("endpoint", &endpoint_name),
+                            ("protocol", &opened_type.qname.short_name()),
+                        ],
+                    )
+                    .with_note(
+                        opened_type.qname.loc(),
+                        &format!(
+                            "protocol `{}' declared here",
+                            opened_type.qname.short_name()
+                        ),
+                    ),
+                );
+                continue;
+            }
+        }
+
+        // The receiver of a message binds the endpoints in its parameters,
+        // and the sender binds the ones in its reply.
+        let receiver = match (mtype.direction, is_return) {
+            (Direction::To(side), false) => side,
+            (Direction::To(ProtocolSide::Parent), true) => ProtocolSide::Child,
+            (Direction::To(ProtocolSide::Child), true) => ProtocolSide::Parent,
+            (Direction::Both, _) => continue,
+        };
+        let processes = side_processes(tuts, tuid, receiver);
+        let opened_processes = side_processes(tuts, &opened, opened_side);
+        let overlap = processes
+            .iter()
+            .any(|p| opened_processes.iter().any(|o| processes_overlap(p, o)));
+        if !processes.is_empty() && !opened_processes.is_empty() && !overlap {
+            errors.push(Diagnostic::from_catalog(
+                &param.name.loc,
+                "endpoint_process",
+                &[
+                    ("endpoint", &endpoint_name),
+                    ("receiver", &side_name(receiver)),
+                    ("protocol", &ptype.qname.short_name()),
+                    ("process", &processes.join("' or `")),
+                    ("side", &side_name(opened_side)),
+                    ("opened", &opened_type.qname.short_name()),
+                    ("opened_process", &opened_processes.join("' or `")),
+                ],
+            ));
+        }
+    }
+
+    errors
+}
+
+fn check_types_protocol(
+    tuts: &TUMap<TranslationUnitType>,
+    tuid: &TUId,
+    ptype: &ProtocolTypeDef,
+) -> Errors {
+    let mut errors = Errors::none();
+
+    // XXX Why does this check every translation unit and not just the current one???
+    errors.append(protocols_managers_acyclic(&tuts));
+
+    for manager in &ptype.managers {
+        let manager_type = match checked_protocol_type(&tuts, &manager, ptype.qname.loc()) {
+            Ok(manager_type) => manager_type,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        if let Err(mismatch) = ptype
+            .message_strength()
+            .check_conversion(&manager_type.message_strength())
+        {
+            errors.push(
+                Diagnostic::from_catalog(
+                    ptype.qname.loc(),
+                    "send_semantics.manager",
+                    &[
+                        ("protocol", &ptype.qname.short_name()),
+                        ("manager", &manager_type.qname.short_name()),
+                        ("mismatch", &mismatch.describe("protocol", "manager")),
+                    ],
+                )
+                .with_note(
+                    manager_type.qname.loc(),
+                    &format!(
+                        "manager `{}' declared here",
+                        manager_type.qname.short_name()
+                    ),
+                ),
+            );
+        }
+
+        if !manager_type.manages.contains(&tuid) {
+            errors.push(
+                Diagnostic::from_catalog(
+                    manager_type.qname.loc(),
+                    "manager_mismatch.manager",
+                    &[
+                        ("protocol", &ptype.qname.short_name()),
+                        ("manager", &manager_type.qname.short_name()),
+                    ],
+                )
+                .with_note(
+                    ptype.qname.loc(),
+                    &format!("protocol `{}' declared here", ptype.qname.short_name()),
+                ),
+            );
+        }
+    }
+
+    for managee in &ptype.manages {
+        let managee_type = match checked_protocol_type(&tuts, &managee, ptype.qname.loc()) {
+            Ok(managee_type) => managee_type,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+
+        if managee != tuid && ptype.managers.contains(managee) {
+            errors.push(
+                Diagnostic::from_catalog(
+                    ptype.qname.loc(),
+                    "manager_cycle.mutual",
+                    &[
+                        ("protocol", &ptype.qname.short_name()),
+                        ("managee", &managee_type.qname.short_name()),
+                    ],
+                )
+                .with_note(
+                    managee_type.qname.loc(),
+                    &format!("`{}' declared here", managee_type.qname.short_name()),
+                ),
+            );
+            // Whether the managee lists this protocol as a manager doesn't
+            // matter, so that isn't reported on top of this.
+            continue;
+        }
+
+        if !managee_type.managers.contains(&tuid) {
+            errors.push(
+                Diagnostic::from_catalog(
+                    managee_type.qname.loc(),
+                    "manager_mismatch.managee",
+                    &[
+                        ("protocol", &ptype.qname.short_name()),
+                        ("managee", &managee_type.qname.short_name()),
+                    ],
+                )
+                .with_note(
+                    ptype.qname.loc(),
+                    &format!("protocol `{}' declared here", ptype.qname.short_name()),
+                ),
+            );
+        }
+    }
+
+    for mtype in &ptype.messages {
+        errors.append(check_types_message(tuts, &ptype, &mtype));
+        errors.append(check_message_endpoints(tuts, tuid, ptype, mtype));
+        errors.append(check_nesting_managers(tuts, ptype, mtype));
+        errors.append(check_carried_actors(tuts, ptype, mtype));
+        errors.append(check_ctor_direction(tuts, mtype));
+    }
+
+    errors
+}
+
+// The side that sends a constructor is the one that creates the actor. If
+// every message of the managed protocol other than its destructor is sent
+// to that side, the side that created the actor never sends anything on
+// it, which usually means that the constructor is in the wrong section.
+fn check_ctor_direction(tuts: &TUMap<TranslationUnitType>, mtype: &MessageTypeDef) -> Errors {
+    let mut errors = Errors::none();
+    let (managee, receiver) = match (&mtype.mtype, mtype.direction) {
+        (MessageType::Ctor(managee), Direction::To(receiver)) => (managee, receiver),
+        _ => return errors,
+    };
+    let creator = match receiver {
+        ProtocolSide::Parent => ProtocolSide::Child,
+        ProtocolSide::Child => ProtocolSide::Parent,
+    };
+    let managee_type = match checked_protocol_type(tuts, managee, &mtype.name.loc) {
+        Ok(managee_type) => managee_type,
+        Err(e) => return Errors::from_diagnostic(e),
+    };
+    let mut messages = managee_type.messages.iter().filter(|m| !m.is_dtor());
+    let first = match messages.next() {
+        Some(first) => first,
+        None => return errors,
+    };
+    if std::iter::once(first)
+        .chain(messages)
+        .all(|m| m.direction == Direction::To(creator))
+    {
+        errors.push(
+            Diagnostic::from_catalog(
+                &mtype.name.loc,
+                "ctor_direction",
+                &[
+                    ("message", &mtype.name.id),
+                    ("receiver", &side_name(receiver)),
+                    ("protocol", &managee_type.qname.short_name()),
+                    ("creator", &side_name(creator)),
+                ],
+            )
+            .with_note(
+                &first.name.loc,
+                &format!("`{}' is sent to the {}", first.name.id, side_name(creator)),
+            ),
+        );
+    }
+    errors
+}
+
+// The protocol of the actor that |t| is, looking through arrays and the
+// like.
+fn actor_protocol(t: &IPDLType) -> Option<&TUId> {
+    match t {
+        IPDLType::ActorType(actor) => Some(actor),
+        IPDLType::ArrayType(t_inner)
+        | IPDLType::MaybeType(t_inner)
+        | IPDLType::UniquePtrType(t_inner)
+        | IPDLType::NotNullType(t_inner) => actor_protocol(t_inner),
+        _ => None,
+    }
+}
+
+// The receiver of a message can use the actors that it carries as soon as
+// it gets them, while the sender may still be blocked, so the message has
+// to be one that the protocols of those actors could send themselves.
+// Messages that their own protocol already rejects are skipped.
+fn check_carried_actors(
+    tuts: &TUMap<TranslationUnitType>,
+    ptype: &ProtocolTypeDef,
+    mtype: &MessageTypeDef,
+) -> Errors {
+    let mut errors = Errors::none();
+    if !mtype.converts_to(ptype) {
+        return errors;
+    }
+    for param in mtype.params.iter().chain(&mtype.returns) {
+        let actor = match actor_protocol(param.param_type.lookup(tuts)) {
+            Some(actor) => match checked_protocol_type(tuts, actor, &param.name.loc) {
+                Ok(actor) => actor,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            },
+            None => continue,
+        };
+        if let Err(mismatch) = mtype
+            .message_strength()
+            .check_conversion(&actor.message_strength())
+        {
+            errors.push(
+                Diagnostic::from_catalog(
+                    &param.name.loc,
+                    "send_semantics.actor",
+                    &[
+                        ("message", &mtype.name.id),
+                        ("protocol", &actor.qname.short_name()),
+                        ("param", &param.name.id),
+                        ("mismatch", &mismatch),
+                    ],
+                )
+                .with_note(
+                    actor.qname.loc(),
+                    &format!("protocol `{}' declared here", actor.qname.short_name()),
+                ),
+            );
+        }
+    }
+    errors
+}
+
+// A nested message is only delivered as nested if every protocol on the
+// way up to the top-level protocol allows that, not just its own protocol.
+// The first manager that doesn't is reported, along with the managers in
+// between. Messages that their own protocol already rejects are skipped.
+fn check_nesting_managers(
+    tuts: &TUMap<TranslationUnitType>,
+    ptype: &ProtocolTypeDef,
+    mtype: &MessageTypeDef,
+) -> Errors {
+    let mut errors = Errors::none();
+    let nesting = match mtype.nested {
+        Nesting::None => return errors,
+        Nesting::InsideSync => "inside_sync",
+        Nesting::InsideCpow => "inside_cpow",
+    };
+    if !mtype.converts_to(ptype) {
+        return errors;
+    }
+
+    let strength = mtype.message_strength();
+    let mut visited = HashSet::new();
+    let mut pending = ptype
+        .managers
+        .iter()
+        .map(|m| (m, Vec::<&ProtocolTypeDef>::new()))
+        .collect::<Vec<_>>();
+    while let Some((tuid, through)) = pending.pop() {
+        if !visited.insert(tuid) {
+            continue;
+        }
+        let manager = match checked_protocol_type(tuts, tuid, &mtype.name.loc) {
+            Ok(manager) => manager,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        if !strength.converts_to(&manager.message_strength()) {
+            let mut e = Diagnostic::from_catalog(
+                &mtype.name.loc,
+                "nesting.manager",
+                &[
+                    ("message", &mtype.name.id),
+                    ("nesting", &nesting),
+                    ("manager", &manager.qname.short_name()),
+                    ("protocol", &ptype.qname.short_name()),
+                ],
+            )
+            .with_note(
+                manager.qname.loc(),
+                &format!("manager `{}' declared here", manager.qname.short_name()),
+            );
+            for between in &through {
+                e = e.with_note(
+                    between.qname.loc(),
+                    &format!("through manager `{}'", between.qname.short_name()),
+                );
+            }
+            errors.push(e);
+            return errors;
+        }
+        for next in &manager.managers {
+            let mut through = through.clone();
+            through.push(manager);
+            pending.push((next, through));
+        }
+    }
+
+    errors
+}
+
+// Whether a manager can construct actors of a managed protocol, either with
+// a ctor message or by sending a managed endpoint to bind one to.
+fn constructs(
+    tuts: &TUMap<TranslationUnitType>,
+    manager: &ProtocolTypeDef,
+    managee: &TUId,
+) -> bool {
+    manager.messages.iter().any(|m| {
+        m.ctor_type() == Some(managee)
+            || m.params.iter().chain(m.returns.iter()).any(|param| {
+                match endpoint_type(param.param_type.lookup(tuts)) {
+                    Some(IPDLType::ManagedEndpointType(_, t)) => t == managee,
+                    _ => false,
+                }
+            })
+    })
+}
+
+// Warn about managed protocols that the manager has no way to construct,
+// even though other managers do. If no manager does, that is reported by
+// check_orphaned_protocols.
+fn check_managee_ctors(
+    tuts: &TUMap<TranslationUnitType>,
+    tu: &TranslationUnit,
+    ptype: &ProtocolTypeDef,
+) -> Errors {
+    let mut errors = Errors::none();
+    let p = match &tu.protocol {
+        Some((_, p)) => p,
+        None => return errors,
+    };
+
+    for managee in &ptype.manages {
+        let managee_type = match checked_protocol_type(tuts, managee, ptype.qname.loc()) {
+            Ok(managee_type) => managee_type,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        let constructed_elsewhere = managee_type.managers.iter().any(|m| {
+            get_protocol_type(tuts, m).is_some_and(|manager| constructs(tuts, manager, managee))
+        });
+        if constructs(tuts, ptype, managee) || !constructed_elsewhere {
+            continue;
+        }
+
+        let managee_name = managee_type.qname.short_name();
+        let loc = p
+            .manages
+            .iter()
+            .find(|m| m.id == managee_name)
+            .map_or(ptype.qname.loc(), |m| &m.loc);
+        errors.push(Diagnostic::from_catalog(
+            loc,
+            "missing_ctor",
+            &[
+                ("manager", &ptype.qname.short_name()),
+                ("protocol", &managee_name),
+            ],
+        ));
+    }
+
+    errors
+}
+
+// Warn about managed protocols that no manager constructs, because their
+// code is still generated. There is no need to also look for protocols whose
+// chain of managers never reaches a top-level protocol: that can only happen
+// if the managers form a cycle, which is an error.
+fn check_orphaned_protocols(tuts: &TUMap<TranslationUnitType>) -> Errors {
+    let mut errors = Errors::none();
+    let mut ptypes = tuts
+        .iter()
+        .filter_map(|(tuid, tut)| tut.protocol.as_ref().map(|ptype| (tuid, ptype)))
+        .collect::<Vec<_>>();
+    ptypes.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (tuid, ptype) in ptypes {
+        if ptype.is_top_level() {
+            continue;
+        }
+        if !ptype.managers.iter().any(|m| {
+            get_protocol_type(tuts, m).is_some_and(|manager| constructs(tuts, manager, tuid))
+        }) {
+            errors.push(Diagnostic::from_catalog(
+                ptype.qname.loc(),
+                "never_constructed",
+                &[("protocol", &ptype.qname.short_name())],
+            ));
+        }
+    }
+
+    errors
+}
+
+// The number of levels of managers above a protocol, which is 0 for a
+// top-level protocol. The managers must not have any cycles.
+fn manager_depth(
+    tuts: &TUMap<TranslationUnitType>,
+    depths: &mut TUMap<usize>,
+    tuid: &TUId,
+) -> usize {
+    if let Some(&depth) = depths.get(tuid) {
+        return depth;
+    }
+    let managers = get_protocol_type(tuts, tuid).map_or(&[][..], |ptype| &ptype.managers[..]);
+    let depth = managers
+        .iter()
+        .filter(|m| *m != tuid)
+        .map(|m| manager_depth(tuts, depths, m) + 1)
+        .max()
+        .unwrap_or(0);
+    depths.insert(*tuid, depth);
+    depth
+}
+
+// Warn about actor trees that are getting hard to follow.
+fn check_manager_limits(tuts: &TUMap<TranslationUnitType>, options: &CheckOptions) -> Errors {
+    let mut errors = Errors::none();
+    let mut ptypes = tuts
+        .iter()
+        .filter_map(|(tuid, tut)| tut.protocol.as_ref().map(|ptype| (tuid, ptype)))
+        .collect::<Vec<_>>();
+    ptypes.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut depths = TUMap::new();
+    for (tuid, ptype) in ptypes {
+        if let Some(max_depth) = options.max_manager_depth {
+            let depth = manager_depth(tuts, &mut depths, tuid);
+            if depth > max_depth {
+                errors.push(Diagnostic::from_catalog(
+                    ptype.qname.loc(),
+                    "manager_depth",
+                    &[
+                        ("protocol", &ptype.qname.short_name()),
+                        ("depth", &depth),
+                        ("limit", &max_depth),
+                    ],
+                ));
+            }
+        }
+        if let Some(max_managees) = options.max_managees {
+            if ptype.manages.len() > max_managees {
+                errors.push(Diagnostic::from_catalog(
+                    ptype.qname.loc(),
+                    "too_many_managees",
+                    &[
+                        ("protocol", &ptype.qname.short_name()),
+                        ("count", &ptype.manages.len()),
+                        ("limit", &max_managees),
+                    ],
+                ));
+            }
+        }
+    }
+
+    errors
+}
+
+// Warn about messages with so many parameters that it is easy to pass
+// them in the wrong order.
+fn check_param_limits(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+    options: &CheckOptions,
+) -> Errors {
+    let mut errors = Errors::none();
+    let max_params = match options.max_params {
+        Some(max_params) => max_params,
+        None => return errors,
+    };
+
+    for (tuid, tut) in tuts {
+        let (ptype, p) = match (&tut.protocol, &tus[tuid].protocol) {
+            (Some(ptype), Some((_, p))) => (ptype, p),
+            _ => continue,
+        };
+        for (mtype, md) in ptype.messages.iter().zip(p.messages.iter()) {
+            if md.in_params.len() > max_params && !allows_warning(&md.attributes, "too_many_params")
+            {
+                errors.push(Diagnostic::from_catalog(
+                    &mtype.name.loc,
+                    "too_many_params",
+                    &[
+                        ("message", &mtype.name.id),
+                        ("count", &md.in_params.len()),
+                        ("limit", &max_params),
+                    ],
+                ));
+            }
+        }
+    }
+
+    errors
+}
+
+// The checks that only strict mode does.
+fn check_strict(tuts: &TUMap<TranslationUnitType>, options: &CheckOptions) -> Errors {
+    let mut errors = Errors::none();
+    if !options.strict {
+        return errors;
+    }
+    for (_, tut) in tuts {
+        let ptype = match &tut.protocol {
+            Some(ptype) => ptype,
+            None => continue,
+        };
+        let name = ptype.qname.short_name();
+        if ptype.send_semantics == SendSemantics::Intr {
+            errors.push(Diagnostic::from_catalog(
+                ptype.qname.loc(),
+                "intr",
+                &[("protocol", &name)],
+            ));
+        }
+        if ptype.is_top_level() && ptype.proc_parent.is_none() {
+            errors.push(Diagnostic::from_catalog(
+                ptype.qname.loc(),
+                "toplevel_process",
+                &[("protocol", &name)],
+            ));
+        }
+    }
+    errors
+}
+
+// A union that isn't fully defined and has itself as one of its
+// components, not in an array or the like, is reported at that component,
+// because that is usually the mistake. A union with another component that
+// is fully defined can contain itself, like `union R { int; R; }'.
+fn self_component(
+    tu: &TranslationUnit,
+    tut: &TranslationUnitType,
+    i: usize,
+    kind: CompoundType,
+    tr: &TypeRef,
+) -> Option<Diagnostic> {
+    if kind != CompoundType::Union {
+        return None;
+    }
+    let (ref ns, _, ref components) = tu.unions[i];
+    let (c, _) = components
+        .iter()
+        .zip(
+            tut.unions[i]
+                .components
+                .iter()
+                .map(|c| tut.types.get(c.index)),
+        )
+        .find(|(_, t)| match t {
+            IPDLType::UnionType(r) => r == tr,
+            _ => false,
+        })?;
+    Some(
+        Diagnostic::from_catalog(
+            &c.loc,
+            "partially_defined.self_component",
+            &[("union", &ns.name.id)],
+        )
+        .with_note(
+            &ns.name.loc,
+            &format!("union `{}' is declared here", ns.name.id),
+        ),
+    )
+}
+
+fn check_types_tu(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+    defined: &mut HashMap<(CompoundType, TypeRef), FullyDefinedState>,
+    tuid: &TUId,
+    tut: &TranslationUnitType,
+) -> Errors {
+    let mut errors = Errors::none();
+
+    let tu = match tus.get(tuid) {
+        Some(tu) => tu,
+        None => {
+            errors.push(Diagnostic::without_location(&format!(
+                "internal error: translation unit {} was not loaded",
+                tuid
+            )));
+            return errors;
+        }
+    };
+
+    let compounds = (0..tut.structs.len())
+        .map(|i| (CompoundType::Struct, i, &tu.structs[i].0))
+        .chain((0..tut.unions.len()).map(|i| (CompoundType::Union, i, &tu.unions[i].0)));
+    for (kind, i, ns) in compounds {
+        let tr = TypeRef::new(tuid, i);
+        let t = match kind {
+            CompoundType::Struct => IPDLType::StructType(tr.clone()),
+            CompoundType::Union => IPDLType::UnionType(tr.clone()),
+        };
+        if fully_defined(tuts, defined, &t) {
+            continue;
+        }
+        if let Some(d) = self_component(tu, tut, i, kind, &tr) {
+            errors.push(d);
+            continue;
+        }
+        let what = match kind {
+            CompoundType::Struct => "struct",
+            CompoundType::Union => "union",
+        };
+        let mut d = Diagnostic::from_catalog(
+            &ns.name.loc,
+            "partially_defined",
+            &[("kind", &what), ("name", &ns.name.id)],
+        );
+        if let Some((chain, loc)) = undefined_chain(tus, tuts, defined, (kind, tr)) {
+            d = d.with_note(&loc, &format!("because of the cycle {}", chain));
+        }
+        errors.push(d);
+    }
+
+    if let &Some(ref pt) = &tut.protocol {
+        errors.append(check_types_protocol(&tuts, &tuid, &pt));
+        errors.append(check_managee_ctors(tuts, tu, pt));
+    }
+
+    // XXX We don't need to track visited because we will visited all
+    // translation units at the top level.
+
+    // XXX What is "ptype"? In Python, it is set to None at the top of this method.
+
+    // XXX The Python checker calls visitIncludes on tu.includes,
+    // which checks any included protocols. I don't know why that
+    // would be useful.
+
+    errors
+}
+
+// The translation units whose structs and unions can be reached from a
+// protocol, or from a header that no other file includes. Anything else is
+// a header that is included but never used.
+fn used_translation_units(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+) -> HashSet<TUId> {
+    let included = tus
+        .values()
+        .flat_map(|tu| &tu.includes)
+        .collect::<HashSet<_>>();
+    let mut work_list = tus
+        .iter()
+        .filter(|(tuid, tu)| tu.file_type == FileType::Protocol || !included.contains(tuid))
+        .map(|(tuid, _)| *tuid)
+        .collect::<Vec<_>>();
+    let mut used = work_list.iter().cloned().collect::<HashSet<_>>();
+    while let Some(tuid) = work_list.pop() {
+        let tut = match tuts.get(&tuid) {
+            Some(tut) => tut,
+            None => continue,
+        };
+        // The type table has every type that a field, component or
+        // parameter uses.
+        for mut t in tut.types.iter() {
+            while let IPDLType::ArrayType(inner)
+            | IPDLType::MaybeType(inner)
+            | IPDLType::UniquePtrType(inner)
+            | IPDLType::NotNullType(inner) = t
+            {
+                t = inner;
+            }
+            if let IPDLType::StructType(tr) | IPDLType::UnionType(tr) = t {
+                if used.insert(tr.tu) {
+                    work_list.push(tr.tu);
+                }
+            }
+        }
+    }
+    used
+}
+
+fn contains_actor(t: &IPDLType) -> bool {
+    match t {
+        IPDLType::ActorType(_) => true,
+        IPDLType::ArrayType(t_inner)
+        | IPDLType::MaybeType(t_inner)
+        | IPDLType::UniquePtrType(t_inner)
+        | IPDLType::NotNullType(t_inner) => contains_actor(t_inner),
+        _ => false,
+    }
+}
+
+// Whether |t| is an actor of the protocol |tuid|, looking through arrays
+// and the like.
+fn contains_actor_of(t: &IPDLType, tuid: &TUId) -> bool {
+    match t {
+        IPDLType::ActorType(actor) => actor == tuid,
+        IPDLType::ArrayType(t_inner)
+        | IPDLType::MaybeType(t_inner)
+        | IPDLType::UniquePtrType(t_inner)
+        | IPDLType::NotNullType(t_inner) => contains_actor_of(t_inner, tuid),
+        _ => false,
+    }
+}
+
+// Add the unions that can be reached from |t| to |unions|, looking into
+// struct fields and union components.
+fn reachable_unions(
+    tuts: &TUMap<TranslationUnitType>,
+    t: &IPDLType,
+    visited: &mut HashSet<(CompoundType, TypeRef)>,
+    unions: &mut Vec<TypeRef>,
+) {
+    let (key, fields) = match t {
+        IPDLType::StructType(tr) => (
+            (CompoundType::Struct, tr.clone()),
+            &tr.lookup_struct(tuts).fields,
+        ),
+        IPDLType::UnionType(tr) => (
+            (CompoundType::Union, tr.clone()),
+            &tr.lookup_union(tuts).components,
+        ),
+        IPDLType::ArrayType(t_inner)
+        | IPDLType::MaybeType(t_inner)
+        | IPDLType::UniquePtrType(t_inner)
+        | IPDLType::NotNullType(t_inner) => {
+            return reachable_unions(tuts, t_inner, visited, unions)
+        }
+        _ => return,
+    };
+    if !visited.insert(key.clone()) {
+        return;
+    }
+    if key.0 == CompoundType::Union {
+        unions.push(key.1);
+    }
+    for f in fields {
+        reachable_unions(tuts, f.lookup(tuts), visited, unions);
+    }
+}
+
+// A refcounted imported type that can be reached from |t|, looking into
+// struct fields and union components.
+fn reachable_refcounted<'a>(
+    tuts: &'a TUMap<TranslationUnitType>,
+    t: &'a IPDLType,
+    visited: &mut HashSet<(CompoundType, TypeRef)>,
+) -> Option<&'a IPDLType> {
+    let (key, fields) = match t {
+        IPDLType::ImportedCxxType(_, Lifetime::RefCounted, _, _) => return Some(t),
+        IPDLType::StructType(tr) => (
+            (CompoundType::Struct, tr.clone()),
+            &tr.lookup_struct(tuts).fields,
+        ),
+        IPDLType::UnionType(tr) => (
+            (CompoundType::Union, tr.clone()),
+            &tr.lookup_union(tuts).components,
+        ),
+        IPDLType::ArrayType(t_inner)
+        | IPDLType::MaybeType(t_inner)
+        | IPDLType::UniquePtrType(t_inner)
+        | IPDLType::NotNullType(t_inner) => return reachable_refcounted(tuts, t_inner, visited),
+        _ => return None,
+    };
+    if !visited.insert(key) {
+        return None;
+    }
+    fields
+        .iter()
+        .find_map(|f| reachable_refcounted(tuts, f.lookup(tuts), visited))
+}
+
+// A `[PlainOldData]' struct is serialized by copying its bytes, so that an
+// array of them can be sent with one memcpy. A refcounted type anywhere
+// inside of it would be copied without adding a reference, which the C++
+// compiler only rejects with an error deep inside of the ParamTraits
+// templates.
+fn check_plain_old_data(tus: &TUMap<TranslationUnit>, tuts: &TUMap<TranslationUnitType>) -> Errors {
+    let mut errors = Errors::none();
+    let mut tuids = tuts.keys().collect::<Vec<_>>();
+    tuids.sort();
+    for tuid in tuids {
+        for ((ns, attributes, fields), sdef) in tus[tuid].structs.iter().zip(&tuts[tuid].structs) {
+            if !attributes.contains_key(PLAIN_OLD_DATA) {
+                continue;
+            }
+            for (f, t) in fields.iter().zip(sdef.field_types(tuts)) {
+                let qid = match reachable_refcounted(tuts, t, &mut HashSet::new()) {
+                    Some(IPDLType::ImportedCxxType(qid, _, _, _)) => qid,
+                    _ => continue,
+                };
+                errors.push(
+                    Diagnostic::from_catalog(
+                        &f.name.loc,
+                        "plain_old_data",
+                        &[
+                            ("field", &f.name.id),
+                            ("struct", &ns.name.id),
+                            ("type", &qid.short_name()),
+                        ],
+                    )
+                    .with_note(
+                        qid.loc(),
+                        &format!("`{}' is declared [RefCounted] here", qid.short_name()),
+                    ),
+                );
+            }
+        }
+    }
+    errors
+}
+
+// Structs in different files with the same fields, which could be one
+// struct in a header that both files include. Fields are compared by name
+// and by type, with the names of types resolved, so fields spelled with
+// different aliases or `using's of the same type match. Structs without
+// fields are left to the empty_struct warning. Each struct is reported
+// against the first one with the same fields, in the order the files were
+// parsed.
+fn check_duplicate_structs(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+) -> Errors {
+    let mut errors = Errors::none();
+    let mut tuids = tuts.keys().collect::<Vec<_>>();
+    tuids.sort();
+
+    let mut first: HashMap<Vec<(String, String)>, (&TUId, &Namespace)> = HashMap::new();
+    for tuid in tuids {
+        for ((ns, _, fields), sdef) in tus[tuid].structs.iter().zip(&tuts[tuid].structs) {
+            if fields.is_empty() {
+                continue;
+            }
+            let key = fields
+                .iter()
+                .zip(sdef.field_types(tuts))
+                .map(|(f, t)| (f.name.id.clone(), t.identity()))
+                .collect::<Vec<_>>();
+            let (first_tuid, first_ns) = *first.entry(key).or_insert((tuid, ns));
+            if first_tuid == tuid {
+                continue;
+            }
+            errors.push(
+                Diagnostic::from_catalog(
+                    &ns.name.loc,
+                    "duplicate_struct",
+                    &[
+                        ("struct", &ns.name.id),
+                        ("other", &first_ns.qname().to_string()),
+                        ("file", &tus[first_tuid].file_name.display()),
+                    ],
+                )
+                .with_note(
+                    &first_ns.name.loc,
+                    &format!("`{}' defined here", first_ns.name.id),
+                ),
+            );
+        }
+    }
+    errors
+}
+
+// An actor is sent as a reference to an actor that the receiving side
+// already knows about, and for actors inside of unions the generated code
+// only handles this in one direction, so a union that contains an actor
+// can't be sent both to the parent and to the child.
+fn check_actor_placement(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+) -> Errors {
+    let mut errors = Errors::none();
+    let mut tuids = tuts.keys().collect::<Vec<_>>();
+    tuids.sort();
+
+    // For each union, the first place it is sent to the parent and to the
+    // child, as the parameter and the message it is sent in.
+    let mut senders: HashMap<TypeRef, [Option<(&ParamTypeDef, &MessageTypeDef)>; 2]> =
+        HashMap::new();
+    let mut union_order = Vec::new();
+    for tuid in tuids {
+        let ptype = match &tuts[tuid].protocol {
+            Some(ptype) => ptype,
+            None => continue,
+        };
+        for mtype in &ptype.messages {
+            // Return values travel in the other direction.
+            let params = mtype.params.iter().map(|p| (p, false));
+            let returns = mtype.returns.iter().map(|p| (p, true));
+            for (param, is_return) in params.chain(returns) {
+                let to_parent = if is_return {
+                    mtype.direction.is_to_child()
+                } else {
+                    mtype.direction.is_to_parent()
+                };
+                let to_child = if is_return {
+                    mtype.direction.is_to_parent()
+                } else {
+                    mtype.direction.is_to_child()
+                };
+                let mut unions = Vec::new();
+                reachable_unions(
+                    tuts,
+                    param.param_type.lookup(tuts),
+                    &mut HashSet::new(),
+                    &mut unions,
+                );
+                for tr in unions {
+                    let entry = senders.entry(tr.clone()).or_insert_with(|| {
+                        union_order.push(tr);
+                        [None, None]
+                    });
+                    if to_parent && entry[0].is_none() {
+                        entry[0] = Some((param, mtype));
+                    }
+                    if to_child && entry[1].is_none() {
+                        entry[1] = Some((param, mtype));
+                    }
+                }
+            }
+        }
+    }
+
+    for tr in union_order {
+        let (to_parent, to_child) = match senders[&tr] {
+            [Some(to_parent), Some(to_child)] => (to_parent, to_child),
+            _ => continue,
+        };
+        let udef = tr.lookup_union(tuts);
+        let actor = match udef.component_types(tuts).position(contains_actor) {
+            Some(i) => i,
+            None => continue,
+        };
+        let component = &tus[&tr.tu].unions[tr.index].2[actor];
+        let note = |(param, mtype): (&ParamTypeDef, &MessageTypeDef), side: &str| {
+            (
+                param.name.loc.clone(),
+                format!("sent to the {} here, in message `{}'", side, mtype.name.id),
+            )
+        };
+        let (parent_loc, parent_note) = note(to_parent, "parent");
+        let (child_loc, child_note) = note(to_child, "child");
+        errors.push(
+            Diagnostic::from_catalog(
+                component.loc(),
+                "bidirectional_actor_union",
+                &[("union", &udef.qname.short_name()), ("type", component)],
+            )
+            .with_note(&parent_loc, &parent_note)
+            .with_note(&child_loc, &child_note),
+        );
+    }
+
+    errors
+}
+
+// Names that can't be used in C++ code: a keyword like `class` or
+// `operator`, or an identifier reserved for the implementation, like
+// `_Foo` or `a__b`.
+fn cxx_reserved_reason(id: &str) -> Option<&'static str> {
+    if CXX_KEYWORDS.contains(&id) {
+        Some("a C++ keyword")
+    } else if id.contains("__")
+        || (id.starts_with('_') && id[1..].starts_with(|c: char| c.is_ascii_uppercase()))
+    {
+        Some("a reserved C++ identifier")
+    } else {
+        None
+    }
+}
+
+// Check that the names of structs, unions, protocols, fields, messages and
+// parameters can be used in the generated C++ code. These would otherwise
+// only be caught when compiling the generated headers.
+fn check_cxx_identifiers(tu: &TranslationUnit) -> Errors {
+    let mut errors = Errors::none();
+    let mut check = |id: &Identifier, what: &str| {
+        if let Some(reason) = cxx_reserved_reason(&id.id) {
+            errors.push(Diagnostic::from_catalog(
+                &id.loc,
+                "reserved_name",
+                &[("what", &what), ("name", &id.id), ("reason", &reason)],
+            ));
+        }
+    };
+
+    for (ns, _, fields) in &tu.structs {
+        check(&ns.name, "struct");
+        for f in fields {
+            check(&f.name, "field");
+        }
+    }
+    for (ns, _, _) in &tu.unions {
+        check(&ns.name, "union");
+    }
+    if let Some((ns, p)) = &tu.protocol {
+        check(&ns.name, "protocol");
+        for md in &p.messages {
+            if md.name.id != DELETE_MESSAGE_NAME {
+                check(&md.name, "message");
+            }
+            for param in md.in_params.iter().chain(md.out_params.iter()) {
+                check(&param.name, "parameter");
+            }
+        }
+    }
+
+    errors
+}
+
+// Report names that differ only in case from an earlier name in the same
+// scope. Each name is given as (scope, kind, identifier). Names that are
+// exactly the same are redeclarations, which are reported elsewhere.
+fn check_case_collisions(names: &[(String, &str, &Identifier)]) -> Errors {
+    let mut errors = Errors::none();
+    let mut seen: HashMap<(&str, String), (&str, &Identifier)> = HashMap::new();
+    for &(ref scope, kind, id) in names {
+        let key = (scope.as_str(), id.id.to_lowercase());
+        match seen.get(&key) {
+            Some(&(first_kind, first)) => {
+                if first.id != id.id {
+                    errors.push(
+                        Diagnostic::from_catalog(
+                            &id.loc,
+                            "case_collision",
+                            &[
+                                ("kind", &kind),
+                                ("name", &id.id),
+                                ("other_kind", &first_kind),
+                                ("other", &first.id),
+                            ],
+                        )
+                        .with_note(
+                            &first.loc,
+                            &format!("{} `{}' declared here", first_kind, first.id),
+                        ),
+                    );
+                }
+            }
+            None => {
+                seen.insert(key, (kind, id));
+            }
+        }
+    }
+    errors
+}
+
+// Messages that differ only in case produce confusing C++ overloads, and
+// structs and unions that do are easy to mix up, so reject them.
+fn check_tu_case_collisions(tu: &TranslationUnit) -> Errors {
+    // Struct and union names are scoped by their namespace.
+    let mut names = Vec::new();
+    for (ns, _, _) in &tu.structs {
+        names.push((ns.namespaces.join("::"), "struct", &ns.name));
+    }
+    for (ns, _, _) in &tu.unions {
+        names.push((ns.namespaces.join("::"), "union", &ns.name));
+    }
+    if let Some((ns, p)) = &tu.protocol {
+        for md in &p.messages {
+            names.push((ns.qname().to_string(), "message", &md.name));
+        }
+    }
+    check_case_collisions(&names)
+}
+
+// The path of the header generated for a translation unit, like
+// `mozilla/dom/PFoo.h`.
+fn generated_header_path(tu: &TranslationUnit) -> String {
+    let mut path = tu.namespace.namespaces.join("/");
+    if !path.is_empty() {
+        path.push('/');
+    }
+    if let Some(stem) = tu.file_name.file_stem() {
+        path.push_str(&stem.to_string_lossy());
+    }
+    path.push_str(".h");
+    path
+}
+
+// Two translation units whose generated headers only differ in case clash
+// on case-insensitive file systems, like the default ones on Windows and
+// macOS.
+fn check_header_case_collisions(tus: &TUMap<TranslationUnit>) -> Errors {
+    let mut errors = Errors::none();
+    let mut tuids = tus.keys().collect::<Vec<_>>();
+    tuids.sort();
+
+    let mut seen: HashMap<String, (String, &TranslationUnit)> = HashMap::new();
+    for tuid in tuids {
+        let tu = &tus[tuid];
+        let path = generated_header_path(tu);
+        match seen.get(&path.to_lowercase()) {
+            Some((first_path, first)) => {
+                if *first_path != path {
+                    errors.push(
+                        Diagnostic::from_catalog(
+                            &tu.namespace.name.loc,
+                            "case_collision.header",
+                            &[("header", &path), ("other", first_path)],
+                        )
+                        .with_note(
+                            &first.namespace.name.loc,
+                            &format!("`{}' is generated for this translation unit", first_path),
+                        ),
+                    );
+                }
+            }
+            None => {
+                seen.insert(path.to_lowercase(), (path, tu));
+            }
+        }
+    }
+
+    errors
+}
+
+// Structs and unions with the same qualified name, defined in different
+// translation units that are both in the include closure of some
+// translation unit, so that their generated C++ ends up in the same program.
+// When both definitions are visible in some translation unit, that is
+// already reported as a redeclaration, but if they only meet further down
+// the includes, like in the headers of a protocol and of one it manages,
+// then nothing else catches it, and the generated C++ has two definitions
+// of the same class. Files that never meet, like unrelated files checked
+// in one run, can reuse names.
+fn check_duplicate_compound_types(tus: &TUMap<TranslationUnit>) -> Errors {
+    let mut errors = Errors::none();
+    let mut tuids = tus.keys().collect::<Vec<_>>();
+    tuids.sort();
+
+    // The translation units that each one includes, directly or not,
+    // including itself.
+    let closures = tus
+        .keys()
+        .map(|tuid| {
+            let mut closure = HashSet::new();
+            let mut pending = vec![tuid];
+            while let Some(next) = pending.pop() {
+                if closure.insert(next) {
+                    if let Some(tu) = tus.get(next) {
+                        pending.extend(&tu.includes);
+                    }
+                }
+            }
+            (tuid, closure)
+        })
+        .collect::<Vec<_>>();
+    let in_one_closure = |a: &TUId, b: &TUId| {
+        closures
+            .iter()
+            .any(|(_, closure)| closure.contains(a) && closure.contains(b))
+    };
+
+    // Whether the definitions in two translation units are both visible in
+    // some translation unit.
+    let seen_together = |a: &TUId, b: &TUId| {
+        tus.iter().any(|(tuid, tu)| {
+            let visible = |x: &TUId| x == tuid || tu.includes.contains(x);
+            visible(a) && visible(b)
+        })
+    };
+
+    // The definitions so far of each name, since a definition may only
+    // clash with some of them.
+    let mut seen: HashMap<String, Vec<(&TUId, &Namespace)>> = HashMap::new();
+    for tuid in tuids {
+        let tu = &tus[tuid];
+        let compounds = tu
+            .structs
+            .iter()
+            .map(|s| ("struct", &s.0))
+            .chain(tu.unions.iter().map(|u| ("union", &u.0)));
+        for (kind, ns) in compounds {
+            let qname = ns.qname().to_string();
+            let others = seen.entry(qname.clone()).or_default();
+            let clash = others.iter().find(|&&(first_tuid, _)| {
+                first_tuid != tuid
+                    && in_one_closure(first_tuid, tuid)
+                    && !seen_together(first_tuid, tuid)
+            });
+            if let Some(&(first_tuid, first_ns)) = clash {
+                errors.push(
+                    Diagnostic::from_catalog(
+                        &ns.name.loc,
+                        "duplicate_definition",
+                        &[
+                            ("kind", &kind),
+                            ("name", &qname),
+                            ("file", &tus[first_tuid].file_name.display()),
+                        ],
+                    )
+                    .with_note(&first_ns.name.loc, "other definition is here"),
+                );
+            }
+            others.push((tuid, ns));
+        }
+    }
+
+    errors
+}
+
+// Protocols with the same name, defined in different translation units.
+// Two with the same qualified name generate the same classes, which is an
+// error. Two in different namespaces compile, but the message ID tables,
+// like the `PFooMsgStart' enum, and IPC logging only use the short name, so
+// they clash there, which is a warning.
+fn check_duplicate_protocols(tus: &TUMap<TranslationUnit>) -> Errors {
+    let mut errors = Errors::none();
+    let mut tuids = tus.keys().collect::<Vec<_>>();
+    tuids.sort();
+
+    let mut seen: HashMap<&str, Vec<(&TUId, &Namespace)>> = HashMap::new();
+    for tuid in tuids {
+        let ns = match &tus[tuid].protocol {
+            Some((ns, _)) => ns,
+            None => continue,
+        };
+        let others = seen.entry(&ns.name.id).or_default();
+        let same_qname = others
+            .iter()
+            .find(|(_, other)| other.qname().to_string() == ns.qname().to_string());
+        if let Some(&(first_tuid, first_ns)) = same_qname {
+            errors.push(
+                Diagnostic::from_catalog(
+                    &ns.name.loc,
+                    "duplicate_definition.protocol",
+                    &[
+                        ("protocol", &ns.qname()),
+                        ("file", &tus[first_tuid].file_name.display()),
+                    ],
+                )
+                .with_note(&first_ns.name.loc, "other definition is here"),
+            );
+        } else if let Some(&(first_tuid, first_ns)) = others.first() {
+            errors.push(
+                Diagnostic::from_catalog(
+                    &ns.name.loc,
+                    "duplicate_protocol_name",
+                    &[
+                        ("protocol", &ns.qname()),
+                        ("other", &first_ns.qname()),
+                        ("file", &tus[first_tuid].file_name.display()),
+                    ],
+                )
+                .with_note(
+                    &first_ns.name.loc,
+                    &format!("`{}' defined here", first_ns.qname()),
+                ),
+            );
+        }
+        others.push((tuid, ns));
+    }
+
+    errors
+}
+
+// A C++ type has to be forward declared the same way everywhere, so it
+// can't be imported with `using class' in one file and `using struct' in
+// another.
+fn check_cxx_type_kinds(tus: &TUMap<TranslationUnit>) -> Errors {
+    let mut errors = Errors::none();
+    let mut tuids = tus.keys().collect::<Vec<_>>();
+    tuids.sort();
+
+    let mut seen: HashMap<String, (CxxTypeKind, &UsingStmt)> = HashMap::new();
+    for tuid in tuids {
+        for u in &tus[tuid].using {
+            let kind = match u.kind {
+                Some(kind) => kind,
+                None => continue,
+            };
+            let name = u.cxx_type.to_string();
+            match seen.get(&name) {
+                Some(&(first_kind, first)) if first_kind != kind => {
+                    errors.push(
+                        Diagnostic::from_catalog(
+                            u.cxx_type.loc(),
+                            "using_kind",
+                            &[
+                                ("type", &name),
+                                ("kind", &kind),
+                                ("other_kind", &first_kind),
+                            ],
+                        )
+                        .with_note(first.cxx_type.loc(), "other import is here"),
+                    );
+                }
+                Some(_) => (),
+                None => {
+                    seen.insert(name, (kind, u));
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+// Basic checking that doesn't relate to types specifically.
+pub fn check_translation_unit(tu: &TranslationUnit) -> Result<(), IpdlError> {
+    check_translation_unit_errors(tu, &CheckOptions::default()).into_result(IpdlError::TypeCheck)
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Warn if the directories a file is in don't end with the namespaces of the
+// translation unit, like a protocol in `mozilla::dom' that isn't in a
+// `mozilla/dom' directory.
+fn check_namespace_dirs(tu: &TranslationUnit) -> Errors {
+    let namespaces = &tu.namespace.namespaces;
+    let dirs = tu
+        .file_name
+        .parent()
+        .map(|p| {
+            p.components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if dirs.ends_with(namespaces) {
+        return Errors::none();
+    }
+    Errors::from_diagnostic(Diagnostic::from_catalog(
+        &tu.namespace.name.loc,
+        "namespace_dirs",
+        &[
+            ("name", &tu.namespace.name.id),
+            ("namespace", &namespaces.join("::")),
+            ("dir", &namespaces.join("/")),
+        ],
+    ))
+}
+
+// An include with a qualified name, like `include protocol mozilla::dom::PFoo;',
+// finds the file by turning the namespaces into directories, so the protocol
+// in that file has to be in those namespaces. A header can declare things in
+// several namespaces, so only the directories are used for it.
+fn check_include_namespaces(tus: &TUMap<TranslationUnit>) -> Errors {
+    let mut errors = Errors::none();
+    for tu in tus.values() {
+        for (include, file) in tu.includes.iter().zip(&tu.include_files) {
+            let dirs = match file.id.rsplit_once('/') {
+                Some((dirs, _)) => dirs.split('/').collect::<Vec<_>>(),
+                None => continue,
+            };
+            let (ns, _) = match tus.get(include).and_then(|i| i.protocol.as_ref()) {
+                Some(p) => p,
+                None => continue,
+            };
+            if ns.namespaces == dirs {
+                continue;
+            }
+            errors.push(
+                Diagnostic::from_catalog(
+                    &file.loc,
+                    "include_namespace",
+                    &[
+                        ("dirs", &dirs.join("::")),
+                        ("protocol", &ns.name),
+                        ("namespace", &ns.namespaces.join("::")),
+                    ],
+                )
+                .with_note(&ns.name.loc, "protocol declared here"),
+            );
+        }
+    }
+    errors
+}
+
+// A protocol file has to define a protocol, and a header can't, which the
+// parser already checks for the files it parses. `header;' says that a file
+// is a header, so it can't be in a protocol file.
+fn check_file_kind(tu: &TranslationUnit) -> Errors {
+    let mut errors = Errors::none();
+    match (&tu.file_type, &tu.protocol) {
+        (FileType::Protocol, None) => errors.append_one(&tu.namespace.name.loc, NO_PROTOCOL),
+        (FileType::Header, Some((ns, _))) => errors.append_one(&ns.name.loc, PROTOCOL_IN_HEADER),
+        _ => (),
+    }
+    if let (FileType::Protocol, Some(marker)) = (&tu.file_type, &tu.header_marker) {
+        let file_name = tu
+            .file_name
+            .file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        errors.push(Diagnostic::from_catalog(
+            &marker.loc,
+            "file_name.header_marker",
+            &[("file", &file_name)],
+        ));
+    }
+    errors
+}
+
+fn check_translation_unit_errors(tu: &TranslationUnit, options: &CheckOptions) -> Errors {
+    let mut errors = check_file_kind(tu);
+    errors.append(check_cxx_identifiers(tu));
+    errors.append(check_tu_case_collisions(tu));
+
+    if options.check_namespace_dirs {
+        errors.append(check_namespace_dirs(tu));
+    }
+
+    if !options.check_file_names {
+        return errors;
+    }
+
+    if tu.file_type == FileType::Header {
+        // A header is included by its name, so the name has to be an
+        // identifier.
+        let stem = tu
+            .file_name
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if !is_identifier(&stem) {
+            errors.push(Diagnostic::from_catalog(
+                &tu.namespace.name.loc,
+                "file_name.header",
+                &[("stem", &stem)],
+            ));
+        }
+    }
+
+    if let &Some((ref ns, _)) = &tu.protocol {
+        // For a protocol file, the filename should match the
+        // protocol. (In the Python IPDL compiler, translation units have
+        // a separate "name" field that is checked here, but for protocol
+        // files the name is just the name of the protocol, and for
+        // non-protocols the name is derived from the file name, so this
+        // checking should be equivalent.)
+        let base_file_name = tu
+            .file_name
+            .file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let expected_file_name = ns.name.id.clone() + ".ipdl";
+        if base_file_name != expected_file_name {
+            errors.push(Diagnostic::from_catalog(
+                &tu.namespace.name.loc,
+                "file_name",
+                &[
+                    ("name", &tu.namespace.name.id),
+                    ("expected", &expected_file_name),
+                    ("actual", &base_file_name),
+                ],
+            ));
+        }
+    }
+
+    errors
+}
+
+// The most file descriptors that a value of type |t| can carry, or None if
+// there is no limit, because it goes through an array or a recursive type.
+// |on_path| holds the structs and unions that are being counted.
+fn max_fds(
+    t: &IPDLType,
+    tuts: &TUMap<TranslationUnitType>,
+    on_path: &mut Vec<(CompoundType, TypeRef)>,
+) -> Option<usize> {
+    let key = match t {
+        IPDLType::FDType(_) => return Some(1),
+        IPDLType::ArrayType(inner) => {
+            return match max_fds(inner, tuts, on_path)? {
+                0 => Some(0),
+                _ => None,
+            }
+        }
+        IPDLType::MaybeType(inner)
+        | IPDLType::UniquePtrType(inner)
+        | IPDLType::NotNullType(inner) => return max_fds(inner, tuts, on_path),
+        IPDLType::StructType(tr) => (CompoundType::Struct, tr.clone()),
+        IPDLType::UnionType(tr) => (CompoundType::Union, tr.clone()),
+        _ => return Some(0),
+    };
+    if on_path.contains(&key) {
+        // A recursive type only needs to be counted once, and if it
+        // carries any descriptors there can be any number of them.
+        return if carries_fds(t, tuts) { None } else { Some(0) };
+    }
+    let tr = key.1.clone();
+    on_path.push(key);
+    let mut counts = match t {
+        IPDLType::StructType(_) => tr.lookup_struct(tuts).fields.iter(),
+        _ => tr.lookup_union(tuts).components.iter(),
+    }
+    .map(|f| max_fds(f.lookup(tuts), tuts, on_path));
+    // A struct has all of its fields, but a union only one of its
+    // components.
+    let count = match t {
+        IPDLType::StructType(_) => counts.try_fold(0, |sum, c| Some(sum + c?)),
+        _ => counts.try_fold(0, |max, c| Some(max.max(c?))),
+    };
+    on_path.pop();
+    count
+}
+
+// Whether a value of type |t| can carry any file descriptors at all.
+fn carries_fds(t: &IPDLType, tuts: &TUMap<TranslationUnitType>) -> bool {
+    carries(t, tuts, |t| matches!(t, IPDLType::FDType(_)))
+}
+
+// Whether a value of type |t| can carry a value of a type that |is_leaf|
+// is true for, through arrays, structs, unions and the like.
+fn carries(
+    t: &IPDLType,
+    tuts: &TUMap<TranslationUnitType>,
+    is_leaf: fn(&IPDLType) -> bool,
+) -> bool {
+    fn visit(
+        t: &IPDLType,
+        tuts: &TUMap<TranslationUnitType>,
+        is_leaf: fn(&IPDLType) -> bool,
+        seen: &mut Vec<(CompoundType, TypeRef)>,
+    ) -> bool {
+        if is_leaf(t) {
+            return true;
+        }
+        let key = match t {
+            IPDLType::ArrayType(inner)
+            | IPDLType::MaybeType(inner)
+            | IPDLType::UniquePtrType(inner)
+            | IPDLType::NotNullType(inner) => return visit(inner, tuts, is_leaf, seen),
+            IPDLType::StructType(tr) => (CompoundType::Struct, tr.clone()),
+            IPDLType::UnionType(tr) => (CompoundType::Union, tr.clone()),
+            _ => return false,
+        };
+        if seen.contains(&key) {
+            return false;
+        }
+        let tr = key.1.clone();
+        seen.push(key);
+        match t {
+            IPDLType::StructType(_) => tr.lookup_struct(tuts).fields.iter(),
+            _ => tr.lookup_union(tuts).components.iter(),
+        }
+        .any(|f| visit(f.lookup(tuts), tuts, is_leaf, seen))
+    }
+    visit(t, tuts, is_leaf, &mut Vec::new())
+}
+
+fn carries_shmem(params: &[ParamTypeDef], tuts: &TUMap<TranslationUnitType>) -> bool {
+    params.iter().any(|p| {
+        carries(p.param_type.lookup(tuts), tuts, |t| {
+            matches!(t, IPDLType::ShmemType(_))
+        })
+    })
+}
+
+// The sides that allocate the Shmems that a message carries: the senders
+// for the ones in its parameters, and the receivers for the ones in its
+// reply.
+pub fn shmem_allocators(
+    mtype: &MessageTypeDef,
+    tuts: &TUMap<TranslationUnitType>,
+) -> Vec<ProtocolSide> {
+    let mut sides = Vec::new();
+    if carries_shmem(&mtype.params, tuts) {
+        sides.extend(senders(mtype.direction));
+    }
+    if carries_shmem(&mtype.returns, tuts) {
+        sides.extend(receivers(mtype.direction));
+    }
+    [ProtocolSide::Parent, ProtocolSide::Child]
+        .iter()
+        .filter(|side| sides.contains(side))
+        .cloned()
+        .collect()
+}
+
+// The kind of value that a compressed message can't carry: one that owns
+// a resource, which is lost when the message is coalesced with a later one.
+fn resource_kind(t: &IPDLType) -> Option<&'static str> {
+    match t {
+        IPDLType::ShmemType(_) => Some("a Shmem"),
+        IPDLType::FDType(_) => Some("a FileDescriptor"),
+        IPDLType::EndpointType(_) => Some("an Endpoint"),
+        IPDLType::ManagedEndpointType(_, _) => Some("a ManagedEndpoint"),
+        _ => None,
+    }
+}
+
+// The first resource that a value of type |t| can carry, and the structs
+// and unions it is found through, like `struct Foo', `field bar'.
+fn find_resource(
+    t: &IPDLType,
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+    path: &mut Vec<String>,
+    on_path: &mut Vec<(CompoundType, TypeRef)>,
+) -> Option<&'static str> {
+    if let Some(kind) = resource_kind(t) {
+        return Some(kind);
+    }
+    match t {
+        IPDLType::ArrayType(inner)
+        | IPDLType::MaybeType(inner)
+        | IPDLType::UniquePtrType(inner)
+        | IPDLType::NotNullType(inner) => find_resource(inner, tus, tuts, path, on_path),
+        IPDLType::StructType(tr) if !on_path.contains(&(CompoundType::Struct, tr.clone())) => {
+            let sdef = tr.lookup_struct(tuts);
+            let fields = &tus[&tr.tu].structs[tr.index].2;
+            on_path.push((CompoundType::Struct, tr.clone()));
+            path.push(format!("struct {}", sdef.qname.short_name()));
+            for (f, ft) in fields.iter().zip(sdef.field_types(tuts)) {
+                path.push(format!("field {}", f.name));
+                if let Some(kind) = find_resource(ft, tus, tuts, path, on_path) {
+                    return Some(kind);
+                }
+                path.pop();
+            }
+            path.pop();
+            on_path.pop();
+            None
+        }
+        IPDLType::UnionType(tr) if !on_path.contains(&(CompoundType::Union, tr.clone())) => {
+            let udef = tr.lookup_union(tuts);
+            on_path.push((CompoundType::Union, tr.clone()));
+            path.push(format!("union {}", udef.qname.short_name()));
+            for c in udef.component_types(tuts) {
+                path.push(format!("variant {}", c.name(tuts)));
+                if let Some(kind) = find_resource(c, tus, tuts, path, on_path) {
+                    return Some(kind);
+                }
+                path.pop();
+            }
+            path.pop();
+            on_path.pop();
+            None
+        }
+        _ => None,
+    }
+}
+
+// A compressed message can be dropped in favor of a later one of the same
+// type, and whatever the dropped one carries is dropped with it, so
+// messages that carry Shmems, file descriptors or endpoints can't be
+// compressed.
+fn check_compressed_resources(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+) -> Errors {
+    let mut errors = Errors::none();
+    for tut in tuts.values() {
+        let ptype = match &tut.protocol {
+            Some(ptype) => ptype,
+            None => continue,
+        };
+        for mtype in ptype
+            .messages
+            .iter()
+            .filter(|m| m.compress != Compress::None)
+        {
+            let params = mtype
+                .params
+                .iter()
+                .map(|p| ("parameter", p))
+                .chain(mtype.returns.iter().map(|p| ("return value", p)));
+            for (what, param) in params {
+                let mut path = Vec::new();
+                let kind = match find_resource(
+                    param.param_type.lookup(tuts),
+                    tus,
+                    tuts,
+                    &mut path,
+                    &mut Vec::new(),
+                ) {
+                    Some(kind) => kind,
+                    None => continue,
+                };
+                let note = if path.is_empty() {
+                    format!("{} `{}' is {}", what, param.name.id, kind)
+                } else {
+                    format!(
+                        "{} `{}' holds {} in {}",
+                        what,
+                        param.name.id,
+                        kind,
+                        path.join(" → ")
+                    )
+                };
+                errors.push(
+                    Diagnostic::from_catalog(
+                        &mtype.name.loc,
+                        "compress.coalesced",
+                        &[
+                            ("message", &mtype.name.id),
+                            ("protocol", &ptype.qname.short_name()),
+                            ("kind", &kind),
+                        ],
+                    )
+                    .with_note(&param.name.loc, &note),
+                );
+                break;
+            }
+        }
+    }
+    errors
+}
+
+// Check the messages of protocols with an `[AllocShmem]' attribute against
+// it.
+fn check_alloc_shmem(tus: &TUMap<TranslationUnit>, tuts: &TUMap<TranslationUnitType>) -> Errors {
+    let mut errors = Errors::none();
+    for (tuid, tut) in tuts {
+        let ptype = match &tut.protocol {
+            Some(ptype) => ptype,
+            None => continue,
+        };
+        let allowed = match &ptype.alloc_shmem {
+            Some(allowed) => allowed,
+            None => continue,
+        };
+        let attr_loc = match tus
+            .get(tuid)
+            .and_then(|tu| tu.protocol.as_ref())
+            .and_then(|p| p.1.attributes.get("AllocShmem"))
+        {
+            Some((attr_loc, _)) => attr_loc,
+            None => {
+                errors.push(Diagnostic::internal(
+                    ptype.qname.loc(),
+                    &format!(
+                        "protocol `{}' has no [AllocShmem] attribute",
+                        ptype.qname.short_name()
+                    ),
+                ));
+                continue;
+            }
+        };
+        let allowed_name = match allowed.as_slice() {
+            [] => "None",
+            [ProtocolSide::Parent] => "Parent",
+            [ProtocolSide::Child] => "Child",
+            _ => "Both",
+        };
+        for mtype in &ptype.messages {
+            for (what, params, sides) in [
+                ("parameters", &mtype.params, senders(mtype.direction)),
+                ("reply", &mtype.returns, receivers(mtype.direction)),
+            ] {
+                if !carries_shmem(params, tuts) {
+                    continue;
+                }
+                for side in sides.iter().filter(|side| !allowed.contains(side)) {
+                    errors.push(
+                        Diagnostic::from_catalog(
+                            &mtype.name.loc,
+                            "alloc_shmem",
+                            &[
+                                ("message", &mtype.name.id),
+                                ("side", &side_name(*side)),
+                                ("what", &what),
+                                ("protocol", &ptype.qname.short_name()),
+                                ("allowed", &allowed_name),
+                            ],
+                        )
+                        .with_note(
+                            attr_loc,
+                            "the sides that may allocate Shmems are given here",
+                        ),
+                    );
+                }
+            }
+        }
+    }
+    errors
+}
+
+// Warn about messages that can carry more file descriptors than the IPC
+// transport allows, which otherwise only fails when the message is sent.
+fn check_fd_limits(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+    options: &CheckOptions,
+) -> Errors {
+    let mut errors = Errors::none();
+    let max_fds_per_message = match options.max_fds {
+        Some(max) => max,
+        None => return errors,
+    };
+
+    for (tuid, tut) in tuts {
+        let (ptype, p) = match (&tut.protocol, &tus[tuid].protocol) {
+            (Some(ptype), Some((_, p))) => (ptype, p),
+            _ => continue,
+        };
+        for (mtype, md) in ptype.messages.iter().zip(p.messages.iter()) {
+            if allows_warning(&md.attributes, "too_many_fds") {
+                continue;
+            }
+            // The parameters and the reply are separate messages.
+            for (what, params) in [("parameters", &mtype.params), ("reply", &mtype.returns)] {
+                let count = params.iter().try_fold(0, |sum, p| {
+                    Some(sum + max_fds(p.param_type.lookup(tuts), tuts, &mut Vec::new())?)
+                });
+                let args: &[(&str, &dyn fmt::Display)] = &[
+                    ("what", &what),
+                    ("message", &mtype.name.id),
+                    ("limit", &max_fds_per_message),
+                ];
+                let diagnostic = match count {
+                    Some(count) if count > max_fds_per_message => Diagnostic::from_catalog(
+                        &mtype.name.loc,
+                        "too_many_fds",
+                        &[args, &[("count", &count)]].concat(),
+                    ),
+                    Some(_) => continue,
+                    None => {
+                        Diagnostic::from_catalog(&mtype.name.loc, "too_many_fds.unbounded", args)
+                    }
+                };
+                errors.push(diagnostic);
+            }
+        }
+    }
+
+    errors
+}
+
+// The number of bytes that a value of a builtin type is written as.
+pub fn builtin_size(name: &str) -> Option<usize> {
+    Some(match name {
+        "bool" | "char" | "::int8_t" | "::uint8_t" => 1,
+        "short" | "::int16_t" | "::uint16_t" => 2,
+        "int" | "float" | "::int32_t" | "::uint32_t" | "::nsresult" => 4,
+        "long" | "double" | "::int64_t" | "::uint64_t" | "::intptr_t" | "::uintptr_t"
+        | "::size_t" | "::ssize_t" => 8,
+        _ => return None,
+    })
+}
+
+// The fewest and the most bytes that a value of type |t| is written as,
+// roughly, or None for the most if there is no limit, because it goes
+// through a string, an array, a recursive type or an imported type whose
+// size isn't known. Lengths, union tags and actor IDs count as 4 bytes,
+// and the tag of a Maybe as 1. Shmems, file descriptors and endpoints are
+// sent next to the message, so they don't count.
+fn size_range(
+    t: &IPDLType,
+    tuts: &TUMap<TranslationUnitType>,
+    on_path: &mut Vec<(CompoundType, TypeRef)>,
+) -> (usize, Option<usize>) {
+    let key = match t {
+        IPDLType::BuiltinCType(name) => {
+            let size = builtin_size(name).unwrap_or(0);
+            return (size, Some(size));
+        }
+        IPDLType::ImportedCxxType(qid, _, _, _) => {
+            let name = qid.to_string();
+            return match builtin_size(&name) {
+                Some(size) => (size, Some(size)),
+                None if matches!(
+                    name.as_str(),
+                    "::nsString"
+                        | "::nsCString"
+                        | "::nsDependentSubstring"
+                        | "::nsDependentCSubstring"
+                ) =>
+                {
+                    (4, None)
+                }
+                None => (0, None),
+            };
+        }
+        IPDLType::ByteBufType(_) | IPDLType::ArrayType(_) => return (4, None),
+        IPDLType::ActorType(_) => return (4, Some(4)),
+        IPDLType::MaybeType(inner) | IPDLType::UniquePtrType(inner) => {
+            let (_, max) = size_range(inner, tuts, on_path);
+            return (1, max.map(|max| max + 1));
+        }
+        IPDLType::NotNullType(inner) => return size_range(inner, tuts, on_path),
+        IPDLType::StructType(tr) => (CompoundType::Struct, tr.clone()),
+        IPDLType::UnionType(tr) => (CompoundType::Union, tr.clone()),
+        _ => return (0, Some(0)),
+    };
+    if on_path.contains(&key) {
+        return (0, None);
+    }
+    let tr = key.1.clone();
+    on_path.push(key);
+    let sizes = match t {
+        IPDLType::StructType(_) => tr.lookup_struct(tuts).fields.iter(),
+        _ => tr.lookup_union(tuts).components.iter(),
+    }
+    .map(|f| size_range(f.lookup(tuts), tuts, on_path))
+    .collect::<Vec<_>>();
+    on_path.pop();
+    // A struct has all of its fields, but a union only one of its
+    // components, after its tag.
+    match t {
+        IPDLType::StructType(_) => sizes.iter().fold((0, Some(0)), |(min, max), s| {
+            (min + s.0, max.and_then(|max| Some(max + s.1?)))
+        }),
+        _ => sizes.iter().fold((usize::MAX, Some(0)), |(min, max), s| {
+            (
+                min.min(s.0 + 4),
+                max.and_then(|max| Some(max.max(s.1? + 4))),
+            )
+        }),
+    }
+}
+
+// Check the `[MaxSize]' of messages against the sizes of their parameters
+// and replies, and with a --max-message-size, warn about the messages that
+// can be larger than it. A message can declare a `[MaxSize]' within the
+// limit to say that it is never that large, even though its types could be.
+fn check_message_sizes(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+    options: &CheckOptions,
+) -> Errors {
+    let mut errors = Errors::none();
+
+    for (tuid, tut) in tuts {
+        let (ptype, p) = match (&tut.protocol, &tus[tuid].protocol) {
+            (Some(ptype), Some((_, p))) => (ptype, p),
+            _ => continue,
+        };
+        for (mtype, md) in ptype.messages.iter().zip(p.messages.iter()) {
+            // The parameters and the reply are separate messages.
+            for (what, params) in [("parameters", &mtype.params), ("reply", &mtype.returns)] {
+                let (min, max) = params.iter().fold((0, Some(0)), |(min, max), p| {
+                    let s = size_range(p.param_type.lookup(tuts), tuts, &mut Vec::new());
+                    (min + s.0, max.and_then(|max| Some(max + s.1?)))
+                });
+                let limit = match (mtype.max_size, options.max_message_size) {
+                    (Some(max_size), _) => {
+                        if min > max_size as usize {
+                            errors.push(Diagnostic::from_catalog(
+                                md.attributes.get("MaxSize").map_or(&md.name.loc, |a| &a.0),
+                                "attribute.max_size_exceeded",
+                                &[
+                                    ("message", &mtype.name.id),
+                                    ("size", &min),
+                                    ("what", &what),
+                                    ("max_size", &max_size),
+                                ],
+                            ));
+                        }
+                        continue;
+                    }
+                    (None, Some(limit)) => limit,
+                    (None, None) => continue,
+                };
+                if allows_warning(&md.attributes, "large_message") {
+                    continue;
+                }
+                let args: &[(&str, &dyn fmt::Display)] = &[
+                    ("what", &what),
+                    ("message", &mtype.name.id),
+                    ("limit", &limit),
+                ];
+                let diagnostic = match max {
+                    Some(max) if max > limit => Diagnostic::from_catalog(
+                        &mtype.name.loc,
+                        "large_message",
+                        &[args, &[("size", &max)]].concat(),
+                    ),
+                    Some(_) => continue,
+                    None => {
+                        Diagnostic::from_catalog(&mtype.name.loc, "large_message.unbounded", args)
+                    }
+                };
+                errors.push(diagnostic);
+            }
+        }
+    }
+
+    errors
+}
+
+// Warn about sync messages whose reply is probably only a success flag
+// that nobody checks, because making them async would save a round trip.
+fn check_unused_sync_returns(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+) -> Errors {
+    let mut errors = Errors::none();
+    for (tuid, tut) in tuts {
+        let (ptype, p) = match (&tut.protocol, &tus[tuid].protocol) {
+            (Some(ptype), Some((_, p))) => (ptype, p),
+            _ => continue,
+        };
+        for (mtype, md) in ptype.messages.iter().zip(p.messages.iter()) {
+            if allows_warning(&md.attributes, "unused_sync_return") {
+                continue;
+            }
+            if let Some(ret) = mtype.unused_sync_return(tuts) {
+                errors.push(Diagnostic::from_catalog(
+                    &mtype.name.loc,
+                    "unused_sync_return",
+                    &[("message", &mtype.name.id), ("return", &ret.name)],
+                ));
+            }
+        }
+    }
+    errors
+}
+
+// Whether two types are the same. Imported C++ types are compared by their
+// canonical names, so a type that is imported under two aliases is the same
+// type.
+fn same_type(a: &IPDLType, b: &IPDLType) -> bool {
+    use self::IPDLType::*;
+    match (a, b) {
+        (BuiltinCType(a), BuiltinCType(b)) => a == b,
+        (ImportedCxxType(a, _, _, _), ImportedCxxType(b, _, _, _))
+        | (ShmemType(a), ShmemType(b))
+        | (ByteBufType(a), ByteBufType(b))
+        | (FDType(a), FDType(b))
+        | (EndpointType(a), EndpointType(b)) => a.to_string() == b.to_string(),
+        (ManagedEndpointType(a, _), ManagedEndpointType(b, _)) => a.to_string() == b.to_string(),
+        (MessageType(a), MessageType(b))
+        | (StructType(a), StructType(b))
+        | (UnionType(a), UnionType(b)) => a == b,
+        (ProtocolType(a), ProtocolType(b)) | (ActorType(a), ActorType(b)) => a == b,
+        (ArrayType(a), ArrayType(b))
+        | (MaybeType(a), MaybeType(b))
+        | (UniquePtrType(a), UniquePtrType(b))
+        | (NotNullType(a), NotNullType(b)) => same_type(a, b),
+        _ => false,
+    }
+}
+
+fn same_param_types(
+    tuts: &TUMap<TranslationUnitType>,
+    a: &[ParamTypeDef],
+    b: &[ParamTypeDef],
+) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .all(|(a, b)| same_type(a.param_type.lookup(tuts), b.param_type.lookup(tuts)))
+}
+
+// Point out pairs of async messages, like `FooToParent' and `FooToChild',
+// that only differ in their direction, which could be one message in a
+// `both:' section. Only messages whose names are the same once `Parent' and
+// `Child' are taken out of them are compared, because plenty of unrelated
+// messages have no parameters.
+fn check_mirrored_messages(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+) -> Errors {
+    let mut errors = Errors::none();
+    let direction_free = |name: &str| name.replace("Parent", "\0").replace("Child", "\0");
+    for (tuid, tut) in tuts {
+        let (ptype, p) = match (&tut.protocol, &tus[tuid].protocol) {
+            (Some(ptype), Some((_, p))) => (ptype, p),
+            _ => continue,
+        };
+        let candidates = ptype
+            .messages
+            .iter()
+            .zip(p.messages.iter())
+            .filter(|(mtype, md)| {
+                mtype.is_async()
+                    && matches!(mtype.mtype, MessageType::Other)
+                    && mtype.direction != Direction::Both
+                    && !allows_warning(&md.attributes, "mirrored_messages")
+            })
+            .map(|(mtype, _)| mtype)
+            .collect::<Vec<_>>();
+        for (i, second) in candidates.iter().enumerate() {
+            let first = candidates[..i].iter().find(|first| {
+                first.direction != second.direction
+                    && direction_free(&first.name.id) == direction_free(&second.name.id)
+                    && first.nested == second.nested
+                    && first.prio == second.prio
+                    && same_param_types(tuts, &first.params, &second.params)
+                    && same_param_types(tuts, &first.returns, &second.returns)
+            });
+            if let Some(first) = first {
+                errors.push(
+                    Diagnostic::from_catalog(
+                        &second.name.loc,
+                        "mirrored_messages",
+                        &[("message", &second.name.id), ("other", &first.name.id)],
+                    )
+                    .with_note(
+                        &first.name.loc,
+                        &format!("`{}' declared here", first.name.id),
+                    ),
+                );
+            }
+        }
+    }
+    errors
+}
+
+// A header is only useful for the structs, unions and imported types that
+// it declares, so warn about an included header that none of the files
+// that include it use anything from. Every file has to declare at least one
+// struct, union or protocol, so a header that declares nothing at all is a
+// syntax error already. Includes aren't transitive, so only the files that
+// include the header directly can use it. Imported types are matched by
+// their canonical names, so a type that the including file also imports
+// itself counts as used.
+fn check_unused_includes(
+    tus: &TUMap<TranslationUnit>,
+    tuts: &TUMap<TranslationUnitType>,
+    aliases: &HashMap<String, String>,
+) -> Errors {
+    let mut errors = Errors::none();
+    for (tuid, tu) in tus {
+        if tu.file_type != FileType::Header {
+            continue;
+        }
+        let usings = tu
+            .using
+            .iter()
+            .map(|u| canonical_cxx_type(&u.cxx_type, aliases).to_string())
+            .collect::<HashSet<_>>();
+        let uses = |t: &IPDLType| match t {
+            IPDLType::StructType(tr) | IPDLType::UnionType(tr) => tr.tu == *tuid,
+            IPDLType::ImportedCxxType(qid, _, _, _) => usings.contains(&qid.to_string()),
+            _ => false,
+        };
+        let mut includes = Vec::new();
+        let mut used = false;
+        for (other, other_tu) in tus {
+            let include = match other_tu.includes.iter().position(|i| i == tuid) {
+                Some(i) => &other_tu.include_files[i],
+                None => continue,
+            };
+            includes.push(include);
+            let tut = match tuts.get(other) {
+                Some(tut) => tut,
+                None => continue,
+            };
+            for mut t in tut.types.iter() {
+                while let IPDLType::ArrayType(inner)
+                | IPDLType::MaybeType(inner)
+                | IPDLType::UniquePtrType(inner)
+                | IPDLType::NotNullType(inner) = t
+                {
+                    t = inner;
+                }
+                used |= uses(t);
+            }
+        }
+        if used || includes.is_empty() {
+            continue;
+        }
+        let header = tu
+            .file_name
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut diagnostic =
+            Diagnostic::from_catalog(&includes[0].loc, "unused_include", &[("header", &header)]);
+        for include in &includes[1..] {
+            diagnostic = diagnostic.with_note(&include.loc, "also included here");
+        }
+        errors.push(diagnostic);
+    }
+    errors
+}
+
+// Settings for checking. Most of these are for heuristics rather than
+// rules, which only ever produce warnings.
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    // Warn about protocols whose chain of managers is more than this many
+    // levels deep.
+    pub max_manager_depth: Option<usize>,
+    // Warn about protocols that manage more than this many protocols.
+    pub max_managees: Option<usize>,
+    // Warn about messages with more than this many parameters.
+    pub max_params: Option<usize>,
+    // Warn about messages whose parameters or reply can carry more than
+    // this many file descriptors.
+    pub max_fds: Option<usize>,
+    // Warn about messages whose parameters or reply can be larger than
+    // this many bytes, unless they declare a `[MaxSize]' within it.
+    pub max_message_size: Option<usize>,
+    // The names that are defined for `#if' conditions. Every branch is
+    // parsed, but only the declarations in the branches whose conditions
+    // hold are type checked, so that the branches of one condition can
+    // declare the same names.
+    pub defines: HashSet<String>,
+    // Whether a protocol file has to be named after its protocol, and a
+    // header has to have a name that can be included. This can be turned
+    // off for generated or temporary files.
+    pub check_file_names: bool,
+    // Warn about files that aren't in a directory matching their
+    // namespace.
+    pub check_namespace_dirs: bool,
+    // Warn about structs with the same fields as a struct in another file.
+    pub check_duplicate_structs: bool,
+    // Whether to look for `ipdl.toml' files next to the files and in the
+    // directories above them, and apply their settings.
+    pub use_config_files: bool,
+    // Whether to check the structs and unions of headers that are included
+    // but that nothing uses. Turning this off saves time when checking one
+    // protocol that includes many headers, but errors in the unused
+    // headers are only found when they are checked on their own.
+    pub check_unused_headers: bool,
+    // Other names for imported C++ types, like `nsString' for
+    // `mozilla::nsString', from each alias to the type it names. A type is
+    // the same whichever of its names a `using' spells it with. The names
+    // don't have a leading `::'.
+    pub type_aliases: HashMap<String, String>,
+    // Limits on the size of the input.
+    pub limits: InputLimits,
+    // Record where each symbol is used, in TranslationUnitType::symbols,
+    // for tools that find references or rename things. This costs a
+    // location for every type name in the program, so it is off by
+    // default.
+    pub track_references: bool,
+    // The levels of warnings by code, like the `[lints]' of a config file,
+    // which take precedence over the config files.
+    pub lint_levels: HashMap<String, LintLevel>,
+    // Also warn about intr protocols and about toplevel protocols without
+    // a [ParentProc], which too much existing code would trip over to do
+    // by default.
+    pub strict: bool,
+    // Only report the first few diagnostics with the same code and symbol;
+    // see errors::limit_diagnostics. A warning baseline needs all of them,
+    // to tell the repeats that are new from the ones it already has.
+    pub limit_repeats: bool,
+    // The sync messages that may be declared, like `PContent::SyncMsg', for
+    // every file, as ipdl.py's sync message list gives them. This is used
+    // instead of the `[sync]' lists of the config files.
+    pub sync_messages: Option<Vec<String>>,
+}
+
+// The codes of the warnings that `--strict' reports as errors.
+pub const STRICT_LINTS: &[&str] = &["intr", "sync_without_returns", "toplevel_process"];
+
+impl Default for CheckOptions {
+    fn default() -> CheckOptions {
+        CheckOptions {
+            max_manager_depth: None,
+            max_managees: None,
+            max_params: Some(14),
+            max_fds: None,
+            max_message_size: None,
+            defines: HashSet::new(),
+            check_file_names: true,
+            check_namespace_dirs: false,
+            check_duplicate_structs: false,
+            use_config_files: false,
+            check_unused_headers: true,
+            type_aliases: HashMap::new(),
+            limits: InputLimits::default(),
+            track_references: false,
+            lint_levels: HashMap::new(),
+            strict: false,
+            limit_repeats: true,
+            sync_messages: None,
+        }
+    }
+}
+
+// The declarations gathered for each translation unit in an earlier run,
+// for tools that check the same files again after an edit. Gathering a
+// translation unit only looks at its own AST and the ASTs of the files it
+// includes directly, so the results can be reused as long as none of those
+// have changed. The located fingerprints are used, because the results
+// contain locations.
+#[derive(Default)]
+pub struct GatherCache {
+    entries: TUMap<GatherEntry>,
+    hits: usize,
+    lookups: usize,
+}
+
+struct GatherEntry {
+    key: u64,
+    tut: TranslationUnitType,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl GatherCache {
+    pub fn new() -> GatherCache {
+        GatherCache::default()
+    }
+
+    // How many translation units were reused instead of gathered again.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    // How many translation units were looked up in the cache, whether or
+    // not they were in it.
+    pub fn lookups(&self) -> usize {
+        self.lookups
+    }
+
+    fn key(
+        tus: &TUMap<TranslationUnit>,
+        tuid: &TUId,
+        tu: &TranslationUnit,
+        options: &CheckOptions,
+    ) -> u64 {
+        let mut h = StableHasher::new();
+        h.write_u8(options.track_references as u8);
+        let mut aliases = options.type_aliases.iter().collect::<Vec<_>>();
+        aliases.sort();
+        for (alias, canonical) in aliases {
+            h.write_str(alias);
+            h.write_str(canonical);
+        }
+        h.write_i32(*tuid);
+        h.write_u64(tu.located_fingerprint());
+        for include in &tu.includes {
+            h.write_i32(*include);
+            h.write_u64(tus.get(include).map_or(0, |tu| tu.located_fingerprint()));
+        }
+        h.finish()
+    }
+}
+
+thread_local! {
+    // Whether this thread is running a guarded pass, whose panics are
+    // reported as diagnostics rather than by the panic hook.
+    static IN_GUARDED_PASS: Cell<bool> = Cell::new(false);
+}
+
+static QUIET_PANIC_HOOK: Once = Once::new();
+
+// Run a pass of the checker. The passes assume things that earlier passes
+// have checked, so a mistake in the checker can still make one panic on
+// input it didn't anticipate. That is turned into the internal error that
+// |report| makes from the reason for the panic, and checking carries on
+// with the other passes. The panic hook that would print the panic, and
+// maybe a backtrace, is skipped for it.
+fn run_guarded<F, R>(pass: F, report: R) -> Errors
+where
+    F: FnOnce() -> Errors,
+    R: FnOnce(&str) -> Diagnostic,
+{
+    QUIET_PANIC_HOOK.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !IN_GUARDED_PASS.with(|guarded| guarded.get()) {
+                hook(info);
+            }
+        }));
+    });
+    let was_guarded = IN_GUARDED_PASS.with(|guarded| guarded.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(pass));
+    IN_GUARDED_PASS.with(|guarded| guarded.set(was_guarded));
+    result.unwrap_or_else(|payload| {
+        let reason = payload
+            .downcast_ref::<&str>()
+            .map(|s| String::from(*s))
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("unknown panic"));
+        Errors::from_diagnostic(report(&reason))
+    })
+}
+
+// Run one pass of the checker over a translation unit, reporting a panic
+// as an internal error in the file.
+fn guard_pass<F>(tu: &TranslationUnit, pass: F) -> Errors
+where
+    F: FnOnce() -> Errors,
+{
+    run_guarded(pass, |reason| {
+        Diagnostic::internal(
+            &tu.namespace.name.loc,
+            &format!("checking this file failed: {}", reason),
+        )
+    })
+}
+
+// Run one pass of the checker over all of the translation units at once,
+// reporting a panic as an internal error that isn't in any one file.
+fn guard_program_pass<F>(name: &str, pass: F) -> Errors
+where
+    F: FnOnce() -> Errors,
+{
+    run_guarded(pass, |reason| {
+        Diagnostic::without_location(&format!(
+            "internal error: the {} pass failed: {}",
+            name, reason
+        ))
+    })
+}
+
+// Type check the translation units, and return their typed IR.
+pub fn check(tus: &TUMap<TranslationUnit>) -> Result<TUMap<TranslationUnitType>, IpdlError> {
+    check_with_warnings(tus, &CheckOptions::default()).map(|(tuts, _)| tuts)
+}
+
+// Like check, but also return any warnings.
+pub fn check_with_warnings(
+    tus: &TUMap<TranslationUnit>,
+    options: &CheckOptions,
+) -> Result<(TUMap<TranslationUnitType>, Vec<Diagnostic>), IpdlError> {
+    check_with_cache(tus, options, &mut GatherCache::new())
+}
+
+// Like check_with_warnings, but reuse the declarations in |cache| for the
+// translation units that haven't changed, and record the new ones in it.
+pub fn check_with_cache(
+    tus: &TUMap<TranslationUnit>,
+    options: &CheckOptions,
+    cache: &mut GatherCache,
+) -> Result<(TUMap<TranslationUnitType>, Vec<Diagnostic>), IpdlError> {
+    check_with_events(tus, options, cache, &mut NoEvents)
+}
+
+// Like check_with_cache, but whether each translation unit was found in
+// |cache| is reported to |events|.
+pub fn check_with_events(
+    tus: &TUMap<TranslationUnit>,
+    options: &CheckOptions,
+    cache: &mut GatherCache,
+    events: &mut dyn CompilerEvents,
+) -> Result<(TUMap<TranslationUnitType>, Vec<Diagnostic>), IpdlError> {
+    let mut tuts = TUMap::new();
+
+    // Each translation unit is visited after the ones it includes, so the
+    // passes see files in the same order however they were given.
+    let order = IncludeGraph::new(tus).topological_order();
+    let tus_vec = order
+        .iter()
+        .map(|tuid| (tuid, &tus[tuid]))
+        .collect::<Vec<_>>();
+    let mut errors = Errors::none();
+
+    // XXX Should we get all errors first? Probably...
+    for &(tuid, tu) in &tus_vec {
+        errors.append(guard_pass(tu, || {
+            check_translation_unit_errors(tu, options)
+        }));
+
+        // Create top-level type decl for all protocols. |tus| has one
+        // entry per TUId, and the parser gives a file that is named twice
+        // one TUId, so nothing is replaced here.
+        tuts.insert(*tuid, TranslationUnitType::new(&tu.protocol));
+    }
+
+    errors.append(guard_program_pass("check_header_case_collisions", || {
+        check_header_case_collisions(tus)
+    }));
+    errors.append(guard_program_pass("check_duplicate_compound_types", || {
+        check_duplicate_compound_types(tus)
+    }));
+    errors.append(guard_program_pass("check_duplicate_protocols", || {
+        check_duplicate_protocols(tus)
+    }));
+    errors.append(guard_program_pass("check_cxx_type_kinds", || {
+        check_cxx_type_kinds(tus)
+    }));
+    errors.append(guard_program_pass("check_include_namespaces", || {
+        check_include_namespaces(tus)
+    }));
+
+    // Bail out here if we have errors.
+    //errors.to_result()?;
+
+    cache.entries.retain(|tuid, _| tus.contains_key(tuid));
+    for &(tuid, tu) in &tus_vec {
+        let key = GatherCache::key(tus, tuid, tu, options);
+        cache.lookups += 1;
+        let entry = cache.entries.get(tuid).filter(|e| e.key == key);
+        events.cache_lookup(&tu.file_name, entry.is_some());
+        // Whether a missing type is declared by a file that isn't included
+        // depends on every other file, not only on what the key covers, so
+        // that is worked out after the cache.
+        if let Some(entry) = entry {
+            tuts.insert(*tuid, entry.tut.clone());
+            let mut gathered = Errors::none();
+            for d in &entry.diagnostics {
+                gathered.push(d.clone());
+            }
+            errors.append(report_not_included(tus, tuid, tu, gathered));
+            cache.hits += 1;
+            continue;
+        }
+        let gathered = guard_pass(tu, || {
+            gather_decls_tu(
+                tus,
+                &mut tuts,
+                tuid,
+                tu,
+                &options.type_aliases,
+                options.track_references,
+            )
+        });
+        let entry = GatherEntry {
+            key,
+            tut: tuts[tuid].clone(),
+            diagnostics: gathered.diagnostics().to_vec(),
+        };
+        cache.entries.insert(*tuid, entry);
+        errors.append(report_not_included(tus, tuid, tu, gathered));
+    }
+
+    let tuts_vec = tuts.iter().collect::<Vec<_>>();
+    let used = if options.check_unused_headers {
+        None
+    } else {
+        Some(used_translation_units(tus, &tuts))
+    };
+    let mut defined = HashMap::new();
+    for &(tuid, tut) in &tuts_vec {
+        if used.as_ref().is_some_and(|used| !used.contains(tuid)) {
+            continue;
+        }
+        errors.append(guard_pass(&tus[tuid], || {
+            check_types_tu(tus, &tuts, &mut defined, tuid, tut)
+        }));
+    }
+
+    // This relies on the fields of the struct and union types matching up
+    // with the fields in the AST, which they don't if there were errors.
+    if !errors.has_errors() {
+        errors.append(guard_program_pass("check_actor_placement", || {
+            check_actor_placement(tus, &tuts)
+        }));
+        errors.append(guard_program_pass("check_plain_old_data", || {
+            check_plain_old_data(tus, &tuts)
+        }));
+        errors.append(guard_program_pass("check_orphaned_protocols", || {
+            check_orphaned_protocols(&tuts)
+        }));
+        errors.append(guard_program_pass("check_manager_limits", || {
+            check_manager_limits(&tuts, options)
+        }));
+        errors.append(guard_program_pass("check_param_limits", || {
+            check_param_limits(tus, &tuts, options)
+        }));
+        errors.append(guard_program_pass("check_strict", || {
+            check_strict(&tuts, options)
+        }));
+        errors.append(guard_program_pass("check_fd_limits", || {
+            check_fd_limits(tus, &tuts, options)
+        }));
+        errors.append(guard_program_pass("check_alloc_shmem", || {
+            check_alloc_shmem(tus, &tuts)
+        }));
+        errors.append(guard_program_pass("check_compressed_resources", || {
+            check_compressed_resources(tus, &tuts)
+        }));
+        errors.append(guard_program_pass("check_message_sizes", || {
+            check_message_sizes(tus, &tuts, options)
+        }));
+        errors.append(guard_program_pass("check_unused_sync_returns", || {
+            check_unused_sync_returns(tus, &tuts)
+        }));
+        errors.append(guard_program_pass("check_mirrored_messages", || {
+            check_mirrored_messages(tus, &tuts)
+        }));
+        errors.append(guard_program_pass("check_unused_includes", || {
+            check_unused_includes(tus, &tuts, &options.type_aliases)
+        }));
+        if options.check_duplicate_structs {
+            errors.append(guard_program_pass("check_duplicate_structs", || {
+                check_duplicate_structs(tus, &tuts)
+            }));
+        }
+    }
+
+    errors
+        .into_warnings_with_limit(IpdlError::TypeCheck, options.limit_repeats)
+        .map(|warnings| (tuts, warnings))
+}
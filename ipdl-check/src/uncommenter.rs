@@ -9,11 +9,19 @@ enum State {
 // I think this may produce different results for something like
 // "/**/*/". I think the regex version will turn it into "      ",
 // but the manual version will turn it into "    */".
+#[cfg(test)]
 pub fn uncomment(text: &str) -> String {
+    uncomment_checked(text).0
+}
+
+// Blank out comments, like uncomment, but also return the byte offset of the start of a `/*'
+// comment that is never closed, if any.
+pub fn uncomment_checked(text: &str) -> (String, Option<usize>) {
     let mut state = State::Default;
     let mut s = String::with_capacity(text.len());
+    let mut comment_start = 0;
 
-    for c in text.chars() {
+    for (i, c) in text.char_indices() {
         match (state, c) {
             (State::Default, '/') => state = State::StartComment,
             // As a hack to maximize our ability to process files like
@@ -41,6 +49,7 @@ pub fn uncomment(text: &str) -> String {
                 s.push(' ');
                 s.push(' ');
                 state = State::InMultilineComment;
+                comment_start = i - 1;
             }
             (State::StartComment, _) => {
                 s.push('/');
@@ -76,7 +85,10 @@ pub fn uncomment(text: &str) -> String {
         }
     }
 
-    s
+    match state {
+        State::InMultilineComment | State::EndingMultilineComment => (s, Some(comment_start)),
+        _ => (s, None),
+    }
 }
 
 #[test]
@@ -117,4 +129,9 @@ fn basic_tests() {
     assert_eq!(uncomment("/**x/y*/0"), "        0");
 
     assert_eq!(uncomment("/* ... **/123"), "          123");
+
+    assert_eq!(uncomment_checked("0/*12\n3*/0").1, None);
+    assert_eq!(uncomment_checked("0 /* 1 /* 2 */ 3").1, None);
+    assert_eq!(uncomment_checked("0\n/* 12\n3").1, Some(2));
+    assert_eq!(uncomment_checked("/**").1, Some(0));
 }
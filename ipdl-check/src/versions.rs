@@ -0,0 +1,234 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Checking that protocols bump their `[ProtocolVersion]' when they change,
+// so that processes built from different trees can tell whether they can
+// talk to each other.
+//
+// The older tree is described by the JSON that --export-json wrote for it.
+// A protocol whose messages were added, removed, or changed in direction,
+// send semantics or types must have a higher version than it had there,
+// and the messages it added must be `[Since]' a version after the old one.
+// Only the types of parameters matter, so renaming a parameter is not a
+// change. Protocols without a version in the newer tree aren't checked.
+
+use errors::Diagnostic;
+use export::{direction_name, semantics_name};
+use ir::TypedProgram;
+use json::{parse_json, JsonValue};
+use std::path::PathBuf;
+use type_check::{MessageTypeDef, ParamTypeDef};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OldMessage {
+    pub name: String,
+    // The parts of the message that the other side depends on.
+    pub signature: String,
+    pub since: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OldProtocol {
+    pub name: String,
+    pub version: Option<u32>,
+    pub messages: Vec<OldMessage>,
+}
+
+fn signature(semantics: &str, direction: &str, params: &[String], returns: &[String]) -> String {
+    format!(
+        "{} {}({}) returns ({})",
+        semantics,
+        direction,
+        params.join(", "),
+        returns.join(", ")
+    )
+}
+
+fn message_signature(program: &TypedProgram, md: &MessageTypeDef) -> String {
+    let types = |params: &[ParamTypeDef]| {
+        params
+            .iter()
+            .map(|p| p.param_type.lookup(&program.tuts).name(&program.tuts))
+            .collect::<Vec<_>>()
+    };
+    signature(
+        semantics_name(md.send_semantics),
+        direction_name(md.direction),
+        &types(&md.params),
+        &types(&md.returns),
+    )
+}
+
+fn optional_number(v: Option<&JsonValue>) -> Option<u32> {
+    match v {
+        Some(JsonValue::Number(n)) => Some(*n as u32),
+        _ => None,
+    }
+}
+
+// Parses the output of --export-json.
+pub fn parse_export(text: &str) -> Result<Vec<OldProtocol>, String> {
+    let value = parse_json(text)?;
+    let items = value
+        .get("protocols")
+        .and_then(JsonValue::as_array)
+        .ok_or("expected an object with a `protocols' list")?;
+    let mut protocols = Vec::new();
+    for item in items {
+        let name = item
+            .get("name")
+            .and_then(JsonValue::as_str)
+            .ok_or("every protocol needs a `name'")?;
+        let mut messages = Vec::new();
+        for m in item
+            .get("messages")
+            .and_then(JsonValue::as_array)
+            .unwrap_or(&[])
+        {
+            let field = |name| m.get(name).and_then(JsonValue::as_str).unwrap_or("");
+            let types = |name| {
+                m.get(name)
+                    .and_then(JsonValue::as_array)
+                    .unwrap_or(&[])
+                    .iter()
+                    .map(|p| String::from(p.get("type").and_then(JsonValue::as_str).unwrap_or("")))
+                    .collect::<Vec<_>>()
+            };
+            if field("name").is_empty() {
+                return Err(format!("a message of `{}' has no `name'", name));
+            }
+            messages.push(OldMessage {
+                name: String::from(field("name")),
+                signature: signature(
+                    field("send_semantics"),
+                    field("direction"),
+                    &types("params"),
+                    &types("returns"),
+                ),
+                since: optional_number(m.get("since")),
+            });
+        }
+        protocols.push(OldProtocol {
+            name: String::from(name),
+            version: optional_number(item.get("version")),
+            messages,
+        });
+    }
+    Ok(protocols)
+}
+
+// |program| must have been checked from |file_names|. Returns an error for
+// each protocol that changed without a new version, and for each message
+// whose `[Since]' doesn't fit.
+pub fn check_versions(
+    program: &TypedProgram,
+    file_names: &[PathBuf],
+    old: &[OldProtocol],
+) -> Vec<Diagnostic> {
+    let mut errors = Vec::new();
+    for file in file_names {
+        let tuid = program.tu_for_file(file).unwrap();
+        let ptype = match &program.tuts[&tuid].protocol {
+            Some(ptype) => ptype,
+            None => continue,
+        };
+        let qname = ptype.qname.to_string();
+        let old = match old.iter().find(|p| p.name == qname) {
+            Some(old) => old,
+            None => continue,
+        };
+        let short_name = ptype.qname.short_name();
+        let loc = ptype.qname.loc();
+        let version = match (ptype.version, old.version) {
+            (Some(version), _) => version,
+            (None, Some(old_version)) => {
+                errors.push(Diagnostic::new(
+                    loc,
+                    &format!(
+                        "protocol `{}' was version {}, but has no [ProtocolVersion] now",
+                        short_name, old_version
+                    ),
+                ));
+                continue;
+            }
+            (None, None) => continue,
+        };
+        let old_version = old.version.unwrap_or(0);
+        if version < old_version {
+            errors.push(Diagnostic::new(
+                loc,
+                &format!(
+                    "version of protocol `{}' went down from {} to {}",
+                    short_name, old_version, version
+                ),
+            ));
+            continue;
+        }
+
+        let mut changes = Vec::new();
+        for md in &ptype.messages {
+            let name = &md.name.id;
+            match old.messages.iter().find(|m| m.name == *name) {
+                Some(m) => {
+                    if m.signature != message_signature(program, md) {
+                        changes.push((
+                            md.name.loc.clone(),
+                            format!("message `{}' changed here", name),
+                        ));
+                    }
+                    if m.since != md.since {
+                        errors.push(Diagnostic::new(
+                            &md.name.loc,
+                            &format!(
+                                "[Since] of message `{}' changed from {} to {}",
+                                name,
+                                m.since.unwrap_or(1),
+                                md.since.unwrap_or(1)
+                            ),
+                        ));
+                    }
+                }
+                None => {
+                    changes.push((
+                        md.name.loc.clone(),
+                        format!("message `{}' was added here", name),
+                    ));
+                    if md.since.unwrap_or(1) <= old_version {
+                        errors.push(Diagnostic::new(
+                            &md.name.loc,
+                            &format!(
+                                "message `{}' is new since version {} of protocol `{}', \
+                                 so it needs [Since={}]",
+                                name,
+                                old_version,
+                                short_name,
+                                old_version + 1
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        for m in &old.messages {
+            if !ptype.messages.iter().any(|md| md.name.id == m.name) {
+                changes.push((loc.clone(), format!("message `{}' was removed", m.name)));
+            }
+        }
+
+        if !changes.is_empty() && version == old_version {
+            let mut e = Diagnostic::new(
+                loc,
+                &format!(
+                    "protocol `{}' changed since version {}, so its [ProtocolVersion] must be bumped",
+                    short_name, old_version
+                ),
+            );
+            for (loc, message) in &changes {
+                e = e.with_note(loc, message);
+            }
+            errors.push(e);
+        }
+    }
+    errors
+}
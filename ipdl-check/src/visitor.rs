@@ -0,0 +1,110 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// A visitor over the AST.
+//
+// Every method has a default implementation that visits the children of
+// the node by calling the corresponding walk_* function, so an
+// implementation only needs to override the methods for the nodes it cares
+// about. An overriding method can call the walk_* function itself to keep
+// visiting the children.
+
+use ast::{
+    Attributes, MessageDecl, Namespace, Param, Protocol, StructField, TranslationUnit, TypeSpec,
+    UsingStmt,
+};
+
+pub trait Visitor {
+    fn visit_translation_unit(&mut self, tu: &TranslationUnit) {
+        walk_translation_unit(self, tu)
+    }
+
+    fn visit_using(&mut self, _using: &UsingStmt) {}
+
+    fn visit_struct(&mut self, ns: &Namespace, attributes: &Attributes, fields: &[StructField]) {
+        walk_struct(self, ns, attributes, fields)
+    }
+
+    fn visit_struct_field(&mut self, field: &StructField) {
+        walk_struct_field(self, field)
+    }
+
+    fn visit_union(&mut self, ns: &Namespace, attributes: &Attributes, components: &[TypeSpec]) {
+        walk_union(self, ns, attributes, components)
+    }
+
+    fn visit_protocol(&mut self, ns: &Namespace, protocol: &Protocol) {
+        walk_protocol(self, ns, protocol)
+    }
+
+    fn visit_message(&mut self, md: &MessageDecl) {
+        walk_message(self, md)
+    }
+
+    fn visit_param(&mut self, param: &Param) {
+        walk_param(self, param)
+    }
+
+    fn visit_type_spec(&mut self, _type_spec: &TypeSpec) {}
+}
+
+pub fn walk_translation_unit<V: Visitor + ?Sized>(v: &mut V, tu: &TranslationUnit) {
+    for u in &tu.using {
+        v.visit_using(u);
+    }
+    for (ns, attributes, fields) in &tu.structs {
+        v.visit_struct(ns, attributes, fields);
+    }
+    for (ns, attributes, components) in &tu.unions {
+        v.visit_union(ns, attributes, components);
+    }
+    if let Some((ns, p)) = &tu.protocol {
+        v.visit_protocol(ns, p);
+    }
+}
+
+pub fn walk_struct<V: Visitor + ?Sized>(
+    v: &mut V,
+    _ns: &Namespace,
+    _attributes: &Attributes,
+    fields: &[StructField],
+) {
+    for f in fields {
+        v.visit_struct_field(f);
+    }
+}
+
+pub fn walk_struct_field<V: Visitor + ?Sized>(v: &mut V, field: &StructField) {
+    v.visit_type_spec(&field.type_spec);
+}
+
+pub fn walk_union<V: Visitor + ?Sized>(
+    v: &mut V,
+    _ns: &Namespace,
+    _attributes: &Attributes,
+    components: &[TypeSpec],
+) {
+    for c in components {
+        v.visit_type_spec(c);
+    }
+}
+
+pub fn walk_protocol<V: Visitor + ?Sized>(v: &mut V, _ns: &Namespace, protocol: &Protocol) {
+    for md in &protocol.messages {
+        v.visit_message(md);
+    }
+}
+
+pub fn walk_message<V: Visitor + ?Sized>(v: &mut V, md: &MessageDecl) {
+    for p in &md.in_params {
+        v.visit_param(p);
+    }
+    for p in &md.out_params {
+        v.visit_param(p);
+    }
+}
+
+pub fn walk_param<V: Visitor + ?Sized>(v: &mut V, param: &Param) {
+    v.visit_type_spec(&param.type_spec);
+}
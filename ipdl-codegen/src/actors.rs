@@ -0,0 +1,430 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// The PFooParent and PFooChild actor class declarations.
+//
+// Each class has a pure virtual Recv method for every message the side
+// receives, and the signature of a Send method for every message it sends,
+// following the Python compiler's naming and argument passing conventions
+// so the two outputs can be compared. The Send methods aren't defined yet,
+// and the headers for the C++ types used in messages aren't included.
+
+use ast::{ProtocolSide, TUId};
+use cxx::{actor_class, cxx_side_type, is_move_only};
+use ir::TypedProgram;
+use source_map::marker;
+use std::fmt::Write;
+use std::path::PathBuf;
+use type_check::{IPDLType, Lifetime, MessageTypeDef, ParamTypeDef};
+
+fn other_side(side: ProtocolSide) -> ProtocolSide {
+    match side {
+        ProtocolSide::Parent => ProtocolSide::Child,
+        ProtocolSide::Child => ProtocolSide::Parent,
+    }
+}
+
+fn receives(md: &MessageTypeDef, side: ProtocolSide) -> bool {
+    match side {
+        ProtocolSide::Parent => md.direction.is_to_parent(),
+        ProtocolSide::Child => md.direction.is_to_child(),
+    }
+}
+
+// The actor protocols that appear in a type, which need to be declared.
+fn collect_actors(t: &IPDLType, actors: &mut Vec<TUId>) {
+    match t {
+        IPDLType::ActorType(tuid) => actors.push(*tuid),
+        IPDLType::ArrayType(t)
+        | IPDLType::MaybeType(t)
+        | IPDLType::UniquePtrType(t)
+        | IPDLType::NotNullType(t) => collect_actors(t, actors),
+        _ => (),
+    }
+}
+
+struct ActorGen<'a> {
+    program: &'a TypedProgram,
+    tuid: TUId,
+    side: ProtocolSide,
+}
+
+impl<'a> ActorGen<'a> {
+    fn side_type(&self, t: &IPDLType) -> String {
+        cxx_side_type(t, &self.program.tuts, self.side)
+    }
+
+    // How a parameter is passed. Actors are passed as pointers, and values
+    // that can't be copied are moved.
+    fn in_param(&self, p: &ParamTypeDef) -> String {
+        let param_type = p.param_type.lookup(&self.program.tuts);
+        let t = self.side_type(param_type);
+        let is_actor = match param_type {
+            IPDLType::NotNullType(inner) => matches!(**inner, IPDLType::ActorType(_)),
+            t => matches!(t, IPDLType::ActorType(_)),
+        };
+        if is_actor {
+            format!("{} {}", t, p.name)
+        } else if is_move_only(param_type, &self.program.tuts) {
+            format!("{}&& {}", t, p.name)
+        } else {
+            format!("const {}& {}", t, p.name)
+        }
+    }
+
+    fn in_params(&self, md: &MessageTypeDef) -> Vec<String> {
+        md.params.iter().map(|p| self.in_param(p)).collect()
+    }
+
+    // Synchronous messages return their values through pointers.
+    fn out_params(&self, md: &MessageTypeDef) -> Vec<String> {
+        md.returns
+            .iter()
+            .map(|p| {
+                format!(
+                    "{}* {}",
+                    self.side_type(p.param_type.lookup(&self.program.tuts)),
+                    p.name
+                )
+            })
+            .collect()
+    }
+
+    // The type that an async message with returns is resolved with.
+    fn resolve_type(&self, md: &MessageTypeDef) -> String {
+        let types = md
+            .returns
+            .iter()
+            .map(|p| self.side_type(p.param_type.lookup(&self.program.tuts)))
+            .collect::<Vec<_>>();
+        if types.len() == 1 {
+            types.into_iter().next().unwrap()
+        } else {
+            format!("std::tuple<{}>", types.join(", "))
+        }
+    }
+
+    fn resolver_arg(&self, md: &MessageTypeDef) -> String {
+        let types = md
+            .returns
+            .iter()
+            .map(|p| {
+                format!(
+                    "const {}&",
+                    self.side_type(p.param_type.lookup(&self.program.tuts))
+                )
+            })
+            .collect::<Vec<_>>();
+        if types.len() == 1 {
+            types.into_iter().next().unwrap()
+        } else {
+            format!("std::tuple<{}>&&", types.join(", "))
+        }
+    }
+
+    fn has_async_reply(md: &MessageTypeDef) -> bool {
+        md.is_async() && !md.returns.is_empty()
+    }
+
+    fn typedefs(&self, messages: &[MessageTypeDef], out: &mut String) {
+        for md in messages {
+            if !Self::has_async_reply(md) {
+                continue;
+            }
+            let names = md.cxx_names();
+            if receives(md, other_side(self.side)) {
+                writeln!(
+                    out,
+                    "  typedef MozPromise<{}, mozilla::ipc::ResponseRejectReason, true> {};",
+                    self.resolve_type(md),
+                    names.promise.unwrap()
+                )
+                .unwrap();
+            }
+            if receives(md, self.side) {
+                writeln!(
+                    out,
+                    "  typedef std::function<void({})> {};",
+                    self.resolver_arg(md),
+                    names.resolver.unwrap()
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    // The declarations of the Recv method of a message, after those of the
+    // Alloc and Dealloc methods that a constructor needs.
+    fn recv_decls(&self, md: &MessageTypeDef) -> Vec<String> {
+        let mut decls = Vec::new();
+        let names = md.cxx_names();
+        let mut params = Vec::new();
+        if let Some(managee) = md.ctor_type() {
+            let constructed = actor_class(managee, &self.program.tuts, self.side);
+            let managee = self.program.protocol(managee);
+            let alloc_return = match managee.lifetime {
+                Lifetime::RefCounted => format!("already_AddRefed<{}>", constructed),
+                Lifetime::ManualDealloc => format!("{}*", constructed),
+            };
+            decls.push(format!(
+                "virtual {} Alloc{}{:?}({}) = 0;",
+                alloc_return,
+                managee.qname.short_name(),
+                self.side,
+                self.in_params(md).join(", ")
+            ));
+            if managee.lifetime == Lifetime::ManualDealloc {
+                decls.push(format!(
+                    "virtual bool Dealloc{}{:?}({}* aActor) = 0;",
+                    managee.qname.short_name(),
+                    self.side,
+                    constructed
+                ));
+            }
+            params.push(format!("{}* aActor", constructed));
+        }
+        params.extend(self.in_params(md));
+        if Self::has_async_reply(md) {
+            params.push(format!("{}&& aResolve", names.resolver.unwrap()));
+        } else {
+            params.extend(self.out_params(md));
+        }
+
+        // Constructors and destructors usually have nothing to do, so they
+        // don't have to be overridden.
+        let body = if md.is_ctor() || md.is_dtor() {
+            " { return IPC_OK(); }"
+        } else {
+            " = 0;"
+        };
+        decls.push(format!(
+            "virtual mozilla::ipc::IPCResult {}({}){}",
+            names.recv,
+            params.join(", "),
+            body
+        ));
+        decls
+    }
+
+    // The declarations of the Send methods of a message: two for
+    // constructors and for async messages with returns, and one otherwise.
+    fn send_decls(&self, md: &MessageTypeDef) -> Vec<String> {
+        let names = md.cxx_names();
+        let params = self.in_params(md);
+        // Callers of a `[Deprecated]' message get the hint from the C++
+        // compiler.
+        let attributes = md.deprecated.as_ref().map_or(String::new(), |hint| {
+            format!("[[deprecated(\"{}\")]] ", hint.replace('\\', "\\\\"))
+        });
+        if let Some(managee) = md.ctor_type() {
+            let constructed = actor_class(managee, &self.program.tuts, self.side);
+            let mut with_actor = vec![format!("{}* aActor", constructed)];
+            with_actor.extend(params.iter().cloned());
+            return [with_actor, params]
+                .iter()
+                .map(|params| {
+                    format!(
+                        "{}{}* {}({});",
+                        attributes,
+                        constructed,
+                        names.send,
+                        params.join(", ")
+                    )
+                })
+                .collect();
+        }
+        if md.is_dtor() {
+            let mut params = vec![format!(
+                "{}* aActor",
+                actor_class(&self.tuid, &self.program.tuts, self.side)
+            )];
+            params.extend(self.in_params(md));
+            params.extend(self.out_params(md));
+            return vec![format!(
+                "{}[[nodiscard]] static bool {}({});",
+                attributes,
+                names.send,
+                params.join(", ")
+            )];
+        }
+        if Self::has_async_reply(md) {
+            let mut with_callbacks = params.clone();
+            with_callbacks.push(format!(
+                "mozilla::ipc::ResolveCallback<{}>&& aResolve",
+                self.resolve_type(md)
+            ));
+            with_callbacks.push(String::from("mozilla::ipc::RejectCallback&& aReject"));
+            return vec![
+                format!(
+                    "{}void {}({});",
+                    attributes,
+                    names.send,
+                    with_callbacks.join(", ")
+                ),
+                format!(
+                    "{}RefPtr<{}> {}({});",
+                    attributes,
+                    names.promise.unwrap(),
+                    names.send,
+                    params.join(", ")
+                ),
+            ];
+        }
+        let mut params = params;
+        params.extend(self.out_params(md));
+        vec![format!(
+            "{}[[nodiscard]] bool {}({});",
+            attributes,
+            names.send,
+            params.join(", ")
+        )]
+    }
+
+    fn sent(&self) -> impl Iterator<Item = &'a MessageTypeDef> {
+        let side = self.side;
+        self.program
+            .protocol(&self.tuid)
+            .messages
+            .iter()
+            .filter(move |md| receives(md, other_side(side)))
+    }
+
+    fn received(&self) -> impl Iterator<Item = &'a MessageTypeDef> {
+        let side = self.side;
+        self.program
+            .protocol(&self.tuid)
+            .messages
+            .iter()
+            .filter(move |md| receives(md, side))
+    }
+
+    // The declarations of the methods of the class, in the order of the
+    // header, without the typedefs.
+    fn method_decls(&self) -> Vec<String> {
+        self.sent()
+            .flat_map(|md| self.send_decls(md))
+            .chain(self.received().flat_map(|md| self.recv_decls(md)))
+            .collect()
+    }
+
+    fn methods(&self, decls: Vec<String>, md: &MessageTypeDef, out: &mut String) {
+        writeln!(out, "  {}", marker(&md.name.loc)).unwrap();
+        for decl in decls {
+            writeln!(out, "  {}", decl).unwrap();
+        }
+    }
+
+    fn header(&self) -> String {
+        let ptype = self.program.protocol(&self.tuid);
+        let class = format!("{}{:?}", ptype.qname.short_name(), self.side);
+        let mut out = String::new();
+
+        writeln!(out, "#ifndef {0}_h\n#define {0}_h\n", class).unwrap();
+        writeln!(out, "#include \"mozilla/ipc/ProtocolUtils.h\"").unwrap();
+        let mut path = ptype.qname.quals.clone();
+        path.push(format!("{}.h", ptype.qname.short_name()));
+        writeln!(out, "#include \"{}\"\n", path.join("/")).unwrap();
+
+        // Forward declare the other actor classes this one refers to.
+        let mut actors = ptype.manages.clone();
+        for md in &ptype.messages {
+            for p in md.params.iter().chain(&md.returns) {
+                collect_actors(p.param_type.lookup(&self.program.tuts), &mut actors);
+            }
+        }
+        let order = self.program.decl_order();
+        actors.sort_by_key(|tuid| order.protocol_ordinal(tuid));
+        actors.dedup();
+        actors.retain(|tuid| *tuid != self.tuid);
+        for tuid in &actors {
+            let qname = &self.program.protocol(tuid).qname;
+            let mut decl = format!("class {}{:?};", qname.short_name(), self.side);
+            for ns in qname.quals.iter().rev() {
+                decl = format!("namespace {} {{ {} }}", ns, decl);
+            }
+            writeln!(out, "{}", decl).unwrap();
+        }
+        if !actors.is_empty() {
+            writeln!(out).unwrap();
+        }
+
+        for ns in &ptype.qname.quals {
+            writeln!(out, "namespace {} {{", ns).unwrap();
+        }
+        let base = if ptype.managers.is_empty() {
+            "IToplevelProtocol"
+        } else {
+            "IProtocol"
+        };
+        writeln!(out, "\nclass {} : public mozilla::ipc::{} {{", class, base).unwrap();
+        writeln!(out, " public:").unwrap();
+        if ptype.lifetime == Lifetime::RefCounted {
+            writeln!(out, "  NS_INLINE_DECL_PURE_VIRTUAL_REFCOUNTING").unwrap();
+        }
+        self.typedefs(&ptype.messages, &mut out);
+        for md in self.sent() {
+            self.methods(self.send_decls(md), md, &mut out);
+        }
+        writeln!(out, "\n protected:").unwrap();
+        for md in self.received() {
+            self.methods(self.recv_decls(md), md, &mut out);
+        }
+        writeln!(out, "}};\n").unwrap();
+        for ns in ptype.qname.quals.iter().rev() {
+            writeln!(out, "}}  // namespace {}", ns).unwrap();
+        }
+        writeln!(out, "\n#endif  // {}_h", class).unwrap();
+        out
+    }
+}
+
+// The header with the actor class for one side of a protocol, like
+// PFooParent.h.
+pub fn actor_header(program: &TypedProgram, tuid: &TUId, side: ProtocolSide) -> String {
+    ActorGen {
+        program,
+        tuid: *tuid,
+        side,
+    }
+    .header()
+}
+
+// The declarations of the Send, Recv, Alloc and Dealloc methods of the
+// actor class for one side of a protocol, as actor_header writes them.
+pub fn method_signatures(program: &TypedProgram, tuid: &TUId, side: ProtocolSide) -> Vec<String> {
+    ActorGen {
+        program,
+        tuid: *tuid,
+        side,
+    }
+    .method_decls()
+}
+
+// The method signatures of both actor classes of every protocol in
+// |file_names|, for `--method-signatures', so that they can be diffed
+// across revisions and against the headers of the Python compiler without
+// the rest of the generated code. The classes are sorted by name, so the
+// listing doesn't depend on the order of the files.
+pub fn method_signatures_text(program: &TypedProgram, file_names: &[PathBuf]) -> String {
+    let mut protocols = file_names
+        .iter()
+        .map(|file| program.tu_for_file(file).unwrap())
+        .filter(|tuid| program.tuts[tuid].protocol.is_some())
+        .collect::<Vec<_>>();
+    protocols.sort_by_key(|tuid| program.protocol(tuid).qname.to_string());
+    protocols.dedup();
+    let mut out = String::new();
+    for tuid in &protocols {
+        for side in &[ProtocolSide::Parent, ProtocolSide::Child] {
+            if !out.is_empty() {
+                writeln!(out).unwrap();
+            }
+            writeln!(out, "class {}", actor_class(tuid, &program.tuts, *side)).unwrap();
+            for decl in method_signatures(program, tuid, *side) {
+                writeln!(out, "  {}", decl).unwrap();
+            }
+        }
+    }
+    out
+}
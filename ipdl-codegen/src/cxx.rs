@@ -0,0 +1,139 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// How IPDL types are spelled in the generated C++ code.
+
+use ast::{ProtocolSide, TUId, TUMap};
+use std::collections::HashSet;
+use type_check::{
+    get_protocol_type, IPDLType, Lifetime, ProtocolTypeDef, TranslationUnitType, TypeRef,
+};
+
+// The name of the actor class for one side of a protocol, like
+// `::mozilla::PFooParent'.
+pub fn actor_class(tuid: &TUId, tuts: &TUMap<TranslationUnitType>, side: ProtocolSide) -> String {
+    format!("{}{:?}", protocol_type(tuid, tuts).qname, side)
+}
+
+// The type of a protocol of a checked program.
+fn protocol_type<'a>(tuid: &TUId, tuts: &'a TUMap<TranslationUnitType>) -> &'a ProtocolTypeDef {
+    get_protocol_type(tuts, tuid).expect("translation unit is not a protocol")
+}
+
+fn side_type(
+    t: &IPDLType,
+    tuts: &TUMap<TranslationUnitType>,
+    side: Option<ProtocolSide>,
+) -> String {
+    let inner = |t| side_type(t, tuts, side);
+    match t {
+        IPDLType::BuiltinCType(name) => name.to_string(),
+        IPDLType::ImportedCxxType(qid, Lifetime::RefCounted, _, _) => format!("RefPtr<{}>", qid),
+        IPDLType::ImportedCxxType(qid, _, _, _)
+        | IPDLType::ShmemType(qid)
+        | IPDLType::ByteBufType(qid)
+        | IPDLType::FDType(qid)
+        | IPDLType::EndpointType(qid)
+        | IPDLType::ManagedEndpointType(qid, _) => qid.to_string(),
+        IPDLType::StructType(tr) => tr.lookup_struct(tuts).qname.to_string(),
+        IPDLType::UnionType(tr) => tr.lookup_union(tuts).qname.to_string(),
+        IPDLType::ActorType(tuid) => match side {
+            Some(side) => format!("{}*", actor_class(tuid, tuts, side)),
+            // Code that is shared by both sides holds either actor.
+            None => format!(
+                "mozilla::ipc::SideVariant<{}*, {}*>",
+                actor_class(tuid, tuts, ProtocolSide::Parent),
+                actor_class(tuid, tuts, ProtocolSide::Child)
+            ),
+        },
+        IPDLType::ArrayType(t) => format!("nsTArray<{}>", inner(t)),
+        IPDLType::MaybeType(t) => format!("mozilla::Maybe<{}>", inner(t)),
+        IPDLType::UniquePtrType(t) => format!("mozilla::UniquePtr<{}>", inner(t)),
+        IPDLType::NotNullType(t) => format!("mozilla::NotNull<{}>", inner(t)),
+        IPDLType::MessageType(_) | IPDLType::ProtocolType(_) => {
+            panic!("{} has no C++ type", t.type_name())
+        }
+    }
+}
+
+// The C++ type of a value of type |t|, like a field of a struct, in code
+// that is used by both sides of a protocol. Protocols and messages aren't
+// values, so they have no C++ type.
+pub fn cxx_type(t: &IPDLType, tuts: &TUMap<TranslationUnitType>) -> String {
+    side_type(t, tuts, None)
+}
+
+// The C++ type of |t| in the actor class for one side of a protocol, where
+// actors are pointers to the actor classes of that side.
+pub fn cxx_side_type(
+    t: &IPDLType,
+    tuts: &TUMap<TranslationUnitType>,
+    side: ProtocolSide,
+) -> String {
+    side_type(t, tuts, Some(side))
+}
+
+fn is_move_only_impl(
+    t: &IPDLType,
+    tuts: &TUMap<TranslationUnitType>,
+    seen: &mut HashSet<TypeRef>,
+) -> bool {
+    match t {
+        IPDLType::ImportedCxxType(_, _, send_moveonly, data_moveonly) => {
+            *send_moveonly || *data_moveonly
+        }
+        IPDLType::ShmemType(_)
+        | IPDLType::ByteBufType(_)
+        | IPDLType::FDType(_)
+        | IPDLType::EndpointType(_)
+        | IPDLType::ManagedEndpointType(_, _)
+        | IPDLType::UniquePtrType(_) => true,
+        IPDLType::ArrayType(t) | IPDLType::MaybeType(t) | IPDLType::NotNullType(t) => {
+            is_move_only_impl(t, tuts, seen)
+        }
+        // Structs and unions can be recursive, so each one is only looked
+        // at once.
+        IPDLType::StructType(tr) => {
+            seen.insert(tr.clone())
+                && tr
+                    .lookup_struct(tuts)
+                    .field_types(tuts)
+                    .any(|f| is_move_only_impl(f, tuts, seen))
+        }
+        IPDLType::UnionType(tr) => {
+            seen.insert(tr.clone())
+                && tr
+                    .lookup_union(tuts)
+                    .component_types(tuts)
+                    .any(|c| is_move_only_impl(c, tuts, seen))
+        }
+        _ => false,
+    }
+}
+
+// Whether values of type |t| can't be copied, so they have to be passed by
+// rvalue reference.
+pub fn is_move_only(t: &IPDLType, tuts: &TUMap<TranslationUnitType>) -> bool {
+    is_move_only_impl(t, tuts, &mut HashSet::new())
+}
+
+// A name for |t| that can be used in an identifier, like `ArrayOfFoo' for
+// `Foo[]'. This is used for the tags of unions, like TArrayOfFoo.
+pub fn flat_type_name(t: &IPDLType, tuts: &TUMap<TranslationUnitType>) -> String {
+    let name = match t {
+        IPDLType::ArrayType(t) => format!("ArrayOf{}", flat_type_name(t, tuts)),
+        IPDLType::MaybeType(t) => format!("Maybe{}", flat_type_name(t, tuts)),
+        IPDLType::UniquePtrType(t) => format!("UniquePtrOf{}", flat_type_name(t, tuts)),
+        // A union can't have both a T and a NotNull<T>, so there's no need
+        // to tell them apart.
+        IPDLType::NotNullType(t) => flat_type_name(t, tuts),
+        IPDLType::ActorType(tuid) => protocol_type(tuid, tuts).qname.short_name(),
+        IPDLType::ImportedCxxType(qid, _, _, _) => qid.short_name(),
+        _ => t.name(tuts).rsplit("::").next().unwrap().to_string(),
+    };
+    // Imported types can have template arguments.
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect()
+}
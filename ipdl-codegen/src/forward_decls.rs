@@ -0,0 +1,215 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// The C++ declarations that the generated headers of a protocol need, for
+// hand-written glue code that has to declare the same things.
+//
+// A type imported with `using class' or `using struct' can be forward
+// declared, as can the actor classes of the protocols that a protocol
+// manages, is managed by or sends. Everything else needs its header to be
+// included: the types from a bare `using', template instances, and the
+// structs and unions of other files, which are defined in the generated
+// header of their file, like `mozilla/dom/DOMTypes.h' for DOMTypes.ipdlh.
+
+use ast::{CxxTypeKind, ProtocolSide, TUId};
+use ir::TypedProgram;
+use json::{json_list, json_path, json_string};
+use std::fmt::Write;
+use std::path::PathBuf;
+use type_check::IPDLType;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardDecl {
+    pub kind: CxxTypeKind,
+    pub namespaces: Vec<String>,
+    pub name: String,
+    // The header that defines the type, without the quotes. None for the
+    // actor classes, which are defined by generated headers.
+    pub header: Option<String>,
+}
+
+impl ForwardDecl {
+    pub fn qualified_name(&self) -> String {
+        let mut name = String::new();
+        for ns in &self.namespaces {
+            write!(name, "::{}", ns).unwrap();
+        }
+        write!(name, "::{}", self.name).unwrap();
+        name
+    }
+
+    // Like `namespace mozilla { namespace dom { class Foo; } }'.
+    pub fn to_cxx(&self) -> String {
+        let mut decl = format!("{} {};", self.kind, self.name);
+        for ns in self.namespaces.iter().rev() {
+            decl = format!("namespace {} {{ {} }}", ns, decl);
+        }
+        decl
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtocolDeclarations {
+    pub protocol: TUId,
+    // The headers to include, without the quotes, sorted.
+    pub includes: Vec<String>,
+    // Sorted by their qualified names.
+    pub forward_decls: Vec<ForwardDecl>,
+}
+
+fn collect_actors(t: &IPDLType, actors: &mut Vec<TUId>) {
+    match t {
+        IPDLType::ActorType(tuid) => actors.push(*tuid),
+        IPDLType::ArrayType(t)
+        | IPDLType::MaybeType(t)
+        | IPDLType::UniquePtrType(t)
+        | IPDLType::NotNullType(t) => collect_actors(t, actors),
+        _ => (),
+    }
+}
+
+// The generated header that defines the structs and unions of a file.
+fn generated_header(program: &TypedProgram, tuid: &TUId, quals: &[String]) -> String {
+    let stem = program.tus[tuid].file_name.file_stem().unwrap();
+    let mut path = quals.to_vec();
+    path.push(format!("{}.h", stem.to_string_lossy()));
+    path.join("/")
+}
+
+pub fn protocol_declarations(program: &TypedProgram, tuid: &TUId) -> ProtocolDeclarations {
+    let ptype = program.protocol(tuid);
+    let closure = program.type_closure(tuid);
+    let mut includes = Vec::new();
+    let mut forward_decls = Vec::new();
+
+    for t in &closure.imported {
+        // Builtin and special types don't need anything declared, and
+        // neither does a type that isn't spelled the way it was imported.
+        let name = match t {
+            IPDLType::ImportedCxxType(qid, _, _, _) => qid.to_string(),
+            _ => continue,
+        };
+        let using = program
+            .tu_ids_by_file()
+            .into_iter()
+            .flat_map(|tuid| program.tus[&tuid].using.iter())
+            .find(|u| u.cxx_type.to_string() == name);
+        let using = match using {
+            Some(using) => using,
+            None => continue,
+        };
+        let header = String::from(using.header.trim_matches('"'));
+        match using.kind {
+            Some(kind) if using.template_args.is_empty() => forward_decls.push(ForwardDecl {
+                kind,
+                namespaces: using.cxx_type.quals.clone(),
+                name: using.cxx_type.short_name(),
+                header: Some(header),
+            }),
+            _ => includes.push(header),
+        }
+    }
+    for tr in &closure.structs {
+        if tr.tu != *tuid {
+            let quals = &tr.lookup_struct(&program.tuts).qname.quals;
+            includes.push(generated_header(program, &tr.tu, quals));
+        }
+    }
+    for tr in &closure.unions {
+        if tr.tu != *tuid {
+            let quals = &tr.lookup_union(&program.tuts).qname.quals;
+            includes.push(generated_header(program, &tr.tu, quals));
+        }
+    }
+
+    let mut actors = vec![*tuid];
+    actors.extend(&ptype.manages);
+    actors.extend(&ptype.managers);
+    for md in &ptype.messages {
+        for p in md.params.iter().chain(&md.returns) {
+            collect_actors(p.param_type.lookup(&program.tuts), &mut actors);
+        }
+    }
+    for actor in &actors {
+        let qname = &program.protocol(actor).qname;
+        for side in &[ProtocolSide::Parent, ProtocolSide::Child] {
+            forward_decls.push(ForwardDecl {
+                kind: CxxTypeKind::Class,
+                namespaces: qname.quals.clone(),
+                name: format!("{}{:?}", qname.short_name(), side),
+                header: None,
+            });
+        }
+    }
+
+    includes.sort();
+    includes.dedup();
+    forward_decls.sort_by_key(|d| d.qualified_name());
+    forward_decls.dedup();
+    ProtocolDeclarations {
+        protocol: *tuid,
+        includes,
+        forward_decls,
+    }
+}
+
+// A header fragment with the includes and forward declarations, which can
+// be included before the hand-written code that needs them.
+pub fn header_fragment(program: &TypedProgram, tuid: &TUId) -> String {
+    let decls = protocol_declarations(program, tuid);
+    let guard = format!(
+        "{}ForwardDecls_h",
+        program.protocol(tuid).qname.short_name()
+    );
+    let mut out = String::new();
+    writeln!(out, "#ifndef {0}\n#define {0}\n", guard).unwrap();
+    for header in &decls.includes {
+        writeln!(out, "#include \"{}\"", header).unwrap();
+    }
+    if !decls.includes.is_empty() {
+        writeln!(out).unwrap();
+    }
+    for decl in &decls.forward_decls {
+        writeln!(out, "{}", decl.to_cxx()).unwrap();
+    }
+    writeln!(out, "\n#endif  // {}", guard).unwrap();
+    out
+}
+
+// |program| must have been checked from |file_names|.
+pub fn declarations_json(program: &TypedProgram, file_names: &[PathBuf]) -> String {
+    let mut protocols = Vec::new();
+    for file in file_names {
+        let tuid = program.tu_for_file(file).unwrap();
+        if program.tuts[&tuid].protocol.is_none() {
+            continue;
+        }
+        let decls = protocol_declarations(program, &tuid);
+        let forward_decls = decls.forward_decls.iter().map(|d| {
+            format!(
+                "{{\"kind\": {}, \"name\": {}, \"header\": {}, \"declaration\": {}}}",
+                json_string(&d.kind.to_string()),
+                json_string(&d.qualified_name()),
+                d.header
+                    .as_ref()
+                    .map_or(String::from("null"), |h| json_string(h)),
+                json_string(&d.to_cxx())
+            )
+        });
+        protocols.push(format!(
+            "  {{\n    \"name\": {},\n    \"file\": {},\n    \"includes\": {},\n    \
+             \"forward_declarations\": {}\n  }}",
+            json_string(&program.protocol(&tuid).qname.to_string()),
+            json_path(&program.tus[&tuid].file_name),
+            json_list(decls.includes.iter().map(|h| json_string(h))),
+            json_list(forward_decls)
+        ));
+    }
+
+    if protocols.is_empty() {
+        String::from("{\"protocols\": []}\n")
+    } else {
+        format!("{{\"protocols\": [\n{}\n]}}\n", protocols.join(",\n"))
+    }
+}
@@ -0,0 +1,178 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Golden files: checked-in copies of generated code. Generated code is
+// compared against them, so that any change to what the generators write
+// shows up as a diff that can be reviewed.
+
+use std::cmp;
+use std::fs;
+use std::path::Path;
+
+// How many unchanged lines are shown around each change.
+const CONTEXT: usize = 3;
+
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+// The lines of |old| and |new|, with the lines that only appear in one of
+// them marked.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // Most changes are small, so the common prefix and suffix are left out
+    // of the quadratic part.
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    // lcs[i][j] is the length of the longest common subsequence of a[i..]
+    // and b[j..].
+    let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                cmp::max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines: Vec<Line> = old[..prefix].iter().map(|l| Line::Same(l)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            lines.push(Line::Same(a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(Line::Removed(a[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(b[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(|l| Line::Same(l)));
+    lines
+}
+
+// A unified diff from |expected| to |actual|, without the file header
+// lines. It is empty if the two only differ in their line endings.
+pub fn unified_diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+    let lines = diff_lines(&old, &new);
+    let changed: Vec<usize> = (0..lines.len())
+        .filter(|&k| !matches!(lines[k], Line::Same(_)))
+        .collect();
+
+    let mut diff = String::new();
+    let mut k = 0;
+    while k < changed.len() {
+        // Changes that are close enough for their context to overlap go in
+        // the same hunk.
+        let start = changed[k].saturating_sub(CONTEXT);
+        let mut end = changed[k] + 1;
+        k += 1;
+        while k < changed.len() && changed[k] <= end + 2 * CONTEXT {
+            end = changed[k] + 1;
+            k += 1;
+        }
+        let end = cmp::min(end + CONTEXT, lines.len());
+
+        let count = |lines: &[Line]| {
+            let old = lines
+                .iter()
+                .filter(|l| !matches!(l, Line::Added(_)))
+                .count();
+            let new = lines
+                .iter()
+                .filter(|l| !matches!(l, Line::Removed(_)))
+                .count();
+            (old, new)
+        };
+        let (old_start, new_start) = count(&lines[..start]);
+        let (old_len, new_len) = count(&lines[start..end]);
+        // Like diff, an empty range is numbered after the line before it.
+        let first = |start: usize, len: usize| if len == 0 { start } else { start + 1 };
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            first(old_start, old_len),
+            old_len,
+            first(new_start, new_len),
+            new_len
+        ));
+        for line in &lines[start..end] {
+            let (mark, text) = match line {
+                Line::Same(text) => (' ', text),
+                Line::Removed(text) => ('-', text),
+                Line::Added(text) => ('+', text),
+            };
+            diff.push(mark);
+            diff.push_str(text);
+            diff.push('\n');
+        }
+    }
+    diff
+}
+
+// Compare generated code with the golden file at |path|. The error says
+// how the generated code differs from the file.
+pub fn compare_golden(path: &Path, actual: &str) -> Result<(), String> {
+    let expected = match fs::read_to_string(path) {
+        Ok(expected) => expected,
+        Err(e) => return Err(format!("Can't read `{}': {}", path.display(), e)),
+    };
+    if expected == actual {
+        return Ok(());
+    }
+    let diff = unified_diff(&expected, actual);
+    if diff.is_empty() {
+        return Err(format!(
+            "`{}' only differs from the generated code in its line endings",
+            path.display()
+        ));
+    }
+    Err(format!(
+        "`{}' differs from the generated code:\n--- {}\n+++ generated\n{}",
+        path.display(),
+        path.display(),
+        diff
+    ))
+}
+
+#[test]
+fn unified_diff_tests() {
+    assert_eq!(unified_diff("a\nb\n", "a\nb\n"), "");
+    assert_eq!(unified_diff("a\nb\n", "a\nb"), "");
+    assert_eq!(
+        unified_diff("a\nb\nc\n", "a\nx\nc\n"),
+        "@@ -1,3 +1,3 @@\n a\n-b\n+x\n c\n"
+    );
+    assert_eq!(unified_diff("", "a\n"), "@@ -0,0 +1,1 @@\n+a\n");
+
+    // Changes far apart get their own hunks, with three lines of context.
+    let old: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+    let new: String = (1..=20)
+        .filter(|&i| i != 18)
+        .map(|i| match i {
+            2 => String::from("two\n"),
+            i => format!("{}\n", i),
+        })
+        .collect();
+    assert_eq!(
+        unified_diff(&old, &new),
+        "@@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+         @@ -15,6 +15,5 @@\n 15\n 16\n 17\n-18\n 19\n 20\n"
+    );
+}
@@ -0,0 +1,61 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Where the generated files of a translation unit go in the header and
+// source directories.
+//
+// The Python compiler puts each header in the directories of the namespace
+// of its file, like `mozilla/dom/PFoo.h', so that it can be included by
+// that path, and all of the sources in the source directory itself. The
+// other layouts put everything in the directories themselves, or
+// everything in the namespace directories, so that build rules can move
+// to one of them a directory at a time.
+
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputLayout {
+    Python,
+    Flat,
+    Namespaces,
+}
+
+impl OutputLayout {
+    // The layout for a value of `--output-layout'.
+    pub fn from_name(name: &str) -> Option<OutputLayout> {
+        match name {
+            "python" => Some(OutputLayout::Python),
+            "flat" => Some(OutputLayout::Flat),
+            "namespaces" => Some(OutputLayout::Namespaces),
+            _ => None,
+        }
+    }
+
+    fn in_namespace(dir: &Path, namespaces: &[String], name: &str) -> PathBuf {
+        let mut path = dir.to_path_buf();
+        path.extend(namespaces);
+        path.push(name);
+        path
+    }
+
+    // The path of the header |name| of a file in |namespaces|, in the
+    // header directory |dir|.
+    pub fn header_path(&self, dir: &Path, namespaces: &[String], name: &str) -> PathBuf {
+        match self {
+            OutputLayout::Flat => dir.join(name),
+            OutputLayout::Python | OutputLayout::Namespaces => {
+                Self::in_namespace(dir, namespaces, name)
+            }
+        }
+    }
+
+    // The path of the source file |name| of a file in |namespaces|, in the
+    // source directory |dir|.
+    pub fn source_path(&self, dir: &Path, namespaces: &[String], name: &str) -> PathBuf {
+        match self {
+            OutputLayout::Python | OutputLayout::Flat => dir.join(name),
+            OutputLayout::Namespaces => Self::in_namespace(dir, namespaces, name),
+        }
+    }
+}
@@ -0,0 +1,22 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Code generation from checked protocols: the C++ actor classes and
+// serializers, the Rust and TypeScript bindings, and the message ID tables.
+
+extern crate ipdl_check;
+
+use ipdl_check::{ast, ir, json, stable_hash, type_check};
+
+pub mod actors;
+pub mod cxx;
+pub mod forward_decls;
+pub mod golden;
+pub mod layout;
+pub mod message_ids;
+pub mod param_traits;
+pub mod rust_bindings;
+pub mod source_map;
+pub mod typescript;
+pub mod wire_format;
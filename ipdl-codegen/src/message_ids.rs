@@ -0,0 +1,221 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// The message ID tables, which are the first part of the C++ output that
+// doesn't depend on lowering messages and actors.
+//
+// Each protocol gets a `MessageType' enum with a Msg_Foo__ID constant for
+// every message, in declaration order, and a Reply_Foo__ID after it if the
+// message has a reply. The IDs of a protocol start at its IPCMessageStart
+// value shifted left by 16. IPCMessageStart itself, and the tables that map
+// IDs and protocols to names for IPC logging, cover every protocol in the
+// program, sorted by name, so the output doesn't depend on the order files
+// are given in. Both orders come from TypedProgram::decl_order(), and are
+// stable: the IDs only change when messages or protocols are added,
+// removed, renamed or reordered.
+//
+// IPCMessageTypeName.cpp also has IPCMessageTypeIsLoggable(), which is
+// false for the messages and replies of `[NoLogging]' messages, so that
+// logging code can leave out their data.
+//
+// The same tables are available as JSON, for profiler tooling that has to
+// turn the message types of IPC markers into names without loading the
+// generated code. See profiler_markers_json().
+
+use ir::TypedProgram;
+use json::{json_list, json_string};
+use source_map::marker;
+use stable_hash::StableHasher;
+use std::fmt::Write;
+use std::hash::Hasher;
+use type_check::ProtocolTypeDef;
+
+// The constants of the MessageType enum of a protocol, without the start
+// and end markers.
+pub fn message_ids(ptype: &ProtocolTypeDef) -> Vec<String> {
+    ptype
+        .messages
+        .iter()
+        .flat_map(|md| md.message_ids())
+        .collect()
+}
+
+fn message_start(ptype: &ProtocolTypeDef) -> String {
+    format!("{}MsgStart", ptype.qname.short_name())
+}
+
+// The MessageType enum of a protocol, in the protocol's namespaces.
+pub fn message_enum(ptype: &ProtocolTypeDef) -> String {
+    let name = ptype.qname.short_name();
+    let mut out = String::new();
+    for ns in &ptype.qname.quals {
+        writeln!(out, "namespace {} {{", ns).unwrap();
+    }
+    writeln!(out, "namespace {} {{\n", name).unwrap();
+    writeln!(out, "{}", marker(&ptype.qname.base_id.loc)).unwrap();
+    writeln!(out, "enum MessageType {{").unwrap();
+    writeln!(out, "  {}Start = {} << 16,", name, message_start(ptype)).unwrap();
+    for id in message_ids(ptype) {
+        writeln!(out, "  {},", id).unwrap();
+    }
+    writeln!(out, "  {}End\n}};\n", name).unwrap();
+    writeln!(out, "}}  // namespace {}", name).unwrap();
+    for ns in ptype.qname.quals.iter().rev() {
+        writeln!(out, "}}  // namespace {}", ns).unwrap();
+    }
+    out
+}
+
+fn include_guard(name: &str, body: &str) -> String {
+    format!(
+        "#ifndef {0}_h\n#define {0}_h\n\n{1}\n#endif  // {0}_h\n",
+        name, body
+    )
+}
+
+// The header for a protocol, PFoo.h, which only has the MessageType enum
+// for now.
+pub fn protocol_header(ptype: &ProtocolTypeDef) -> String {
+    let body = format!("#include \"IPCMessageStart.h\"\n\n{}", message_enum(ptype));
+    include_guard(&ptype.qname.short_name(), &body)
+}
+
+// The contents of IPCMessageStart.h.
+pub fn message_start_header(program: &TypedProgram) -> String {
+    include_guard("IPCMessageStart", &message_start_enum(program))
+}
+
+fn message_start_enum(program: &TypedProgram) -> String {
+    let mut out = String::from("enum IPCMessageStart {\n");
+    for tuid in program.decl_order().protocols() {
+        writeln!(out, "  {},", message_start(program.protocol(tuid))).unwrap();
+    }
+    out.push_str("  LastMsgIndex\n};\n\n");
+    out.push_str("static_assert(LastMsgIndex <= 65536, \"need to update IPC_MESSAGE_MACRO\");\n");
+    out
+}
+
+// The contents of IPCMessageTypeName.cpp, which has the names of every
+// message ID and every protocol.
+pub fn message_type_names(program: &TypedProgram) -> String {
+    let protocols = program.decl_order().protocols().to_vec();
+
+    let mut out = String::new();
+    for tuid in &protocols {
+        let qname = &program.protocol(tuid).qname;
+        let mut path = qname.quals.clone();
+        path.push(format!("{}.h", qname.short_name()));
+        writeln!(out, "#include \"{}\"", path.join("/")).unwrap();
+    }
+    out.push_str(
+        "\nconst char* StringFromIPCMessageType(uint32_t aMessageType) {\n  \
+         switch (aMessageType) {\n",
+    );
+    for tuid in &protocols {
+        let ptype = program.protocol(tuid);
+        let name = ptype.qname.short_name();
+        for id in message_ids(ptype) {
+            writeln!(out, "    case {}::{}:", ptype.qname, id).unwrap();
+            writeln!(out, "      return \"{}::{}\";", name, &id[..id.len() - 4]).unwrap();
+        }
+    }
+    out.push_str("    default:\n      return \"<unknown IPC msg name>\";\n  }\n}\n\n");
+
+    let mut unloggable = String::new();
+    for tuid in &protocols {
+        let ptype = program.protocol(tuid);
+        for md in ptype.messages.iter().filter(|md| md.no_logging) {
+            writeln!(unloggable, "    case {}::Msg_{}__ID:", ptype.qname, md.name).unwrap();
+            if md.has_reply() {
+                writeln!(
+                    unloggable,
+                    "    case {}::Reply_{}__ID:",
+                    ptype.qname, md.name
+                )
+                .unwrap();
+            }
+        }
+    }
+    out.push_str("bool IPCMessageTypeIsLoggable(uint32_t aMessageType) {\n");
+    if unloggable.is_empty() {
+        out.push_str("  return true;\n}\n\n");
+    } else {
+        write!(
+            out,
+            "  switch (aMessageType) {{\n{}      return false;\n    default:\n      \
+             return true;\n  }}\n}}\n\n",
+            unloggable
+        )
+        .unwrap();
+    }
+
+    out.push_str("const char* ProtocolIdToName(IPCMessageStart aId) {\n  switch (aId) {\n");
+    for tuid in &protocols {
+        let ptype = program.protocol(tuid);
+        writeln!(out, "    case {}:", message_start(ptype)).unwrap();
+        writeln!(out, "      return \"{}\";", ptype.qname.short_name()).unwrap();
+    }
+    out.push_str("    default:\n      return \"<unknown protocol id>\";\n  }\n}\n");
+    out
+}
+
+// The FNV-1a hash of a message name, as 16 hex digits. Unlike the numeric
+// ID, it doesn't change when other protocols or messages are added, so
+// tooling can match up the messages of profiles from different builds.
+fn name_hash(name: &str) -> String {
+    let mut hasher = StableHasher::new();
+    hasher.write(name.as_bytes());
+    format!("{:016x}", hasher.finish())
+}
+
+// The message tables of IPCMessageTypeName.cpp as JSON, for IPC profiler
+// markers, whose `messageType' is the numeric ID of a message or reply.
+// `strings' is the table of names that StringFromIPCMessageType()
+// returns, like `PFoo::Msg_Bar', and each message refers to its name by
+// its index there. `protocols' maps each IPCMessageStart value to the name
+// that ProtocolIdToName() returns, and the protocol of an ID is the ID
+// shifted right by 16.
+pub fn profiler_markers_json(program: &TypedProgram) -> String {
+    let mut protocols = Vec::new();
+    let mut strings = Vec::new();
+    let mut messages = Vec::new();
+    for (start, tuid) in program.decl_order().protocols().iter().enumerate() {
+        let ptype = program.protocol(tuid);
+        let name = ptype.qname.short_name();
+        protocols.push(format!(
+            "  {{\"id\": {}, \"name\": {}}}",
+            start,
+            json_string(&name)
+        ));
+        // Each message has one or two IDs in message_ids(), in order.
+        let loggable = ptype
+            .messages
+            .iter()
+            .flat_map(|md| vec![!md.no_logging; if md.has_reply() { 2 } else { 1 }]);
+        for (offset, (id, loggable)) in message_ids(ptype).iter().zip(loggable).enumerate() {
+            let message_name = format!("{}::{}", name, &id[..id.len() - 4]);
+            messages.push(format!(
+                "  {{\"id\": {}, \"name\": {}, \"hash\": {}, \"loggable\": {}}}",
+                (start << 16) + offset + 1,
+                strings.len(),
+                json_string(&name_hash(&message_name)),
+                loggable
+            ));
+            strings.push(json_string(&message_name));
+        }
+    }
+    let section = |items: Vec<String>| {
+        if items.is_empty() {
+            String::from("[]")
+        } else {
+            format!("[\n{}\n]", items.join(",\n"))
+        }
+    };
+    format!(
+        "{{\"protocols\": {},\n\"strings\": {},\n\"messages\": {}}}\n",
+        section(protocols),
+        json_list(strings),
+        section(messages)
+    )
+}
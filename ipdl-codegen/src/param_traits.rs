@@ -0,0 +1,186 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// The ParamTraits specializations that serialize IPDL structs and unions.
+//
+// A struct is written field by field, in declaration order, and read back
+// the same way before the struct is constructed from the fields. A union is
+// written as its tag followed by the value of the active variant. This
+// follows the code the Python compiler generates, except that it doesn't
+// pack runs of fields of the same size into a single write.
+
+use ast::TUId;
+use cxx::{cxx_type, flat_type_name};
+use ir::TypedProgram;
+use source_map::marker;
+use std::fmt::Write;
+use type_check::UnionTypeDef;
+
+fn declaration(name: &str) -> String {
+    format!(
+        "template <>\nstruct ParamTraits<{0}> {{\n  \
+         typedef {0} paramType;\n  \
+         static void Write(IPC::MessageWriter* aWriter, const paramType& aVar);\n  \
+         static IPC::ReadResult<paramType> Read(IPC::MessageReader* aReader);\n}};\n",
+        name
+    )
+}
+
+fn read_param(out: &mut String, indent: &str, var: &str, t: &str, error: &str) {
+    writeln!(
+        out,
+        "{}auto maybe__{} = IPC::ReadParam<{}>(aReader);",
+        indent, var, t
+    )
+    .unwrap();
+    writeln!(out, "{}if (!maybe__{}) {{", indent, var).unwrap();
+    writeln!(out, "{}  aReader->FatalError(\"{}\");", indent, error).unwrap();
+    writeln!(out, "{}  return {{}};\n{}}}", indent, indent).unwrap();
+    writeln!(out, "{}auto& {} = *maybe__{};", indent, var, var).unwrap();
+}
+
+fn struct_definition(program: &TypedProgram, tuid: &TUId, index: usize) -> String {
+    // The typed struct only has the types of the fields, so the names come
+    // from the AST.
+    let sdef = &program.tuts[tuid].structs[index];
+    let fields = &program.tus[tuid].structs[index].2;
+    let name = sdef.qname.to_string();
+    let short_name = sdef.qname.short_name();
+    let marker = marker(&sdef.qname.base_id.loc);
+    let mut out = String::new();
+
+    writeln!(out, "{}", marker).unwrap();
+    writeln!(
+        out,
+        "void ParamTraits<{}>::Write(IPC::MessageWriter* aWriter, const paramType& aVar) {{",
+        name
+    )
+    .unwrap();
+    for f in fields {
+        writeln!(out, "  IPC::WriteParam(aWriter, (aVar).{}());", f.name).unwrap();
+    }
+    writeln!(out, "}}\n").unwrap();
+
+    writeln!(out, "{}", marker).unwrap();
+    writeln!(
+        out,
+        "IPC::ReadResult<paramType> ParamTraits<{}>::Read(IPC::MessageReader* aReader) {{",
+        name
+    )
+    .unwrap();
+    for (f, t) in fields.iter().zip(sdef.field_types(&program.tuts)) {
+        let error = format!(
+            "Error deserializing '{}' ({}) member of '{}'",
+            f.name,
+            t.name(&program.tuts),
+            short_name
+        );
+        let t = cxx_type(t, &program.tuts);
+        read_param(&mut out, "  ", &format!("_{}", f.name), &t, &error);
+    }
+    let args = fields
+        .iter()
+        .map(|f| format!(", std::move(_{})", f.name))
+        .collect::<String>();
+    writeln!(
+        out,
+        "  IPC::ReadResult<paramType> result__{{std::in_place{}}};\n  return result__;\n}}",
+        args
+    )
+    .unwrap();
+    out
+}
+
+fn union_definition(program: &TypedProgram, udef: &UnionTypeDef) -> String {
+    let name = udef.qname.to_string();
+    let short_name = udef.qname.short_name();
+    let variants = udef
+        .component_types(&program.tuts)
+        .map(|t| {
+            (
+                format!("T{}", flat_type_name(t, &program.tuts)),
+                cxx_type(t, &program.tuts),
+            )
+        })
+        .collect::<Vec<_>>();
+    let unknown = format!("unknown variant of union {}", short_name);
+    let marker = marker(&udef.qname.base_id.loc);
+    let mut out = String::new();
+
+    writeln!(out, "{}", marker).unwrap();
+    writeln!(
+        out,
+        "void ParamTraits<{}>::Write(IPC::MessageWriter* aWriter, const paramType& aVar) {{",
+        name
+    )
+    .unwrap();
+    writeln!(out, "  typedef {} union__;", name).unwrap();
+    writeln!(out, "  int type = (aVar).type();").unwrap();
+    writeln!(out, "  IPC::WriteParam(aWriter, type);").unwrap();
+    writeln!(out, "  switch (type) {{").unwrap();
+    for (tag, _) in &variants {
+        writeln!(out, "    case union__::{}: {{", tag).unwrap();
+        writeln!(
+            out,
+            "      IPC::WriteParam(aWriter, (aVar).get_{}());",
+            &tag[1..]
+        )
+        .unwrap();
+        writeln!(out, "      return;\n    }}").unwrap();
+    }
+    writeln!(out, "    default: {{").unwrap();
+    writeln!(out, "      aWriter->FatalError(\"{}\");", unknown).unwrap();
+    writeln!(out, "      return;\n    }}\n  }}\n}}\n").unwrap();
+
+    writeln!(out, "{}", marker).unwrap();
+    writeln!(
+        out,
+        "IPC::ReadResult<paramType> ParamTraits<{}>::Read(IPC::MessageReader* aReader) {{",
+        name
+    )
+    .unwrap();
+    writeln!(out, "  typedef {} union__;", name).unwrap();
+    let error = format!("Error deserializing type of union {}", short_name);
+    read_param(&mut out, "  ", "type", "int", &error);
+    writeln!(out, "  switch (type) {{").unwrap();
+    for (tag, t) in &variants {
+        writeln!(out, "    case union__::{}: {{", tag).unwrap();
+        let error = format!(
+            "Error deserializing variant {} of union {}",
+            tag, short_name
+        );
+        read_param(&mut out, "      ", "tmp", t, &error);
+        writeln!(out, "      return std::move(tmp);\n    }}").unwrap();
+    }
+    writeln!(out, "    default: {{").unwrap();
+    writeln!(out, "      aReader->FatalError(\"{}\");", unknown).unwrap();
+    writeln!(out, "      return {{}};\n    }}\n  }}\n}}").unwrap();
+    out
+}
+
+// The ParamTraits declarations and definitions for the structs and unions
+// declared in a translation unit, or None if there aren't any.
+pub fn param_traits(program: &TypedProgram, tuid: &TUId) -> Option<String> {
+    let tut = &program.tuts[tuid];
+    if tut.structs.is_empty() && tut.unions.is_empty() {
+        return None;
+    }
+
+    let mut declarations = Vec::new();
+    let mut definitions = Vec::new();
+    for (index, sdef) in tut.structs.iter().enumerate() {
+        declarations.push(declaration(&sdef.qname.to_string()));
+        definitions.push(struct_definition(program, tuid, index));
+    }
+    for udef in &tut.unions {
+        declarations.push(declaration(&udef.qname.to_string()));
+        definitions.push(union_definition(program, udef));
+    }
+
+    Some(format!(
+        "namespace IPC {{\n\n{}\n{}\n}}  // namespace IPC\n",
+        declarations.join("\n"),
+        definitions.join("\n")
+    ))
+}
@@ -0,0 +1,326 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Rust bindings for IPDL files, for experimenting with actors that are
+// written in Rust.
+//
+// Each struct and union becomes a Rust struct or enum. Each protocol gets an
+// enum of the messages that each side receives, and a handler trait for each
+// side with a method per message. Builtin types are mapped to the Rust types
+// from the nsstring, nserror and ipc crates, and actors are referred to by
+// ipc::ActorId. Other imported C++ types are used by their short names, so
+// they have to be in scope where the bindings are included.
+
+use ast::{ProtocolSide, TUId};
+use cxx::flat_type_name;
+use ir::TypedProgram;
+use source_map::marker;
+use std::collections::HashSet;
+use std::fmt::Write;
+use type_check::{IPDLType, MessageTypeDef, ParamTypeDef, TypeRef};
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+    "mut", "pub", "ref", "return", "static", "struct", "super", "trait", "true", "type", "unsafe",
+    "use", "where", "while", "yield",
+];
+
+// `aFooBar' becomes `a_foo_bar', `PFooURI' becomes `p_foo_uri', and
+// `__delete__' becomes `delete'.
+fn snake_case(name: &str) -> String {
+    let chars = name.trim_matches('_').chars().collect::<Vec<_>>();
+    let mut out = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next_lower)
+            {
+                out.push('_');
+            }
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    if RUST_KEYWORDS.contains(&out.as_str()) {
+        format!("r#{}", out)
+    } else {
+        out
+    }
+}
+
+// `__delete__' becomes `Delete' and `ArrayOfint' becomes `ArrayOfint'.
+fn camel_case(name: &str) -> String {
+    let name = name.trim_matches('_');
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) => c.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+struct RustGen<'a> {
+    program: &'a TypedProgram,
+}
+
+impl<'a> RustGen<'a> {
+    fn builtin(name: &str) -> Option<&'static str> {
+        Some(match name {
+            "bool" => "bool",
+            "char" => "std::os::raw::c_char",
+            "short" => "i16",
+            "int" => "i32",
+            "long" => "std::os::raw::c_long",
+            "float" => "f32",
+            "double" => "f64",
+            "::int8_t" => "i8",
+            "::uint8_t" => "u8",
+            "::int16_t" => "i16",
+            "::uint16_t" => "u16",
+            "::int32_t" => "i32",
+            "::uint32_t" => "u32",
+            "::int64_t" => "i64",
+            "::uint64_t" => "u64",
+            "::intptr_t" | "::ssize_t" => "isize",
+            "::uintptr_t" | "::size_t" => "usize",
+            "::nsresult" => "nserror::nsresult",
+            "::nsString" | "::nsDependentSubstring" => "nsstring::nsString",
+            "::nsCString" | "::nsDependentCSubstring" => "nsstring::nsCString",
+            "::mozilla::ipc::Shmem" => "ipc::Shmem",
+            "::mozilla::ipc::ByteBuf" => "ipc::ByteBuf",
+            "::mozilla::ipc::FileDescriptor" => "ipc::FileDescriptor",
+            _ => return None,
+        })
+    }
+
+    fn rust_type(&self, t: &IPDLType) -> String {
+        let tuts = &self.program.tuts;
+        match t {
+            IPDLType::BuiltinCType(name) => Self::builtin(name).unwrap().to_string(),
+            IPDLType::ImportedCxxType(qid, _, _, _)
+            | IPDLType::ShmemType(qid)
+            | IPDLType::ByteBufType(qid)
+            | IPDLType::FDType(qid) => match Self::builtin(&qid.to_string()) {
+                Some(name) => name.to_string(),
+                None => qid.short_name(),
+            },
+            IPDLType::EndpointType(_) => String::from("ipc::Endpoint"),
+            IPDLType::ManagedEndpointType(_, _) => String::from("ipc::ManagedEndpoint"),
+            IPDLType::StructType(tr) => tr.lookup_struct(tuts).qname.short_name(),
+            IPDLType::UnionType(tr) => tr.lookup_union(tuts).qname.short_name(),
+            // Without NotNull, an actor can be null.
+            IPDLType::ActorType(_) => String::from("Option<ipc::ActorId>"),
+            IPDLType::NotNullType(inner) => match **inner {
+                IPDLType::ActorType(_) => String::from("ipc::ActorId"),
+                ref inner => self.rust_type(inner),
+            },
+            IPDLType::ArrayType(t) => format!("Vec<{}>", self.rust_type(t)),
+            IPDLType::MaybeType(t) => format!("Option<{}>", self.rust_type(t)),
+            IPDLType::UniquePtrType(t) => format!("Box<{}>", self.rust_type(t)),
+            IPDLType::MessageType(_) | IPDLType::ProtocolType(_) => {
+                panic!("{} has no Rust type", t.type_name())
+            }
+        }
+    }
+
+    // Whether a value of type |t| can contain a |target| without going
+    // through a Vec or a Box.
+    fn contains(&self, t: &IPDLType, target: &TypeRef, seen: &mut HashSet<TypeRef>) -> bool {
+        let tuts = &self.program.tuts;
+        match t {
+            IPDLType::MaybeType(t) | IPDLType::NotNullType(t) => self.contains(t, target, seen),
+            IPDLType::StructType(tr) => {
+                tr == target
+                    || (seen.insert(tr.clone())
+                        && tr
+                            .lookup_struct(tuts)
+                            .field_types(tuts)
+                            .any(|f| self.contains(f, target, seen)))
+            }
+            IPDLType::UnionType(tr) => {
+                tr == target
+                    || (seen.insert(tr.clone())
+                        && tr
+                            .lookup_union(tuts)
+                            .component_types(tuts)
+                            .any(|c| self.contains(c, target, seen)))
+            }
+            _ => false,
+        }
+    }
+
+    // The type of a field of the struct or union |owner|, which has to be
+    // boxed if the type is recursive.
+    fn member_type(&self, t: &IPDLType, owner: &TypeRef) -> String {
+        let rust_type = self.rust_type(t);
+        if self.contains(t, owner, &mut HashSet::new()) {
+            format!("Box<{}>", rust_type)
+        } else {
+            rust_type
+        }
+    }
+
+    fn fields(&self, params: &[ParamTypeDef]) -> Vec<String> {
+        params
+            .iter()
+            .map(|p| {
+                format!(
+                    "{}: {}",
+                    snake_case(&p.name.id),
+                    self.rust_type(p.param_type.lookup(&self.program.tuts))
+                )
+            })
+            .collect()
+    }
+
+    fn structs_and_unions(&self, tuid: &TUId, out: &mut String) {
+        let tu = &self.program.tus[tuid];
+        let tut = &self.program.tuts[tuid];
+        for (index, sdef) in tut.structs.iter().enumerate() {
+            let owner = TypeRef::new(tuid, index);
+            writeln!(out, "{}", marker(&sdef.qname.base_id.loc)).unwrap();
+            writeln!(out, "pub struct {} {{", sdef.qname.short_name()).unwrap();
+            for (f, t) in tu.structs[index]
+                .2
+                .iter()
+                .zip(sdef.field_types(&self.program.tuts))
+            {
+                writeln!(
+                    out,
+                    "    pub {}: {},",
+                    snake_case(&f.name.id),
+                    self.member_type(t, &owner)
+                )
+                .unwrap();
+            }
+            writeln!(out, "}}\n").unwrap();
+        }
+        for (index, udef) in tut.unions.iter().enumerate() {
+            let owner = TypeRef::new(tuid, index);
+            writeln!(out, "{}", marker(&udef.qname.base_id.loc)).unwrap();
+            writeln!(out, "pub enum {} {{", udef.qname.short_name()).unwrap();
+            for c in udef.component_types(&self.program.tuts) {
+                writeln!(
+                    out,
+                    "    {}({}),",
+                    camel_case(&flat_type_name(c, &self.program.tuts)),
+                    self.member_type(c, &owner)
+                )
+                .unwrap();
+            }
+            writeln!(out, "}}\n").unwrap();
+        }
+    }
+
+    fn receives(md: &MessageTypeDef, side: ProtocolSide) -> bool {
+        match side {
+            ProtocolSide::Parent => md.direction.is_to_parent(),
+            ProtocolSide::Child => md.direction.is_to_child(),
+        }
+    }
+
+    fn returns(&self, md: &MessageTypeDef) -> String {
+        let types = md
+            .returns
+            .iter()
+            .map(|p| self.rust_type(p.param_type.lookup(&self.program.tuts)))
+            .collect::<Vec<_>>();
+        if types.len() == 1 {
+            types.into_iter().next().unwrap()
+        } else {
+            format!("({})", types.join(", "))
+        }
+    }
+
+    fn protocol(&self, tuid: &TUId, out: &mut String) {
+        let ptype = self.program.protocol(tuid);
+        let name = ptype.qname.short_name();
+        for side in &[ProtocolSide::Parent, ProtocolSide::Child] {
+            let messages = ptype
+                .messages
+                .iter()
+                .filter(|md| Self::receives(md, *side))
+                .collect::<Vec<_>>();
+
+            writeln!(out, "// The messages that {}{:?} receives.", name, side).unwrap();
+            writeln!(out, "pub enum {}{:?}Message {{", name, side).unwrap();
+            for md in &messages {
+                let mut fields = Vec::new();
+                // The new actor comes first, like in the C++ Recv method.
+                if md.is_ctor() {
+                    fields.push(String::from("new_actor: ipc::ActorId"));
+                }
+                fields.extend(self.fields(&md.params));
+                writeln!(out, "    {}", marker(&md.name.loc)).unwrap();
+                if fields.is_empty() {
+                    writeln!(out, "    {},", camel_case(&md.name.id)).unwrap();
+                } else {
+                    writeln!(
+                        out,
+                        "    {} {{ {} }},",
+                        camel_case(&md.name.id),
+                        fields.join(", ")
+                    )
+                    .unwrap();
+                }
+            }
+            writeln!(out, "}}\n").unwrap();
+
+            writeln!(out, "pub trait {}{:?}Handler {{", name, side).unwrap();
+            for md in &messages {
+                let mut params = vec![String::from("&mut self")];
+                if md.is_ctor() {
+                    params.push(String::from("new_actor: ipc::ActorId"));
+                }
+                params.extend(self.fields(&md.params));
+                writeln!(out, "    {}", marker(&md.name.loc)).unwrap();
+                writeln!(
+                    out,
+                    "    fn recv_{}({}) -> Result<{}, ipc::Error>;",
+                    snake_case(&md.name.id),
+                    params.join(", "),
+                    self.returns(md)
+                )
+                .unwrap();
+            }
+            writeln!(out, "}}\n").unwrap();
+        }
+    }
+}
+
+// The Rust bindings for a translation unit, or None if there is nothing
+// to generate.
+pub fn rust_bindings(program: &TypedProgram, tuid: &TUId) -> Option<String> {
+    let tut = &program.tuts[tuid];
+    if tut.structs.is_empty() && tut.unions.is_empty() && tut.protocol.is_none() {
+        return None;
+    }
+
+    let gen = RustGen { program };
+    let mut out = String::from("// Generated from IPDL. Do not edit.\n\n");
+    gen.structs_and_unions(tuid, &mut out);
+    if tut.protocol.is_some() {
+        gen.protocol(tuid, &mut out);
+    }
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    Some(out)
+}
+
+#[test]
+fn name_tests() {
+    assert_eq!(snake_case("aFooBar"), "a_foo_bar");
+    assert_eq!(snake_case("__delete__"), "delete");
+    assert_eq!(snake_case("SomeMsg"), "some_msg");
+    assert_eq!(snake_case("type"), "r#type");
+    assert_eq!(snake_case("aURI2"), "a_uri2");
+    assert_eq!(
+        snake_case("PMyManagedConstructor"),
+        "p_my_managed_constructor"
+    );
+    assert_eq!(camel_case("__delete__"), "Delete");
+    assert_eq!(camel_case("int"), "Int");
+}
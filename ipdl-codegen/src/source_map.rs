@@ -0,0 +1,121 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Source maps from generated C++ back to the IPDL it came from.
+//
+// The generators put a `// from PFoo.ipdl:123' comment before the code for
+// each declaration. Only the file name is used, so the generated code
+// doesn't depend on where the source tree is. A source map is made by
+// finding these markers again: the lines from a marker up to the next
+// marker, blank line or line that is indented less than the marker come
+// from the marked location.
+
+use ast::Location;
+use ir::TypedProgram;
+use json::{json_list, json_path};
+use std::path::{Path, PathBuf};
+
+const MARKER_PREFIX: &str = "// from ";
+
+// The marker comment for code generated from |loc|, without indentation.
+pub fn marker(loc: &Location) -> String {
+    let file = loc
+        .file_name
+        .file_name()
+        .unwrap_or(loc.file_name.as_os_str());
+    format!("{}{}:{}", MARKER_PREFIX, file.to_string_lossy(), loc.lineno)
+}
+
+// A range of generated lines, numbered from 1, and where they came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapping {
+    pub first_line: usize,
+    pub last_line: usize,
+    pub source: PathBuf,
+    pub source_line: usize,
+}
+
+// Parse a marker comment into a file name and a line number.
+fn parse_marker(line: &str) -> Option<(&str, usize)> {
+    let rest = line.trim_start().strip_prefix(MARKER_PREFIX)?;
+    let (file, lineno) = rest.rsplit_once(':')?;
+    Some((file, lineno.trim_end().parse().ok()?))
+}
+
+// Find the mappings of generated code. The file names in the markers are
+// resolved to the files of |program| with those names.
+pub fn mappings(program: &TypedProgram, generated: &str) -> Vec<Mapping> {
+    let source_of = |name: &str| {
+        program.tu_ids().into_iter().find_map(|tuid| {
+            let file = &program.tus[&tuid].file_name;
+            if file.file_name().is_some_and(|f| f == name) {
+                Some(file.clone())
+            } else {
+                None
+            }
+        })
+    };
+
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let mut mappings: Vec<Mapping> = Vec::new();
+    // The indentation of the marker for the last mapping, if more lines
+    // can still be added to it.
+    let mut open = None;
+    for (i, line) in generated.lines().enumerate() {
+        let lineno = i + 1;
+        if let Some((name, source_line)) = parse_marker(line) {
+            open = match source_of(name) {
+                Some(source) => {
+                    mappings.push(Mapping {
+                        first_line: lineno,
+                        last_line: lineno,
+                        source,
+                        source_line,
+                    });
+                    Some(indent(line))
+                }
+                None => None,
+            };
+        } else if line.trim().is_empty() || open.is_none_or(|i| indent(line) < i) {
+            open = None;
+        } else {
+            mappings.last_mut().unwrap().last_line = lineno;
+        }
+    }
+    mappings
+}
+
+// The JSON source map for a generated file.
+pub fn source_map(program: &TypedProgram, generated_file: &Path, generated: &str) -> String {
+    let entries = mappings(program, generated).into_iter().map(|m| {
+        format!(
+            "{{\"lines\": [{}, {}], \"source\": {}, \"source_line\": {}}}",
+            m.first_line,
+            m.last_line,
+            json_path(&m.source),
+            m.source_line
+        )
+    });
+    format!(
+        "{{\n  \"file\": {},\n  \"mappings\": {}\n}}\n",
+        json_path(generated_file),
+        json_list(entries)
+    )
+}
+
+#[test]
+fn marker_tests() {
+    let loc = Location {
+        file_name: PathBuf::from("/src/dom/PFoo.ipdl"),
+        lineno: 12,
+        colno: 3,
+    };
+    assert_eq!(marker(&loc), "// from PFoo.ipdl:12");
+    assert_eq!(
+        parse_marker("    // from PFoo.ipdl:12"),
+        Some(("PFoo.ipdl", 12))
+    );
+    assert_eq!(parse_marker("// fromage"), None);
+    assert_eq!(parse_marker("// from PFoo.ipdl:x"), None);
+}
@@ -0,0 +1,327 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// TypeScript declarations for the payloads of messages, for devtools and
+// test harnesses that simulate IPC in JavaScript.
+//
+// Each struct becomes an interface, each union becomes a union of
+// `{ type: "TFoo", value: Foo }' objects, using the same tags as the C++
+// unions, and each protocol becomes a namespace with an interface per
+// message. The types are mapped like this:
+//
+// - Numbers are `number', except for 64 bit integers, which are `bigint'.
+// - nsString and nsCString are `string'.
+// - Arrays are `T[]' and Maybe and nullable types are `T | null'.
+// - Actors are `ActorRef<"PFoo">', with the protocol name and actor id.
+// - Shmem, ByteBuf, FileDescriptor and endpoints are the interfaces of the
+//   same names from IPDLTypes.d.ts, which describe how a harness should
+//   represent them.
+// - Other imported C++ types are `unknown', because only their C++
+//   ParamTraits know how they are serialized.
+
+use ast::{Direction, ProtocolSide, TUId};
+use cxx::flat_type_name;
+use ir::TypedProgram;
+use source_map::marker;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use type_check::{IPDLType, MessageTypeDef, ParamTypeDef, TypeRef};
+
+// The contents of IPDLTypes.d.ts, which every generated file imports.
+pub const PRELUDE: &str = "\
+// Types shared by the generated IPDL declarations.
+
+// A reference to an actor of protocol P. Actors are identified by their
+// routing id, which is unique for the channel.
+export interface ActorRef<P extends string> {
+  protocol: P;
+  id: number;
+}
+
+// A shared memory segment. A harness doesn't have to share the memory, as
+// long as both sides agree on the contents.
+export interface Shmem {
+  kind: \"Shmem\";
+  id: number;
+  size: number;
+}
+
+// A buffer of bytes that is moved rather than copied.
+export interface ByteBuf {
+  kind: \"ByteBuf\";
+  data: Uint8Array;
+}
+
+// A file descriptor or handle. Harnesses can use any number that they can
+// map back to a file.
+export interface FileDescriptor {
+  kind: \"FileDescriptor\";
+  fd: number;
+}
+
+// One end of a new top level actor of protocol P, for that side.
+export interface Endpoint<P extends string> {
+  kind: \"Endpoint\";
+  protocol: P;
+  side: \"Parent\" | \"Child\";
+}
+
+// One end of a new actor of protocol P that is managed by another actor.
+export interface ManagedEndpoint<P extends string> {
+  kind: \"ManagedEndpoint\";
+  protocol: P;
+  side: \"Parent\" | \"Child\";
+}
+";
+
+struct TsGen<'a> {
+    program: &'a TypedProgram,
+    tuid: TUId,
+    // The structs and unions from other files, by the file they come from.
+    imports: BTreeMap<String, Vec<String>>,
+    // The types from IPDLTypes.d.ts that are used.
+    prelude: BTreeSet<&'static str>,
+}
+
+impl<'a> TsGen<'a> {
+    fn builtin(name: &str) -> Option<&'static str> {
+        Some(match name {
+            "bool" => "boolean",
+            "char" | "short" | "int" | "long" | "float" | "double" => "number",
+            "::int8_t" | "::uint8_t" | "::int16_t" | "::uint16_t" | "::int32_t" | "::uint32_t"
+            | "::intptr_t" | "::uintptr_t" | "::size_t" | "::ssize_t" | "::nsresult" => "number",
+            "::int64_t" | "::uint64_t" => "bigint",
+            "::nsString" | "::nsCString" | "::nsDependentSubstring" | "::nsDependentCSubstring" => {
+                "string"
+            }
+            _ => return None,
+        })
+    }
+
+    // The endpoint types are named like `Endpoint<::ns::PFooParent>'.
+    fn endpoint_protocol(qid_name: &str) -> String {
+        let inner = &qid_name[qid_name.find('<').unwrap() + 1..qid_name.len() - 1];
+        let short = inner.rsplit("::").next().unwrap();
+        let protocol = short
+            .strip_suffix("Parent")
+            .or_else(|| short.strip_suffix("Child"))
+            .unwrap_or(short);
+        String::from(protocol)
+    }
+
+    fn prelude_type(&mut self, name: &'static str) -> String {
+        self.prelude.insert(name);
+        String::from(name)
+    }
+
+    fn named(&mut self, tr: &TypeRef, name: String) -> String {
+        if tr.tu != self.tuid {
+            let file = &self.program.tus[&tr.tu].file_name;
+            let stem = file.file_stem().unwrap().to_string_lossy().into_owned();
+            let names = self.imports.entry(stem).or_default();
+            if !names.contains(&name) {
+                names.push(name.clone());
+            }
+        }
+        name
+    }
+
+    fn ts_type(&mut self, t: &IPDLType) -> String {
+        let tuts = &self.program.tuts;
+        match t {
+            IPDLType::BuiltinCType(name) => Self::builtin(name).unwrap().to_string(),
+            IPDLType::ImportedCxxType(qid, _, _, _) => match Self::builtin(&qid.to_string()) {
+                Some(name) => name.to_string(),
+                None => format!("unknown /* {} */", qid),
+            },
+            IPDLType::ShmemType(_) => self.prelude_type("Shmem"),
+            IPDLType::ByteBufType(_) => self.prelude_type("ByteBuf"),
+            IPDLType::FDType(_) => self.prelude_type("FileDescriptor"),
+            IPDLType::EndpointType(qid) => format!(
+                "{}<\"{}\">",
+                self.prelude_type("Endpoint"),
+                Self::endpoint_protocol(&qid.base_id.id)
+            ),
+            IPDLType::ManagedEndpointType(qid, _) => format!(
+                "{}<\"{}\">",
+                self.prelude_type("ManagedEndpoint"),
+                Self::endpoint_protocol(&qid.base_id.id)
+            ),
+            IPDLType::StructType(tr) => {
+                let name = tr.lookup_struct(tuts).qname.short_name();
+                self.named(tr, name)
+            }
+            IPDLType::UnionType(tr) => {
+                let name = tr.lookup_union(tuts).qname.short_name();
+                self.named(tr, name)
+            }
+            // Without NotNull, actors and refcounted types can be null.
+            IPDLType::ActorType(tuid) => format!(
+                "{}<\"{}\"> | null",
+                self.prelude_type("ActorRef"),
+                self.program.protocol(tuid).qname.short_name()
+            ),
+            IPDLType::NotNullType(inner) => {
+                let t = self.ts_type(inner);
+                t.strip_suffix(" | null").map(String::from).unwrap_or(t)
+            }
+            IPDLType::ArrayType(t) => {
+                let inner = self.ts_type(t);
+                if inner.contains(' ') {
+                    format!("({})[]", inner)
+                } else {
+                    format!("{}[]", inner)
+                }
+            }
+            IPDLType::MaybeType(t) => format!("{} | null", self.ts_type(t)),
+            IPDLType::UniquePtrType(t) => self.ts_type(t),
+            IPDLType::MessageType(_) | IPDLType::ProtocolType(_) => {
+                panic!("{} has no TypeScript type", t.type_name())
+            }
+        }
+    }
+
+    fn object(&mut self, mut fields: Vec<String>, params: &[ParamTypeDef]) -> String {
+        let program = self.program;
+        for p in params {
+            let t = self.ts_type(p.param_type.lookup(&program.tuts));
+            fields.push(format!("{}: {};", p.name, t));
+        }
+        if fields.is_empty() {
+            String::from("{}")
+        } else {
+            format!("{{ {} }}", fields.join(" "))
+        }
+    }
+
+    fn declarations(&mut self) -> String {
+        let program = self.program;
+        let tuid = self.tuid;
+        let tu = &program.tus[&tuid];
+        let tut = &program.tuts[&tuid];
+        let mut out = String::new();
+
+        for (index, sdef) in tut.structs.iter().enumerate() {
+            writeln!(out, "{}", marker(&sdef.qname.base_id.loc)).unwrap();
+            writeln!(out, "export interface {} {{", sdef.qname.short_name()).unwrap();
+            for (f, t) in tu.structs[index]
+                .2
+                .iter()
+                .zip(sdef.field_types(&program.tuts))
+            {
+                let t = self.ts_type(t);
+                writeln!(out, "  {}: {};", f.name, t).unwrap();
+            }
+            writeln!(out, "}}\n").unwrap();
+        }
+
+        for udef in &tut.unions {
+            writeln!(out, "{}", marker(&udef.qname.base_id.loc)).unwrap();
+            writeln!(out, "export type {} =", udef.qname.short_name()).unwrap();
+            let variants = udef
+                .component_types(&program.tuts)
+                .map(|c| {
+                    format!(
+                        "  | {{ type: \"T{}\"; value: {} }}",
+                        flat_type_name(c, &program.tuts),
+                        self.ts_type(c)
+                    )
+                })
+                .collect::<Vec<_>>();
+            writeln!(out, "{};\n", variants.join("\n")).unwrap();
+        }
+
+        if let Some(ref ptype) = tut.protocol {
+            writeln!(out, "export namespace {} {{", ptype.qname.short_name()).unwrap();
+            for md in &ptype.messages {
+                let direction = match md.direction {
+                    Direction::To(side) => format!("to{:?}", side),
+                    Direction::Both => String::from("both"),
+                };
+                // A constructor carries the id of the new actor.
+                let mut new_actor = Vec::new();
+                if let Some(managee) = md.ctor_type() {
+                    let managee = program.protocol(managee).qname.short_name();
+                    let actor_ref = self.prelude_type("ActorRef");
+                    new_actor.push(format!("newActor: {}<\"{}\">;", actor_ref, managee));
+                }
+                let params = self.object(new_actor, &md.params);
+                let returns = self.object(Vec::new(), &md.returns);
+                writeln!(out, "  {}", marker(&md.name.loc)).unwrap();
+                writeln!(out, "  export interface {} {{", md.name).unwrap();
+                writeln!(out, "    name: \"{}\";", md.name).unwrap();
+                writeln!(out, "    direction: \"{}\";", direction).unwrap();
+                writeln!(out, "    params: {};", params).unwrap();
+                writeln!(out, "    returns: {};", returns).unwrap();
+                writeln!(out, "  }}\n").unwrap();
+            }
+            let union = |messages: Vec<&MessageTypeDef>| {
+                if messages.is_empty() {
+                    String::from("never")
+                } else {
+                    messages
+                        .iter()
+                        .map(|md| md.name.id.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" | ")
+                }
+            };
+            let all = ptype.messages.iter().collect();
+            writeln!(out, "  export type Message = {};", union(all)).unwrap();
+            // The messages each side can send, with `both' messages in both.
+            let to_parent = self
+                .program
+                .messages_sent_by(&self.tuid, ProtocolSide::Child);
+            let to_child = self
+                .program
+                .messages_sent_by(&self.tuid, ProtocolSide::Parent);
+            writeln!(out, "  export type ToParent = {};", union(to_parent)).unwrap();
+            writeln!(out, "  export type ToChild = {};\n}}", union(to_child)).unwrap();
+        }
+        out
+    }
+}
+
+// The TypeScript declarations for a translation unit, or None if there
+// is nothing to declare.
+pub fn typescript_declarations(program: &TypedProgram, tuid: &TUId) -> Option<String> {
+    let tut = &program.tuts[tuid];
+    if tut.structs.is_empty() && tut.unions.is_empty() && tut.protocol.is_none() {
+        return None;
+    }
+
+    let mut gen = TsGen {
+        program,
+        tuid: *tuid,
+        imports: BTreeMap::new(),
+        prelude: BTreeSet::new(),
+    };
+    let declarations = gen.declarations();
+
+    let mut out = String::from("// Generated from IPDL. Do not edit.\n\n");
+    let prelude = gen.prelude.iter().cloned().collect::<Vec<_>>();
+    if !prelude.is_empty() {
+        writeln!(
+            out,
+            "import type {{ {} }} from \"./IPDLTypes\";",
+            prelude.join(", ")
+        )
+        .unwrap();
+    }
+    for (file, names) in &gen.imports {
+        writeln!(
+            out,
+            "import type {{ {} }} from \"./{}\";",
+            names.join(", "),
+            file
+        )
+        .unwrap();
+    }
+    if !prelude.is_empty() || !gen.imports.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(declarations.trim_end());
+    out.push('\n');
+    Some(out)
+}
@@ -0,0 +1,256 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// The wire format of each message: the sequence of reads that the
+// ParamTraits of its parameters do, with structs flattened into their
+// fields, for fuzzers that build message payloads themselves.
+//
+// This follows the ParamTraits in param_traits.rs. A struct is its fields
+// in declaration order. A union is an int tag, which is 1 for the first
+// component, as in the C++ enum where T__None is 0, followed by the value
+// of that component. A Maybe or a UniquePtr is a bool, followed by the
+// value if it is true. An array or a ByteBuf is a uint32_t length followed
+// by that many elements. An actor is its int32_t ID. Shmems, file
+// descriptors and endpoints are sent next to the message, rather than in
+// it. Any other imported type is a single value, written by whatever
+// ParamTraits it has, so the fuzzer has to know about it.
+//
+// A struct or union that contains itself is only expanded once. Inside of
+// itself, it is a `recursive' item that refers back to it by name.
+
+use ast::TUId;
+use cxx::{cxx_type, flat_type_name};
+use ir::TypedProgram;
+use json::{json_list, json_string};
+use std::path::PathBuf;
+use type_check::{builtin_size, IPDLType, ParamTypeDef, TypeRef};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WireKind {
+    // A value with its own ParamTraits, and its size in bytes if it is
+    // fixed.
+    Value {
+        cxx_type: String,
+        size: Option<usize>,
+    },
+    // A length, followed by that many of the element.
+    Array(Vec<WireItem>),
+    // A bool, followed by the value if it is true.
+    Optional(Vec<WireItem>),
+    // A tag, followed by the value of the variant with that tag, which
+    // are listed with their C++ names in tag order.
+    Union {
+        name: String,
+        variants: Vec<(String, Vec<WireItem>)>,
+    },
+    // A struct or union that is already being expanded.
+    Recursive(String),
+    // The ID of an actor of a protocol.
+    Actor(String),
+    // Something that is sent next to the message instead of in it.
+    Attachment(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WireItem {
+    // Where the value comes from, like `aParam.field' for a field of a
+    // struct parameter, or `aParam[]' for the elements of an array. The
+    // value of a Maybe or a union has the same path as the Maybe or union.
+    pub path: String,
+    pub kind: WireKind,
+}
+
+struct Flattener<'a> {
+    program: &'a TypedProgram,
+    // The names of the structs and unions that are being expanded.
+    on_path: Vec<String>,
+}
+
+impl<'a> Flattener<'a> {
+    fn items(&mut self, t: &IPDLType, path: &str) -> Vec<WireItem> {
+        let tuts = &self.program.tuts;
+        let item = |kind| {
+            vec![WireItem {
+                path: path.to_string(),
+                kind,
+            }]
+        };
+        match t {
+            IPDLType::StructType(tr) => self.compound(tr, true, path),
+            IPDLType::UnionType(tr) => self.compound(tr, false, path),
+            IPDLType::ArrayType(inner) => {
+                item(WireKind::Array(self.items(inner, &format!("{}[]", path))))
+            }
+            IPDLType::ByteBufType(_) => item(WireKind::Array(vec![WireItem {
+                path: format!("{}[]", path),
+                kind: WireKind::Value {
+                    cxx_type: String::from("::uint8_t"),
+                    size: Some(1),
+                },
+            }])),
+            IPDLType::MaybeType(inner) | IPDLType::UniquePtrType(inner) => {
+                item(WireKind::Optional(self.items(inner, path)))
+            }
+            IPDLType::NotNullType(inner) => self.items(inner, path),
+            IPDLType::ActorType(tuid) | IPDLType::ProtocolType(tuid) => item(WireKind::Actor(
+                self.program.protocol(tuid).qname.to_string(),
+            )),
+            IPDLType::ShmemType(_)
+            | IPDLType::FDType(_)
+            | IPDLType::EndpointType(_)
+            | IPDLType::ManagedEndpointType(_, _) => item(WireKind::Attachment(cxx_type(t, tuts))),
+            IPDLType::BuiltinCType(name) => item(WireKind::Value {
+                cxx_type: cxx_type(t, tuts),
+                size: builtin_size(name),
+            }),
+            IPDLType::ImportedCxxType(qid, _, _, _) => item(WireKind::Value {
+                cxx_type: cxx_type(t, tuts),
+                size: builtin_size(&qid.to_string()),
+            }),
+            IPDLType::MessageType(_) => item(WireKind::Value {
+                cxx_type: cxx_type(t, tuts),
+                size: None,
+            }),
+        }
+    }
+
+    fn compound(&mut self, tr: &TypeRef, is_struct: bool, path: &str) -> Vec<WireItem> {
+        let program = self.program;
+        let name = if is_struct {
+            tr.lookup_struct(&program.tuts).qname.to_string()
+        } else {
+            tr.lookup_union(&program.tuts).qname.to_string()
+        };
+        if self.on_path.contains(&name) {
+            return vec![WireItem {
+                path: path.to_string(),
+                kind: WireKind::Recursive(name),
+            }];
+        }
+        self.on_path.push(name.clone());
+        let items = if is_struct {
+            // The typed struct only has the types of the fields, so the
+            // names come from the AST.
+            let sdef = tr.lookup_struct(&program.tuts);
+            let fields = &program.tus[&tr.tu].structs[tr.index].2;
+            fields
+                .iter()
+                .zip(sdef.field_types(&program.tuts))
+                .flat_map(|(f, t)| self.items(t, &format!("{}.{}", path, f.name)))
+                .collect()
+        } else {
+            let udef = tr.lookup_union(&program.tuts);
+            let variants = udef
+                .component_types(&program.tuts)
+                .map(|t| {
+                    (
+                        format!("T{}", flat_type_name(t, &program.tuts)),
+                        self.items(t, path),
+                    )
+                })
+                .collect();
+            vec![WireItem {
+                path: path.to_string(),
+                kind: WireKind::Union { name, variants },
+            }]
+        };
+        self.on_path.pop();
+        items
+    }
+}
+
+// The wire format of a list of parameters, in the order they are written.
+pub fn wire_format(program: &TypedProgram, params: &[ParamTypeDef]) -> Vec<WireItem> {
+    let mut flattener = Flattener {
+        program,
+        on_path: Vec::new(),
+    };
+    params
+        .iter()
+        .flat_map(|p| flattener.items(p.param_type.lookup(&program.tuts), &p.name.id))
+        .collect()
+}
+
+fn item_json(item: &WireItem) -> String {
+    let items_json = |items: &[WireItem]| json_list(items.iter().map(item_json));
+    let fields = match &item.kind {
+        WireKind::Value { cxx_type, size } => format!(
+            "\"read\": \"value\", \"type\": {}, \"size\": {}",
+            json_string(cxx_type),
+            size.map_or(String::from("null"), |s| s.to_string())
+        ),
+        WireKind::Array(element) => format!(
+            "\"read\": \"array\", \"length_type\": \"uint32_t\", \"element\": {}",
+            items_json(element)
+        ),
+        WireKind::Optional(value) => format!(
+            "\"read\": \"optional\", \"tag_type\": \"bool\", \"value\": {}",
+            items_json(value)
+        ),
+        WireKind::Union { name, variants } => format!(
+            "\"read\": \"union\", \"union\": {}, \"tag_type\": \"int\", \"variants\": {}",
+            json_string(name),
+            json_list(variants.iter().enumerate().map(|(i, (tag, items))| {
+                format!(
+                    "{{\"tag\": {}, \"name\": {}, \"value\": {}}}",
+                    i + 1,
+                    json_string(tag),
+                    items_json(items)
+                )
+            }))
+        ),
+        WireKind::Recursive(name) => {
+            format!("\"read\": \"recursive\", \"type\": {}", json_string(name))
+        }
+        WireKind::Actor(protocol) => format!(
+            "\"read\": \"actor\", \"type\": \"int32_t\", \"protocol\": {}",
+            json_string(protocol)
+        ),
+        WireKind::Attachment(cxx_type) => format!(
+            "\"read\": \"attachment\", \"type\": {}",
+            json_string(cxx_type)
+        ),
+    };
+    format!("{{\"path\": {}, {}}}", json_string(&item.path), fields)
+}
+
+fn protocol_json(program: &TypedProgram, tuid: &TUId) -> Option<String> {
+    let ptype = program.tuts[tuid].protocol.as_ref()?;
+    let messages = ptype
+        .messages
+        .iter()
+        .map(|md| {
+            format!(
+                "      {{\"name\": {}, \"params\": {}, \"returns\": {}}}",
+                json_string(&md.name.id),
+                json_list(wire_format(program, &md.params).iter().map(item_json)),
+                json_list(wire_format(program, &md.returns).iter().map(item_json))
+            )
+        })
+        .collect::<Vec<_>>();
+    let messages = if messages.is_empty() {
+        String::from("[]")
+    } else {
+        format!("[\n{}\n    ]", messages.join(",\n"))
+    };
+    Some(format!(
+        "  {{\n    \"name\": {},\n    \"messages\": {}\n  }}",
+        json_string(&ptype.qname.to_string()),
+        messages
+    ))
+}
+
+// A JSON description of the wire format of the messages of the protocols
+// in |file_names|, which |program| must have been checked from.
+pub fn wire_format_json(program: &TypedProgram, file_names: &[PathBuf]) -> String {
+    let protocols = file_names
+        .iter()
+        .filter_map(|file| protocol_json(program, &program.tu_for_file(file).unwrap()))
+        .collect::<Vec<_>>();
+    if protocols.is_empty() {
+        String::from("{\"protocols\": []}\n")
+    } else {
+        format!("{{\"protocols\": [\n{}\n]}}\n", protocols.join(",\n"))
+    }
+}
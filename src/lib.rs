@@ -1,7 +1,9 @@
-pub mod ast;
-pub mod compiler;
-mod errors;
-mod ipdl;
-pub mod parser;
-mod type_check;
-mod uncommenter;
+// Everything lives in the workspace crates; this re-exports them under one
+// name, as the library was before it was split up.
+extern crate ipdl_check;
+#[cfg(feature = "codegen")]
+extern crate ipdl_codegen;
+
+pub use ipdl_check::*;
+#[cfg(feature = "codegen")]
+pub use ipdl_codegen::*;
@@ -3,18 +3,23 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 extern crate getopts;
+extern crate ipdl_parser;
 
-pub mod ast;
-pub mod compiler;
-pub mod errors;
-pub mod ipdl;
-pub mod parser;
-pub mod type_check;
-pub mod uncommenter;
-
+use baseline::Baseline;
 use getopts::Options;
+use ipdl_parser::{
+    actors, ast, ast_diff, baseline, batch, catalog, commands, compiler, config, endpoints, errors,
+    explain, export, fixes, forward_decls, golden, include_graph, inputs, ir, layout,
+    lifetime_diagrams, managers, message_ids, metrics, param_traits, parser, progress,
+    rust_bindings, shmem, sinks, snapshot, source_map, type_check, typescript, versions,
+    wire_format,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
 
 fn get_options_parser() -> Options {
     let mut opts = Options::new();
@@ -42,23 +47,1327 @@ fn get_options_parser() -> Options {
                 to be generated",
         "CPP_DIR",
     );
+    opts.optopt(
+        "",
+        "roots",
+        "Comma-separated list of top-level protocols. Every protocol that \
+                can't be reached from them through |manages| declarations \
+                is reported",
+        "PROTOCOLS",
+    );
+    opts.optopt(
+        "",
+        "max-manager-depth",
+        "Warn about protocols with more than this many levels of managers",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "max-managees",
+        "Warn about protocols that manage more than this many protocols",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "max-params",
+        "Warn about messages with more than this many parameters (default 14)",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "max-fds",
+        "Warn about messages whose parameters or reply can carry more than \
+                this many file descriptors",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "max-message-size",
+        "Warn about messages whose parameters or reply can be larger than \
+                this many bytes, unless they declare a [MaxSize] within it",
+        "BYTES",
+    );
+    opts.optopt(
+        "",
+        "max-file-size",
+        "Reject input files larger than this many bytes (default 16 MiB)",
+        "BYTES",
+    );
+    opts.optopt(
+        "",
+        "max-include-depth",
+        "Reject includes nested more than this many levels deep (default 64)",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "max-declarations",
+        "Reject files that declare more than this many types, fields, \
+                messages and parameters (default 100000)",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "defines",
+        "Comma-separated list of the names that are defined for `#if' \
+                conditions. Without this, no names are defined",
+        "NAMES",
+    );
+    opts.optopt(
+        "",
+        "sync-msg-list",
+        "Warn about sync messages that aren't in FILE, which lists each \
+                allowed message as an INI section like [PContent::SyncMsg], \
+                as for ipdl.py, instead of the [sync] lists of the config files",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "type-aliases",
+        "Comma-separated list of ALIAS=TYPE pairs, like \
+                nsString=mozilla::nsString, for C++ types that can be \
+                imported under more than one name",
+        "ALIASES",
+    );
+    opts.optflag(
+        "",
+        "no-filename-check",
+        "Don't check that file names match protocol names, for generated files",
+    );
+    opts.optflag(
+        "",
+        "check-namespace-dirs",
+        "Warn about files that aren't in a directory that matches their namespace",
+    );
+    opts.optflag(
+        "",
+        "check-duplicate-structs",
+        "Warn about structs with the same fields as a struct in another file",
+    );
+    opts.optflag(
+        "",
+        "skip-unused-headers",
+        "Don't check the structs and unions of included headers that nothing uses",
+    );
+    opts.optflag(
+        "",
+        "show-source",
+        "Quote the line of IPDL that each error and warning is about",
+    );
+    opts.optflag(
+        "",
+        "progress",
+        "Show how many files have been parsed and checked so far on stderr",
+    );
+    opts.optflag(
+        "",
+        "no-config",
+        "Ignore the ipdl.toml files next to the files and in the directories above them",
+    );
+    opts.optmulti(
+        "D",
+        "deny",
+        "Report the warnings with this code, like `redundant_using', as errors, \
+                whatever the ipdl.toml files say",
+        "CODE",
+    );
+    opts.optmulti(
+        "W",
+        "warn",
+        "Report the warnings with this code as warnings, whatever the \
+                ipdl.toml files say",
+        "CODE",
+    );
+    opts.optmulti(
+        "A",
+        "allow",
+        "Don't report the warnings with this code, whatever the ipdl.toml \
+                files say",
+        "CODE",
+    );
+    opts.optflag(
+        "",
+        "strict",
+        "Also check for intr protocols and for toplevel protocols without a \
+                [ParentProc], and report them and sync messages without \
+                return values as errors, unless -W or -A says otherwise",
+    );
+    opts.optopt(
+        "",
+        "jobs",
+        "Split the files into N partitions and check each one in a \
+                process of its own, which only loads the files it includes, \
+                then merge their diagnostics. Only the checking options can \
+                be combined with this",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "partition",
+        "How --jobs splits the files. Only `hash', by the hash of each \
+                path, is supported",
+        "KIND",
+    );
+    opts.optmulti(
+        "",
+        "emit",
+        "Generate code that is normally left to the Python compiler: \
+                `structs', for the ParamTraits of structs and unions, which \
+                are written to CPP_DIR, or `actors', for the Parent and Child \
+                actor classes, which are written to HDR_DIR. `rust' writes \
+                Rust bindings for each file to CPP_DIR, and `typescript' \
+                writes TypeScript declarations of the message payloads there. \
+                `forward-decls' writes PFooForwardDecls.h to HDR_DIR, with \
+                the includes and forward declarations that the headers of \
+                each protocol need",
+        "KIND",
+    );
+    opts.optopt(
+        "",
+        "emit-to",
+        "Write the message tables and every kind of code that --emit can \
+                generate to DIR, in place of HDR_DIR and CPP_DIR, unless \
+                --emit says which kinds to generate",
+        "DIR",
+    );
+    opts.optflag(
+        "",
+        "compare-emitted",
+        "Instead of writing generated code, compare it with the files that \
+                are already in the output directories, print how they differ \
+                and fail if any do. This is for checking golden copies of \
+                the generated code",
+    );
+    opts.optopt(
+        "",
+        "output-layout",
+        "Where generated files go: `python', the default, puts headers in \
+                the directories of their namespaces below HDR_DIR and \
+                sources directly in CPP_DIR, like the Python compiler, \
+                `flat' puts all of them directly in the directories, and \
+                `namespaces' puts all of them in namespace directories",
+        "KIND",
+    );
+    opts.optflag(
+        "",
+        "source-maps",
+        "Write a JSON source map next to each generated file, which maps \
+                the generated lines back to the IPDL they came from",
+    );
+    opts.optopt(
+        "",
+        "diagnostics-json",
+        "Also write the errors or warnings to FILE as JSON, with their \
+                codes, locations and notes",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "baseline",
+        "Only report the warnings that aren't recorded in FILE, and fail \
+                if there are any. If FILE doesn't exist, the current \
+                warnings are recorded in it instead",
+        "FILE",
+    );
+    opts.optflag(
+        "",
+        "update-baseline",
+        "Record the current warnings in the --baseline file, even if it exists",
+    );
+    opts.optflag(
+        "",
+        "apply-fixes",
+        "Apply the fixes that diagnostics suggest to the files they are about",
+    );
+    opts.optflag(
+        "",
+        "dry-run",
+        "With --apply-fixes, print the changes as unified diffs instead of \
+                writing them",
+    );
+    opts.optopt(
+        "",
+        "compile-commands",
+        "Write a JSON description of how each file was checked, with its \
+                include path and resolved includes, for editors and indexers",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "metrics-out",
+        "Write the parse time, token and AST node counts, and numbers of \
+                errors and warnings of each file that was read to FILE as \
+                JSON, whether or not checking succeeded",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "summary-json",
+        "Write a summary of the run to FILE as JSON, whether or not \
+                checking succeeded: the status of each file, the number of \
+                diagnostics with each code, the time of each phase and the \
+                cache hits, for CI to keep",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "order",
+        "Only parse the files, and write each of them and the files it \
+                includes to FILE, in an order where each file comes after \
+                the files it includes, with the number of the include cycle \
+                it is in",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "ast-diff",
+        "Print how the declarations of the one file given on the command \
+                line differ from those in OLD, an older version of it, \
+                ignoring formatting and comments, and warn about the uses \
+                of deprecated protocols that it adds",
+        "OLD",
+    );
+    opts.optopt(
+        "",
+        "export-json",
+        "Write a JSON description of the protocols given on the command \
+                line to FILE, with the direction and senders of each message",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "wire-format-json",
+        "Write the wire format of each message of the protocols given on \
+                the command line to FILE as JSON: the values that are read \
+                for its parameters and reply, for fuzzers",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "endpoint-graph",
+        "Write the graph of which protocols can open which, by sending \
+                Endpoints and ManagedEndpoints in their messages, to FILE in \
+                the Graphviz dot language",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "manager-graph",
+        "Write the graph of which protocols manage which, labeled with \
+                their constructors, to FILE in the Graphviz dot language",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "manager-graph-json",
+        "Write the graph of which protocols manage which to FILE as JSON \
+                adjacency lists, with the constructors of each managed protocol",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "lifetime-diagrams",
+        "Write a Mermaid sequence diagram of how the actors of each managed \
+                protocol given on the command line are constructed and \
+                deleted to FILE, as Markdown",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "check-versions",
+        "Compare the protocols given on the command line against FILE, \
+                which --export-json wrote for an older tree, and fail if a \
+                protocol changed without bumping its [ProtocolVersion]",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "method-signatures",
+        "Write the Send and Recv method signatures of the actor classes of \
+                each protocol given on the command line to FILE, one per \
+                line, for diffing against another revision",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "forward-decls-json",
+        "Write the includes and C++ forward declarations that the headers \
+                of each protocol given on the command line need to FILE",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "profiler-markers-json",
+        "Write the numeric IDs, names and name hashes of the messages of \
+                every protocol to FILE as JSON, for resolving the message \
+                types of IPC profiler markers",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "write-snapshot",
+        "Write the parsed form of every file that was checked to FILE, for \
+                checking edited files against with --snapshot",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "snapshot",
+        "Take the included files that haven't changed from the snapshot in \
+                FILE instead of parsing them again",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "definition",
+        "Print where the protocol, struct, union or message NAME is \
+                declared. Messages are named after their protocol, like \
+                `mozilla::PFoo::Msg'",
+        "NAME",
+    );
+    opts.optopt(
+        "",
+        "uses",
+        "Print every message, struct and union that contains TYPE, \
+                directly or through other structs and unions",
+        "TYPE",
+    );
+    opts.optopt(
+        "",
+        "hosted-by",
+        "Print the top level protocols that can have a side in a PROCESS, \
+                like `GPU', going by their [ParentProc] and [ChildProc], and \
+                the protocols they manage",
+        "PROCESS",
+    );
+    opts.optopt(
+        "",
+        "type-closure",
+        "Print every struct, union and imported type that the messages of \
+                PROTOCOL need, and the headers the imported types come from",
+        "PROTOCOL",
+    );
+    opts.optopt(
+        "",
+        "explain",
+        "Print a summary of PROTOCOL: its managers and managees, its \
+                messages with their parameter types, and the files that \
+                provide the types it uses",
+        "PROTOCOL",
+    );
+    opts.optflag(
+        "",
+        "shmem-audit",
+        "Print every message that carries a Shmem, with its direction and \
+                whether it has an [AllowShmem] attribute",
+    );
+    opts.optflag(
+        "",
+        "sync-audit",
+        "Print every sync message, with its direction and any \
+                [AllowSyncFrom] exception it has",
+    );
+    opts.optflag(
+        "",
+        "lifetimes",
+        "Print which side can construct and delete each actor that the \
+                protocols given on the command line manage",
+    );
+    opts.optflag(
+        "",
+        "dump-symbols",
+        "Print the symbols that are visible in each file given on the command \
+                line, and how many times each is used in it",
+    );
+    opts
+}
+
+// The exit code for a bad command line, as EX_USAGE in sysexits.h. It is
+// apart from the exit codes of IpdlError, which are about the IPDL files.
+const USAGE_EXIT_CODE: i32 = 64;
+
+// Report a bad command line, as getopts does for an unknown option, and
+// stop without checking anything.
+fn usage_error(message: &str) -> ! {
+    println!("error: {}", message);
+    process::exit(USAGE_EXIT_CODE);
+}
+
+// The messages of the sync message list given with --sync-msg-list.
+fn read_sync_message_list(file: &str) -> Vec<String> {
+    match fs::read_to_string(file) {
+        Ok(text) => config::parse_sync_message_list(&text),
+        Err(e) => {
+            let e = errors::IpdlError::Io(format!("Can't read `{}': {}", file, e));
+            println!("{}", e);
+            process::exit(e.exit_code());
+        }
+    }
+}
+
+// The value of a numeric option, if it is present.
+fn opt_usize(matches: &getopts::Matches, name: &str) -> Option<usize> {
+    matches.opt_str(name).map(|v| match v.parse() {
+        Ok(n) => n,
+        Err(_) => usage_error(&format!("Expected a number for --{}, got `{}'", name, v)),
+    })
+}
+
+// The levels of -D, -W and -A. When a code is given more than once, the
+// last one wins, as with rustc.
+fn lint_levels(matches: &getopts::Matches) -> HashMap<String, config::LintLevel> {
+    let strict = if matches.opt_present("strict") {
+        type_check::STRICT_LINTS
+    } else {
+        &[]
+    };
+    let mut levels = [
+        ("D", config::LintLevel::Deny),
+        ("W", config::LintLevel::Warn),
+        ("A", config::LintLevel::Allow),
+    ]
+    .iter()
+    .flat_map(|(name, level)| {
+        matches
+            .opt_strs_pos(name)
+            .into_iter()
+            .map(move |(pos, code)| (pos, code, *level))
+    })
+    .collect::<Vec<_>>();
+    levels.sort_by_key(|(pos, _, _)| *pos);
+    let levels = levels
+        .into_iter()
+        .map(|(_, code, level)| (code.replace('-', "_"), level))
+        .collect::<Vec<_>>();
+    // A misspelled code would otherwise be silently ignored, and so would
+    // the code of an error, which can't be allowed.
+    for (code, _) in &levels {
+        let is_warning = catalog::entries()
+            .iter()
+            .any(|entry| entry.code() == code && entry.severity == errors::Severity::Warning);
+        if !is_warning {
+            usage_error(&format!("Unknown warning code `{}' for -D, -W or -A", code));
+        }
+    }
+    strict
+        .iter()
+        .map(|code| (String::from(*code), config::LintLevel::Deny))
+        .chain(levels)
+        .collect()
+}
+
+// The options that the workers of --jobs are given, with the options for
+// how to check files, like -I and -W, passed on from the command line.
+fn worker_args(matches: &getopts::Matches) -> Vec<String> {
+    let mut args = Vec::new();
+    for dir in matches.opt_strs("I") {
+        args.push(String::from("-I"));
+        args.push(dir);
+    }
+    // The order of -D, -W and -A matters.
+    let mut levels = ["D", "W", "A"]
+        .iter()
+        .flat_map(|name| {
+            matches
+                .opt_strs_pos(name)
+                .into_iter()
+                .map(move |(pos, code)| (pos, format!("-{}", name), code))
+        })
+        .collect::<Vec<_>>();
+    levels.sort_by_key(|(pos, _, _)| *pos);
+    for (_, flag, code) in levels {
+        args.push(flag);
+        args.push(code);
+    }
+    for name in &[
+        "max-manager-depth",
+        "max-managees",
+        "max-params",
+        "max-fds",
+        "max-message-size",
+        "max-file-size",
+        "max-include-depth",
+        "max-declarations",
+        "defines",
+        "type-aliases",
+        "sync-msg-list",
+    ] {
+        if let Some(value) = matches.opt_str(name) {
+            args.push(format!("--{}", name));
+            args.push(value);
+        }
+    }
+    for name in &[
+        "no-filename-check",
+        "check-namespace-dirs",
+        "check-duplicate-structs",
+        "skip-unused-headers",
+        "no-config",
+        "strict",
+    ] {
+        if matches.opt_present(name) {
+            args.push(format!("--{}", name));
+        }
+    }
+    args
+}
+
+// Check |file_names| in |jobs| worker processes, which are copies of this
+// one, and report their merged diagnostics like a single check would.
+fn run_jobs(matches: &getopts::Matches, file_names: &[PathBuf], jobs: usize) {
+    match matches.opt_str("partition").as_deref() {
+        None | Some("hash") => (),
+        Some(kind) => usage_error(&format!("Unknown kind of --partition: `{}'", kind)),
+    }
+    // Everything else is about one program, which no worker has all of.
+    for name in &[
+        "d",
+        "o",
+        "roots",
+        "show-source",
+        "progress",
+        "emit",
+        "emit-to",
+        "compare-emitted",
+        "source-maps",
+        "output-layout",
+        "baseline",
+        "update-baseline",
+        "apply-fixes",
+        "dry-run",
+        "compile-commands",
+        "metrics-out",
+        "summary-json",
+        "export-json",
+        "wire-format-json",
+        "endpoint-graph",
+        "manager-graph",
+        "manager-graph-json",
+        "lifetime-diagrams",
+        "check-versions",
+        "method-signatures",
+        "forward-decls-json",
+        "profiler-markers-json",
+        "write-snapshot",
+        "snapshot",
+        "definition",
+        "uses",
+        "hosted-by",
+        "type-closure",
+        "explain",
+        "shmem-audit",
+        "sync-audit",
+        "lifetimes",
+        "dump-symbols",
+    ] {
+        if matches.opt_present(name) {
+            usage_error(&format!("--{} can't be combined with --jobs", name));
+        }
+    }
+
+    let (exit_codes, parts) = match run_workers(matches, file_names, jobs) {
+        Ok(results) => results,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(e.exit_code());
+        }
+    };
+    let diagnostics = batch::merge_diagnostics(parts);
+    if let Some(file) = matches.opt_str("diagnostics-json") {
+        let mut sink = sinks::JsonSink::new(Vec::new());
+        sinks::DiagnosticSink::publish(&mut sink, &diagnostics);
+        write_file(
+            Path::new(&file),
+            &String::from_utf8(sink.into_inner()).unwrap(),
+        );
+    }
+    match batch::merged_result(&exit_codes, diagnostics) {
+        Ok(warnings) => {
+            for w in &warnings {
+                println!("{}", w);
+            }
+            println!("Compile successful")
+        }
+        Err(e) => {
+            println!("{}", e);
+            process::exit(e.exit_code());
+        }
+    }
+}
+
+// Start a worker for each partition of |file_names|, and wait for them.
+// Returns the exit code of each worker, which is None if it was killed by
+// a signal, and the diagnostics of the ones that wrote any.
+type WorkerResults = (Vec<Option<i32>>, Vec<Vec<errors::Diagnostic>>);
+
+fn run_workers(
+    matches: &getopts::Matches,
+    file_names: &[PathBuf],
+    jobs: usize,
+) -> Result<WorkerResults, errors::IpdlError> {
+    let exe = env::current_exe().map_err(|e| {
+        errors::IpdlError::Io(format!(
+            "Can't find the ipdl_parser executable for --jobs: {}",
+            e
+        ))
+    })?;
+    let args = worker_args(matches);
+    let workers = batch::partition_by_hash(file_names, jobs)
+        .into_iter()
+        .enumerate()
+        .map(|(i, files)| {
+            let json = env::temp_dir().join(format!("ipdl_parser_{}_job{}.json", process::id(), i));
+            Command::new(&exe)
+                .args(&args)
+                .arg("--diagnostics-json")
+                .arg(&json)
+                .args(&files)
+                .stdout(Stdio::null())
+                .spawn()
+                .map(|child| (json, child))
+                .map_err(|e| {
+                    errors::IpdlError::Io(format!("Can't start a worker for --jobs: {}", e))
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut exit_codes = Vec::new();
+    let mut parts = Vec::new();
+    for (json, mut child) in workers {
+        let code = child.wait().ok().and_then(|status| status.code());
+        exit_codes.push(code);
+        // A worker that crashed may not have written anything.
+        let diagnostics = fs::read_to_string(&json)
+            .map_err(|e| e.to_string())
+            .and_then(|text| batch::read_diagnostics_json(&text));
+        let _ = fs::remove_file(&json);
+        match diagnostics {
+            Ok(diagnostics) => parts.push(diagnostics),
+            Err(e) if code == Some(0) => {
+                return Err(errors::IpdlError::Io(format!(
+                    "Can't read the diagnostics of a worker: {}",
+                    e
+                )))
+            }
+            Err(_) => (),
+        }
+    }
+    Ok((exit_codes, parts))
+}
+
+fn report_unreachable(program: &ir::TypedProgram, roots: &str) {
+    let roots = roots
+        .split(',')
+        .map(|name| match program.protocol_by_name(name.trim()) {
+            Some(tuid) => tuid,
+            None => usage_error(&format!(
+                "Unknown root protocol `{}' for --roots",
+                name.trim()
+            )),
+        })
+        .collect::<Vec<_>>();
+    for tuid in program.unreachable_protocols(&roots) {
+        let p = program.protocol(&tuid);
+        let w = errors::Diagnostic::warning(
+            p.qname.loc(),
+            &format!(
+                "protocol `{}' is not reachable from the root protocols",
+                p.qname.short_name()
+            ),
+        );
+        println!("{}", w);
+    }
+}
+
+fn report_uses(program: &ir::TypedProgram, name: &str) {
+    let uses = program.uses_of_type(name);
+    if uses.is_empty() {
+        println!("Nothing uses `{}'", name);
+    }
+    for u in uses {
+        println!("{}: {}", u.loc, u);
+    }
+}
+
+fn report_hosted_by(program: &ir::TypedProgram, process: &str) {
+    let hosted = program.protocols_hosted_by(process);
+    if hosted.is_empty() {
+        println!("No protocols can be in a `{}' process", process);
+    }
+    fn print_tree(program: &ir::TypedProgram, tuid: &ast::TUId, depth: usize) {
+        let p = program.protocol(tuid);
+        println!("{}{}", "  ".repeat(depth), p.qname);
+        for managee in program.managees_of(tuid) {
+            print_tree(program, managee, depth + 1);
+        }
+    }
+    for tuid in hosted
+        .iter()
+        .filter(|tuid| program.protocol(tuid).is_top_level())
+    {
+        let sides = program
+            .protocol(tuid)
+            .sides_in_process(process)
+            .iter()
+            .map(|side| format!("{:?}", side).to_lowercase())
+            .collect::<Vec<_>>();
+        println!("{} side of:", sides.join(" and "));
+        print_tree(program, tuid, 1);
+    }
+}
+
+fn report_shmem(program: &ir::TypedProgram) {
+    let messages = shmem::shmem_messages(program);
+    println!("Messages that carry Shmem:");
+    for m in &messages {
+        let place = match (m.in_params, m.in_returns) {
+            (true, true) => "params and returns",
+            (true, false) => "params",
+            _ => "returns",
+        };
+        let direction = match m.direction {
+            ast::Direction::To(side) => format!("to {:?}", side).to_lowercase(),
+            ast::Direction::Both => String::from("both ways"),
+        };
+        let allocated_by = m
+            .allocated_by
+            .iter()
+            .map(|side| format!("{:?}", side).to_lowercase())
+            .collect::<Vec<_>>();
+        println!(
+            "  {}: {}, {}, in {}{}, allocated by the {}, declared at {}",
+            m.name,
+            format!("{:?}", m.send_semantics).to_lowercase(),
+            direction,
+            place,
+            if m.allowed { ", [AllowShmem]" } else { "" },
+            allocated_by.join(" and "),
+            m.loc
+        );
+    }
+    if messages.is_empty() {
+        println!("  none");
+    }
+}
+
+fn report_sync(program: &ir::TypedProgram) {
+    println!("Sync messages:");
+    let mut any = false;
+    for tuid in program.tu_ids() {
+        let ptype = match &program.tuts[&tuid].protocol {
+            Some(ptype) => ptype,
+            None => continue,
+        };
+        for md in ptype.messages.iter().filter(|md| md.is_sync()) {
+            let direction = match md.direction {
+                ast::Direction::To(side) => format!("to {:?}", side).to_lowercase(),
+                ast::Direction::Both => String::from("both ways"),
+            };
+            let exception = match md.allow_sync_from {
+                Some(side) => format!(", [AllowSyncFrom={:?}]", side),
+                None => String::new(),
+            };
+            println!(
+                "  {}::{}: {}{}, declared at {}",
+                ptype.qname.short_name(),
+                md.name.id,
+                direction,
+                exception,
+                md.name.loc
+            );
+            any = true;
+        }
+    }
+    if !any {
+        println!("  none");
+    }
+}
+
+fn report_type_closure(program: &ir::TypedProgram, name: &str) {
+    let tuid = match program.protocol_by_name(name) {
+        Some(tuid) => tuid,
+        None => usage_error(&format!("Unknown protocol `{}' for --type-closure", name)),
+    };
+    let closure = program.type_closure(&tuid);
+    println!("Types used by `{}':", name);
+    for tr in &closure.structs {
+        let s = tr.lookup_struct(&program.tuts);
+        println!("  struct {}, declared at {}", s.qname, s.qname.loc());
+    }
+    for tr in &closure.unions {
+        let u = tr.lookup_union(&program.tuts);
+        println!("  union {}, declared at {}", u.qname, u.qname.loc());
+    }
+    for t in &closure.imported {
+        let name = program.qualified_name(t).unwrap();
+        match program.header_of(t) {
+            Some(header) => println!("  {}, from \"{}\"", name, header),
+            None => println!("  {}", name),
+        }
+    }
+    println!("Headers:");
+    for header in &closure.headers {
+        println!("  {}", header);
+    }
+}
+
+fn dump_symbols(program: &ir::TypedProgram, file_names: &[PathBuf]) {
+    for file in file_names {
+        let tuid = program.tu_for_file(file).unwrap();
+        println!("Symbols visible in `{}':", file.display());
+        for symbol in &program.tuts[&tuid].symbols {
+            let loc = if symbol.loc == ast::Location::builtin() {
+                String::from("builtin")
+            } else {
+                format!("declared at {}", symbol.loc)
+            };
+            // Messages have no type name of their own.
+            let type_name = match symbol.symbol_type {
+                type_check::IPDLType::MessageType(_) => String::from("message"),
+                ref t => format!("{} `{}'", t.type_name(), t.name(&program.tuts)),
+            };
+            println!(
+                "  {}: {}, {}, used {} times",
+                symbol.name,
+                type_name,
+                loc,
+                symbol.references.len()
+            );
+        }
+    }
+}
+
+// Compare the warnings against a baseline file, or record them in it if it
+// doesn't exist yet or |update| is set. Returns the warnings that aren't
+// in the baseline.
+fn check_baseline(
+    warnings: &[errors::Diagnostic],
+    file: &Path,
+    update: bool,
+) -> Vec<errors::Diagnostic> {
+    let root = match file.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    if update || !file.exists() {
+        write_file(file, &Baseline::from_warnings(warnings, root).to_json());
+        println!(
+            "Recorded {} warnings in `{}'",
+            warnings.len(),
+            file.display()
+        );
+        return Vec::new();
+    }
+
+    let baseline = fs::read_to_string(file)
+        .map_err(|e| e.to_string())
+        .and_then(|text| Baseline::parse(&text));
+    let baseline = match baseline {
+        Ok(baseline) => baseline,
+        Err(e) => {
+            let e = errors::IpdlError::Io(format!("Can't read `{}': {}", file.display(), e));
+            println!("{}", e);
+            process::exit(e.exit_code());
+        }
+    };
+    let comparison = baseline.compare(warnings, root);
+    if !comparison.fixed.is_empty() {
+        println!(
+            "{} warnings in `{}' no longer occur, use --update-baseline to remove them",
+            comparison.fixed.len(),
+            file.display()
+        );
+    }
+    comparison.new_warnings
+}
+
+// Exit with the protocols that changed since the --export-json in |file|
+// without a new version.
+fn check_versions(program: &ir::TypedProgram, file_names: &[PathBuf], file: &Path) {
+    let old = fs::read_to_string(file)
+        .map_err(|e| e.to_string())
+        .and_then(|text| versions::parse_export(&text));
+    let old = match old {
+        Ok(old) => old,
+        Err(e) => {
+            let e = errors::IpdlError::Io(format!("Can't read `{}': {}", file.display(), e));
+            println!("{}", e);
+            process::exit(e.exit_code());
+        }
+    };
+    let problems = versions::check_versions(program, file_names, &old);
+    if !problems.is_empty() {
+        let e = errors::IpdlError::TypeCheck(problems);
+        println!("{}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+// Parse a file on its own, for --ast-diff, or exit with its errors.
+fn parse_alone(include_dirs: &[PathBuf], file: &Path) -> ast::TranslationUnit {
+    let mut include_dirs = include_dirs.to_vec();
+    include_dirs.extend(file.parent().map(Path::to_path_buf));
+    let (tu, diagnostics) = parser::parse_partial(&include_dirs, file);
+    match tu {
+        Some(tu) if diagnostics.is_empty() => tu,
+        _ => {
+            let e = errors::IpdlError::Parse(diagnostics);
+            println!("{}", e);
+            process::exit(e.exit_code());
+        }
+    }
+}
+
+// Write the files that |file_names| include, directly or not, to |out|,
+// each after the files it includes, for schedulers that split up the
+// checking.
+fn write_order(include_dirs: &Vec<PathBuf>, file_names: Vec<PathBuf>, out: &Path) {
+    let tus = match parser::parse_with_errors(include_dirs, file_names) {
+        Ok(tus) => tus,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(e.exit_code());
+        }
+    };
+    let order = include_graph::IncludeGraph::new(&tus)
+        .ordered_files(&tus)
+        .iter()
+        .map(|file| format!("{}\n", file))
+        .collect::<String>();
+    write_file(out, &order);
+}
+
+fn ast_diff(include_dirs: &[PathBuf], old: &Path, new_file: &Path) {
+    let old = parse_alone(include_dirs, old);
+    let new = parse_alone(include_dirs, new_file);
+    let changes = ast_diff::diff_translation_units(&old, &new);
+    if changes.is_empty() {
+        println!("No changes");
+    }
+    for change in changes {
+        println!("{}", change);
+    }
+
+    // Finding the uses of deprecated protocols that were added needs the
+    // new version to check.
+    let mut include_dirs = include_dirs.to_vec();
+    include_dirs.extend(new_file.parent().map(Path::to_path_buf));
+    if let Ok(program) = compiler::check(&include_dirs, vec![new_file.to_path_buf()]) {
+        let tuid = program.tu_for_file(new_file).unwrap();
+        for warning in ast_diff::new_deprecated_uses(&old, &program, &tuid) {
+            println!("{}", warning);
+        }
+    }
+}
+
+// Apply the fixes of the diagnostics of |session|, or with |dry_run|,
+// print them as unified diffs, with the names of the files relative to
+// the current directory if they are below it.
+fn apply_fixes(session: &compiler::Session, dry_run: bool) {
+    let cwd = env::current_dir().ok();
+    for file in fixes::fixed_files(session.diagnostics(), &session.sources) {
+        if dry_run {
+            let name = cwd
+                .as_ref()
+                .and_then(|cwd| file.file_name.strip_prefix(cwd).ok())
+                .unwrap_or(&file.file_name);
+            print!(
+                "{}",
+                fixes::unified_diff(&name.to_string_lossy(), &file.old, &file.new)
+            );
+        } else {
+            write_file(&file.file_name, &file.new);
+            println!(
+                "Applied {} fixes to `{}'",
+                file.applied,
+                file.file_name.display()
+            );
+        }
+        if file.skipped > 0 {
+            println!(
+                "Skipped {} fixes to `{}' that overlap others; check again to apply them",
+                file.skipped,
+                file.file_name.display()
+            );
+        }
+        if file.stale > 0 {
+            println!(
+                "Skipped {} fixes to `{}' whose locations aren't in it; it changed after the check",
+                file.stale,
+                file.file_name.display()
+            );
+        }
+    }
+}
+
+fn write_file(path: &Path, contents: &str) {
+    let result = match path.parent() {
+        Some(dir) => fs::create_dir_all(dir).and_then(|_| fs::write(path, contents)),
+        None => fs::write(path, contents),
+    };
+    if let Err(e) = result {
+        let e = errors::IpdlError::Io(format!("Can't write `{}': {}", path.display(), e));
+        println!("{}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+// Where generated code goes.
+struct Output<'a> {
+    program: &'a ir::TypedProgram,
+    // Whether to write a source map, Foo.h.map.json, next to each
+    // generated file Foo.h.
+    source_maps: bool,
+    // Whether to compare the generated code with the files that are
+    // already there, instead of writing it.
+    compare: bool,
+    // How the files that were compared differ from the generated code.
+    mismatches: RefCell<Vec<String>>,
+    layout: layout::OutputLayout,
+    // The files generated from each file given on the command line, for
+    // --compile-commands.
+    generated: RefCell<HashMap<PathBuf, Vec<PathBuf>>>,
+}
+
+impl<'a> Output<'a> {
+    fn write_file(&self, path: &Path, contents: &str) {
+        if !self.compare {
+            write_file(path, contents);
+        } else if let Err(e) = golden::compare_golden(path, contents) {
+            self.mismatches.borrow_mut().push(e);
+        }
+    }
+
+    fn write(&self, path: &Path, code: &str) {
+        self.write_file(path, code);
+        if self.source_maps {
+            let mut map_path = path.as_os_str().to_os_string();
+            map_path.push(".map.json");
+            self.write_file(
+                Path::new(&map_path),
+                &source_map::source_map(self.program, path, code),
+            );
+        }
+    }
+
+    // Write the code generated from |file|.
+    fn write_generated(&self, file: &Path, path: &Path, code: &str) {
+        self.write(path, code);
+        self.generated
+            .borrow_mut()
+            .entry(file.to_path_buf())
+            .or_default()
+            .push(path.to_path_buf());
+    }
+
+    fn namespaces(&self, file: &Path) -> &[String] {
+        let tuid = self.program.tu_for_file(file).unwrap();
+        &self.program.tus[&tuid].namespace.namespaces
+    }
+
+    fn header_path(&self, file: &Path, dir: &Path, name: &str) -> PathBuf {
+        self.layout.header_path(dir, self.namespaces(file), name)
+    }
+
+    fn source_path(&self, file: &Path, dir: &Path, name: &str) -> PathBuf {
+        self.layout.source_path(dir, self.namespaces(file), name)
+    }
+}
+
+// Write the ParamTraits for the structs and unions of each file given on
+// the command line to FooParamTraits.cpp, for a file Foo.ipdl or
+// Foo.ipdlh.
+fn write_param_traits(out: &Output, file_names: &[PathBuf], cpp_dir: &Path) {
+    let program = out.program;
+    for file in file_names {
+        let tuid = program.tu_for_file(file).unwrap();
+        if let Some(code) = param_traits::param_traits(program, &tuid) {
+            let stem = file.file_stem().unwrap().to_string_lossy();
+            let path = out.source_path(file, cpp_dir, &format!("{}ParamTraits.cpp", stem));
+            out.write_generated(file, &path, &code);
+        }
+    }
+}
+
+// Write the Rust bindings for each file given on the command line to
+// Foo.rs.
+fn write_rust_bindings(out: &Output, file_names: &[PathBuf], dir: &Path) {
+    for file in file_names {
+        let tuid = out.program.tu_for_file(file).unwrap();
+        if let Some(code) = rust_bindings::rust_bindings(out.program, &tuid) {
+            let stem = file.file_stem().unwrap().to_string_lossy();
+            let path = out.source_path(file, dir, &format!("{}.rs", stem));
+            out.write_generated(file, &path, &code);
+        }
+    }
+}
+
+// Write the TypeScript declarations for each file given on the command
+// line to Foo.d.ts, along with the IPDLTypes.d.ts they all use.
+fn write_typescript(out: &Output, file_names: &[PathBuf], dir: &Path) {
+    for file in file_names {
+        let tuid = out.program.tu_for_file(file).unwrap();
+        if let Some(code) = typescript::typescript_declarations(out.program, &tuid) {
+            let stem = file.file_stem().unwrap().to_string_lossy();
+            let path = out.source_path(file, dir, &format!("{}.d.ts", stem));
+            out.write_generated(file, &path, &code);
+        }
+    }
+    out.write_file(&dir.join("IPDLTypes.d.ts"), typescript::PRELUDE);
+}
+
+// Write PFooParent.h and PFooChild.h for each protocol given on the
+// command line, next to PFoo.h.
+fn write_actor_headers(out: &Output, file_names: &[PathBuf], header_dir: &Path) {
+    let program = out.program;
+    for file in file_names {
+        let tuid = program.tu_for_file(file).unwrap();
+        if let Some(ref ptype) = program.tuts[&tuid].protocol {
+            for side in &[ast::ProtocolSide::Parent, ast::ProtocolSide::Child] {
+                let name = format!("{}{:?}.h", ptype.qname.short_name(), side);
+                out.write_generated(
+                    file,
+                    &out.header_path(file, header_dir, &name),
+                    &actors::actor_header(program, &tuid, *side),
+                );
+            }
+        }
+    }
+}
+
+// Write PFooForwardDecls.h for each protocol given on the command line,
+// next to PFoo.h.
+fn write_forward_decls(out: &Output, file_names: &[PathBuf], header_dir: &Path) {
+    let program = out.program;
+    for file in file_names {
+        let tuid = program.tu_for_file(file).unwrap();
+        if let Some(ref ptype) = program.tuts[&tuid].protocol {
+            let name = format!("{}ForwardDecls.h", ptype.qname.short_name());
+            out.write_generated(
+                file,
+                &out.header_path(file, header_dir, &name),
+                &forward_decls::header_fragment(program, &tuid),
+            );
+        }
+    }
+}
+
+// Write the message ID tables. Each protocol given on the command line gets
+// a header in the directory for its namespace, like the full headers will.
+fn write_message_tables(
+    out: &Output,
+    file_names: &[PathBuf],
+    header_dir: Option<String>,
+    cpp_dir: Option<String>,
+) {
+    let program = out.program;
+    if let Some(dir) = header_dir {
+        let dir = PathBuf::from(dir);
+        for file in file_names {
+            let tuid = program.tu_for_file(file).unwrap();
+            if let Some(ref ptype) = program.tuts[&tuid].protocol {
+                let name = format!("{}.h", ptype.qname.short_name());
+                out.write_generated(
+                    file,
+                    &out.header_path(file, &dir, &name),
+                    &message_ids::protocol_header(ptype),
+                );
+            }
+        }
+        out.write(
+            &dir.join("IPCMessageStart.h"),
+            &message_ids::message_start_header(program),
+        );
+    }
+    if let Some(dir) = cpp_dir {
+        out.write(
+            &Path::new(&dir).join("IPCMessageTypeName.cpp"),
+            &message_ids::message_type_names(program),
+        );
+    }
+}
+
+// The options of the Python compiler, ipdl.py, for --python-compat.
+fn python_options_parser() -> Options {
+    let mut opts = Options::new();
+    opts.optmulti("I", "include", "", "DIR");
+    opts.optopt("s", "sync-msg-list", "", "FILE");
+    opts.optopt("m", "msg-metadata", "", "FILE");
+    opts.optflagmulti("v", "verbose", "");
+    opts.optflag("q", "quiet", "");
+    opts.optopt("d", "outheaders-dir", "", "HDR_DIR");
+    opts.optopt("o", "outcpp-dir", "", "CPP_DIR");
+    opts.optopt("F", "file-list", "", "FILE");
     opts
 }
 
+// The arguments of this driver that do what |args| do for ipdl.py, so
+// that a build can switch compilers without changing how it runs them.
+// Like ipdl.py, the code goes to the current directory unless -d and -o
+// say otherwise, and the files are the ones given after the options and
+// those listed, one per line, in the --file-list. The sync message list
+// is used like --sync-msg-list, so unlisted sync messages get warnings.
+// The message metadata is for checks that this one doesn't have yet, and
+// the verbosity is ignored.
+fn python_compat_args(args: &[String]) -> Vec<String> {
+    let matches = match python_options_parser().parse(args) {
+        Ok(m) => m,
+        Err(f) => usage_error(&f.to_string()),
+    };
+    let mut native = Vec::new();
+    for dir in matches.opt_strs("I") {
+        native.push(String::from("-I"));
+        native.push(dir);
+    }
+    for (flag, name) in &[("-d", "d"), ("-o", "o")] {
+        native.push(flag.to_string());
+        native.push(matches.opt_str(name).unwrap_or_else(|| String::from(".")));
+    }
+    for kind in &["actors", "structs"] {
+        native.push(String::from("--emit"));
+        native.push(kind.to_string());
+    }
+    if let Some(list) = matches.opt_str("s") {
+        native.push(String::from("--sync-msg-list"));
+        native.push(list);
+    }
+    native.push(String::from("--"));
+    native.extend(matches.free.iter().cloned());
+    if let Some(list) = matches.opt_str("F") {
+        match fs::read_to_string(&list) {
+            Ok(text) => native.extend(
+                text.lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(String::from),
+            ),
+            Err(e) => {
+                let e = errors::IpdlError::Io(format!("Can't read `{}': {}", list, e));
+                println!("{}", e);
+                process::exit(e.exit_code());
+            }
+        }
+    }
+    native
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--python-compat") {
+        let native = python_compat_args(&args[2..]);
+        args.truncate(1);
+        args.extend(native);
+    }
 
     let opts = get_options_parser();
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
-        Err(f) => {
-            panic!("{}", f.to_string())
-        }
+        Err(f) => usage_error(&f.to_string()),
     };
 
     if matches.free.is_empty() {
-        panic!("Expected at least one IPDL file to be specified.");
+        usage_error("Expected at least one IPDL file, directory or glob pattern to be specified.");
     }
 
     let mut include_dirs = Vec::new();
@@ -66,13 +1375,343 @@ fn main() {
         include_dirs.push(PathBuf::from(i))
     }
 
-    let mut file_names = Vec::new();
-    for f in matches.free {
-        file_names.push(PathBuf::from(f));
+    let file_names = match inputs::expand_inputs(&matches.free) {
+        Ok(f) => f,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(e.exit_code());
+        }
+    };
+
+    if let Some(old) = matches.opt_str("ast-diff") {
+        if file_names.len() != 1 {
+            usage_error("--ast-diff compares one file with an older version of it");
+        }
+        ast_diff(&include_dirs, Path::new(&old), &file_names[0]);
+        return;
+    }
+
+    if let Some(out) = matches.opt_str("order") {
+        write_order(&include_dirs, file_names, Path::new(&out));
+        return;
+    }
+
+    if let Some(jobs) = opt_usize(&matches, "jobs").filter(|&jobs| jobs > 1) {
+        run_jobs(&matches, &file_names, jobs);
+        return;
     }
 
-    match compiler::compile(&include_dirs, file_names) {
-        Ok(()) => println!("Compile successful"),
-        Err(e) => println!("{}", e),
+    let defaults = type_check::CheckOptions::default();
+    let options = type_check::CheckOptions {
+        max_manager_depth: opt_usize(&matches, "max-manager-depth"),
+        max_managees: opt_usize(&matches, "max-managees"),
+        max_params: opt_usize(&matches, "max-params").or(defaults.max_params),
+        max_fds: opt_usize(&matches, "max-fds"),
+        max_message_size: opt_usize(&matches, "max-message-size"),
+        defines: matches
+            .opt_str("defines")
+            .map(|names| {
+                names
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        check_file_names: !matches.opt_present("no-filename-check"),
+        check_namespace_dirs: matches.opt_present("check-namespace-dirs"),
+        check_duplicate_structs: matches.opt_present("check-duplicate-structs"),
+        use_config_files: !matches.opt_present("no-config"),
+        check_unused_headers: !matches.opt_present("skip-unused-headers"),
+        type_aliases: matches
+            .opt_str("type-aliases")
+            .map(|aliases| {
+                aliases
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|alias| !alias.is_empty())
+                    .map(|alias| match alias.split_once('=') {
+                        Some((alias, canonical)) => (
+                            String::from(alias.trim().trim_start_matches("::")),
+                            String::from(canonical.trim().trim_start_matches("::")),
+                        ),
+                        None => usage_error(&format!(
+                            "Expected ALIAS=TYPE for --type-aliases, got `{}'",
+                            alias
+                        )),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        limits: parser::InputLimits {
+            max_file_size: opt_usize(&matches, "max-file-size")
+                .map(|n| n as u64)
+                .or(defaults.limits.max_file_size),
+            max_include_depth: opt_usize(&matches, "max-include-depth")
+                .or(defaults.limits.max_include_depth),
+            max_declarations: opt_usize(&matches, "max-declarations")
+                .or(defaults.limits.max_declarations),
+        },
+        track_references: matches.opt_present("dump-symbols"),
+        lint_levels: lint_levels(&matches),
+        strict: matches.opt_present("strict"),
+        // The baseline records every warning, and the new ones are limited
+        // when they are reported.
+        limit_repeats: !matches.opt_present("baseline"),
+        sync_messages: matches
+            .opt_str("sync-msg-list")
+            .map(|file| read_sync_message_list(&file)),
+    };
+
+    let mut session = compiler::Session::new(include_dirs.clone(), options);
+    session.show_source = matches.opt_present("show-source");
+    if matches.opt_present("progress") {
+        session.add_events(Box::new(progress::Progress::stderr()));
+    }
+    if let Some(file) = matches.opt_str("diagnostics-json") {
+        match fs::File::create(&file) {
+            Ok(out) => session.add_sink(Box::new(sinks::JsonSink::new(out))),
+            Err(e) => {
+                let e = errors::IpdlError::Io(format!("Can't write `{}': {}", file, e));
+                println!("{}", e);
+                process::exit(e.exit_code());
+            }
+        }
+    }
+    if let Some(file) = matches.opt_str("snapshot") {
+        match snapshot::Snapshot::read(Path::new(&file)) {
+            Ok(snapshot) => session.snapshot = Some(snapshot),
+            Err(e) => {
+                println!("{}", e);
+                process::exit(e.exit_code());
+            }
+        }
+    }
+    let result = session.check(file_names.clone()).map(|_| ());
+    if matches.opt_present("apply-fixes") {
+        apply_fixes(&session, matches.opt_present("dry-run"));
+    } else if matches.opt_present("dry-run") {
+        usage_error("--dry-run only works with --apply-fixes");
+    }
+    if let Some(out) = matches.opt_str("metrics-out") {
+        let metrics =
+            metrics::file_metrics(&session.sources, session.program(), session.diagnostics());
+        write_file(Path::new(&out), &metrics::metrics_json(&metrics));
+    }
+    if let Some(out) = matches.opt_str("summary-json") {
+        write_file(
+            Path::new(&out),
+            &metrics::summary_json(&session, &file_names),
+        );
+    }
+    match result.map(|()| session.program().unwrap()) {
+        Ok(program) => {
+            match matches.opt_str("baseline") {
+                Some(file) => {
+                    let update = matches.opt_present("update-baseline");
+                    let mut new_warnings =
+                        check_baseline(&program.warnings, Path::new(&file), update);
+                    errors::limit_diagnostics(&mut new_warnings);
+                    if !new_warnings.is_empty() {
+                        let e = errors::IpdlError::Lint(new_warnings);
+                        println!("{}", e);
+                        process::exit(e.exit_code());
+                    }
+                }
+                None => {
+                    for w in &program.warnings {
+                        println!("{}", w);
+                    }
+                }
+            }
+            if let Some(roots) = matches.opt_str("roots") {
+                report_unreachable(&program, &roots);
+            }
+            let out = Output {
+                program: &program,
+                source_maps: matches.opt_present("source-maps"),
+                compare: matches.opt_present("compare-emitted"),
+                mismatches: RefCell::new(Vec::new()),
+                layout: match matches.opt_str("output-layout") {
+                    None => layout::OutputLayout::Python,
+                    Some(name) => match layout::OutputLayout::from_name(&name) {
+                        Some(layout) => layout,
+                        None => {
+                            usage_error(&format!("Unknown kind of --output-layout: `{}'", name))
+                        }
+                    },
+                },
+                generated: RefCell::new(HashMap::new()),
+            };
+            let emit_to = matches.opt_str("emit-to");
+            let header_dir = emit_to.clone().or_else(|| matches.opt_str("d"));
+            let cpp_dir = emit_to.clone().or_else(|| matches.opt_str("o"));
+            write_message_tables(&out, &file_names, header_dir.clone(), cpp_dir.clone());
+            let mut kinds = matches.opt_strs("emit");
+            if kinds.is_empty() && emit_to.is_some() {
+                kinds = ["actors", "forward-decls", "rust", "structs", "typescript"]
+                    .iter()
+                    .map(|k| k.to_string())
+                    .collect();
+            }
+            for kind in kinds {
+                let dir = match kind.as_str() {
+                    "actors" | "forward-decls" => header_dir.clone(),
+                    _ => cpp_dir.clone(),
+                };
+                match (kind.as_str(), dir) {
+                    ("structs", Some(dir)) => {
+                        write_param_traits(&out, &file_names, Path::new(&dir))
+                    }
+                    ("structs", None) => {
+                        usage_error("--emit=structs needs an output directory (-o)")
+                    }
+                    ("actors", Some(dir)) => {
+                        write_actor_headers(&out, &file_names, Path::new(&dir))
+                    }
+                    ("actors", None) => usage_error("--emit=actors needs a header directory (-d)"),
+                    ("forward-decls", Some(dir)) => {
+                        write_forward_decls(&out, &file_names, Path::new(&dir))
+                    }
+                    ("forward-decls", None) => {
+                        usage_error("--emit=forward-decls needs a header directory (-d)")
+                    }
+                    ("rust", Some(dir)) => write_rust_bindings(&out, &file_names, Path::new(&dir)),
+                    ("rust", None) => usage_error("--emit=rust needs an output directory (-o)"),
+                    ("typescript", Some(dir)) => {
+                        write_typescript(&out, &file_names, Path::new(&dir))
+                    }
+                    ("typescript", None) => {
+                        usage_error("--emit=typescript needs an output directory (-o)")
+                    }
+                    _ => usage_error(&format!("Unknown kind of code for --emit: `{}'", kind)),
+                }
+            }
+            let generated = out.generated.into_inner();
+            let mismatches = out.mismatches.into_inner();
+            if !mismatches.is_empty() {
+                for m in &mismatches {
+                    println!("{}", m);
+                }
+                println!(
+                    "{} generated files differ, use --emit-to without --compare-emitted \
+                     to update them",
+                    mismatches.len()
+                );
+                process::exit(1);
+            }
+            if let Some(out) = matches.opt_str("compile-commands") {
+                let json = commands::compile_commands_with_outputs(
+                    &program,
+                    &include_dirs,
+                    &file_names,
+                    &generated,
+                );
+                write_file(Path::new(&out), &json);
+            }
+            if let Some(out) = matches.opt_str("export-json") {
+                write_file(Path::new(&out), &export::export_json(program, &file_names));
+            }
+            if let Some(out) = matches.opt_str("wire-format-json") {
+                write_file(
+                    Path::new(&out),
+                    &wire_format::wire_format_json(program, &file_names),
+                );
+            }
+            if let Some(out) = matches.opt_str("endpoint-graph") {
+                write_file(Path::new(&out), &endpoints::endpoint_graph_dot(program));
+            }
+            if let Some(out) = matches.opt_str("manager-graph") {
+                write_file(Path::new(&out), &managers::manager_graph_dot(program));
+            }
+            if let Some(out) = matches.opt_str("manager-graph-json") {
+                write_file(Path::new(&out), &export::manager_graph_json(program));
+            }
+            if let Some(out) = matches.opt_str("lifetime-diagrams") {
+                write_file(
+                    Path::new(&out),
+                    &lifetime_diagrams::lifetime_diagrams_markdown(program, &file_names),
+                );
+            }
+            if let Some(file) = matches.opt_str("check-versions") {
+                check_versions(program, &file_names, Path::new(&file));
+            }
+            if let Some(out) = matches.opt_str("method-signatures") {
+                write_file(
+                    Path::new(&out),
+                    &actors::method_signatures_text(program, &file_names),
+                );
+            }
+            if let Some(out) = matches.opt_str("forward-decls-json") {
+                write_file(
+                    Path::new(&out),
+                    &forward_decls::declarations_json(program, &file_names),
+                );
+            }
+            if let Some(out) = matches.opt_str("profiler-markers-json") {
+                write_file(
+                    Path::new(&out),
+                    &message_ids::profiler_markers_json(program),
+                );
+            }
+            if let Some(name) = matches.opt_str("definition") {
+                match program.definition_index().lookup(&name) {
+                    Some(d) => println!("{}: {:?} {}", d.loc, d.kind, d.name),
+                    // Not finding the name is an answer, not a bad command
+                    // line, but scripts still need to tell it apart.
+                    None => {
+                        println!("No definition of `{}' found", name);
+                        process::exit(1);
+                    }
+                }
+            }
+            if let Some(name) = matches.opt_str("uses") {
+                report_uses(&program, &name);
+            }
+            if let Some(process) = matches.opt_str("hosted-by") {
+                report_hosted_by(program, &process);
+            }
+            if let Some(name) = matches.opt_str("type-closure") {
+                report_type_closure(&program, &name);
+            }
+            if let Some(name) = matches.opt_str("explain") {
+                match program.protocol_by_name(&name) {
+                    Some(tuid) => print!("{}", explain::explain_protocol(program, &tuid)),
+                    None => usage_error(&format!("Unknown protocol `{}' for --explain", name)),
+                }
+            }
+            if matches.opt_present("shmem-audit") {
+                report_shmem(program);
+            }
+            if matches.opt_present("sync-audit") {
+                report_sync(program);
+            }
+            if matches.opt_present("lifetimes") {
+                for file in &file_names {
+                    let tuid = program.tu_for_file(file).unwrap();
+                    if program.tuts[&tuid].protocol.is_some() {
+                        print!("{}", explain::lifetime_table(program, &tuid));
+                    }
+                }
+            }
+            if matches.opt_present("dump-symbols") {
+                dump_symbols(&program, &file_names);
+            }
+            println!("Compile successful")
+        }
+        Err(mut e) => {
+            // Repeats aren't dropped by the check for a --baseline.
+            if let errors::IpdlError::TypeCheck(ds) | errors::IpdlError::Lint(ds) = &mut e {
+                errors::limit_diagnostics(ds);
+            }
+            println!("{}", e);
+            process::exit(e.exit_code());
+        }
+    }
+    if let Some(out) = matches.opt_str("write-snapshot") {
+        let program = session.program().unwrap();
+        let snapshot = snapshot::Snapshot::from_program(program, &session.sources);
+        write_file(Path::new(&out), &snapshot.to_json());
     }
 }
@@ -0,0 +1,4548 @@
+extern crate ipdl_parser;
+
+use ipdl_parser::actors;
+use ipdl_parser::ast::{
+    CxxTypeKind, Direction, Identifier, Location, MessageDecl, Nesting, Priority, ProtocolSide,
+    SendSemantics, TranslationUnit, TypeSpec,
+};
+use ipdl_parser::ast_diff;
+use ipdl_parser::baseline::Baseline;
+use ipdl_parser::batch;
+use ipdl_parser::config::{self, LintLevel};
+use ipdl_parser::endpoints;
+use ipdl_parser::errors::{
+    Diagnostic, Diagnostics, Edit, Errors, FixAvailability, IpdlError, Severity,
+};
+use ipdl_parser::events::{CompilerEvents, Phase};
+use ipdl_parser::explain;
+use ipdl_parser::export;
+use ipdl_parser::fingerprint::Fingerprint;
+use ipdl_parser::fixes;
+use ipdl_parser::forward_decls;
+use ipdl_parser::ir::{DefinitionKind, TypeCompleteness, TypedProgram};
+use ipdl_parser::json::{self, JsonValue};
+use ipdl_parser::layout::OutputLayout;
+use ipdl_parser::lifetime_diagrams;
+use ipdl_parser::managers;
+use ipdl_parser::message_ids;
+use ipdl_parser::metrics;
+use ipdl_parser::node_id::{Node, NodeMap};
+use ipdl_parser::progress::Progress;
+use ipdl_parser::sinks::{self, DiagnosticSink};
+use ipdl_parser::snapshot::Snapshot;
+use ipdl_parser::sources::SourceManager;
+use ipdl_parser::trivia::{Comment, TriviaMap};
+use ipdl_parser::type_check::{
+    self, CheckOptions, GatherCache, IPDLType, MessageCxxNames, MessageTypeDef, StrengthMismatch,
+    TypeEnv,
+};
+use ipdl_parser::typescript;
+use ipdl_parser::versions;
+use ipdl_parser::visitor::{self, Visitor};
+use ipdl_parser::wire_format::{self, WireItem, WireKind};
+use ipdl_parser::{commands, compiler, golden};
+use ipdl_parser::{param_traits, parser, rust_bindings, shmem, source_map};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+const BASE_PATH: [&str; 2] = [".", "tests"];
+const OK_PATH: &str = "ok";
+
+fn ok_dir() -> PathBuf {
+    let mut path: PathBuf = BASE_PATH.iter().collect();
+    path.push(OK_PATH);
+    path
+}
+
+// Parse a file in tests/ok/, and return the translation unit for it.
+fn parse_ok_file(file_name: &str) -> TranslationUnit {
+    let include_dirs = vec![ok_dir()];
+    let file = ok_dir().join(file_name);
+    let file_name = file.canonicalize().unwrap();
+    let tus = parser::parse_with_errors(&include_dirs, vec![file]).unwrap();
+    tus.into_values()
+        .find(|tu| tu.file_name == file_name)
+        .unwrap()
+}
+
+// A fresh temporary directory for a test, which is removed with everything
+// in it when the test is done, whether it passes or not.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(test_name: &str) -> TempDir {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("ipdl_parser_{}_{}", test_name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    // Write |text| to a file in the directory.
+    fn write(&self, file_name: &str, text: &str) -> PathBuf {
+        let file = self.0.join(file_name);
+        fs::write(&file, text).unwrap();
+        file
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn parse_file(file: &Path) -> TranslationUnit {
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let file_name = file.canonicalize().unwrap();
+    let tus = parser::parse_with_errors(&include_dirs, vec![file.to_path_buf()]).unwrap();
+    tus.into_values()
+        .find(|tu| tu.file_name == file_name)
+        .unwrap()
+}
+
+fn check_ok_file(file_name: &str) -> TypedProgram {
+    compiler::check(&vec![ok_dir()], vec![ok_dir().join(file_name)]).unwrap()
+}
+
+#[derive(Default)]
+struct Counter {
+    messages: Vec<String>,
+    type_specs: Vec<String>,
+}
+
+impl Visitor for Counter {
+    fn visit_message(&mut self, md: &MessageDecl) {
+        self.messages.push(md.name.id.clone());
+        visitor::walk_message(self, md);
+    }
+
+    fn visit_type_spec(&mut self, type_spec: &TypeSpec) {
+        self.type_specs.push(type_spec.spec.clone());
+    }
+}
+
+#[test]
+fn visitor() {
+    let tu = parse_ok_file("PMyManager.ipdl");
+    let mut counter = Counter::default();
+    counter.visit_translation_unit(&tu);
+    assert_eq!(
+        counter.messages,
+        vec!["__delete__", "SomeMsg", "PMyManaged", "AnotherMsg"]
+    );
+    // Two union components and nine message parameters.
+    assert_eq!(counter.type_specs.len(), 11);
+    assert_eq!(counter.type_specs[0], "float");
+    assert_eq!(counter.type_specs[2], "nsString");
+}
+
+#[test]
+fn typed_queries() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let manager = program.protocol_by_name("PMyManager").unwrap();
+    assert_eq!(
+        program.protocol_by_name("mozilla::myns::PMyManager"),
+        Some(manager)
+    );
+    assert_eq!(program.protocol_by_name("PNotAProtocol"), None);
+
+    let managed = program.protocol_by_name("PMyManaged").unwrap();
+    assert_eq!(program.managees_of(&manager), &[managed]);
+    assert_eq!(program.managers_of(&managed), &[manager]);
+    assert!(program.managers_of(&manager).is_empty());
+    assert!(program.unreachable_protocols(&[manager]).is_empty());
+    assert_eq!(program.unreachable_protocols(&[managed]), vec![manager]);
+
+    let messages = program
+        .messages_of(&manager)
+        .iter()
+        .map(|m| m.name.id.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            "__delete__",
+            "SomeMsg",
+            "PMyManagedConstructor",
+            "AnotherMsg"
+        ]
+    );
+
+    let shmem_users = program
+        .tu_ids()
+        .into_iter()
+        .filter(|tuid| program.tuts[tuid].protocol.is_some())
+        .filter(|tuid| {
+            program
+                .types_used_by(tuid)
+                .iter()
+                .any(|t| t.type_name() == "ShmemType")
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(shmem_users, vec![managed]);
+
+    let used = program
+        .types_used_by(&manager)
+        .iter()
+        .map(|t| t.name(&program.tuts))
+        .collect::<Vec<_>>();
+    assert!(used.contains(&"nsString".to_string()));
+    assert!(used.contains(&"::MyActorPair?".to_string()));
+    assert!(used.contains(&"::MyActorPair".to_string()));
+}
+
+#[test]
+fn type_uses() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let uses = |name| {
+        program
+            .uses_of_type(name)
+            .iter()
+            .map(|u| (u.loc.lineno, u.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        uses("PMyManaged"),
+        vec![
+            (
+                22,
+                String::from(
+                    "PMyManager::SomeMsg → arg 1 (aActors) → struct MyActorPair → field actor1"
+                )
+            ),
+            (
+                22,
+                String::from(
+                    "PMyManager::SomeMsg → arg 1 (aActors) → struct MyActorPair → field actor2"
+                )
+            ),
+            (3, String::from("struct MyActorPair → field actor1")),
+            (3, String::from("struct MyActorPair → field actor2")),
+        ]
+    );
+    assert_eq!(
+        uses("::MyActorPair"),
+        vec![(22, String::from("PMyManager::SomeMsg → arg 1 (aActors)"))]
+    );
+    assert_eq!(
+        uses("Shmem"),
+        vec![(9, String::from("PMyManaged::__delete__ → arg 1 (aShmem)"))]
+    );
+    assert!(uses("mozilla::ipc::FileDescriptor").is_empty());
+
+    // The first struct and the first union of a file are told apart.
+    let temp = TempDir::new("type_uses");
+    let file = temp.write(
+        "PSameIndex.ipdl",
+        "struct S { Shmem s; };\nunion U { int; S; };\n\
+         [ChildProc=any]\nprotocol PSameIndex {\nchild:\n  async M(U u);\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let program = compiler::check(&include_dirs, vec![file]).unwrap();
+    let uses = program
+        .uses_of_type("Shmem")
+        .iter()
+        .map(|u| u.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        uses,
+        [
+            "struct S → field s",
+            "union U → variant ::S → struct S → field s",
+            "PSameIndex::M → arg 1 (u) → union U → variant ::S → struct S → field s",
+        ]
+    );
+}
+
+#[test]
+fn type_completeness() {
+    let program = check_ok_file("PmutualRecStructUnion.ipdl");
+    let tuid = program.protocol_by_name("PmutualRecStructUnion").unwrap();
+    let params = &program.protocol(&tuid).messages[0].params;
+    let completeness = params
+        .iter()
+        .map(|p| program.completeness(p.param_type.lookup(&program.tuts)))
+        .collect::<Vec<_>>();
+    let path = |p: &[&str]| TypeCompleteness::Recursive(p.iter().map(|s| s.to_string()).collect());
+    assert_eq!(
+        completeness,
+        vec![
+            path(&["struct X", "field y", "union Y", "variant ::X"]),
+            path(&["union Y", "variant ::X", "struct X", "field y"]),
+            path(&[
+                "struct Z",
+                "field x",
+                "struct X",
+                "field y",
+                "union Y",
+                "variant ::Z"
+            ]),
+        ]
+    );
+
+    let program = check_ok_file("PMyManager.ipdl");
+    let tuid = program.protocol_by_name("PMyManager").unwrap();
+    for md in program.messages_of(&tuid) {
+        for p in md.params.iter().chain(&md.returns) {
+            assert_eq!(
+                program.completeness(p.param_type.lookup(&program.tuts)),
+                TypeCompleteness::Defined
+            );
+        }
+    }
+}
+
+#[test]
+fn type_closures() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let manager = program.protocol_by_name("PMyManager").unwrap();
+    let closure = program.type_closure(&manager);
+    let names = |types: Vec<IPDLType>| {
+        types
+            .iter()
+            .map(|t| program.qualified_name(t).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        names(
+            closure
+                .structs
+                .into_iter()
+                .map(IPDLType::StructType)
+                .collect()
+        ),
+        vec!["::MyActorPair"]
+    );
+    assert_eq!(
+        names(
+            closure
+                .unions
+                .into_iter()
+                .map(IPDLType::UnionType)
+                .collect()
+        ),
+        vec!["::mozilla::myns::MyUnion"]
+    );
+    assert_eq!(
+        names(closure.imported),
+        vec![
+            "::MyActorEnum",
+            "::int32_t",
+            "::mozilla::myns::MyData",
+            "::mozilla::myns::MyOtherData",
+            "::nsString",
+            "float"
+        ]
+    );
+    assert_eq!(
+        closure.headers,
+        vec!["mozilla/MyDataTypes.h", "mozilla/myns/MyActorUtils.h"]
+    );
+
+    let managed = program.protocol_by_name("PMyManaged").unwrap();
+    let closure = program.type_closure(&managed);
+    assert!(closure.structs.is_empty() && closure.headers.is_empty());
+    assert_eq!(names(closure.imported), vec!["::mozilla::ipc::Shmem"]);
+}
+
+#[test]
+fn explained_protocols() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let manager = program.protocol_by_name("PMyManager").unwrap();
+    let text = explain::explain_protocol(&program, &manager);
+    let lines = text.lines().collect::<Vec<_>>();
+    assert!(lines[0].starts_with("protocol ::mozilla::myns::PMyManager, declared at "));
+    assert!(lines[0].ends_with("PMyManager.ipdl:18:14"));
+    assert_eq!(
+        lines[1..],
+        [
+            "  sync, not nested",
+            "  parent process: unspecified, child process: any",
+            "  top level",
+            "  manages: PMyManaged",
+            "Messages:",
+            "  parent: async __delete__(nsString aNote) [destructor]",
+            "  parent: sync SomeMsg(::MyActorPair? aActors, MyData[] aMyData) \
+             returns (int32_t x, int32_t y, ::mozilla::myns::MyUnion aUnion)",
+            "  parent: async PMyManagedConstructor() [constructs PMyManaged]",
+            "  both: async AnotherMsg(MyActorEnum aEnum, int32_t aNumber) \
+             returns (MyOtherData aOtherData)",
+            "Types, by the file that provides them:",
+            "  PMyManager.ipdl:",
+            "    union ::mozilla::myns::MyUnion",
+            "    ::MyActorEnum, from \"mozilla/myns/MyActorUtils.h\"",
+            "    ::mozilla::myns::MyData, from \"mozilla/MyDataTypes.h\"",
+            "    ::mozilla::myns::MyOtherData, from \"mozilla/MyDataTypes.h\"",
+            "  MyTypes.ipdlh:",
+            "    struct ::MyActorPair",
+            "  builtin: ::int32_t, ::nsString, float",
+        ]
+    );
+}
+
+#[test]
+fn actor_lifetimes() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let manager = program.protocol_by_name("PMyManager").unwrap();
+    let managed = program.protocol_by_name("PMyManaged").unwrap();
+    let lifetimes = explain::actor_lifetimes(&program, &manager);
+    assert_eq!(lifetimes.len(), 1);
+    assert_eq!(lifetimes[0].actor, managed);
+    assert_eq!(lifetimes[0].constructed_by, [ProtocolSide::Child]);
+    assert_eq!(lifetimes[0].deleted_by, [ProtocolSide::Parent]);
+    assert!(lifetimes[0].child_constructs());
+    assert_eq!(
+        explain::lifetime_table(&program, &manager),
+        "protocol ::mozilla::myns::PMyManager\n  \
+         actor       constructed by  deleted by\n  \
+         PMyManaged  child           parent  (child can construct parent-side state)\n"
+    );
+    assert_eq!(
+        explain::lifetime_table(&program, &managed),
+        "protocol ::mozilla::myns::PMyManaged\n  manages nothing\n"
+    );
+
+    let program = check_ok_file("PmultiManager1.ipdl");
+    let manager = program.protocol_by_name("PmultiManager1").unwrap();
+    let lifetimes = explain::actor_lifetimes(&program, &manager);
+    assert_eq!(lifetimes[0].constructed_by, [ProtocolSide::Parent]);
+    assert!(!lifetimes[0].child_constructs());
+}
+
+#[test]
+fn lifetime_diagrams() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let manager = program.protocol_by_name("PMyManager").unwrap();
+    let managed = program.protocol_by_name("PMyManaged").unwrap();
+    assert_eq!(
+        lifetime_diagrams::lifetime_diagram(&program, &manager),
+        None
+    );
+    assert_eq!(
+        lifetime_diagrams::lifetime_diagram(&program, &managed).unwrap(),
+        "sequenceDiagram\n    \
+         participant Parent as PMyManagedParent\n    \
+         participant Child as PMyManagedChild\n    \
+         Note over Parent,Child: the child sends PMyManager::PMyManagedConstructor\n    \
+         Note over Child: SendPMyManagedConstructor binds the new PMyManagedChild\n    \
+         Child->>Parent: async PMyManager::PMyManagedConstructor()\n    \
+         Note over Parent: the new PMyManagedParent is bound before RecvPMyManagedConstructor\n    \
+         Parent->>Child: async __delete__(Shmem aShmem)\n    \
+         Note over Parent: Send__delete__ calls ActorDestroy(Deletion)\n    \
+         Note over Child: Recv__delete__ is called, then ActorDestroy(Deletion)\n    \
+         Note over Parent,Child: IPC releases its reference to the actor on each side\n"
+    );
+
+    let temp = TempDir::new("lifetime_diagrams");
+    let top = temp.write(
+        "PTop.ipdl",
+        "include protocol PSub;\n[ChildProc=any]\nprotocol PTop {\n  manages PSub;\n\
+         both:\n  async PSub(int a);\n};\n",
+    );
+    let dir = top.parent().unwrap().to_path_buf();
+    fs::write(
+        dir.join("PMid.ipdl"),
+        "include protocol PSub;\n[ChildProc=any]\nsync protocol PMid {\n  manages PSub;\n\
+         parent:\n  sync PSub();\n};\n",
+    )
+    .unwrap();
+    let sub = dir.join("PSub.ipdl");
+    fs::write(
+        &sub,
+        "include protocol PMid;\ninclude protocol PTop;\n[ManualDealloc]\n\
+         protocol PSub {\n  manager PMid or PTop;\nchild:\n  async __delete__();\n};\n",
+    )
+    .unwrap();
+    let program = compiler::check(&vec![dir], vec![sub.clone()]).unwrap();
+    let markdown = lifetime_diagrams::lifetime_diagrams_markdown(&program, &[top, sub]);
+    assert!(markdown.starts_with("## ::PSub\n\n```mermaid\nsequenceDiagram\n"));
+    assert!(markdown.ends_with("free the actors\n```\n"));
+    let lines = markdown.lines().collect::<Vec<_>>();
+    for line in [
+        "    alt the child sends PMid::PSubConstructor",
+        "        Child->>Parent: sync PMid::PSubConstructor()",
+        "        Parent-->>Child: reply ()",
+        "        Note over Child: SendPSubConstructor waits for the reply",
+        "    else the parent sends PTop::PSubConstructor",
+        "        Parent->>Child: async PTop::PSubConstructor(int a)",
+        "    else the child sends PTop::PSubConstructor",
+        "    end",
+        "    Parent->>Child: async __delete__()",
+        "    Note over Parent,Child: DeallocPSubParent and DeallocPSubChild free the actors",
+    ] {
+        assert!(lines.contains(&line), "missing `{}' in\n{}", line, markdown);
+    }
+}
+
+#[test]
+fn node_ids() {
+    let text = "struct S { int i; };\n\
+                [ChildProc=any]\n\
+                protocol PNodes {\n\
+                child: async Msg(S s, int i) returns (int j);\n\
+                };\n";
+    let spaced = text.replace(" ", "   ").replace("\n", "\n\n// Comment\n");
+    let tu = parse_file(&TempDir::new("node_ids").write("PNodes.ipdl", text));
+    let spaced_tu = parse_file(&TempDir::new("node_ids_spaced").write("PNodes.ipdl", &spaced));
+    let map = NodeMap::new(&tu);
+    let spaced_map = NodeMap::new(&spaced_tu);
+
+    // The struct, its field, the protocol, the message and its three params.
+    assert_eq!(map.ids().len(), 7);
+    assert_eq!(map.ids(), spaced_map.ids());
+
+    let mut unique = map.ids().to_vec();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), map.ids().len());
+
+    let id = map.ids()[3];
+    match map.node(&id) {
+        Some(Node::Message(md)) => assert_eq!(md.name.id, "Msg"),
+        n => panic!("Expected a message, got {:?}", n),
+    }
+    assert_eq!(map.location(&id).unwrap().lineno, 4);
+    assert_eq!(spaced_map.location(&id).unwrap().lineno, 10);
+}
+
+#[test]
+fn fingerprints() {
+    let text = "include protocol PFingerprintManager;\n\
+                [ChildProc=any]\n\
+                protocol PFingerprint {\n\
+                child: [Priority=input] async Msg(int i) returns (int j);\n\
+                };\n";
+    let fingerprint = |name: &str, text: &str| {
+        let temp = TempDir::new(name);
+        let file = temp.write("PFingerprint.ipdl", text);
+        for manager in &["PFingerprintManager", "POtherManager"] {
+            let manager_text = format!("protocol {} {{ child: async Msg(); }};", manager);
+            fs::write(
+                file.with_file_name(format!("{}.ipdl", manager)),
+                manager_text,
+            )
+            .unwrap();
+        }
+        parse_file(&file).fingerprint()
+    };
+    let base = fingerprint("fingerprint", text);
+    let spaced = text.replace(" ", "  ").replace("\n", "\n/* Comment */\n");
+    assert_eq!(base, fingerprint("fingerprint_spaced", &spaced));
+    for changed in &[
+        text.replace("int i", "int k"),
+        text.replace("int j", "uint32_t j"),
+        text.replace("input", "vsync"),
+        text.replace("child:", "parent:"),
+        text.replace("async", "sync"),
+        text.replace("PFingerprintManager", "POtherManager"),
+    ] {
+        assert_ne!(base, fingerprint("fingerprint_changed", changed));
+    }
+
+    // The header marker, the template arguments of a `using' and the
+    // conditions of `#if' sections count too.
+    let header = "using mozilla::Maybe<int32_t> from \"mozilla/Maybe.h\";\n\
+                  #ifdef MOZ_A\nstruct S { int x; };\n#endif\n";
+    let header_fingerprint = |text: &str| {
+        parse_file(&TempDir::new("fingerprint_header").write("Fingerprint.ipdlh", text))
+            .fingerprint()
+    };
+    let base = header_fingerprint(header);
+    for changed in &[
+        format!("header;\n{}", header),
+        header.replace("int32_t", "uint32_t"),
+        header.replace("MOZ_A", "MOZ_B"),
+    ] {
+        assert_ne!(base, header_fingerprint(changed));
+    }
+}
+
+#[test]
+fn gather_cache() {
+    let temp = TempDir::new("gather_cache");
+    let file = temp.write(
+        "PCached.ipdl",
+        "include Cached;\n[ChildProc=any]\nprotocol PCached {\nchild:\n  async M(CachedStruct s);\n};\n",
+    );
+    let dir = file.parent().unwrap().to_path_buf();
+    let header = dir.join("Cached.ipdlh");
+    let other = dir.join("POther.ipdl");
+    fs::write(
+        &other,
+        "[ChildProc=any]\nprotocol POther {\nchild:\n  async M();\n};\n",
+    )
+    .unwrap();
+    let mut cache = GatherCache::new();
+    let mut check = |header_text: &str| {
+        fs::write(&header, header_text).unwrap();
+        let tus = parser::parse_with_errors(&vec![dir.clone()], vec![file.clone(), other.clone()])
+            .unwrap();
+        let hits = cache.hits();
+        let (_, warnings) =
+            type_check::check_with_cache(&tus, &CheckOptions::default(), &mut cache).unwrap();
+        let lines = warnings
+            .iter()
+            .map(|w| w.loc.as_ref().unwrap().lineno)
+            .collect::<Vec<_>>();
+        (cache.hits() - hits, lines)
+    };
+
+    let text = "struct CachedStruct { int x; };\nstruct Empty {};\n";
+    assert_eq!(check(text), (0, vec![2]));
+    assert_eq!(check(text), (3, vec![2]));
+    // Moving the header's declarations changes the locations in the
+    // results for it and for the protocol that includes it.
+    assert_eq!(check(&format!("\n{}", text)), (1, vec![3]));
+}
+
+#[test]
+fn sessions() {
+    let temp = TempDir::new("sessions");
+    let file = temp.write(
+        "PSession.ipdl",
+        "[ChildProc=any]\nprotocol PSession {\nchild:\n  async M();\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let mut session = compiler::Session::new(include_dirs, CheckOptions::default());
+    session.show_source = true;
+    assert!(session.check(vec![file.clone()]).is_ok());
+    assert!(session.program().is_some());
+    assert!(session.diagnostics().is_empty());
+
+    // Without a reset, the text that was read first is still used.
+    fs::write(
+        &file,
+        "[ChildProc=any]\nprotocol PSession {\nchild:\n  async M(Missing m);\n};\n",
+    )
+    .unwrap();
+    assert!(session.check(vec![file.clone()]).is_ok());
+    assert_eq!(session.cache().hits(), 1);
+
+    session.reset();
+    assert!(session.check(vec![file.clone()]).is_err());
+    assert!(session.program().is_none());
+    assert_eq!(session.cache().hits(), 1);
+    let d = &session.diagnostics()[0];
+    assert_eq!(d.loc.as_ref().unwrap().lineno, 4);
+    assert_eq!(
+        d.snippet.as_deref(),
+        Some("      async M(Missing m);\n              ^")
+    );
+}
+
+#[test]
+fn diagnostic_sinks() {
+    let temp = TempDir::new("diagnostic_sinks");
+    let file = temp.write(
+        "PSink.ipdl",
+        "[ChildProc=any]\nprotocol PSink {\nchild:\n  async M(Missing m);\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let mut session = compiler::Session::new(include_dirs, CheckOptions::default());
+    let collector = sinks::Collector::new();
+    session.add_sink(Box::new(collector.clone()));
+    assert!(session.check(vec![file.clone()]).is_err());
+    fs::remove_file(&file).unwrap();
+    session.reset();
+    assert!(session.check(vec![file.clone()]).is_err());
+
+    let checks = collector.checks();
+    assert_eq!(checks.len(), 2);
+    assert_eq!(checks[0][0].loc.as_ref().unwrap().lineno, 4);
+    assert_eq!(checks[1].len(), 1);
+    assert!(checks[1][0].loc.is_none());
+
+    let mut json = sinks::JsonSink::new(Vec::new());
+    json.publish(&checks[0][..1]);
+    json.publish(&[]);
+    let text = String::from_utf8(json.into_inner()).unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0]
+        .starts_with("{\"diagnostics\": [{\"severity\": \"error\", \"code\": \"unknown_type\", "));
+    assert!(lines[0].contains("\"line\": 4, \"column\": "));
+    assert_eq!(lines[1], "{\"diagnostics\": []}");
+}
+
+// Records the events of a check as text, for compiler_events.
+#[derive(Clone, Default)]
+struct EventLog(Rc<RefCell<Vec<String>>>);
+
+impl CompilerEvents for EventLog {
+    fn phase_started(&mut self, phase: Phase) {
+        self.0.borrow_mut().push(format!("start {}", phase.name()));
+    }
+
+    fn phase_finished(&mut self, phase: Phase, _time: Duration) {
+        self.0.borrow_mut().push(format!("finish {}", phase.name()));
+    }
+
+    fn file_parsed(&mut self, file: &Path, time: Option<Duration>) {
+        let name = file.file_name().unwrap().to_str().unwrap();
+        let how = if time.is_some() { "parsed" } else { "snapshot" };
+        self.0.borrow_mut().push(format!("{} {}", how, name));
+    }
+
+    fn cache_lookup(&mut self, file: &Path, hit: bool) {
+        let name = file.file_name().unwrap().to_str().unwrap();
+        let how = if hit { "hit" } else { "miss" };
+        self.0.borrow_mut().push(format!("{} {}", how, name));
+    }
+
+    fn diagnostics_emitted(&mut self, diagnostics: &[Diagnostic]) {
+        self.0
+            .borrow_mut()
+            .push(format!("{} diagnostics", diagnostics.len()));
+    }
+}
+
+#[test]
+fn duplicate_inputs() {
+    let temp = TempDir::new("duplicate_inputs");
+    let file = temp.write(
+        "PTwice.ipdl",
+        "[ChildProc=any]\nprotocol PTwice {\nchild:\n  async M();\n};\n",
+    );
+    let dir = file.parent().unwrap().to_path_buf();
+    let mut session = compiler::Session::new(vec![dir.clone()], CheckOptions::default());
+    let log = EventLog::default();
+    session.add_events(Box::new(log.clone()));
+    let other_path = dir.join(".").join("PTwice.ipdl");
+    let program = session
+        .check(vec![other_path.clone(), file.clone(), file.clone()])
+        .unwrap();
+    assert_eq!(program.tus.len(), 1);
+    // The translation unit has the canonical path, not the first spelling.
+    let tu = program.tus.values().next().unwrap();
+    assert_eq!(tu.file_name, file.canonicalize().unwrap());
+    let parsed = log
+        .0
+        .borrow()
+        .iter()
+        .filter(|e| e.starts_with("parsed"))
+        .count();
+    assert_eq!(parsed, 1);
+}
+
+#[test]
+fn compiler_events() {
+    let temp = TempDir::new("compiler_events");
+    let file = temp.write(
+        "PEvents.ipdl",
+        "include Types;\n[ChildProc=any]\nprotocol PEvents {\nchild:\n  async M(S s);\n};\n",
+    );
+    fs::write(file.with_file_name("Types.ipdlh"), "struct S { int i; };\n").unwrap();
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let mut session = compiler::Session::new(include_dirs, CheckOptions::default());
+    let log = EventLog::default();
+    session.add_events(Box::new(log.clone()));
+    session.check(vec![file.clone()]).unwrap();
+    assert_eq!(
+        *log.0.borrow(),
+        [
+            "start parse",
+            "parsed PEvents.ipdl",
+            "parsed Types.ipdlh",
+            "finish parse",
+            "start type_check",
+            "miss Types.ipdlh",
+            "miss PEvents.ipdl",
+            "finish type_check",
+            "start lint_levels",
+            "finish lint_levels",
+            "0 diagnostics",
+        ]
+    );
+
+    // Checking again takes the declarations from the cache, and a check
+    // that fails to parse ends after the parse phase.
+    log.0.borrow_mut().clear();
+    session.reset();
+    session.check(vec![file.clone()]).unwrap();
+    assert!(log.0.borrow().contains(&String::from("hit Types.ipdlh")));
+    fs::write(&file, "protocol {").unwrap();
+    log.0.borrow_mut().clear();
+    session.reset();
+    assert!(session.check(vec![file]).is_err());
+    assert_eq!(
+        *log.0.borrow(),
+        [
+            "start parse",
+            "parsed PEvents.ipdl",
+            "finish parse",
+            "1 diagnostics"
+        ]
+    );
+}
+
+#[test]
+fn progress() {
+    let mut progress = Progress::new(Vec::new());
+    progress.phase_started(Phase::Parse);
+    progress.file_parsed(Path::new("Types.ipdlh"), None);
+    progress.file_parsed(Path::new("P.ipdl"), None);
+    progress.cache_lookup(Path::new("P.ipdl"), false);
+    progress.diagnostics_emitted(&[Diagnostic::without_location("oops")]);
+    assert_eq!(
+        String::from_utf8(progress.into_inner()).unwrap(),
+        "\rparsed 1 files, checked 0 of them, 0 errors\
+         \rparsed 2 files, checked 0 of them, 0 errors\
+         \rparsed 2 files, checked 1 of them, 0 errors\
+         \rparsed 2 files, checked 1 of them, 1 errors, 0 warnings\n"
+    );
+}
+
+#[test]
+fn batch_partitions() {
+    let files = (0..20)
+        .map(|i| PathBuf::from(format!("dir/P{}.ipdl", i)))
+        .collect::<Vec<_>>();
+    let partitions = batch::partition_by_hash(&files, 3);
+    assert_eq!(partitions.len(), 3);
+    assert_eq!(partitions.iter().map(Vec::len).sum::<usize>(), 20);
+    // The same files always go to the same partitions, in their order.
+    assert_eq!(batch::partition_by_hash(&files, 3), partitions);
+    for p in &partitions {
+        assert!(
+            p.windows(2)
+                .all(|w| files.iter().position(|f| *f == w[0])
+                    < files.iter().position(|f| *f == w[1]))
+        );
+    }
+    assert_eq!(batch::partition_by_hash(&files[..1], 8).len(), 1);
+
+    // Two workers that both check a header report its warning once.
+    let temp = TempDir::new("batch_partitions");
+    let header = temp.write(
+        "BatchHeader.ipdlh",
+        "struct Empty {};
+",
+    );
+    let dir = header.parent().unwrap().to_path_buf();
+    let mut parts = Vec::new();
+    for (name, body) in &[
+        ("PBatchA", "async M(Empty e);"),
+        ("PBatchB", "async M(Missing m);"),
+    ] {
+        let file = dir.join(format!("{}.ipdl", name));
+        fs::write(
+            &file,
+            format!(
+                "include BatchHeader;\n[ChildProc=any]\nprotocol {} {{\nchild:\n  {}\n}};\n",
+                name, body
+            ),
+        )
+        .unwrap();
+        let mut session = compiler::Session::new(vec![dir.clone()], CheckOptions::default());
+        let mut json = sinks::JsonSink::new(Vec::new());
+        json.publish(&[]);
+        let _ = session.check(vec![file]);
+        json.publish(session.diagnostics());
+        let text = String::from_utf8(json.into_inner()).unwrap();
+        parts.push(batch::read_diagnostics_json(&text).unwrap());
+    }
+    assert_eq!(parts[0].len(), 1);
+    assert_eq!(parts[0][0].code, Some("empty_struct"));
+    let merged = batch::merge_diagnostics(parts.clone());
+    assert_eq!(merged.len(), 2);
+    assert!(merged[0]
+        .loc
+        .as_ref()
+        .unwrap()
+        .file_name
+        .ends_with("BatchHeader.ipdlh"));
+    assert_eq!(merged[1].code, Some("unknown_type"));
+    assert_eq!(parts[1].len(), 2);
+    assert_eq!(merged[1].loc.as_ref().unwrap().lineno, 5);
+
+    assert!(batch::merged_result(&[Some(0), Some(0)], merged.clone()).is_ok());
+    let e = batch::merged_result(&[Some(0), Some(4), Some(5)], merged.clone()).unwrap_err();
+    assert_eq!(e.exit_code(), 4);
+    // A worker that crashed or was killed isn't hidden by the errors of
+    // the others.
+    let e = batch::merged_result(&[Some(4), Some(101)], merged.clone()).unwrap_err();
+    assert_eq!(e.to_string(), "a worker process failed with exit code 101");
+    let e = batch::merged_result(&[Some(64), Some(2)], Vec::new()).unwrap_err();
+    assert_eq!(e.to_string(), "a worker process failed with exit code 64");
+    let e = batch::merged_result(&[Some(5), None], merged.clone()).unwrap_err();
+    assert_eq!(e.to_string(), "a worker process was killed by a signal");
+    assert_eq!(e.exit_code(), 2);
+
+    assert!(batch::read_diagnostics_json("{\"diagnostics\": [{\"severity\": \"bad\"}]}").is_err());
+}
+
+#[test]
+fn snapshots() {
+    let temp = TempDir::new("snapshots");
+    let header = temp.write("SnapHeader.ipdlh", "struct SnapA { int x; };\n");
+    let dir = header.parent().unwrap().to_path_buf();
+    let file = dir.join("PSnap.ipdl");
+    fs::write(
+        &file,
+        "include SnapHeader;\n[ChildProc=any]\nprotocol PSnap {\nchild:\n  async M(SnapA a);\n};\n",
+    )
+    .unwrap();
+    let mut session = compiler::Session::new(vec![dir.clone()], CheckOptions::default());
+    assert!(session.check(vec![file.clone()]).is_ok());
+    let snapshot = Snapshot::from_program(session.program().unwrap(), &session.sources);
+    let header = header.canonicalize().unwrap();
+    assert_eq!(
+        snapshot.file_names(),
+        vec![file.canonicalize().unwrap().as_path(), header.as_path()]
+    );
+    let json = snapshot.to_json();
+    let snapshot = Snapshot::from_json(&json).unwrap();
+    assert_eq!(snapshot.to_json(), json);
+
+    // The header is only taken from the snapshot while it is unchanged.
+    let header_text = fs::read_to_string(&header).unwrap();
+    let (tu, includes) = snapshot.translation_unit(&header, &header_text).unwrap();
+    assert_eq!(tu.structs[0].0.name.id, "SnapA");
+    assert!(includes.is_empty());
+    assert!(snapshot.translation_unit(&header, "").is_none());
+
+    // The edited protocol is parsed again, and uses the header from the
+    // snapshot.
+    fs::write(
+        &file,
+        "include SnapHeader;\n[ChildProc=any]\nprotocol PSnap {\nchild:\n  async M(SnapA a);\n  async N(SnapA a, SnapB b);\n};\n",
+    )
+    .unwrap();
+    let mut session = compiler::Session::new(vec![dir.clone()], CheckOptions::default());
+    session.snapshot = Some(snapshot);
+    let e = session.check(vec![file.clone()]).err().unwrap();
+    assert!(e.to_string().contains("SnapB"), "{}", e);
+
+    // A header that was edited since is parsed again too.
+    fs::write(
+        &header,
+        "struct SnapA { int x; };\nstruct SnapB { int y; };\n",
+    )
+    .unwrap();
+    session.reset();
+    let program = session.check(vec![file.clone()]).unwrap();
+    let tuid = program.tu_for_file(&file).unwrap();
+    assert_eq!(program.protocol(&tuid).messages.len(), 2);
+
+    // Every file in tests/ok survives a round trip.
+    for entry in fs::read_dir(ok_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        let mut sources = SourceManager::new();
+        let program = compiler::check_with_sources(
+            &vec![ok_dir()],
+            vec![path.clone()],
+            &CheckOptions::default(),
+            &mut sources,
+        )
+        .unwrap();
+        let json = Snapshot::from_program(&program, &sources).to_json();
+        assert_eq!(
+            Snapshot::from_json(&json).unwrap().to_json(),
+            json,
+            "{}",
+            path.display()
+        );
+    }
+
+    let e = Snapshot::from_json("{\"version\": 3, \"files\": []}").unwrap_err();
+    assert_eq!(e, "unsupported snapshot version 3");
+}
+
+#[test]
+fn partial_parse() {
+    let temp = TempDir::new("partial_parse");
+    let file = temp.write(
+        "PPartial.ipdl",
+        "struct S { int a; int struct; bool b; };\n\
+         protocol PPartial {\n\
+         child:\n\
+         \x20 async First();\n\
+         \x20 async Second(int) returns;\n\
+         \x20 async Third(bool x) ~;\n\
+         \x20 async Fourth();\n\
+         };\n",
+    );
+    let (tu, diagnostics) = parser::parse_partial(&[], &file);
+    let tu = tu.expect("Expected a partial translation unit");
+
+    let fields = tu.structs[0].2.iter().map(|f| f.name.id.as_str());
+    assert_eq!(fields.collect::<Vec<_>>(), vec!["a", "b"]);
+    let messages = tu.protocol.as_ref().unwrap().1.messages.iter();
+    let messages = messages.map(|md| md.name.id.as_str());
+    assert_eq!(messages.collect::<Vec<_>>(), vec!["First", "Fourth"]);
+
+    let diagnostics = diagnostics
+        .iter()
+        .map(|d| {
+            let first_line = d.message.lines().next().unwrap();
+            (d.loc.as_ref().unwrap().lineno, first_line)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        diagnostics,
+        vec![
+            (1, "bad syntax near `struct'"),
+            (5, "bad syntax near `)'"),
+            (
+                6,
+                "Unexpected token: the character `~' (U+007E) can't start any token"
+            ),
+        ]
+    );
+
+    let (tu, diagnostics) = parser::parse_partial(&[], &file.with_file_name("PMissing.ipdl"));
+    assert!(tu.is_none());
+    assert_eq!(diagnostics.len(), 1);
+}
+
+#[test]
+fn trivia() {
+    let text = "// License header.\n\
+                \n\
+                // The first struct.\n\
+                /* Really. */\n\
+                [Comparable]\n\
+                struct S {\n\
+                \x20 int a; // Trailing.\n\
+                \n\
+                \x20 // About b.\n\
+                \x20 int b;\n\
+                };\n\
+                // The end.\n";
+    let temp = TempDir::new("trivia");
+    let file = temp.write("trivia.ipdlh", text);
+    let tu = parse_file(&file);
+    let nodes = NodeMap::new(&tu);
+    let trivia = TriviaMap::new(&nodes, text);
+    let ids = nodes.ids();
+
+    let comments = |cs: &[Comment]| cs.iter().map(|c| c.text.clone()).collect::<Vec<_>>();
+    let s = trivia.get(&ids[0]).unwrap();
+    assert_eq!(
+        comments(&s.leading),
+        vec!["// The first struct.", "/* Really. */"]
+    );
+    assert!(s.blank_line_before);
+    assert_eq!(s.trailing, None);
+
+    let a = trivia.get(&ids[1]).unwrap();
+    assert!(a.leading.is_empty());
+    assert_eq!(a.trailing.as_ref().unwrap().text, "// Trailing.");
+    assert_eq!(a.trailing.as_ref().unwrap().lineno, 7);
+
+    let b = trivia.get(&ids[2]).unwrap();
+    assert_eq!(comments(&b.leading), vec!["// About b."]);
+    assert!(b.blank_line_before);
+
+    assert_eq!(
+        comments(trivia.detached()),
+        vec!["// License header.", "// The end."]
+    );
+}
+
+#[test]
+fn structured_errors() {
+    let temp = TempDir::new("structured_errors");
+    let file = temp.write(
+        "PErrors.ipdl",
+        "struct S { int a; };\nstruct S { int b; };\n[ChildProc=any]\nprotocol PErrors { child: async M(); };\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let e = compiler::check(&include_dirs, vec![file.clone()])
+        .err()
+        .unwrap();
+    match &e {
+        IpdlError::TypeCheck(ds) => {
+            assert!(!ds.is_empty());
+            assert!(ds.iter().all(|d| d.loc.as_ref().unwrap().lineno == 2));
+            // The first declaration is a note rather than just text.
+            assert_eq!(ds[0].notes.len(), 1);
+            assert_eq!(ds[0].notes[0].loc.lineno, 1);
+            assert_eq!(ds[0].notes[0].message, "first declared here");
+        }
+        e => panic!("Expected a type error, got {:?}", e),
+    }
+    let e: Box<dyn Error> = Box::new(e);
+    assert!(e.to_string().contains("PErrors.ipdl:2:"));
+    assert!(e
+        .to_string()
+        .contains("PErrors.ipdl:1:7: note: first declared here"));
+
+    let missing = file.with_file_name("PMissing.ipdl");
+    match compiler::check(&include_dirs, vec![missing]) {
+        Err(IpdlError::Io(_)) => (),
+        r => panic!("Expected an I/O error, got {:?}", r.err()),
+    }
+}
+
+#[test]
+fn source_snippets() {
+    let temp = TempDir::new("source_snippets");
+    let file = temp.write(
+        "PSnippets.ipdl",
+        "[ChildProc=any]\nprotocol PSnippets {\nchild:\n\tasync M(Missing m);\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let mut sources = SourceManager::new();
+    let mut e = compiler::check_with_sources(
+        &include_dirs,
+        vec![file.clone()],
+        &CheckOptions::default(),
+        &mut sources,
+    )
+    .err()
+    .unwrap();
+
+    // The text is kept after the failure, and under the canonical name of
+    // the file that locations use.
+    let canonical = file.canonicalize().unwrap();
+    assert!(sources
+        .text(&canonical)
+        .unwrap()
+        .starts_with("[ChildProc=any]"));
+    match &mut e {
+        IpdlError::TypeCheck(ds) => {
+            assert_eq!(ds[0].snippet, None);
+            sources.add_snippets(ds);
+            assert_eq!(
+                ds[0].snippet.as_deref(),
+                Some("    \tasync M(Missing m);\n    \t        ^")
+            );
+        }
+        e => panic!("Expected a type error, got {:?}", e),
+    }
+    assert!(e
+        .to_string()
+        .contains("has not been declared [unknown_type]\n    \tasync M(Missing m);\n"));
+}
+
+#[test]
+fn grouped_errors() {
+    // The headers use different names, so they don't clash with each other.
+    let redecl = |s: &str, t: &str| {
+        format!(
+            "struct {0} {{ int a; }};\nstruct {1} {{ int b; }};\nstruct {0} {{ int c; }};\nstruct {1} {{ int d; }};\n",
+            s, t
+        )
+    };
+    let temp = TempDir::new("grouped_errors");
+    let a = temp.write("A.ipdlh", &redecl("S", "T"));
+    let b = a.with_file_name("B.ipdlh");
+    fs::write(&b, redecl("U", "V")).unwrap();
+
+    let include_dirs = vec![a.parent().unwrap().to_path_buf()];
+    let e = compiler::check(&include_dirs, vec![b.clone(), a.clone()])
+        .err()
+        .unwrap();
+    let locations = e
+        .diagnostics()
+        .iter()
+        .map(|d| {
+            let loc = d.loc.as_ref().unwrap();
+            (
+                loc.file_name.file_name().unwrap().to_str().unwrap(),
+                loc.lineno,
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut sorted = locations.clone();
+    sorted.sort();
+    assert_eq!(locations, sorted);
+    assert_eq!(locations.first(), Some(&("A.ipdlh", 3)));
+    assert_eq!(locations.last(), Some(&("B.ipdlh", 4)));
+
+    let message = e.to_string();
+    let headers = message.lines().filter(|l| l.starts_with("In file `"));
+    assert_eq!(headers.count(), 2);
+}
+
+#[test]
+fn warnings() {
+    // PManualDealloc_manager manages PManualDealloc, but never constructs it.
+    let program = check_ok_file("PManualDealloc_manager.ipdl");
+    assert_eq!(program.warnings.len(), 1);
+    let w = &program.warnings[0];
+    assert_eq!(w.severity, Severity::Warning);
+    assert_eq!(w.code, Some("never_constructed"));
+    assert!(w.to_string().contains(
+        "PManualDealloc.ipdl:3:31: warning: managed protocol `PManualDealloc' is never constructed by any of its managers"
+    ));
+
+    assert!(check_ok_file("PMyManager.ipdl").warnings.is_empty());
+}
+
+#[test]
+fn suppressed_warnings() {
+    let text = |attributes: &str| {
+        format!(
+            "[ChildProc=any]\nsync protocol PSyncNoReturns {{\nparent:\n  {} sync Msg();\n}};\n",
+            attributes
+        )
+    };
+    let temp = TempDir::new("suppressed_warnings");
+    let file = temp.write("PSyncNoReturns.ipdl", &text(""));
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let program = compiler::check(&include_dirs, vec![file.clone()]).unwrap();
+    assert_eq!(program.warnings.len(), 1);
+    assert_eq!(program.warnings[0].code, Some("sync_without_returns"));
+    assert!(program.warnings[0]
+        .to_string()
+        .ends_with("warning: sync message `Msg' has no return values, so it could be async [sync_without_returns]"));
+
+    fs::write(&file, text("[Allow=sync_without_returns]")).unwrap();
+    let program = compiler::check(&include_dirs, vec![file.clone()]).unwrap();
+    assert!(program.warnings.is_empty());
+
+    fs::write(&file, text("[Allow=no_such_warning]")).unwrap();
+    assert!(compiler::check(&include_dirs, vec![file]).is_err());
+}
+
+#[test]
+fn repeated_field_and_param_names() {
+    let temp = TempDir::new("repeated_field_and_param_names");
+    let file = temp.write(
+        "PRepeated.ipdl",
+        "struct Point { int x; int y; double x; };\n[ChildProc=any]\nprotocol PRepeated {\n\
+         child:\n  async Msg(int a, bool a);\n  async Ret() returns (int r, int r);\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let ds = match compiler::check(&include_dirs, vec![file]) {
+        Err(IpdlError::TypeCheck(ds)) => ds,
+        r => panic!("Expected a type error, got {:?}", r.err()),
+    };
+    let errors = ds
+        .iter()
+        .map(|d| {
+            let message = d.message.split(": ").nth(1).unwrap();
+            (
+                d.loc.as_ref().unwrap().lineno,
+                message,
+                d.notes[0].loc.colno,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        vec![
+            (1, "struct `Point' has two fields named `x'", 19),
+            (5, "message `Msg' has two parameters named `a'", 16),
+            (6, "message `Ret' has two return values named `r'", 27),
+        ]
+    );
+    assert_eq!(
+        ds[0].message,
+        "redeclaration of symbol `x': struct `Point' has two fields named `x'"
+    );
+}
+
+#[test]
+fn warning_baselines() {
+    let program = check_ok_file("header.ipdlh");
+    assert_eq!(program.warnings.len(), 3);
+    let baseline = Baseline::from_warnings(&program.warnings[..2], &ok_dir());
+    let json = baseline.to_json();
+    assert!(json.contains(
+        "{\"file\": \"header.ipdlh\", \"code\": \"empty_struct\", \"message\": \"struct `Inner1' has no fields\"}"
+    ));
+    assert_eq!(Baseline::parse(&json), Ok(baseline.clone()));
+
+    let comparison = baseline.compare(&program.warnings, &ok_dir());
+    assert_eq!(comparison.new_warnings, vec![program.warnings[2].clone()]);
+    assert!(comparison.fixed.is_empty());
+
+    // Entries only match warnings in the same file.
+    let comparison = baseline.compare(&program.warnings[..1], Path::new("/"));
+    assert_eq!(comparison.new_warnings.len(), 1);
+    assert_eq!(comparison.fixed, baseline.entries());
+
+    assert!(Baseline::parse("{\"warnings\": [{\"file\": \"a.ipdl\"}]}").is_err());
+    assert!(Baseline::parse("[]").is_err());
+}
+
+#[test]
+fn baselines_keep_repeats() {
+    // Warnings about the same symbol are usually limited to the first few,
+    // but a baseline needs every one of them, or the ones after the limit
+    // would be new the next time.
+    let warnings = |limit| {
+        let mut errors = Errors::none();
+        for lineno in 1..6 {
+            let loc = Location {
+                file_name: ok_dir().join("header.ipdlh"),
+                lineno,
+                colno: 1,
+            };
+            errors.push(
+                Diagnostic::from_catalog(&loc, "empty_struct", &[("struct", &"Inner")])
+                    .with_symbol("Inner"),
+            );
+        }
+        errors
+            .into_warnings_with_limit(IpdlError::Lint, limit)
+            .unwrap()
+    };
+    assert_eq!(warnings(true).len(), 3);
+    let all = warnings(false);
+    assert_eq!(all.len(), 5);
+    let baseline = Baseline::from_warnings(&all, &ok_dir());
+    assert_eq!(baseline.entries().len(), 5);
+    let comparison = baseline.compare(&all, &ok_dir());
+    assert!(comparison.new_warnings.is_empty());
+    assert!(comparison.fixed.is_empty());
+
+    assert!(CheckOptions::default().limit_repeats);
+}
+
+#[test]
+fn config_files() {
+    let temp = TempDir::new("config_files");
+    let root = temp.write(
+        "ipdl.toml",
+        "root = true\n[lints]\nempty_struct = \"deny\"\n[sync]\nallowed = [\"PTop::Sync1\"]\n",
+    );
+    let dir = root.parent().unwrap().to_path_buf();
+    let sub = dir.join("sub");
+    fs::create_dir_all(&sub).unwrap();
+    fs::write(sub.join("ipdl.toml"), "[lints]\nempty_struct = \"allow\"\n").unwrap();
+    fs::write(dir.join("E.ipdlh"), "struct E {};\n").unwrap();
+    fs::write(sub.join("F.ipdlh"), "struct F {};\n").unwrap();
+    fs::write(
+        sub.join("PTop.ipdl"),
+        "[ChildProc=any]\nsync protocol PTop {\nparent:\n  sync Sync1() returns (int a);\n\
+         sync Sync2() returns (int a);\n};\n",
+    )
+    .unwrap();
+
+    let options = CheckOptions {
+        use_config_files: true,
+        ..CheckOptions::default()
+    };
+    let include_dirs = vec![dir.clone(), sub.clone()];
+    let check = |files: Vec<PathBuf>| compiler::check_with_options(&include_dirs, files, &options);
+
+    // The sub directory allows empty structs, and keeps the list of sync
+    // messages from the root.
+    let program = check(vec![sub.join("F.ipdlh"), sub.join("PTop.ipdl")]).unwrap();
+    let warnings = program
+        .warnings
+        .iter()
+        .map(|w| (w.code, w.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec![(
+            Some("unlisted_sync_message"),
+            "sync message `PTop::Sync2' is not in the list of allowed sync messages"
+        )]
+    );
+
+    match check(vec![dir.join("E.ipdlh")]) {
+        Err(IpdlError::Lint(ds)) => {
+            assert_eq!(ds.len(), 1);
+            assert_eq!(ds[0].severity, Severity::Error);
+            assert_eq!(ds[0].code, Some("empty_struct"));
+        }
+        r => panic!("Expected a lint error, got {:?}", r.err()),
+    }
+
+    fs::write(sub.join("ipdl.toml"), "[lints]\nempty_struct = 1\n").unwrap();
+    match check(vec![sub.join("F.ipdlh")]) {
+        Err(IpdlError::Parse(ds)) => {
+            assert_eq!(ds[0].loc.as_ref().unwrap().lineno, 2);
+            assert_eq!(
+                ds[0].message,
+                "`empty_struct' must be a string, not an integer"
+            );
+        }
+        r => panic!("Expected a config error, got {:?}", r.err()),
+    }
+}
+
+#[test]
+fn sync_message_lists() {
+    let list = "# Sync messages.\n[PsyncProtocol::Other]\ndescription = Not here\n\n\
+                [ PContent::SyncMsg ]\nplatform = win\n";
+    assert_eq!(
+        config::parse_sync_message_list(list),
+        vec!["PsyncProtocol::Other", "PContent::SyncMsg"]
+    );
+
+    // The list applies without any config files.
+    let check = |listed: &[&str]| {
+        let options = CheckOptions {
+            sync_messages: Some(listed.iter().map(|m| m.to_string()).collect()),
+            ..CheckOptions::default()
+        };
+        let files = vec![ok_dir().join("PsyncProtocol.ipdl")];
+        compiler::check_with_options(&vec![ok_dir()], files, &options).unwrap()
+    };
+    let program = check(&["PsyncProtocol::Other"]);
+    assert_eq!(program.warnings.len(), 1);
+    assert_eq!(
+        program.warnings[0].message,
+        "sync message `PsyncProtocol::SyncMsg' is not in the list of allowed sync messages"
+    );
+    assert!(check(&["PsyncProtocol::SyncMsg"]).warnings.is_empty());
+}
+
+#[test]
+fn duplicate_structs() {
+    let temp = TempDir::new("duplicate_structs");
+    let a = temp.write(
+        "A.ipdlh",
+        "struct Point { int x; nsString name; };\nstruct Size { int x; nsString width; };\n",
+    );
+    let dir = a.parent().unwrap().to_path_buf();
+    let b = dir.join("B.ipdlh");
+    fs::write(
+        &b,
+        "namespace mozilla {\n\nstruct Place { int x; nsString name; };\n}\n",
+    )
+    .unwrap();
+    let files = vec![a, b];
+    let options = CheckOptions {
+        check_duplicate_structs: true,
+        ..CheckOptions::default()
+    };
+    let program =
+        compiler::check_with_options(&vec![dir.clone()], files.clone(), &options).unwrap();
+    assert_eq!(program.warnings.len(), 1);
+    let w = &program.warnings[0];
+    assert_eq!(w.code, Some("duplicate_struct"));
+    assert_eq!(w.loc.as_ref().unwrap().lineno, 3);
+    assert_eq!(
+        w.message,
+        format!(
+            "struct `Place' has the same fields as `::Point' in `{}'; they could be one struct \
+             in a shared .ipdlh",
+            files[0].display()
+        )
+    );
+    assert_eq!(w.notes[0].message, "`Point' defined here");
+
+    // The lint is off by default.
+    assert!(compiler::check(&vec![dir], files)
+        .unwrap()
+        .warnings
+        .is_empty());
+}
+
+#[test]
+fn duplicate_compound_types() {
+    let temp = TempDir::new("duplicate_compound_types");
+    let unrelated = temp.write("Unrelated.ipdlh", "struct S { int a; };\n");
+    let left = temp.write("Left.ipdlh", "struct S { int b; };\n");
+    temp.write("Right.ipdlh", "struct S { int c; };\n");
+    temp.write("Middle.ipdlh", "include Right;\nstruct M { S s; };\n");
+    let top = temp.write(
+        "Top.ipdlh",
+        "include Left;\ninclude Middle;\nstruct T { S s; M m; };\n",
+    );
+    let dir = vec![top.parent().unwrap().to_path_buf()];
+
+    // Files that never meet can use the same names, whatever order they're
+    // checked in, but the two that meet in Top.ipdlh still clash.
+    assert!(compiler::check(&dir, vec![unrelated.clone(), left.clone()]).is_ok());
+    match compiler::check(&dir, vec![unrelated, top]) {
+        Err(IpdlError::TypeCheck(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert!(errors[0]
+                .message
+                .starts_with("struct `::S' is also defined in"));
+            assert_eq!(errors[0].notes[0].message, "other definition is here");
+            let files = [
+                &errors[0].loc.as_ref().unwrap().file_name,
+                &errors[0].notes[0].loc.file_name,
+            ]
+            .iter()
+            .map(|f| f.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+            assert_eq!(files, ["Right.ipdlh", "Left.ipdlh"]);
+        }
+        r => panic!("Expected a type error, got {:?}", r.err()),
+    }
+}
+
+#[test]
+fn duplicate_protocols() {
+    let protocol = |namespace: &str| {
+        format!(
+            "namespace {} {{\n[ChildProc=any]\nprotocol PDup {{\nchild:\n  async Msg();\n}};\n}}\n",
+            namespace
+        )
+    };
+    let temp = TempDir::new("duplicate_protocols");
+    let a = temp.write("PDup.ipdl", &protocol("mozilla"));
+    let dir = a.parent().unwrap().to_path_buf();
+    let write = |name: &str, text: &str| {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, text).unwrap();
+        path
+    };
+    let b = write("b/PDup.ipdl", &protocol("mozilla"));
+    let c = write("c/PDup.ipdl", &protocol("other"));
+
+    // The same qualified name is an error.
+    match compiler::check(&vec![dir.clone()], vec![a.clone(), b]) {
+        Err(IpdlError::TypeCheck(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(
+                errors[0].message,
+                format!(
+                    "protocol `::mozilla::PDup' is also defined in `{}'",
+                    a.display()
+                )
+            );
+        }
+        _ => panic!("expected a type check error"),
+    }
+
+    // The same short name in another namespace is a warning.
+    let program = compiler::check(&vec![dir.clone()], vec![a.clone(), c.clone()]).unwrap();
+    assert_eq!(program.warnings.len(), 1);
+    let w = &program.warnings[0];
+    assert_eq!(w.code, Some("duplicate_protocol_name"));
+    assert_eq!(w.loc.as_ref().unwrap().file_name, c);
+    assert_eq!(w.notes[0].message, "`::mozilla::PDup' defined here");
+
+    let options = CheckOptions {
+        lint_levels: vec![(String::from("duplicate_protocol_name"), LintLevel::Deny)]
+            .into_iter()
+            .collect(),
+        ..CheckOptions::default()
+    };
+    assert!(compiler::check_with_options(&vec![dir], vec![a, c], &options).is_err());
+}
+
+#[test]
+fn strict_mode() {
+    let temp = TempDir::new("strict_mode");
+    let file = temp.write(
+        "PStrict.ipdl",
+        "[ChildProc=any]\nintr protocol PStrict {\nparent:\n  sync Ping();\n  \
+         sync Get() returns (int x);\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let check = |strict: bool| {
+        let options = CheckOptions {
+            strict,
+            lint_levels: if strict {
+                type_check::STRICT_LINTS
+                    .iter()
+                    .map(|code| (String::from(*code), LintLevel::Deny))
+                    .collect()
+            } else {
+                HashMap::new()
+            },
+            ..CheckOptions::default()
+        };
+        compiler::check_with_options(&include_dirs, vec![file.clone()], &options)
+    };
+
+    let program = check(false).unwrap();
+    let codes = program
+        .warnings
+        .iter()
+        .map(|w| w.code.unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(codes, vec!["sync_without_returns"]);
+
+    match check(true) {
+        Err(IpdlError::Lint(ds)) => {
+            let ds = ds
+                .iter()
+                .map(|d| (d.loc.as_ref().unwrap().lineno, d.code.unwrap(), d.severity))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                ds,
+                vec![
+                    (2, "intr", Severity::Error),
+                    (2, "toplevel_process", Severity::Error),
+                    (4, "sync_without_returns", Severity::Error),
+                ]
+            );
+        }
+        r => panic!("expected lint errors, got {:?}", r.map(|p| p.warnings)),
+    }
+}
+
+#[test]
+fn lint_level_overrides() {
+    let temp = TempDir::new("lint_level_overrides");
+    let root = temp.write(
+        "ipdl.toml",
+        "root = true\n[lints]\nempty_struct = \"deny\"\n",
+    );
+    let dir = root.parent().unwrap().to_path_buf();
+    fs::write(dir.join("E.ipdlh"), "struct E {};\nunion One { int; };\n").unwrap();
+    let include_dirs = vec![dir.clone()];
+    let check = |use_config_files: bool, levels: &[(&str, LintLevel)]| {
+        let options = CheckOptions {
+            use_config_files,
+            lint_levels: levels
+                .iter()
+                .map(|(code, level)| (String::from(*code), *level))
+                .collect(),
+            ..CheckOptions::default()
+        };
+        compiler::check_with_options(&include_dirs, vec![dir.join("E.ipdlh")], &options)
+    };
+    let codes = |program: TypedProgram| {
+        program
+            .warnings
+            .iter()
+            .map(|w| (w.code.unwrap(), w.severity))
+            .collect::<Vec<_>>()
+    };
+
+    // The command line takes precedence over the config file.
+    let program = check(true, &[("empty_struct", LintLevel::Warn)]).unwrap();
+    assert_eq!(
+        codes(program),
+        vec![
+            ("empty_struct", Severity::Warning),
+            ("single_component_union", Severity::Warning)
+        ]
+    );
+    let program = check(true, &[("empty_struct", LintLevel::Allow)]).unwrap();
+    assert_eq!(
+        codes(program),
+        vec![("single_component_union", Severity::Warning)]
+    );
+
+    // Without the config files, only the overrides apply.
+    let program = check(false, &[]).unwrap();
+    assert_eq!(codes(program).len(), 2);
+    match check(false, &[("single_component_union", LintLevel::Deny)]) {
+        Err(IpdlError::Lint(ds)) => {
+            let ds = ds
+                .iter()
+                .map(|d| (d.code.unwrap(), d.severity))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                ds,
+                vec![
+                    ("empty_struct", Severity::Warning),
+                    ("single_component_union", Severity::Error)
+                ]
+            );
+        }
+        r => panic!("Expected a lint error, got {:?}", r.err()),
+    }
+}
+
+#[test]
+fn config_policies() {
+    let temp = TempDir::new("config_policies");
+    let config = temp.write(
+        "ipdl.toml",
+        "root = true\n\
+         [[policy]]\nname = \"parent-proc\"\nprotocols = \"toplevel\"\n\
+         require-attribute = \"ParentProc\"\n\
+         [[policy]]\nname = \"async-fds\"\nmessages = \"all\"\n\
+         carrying = \"FileDescriptor\"\nrequire = \"async\"\n",
+    );
+    let dir = config.parent().unwrap().to_path_buf();
+    fs::write(
+        dir.join("PTop.ipdl"),
+        "include protocol PSub;\nstruct Fds { FileDescriptor[] fds; };\n\
+         [ChildProc=any]\nsync protocol PTop {\n  manages PSub;\nparent:\n\
+         sync A(Fds f) returns (int a);\n  async B(FileDescriptor f);\n  async PSub();\n};\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("PSub.ipdl"),
+        "include protocol PTop;\nsync protocol PSub {\n  manager PTop;\nparent:\n\
+         sync C(FileDescriptor f) returns (int a);\n  async __delete__();\n};\n",
+    )
+    .unwrap();
+
+    let options = CheckOptions {
+        use_config_files: true,
+        ..CheckOptions::default()
+    };
+    let include_dirs = vec![dir.clone()];
+    let check =
+        || compiler::check_with_options(&include_dirs, vec![dir.join("PTop.ipdl")], &options);
+    let program = check().unwrap();
+    let warnings = program
+        .warnings
+        .iter()
+        .map(|w| (w.loc.as_ref().unwrap().lineno, w.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec![
+            (
+                5,
+                "message `PSub::C' must be async, because of policy `async-fds'"
+            ),
+            (
+                4,
+                "protocol `PTop' must have a `[ParentProc]' attribute, because of policy `parent-proc'"
+            ),
+            (
+                7,
+                "message `PTop::A' must be async, because of policy `async-fds'"
+            ),
+        ]
+    );
+    assert_eq!(program.warnings[1].code, Some("policy"));
+    assert_eq!(program.warnings[1].notes[0].loc.lineno, 2);
+
+    // A nearer config can turn a policy off, or make it an error.
+    fs::write(
+        dir.join("ipdl.toml"),
+        "root = true\n[[policy]]\nname = \"async-fds\"\nmessages = \"sync\"\n\
+         carrying = \"FileDescriptor\"\nforbid-attribute = \"Tainted\"\nrequire = \"async\"\n\
+         level = \"deny\"\n",
+    )
+    .unwrap();
+    match check() {
+        Err(IpdlError::Lint(ds)) => assert_eq!(ds.len(), 2),
+        r => panic!("Expected a lint error, got {:?}", r.err()),
+    }
+
+    fs::write(
+        dir.join("ipdl.toml"),
+        "[[policy]]\nname = \"x\"\nprotocols = \"all\"\nmessages = \"all\"\n",
+    )
+    .unwrap();
+    match check() {
+        Err(IpdlError::Parse(ds)) => assert_eq!(
+            ds[0].message,
+            "a policy needs exactly one of `protocols' and `messages'"
+        ),
+        r => panic!("Expected a config error, got {:?}", r.err()),
+    }
+}
+
+#[test]
+fn config_direction_rules() {
+    let temp = TempDir::new("config_direction_rules");
+    let config = temp.write(
+        "ipdl.toml",
+        "root = true\n\
+         [[direction-rule]]\npattern = \"^Notify\"\ndirection = \"child\"\n\
+         [[direction-rule]]\npattern = \"^Request\"\ndirection = \"parent\"\n",
+    );
+    let dir = config.parent().unwrap().to_path_buf();
+    fs::write(
+        dir.join("PTop.ipdl"),
+        "[ChildProc=any]\nasync protocol PTop {\nparent:\n  async NotifyA();\n\
+         async RequestB();\nchild:\n  async NotifyC();\n  async RequestD();\n\
+         both:\n  async NotifyE();\n};\n",
+    )
+    .unwrap();
+
+    let options = CheckOptions {
+        use_config_files: true,
+        ..CheckOptions::default()
+    };
+    let include_dirs = vec![dir.clone()];
+    let check =
+        || compiler::check_with_options(&include_dirs, vec![dir.join("PTop.ipdl")], &options);
+    let program = check().unwrap();
+    let warnings = program
+        .warnings
+        .iter()
+        .map(|w| (w.loc.as_ref().unwrap().lineno, w.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec![
+            (
+                4,
+                "message `PTop::NotifyA' is in a `parent:' section, but its name matches \
+                 `^Notify', which is for messages in a `child:' section"
+            ),
+            (
+                8,
+                "message `PTop::RequestD' is in a `child:' section, but its name matches \
+                 `^Request', which is for messages in a `parent:' section"
+            ),
+        ]
+    );
+    assert_eq!(program.warnings[0].code, Some("message_direction"));
+    assert_eq!(program.warnings[0].notes[0].loc.lineno, 2);
+
+    // The lint level applies to the warnings.
+    fs::write(
+        dir.join("ipdl.toml"),
+        "root = true\n[lints]\nmessage_direction = \"deny\"\n\
+         [[direction-rule]]\npattern = \"^Notify\"\ndirection = \"child\"\n",
+    )
+    .unwrap();
+    match check() {
+        Err(IpdlError::Lint(ds)) => assert_eq!(ds.len(), 1),
+        r => panic!("Expected a lint error, got {:?}", r.err()),
+    }
+
+    fs::write(
+        dir.join("ipdl.toml"),
+        "[[direction-rule]]\npattern = \"(\"\ndirection = \"child\"\n",
+    )
+    .unwrap();
+    match check() {
+        Err(IpdlError::Parse(ds)) => {
+            assert_eq!(ds.len(), 1);
+            assert_eq!(ds[0].message, "invalid pattern `(': unclosed group");
+        }
+        r => panic!("Expected a config error, got {:?}", r.err()),
+    }
+}
+
+#[test]
+fn alloc_shmem() {
+    let file = ok_dir().join("PAllocShmem.ipdl");
+    let program = check_ok_file("PAllocShmem.ipdl");
+    let tuid = program.tu_for_file(&file).unwrap();
+    assert_eq!(
+        program.protocol(&tuid).alloc_shmem,
+        Some(vec![ProtocolSide::Child])
+    );
+    let allocated_by = shmem::shmem_messages(&program)
+        .into_iter()
+        .map(|m| (m.name, m.allocated_by))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        allocated_by,
+        vec![
+            (
+                String::from("PAllocShmem::Upload"),
+                vec![ProtocolSide::Child]
+            ),
+            (
+                String::from("PAllocShmem::Fetch"),
+                vec![ProtocolSide::Child]
+            ),
+        ]
+    );
+
+    let json = export::export_json(&program, &[file]);
+    assert!(json.contains("    \"alloc_shmem\": [\"child\"],\n"));
+    assert!(json.contains("\"max_size\": null, \"shmem_allocated_by\": [\"child\"], "));
+    assert!(json.contains("\"max_size\": null, \"shmem_allocated_by\": [], "));
+}
+
+#[test]
+fn config_shmem() {
+    let temp = TempDir::new("config_shmem");
+    let config = temp.write(
+        "ipdl.toml",
+        "root = true\n[shmem]\nrequire-attribute = true\nforbid-in-sync-replies = true\n",
+    );
+    let dir = config.parent().unwrap().to_path_buf();
+    fs::write(
+        dir.join("PTop.ipdl"),
+        "struct S { Shmem[] s; };\n[ChildProc=any]\nsync protocol PTop {\nparent:\n\
+         [AllowShmem] async A(Shmem s);\n  async B(S s);\n\
+         [AllowShmem] sync C() returns (S s);\n  async D() returns (Shmem s);\n\
+         async E(int x);\n};\n",
+    )
+    .unwrap();
+
+    let options = CheckOptions {
+        use_config_files: true,
+        ..CheckOptions::default()
+    };
+    let include_dirs = vec![dir.clone()];
+    let check =
+        || compiler::check_with_options(&include_dirs, vec![dir.join("PTop.ipdl")], &options);
+    let program = check().unwrap();
+    let uses = shmem::shmem_messages(&program)
+        .into_iter()
+        .map(|m| (m.name, m.in_params, m.in_returns, m.allowed))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        uses,
+        vec![
+            (String::from("PTop::A"), true, false, true),
+            (String::from("PTop::B"), true, false, false),
+            (String::from("PTop::C"), false, true, true),
+            (String::from("PTop::D"), false, true, false),
+        ]
+    );
+    let warnings = program
+        .warnings
+        .iter()
+        .map(|w| (w.code.unwrap(), w.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec![
+            (
+                "shmem_without_attribute",
+                "message `PTop::B' carries a Shmem, but doesn't have an `[AllowShmem]' attribute"
+            ),
+            (
+                "shmem_in_sync_reply",
+                "sync message `PTop::C' returns a Shmem"
+            ),
+            (
+                "shmem_without_attribute",
+                "message `PTop::D' carries a Shmem, but doesn't have an `[AllowShmem]' attribute"
+            ),
+        ]
+    );
+
+    // Nothing is checked without the settings.
+    fs::write(dir.join("ipdl.toml"), "root = true\n").unwrap();
+    assert!(check().unwrap().warnings.is_empty());
+
+    fs::write(
+        dir.join("ipdl.toml"),
+        "[shmem]\nrequire-attribute = \"yes\"\n",
+    )
+    .unwrap();
+    match check() {
+        Err(IpdlError::Parse(ds)) => assert_eq!(
+            ds[0].message,
+            "`require-attribute' must be a boolean, not a string"
+        ),
+        r => panic!("Expected a config error, got {:?}", r.err()),
+    }
+}
+
+#[test]
+fn conditional_sections() {
+    let temp = TempDir::new("conditional_sections");
+    let file = temp.write(
+        "PConditional.ipdl",
+        "[ChildProc=any]\nprotocol PConditional {\nchild:\n\
+         #if defined(MOZ_WIDGET_ANDROID)\n  async Android();\n\
+         #elif defined(XP_WIN)\n  async Windows();\n\
+         #else\n  async Other();\n#endif\n  async __delete__();\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let message_names = |defines: &[&str]| {
+        let options = CheckOptions {
+            defines: defines.iter().map(|d| d.to_string()).collect(),
+            ..CheckOptions::default()
+        };
+        let program =
+            compiler::check_with_options(&include_dirs, vec![file.clone()], &options).unwrap();
+        let tuid = program.protocol_by_name("PConditional").unwrap();
+        program
+            .protocol(&tuid)
+            .messages
+            .iter()
+            .map(|m| m.name.id.clone())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        message_names(&["MOZ_WIDGET_ANDROID"]),
+        vec!["Android", "__delete__"]
+    );
+    assert_eq!(message_names(&["XP_WIN"]), vec!["Windows", "__delete__"]);
+    assert_eq!(message_names(&[]), vec!["Other", "__delete__"]);
+
+    // The branches of a condition can declare the same message, whatever
+    // is defined.
+    let file = temp.write(
+        "PConditionalSame.ipdl",
+        "[ChildProc=any]\nprotocol PConditionalSame {\nchild:\n\
+         #if defined(X)\n  async Foo(int a);\n#else\n  async Foo(bool a);\n#endif\n};\n",
+    );
+    for defines in &[&[][..], &["X"][..]] {
+        let options = CheckOptions {
+            defines: defines.iter().map(|d| d.to_string()).collect(),
+            ..CheckOptions::default()
+        };
+        assert!(compiler::check_with_options(&include_dirs, vec![file.clone()], &options).is_ok());
+    }
+}
+
+#[test]
+fn cxx_type_kinds() {
+    let program = check_ok_file("PbasicUsing.ipdl");
+    let tuid = program.tu_ids()[0];
+    let kinds = program.tuts[&tuid].unions[0]
+        .component_types(&program.tuts)
+        .take(3)
+        .map(|t| program.cxx_type_kind(t))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![None, Some(CxxTypeKind::Class), Some(CxxTypeKind::Struct)]
+    );
+}
+
+#[test]
+fn conflicting_usings() {
+    let temp = TempDir::new("conflicting_usings");
+    let file = temp.write(
+        "PConflictingUsings.ipdl",
+        "include usinga;\ninclude usingb;\n\
+         [ChildProc=any]\nprotocol PConflictingUsings {\nchild:\n  async Msg(StructA a, StructB b);\n};\n",
+    );
+    let dir = file.parent().unwrap().to_path_buf();
+    fs::write(
+        dir.join("usinga.ipdlh"),
+        "using Foo from \"Foo.h\";\nstruct StructA { Foo f; };\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("usingb.ipdlh"),
+        "[RefCounted] using Foo from \"Foo.h\";\nstruct StructB { Foo f; };\n",
+    )
+    .unwrap();
+
+    let e = match compiler::check(&vec![dir], vec![file]) {
+        Err(e) => e,
+        Ok(_) => panic!("expected an error"),
+    };
+    let d = e
+        .diagnostics()
+        .iter()
+        .find(|d| d.message.starts_with("inconsistent refcounted status"))
+        .unwrap();
+    assert!(d.loc.as_ref().unwrap().file_name.ends_with("usingb.ipdlh"));
+    let notes = d
+        .notes
+        .iter()
+        .map(|n| {
+            (
+                n.loc.file_name.file_name().unwrap().to_str().unwrap(),
+                n.loc.lineno,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        notes,
+        vec![
+            ("usinga.ipdlh", 1),
+            ("PConflictingUsings.ipdl", 2),
+            ("PConflictingUsings.ipdl", 1)
+        ]
+    );
+}
+
+#[test]
+fn type_aliases() {
+    let temp = TempDir::new("type_aliases");
+    let file = temp.write(
+        "PTypeAliases.ipdl",
+        "using mozilla::nsString from \"nsString.h\";\n\
+         using Bar from \"Bar.h\";\n\
+         using mozilla::Bar from \"mozilla/Bar.h\";\n\
+         using Baz from \"Baz.h\";\n\
+         using Baz from \"Baz.h\";\n\
+         [ChildProc=any]\nprotocol PTypeAliases {\nchild:\n  \
+         async M(nsString a, mozilla::nsString b, Bar c, mozilla::Bar d, Baz e);\n};\n",
+    );
+    let dir = file.parent().unwrap().to_path_buf();
+
+    // Without aliases, the qualified nsString is a different type from the
+    // builtin one, with the same short name.
+    let e = match compiler::check(&vec![dir.clone()], vec![file.clone()]) {
+        Err(e) => e,
+        Ok(_) => panic!("expected an error"),
+    };
+    assert!(
+        e.to_string().contains("redeclaration of symbol `nsString'"),
+        "{}",
+        e
+    );
+
+    let options = CheckOptions {
+        type_aliases: [("nsString", "mozilla::nsString"), ("Bar", "mozilla::Bar")]
+            .iter()
+            .map(|(a, t)| (a.to_string(), t.to_string()))
+            .collect(),
+        ..CheckOptions::default()
+    };
+    let program = compiler::check_with_options(&vec![dir], vec![file.clone()], &options).unwrap();
+    let tuid = program.tu_for_file(&file).unwrap();
+    let names = program.protocol(&tuid).messages[0]
+        .params
+        .iter()
+        .map(|p| {
+            program
+                .qualified_name(p.param_type.lookup(&program.tuts))
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "::mozilla::nsString",
+            "::mozilla::nsString",
+            "::mozilla::Bar",
+            "::mozilla::Bar",
+            "::Baz"
+        ]
+    );
+    let warnings = program
+        .warnings
+        .iter()
+        .filter(|w| w.code == Some("redundant_using"))
+        .map(|w| {
+            (
+                w.loc.as_ref().unwrap().lineno,
+                w.message.as_str(),
+                w.notes[0].loc.lineno,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec![
+            (
+                3,
+                "type `mozilla::Bar' is already imported as `Bar', which is the same type",
+                2
+            ),
+            (5, "type `Baz' is imported more than once", 4),
+        ]
+    );
+}
+
+#[test]
+fn namespaced_includes() {
+    let temp = TempDir::new("namespaced_includes");
+    let file = temp.write(
+        "PTop.ipdl",
+        "include protocol mozilla::dom::PNested;\nnamespace mozilla {\n[ChildProc=any]\n\
+         protocol PTop {\n  manages PNested;\nchild:\n  async PNested();\n};\n}\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let dir = include_dirs[0].join("mozilla").join("dom");
+    fs::create_dir_all(&dir).unwrap();
+    let nested = |namespace: &str| {
+        format!(
+            "include protocol PTop;\nnamespace mozilla {{\nnamespace {} {{\n\
+             protocol PNested {{\n  manager PTop;\nchild:\n  async __delete__();\n}};\n}}\n}}\n",
+            namespace
+        )
+    };
+    fs::write(dir.join("PNested.ipdl"), nested("dom")).unwrap();
+    let options = CheckOptions {
+        check_namespace_dirs: true,
+        ..CheckOptions::default()
+    };
+    let program =
+        compiler::check_with_options(&include_dirs, vec![file.clone()], &options).unwrap();
+    let nested_tuid = program.tu_for_file(&dir.join("PNested.ipdl")).unwrap();
+    assert_eq!(
+        program.protocol(&nested_tuid).qname.to_string(),
+        "::mozilla::dom::PNested"
+    );
+    // Only the nested protocol is where its namespace says it should be.
+    assert_eq!(
+        program
+            .warnings
+            .iter()
+            .map(|w| w.code.unwrap())
+            .collect::<Vec<_>>(),
+        vec!["namespace_dirs"]
+    );
+
+    fs::write(dir.join("PNested.ipdl"), nested("ipc")).unwrap();
+    match compiler::check(&include_dirs, vec![file]) {
+        Err(IpdlError::TypeCheck(ds)) => {
+            assert_eq!(
+                ds[0].message,
+                "`include protocol mozilla::dom::PNested' names namespace `mozilla::dom', but \
+                 protocol `PNested' is in namespace `mozilla::ipc'"
+            );
+            assert_eq!(ds[0].loc.as_ref().unwrap().lineno, 1);
+        }
+        r => panic!("Expected a type error, got {:?}", r.err()),
+    }
+}
+
+// A program that is missing a file that another one includes can't come
+// from the parser, but the checker reports it as an internal error, and
+// carries on without panicking.
+#[test]
+fn missing_translation_unit() {
+    let include_dirs = vec![ok_dir()];
+    let mut tus =
+        parser::parse_with_errors(&include_dirs, vec![ok_dir().join("PMyManager.ipdl")]).unwrap();
+    let managed = tus
+        .iter()
+        .find(|(_, tu)| tu.file_name.ends_with("PMyManaged.ipdl"))
+        .map(|(tuid, _)| *tuid)
+        .unwrap();
+    tus.remove(&managed);
+    match type_check::check(&tus) {
+        Err(IpdlError::TypeCheck(diagnostics)) => {
+            assert!(diagnostics[0]
+                .message
+                .starts_with("internal error: included translation unit"));
+            // None of the passes panicked.
+            assert!(!diagnostics.iter().any(|d| d.message.contains("failed: ")));
+        }
+        _ => panic!("expected a type check error"),
+    }
+}
+
+#[test]
+fn file_name_checks() {
+    let temp = TempDir::new("file_name_checks");
+    let file = temp.write(
+        "PWrongName.ipdl",
+        "namespace mozilla {\n[ChildProc=any]\nprotocol PRightName {\nchild:\n  async __delete__();\n};\n}\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    assert!(compiler::check(&include_dirs, vec![file.clone()]).is_err());
+
+    let options = CheckOptions {
+        check_file_names: false,
+        ..CheckOptions::default()
+    };
+    let program =
+        compiler::check_with_options(&include_dirs, vec![file.clone()], &options).unwrap();
+    assert!(program.warnings.is_empty());
+
+    let options = CheckOptions {
+        check_namespace_dirs: true,
+        ..options
+    };
+    let program =
+        compiler::check_with_options(&include_dirs, vec![file.clone()], &options).unwrap();
+    assert_eq!(program.warnings.len(), 1);
+    assert_eq!(program.warnings[0].code, Some("namespace_dirs"));
+
+    let dir = file.parent().unwrap().join("mozilla");
+    fs::create_dir(&dir).unwrap();
+    let moved = dir.join("PWrongName.ipdl");
+    fs::rename(&file, &moved).unwrap();
+    let program =
+        compiler::check_with_options(&include_dirs, vec![moved.clone()], &options).unwrap();
+    assert!(program.warnings.is_empty());
+
+    // A file name that isn't UTF-8 is reported rather than crashing the
+    // checker.
+    #[cfg(unix)]
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        let bad = dir.join(OsStr::from_bytes(b"PWrong\xffName.ipdl"));
+        fs::rename(&moved, &bad).unwrap();
+        match compiler::check(&include_dirs, vec![bad]) {
+            Err(IpdlError::TypeCheck(diagnostics)) => {
+                assert!(diagnostics[0]
+                    .message
+                    .contains("instead it's named `PWrong\u{fffd}Name.ipdl'"));
+            }
+            _ => panic!("expected a type check error"),
+        }
+    }
+}
+
+#[test]
+fn encodings() {
+    let temp = TempDir::new("encodings");
+    let file = temp.write(
+        "PWindows.ipdl",
+        "\u{feff}[ChildProc=any]\r\nprotocol PWindows {\r\nchild:\r\n  async Msg();\r\n};\r\n",
+    );
+    let tu = parse_file(&file);
+    let msg = &tu.protocol.as_ref().unwrap().1.messages[0];
+    assert_eq!((msg.name.loc.lineno, msg.name.loc.colno), (4, 8));
+
+    fs::write(
+        &file,
+        b"[ChildProc=any]\nprotocol PWindows {\n  // \xff\n};\n",
+    )
+    .unwrap();
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    match parser::parse_with_errors(&include_dirs, vec![file]) {
+        Err(IpdlError::Io(message)) => assert!(
+            message.ends_with("invalid UTF-8 at line 3, column 5 (byte offset 41)"),
+            "{}",
+            message
+        ),
+        _ => panic!("expected an I/O error"),
+    }
+}
+
+#[test]
+fn shared_types() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let tuid = program.protocol_by_name("PMyManager").unwrap();
+    let messages = program.messages_of(&tuid);
+    let some_msg = messages.iter().find(|m| m.name.id == "SomeMsg").unwrap();
+    let another_msg = messages.iter().find(|m| m.name.id == "AnotherMsg").unwrap();
+
+    // Uses of the same type refer to the same entry in the type table of
+    // the translation unit.
+    let int32 = some_msg.returns[0].param_type;
+    assert_eq!(some_msg.returns[1].param_type, int32);
+    assert_eq!(another_msg.params[1].param_type, int32);
+    assert_eq!(int32.tu, tuid);
+    assert_eq!(int32.lookup(&program.tuts).name(&program.tuts), "int32_t");
+    let union_component = program.tuts[&tuid].unions[0].components[1];
+    assert_eq!(another_msg.returns[0].param_type, union_component);
+
+    let types = &program.tuts[&tuid].types;
+    let mut names = types
+        .iter()
+        .map(|t| t.name(&program.tuts))
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), types.len());
+    assert_eq!(types.len(), 8);
+}
+
+#[test]
+fn nullable_arrays() {
+    let program = check_ok_file("PNullable.ipdl");
+    let tuid = program.protocol_by_name("PNullable").unwrap();
+    let param_type = |name: &str| {
+        let m = program
+            .messages_of(&tuid)
+            .iter()
+            .find(|m| m.name.id == name)
+            .unwrap();
+        m.params[0].param_type.lookup(&program.tuts).clone()
+    };
+    match param_type("Msg2") {
+        IPDLType::ArrayType(t) => assert!(matches!(*t, IPDLType::ActorType(_))),
+        t => panic!("expected an array, got {:?}", t),
+    }
+}
+
+#[test]
+fn template_args() {
+    let tu = parse_ok_file("PTemplateArgs.ipdl");
+    let map = &tu.using[1];
+    assert_eq!(
+        map.cxx_type.short_name(),
+        "nsTHashMap<nsCString, nsTArray<uint32_t>>"
+    );
+    let args = map
+        .template_args
+        .iter()
+        .map(|a| (a.spec.as_str(), a.template_args.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(args, vec![("nsCString", 0), ("nsTArray<uint32_t>", 1)]);
+    assert_eq!(map.template_args[1].template_args[0].spec, "uint32_t");
+}
+
+#[test]
+fn cxx_includes() {
+    let temp = TempDir::new("cxx_includes");
+    let file = temp.write(
+        "PCxxIncludes.ipdl",
+        "include \"mozilla/dom/Foo.h\";\ninclude \"nsString.h\";\n\
+         [ChildProc=any]\nprotocol PCxxIncludes {\nchild:\n  async __delete__();\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let program = compiler::check(&include_dirs, vec![file]).unwrap();
+    let tuid = program.protocol_by_name("PCxxIncludes").unwrap();
+    assert_eq!(
+        program.cxx_includes_of(&tuid),
+        vec!["mozilla/dom/Foo.h", "nsString.h"]
+    );
+    assert_eq!(program.tus[&tuid].cxx_includes[1].loc.lineno, 2);
+}
+
+#[test]
+fn param_limits() {
+    let params = (0..15)
+        .map(|i| format!("int a{}", i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let temp = TempDir::new("param_limits");
+    let file = temp.write(
+        "PManyParams.ipdl",
+        &format!(
+            "[ChildProc=any]\nprotocol PManyParams {{\nchild:\n  async Msg({0});\n\
+             [Allow=too_many_params] async Allowed({0});\n}};\n",
+            params
+        ),
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let program = compiler::check(&include_dirs, vec![file.clone()]).unwrap();
+    assert_eq!(program.warnings.len(), 1);
+    assert_eq!(program.warnings[0].code, Some("too_many_params"));
+    assert_eq!(program.warnings[0].loc.as_ref().unwrap().lineno, 4);
+
+    let options = CheckOptions {
+        max_params: Some(15),
+        ..CheckOptions::default()
+    };
+    let program = compiler::check_with_options(&include_dirs, vec![file], &options).unwrap();
+    assert!(program.warnings.is_empty());
+}
+
+#[test]
+fn fd_limits() {
+    let temp = TempDir::new("fd_limits");
+    let file = temp.write(
+        "PFds.ipdl",
+        "struct Two { FileDescriptor a; FileDescriptor? b; };\n\
+         union OneOf { FileDescriptor; Two; };\n\
+         union Chain { int; Link; };\nstruct Link { FileDescriptor fd; Chain next; };\n\
+         union NoFds { int; NoFds; };\n\
+         [ChildProc=any]\nsync protocol PFds {\nchild:\n\
+         async A(Two t, FileDescriptor c);\n  async B(OneOf u, NoFds n, int[] i);\n\
+         async C(FileDescriptor[] fds);\n  async D(Chain c);\n\
+         [Allow=too_many_fds] async E(FileDescriptor[] fds);\nparent:\n\
+         sync F(Two t) returns (Two r, Two s);\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let program = compiler::check(&include_dirs, vec![file.clone()]).unwrap();
+    assert!(program.warnings.is_empty());
+
+    let options = CheckOptions {
+        max_fds: Some(2),
+        ..CheckOptions::default()
+    };
+    let program = compiler::check_with_options(&include_dirs, vec![file], &options).unwrap();
+    let warnings = program
+        .warnings
+        .iter()
+        .map(|w| (w.loc.as_ref().unwrap().lineno, w.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec![
+            (
+                9,
+                "the parameters of message `A' can carry 3 file descriptors, more than the limit of 2"
+            ),
+            (
+                11,
+                "the parameters of message `C' can carry any number of file descriptors, but the limit is 2"
+            ),
+            (
+                12,
+                "the parameters of message `D' can carry any number of file descriptors, but the limit is 2"
+            ),
+            (
+                15,
+                "the reply of message `F' can carry 4 file descriptors, more than the limit of 2"
+            ),
+        ]
+    );
+    assert_eq!(program.warnings[0].code, Some("too_many_fds"));
+}
+
+#[test]
+fn unused_sync_returns() {
+    let temp = TempDir::new("unused_sync_returns");
+    let file = temp.write(
+        "PUnusedReturns.ipdl",
+        "[ChildProc=any]\nsync protocol PUnusedReturns {\nparent:\n  \
+         sync NotifyReady() returns (bool ok);\n  \
+         sync Notifying() returns (bool ok);\n  \
+         sync ReportCount() returns (int count);\n  \
+         sync GetReady() returns (bool ok);\n  \
+         [Allow=unused_sync_return] sync LogThing() returns (bool ok);\n};\n",
+    );
+    let dir = file.parent().unwrap().to_path_buf();
+    let program = compiler::check(&vec![dir], vec![file.clone()]).unwrap();
+    let warnings = program
+        .warnings
+        .iter()
+        .filter(|w| w.code == Some("unused_sync_return"))
+        .map(|w| w.message.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec![
+            "sync message `NotifyReady' only returns `bool ok', and its name suggests that \
+             nothing waits for the result, so it could be async"
+        ]
+    );
+
+    // The guess is also made for suppressed warnings, for other tools.
+    let tuid = program.tu_for_file(&file).unwrap();
+    let guessed = program
+        .protocol(&tuid)
+        .messages
+        .iter()
+        .filter(|md| md.unused_sync_return(&program.tuts).is_some())
+        .map(|md| md.name.id.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(guessed, vec!["NotifyReady", "LogThing"]);
+    let json = export::export_json(&program, &[file]);
+    assert!(json.contains("\"name\": \"Notifying\""));
+    assert_eq!(json.matches("\"unused_sync_return\": true").count(), 2);
+}
+
+#[test]
+fn manager_limits() {
+    let options = CheckOptions {
+        max_manager_depth: Some(0),
+        max_managees: Some(0),
+        ..CheckOptions::default()
+    };
+    let file = ok_dir().join("PMyManager.ipdl");
+    let program = compiler::check_with_options(&vec![ok_dir()], vec![file], &options).unwrap();
+    let messages = program
+        .warnings
+        .iter()
+        .map(|w| w.message.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            "the manager chain of protocol `PMyManaged' is 1 deep, more than the limit of 0",
+            "protocol `PMyManager' manages 1 protocols, more than the limit of 0",
+        ]
+    );
+
+    assert!(check_ok_file("PMyManager.ipdl").warnings.is_empty());
+}
+
+#[test]
+fn unused_headers() {
+    let temp = TempDir::new("unused_headers");
+    let file = temp.write(
+        "PUsesHeaders.ipdl",
+        "include Used;\ninclude Unused;\n[ChildProc=any]\nprotocol PUsesHeaders {\nchild:\n  async M(UsedStruct s);\n};\n",
+    );
+    let dir = file.parent().unwrap().to_path_buf();
+    fs::write(
+        dir.join("Used.ipdlh"),
+        "include Nested;\nstruct UsedStruct { NestedStruct n; };\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("Nested.ipdlh"),
+        "struct NestedStruct { int x; };\n",
+    )
+    .unwrap();
+    // A struct that contains itself is only partially defined.
+    fs::write(
+        dir.join("Unused.ipdlh"),
+        "struct UnusedStruct { UnusedStruct u; };\n",
+    )
+    .unwrap();
+    let include_dirs = vec![dir.clone()];
+
+    match compiler::check(&include_dirs, vec![file.clone()]) {
+        Err(IpdlError::TypeCheck(ds)) => {
+            assert_eq!(ds.len(), 1);
+            assert_eq!(
+                ds[0].message,
+                "struct `UnusedStruct' is only partially defined"
+            );
+        }
+        r => panic!("Expected a type error, got {:?}", r.err()),
+    }
+
+    let options = CheckOptions {
+        check_unused_headers: false,
+        ..CheckOptions::default()
+    };
+    assert!(compiler::check_with_options(&include_dirs, vec![file], &options).is_ok());
+    // A header given by itself is still checked.
+    assert!(
+        compiler::check_with_options(&include_dirs, vec![dir.join("Unused.ipdlh")], &options)
+            .is_err()
+    );
+}
+
+#[test]
+fn protocol_symbols() {
+    let program = check_ok_file("PMyManaged.ipdl");
+    let tuid = program
+        .tu_for_file(&ok_dir().join("PMyManaged.ipdl"))
+        .unwrap();
+    let symbols = &program.tuts[&tuid].symbols;
+    let find = |name: &str| symbols.iter().find(|s| s.name == name).unwrap();
+
+    assert_eq!(find("__delete__").loc.lineno, 9);
+    assert_eq!(find("Shmem").loc, Location::builtin());
+    assert!(find("PMyManager")
+        .loc
+        .file_name
+        .ends_with("PMyManager.ipdl"));
+    assert!(symbols.windows(2).all(|w| w[0].name <= w[1].name));
+}
+
+#[test]
+fn type_env() {
+    let program = check_ok_file("PMyManaged.ipdl");
+    let tuid = program
+        .tu_for_file(&ok_dir().join("PMyManaged.ipdl"))
+        .unwrap();
+    let env = TypeEnv::from_symbols(&program.tuts[&tuid].symbols);
+    let spec = |name: &str| TypeSpec::new(Identifier::new(String::from(name), Location::builtin()));
+    let ns = vec![String::from("mozilla"), String::from("myns")];
+
+    let t = env
+        .resolve(&program.tuts, &spec("PMyManager").set_array(true), &ns)
+        .unwrap();
+    assert_eq!(
+        t.name(&program.tuts),
+        "NotNullPtr<::mozilla::myns::PMyManager>[]"
+    );
+    let t = env
+        .resolve(&program.tuts, &spec("PMyManager").set_nullable(true), &ns)
+        .unwrap();
+    assert!(matches!(t, IPDLType::ActorType(_)));
+    // Partially qualified names are looked for in the enclosing namespaces.
+    assert!(env.lookup("myns::PMyManaged", &ns).is_some());
+
+    let e = env
+        .resolve(&program.tuts, &spec("int").set_nullable(true), &ns)
+        .unwrap_err();
+    assert!(e[0].message.contains("`nullable' qualifier"));
+    let e = env.resolve(&program.tuts, &spec("Nope"), &ns).unwrap_err();
+    assert_eq!(e[0].message, "unknown type `Nope'");
+    assert_eq!(e[0].symbol.as_deref(), Some("Nope"));
+
+    let mut env = TypeEnv::builtins();
+    assert!(env.lookup("Shmem", &[]).is_some());
+    assert!(env.lookup("PMyManager", &[]).is_none());
+    assert!(env
+        .declare("int", &Location::builtin(), IPDLType::BuiltinCType("int"))
+        .has_errors());
+}
+
+#[test]
+fn display_forms() {
+    let spec = TypeSpec::new(Identifier::new(String::from("PFoo"), Location::builtin()));
+    assert_eq!(spec.to_string(), "PFoo");
+    assert_eq!(
+        spec.clone().set_nullable(true).set_array(true).to_string(),
+        "nullable PFoo[]"
+    );
+    assert_eq!(
+        spec.set_uniqueptr(true).set_maybe(true).to_string(),
+        "UniquePtr<PFoo>?"
+    );
+
+    let program = check_ok_file("PMyManager.ipdl");
+    let manager = program.protocol(&program.protocol_by_name("PMyManager").unwrap());
+    let some_msg = &manager.messages[1];
+    assert_eq!(
+        some_msg.params[0]
+            .param_type
+            .lookup(&program.tuts)
+            .display(&program.tuts)
+            .to_string(),
+        "::MyActorPair?"
+    );
+    assert_eq!(
+        some_msg.signature(&program.tuts),
+        "sync SomeMsg(::MyActorPair? aActors, MyData[] aMyData) \
+         returns (int32_t x, int32_t y, ::mozilla::myns::MyUnion aUnion)"
+    );
+    assert_eq!(
+        manager.messages[0].signature(&program.tuts),
+        "async __delete__(nsString aNote)"
+    );
+}
+
+#[test]
+fn compile_commands() {
+    let files = vec![ok_dir().join("PMyManaged.ipdl")];
+    let program = check_ok_file("PMyManaged.ipdl");
+    let json = commands::compile_commands(&program, &[ok_dir()], &files);
+
+    let dir = ok_dir().canonicalize().unwrap();
+    let manager = dir.join("PMyManager.ipdl");
+    let file = files[0].canonicalize().unwrap();
+    assert!(json.starts_with("[\n  {"));
+    assert!(json.contains(&format!("\"file\": \"{}\"", file.display())));
+    assert!(json.contains(&format!("\"include_dirs\": [\"{}\"]", dir.display())));
+    assert!(json.contains(&format!("\"includes\": [\"{}\"]", manager.display())));
+    assert!(json.contains(&format!(
+        "\"arguments\": [\"ipdl_parser\", \"-I\", \"{}\", \"{}\"]",
+        dir.display(),
+        file.display()
+    )));
+}
+
+#[test]
+fn output_layouts() {
+    let (dir, ns) = (Path::new("out"), ["mozilla".to_string(), "dom".to_string()]);
+    let paths = |layout: OutputLayout| {
+        (
+            layout.header_path(dir, &ns, "PFoo.h"),
+            layout.source_path(dir, &ns, "PFoo.rs"),
+        )
+    };
+    assert_eq!(
+        paths(OutputLayout::Python),
+        (
+            PathBuf::from("out/mozilla/dom/PFoo.h"),
+            PathBuf::from("out/PFoo.rs")
+        )
+    );
+    assert_eq!(
+        paths(OutputLayout::Flat),
+        (PathBuf::from("out/PFoo.h"), PathBuf::from("out/PFoo.rs"))
+    );
+    assert_eq!(
+        paths(OutputLayout::from_name("namespaces").unwrap()),
+        (
+            PathBuf::from("out/mozilla/dom/PFoo.h"),
+            PathBuf::from("out/mozilla/dom/PFoo.rs")
+        )
+    );
+    assert_eq!(OutputLayout::from_name("tree"), None);
+
+    // The compilation database lists the files generated from each file.
+    let files = vec![ok_dir().join("PMyManaged.ipdl")];
+    let program = check_ok_file("PMyManaged.ipdl");
+    let outputs = HashMap::from([(files[0].clone(), vec![PathBuf::from("out/PFoo.h")])]);
+    let json = commands::compile_commands_with_outputs(&program, &[ok_dir()], &files, &outputs);
+    assert!(json.contains("\"outputs\": [\"out/PFoo.h\"]\n"));
+    let json = commands::compile_commands(&program, &[ok_dir()], &files);
+    assert!(json.contains("\"outputs\": []\n"));
+}
+
+#[test]
+fn file_metrics() {
+    let mut session = compiler::Session::new(vec![ok_dir()], CheckOptions::default());
+    session
+        .check(vec![ok_dir().join("PMyManaged.ipdl")])
+        .unwrap();
+    let metrics = metrics::file_metrics(&session.sources, session.program(), session.diagnostics());
+    let files = metrics
+        .iter()
+        .map(|m| m.file.file_name().unwrap().to_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        files,
+        vec!["MyTypes.ipdlh", "PMyManaged.ipdl", "PMyManager.ipdl"]
+    );
+    let managed = &metrics[1];
+    assert!(managed.parse_time.is_some());
+    assert_eq!(
+        (
+            managed.tokens,
+            managed.nodes,
+            managed.errors,
+            managed.warnings
+        ),
+        (29, Some(3), 0, 0)
+    );
+
+    // Without a program, the nodes aren't known.
+    let temp = TempDir::new("file_metrics");
+    let file = temp.write("bad.ipdlh", "struct S { int x; int x; };\n");
+    let mut session = compiler::Session::new(vec![], CheckOptions::default());
+    assert!(session.check(vec![file]).is_err());
+    let metrics = metrics::file_metrics(&session.sources, session.program(), session.diagnostics());
+    assert_eq!(
+        (metrics[0].tokens, metrics[0].nodes, metrics[0].errors),
+        (11, None, 1)
+    );
+    let json = metrics::metrics_json(&metrics);
+    assert!(json.starts_with("{\"files\": [\n  {\"file\": "));
+    assert!(json.contains("\"tokens\": 11, \"nodes\": null, \"errors\": 1, \"warnings\": 0}\n]}"));
+}
+
+#[test]
+fn run_summary() {
+    let temp = TempDir::new("run_summary");
+    let file = temp.write("bad.ipdlh", "struct S { int x; int x; };\nstruct T {};\n");
+    let mut session = compiler::Session::new(vec![], CheckOptions::default());
+    assert!(session.check(vec![file.clone()]).is_err());
+    let summary = json::parse_json(&metrics::summary_json(&session, &[file])).unwrap();
+    let number = |v: &JsonValue| match v {
+        JsonValue::Number(n) => *n as usize,
+        _ => panic!("not a number: {:?}", v),
+    };
+    assert_eq!(summary.get("result").unwrap().as_str(), Some("errors"));
+    let files = summary.get("files").unwrap().as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].get("status").unwrap().as_str(), Some("errors"));
+    assert_eq!(number(summary.get("errors").unwrap()), 1);
+    assert_eq!(number(summary.get("warnings").unwrap()), 1);
+    assert_eq!(
+        number(summary.get("codes").unwrap().get("empty_struct").unwrap()),
+        1
+    );
+    let cache = summary.get("gather_cache").unwrap();
+    assert_eq!(
+        (
+            number(cache.get("hits").unwrap()),
+            number(cache.get("lookups").unwrap())
+        ),
+        (0, 1)
+    );
+
+    // Checking the same file again takes its declarations from the cache.
+    session.reset();
+    let file = temp.write("ok.ipdlh", "struct S { int x; };\n");
+    session.check(vec![file.clone()]).unwrap();
+    session.reset();
+    session.check(vec![file.clone()]).unwrap();
+    assert!(session.phase_times().type_check > Duration::from_secs(0));
+    let summary = json::parse_json(&metrics::summary_json(&session, &[file])).unwrap();
+    assert_eq!(summary.get("result").unwrap().as_str(), Some("ok"));
+    let files = summary.get("files").unwrap().as_array().unwrap();
+    assert_eq!(files[0].get("status").unwrap().as_str(), Some("ok"));
+    let cache = summary.get("gather_cache").unwrap();
+    assert_eq!(number(cache.get("hits").unwrap()), 1);
+}
+
+#[test]
+fn message_id_tables() {
+    let program = check_ok_file("PMyManaged.ipdl");
+    let manager = program.protocol_by_name("PMyManager").unwrap();
+    assert_eq!(
+        message_ids::message_ids(program.protocol(&manager)),
+        vec![
+            "Msg___delete____ID",
+            "Msg_SomeMsg__ID",
+            "Reply_SomeMsg__ID",
+            "Msg_PMyManagedConstructor__ID",
+            "Msg_AnotherMsg__ID",
+            "Reply_AnotherMsg__ID",
+        ]
+    );
+
+    let header = message_ids::protocol_header(program.protocol(&manager));
+    assert!(header.contains(
+        "namespace PMyManager {\n\n// from PMyManager.ipdl:18\nenum MessageType {\n  \
+         PMyManagerStart = PMyManagerMsgStart << 16,\n  Msg___delete____ID,\n"
+    ));
+
+    let start = message_ids::message_start_header(&program);
+    assert!(start.contains("  PMyManagedMsgStart,\n  PMyManagerMsgStart,\n  LastMsgIndex\n"));
+
+    let names = message_ids::message_type_names(&program);
+    assert!(names.contains("#include \"mozilla/myns/PMyManager.h\"\n"));
+    assert!(names.contains(
+        "    case ::mozilla::myns::PMyManager::Reply_SomeMsg__ID:\n      \
+         return \"PMyManager::Reply_SomeMsg\";\n"
+    ));
+    assert!(names.contains("    case PMyManagedMsgStart:\n      return \"PMyManaged\";\n"));
+
+    // The profiler tables have the same IDs and names as the C++ ones.
+    let json = message_ids::profiler_markers_json(&program);
+    assert!(json.starts_with(
+        "{\"protocols\": [\n  {\"id\": 0, \"name\": \"PMyManaged\"},\n  \
+         {\"id\": 1, \"name\": \"PMyManager\"}\n],\n"
+    ));
+    assert!(json.contains(
+        "\"strings\": [\"PMyManaged::Msg___delete__\", \"PMyManager::Msg___delete__\", \
+         \"PMyManager::Msg_SomeMsg\", \"PMyManager::Reply_SomeMsg\","
+    ));
+    assert!(json.contains(
+        "  {\"id\": 65539, \"name\": 3, \"hash\": \"4386ec4dbf658b82\", \"loggable\": true},\n"
+    ));
+}
+
+#[test]
+fn struct_param_traits() {
+    let program = check_ok_file("Parray_Union.ipdl");
+    let tuid = program
+        .tu_for_file(&ok_dir().join("Parray_Union.ipdl"))
+        .unwrap();
+    let code = param_traits::param_traits(&program, &tuid).unwrap();
+    assert!(code.starts_with("namespace IPC {\n\ntemplate <>\nstruct ParamTraits<::Union> {\n"));
+    assert!(code.contains(
+        "    case union__::TArrayOfint: {\n      \
+         IPC::WriteParam(aWriter, (aVar).get_ArrayOfint());\n"
+    ));
+    assert!(code.contains("auto maybe__tmp = IPC::ReadParam<nsTArray<int>>(aReader);"));
+
+    let program = check_ok_file("MyTypes.ipdlh");
+    let tuid = program
+        .tu_for_file(&ok_dir().join("MyTypes.ipdlh"))
+        .unwrap();
+    let code = param_traits::param_traits(&program, &tuid).unwrap();
+    assert!(code.contains(
+        "  IPC::WriteParam(aWriter, (aVar).actor1());\n  \
+         IPC::WriteParam(aWriter, (aVar).actor2());\n}"
+    ));
+    assert!(code.contains(
+        "\"Error deserializing 'actor2' (::mozilla::myns::PMyManaged) member of 'MyActorPair'\""
+    ));
+    assert!(code.contains("result__{std::in_place, std::move(_actor1), std::move(_actor2)};"));
+
+    let program = check_ok_file("PMyManaged.ipdl");
+    let tuid = program
+        .tu_for_file(&ok_dir().join("PMyManaged.ipdl"))
+        .unwrap();
+    assert!(param_traits::param_traits(&program, &tuid).is_none());
+}
+
+#[test]
+fn actor_headers() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let manager = program.protocol_by_name("PMyManager").unwrap();
+    let parent = actors::actor_header(&program, &manager, ProtocolSide::Parent);
+    let child = actors::actor_header(&program, &manager, ProtocolSide::Child);
+
+    assert!(parent
+        .contains("class PMyManagerParent : public mozilla::ipc::IToplevelProtocol {\n public:\n"));
+    assert!(parent.contains("namespace mozilla { namespace myns { class PMyManagedParent; } }\n"));
+    assert!(parent.contains(
+        "  virtual mozilla::ipc::IPCResult RecvSomeMsg(\
+         const mozilla::Maybe<::MyActorPair>& aActors, \
+         const nsTArray<::mozilla::myns::MyData>& aMyData, \
+         ::int32_t* x, ::int32_t* y, ::mozilla::myns::MyUnion* aUnion) = 0;\n"
+    ));
+    assert!(parent.contains(
+        "  virtual already_AddRefed<::mozilla::myns::PMyManagedParent> \
+         AllocPMyManagedParent() = 0;\n"
+    ));
+    assert!(!parent.contains("SendSomeMsg"));
+
+    assert!(child.contains(
+        "  [[nodiscard]] static bool Send__delete__(\
+         ::mozilla::myns::PMyManagerChild* aActor, const ::nsString& aNote);\n"
+    ));
+    assert!(child.contains(
+        "  RefPtr<AnotherMsgPromise> SendAnotherMsg(\
+         const ::MyActorEnum& aEnum, const ::int32_t& aNumber);\n"
+    ));
+    assert!(child.contains(
+        "  virtual mozilla::ipc::IPCResult RecvAnotherMsg(\
+         const ::MyActorEnum& aEnum, const ::int32_t& aNumber, \
+         AnotherMsgResolver&& aResolve) = 0;\n"
+    ));
+    assert!(!child.contains("RecvSomeMsg"));
+
+    let managed = program.protocol_by_name("PMyManaged").unwrap();
+    let child = actors::actor_header(&program, &managed, ProtocolSide::Child);
+    assert!(child.contains("class PMyManagedChild : public mozilla::ipc::IProtocol {\n"));
+    assert!(child.contains(
+        "  virtual mozilla::ipc::IPCResult Recv__delete__(\
+         ::mozilla::ipc::Shmem&& aShmem) { return IPC_OK(); }\n"
+    ));
+}
+
+#[test]
+fn method_signatures() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let manager = program.protocol_by_name("PMyManager").unwrap();
+    // Every declared method of the header is listed, in the same order.
+    for side in &[ProtocolSide::Parent, ProtocolSide::Child] {
+        let header = actors::actor_header(&program, &manager, *side);
+        let methods = header
+            .lines()
+            .filter(|l| l.contains('(') && !l.contains("typedef"))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actors::method_signatures(&program, &manager, *side),
+            methods
+        );
+    }
+
+    let files = vec![
+        ok_dir().join("PMyManager.ipdl"),
+        ok_dir().join("PMyManaged.ipdl"),
+    ];
+    let text = actors::method_signatures_text(&program, &files);
+    assert!(text.starts_with(
+        "class ::mozilla::myns::PMyManagedParent\n  \
+         [[nodiscard]] static bool Send__delete__(\
+         ::mozilla::myns::PMyManagedParent* aActor, ::mozilla::ipc::Shmem&& aShmem);\n\n\
+         class ::mozilla::myns::PMyManagedChild\n"
+    ));
+    assert!(text.contains(
+        "\nclass ::mozilla::myns::PMyManagerChild\n  \
+         [[nodiscard]] static bool Send__delete__("
+    ));
+    let reversed = files.iter().rev().cloned().collect::<Vec<_>>();
+    assert_eq!(actors::method_signatures_text(&program, &reversed), text);
+}
+
+#[test]
+fn source_maps() {
+    let program = check_ok_file("Parray_Union.ipdl");
+    let tuid = program
+        .tu_for_file(&ok_dir().join("Parray_Union.ipdl"))
+        .unwrap();
+    let code = param_traits::param_traits(&program, &tuid).unwrap();
+    let marker_lines = code
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| *l == "// from Parray_Union.ipdl:1")
+        .map(|(i, _)| i + 1)
+        .collect::<Vec<_>>();
+    assert_eq!(marker_lines.len(), 2);
+
+    let source = ok_dir().join("Parray_Union.ipdl").canonicalize().unwrap();
+    let mappings = source_map::mappings(&program, &code);
+    assert_eq!(
+        mappings
+            .iter()
+            .map(|m| (m.first_line, m.source.clone(), m.source_line))
+            .collect::<Vec<_>>(),
+        marker_lines
+            .iter()
+            .map(|l| (*l, source.clone(), 1))
+            .collect::<Vec<_>>()
+    );
+    // A mapping covers the definition up to the blank line after it.
+    let lines = code.lines().collect::<Vec<_>>();
+    assert_eq!(lines[mappings[0].last_line - 1], "}");
+    assert_eq!(lines[mappings[0].last_line], "");
+
+    let json = source_map::source_map(&program, Path::new("Parray_UnionParamTraits.cpp"), &code);
+    assert!(json.starts_with(
+        "{\n  \"file\": \"Parray_UnionParamTraits.cpp\",\n  \"mappings\": [{\"lines\": ["
+    ));
+    assert!(json.contains(&format!(
+        "\"source\": \"{}\", \"source_line\": 1}}",
+        source.display()
+    )));
+}
+
+#[test]
+fn rust_bindings() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let manager = program.protocol_by_name("PMyManager").unwrap();
+    let code = rust_bindings::rust_bindings(&program, &manager).unwrap();
+    assert!(
+        code.contains("pub enum MyUnion {\n    Float(f32),\n    MyOtherData(MyOtherData),\n}\n")
+    );
+    assert!(
+        code.contains("    SomeMsg { a_actors: Option<MyActorPair>, a_my_data: Vec<MyData> },\n")
+    );
+    assert!(code.contains(
+        "    fn recv_some_msg(&mut self, a_actors: Option<MyActorPair>, a_my_data: Vec<MyData>) \
+         -> Result<(i32, i32, MyUnion), ipc::Error>;\n"
+    ));
+    assert!(code.contains(
+        "pub enum PMyManagerChildMessage {\n    // from PMyManager.ipdl:26\n    \
+         AnotherMsg { a_enum: MyActorEnum, a_number: i32 },\n}\n"
+    ));
+
+    let program = check_ok_file("PselfRecUnion.ipdl");
+    let tuid = program
+        .tu_for_file(&ok_dir().join("PselfRecUnion.ipdl"))
+        .unwrap();
+    let code = rust_bindings::rust_bindings(&program, &tuid).unwrap();
+    assert!(code.contains("    R(Box<R>),\n"));
+    assert!(code.contains("    fn recv_delete(&mut self) -> Result<(), ipc::Error>;\n"));
+}
+
+#[test]
+fn message_directions() {
+    let program = check_ok_file("PthreeDirections.ipdl");
+    let tuid = program.protocol_by_name("PthreeDirections").unwrap();
+    let names = |messages: Vec<&MessageTypeDef>| {
+        messages
+            .iter()
+            .map(|md| md.name.id.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(program.messages_sent_by(&tuid, ProtocolSide::Child)),
+        ["ParentMsg", "BothMsg"]
+    );
+    assert_eq!(
+        names(program.messages_received_by(&tuid, ProtocolSide::Child)),
+        ["ChildMsg", "BothMsg"]
+    );
+
+    let json = export::export_json(&program, &[ok_dir().join("PthreeDirections.ipdl")]);
+    assert!(json.contains(
+        "    \"parent_sends\": [\"ChildMsg\", \"BothMsg\"],\n    \
+         \"child_sends\": [\"ParentMsg\", \"BothMsg\"],\n"
+    ));
+    assert!(json.contains(
+        "{\"name\": \"BothMsg\", \"direction\": \"both\", \"senders\": [\"parent\", \"child\"], "
+    ));
+    assert!(json.contains("{\"name\": \"ChildMsg\", \"direction\": \"toChild\", "));
+}
+
+#[test]
+fn message_cxx_names() {
+    let temp = TempDir::new("message_cxx_names");
+    let file = temp.write(
+        "PNames.ipdl",
+        "[ChildProc=any]\nintr protocol PNames {\nchild:\n  async Reply() returns (int x);\n\
+         async Plain();\n  intr Call();\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let program = compiler::check(&include_dirs, vec![file.clone()]).unwrap();
+    let tuid = program.tu_for_file(&file).unwrap();
+    let names = program
+        .protocol(&tuid)
+        .messages
+        .iter()
+        .map(|md| md.cxx_names())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            MessageCxxNames {
+                send: String::from("SendReply"),
+                recv: String::from("RecvReply"),
+                promise: Some(String::from("ReplyPromise")),
+                resolver: Some(String::from("ReplyResolver")),
+            },
+            MessageCxxNames {
+                send: String::from("SendPlain"),
+                recv: String::from("RecvPlain"),
+                promise: None,
+                resolver: None,
+            },
+            MessageCxxNames {
+                send: String::from("CallCall"),
+                recv: String::from("AnswerCall"),
+                promise: None,
+                resolver: None,
+            },
+        ]
+    );
+
+    let json = export::export_json(&program, &[file]);
+    assert!(json.contains(
+        "\"cxx_names\": {\"send\": \"SendReply\", \"recv\": \"RecvReply\", \
+         \"promise\": \"ReplyPromise\", \"resolver\": \"ReplyResolver\"}"
+    ));
+    assert!(json.contains(
+        "\"cxx_names\": {\"send\": \"CallCall\", \"recv\": \"AnswerCall\", \
+         \"promise\": null, \"resolver\": null}"
+    ));
+}
+
+#[test]
+fn typescript_declarations() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let manager = program.protocol_by_name("PMyManager").unwrap();
+    let code = typescript::typescript_declarations(&program, &manager).unwrap();
+    assert!(code.contains(
+        "import type { ActorRef } from \"./IPDLTypes\";\n\
+         import type { MyActorPair } from \"./MyTypes\";\n"
+    ));
+    assert!(code.contains(
+        "export type MyUnion =\n  | { type: \"Tfloat\"; value: number }\n  \
+         | { type: \"TMyOtherData\"; value: unknown /* ::mozilla::myns::MyOtherData */ };\n"
+    ));
+    assert!(code.contains(
+        "    params: { aActors: MyActorPair | null; \
+         aMyData: (unknown /* ::mozilla::myns::MyData */)[]; };\n    \
+         returns: { x: number; y: number; aUnion: MyUnion; };\n"
+    ));
+    assert!(code.contains("    params: { newActor: ActorRef<\"PMyManaged\">; };\n"));
+    assert!(code.contains(
+        "  export type Message = __delete__ | SomeMsg | PMyManagedConstructor | AnotherMsg;\n"
+    ));
+    assert!(code.contains(
+        "  export type ToParent = __delete__ | SomeMsg | PMyManagedConstructor | AnotherMsg;\n  \
+         export type ToChild = AnotherMsg;\n}\n"
+    ));
+
+    let program = check_ok_file("MyTypes.ipdlh");
+    let tuid = program
+        .tu_for_file(&ok_dir().join("MyTypes.ipdlh"))
+        .unwrap();
+    let code = typescript::typescript_declarations(&program, &tuid).unwrap();
+    assert!(code.contains(
+        "  actor1: ActorRef<\"PMyManaged\">;\n  actor2: ActorRef<\"PMyManaged\"> | null;\n"
+    ));
+}
+
+#[test]
+fn forward_declarations() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let manager = program.protocol_by_name("PMyManager").unwrap();
+    let decls = forward_decls::protocol_declarations(&program, &manager);
+    assert_eq!(
+        decls.includes,
+        vec![
+            "MyTypes.h",
+            "mozilla/MyDataTypes.h",
+            "mozilla/myns/MyActorUtils.h",
+        ]
+    );
+    let names = decls
+        .forward_decls
+        .iter()
+        .map(|d| d.qualified_name())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "::mozilla::myns::MyOtherData",
+            "::mozilla::myns::PMyManagedChild",
+            "::mozilla::myns::PMyManagedParent",
+            "::mozilla::myns::PMyManagerChild",
+            "::mozilla::myns::PMyManagerParent",
+        ]
+    );
+    assert_eq!(
+        decls.forward_decls[0].header.as_deref(),
+        Some("mozilla/MyDataTypes.h")
+    );
+    assert_eq!(
+        decls.forward_decls[0].to_cxx(),
+        "namespace mozilla { namespace myns { class MyOtherData; } }"
+    );
+
+    let header = forward_decls::header_fragment(&program, &manager);
+    assert!(header.starts_with("#ifndef PMyManagerForwardDecls_h\n"));
+    assert!(header.contains("#include \"MyTypes.h\"\n"));
+    assert!(header.contains("namespace mozilla { namespace myns { class PMyManagedChild; } }\n"));
+
+    let json = forward_decls::declarations_json(&program, &[ok_dir().join("PMyManager.ipdl")]);
+    assert!(json.contains("\"name\": \"::mozilla::myns::PMyManager\""));
+    assert!(json.contains(
+        "{\"kind\": \"class\", \"name\": \"::mozilla::myns::MyOtherData\", \
+         \"header\": \"mozilla/MyDataTypes.h\", \
+         \"declaration\": \"namespace mozilla { namespace myns { class MyOtherData; } }\"}"
+    ));
+}
+
+#[test]
+fn protocol_versions() {
+    let program = check_ok_file("PVersioned.ipdl");
+    let files = [ok_dir().join("PVersioned.ipdl")];
+    let json = export::export_json(&program, &files);
+    assert!(json.contains("\"version\": 3,"));
+    assert!(json.contains("\"name\": \"Failed\""));
+    assert!(json.contains("\"since\": 3, "));
+
+    let old = versions::parse_export(&json).unwrap();
+    assert!(versions::check_versions(&program, &files, &old).is_empty());
+
+    // Version 2 didn't have Failed yet.
+    let mut old_protocol = old[0].clone();
+    old_protocol.messages.retain(|m| m.name != "Failed");
+    old_protocol.version = Some(2);
+    let old = vec![old_protocol.clone()];
+    assert!(versions::check_versions(&program, &files, &old).is_empty());
+
+    // Adding Failed to version 3 needs a new version.
+    old_protocol.version = Some(3);
+    let old = vec![old_protocol.clone()];
+    let errors = versions::check_versions(&program, &files, &old);
+    let messages = errors
+        .iter()
+        .map(|e| e.message.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            "message `Failed' is new since version 3 of protocol `PVersioned', \
+             so it needs [Since=4]",
+            "protocol `PVersioned' changed since version 3, so its [ProtocolVersion] must be bumped",
+        ]
+    );
+    assert_eq!(
+        errors[1].notes[0].message,
+        "message `Failed' was added here"
+    );
+
+    // Changing the type of a parameter is a change too.
+    old_protocol.version = Some(3);
+    old_protocol.messages = old[0].messages.clone();
+    old_protocol.messages.push(versions::OldMessage {
+        name: String::from("Failed"),
+        signature: String::from("async toParent(nsString) returns ()"),
+        since: Some(3),
+    });
+    let errors = versions::check_versions(&program, &files, &[old_protocol]);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].notes[0].message, "message `Failed' changed here");
+}
+
+#[test]
+fn no_logging_messages() {
+    let program = check_ok_file("PNoLogging.ipdl");
+    let tuid = program.protocol_by_name("PNoLogging").unwrap();
+    let hidden = program
+        .protocol(&tuid)
+        .messages
+        .iter()
+        .filter(|md| md.no_logging)
+        .map(|md| md.name.id.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(hidden, vec!["StorePassword", "ReadPassword"]);
+
+    let json = export::export_json(&program, &[ok_dir().join("PNoLogging.ipdl")]);
+    assert_eq!(json.matches("\"no_logging\": true").count(), 2);
+
+    let names = message_ids::message_type_names(&program);
+    assert!(names.contains(
+        "bool IPCMessageTypeIsLoggable(uint32_t aMessageType) {\n  switch (aMessageType) {\n    \
+         case ::PNoLogging::Msg_StorePassword__ID:\n    \
+         case ::PNoLogging::Msg_ReadPassword__ID:\n    \
+         case ::PNoLogging::Reply_ReadPassword__ID:\n      \
+         return false;\n    default:\n      return true;\n  }\n}\n"
+    ));
+    let json = message_ids::profiler_markers_json(&program);
+    assert_eq!(json.matches("\"loggable\": false").count(), 3);
+
+    let program = check_ok_file("PMyManager.ipdl");
+    let names = message_ids::message_type_names(&program);
+    assert!(names
+        .contains("bool IPCMessageTypeIsLoggable(uint32_t aMessageType) {\n  return true;\n}\n"));
+}
+
+#[test]
+fn message_sizes() {
+    let temp = TempDir::new("message_sizes");
+    let file = temp.write(
+        "PSizes.ipdl",
+        "struct Point { int32_t x; int32_t y; };\n\
+         union Shape { Point; double; };\n\
+         [ChildProc=any]\nsync protocol PSizes {\nchild:\n  \
+         async A(Point p, Shape s);\n  \
+         async B(nsCString name);\n  \
+         [MaxSize=64] async C(uint8_t[] data);\n  \
+         [Allow=large_message] async D(uint8_t[] data);\nparent:\n  \
+         [MaxSize=4] async E(Point p);\n  \
+         sync F(int32_t i) returns (Point[] points);\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let result = compiler::check(&include_dirs, vec![file.clone()]);
+    let errors = result.err().unwrap().to_string();
+    assert!(errors.contains(
+        "PSizes.ipdl:11:3: error: message `E' always has at least 8 bytes of parameters, \
+         more than its [MaxSize=4]"
+    ));
+
+    fs::write(
+        &file,
+        fs::read_to_string(&file)
+            .unwrap()
+            .replace("[MaxSize=4]", "[MaxSize=8]"),
+    )
+    .unwrap();
+    let program = compiler::check(&include_dirs, vec![file.clone()]).unwrap();
+    assert!(program.warnings.is_empty());
+    let tuid = program.protocol_by_name("PSizes").unwrap();
+    let max_sizes = program
+        .protocol(&tuid)
+        .messages
+        .iter()
+        .map(|md| md.max_size)
+        .collect::<Vec<_>>();
+    assert_eq!(max_sizes, vec![None, None, Some(64), None, Some(8), None]);
+
+    let options = CheckOptions {
+        max_message_size: Some(16),
+        ..CheckOptions::default()
+    };
+    let program = compiler::check_with_options(&include_dirs, vec![file], &options).unwrap();
+    let warnings = program
+        .warnings
+        .iter()
+        .map(|w| (w.loc.as_ref().unwrap().lineno, w.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec![
+            (
+                6,
+                "the parameters of message `A' can be 20 bytes, more than the limit of 16"
+            ),
+            (
+                7,
+                "the size of the parameters of message `B' has no limit, but the limit is 16 bytes; \
+                 declare a [MaxSize] if it is never larger"
+            ),
+            (
+                12,
+                "the size of the reply of message `F' has no limit, but the limit is 16 bytes; \
+                 declare a [MaxSize] if it is never larger"
+            ),
+        ]
+    );
+    assert_eq!(program.warnings[0].code, Some("large_message"));
+}
+
+// The code generated for some of the files in tests/ok/ is checked in
+// under tests/golden/. If a change to a generator is intended, update the
+// files with:
+//   ipdl_parser -I tests/ok --emit-to tests/golden tests/ok/PStruct.ipdl \
+//       tests/ok/Punion_Basic.ipdl
+#[test]
+fn golden_outputs() {
+    let golden_dir: PathBuf = BASE_PATH.iter().chain(&["golden"]).collect();
+    let files: Vec<PathBuf> = ["PStruct.ipdl", "Punion_Basic.ipdl"]
+        .iter()
+        .map(|f| ok_dir().join(f))
+        .collect();
+    let program = compiler::check(&vec![ok_dir()], files.clone()).unwrap();
+
+    let mut generated = vec![
+        (
+            String::from("IPCMessageStart.h"),
+            message_ids::message_start_header(&program),
+        ),
+        (
+            String::from("IPCMessageTypeName.cpp"),
+            message_ids::message_type_names(&program),
+        ),
+        (
+            String::from("IPDLTypes.d.ts"),
+            String::from(typescript::PRELUDE),
+        ),
+    ];
+    for file in &files {
+        let tuid = program.tu_for_file(file).unwrap();
+        let ptype = program.tuts[&tuid].protocol.as_ref().unwrap();
+        let name = ptype.qname.short_name();
+        generated.push((format!("{}.h", name), message_ids::protocol_header(ptype)));
+        for side in &[ProtocolSide::Parent, ProtocolSide::Child] {
+            generated.push((
+                format!("{}{:?}.h", name, side),
+                actors::actor_header(&program, &tuid, *side),
+            ));
+        }
+        generated.push((
+            format!("{}ForwardDecls.h", name),
+            forward_decls::header_fragment(&program, &tuid),
+        ));
+        generated.push((
+            format!("{}ParamTraits.cpp", name),
+            param_traits::param_traits(&program, &tuid).unwrap(),
+        ));
+        generated.push((
+            format!("{}.rs", name),
+            rust_bindings::rust_bindings(&program, &tuid).unwrap(),
+        ));
+        generated.push((
+            format!("{}.d.ts", name),
+            typescript::typescript_declarations(&program, &tuid).unwrap(),
+        ));
+    }
+
+    let mismatches: Vec<String> = generated
+        .iter()
+        .filter_map(|(name, code)| golden::compare_golden(&golden_dir.join(name), code).err())
+        .collect();
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+    assert_eq!(fs::read_dir(&golden_dir).unwrap().count(), generated.len());
+
+    // A change shows up as a diff of the lines around it.
+    let (ref name, ref code) = generated[3];
+    let e = golden::compare_golden(
+        &golden_dir.join(name),
+        &code.replacen("\n", "\n// Changed\n", 4),
+    )
+    .err()
+    .unwrap();
+    assert!(e.contains("+// Changed\n"));
+    assert!(e.contains("\n@@ -"));
+}
+
+#[test]
+fn include_graph() {
+    let temp = TempDir::new("include_graph");
+    let dir = temp
+        .write("Shared.ipdlh", "struct Shared { int x; };\n")
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let protocol = |name: &str, includes: &str, body: &str| {
+        fs::write(
+            dir.join(format!("{}.ipdl", name)),
+            format!(
+                "{}[ChildProc=any]\nprotocol {} {{\n{}child:\n  async __delete__();\n}};\n",
+                includes, name, body
+            ),
+        )
+        .unwrap();
+    };
+    protocol(
+        "PManager",
+        "include protocol PManaged;\ninclude Shared;\n",
+        "  manages PManaged;\nparent:\n  async PManaged(Shared s);\n",
+    );
+    protocol(
+        "PManaged",
+        "include protocol PManager;\n",
+        "  manager PManager;\n",
+    );
+    protocol(
+        "PLeaf",
+        "include Shared;\n",
+        "parent:\n  async M(Shared s);\n",
+    );
+    let program = compiler::check(
+        &vec![dir.clone()],
+        vec![dir.join("PManager.ipdl"), dir.join("PLeaf.ipdl")],
+    )
+    .unwrap();
+    let tuid = |name: &str| program.tu_for_file(&dir.join(name)).unwrap();
+    let (manager, managed, leaf, shared) = (
+        tuid("PManager.ipdl"),
+        tuid("PManaged.ipdl"),
+        tuid("PLeaf.ipdl"),
+        tuid("Shared.ipdlh"),
+    );
+
+    let graph = program.include_graph();
+    assert_eq!(graph.nodes().len(), 4);
+    assert_eq!(graph.includes(&manager), &[managed, shared]);
+    let mut includers = vec![manager, leaf];
+    includers.sort();
+    assert_eq!(graph.included_by(&shared), includers);
+
+    // The header comes first, and the manager and its managee include each
+    // other, so they are one component.
+    let components = graph.strongly_connected_components();
+    assert_eq!(components.len(), 3);
+    assert_eq!(components[0], vec![shared]);
+    let mut cycle = vec![manager, managed];
+    cycle.sort();
+    assert!(components.contains(&cycle));
+    let order = graph.topological_order();
+    assert_eq!(order[0], shared);
+    assert_eq!(order.len(), 4);
+
+    assert_eq!(graph.dependents(&[leaf]), vec![leaf]);
+    assert_eq!(graph.dependents(&[shared]), order);
+    let dependents = graph.dependents(&[managed]);
+    assert_eq!(dependents.len(), 2);
+    assert!(dependents.contains(&manager));
+
+    let files = graph.ordered_files(&program.tus);
+    assert_eq!(files.len(), 4);
+    assert_eq!(
+        files[0].to_string(),
+        format!("0 {}:", dir.join("Shared.ipdlh").display())
+    );
+    let manager_file = files
+        .iter()
+        .find(|f| f.file_name == dir.join("PManager.ipdl"))
+        .unwrap();
+    let managed_file = files
+        .iter()
+        .find(|f| f.file_name == dir.join("PManaged.ipdl"))
+        .unwrap();
+    assert_eq!(manager_file.component, managed_file.component);
+    assert_eq!(
+        manager_file.includes,
+        vec![dir.join("PManaged.ipdl"), dir.join("Shared.ipdlh")]
+    );
+}
+
+#[test]
+fn declaration_order() {
+    let files = [
+        "PmultiManaged.ipdl",
+        "PmultiManager2.ipdl",
+        "PmultiManager1.ipdl",
+        "PmutualRecStructUnion.ipdl",
+        "PStruct.ipdl",
+    ];
+    let check = |files: Vec<&str>| {
+        let files: Vec<PathBuf> = files.iter().map(|f| ok_dir().join(f)).collect();
+        compiler::check(&vec![ok_dir()], files).unwrap()
+    };
+    let forward = check(files.to_vec());
+    let backward = check(files.iter().rev().cloned().collect());
+
+    let names = |program: &TypedProgram| {
+        let order = program.decl_order();
+        let protocols: Vec<String> = order
+            .protocols()
+            .iter()
+            .map(|tuid| program.protocol(tuid).qname.to_string())
+            .collect();
+        let structs: Vec<String> = order
+            .structs()
+            .iter()
+            .map(|tr| tr.lookup_struct(&program.tuts).qname.to_string())
+            .collect();
+        let unions: Vec<String> = order
+            .unions()
+            .iter()
+            .map(|tr| tr.lookup_union(&program.tuts).qname.to_string())
+            .collect();
+        (protocols, structs, unions)
+    };
+    let (protocols, structs, unions) = names(&forward);
+    assert_eq!(
+        protocols,
+        vec![
+            "::PStruct",
+            "::PmultiManaged",
+            "::PmultiManager1",
+            "::PmultiManager2",
+            "::PmutualRecStructUnion"
+        ]
+    );
+    // Declaration order, with the files sorted by name.
+    assert_eq!(structs, vec!["::S", "::X", "::Z"]);
+    assert_eq!(unions, vec!["::Y"]);
+    assert_eq!(names(&backward), (protocols, structs, unions));
+
+    // The files were parsed in a different order, but the generated code is
+    // the same.
+    assert_eq!(
+        message_ids::message_type_names(&forward),
+        message_ids::message_type_names(&backward)
+    );
+    for file in &files {
+        let file = ok_dir().join(file);
+        let (f, b) = (
+            forward.tu_for_file(&file).unwrap(),
+            backward.tu_for_file(&file).unwrap(),
+        );
+        assert_eq!(
+            forward.decl_order().protocol_ordinal(&f),
+            backward.decl_order().protocol_ordinal(&b)
+        );
+        for side in &[ProtocolSide::Parent, ProtocolSide::Child] {
+            assert_eq!(
+                actors::actor_header(&forward, &f, *side),
+                actors::actor_header(&backward, &b, *side)
+            );
+        }
+        assert_eq!(
+            forward_decls::header_fragment(&forward, &f),
+            forward_decls::header_fragment(&backward, &b)
+        );
+    }
+}
+
+#[test]
+fn definitions() {
+    let program = compiler::check(
+        &vec![ok_dir()],
+        vec![
+            ok_dir().join("Pnamespace_Basic.ipdl"),
+            ok_dir().join("PStruct.ipdl"),
+        ],
+    )
+    .unwrap();
+    let index = program.definition_index();
+
+    let d = index.lookup("::PStruct::test").unwrap();
+    assert_eq!(d.kind, DefinitionKind::Message);
+    assert_eq!(d.loc.file_name.file_name().unwrap(), "PStruct.ipdl");
+    assert_eq!((d.loc.lineno, d.loc.colno, d.len), (9, 9, 4));
+
+    let d = index.lookup("S").unwrap();
+    assert_eq!(d.kind, DefinitionKind::Struct);
+    assert_eq!((d.loc.lineno, d.loc.colno, d.len), (1, 7, 1));
+    assert_eq!(
+        index.lookup("PStruct").unwrap().kind,
+        DefinitionKind::Protocol
+    );
+    assert!(index.lookup("test").is_none());
+    assert!(index.lookup("PStruct::S").is_none());
+
+    let names: Vec<&str> = index
+        .definitions()
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect();
+    assert!(names.windows(2).all(|w| w[0] < w[1]));
+    assert!(names.contains(&"::basic::Pnamespace_Basic::Msg"));
+}
+
+#[test]
+fn repeated_unknown_types() {
+    let temp = TempDir::new("repeated_unknown_types");
+    let file = temp.write(
+        "PRepeatedUnknown.ipdl",
+        "struct S {\n  Missing a;\n  Missing b;\n};\n\n\
+         [ChildProc=any]\nprotocol PRepeatedUnknown {\nchild:\n  \
+         async A(S s, Missing m, Other o);\n  async B(Missing m, Missing n);\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let e = compiler::check(&include_dirs, vec![file]).err().unwrap();
+    let ds = e.diagnostics();
+    assert_eq!(
+        ds.iter()
+            .map(|d| (d.loc.as_ref().unwrap().lineno, d.symbol.as_deref()))
+            .collect::<Vec<_>>(),
+        vec![
+            (2, Some("Missing")),
+            (3, Some("Missing")),
+            (9, Some("Missing")),
+            (9, Some("Other")),
+        ]
+    );
+    assert!(ds.iter().all(|d| d.code == Some("unknown_type")));
+    let note = &ds[2].notes[0];
+    assert_eq!(
+        note.message,
+        "error limit reached for `Missing': 2 more like this are not reported"
+    );
+    assert_eq!(note.loc.lineno, 10);
+    assert!(ds[3].notes.is_empty());
+}
+
+#[test]
+fn input_limits() {
+    let temp = TempDir::new("input_limits");
+    let file = temp.write(
+        "PLimits.ipdl",
+        "include PLimitsIncluded;\n\n\
+         struct S {\n  int a;\n  int b;\n};\n\n\
+         [ChildProc=any]\nprotocol PLimits {\nchild:\n  async A(int x, int y);\n};\n",
+    );
+    let dir = file.parent().unwrap().to_path_buf();
+    fs::write(
+        dir.join("PLimitsIncluded.ipdlh"),
+        "struct T {\n  int c;\n};\n",
+    )
+    .unwrap();
+    let include_dirs = vec![dir];
+    let check = |limits: parser::InputLimits| {
+        let options = CheckOptions {
+            limits,
+            ..CheckOptions::default()
+        };
+        compiler::check_with_options(&include_dirs, vec![file.clone()], &options)
+    };
+    assert!(check(parser::InputLimits::default()).is_ok());
+
+    let e = check(parser::InputLimits {
+        max_file_size: Some(20),
+        ..parser::InputLimits::default()
+    })
+    .err()
+    .unwrap();
+    assert!(e.to_string().contains("more than the limit of 20 bytes"));
+
+    let e = check(parser::InputLimits {
+        max_include_depth: Some(0),
+        ..parser::InputLimits::default()
+    })
+    .err()
+    .unwrap();
+    assert!(e
+        .to_string()
+        .contains("includes are nested more than the limit of 0 levels deep"));
+
+    // S, a, b, A, x, y: the limit is reached at the message parameter y.
+    let e = check(parser::InputLimits {
+        max_declarations: Some(5),
+        ..parser::InputLimits::default()
+    })
+    .err()
+    .unwrap();
+    let ds = e.diagnostics();
+    assert_eq!(ds.len(), 1);
+    assert_eq!(ds[0].loc.as_ref().unwrap().lineno, 11);
+    assert!(ds[0]
+        .message
+        .contains("declares more than the limit of 5 types"));
+    assert!(check(parser::InputLimits {
+        max_declarations: Some(6),
+        ..parser::InputLimits::default()
+    })
+    .is_ok());
+}
+
+#[test]
+fn wire_format() {
+    let temp = TempDir::new("wire_format");
+    let file = temp.write(
+        "PWire.ipdl",
+        "struct Point {\n  int x;\n  int y;\n};\n\n\
+         union Shape {\n  Point;\n  Shape[];\n};\n\n\
+         [ChildProc=any]\nprotocol PWire {\nchild:\n  \
+         async Draw(Point? p, Shape s, uint8_t[] bytes);\n};\n",
+    );
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let program = compiler::check(&include_dirs, vec![file.clone()]).unwrap();
+    let tuid = program.protocol_by_name("PWire").unwrap();
+    let md = &program.tuts[&tuid].protocol.as_ref().unwrap().messages[0];
+    let items = wire_format::wire_format(&program, &md.params);
+
+    let int = |path: &str| WireItem {
+        path: path.to_string(),
+        kind: WireKind::Value {
+            cxx_type: String::from("int"),
+            size: Some(4),
+        },
+    };
+    assert_eq!(items.len(), 3);
+    assert_eq!(
+        items[0],
+        WireItem {
+            path: String::from("p"),
+            kind: WireKind::Optional(vec![int("p.x"), int("p.y")]),
+        }
+    );
+    // The array of shapes inside of Shape refers back to it.
+    assert_eq!(
+        items[1],
+        WireItem {
+            path: String::from("s"),
+            kind: WireKind::Union {
+                name: String::from("::Shape"),
+                variants: vec![
+                    (String::from("TPoint"), vec![int("s.x"), int("s.y")]),
+                    (
+                        String::from("TArrayOfShape"),
+                        vec![WireItem {
+                            path: String::from("s"),
+                            kind: WireKind::Array(vec![WireItem {
+                                path: String::from("s[]"),
+                                kind: WireKind::Recursive(String::from("::Shape")),
+                            }]),
+                        }]
+                    ),
+                ],
+            },
+        }
+    );
+    assert_eq!(
+        items[2],
+        WireItem {
+            path: String::from("bytes"),
+            kind: WireKind::Array(vec![WireItem {
+                path: String::from("bytes[]"),
+                kind: WireKind::Value {
+                    cxx_type: String::from("::uint8_t"),
+                    size: Some(1),
+                },
+            }]),
+        }
+    );
+
+    let json = wire_format::wire_format_json(&program, &[file]);
+    assert!(json.contains(
+        "{\"path\": \"s\", \"read\": \"union\", \"union\": \"::Shape\", \"tag_type\": \"int\", \
+         \"variants\": [{\"tag\": 1, \"name\": \"TPoint\", \"value\": [{\"path\": \"s.x\", \
+         \"read\": \"value\", \"type\": \"int\", \"size\": 4}, {\"path\": \"s.y\", \
+         \"read\": \"value\", \"type\": \"int\", \"size\": 4}]}, {\"tag\": 2"
+    ));
+    assert!(json.contains("\"read\": \"recursive\", \"type\": \"::Shape\""));
+    assert!(json.contains("\"returns\": []"));
+}
+
+#[test]
+fn sync_both_directions() {
+    let temp = TempDir::new("sync_both_directions");
+    let file = temp.write(
+        "PSyncBoth.ipdl",
+        "[NestedUpTo=inside_sync, ChildProc=any]\nsync protocol PSyncBoth {\nboth:\n  \
+         [Nested=inside_sync] sync Ping() returns (int a);\n  async Pong();\n};\n",
+    );
+    let dir = file.parent().unwrap().to_path_buf();
+    let include_dirs = vec![dir.clone()];
+    let program = compiler::check(&include_dirs, vec![file.clone()]).unwrap();
+    assert_eq!(program.warnings.len(), 1);
+    assert_eq!(program.warnings[0].code, Some("sync_both_directions"));
+    assert_eq!(program.warnings[0].loc.as_ref().unwrap().lineno, 4);
+
+    // An unnested sync message in a `both:' section is also an error.
+    fs::write(
+        &file,
+        "[ChildProc=any]\nsync protocol PSyncBoth {\nboth:\n  sync Ping() returns (int a);\n};\n",
+    )
+    .unwrap();
+    let e = compiler::check(&include_dirs, vec![file.clone()])
+        .err()
+        .unwrap();
+    let codes = e.diagnostics().iter().map(|d| d.code).collect::<Vec<_>>();
+    assert_eq!(
+        codes,
+        vec![Some("sync_both_directions"), Some("sync_to_child")]
+    );
+
+    // A config can make it an error on its own.
+    fs::write(
+        &file,
+        "[NestedUpTo=inside_sync, ChildProc=any]\nsync protocol PSyncBoth {\nboth:\n  \
+         [Nested=inside_sync] sync Ping() returns (int a);\n};\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("ipdl.toml"),
+        "root = true\n[lints]\nsync_both_directions = \"deny\"\n",
+    )
+    .unwrap();
+    let options = CheckOptions {
+        use_config_files: true,
+        ..CheckOptions::default()
+    };
+    let e = compiler::check_with_options(&include_dirs, vec![file], &options)
+        .err()
+        .unwrap();
+    assert_eq!(e.diagnostics().len(), 1);
+    assert_eq!(e.diagnostics()[0].severity, Severity::Error);
+}
+
+#[test]
+fn manager_order() {
+    let temp = TempDir::new("manager_order");
+    let dir = temp
+        .write(
+            "PManagerB.ipdl",
+            "include protocol POrdered;\n[ChildProc=any]\nprotocol PManagerB {\n  \
+         manages POrdered;\nchild:\n  async POrdered();\n};\n",
+        )
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    fs::write(
+        dir.join("PManagerA.ipdl"),
+        "include protocol POrdered;\n[ChildProc=any]\nprotocol PManagerA {\n  \
+         manages POrdered;\nchild:\n  async POrdered();\n};\n",
+    )
+    .unwrap();
+    let text = |attributes: &str| {
+        format!(
+            "include protocol PManagerA;\ninclude protocol PManagerB;\n{}\
+             protocol POrdered {{\n  manager PManagerB or PManagerA;\n\
+             child:\n  async __delete__();\n}};\n",
+            attributes
+        )
+    };
+    let file = dir.join("POrdered.ipdl");
+    fs::write(&file, text("")).unwrap();
+    let include_dirs = vec![dir.clone()];
+    let mut sources = SourceManager::new();
+    let program = compiler::check_with_sources(
+        &include_dirs,
+        vec![file.clone()],
+        &CheckOptions::default(),
+        &mut sources,
+    )
+    .unwrap();
+    assert_eq!(program.warnings.len(), 1);
+    let w = &program.warnings[0];
+    assert_eq!(w.code, Some("manager_order"));
+    assert!(w
+        .to_string()
+        .ends_with("4:10: fix: list them as `manager PManagerA or PManagerB'"));
+    let fixed = w
+        .fix
+        .as_ref()
+        .unwrap()
+        .apply(sources.text(&w.loc.as_ref().unwrap().file_name).unwrap());
+    assert!(fixed.contains("  manager PManagerA or PManagerB;\n"));
+
+    fs::write(&file, text("[Allow=manager_order]\n")).unwrap();
+    let program = compiler::check(&include_dirs, vec![file]).unwrap();
+    assert!(program.warnings.is_empty());
+}
+
+#[test]
+fn fix_diffs() {
+    let old = (1..=12)
+        .map(|i| format!("line {}\n", i))
+        .collect::<String>();
+    let new = old
+        .replace("line 2\n", "line two\n")
+        .replace("line 11\n", "");
+    assert_eq!(
+        fixes::unified_diff("dir/PFoo.ipdl", &old, &new),
+        "--- a/dir/PFoo.ipdl\n+++ b/dir/PFoo.ipdl\n\
+         @@ -1,5 +1,5 @@\n line 1\n-line 2\n+line two\n line 3\n line 4\n line 5\n\
+         @@ -8,5 +8,4 @@\n line 8\n line 9\n line 10\n-line 11\n line 12\n"
+    );
+    assert_eq!(fixes::unified_diff("PFoo.ipdl", &old, &old), "");
+    assert_eq!(
+        fixes::unified_diff("PFoo.ipdl", "", "a\n"),
+        "--- a/PFoo.ipdl\n+++ b/PFoo.ipdl\n@@ -0,0 +1 @@\n+a\n"
+    );
+    assert_eq!(
+        fixes::unified_diff("PFoo.ipdl", "a\nb", "a\nc\n"),
+        "--- a/PFoo.ipdl\n+++ b/PFoo.ipdl\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+c\n"
+    );
+    assert_eq!(
+        fixes::unified_diff("PFoo.ipdl", "a\n", "a"),
+        "--- a/PFoo.ipdl\n+++ b/PFoo.ipdl\n@@ -1 +1 @@\n-a\n+a\n\\ No newline at end of file\n"
+    );
+
+    // Two fixes that replace the same text: only the first is applied.
+    let temp = TempDir::new("fix_diffs");
+    let file = temp.write("PFixes.ipdl", "protocol PFixes {};\n");
+    let mut sources = SourceManager::new();
+    sources.load(&file).unwrap();
+    let edit = |replacement: &str| Edit {
+        loc: Location {
+            file_name: file.clone(),
+            lineno: 1,
+            colno: 9,
+        },
+        len: 6,
+        replacement: String::from(replacement),
+    };
+    let diagnostics = vec![
+        Diagnostic::without_location("first").with_fix("rename", vec![edit("PFirst")]),
+        Diagnostic::without_location("second").with_fix("rename", vec![edit("PSecond")]),
+    ];
+    let fixed = fixes::fixed_files(&diagnostics, &sources);
+    assert_eq!(fixed.len(), 1);
+    assert_eq!(fixed[0].new, "protocol PFirst {};\n");
+    assert_eq!((fixed[0].applied, fixed[0].skipped), (1, 1));
+
+    // The same fix from two diagnostics is applied once, and isn't skipped.
+    let diagnostics = vec![
+        Diagnostic::without_location("first").with_fix("rename", vec![edit("PFirst")]),
+        Diagnostic::without_location("again").with_fix("rename", vec![edit("PFirst")]),
+    ];
+    let fixed = fixes::fixed_files(&diagnostics, &sources);
+    assert_eq!(fixed[0].new, "protocol PFirst {};\n");
+    assert_eq!((fixed[0].applied, fixed[0].skipped), (1, 0));
+
+    // A fix for a line that isn't there any more is left out instead of
+    // panicking.
+    let stale = Edit {
+        loc: Location {
+            file_name: file.clone(),
+            lineno: 9,
+            colno: 0,
+        },
+        len: 1,
+        replacement: String::from("x"),
+    };
+    let fix = Diagnostic::without_location("stale").with_fix("edit", vec![stale]);
+    assert_eq!(
+        fix.fix.as_ref().unwrap().apply("protocol PFixes {};\n"),
+        "protocol PFixes {};\n"
+    );
+    let fixed = fixes::fixed_files(&[fix], &sources);
+    assert_eq!(fixed[0].new, fixed[0].old);
+    assert_eq!((fixed[0].applied, fixed[0].stale), (0, 1));
+}
+
+#[test]
+fn diagnostics_view() {
+    let loc = |file: &str, lineno: usize| Location {
+        file_name: PathBuf::from(file),
+        lineno,
+        colno: 0,
+    };
+    let edit = |file: &str| Edit {
+        loc: loc(file, 1),
+        len: 0,
+        replacement: String::from("x"),
+    };
+    let diagnostics = vec![
+        Diagnostic::from_catalog(&loc("PA.ipdl", 1), "manager_order", &[("protocol", &"PA")])
+            .with_fix("sort", vec![edit("PA.ipdl"), edit("PA.ipdl")]),
+        Diagnostic::new(&loc("PA.ipdl", 2), "no fix"),
+        Diagnostic::warning(&loc("PB.ipdl", 1), "two files")
+            .with_fix("rename", vec![edit("PA.ipdl"), edit("PB.ipdl")]),
+        Diagnostic::without_location("no edits").with_fix("think about it", Vec::new()),
+    ];
+    let availability = diagnostics
+        .iter()
+        .map(Diagnostic::fix_availability)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        availability,
+        vec![
+            FixAvailability::MachineApplicable,
+            FixAvailability::NoFix,
+            FixAvailability::Manual,
+            FixAvailability::Manual,
+        ]
+    );
+
+    let all = Diagnostics::new(&diagnostics);
+    assert_eq!(all.len(), 4);
+    let messages = |d: Diagnostics| d.iter().map(|d| d.message.clone()).collect::<Vec<_>>();
+    assert_eq!(
+        messages(all.clone().with_severity(Severity::Warning)),
+        vec![diagnostics[0].message.clone(), String::from("two files")]
+    );
+    assert_eq!(messages(all.clone().with_code("manager_order")).len(), 1);
+    assert_eq!(
+        messages(all.clone().in_file(Path::new("PA.ipdl"))),
+        vec![diagnostics[0].message.clone(), String::from("no fix")]
+    );
+    assert!(all.clone().in_file(Path::new("PC.ipdl")).is_empty());
+    assert_eq!(all.clone().fixable().len(), 1);
+    assert_eq!(
+        all.fixes().map(|f| &f.message[..]).collect::<Vec<_>>(),
+        vec!["sort"]
+    );
+}
+
+#[test]
+fn mirrored_messages() {
+    let text = |attributes: &str| {
+        format!(
+            "using nsString from \"nsString.h\";\nusing mozilla::String from \"mozilla/String.h\";\n\
+             [ChildProc=any]\nprotocol PMirrored {{\nparent:\n  \
+             async UpdateToParent(nsString s, int n);\n  async Start();\nchild:\n  \
+             {} async UpdateToChild(String t, int m);\n  async Stop();\n  \
+             async NotifyChild(int n);\n}};\n",
+            attributes
+        )
+    };
+    let temp = TempDir::new("mirrored_messages");
+    let file = temp.write("PMirrored.ipdl", &text(""));
+    let include_dirs = vec![file.parent().unwrap().to_path_buf()];
+    let options = CheckOptions {
+        type_aliases: [("mozilla::String", "nsString")]
+            .iter()
+            .map(|(a, t)| (a.to_string(), t.to_string()))
+            .collect(),
+        ..CheckOptions::default()
+    };
+    let program =
+        compiler::check_with_options(&include_dirs, vec![file.clone()], &options).unwrap();
+    let warnings = program
+        .warnings
+        .iter()
+        .filter(|w| w.code == Some("mirrored_messages"))
+        .collect::<Vec<_>>();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(
+        warnings[0].message,
+        "message `UpdateToChild' is the same as `UpdateToParent' except for its direction; \
+         they could be one message in a `both:' section"
+    );
+    assert_eq!(warnings[0].notes[0].loc.lineno, 6);
+
+    fs::write(&file, text("[Allow=mirrored_messages]")).unwrap();
+    let program = compiler::check_with_options(&include_dirs, vec![file], &options).unwrap();
+    assert!(program
+        .warnings
+        .iter()
+        .all(|w| w.code != Some("mirrored_messages")));
+}
+
+#[test]
+fn ast_diff() {
+    let old_temp = TempDir::new("ast_diff_old");
+    let old = old_temp.write(
+        "PDiff.ipdl",
+        "using struct mozilla::Foo from \"Foo.h\";\nnamespace mozilla {\n\
+         struct S { int a; Foo f; };\nunion U { int; bool; };\n\
+         [ChildProc=any]\nprotocol PDiff {\nparent:\n  async A(int x);\n  \
+         async B();\n  sync C() returns (int r);\n};\n}\n",
+    );
+    let new_temp = TempDir::new("ast_diff_new");
+    let new = new_temp.write(
+        "PDiff.ipdl",
+        "// Only the comments and formatting of A changed.\n\
+         using class mozilla::Foo from \"Foo.h\";\nnamespace mozilla {\n\
+         struct S {\n  int a;\n  Foo f;\n  bool b;\n};\nunion U { int; Foo[]; };\n\
+         [ChildProc=any]\nprotocol PDiff {\nparent:\n  async A( int x );\n  \
+         [Compress] async B();\nchild:\n  async C(nsCString s) returns (int result);\n};\n}\n",
+    );
+    let parse = |file: &Path| {
+        let (tu, diagnostics) = parser::parse_partial(&[], file);
+        assert!(diagnostics.is_empty());
+        tu.unwrap()
+    };
+    let changes = ast_diff::diff_translation_units(&parse(&old), &parse(&new))
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        vec![
+            "~ using mozilla::Foo: the kind changed from `struct' to `class'",
+            "~ struct mozilla::S: field `bool b' added",
+            "~ union mozilla::U: component type `bool' removed",
+            "~ union mozilla::U: component type `Foo[]' added",
+            "~ message PDiff::B: the attributes changed from `' to `[Compress]'",
+            "~ message PDiff::C: the send semantics changed from `sync' to `async'",
+            "~ message PDiff::C: the direction changed from `parent' to `child'",
+            "~ message PDiff::C: parameter `nsCString s' added",
+            "~ message PDiff::C: the name of return value 1 changed from `r' to `result'",
+        ]
+    );
+    assert!(ast_diff::diff_translation_units(&parse(&new), &parse(&new)).is_empty());
+}
+
+#[test]
+fn deprecated_protocols() {
+    let top_text = |hold: &str| {
+        format!(
+            "include protocol POld;\ninclude protocol PNew;\n[ChildProc=any]\n\
+             protocol PTop {{\n  manages POld;\n  manages PNew;\nparent:\n  \
+             async POld();\n  async PNew();\n{}  [Deprecated=\"use New\"] async Old();\n}};\n",
+            hold
+        )
+    };
+    let temp = TempDir::new("deprecated_protocols");
+    let file = temp.write("PTop.ipdl", &top_text("  async Hold(POld actor);\n"));
+    let dir = file.parent().unwrap().to_path_buf();
+    for (name, attributes) in &[("POld", "[Deprecated=\"use PNew\"]\n"), ("PNew", "")] {
+        fs::write(
+            dir.join(format!("{}.ipdl", name)),
+            format!(
+                "include protocol PTop;\n{}protocol {} {{\n  manager PTop;\nchild:\n  \
+                 async __delete__();\n}};\n",
+                attributes, name
+            ),
+        )
+        .unwrap();
+    }
+    let program = compiler::check(&vec![dir.clone()], vec![file.clone()]).unwrap();
+    let warnings = program
+        .warnings
+        .iter()
+        .map(|w| (w.loc.as_ref().unwrap().lineno, w.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec![
+            (5, "protocol `POld' is deprecated: use PNew"),
+            (8, "protocol `POld' is deprecated: use PNew"),
+        ]
+    );
+
+    // Only the actor parameter is new since the old version.
+    let old_temp = TempDir::new("deprecated_protocols_old");
+    let old = old_temp.write("PTop.ipdl", &top_text(""));
+    let (old, diagnostics) = parser::parse_partial(&[dir], &old);
+    assert!(diagnostics.is_empty());
+    let tuid = program.tu_for_file(&file).unwrap();
+    let uses = ast_diff::new_deprecated_uses(&old.unwrap(), &program, &tuid)
+        .iter()
+        .map(|w| (w.loc.as_ref().unwrap().lineno, w.message.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        uses,
+        vec![(10, String::from("protocol `POld' is deprecated: use PNew"))]
+    );
+
+    let header = actors::actor_header(&program, &tuid, ProtocolSide::Child);
+    assert!(header.contains("  [[deprecated(\"use New\")]] [[nodiscard]] bool SendOld();\n"));
+}
+
+#[test]
+fn protocols_hosted_by() {
+    let temp = TempDir::new("protocols_hosted_by");
+    let gpu = temp.write(
+        "PGpu.ipdl",
+        "include protocol PGpuManaged;\n[ParentProc=Parent, ChildProc=GPU]\n\
+         protocol PGpu {\n  manages PGpuManaged;\nchild:\n  async PGpuManaged();\n};\n",
+    );
+    let dir = gpu.parent().unwrap().to_path_buf();
+    let managed = dir.join("PGpuManaged.ipdl");
+    fs::write(
+        &managed,
+        "include protocol PGpu;\nprotocol PGpuManaged {\n  manager PGpu;\n\
+         parent:\n  async __delete__();\n};\n",
+    )
+    .unwrap();
+    let content = dir.join("PContent.ipdl");
+    fs::write(
+        &content,
+        "[ParentProc=Parent, ChildProc=Content]\nprotocol PContent {\nchild:\n  async A();\n};\n",
+    )
+    .unwrap();
+    let anywhere = dir.join("PAnyChild.ipdl");
+    fs::write(
+        &anywhere,
+        "[ParentProc=compositor, ChildProc=anychild]\nprotocol PAnyChild {\nchild:\n  \
+         async A();\n};\n",
+    )
+    .unwrap();
+    let program = compiler::check(&vec![dir], vec![gpu, managed, content, anywhere]).unwrap();
+    let names = |process: &str| {
+        program
+            .protocols_hosted_by(process)
+            .iter()
+            .map(|tuid| program.protocol(tuid).qname.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("GPU"), vec!["::PAnyChild", "::PGpu", "::PGpuManaged"]);
+    assert_eq!(names("gpu"), names("GPU"));
+    assert_eq!(names("Content"), vec!["::PAnyChild", "::PContent"]);
+    assert_eq!(
+        names("Parent"),
+        vec!["::PAnyChild", "::PContent", "::PGpu", "::PGpuManaged"]
+    );
+    assert!(names("VR").contains(&String::from("::PAnyChild")));
+    assert_eq!(names("NotAProcess"), vec!["::PAnyChild"]);
+
+    let gpu = program.protocol_by_name("PGpu").unwrap();
+    assert_eq!(
+        program.protocol(&gpu).sides_in_process("GPU"),
+        vec![ProtocolSide::Child]
+    );
+}
+
+#[test]
+fn manager_graph() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let edges = managers::manager_edges(&program);
+    assert_eq!(edges.len(), 1);
+    assert_eq!(
+        program.protocol(&edges[0].manager).qname.short_name(),
+        "PMyManager"
+    );
+    assert_eq!(
+        program.protocol(&edges[0].managee).qname.short_name(),
+        "PMyManaged"
+    );
+    assert_eq!(
+        edges[0].constructors,
+        vec![managers::Constructor {
+            message: String::from("PMyManagedConstructor"),
+            send_semantics: SendSemantics::Async,
+            direction: Direction::To(ProtocolSide::Parent),
+        }]
+    );
+
+    let dot = managers::manager_graph_dot(&program);
+    assert!(dot.contains(
+        "  \"::mozilla::myns::PMyManager\" -> \"::mozilla::myns::PMyManaged\" \
+         [label=\"PMyManagedConstructor\"];\n"
+    ));
+    let json = export::manager_graph_json(&program);
+    assert!(json.contains(
+        "\"managers\": [], \"manages\": [{\"protocol\": \"::mozilla::myns::PMyManaged\", \
+         \"constructors\": [{\"message\": \"PMyManagedConstructor\", \"send_semantics\": \"async\", \
+         \"direction\": \"toParent\", \"senders\": [\"child\"]}]}]}"
+    ));
+    assert!(json.contains("\"managers\": [\"::mozilla::myns::PMyManager\"], \"manages\": []}"));
+}
+
+#[test]
+fn endpoint_graph() {
+    let program = check_ok_file("PEndpointUse.ipdl");
+    let edges = endpoints::endpoint_edges(&program);
+    let summary = edges
+        .iter()
+        .map(|e| {
+            format!(
+                "{}::{} -> {} {}, bound by {}",
+                program.protocol(&e.protocol).qname.short_name(),
+                e.message,
+                program.protocol(&e.opens).qname.short_name(),
+                endpoints::side_name(Some(e.side)),
+                endpoints::side_name(e.bound_by)
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            "PEndpointUse::Message -> PEndpointDecl parent, bound by child",
+            "PEndpointUse::Message -> PEndpointDecl child, bound by child",
+            "PEndpointDecl::Message -> PEndpointDecl parent, bound by child",
+            "PEndpointDecl::Message -> PEndpointDecl child, bound by child",
+        ]
+    );
+    assert!(edges.iter().all(|e| !e.managed));
+    let dot = endpoints::endpoint_graph_dot(&program);
+    assert!(dot.contains(
+        "  \"::PEndpointUse\" -> \"::mozilla::PEndpointDecl\" \
+         [label=\"Message (child opens parent)\"];\n"
+    ));
+    let json = export::export_json(&program, &[ok_dir().join("PEndpointUse.ipdl")]);
+    assert!(json.contains(
+        "\"opens\": [{\"message\": \"Message\", \"bound_by\": \"child\", \
+         \"protocol\": \"::mozilla::PEndpointDecl\", \"side\": \"parent\", \"managed\": false}, "
+    ));
+
+    // The GPU process can't bind the parent side of a protocol that is in
+    // the parent process.
+    let temp = TempDir::new("endpoint_graph");
+    let opener = temp.write(
+        "POpener.ipdl",
+        "include protocol POpened;\n[ParentProc=Parent, ChildProc=GPU]\n\
+         protocol POpener {\nchild:\n  async Open(Endpoint<POpenedParent> e);\n\
+         parent:\n  async Other() returns (Endpoint<POpenedChild> e);\n};\n",
+    );
+    let dir = opener.parent().unwrap().to_path_buf();
+    let opened = dir.join("POpened.ipdl");
+    let text = |child_process: &str| {
+        format!(
+            "[ParentProc=Parent, ChildProc={}]\nprotocol POpened {{\nchild:\n  \
+             async A();\n}};\n",
+            child_process
+        )
+    };
+    fs::write(&opened, text("GPU")).unwrap();
+    let endpoint_warnings = || {
+        let program = compiler::check(&vec![dir.clone()], vec![opener.clone()]).unwrap();
+        program
+            .warnings
+            .iter()
+            .filter(|w| w.code == Some("endpoint_process"))
+            .map(|w| w.message.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        endpoint_warnings(),
+        vec![
+            "endpoint `Endpoint<::POpenedParent>' is bound by the child side of `POpener', \
+             in a `GPU' process, but the parent side of `POpened' is in a `Parent' process"
+        ]
+    );
+    // The reply is bound by the child of POpener, which is in the GPU
+    // process, like the child of POpened.
+    fs::write(&opened, text("anychild")).unwrap();
+    assert_eq!(endpoint_warnings().len(), 1);
+    fs::write(&opened, text("Content")).unwrap();
+    assert_eq!(endpoint_warnings().len(), 2);
+}
+
+#[test]
+fn can_send() {
+    let program = check_ok_file("PMyManager.ipdl");
+    let manager = program.protocol_by_name("PMyManager").unwrap();
+    let managed = program.protocol_by_name("PMyManaged").unwrap();
+    let some_msg = program
+        .messages_of(&manager)
+        .iter()
+        .find(|m| m.name.id == "SomeMsg")
+        .unwrap()
+        .clone();
+    assert_eq!(program.can_send(&some_msg, &[manager]), Ok(()));
+    let (rejected_by, mismatch) = program
+        .can_send(&some_msg, &[managed, manager])
+        .unwrap_err();
+    assert_eq!(rejected_by, managed);
+    assert_eq!(
+        mismatch,
+        StrengthMismatch::SendSemantics {
+            message: SendSemantics::Sync,
+            protocol: SendSemantics::Async,
+        }
+    );
+    assert_eq!(
+        mismatch.to_string(),
+        "the message is sync, but the protocol is only async"
+    );
+    assert_eq!(
+        mismatch.describe("protocol", "manager"),
+        "the protocol is sync, but the manager is only async"
+    );
+    let nested = StrengthMismatch::NestedAbove {
+        message: Nesting::InsideSync,
+        protocol: Nesting::None,
+    };
+    assert_eq!(
+        nested.to_string(),
+        "the message is nested inside_sync, but the protocol is only [NestedUpTo=not]"
+    );
+    assert_eq!(
+        nested.describe("protocol", "manager"),
+        "the protocol is [NestedUpTo=inside_sync], but the manager is only [NestedUpTo=not]"
+    );
+
+    let mut nested = some_msg.clone();
+    nested.nested = Nesting::InsideSync;
+    assert_eq!(
+        program.can_send(&nested, &[manager]),
+        Err((
+            manager,
+            StrengthMismatch::NestedAbove {
+                message: Nesting::InsideSync,
+                protocol: Nesting::None,
+            }
+        ))
+    );
+    nested.prio = Priority::Input;
+    assert_eq!(
+        program.can_send(&nested, &[manager]),
+        Err((manager, StrengthMismatch::NestedPriority(Priority::Input)))
+    );
+    assert!(program
+        .protocol(&manager)
+        .message_strength()
+        .converts_to(&program.protocol(&manager).message_strength()));
+}
+
+#[test]
+fn track_references() {
+    let file = ok_dir().join("PMyManager.ipdl");
+    let options = CheckOptions {
+        track_references: true,
+        ..CheckOptions::default()
+    };
+    let program =
+        compiler::check_with_options(&vec![ok_dir()], vec![file.clone()], &options).unwrap();
+    let tuid = program.tu_for_file(&file).unwrap();
+    let symbol = |name: &str| {
+        program.tuts[&tuid]
+            .symbols
+            .iter()
+            .find(|s| s.name == name)
+            .unwrap()
+    };
+    // In the union MyUnion and in the reply of AnotherMsg.
+    let other_data = symbol("MyOtherData");
+    let lines = other_data
+        .references
+        .iter()
+        .map(|r| r.lineno)
+        .collect::<Vec<_>>();
+    assert_eq!(lines, vec![14, 27]);
+    assert_eq!(
+        symbol("::mozilla::myns::MyOtherData").references,
+        other_data.references
+    );
+    assert!(symbol("MyThirdData").references.is_empty());
+
+    // The manager of PMyManaged is used in its own file.
+    let manager = symbol("PMyManager").loc.clone();
+    let references = program.references_to(&manager);
+    assert_eq!(references.len(), 1);
+    assert!(references[0].file_name.ends_with("PMyManaged.ipdl"));
+
+    let program = check_ok_file("PMyManager.ipdl");
+    assert!(program.references_to(&manager).is_empty());
+}
+
+#[test]
+fn not_included() {
+    let temp = TempDir::new("not_included");
+    let types = temp.write("ClientTypes.ipdlh", "struct ClientInfo { int id; };\n");
+    let dir = types.parent().unwrap().to_path_buf();
+    fs::write(dir.join("Other.ipdlh"), "struct Other { int x; };\n").unwrap();
+    let file = dir.join("PFoo.ipdl");
+    fs::write(
+        &file,
+        "include Other;\n\n[ChildProc=any]\nprotocol PFoo {\nchild:\n  async Msg(ClientInfo c, Other o);\n};\n",
+    )
+    .unwrap();
+
+    let include_dirs = vec![dir.clone()];
+    let mut sources = SourceManager::new();
+    let e = compiler::check_with_sources(
+        &include_dirs,
+        vec![file.clone(), types.clone()],
+        &CheckOptions::default(),
+        &mut sources,
+    )
+    .err()
+    .unwrap();
+    assert_eq!(e.diagnostics().len(), 1);
+    let d = &e.diagnostics()[0];
+    assert_eq!(d.code, Some("not_included"));
+    assert_eq!(
+        d.message,
+        format!(
+            "type `ClientInfo' is declared in `{}', which isn't included here; add \
+             `include ClientTypes;'",
+            types.canonicalize().unwrap().display()
+        )
+    );
+    assert_eq!(d.notes[0].loc.lineno, 1);
+    let fixed = d
+        .fix
+        .as_ref()
+        .unwrap()
+        .apply(sources.text(&file.canonicalize().unwrap()).unwrap());
+    assert!(fixed
+        .starts_with("include Other;\ninclude ClientTypes;\n\n[ChildProc=any]\nprotocol PFoo {"));
+
+    // Without the header among the files loaded, it is an unknown type.
+    let e = compiler::check(&include_dirs, vec![file.clone()])
+        .err()
+        .unwrap();
+    assert_eq!(e.diagnostics()[0].code, Some("unknown_type"));
+
+    // A session that checks again after the header starts declaring the
+    // type suggests the include, even though PFoo, whose includes haven't
+    // changed, comes from the gather cache.
+    fs::write(&types, "struct ClientName { int id; };\n").unwrap();
+    let mut session = compiler::Session::new(include_dirs, CheckOptions::default());
+    let files = vec![file, types.clone()];
+    assert!(session.check(files.clone()).is_err());
+    assert_eq!(session.diagnostics()[0].code, Some("unknown_type"));
+    fs::write(&types, "struct ClientInfo { int id; };\n").unwrap();
+    session.reset();
+    assert!(session.check(files).is_err());
+    assert!(session.cache().hits() > 0);
+    assert_eq!(session.diagnostics()[0].code, Some("not_included"));
+}
@@ -0,0 +1,12 @@
+#ifndef IPCMessageStart_h
+#define IPCMessageStart_h
+
+enum IPCMessageStart {
+  PStructMsgStart,
+  Punion_BasicMsgStart,
+  LastMsgIndex
+};
+
+static_assert(LastMsgIndex <= 65536, "need to update IPC_MESSAGE_MACRO");
+
+#endif  // IPCMessageStart_h
@@ -0,0 +1,34 @@
+#include "PStruct.h"
+#include "Punion_Basic.h"
+
+const char* StringFromIPCMessageType(uint32_t aMessageType) {
+  switch (aMessageType) {
+    case ::PStruct::Msg_test__ID:
+      return "PStruct::Msg_test";
+    case ::PStruct::Reply_test__ID:
+      return "PStruct::Reply_test";
+    case ::PStruct::Msg___delete____ID:
+      return "PStruct::Msg___delete__";
+    case ::Punion_Basic::Msg_Msg__ID:
+      return "Punion_Basic::Msg_Msg";
+    case ::Punion_Basic::Reply_Msg__ID:
+      return "Punion_Basic::Reply_Msg";
+    default:
+      return "<unknown IPC msg name>";
+  }
+}
+
+bool IPCMessageTypeIsLoggable(uint32_t aMessageType) {
+  return true;
+}
+
+const char* ProtocolIdToName(IPCMessageStart aId) {
+  switch (aId) {
+    case PStructMsgStart:
+      return "PStruct";
+    case Punion_BasicMsgStart:
+      return "Punion_Basic";
+    default:
+      return "<unknown protocol id>";
+  }
+}
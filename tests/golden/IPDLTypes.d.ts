@@ -0,0 +1,43 @@
+// Types shared by the generated IPDL declarations.
+
+// A reference to an actor of protocol P. Actors are identified by their
+// routing id, which is unique for the channel.
+export interface ActorRef<P extends string> {
+  protocol: P;
+  id: number;
+}
+
+// A shared memory segment. A harness doesn't have to share the memory, as
+// long as both sides agree on the contents.
+export interface Shmem {
+  kind: "Shmem";
+  id: number;
+  size: number;
+}
+
+// A buffer of bytes that is moved rather than copied.
+export interface ByteBuf {
+  kind: "ByteBuf";
+  data: Uint8Array;
+}
+
+// A file descriptor or handle. Harnesses can use any number that they can
+// map back to a file.
+export interface FileDescriptor {
+  kind: "FileDescriptor";
+  fd: number;
+}
+
+// One end of a new top level actor of protocol P, for that side.
+export interface Endpoint<P extends string> {
+  kind: "Endpoint";
+  protocol: P;
+  side: "Parent" | "Child";
+}
+
+// One end of a new actor of protocol P that is managed by another actor.
+export interface ManagedEndpoint<P extends string> {
+  kind: "ManagedEndpoint";
+  protocol: P;
+  side: "Parent" | "Child";
+}
@@ -0,0 +1,29 @@
+// Generated from IPDL. Do not edit.
+
+// from PStruct.ipdl:1
+export interface S {
+  i: number;
+  d: number;
+}
+
+export namespace PStruct {
+  // from PStruct.ipdl:9
+  export interface test {
+    name: "test";
+    direction: "toParent";
+    params: { s: S; };
+    returns: { ss: S; };
+  }
+
+  // from PStruct.ipdl:10
+  export interface __delete__ {
+    name: "__delete__";
+    direction: "toParent";
+    params: {};
+    returns: {};
+  }
+
+  export type Message = test | __delete__;
+  export type ToParent = test | __delete__;
+  export type ToChild = never;
+}
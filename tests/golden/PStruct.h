@@ -0,0 +1,19 @@
+#ifndef PStruct_h
+#define PStruct_h
+
+#include "IPCMessageStart.h"
+
+namespace PStruct {
+
+// from PStruct.ipdl:7
+enum MessageType {
+  PStructStart = PStructMsgStart << 16,
+  Msg_test__ID,
+  Reply_test__ID,
+  Msg___delete____ID,
+  PStructEnd
+};
+
+}  // namespace PStruct
+
+#endif  // PStruct_h
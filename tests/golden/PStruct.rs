@@ -0,0 +1,29 @@
+// Generated from IPDL. Do not edit.
+
+// from PStruct.ipdl:1
+pub struct S {
+    pub i: i32,
+    pub d: f64,
+}
+
+// The messages that PStructParent receives.
+pub enum PStructParentMessage {
+    // from PStruct.ipdl:9
+    Test { s: S },
+    // from PStruct.ipdl:10
+    Delete,
+}
+
+pub trait PStructParentHandler {
+    // from PStruct.ipdl:9
+    fn recv_test(&mut self, s: S) -> Result<S, ipc::Error>;
+    // from PStruct.ipdl:10
+    fn recv_delete(&mut self) -> Result<(), ipc::Error>;
+}
+
+// The messages that PStructChild receives.
+pub enum PStructChildMessage {
+}
+
+pub trait PStructChildHandler {
+}
@@ -0,0 +1,20 @@
+#ifndef PStructChild_h
+#define PStructChild_h
+
+#include "mozilla/ipc/ProtocolUtils.h"
+#include "PStruct.h"
+
+
+class PStructChild : public mozilla::ipc::IToplevelProtocol {
+ public:
+  NS_INLINE_DECL_PURE_VIRTUAL_REFCOUNTING
+  // from PStruct.ipdl:9
+  [[nodiscard]] bool Sendtest(const ::S& s, ::S* ss);
+  // from PStruct.ipdl:10
+  [[nodiscard]] static bool Send__delete__(::PStructChild* aActor);
+
+ protected:
+};
+
+
+#endif  // PStructChild_h
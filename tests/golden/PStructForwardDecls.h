@@ -0,0 +1,7 @@
+#ifndef PStructForwardDecls_h
+#define PStructForwardDecls_h
+
+class PStructChild;
+class PStructParent;
+
+#endif  // PStructForwardDecls_h
@@ -0,0 +1,34 @@
+namespace IPC {
+
+template <>
+struct ParamTraits<::S> {
+  typedef ::S paramType;
+  static void Write(IPC::MessageWriter* aWriter, const paramType& aVar);
+  static IPC::ReadResult<paramType> Read(IPC::MessageReader* aReader);
+};
+
+// from PStruct.ipdl:1
+void ParamTraits<::S>::Write(IPC::MessageWriter* aWriter, const paramType& aVar) {
+  IPC::WriteParam(aWriter, (aVar).i());
+  IPC::WriteParam(aWriter, (aVar).d());
+}
+
+// from PStruct.ipdl:1
+IPC::ReadResult<paramType> ParamTraits<::S>::Read(IPC::MessageReader* aReader) {
+  auto maybe___i = IPC::ReadParam<int>(aReader);
+  if (!maybe___i) {
+    aReader->FatalError("Error deserializing 'i' (int) member of 'S'");
+    return {};
+  }
+  auto& _i = *maybe___i;
+  auto maybe___d = IPC::ReadParam<double>(aReader);
+  if (!maybe___d) {
+    aReader->FatalError("Error deserializing 'd' (double) member of 'S'");
+    return {};
+  }
+  auto& _d = *maybe___d;
+  IPC::ReadResult<paramType> result__{std::in_place, std::move(_i), std::move(_d)};
+  return result__;
+}
+
+}  // namespace IPC
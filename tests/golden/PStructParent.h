@@ -0,0 +1,20 @@
+#ifndef PStructParent_h
+#define PStructParent_h
+
+#include "mozilla/ipc/ProtocolUtils.h"
+#include "PStruct.h"
+
+
+class PStructParent : public mozilla::ipc::IToplevelProtocol {
+ public:
+  NS_INLINE_DECL_PURE_VIRTUAL_REFCOUNTING
+
+ protected:
+  // from PStruct.ipdl:9
+  virtual mozilla::ipc::IPCResult Recvtest(const ::S& s, ::S* ss) = 0;
+  // from PStruct.ipdl:10
+  virtual mozilla::ipc::IPCResult Recv__delete__() { return IPC_OK(); }
+};
+
+
+#endif  // PStructParent_h
@@ -0,0 +1,20 @@
+// Generated from IPDL. Do not edit.
+
+// from Punion_Basic.ipdl:1
+export type Basic =
+  | { type: "Tint"; value: number }
+  | { type: "Tdouble"; value: number };
+
+export namespace Punion_Basic {
+  // from Punion_Basic.ipdl:10
+  export interface Msg {
+    name: "Msg";
+    direction: "toParent";
+    params: { p: Basic; };
+    returns: { r: Basic; };
+  }
+
+  export type Message = Msg;
+  export type ToParent = Msg;
+  export type ToChild = never;
+}
@@ -0,0 +1,18 @@
+#ifndef Punion_Basic_h
+#define Punion_Basic_h
+
+#include "IPCMessageStart.h"
+
+namespace Punion_Basic {
+
+// from Punion_Basic.ipdl:7
+enum MessageType {
+  Punion_BasicStart = Punion_BasicMsgStart << 16,
+  Msg_Msg__ID,
+  Reply_Msg__ID,
+  Punion_BasicEnd
+};
+
+}  // namespace Punion_Basic
+
+#endif  // Punion_Basic_h
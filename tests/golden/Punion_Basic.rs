@@ -0,0 +1,25 @@
+// Generated from IPDL. Do not edit.
+
+// from Punion_Basic.ipdl:1
+pub enum Basic {
+    Int(i32),
+    Double(f64),
+}
+
+// The messages that Punion_BasicParent receives.
+pub enum Punion_BasicParentMessage {
+    // from Punion_Basic.ipdl:10
+    Msg { p: Basic },
+}
+
+pub trait Punion_BasicParentHandler {
+    // from Punion_Basic.ipdl:10
+    fn recv_msg(&mut self, p: Basic) -> Result<Basic, ipc::Error>;
+}
+
+// The messages that Punion_BasicChild receives.
+pub enum Punion_BasicChildMessage {
+}
+
+pub trait Punion_BasicChildHandler {
+}
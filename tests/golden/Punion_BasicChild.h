@@ -0,0 +1,18 @@
+#ifndef Punion_BasicChild_h
+#define Punion_BasicChild_h
+
+#include "mozilla/ipc/ProtocolUtils.h"
+#include "Punion_Basic.h"
+
+
+class Punion_BasicChild : public mozilla::ipc::IToplevelProtocol {
+ public:
+  NS_INLINE_DECL_PURE_VIRTUAL_REFCOUNTING
+  // from Punion_Basic.ipdl:10
+  [[nodiscard]] bool SendMsg(const ::Basic& p, ::Basic* r);
+
+ protected:
+};
+
+
+#endif  // Punion_BasicChild_h
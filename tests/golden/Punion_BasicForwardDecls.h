@@ -0,0 +1,7 @@
+#ifndef Punion_BasicForwardDecls_h
+#define Punion_BasicForwardDecls_h
+
+class Punion_BasicChild;
+class Punion_BasicParent;
+
+#endif  // Punion_BasicForwardDecls_h
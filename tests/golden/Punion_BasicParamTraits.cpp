@@ -0,0 +1,66 @@
+namespace IPC {
+
+template <>
+struct ParamTraits<::Basic> {
+  typedef ::Basic paramType;
+  static void Write(IPC::MessageWriter* aWriter, const paramType& aVar);
+  static IPC::ReadResult<paramType> Read(IPC::MessageReader* aReader);
+};
+
+// from Punion_Basic.ipdl:1
+void ParamTraits<::Basic>::Write(IPC::MessageWriter* aWriter, const paramType& aVar) {
+  typedef ::Basic union__;
+  int type = (aVar).type();
+  IPC::WriteParam(aWriter, type);
+  switch (type) {
+    case union__::Tint: {
+      IPC::WriteParam(aWriter, (aVar).get_int());
+      return;
+    }
+    case union__::Tdouble: {
+      IPC::WriteParam(aWriter, (aVar).get_double());
+      return;
+    }
+    default: {
+      aWriter->FatalError("unknown variant of union Basic");
+      return;
+    }
+  }
+}
+
+// from Punion_Basic.ipdl:1
+IPC::ReadResult<paramType> ParamTraits<::Basic>::Read(IPC::MessageReader* aReader) {
+  typedef ::Basic union__;
+  auto maybe__type = IPC::ReadParam<int>(aReader);
+  if (!maybe__type) {
+    aReader->FatalError("Error deserializing type of union Basic");
+    return {};
+  }
+  auto& type = *maybe__type;
+  switch (type) {
+    case union__::Tint: {
+      auto maybe__tmp = IPC::ReadParam<int>(aReader);
+      if (!maybe__tmp) {
+        aReader->FatalError("Error deserializing variant Tint of union Basic");
+        return {};
+      }
+      auto& tmp = *maybe__tmp;
+      return std::move(tmp);
+    }
+    case union__::Tdouble: {
+      auto maybe__tmp = IPC::ReadParam<double>(aReader);
+      if (!maybe__tmp) {
+        aReader->FatalError("Error deserializing variant Tdouble of union Basic");
+        return {};
+      }
+      auto& tmp = *maybe__tmp;
+      return std::move(tmp);
+    }
+    default: {
+      aReader->FatalError("unknown variant of union Basic");
+      return {};
+    }
+  }
+}
+
+}  // namespace IPC
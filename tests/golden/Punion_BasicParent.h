@@ -0,0 +1,18 @@
+#ifndef Punion_BasicParent_h
+#define Punion_BasicParent_h
+
+#include "mozilla/ipc/ProtocolUtils.h"
+#include "Punion_Basic.h"
+
+
+class Punion_BasicParent : public mozilla::ipc::IToplevelProtocol {
+ public:
+  NS_INLINE_DECL_PURE_VIRTUAL_REFCOUNTING
+
+ protected:
+  // from Punion_Basic.ipdl:10
+  virtual mozilla::ipc::IPCResult RecvMsg(const ::Basic& p, ::Basic* r) = 0;
+};
+
+
+#endif  // Punion_BasicParent_h
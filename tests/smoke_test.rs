@@ -1,5 +1,7 @@
 extern crate ipdl_parser;
 
+use ipdl_parser::errors::IpdlError;
+use ipdl_parser::ir::TypedProgram;
 use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::fs;
@@ -28,20 +30,59 @@ const WRONG_ERROR_TESTS: &'static [&'static str] = &[
     "PDouble.ipdl",
 ];
 
-fn file_expected_error(file_name: &PathBuf) -> Vec<String> {
-    let mut errors = Vec::new();
+// The lines of a test file that start with one of |prefixes|, without the
+// leading `//', so that they can be looked for in the compiler's output.
+fn file_expectations(file_name: &PathBuf, prefixes: &[&str]) -> Vec<String> {
+    let mut expectations = Vec::new();
     let f = File::open(file_name).unwrap();
 
     for line in BufReader::new(f).lines() {
-        if line.as_ref().unwrap().starts_with("//error:") {
-            errors.push(line.unwrap().split_off(2));
+        let line = line.unwrap();
+        if prefixes.iter().any(|p| line.starts_with(p)) {
+            expectations.push(String::from(&line[2..]));
         }
     }
+    expectations
+}
+
+fn file_expected_error(file_name: &PathBuf) -> Vec<String> {
+    let errors = file_expectations(file_name, &["//error:"]);
     assert!(
         errors.len() > 0,
         "Test file should contain expected errors."
     );
-    errors
+    file_expectations(file_name, &["//error:", "//note:"])
+}
+
+// A file in ok/ can list the warnings it is expected to produce, with
+// `//warning:' lines and `//note:' lines for their notes. If it lists any,
+// it must produce exactly those warnings.
+fn check_expected_warnings(file_name: &PathBuf, program: &TypedProgram) {
+    let expected = file_expectations(file_name, &["//warning:"]);
+    if expected.is_empty() {
+        return;
+    }
+    let actual = program
+        .warnings
+        .iter()
+        .map(|w| w.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert_eq!(
+        program.warnings.len(),
+        expected.len(),
+        "Expected {} warnings in \"{}\"",
+        expected.len(),
+        actual
+    );
+    for expected_warning in file_expectations(file_name, &["//warning:", "//note:"]) {
+        assert!(
+            actual.find(&expected_warning).is_some(),
+            "Expected \"{}\" in \"{}\"",
+            expected_warning,
+            actual
+        );
+    }
 }
 
 // XXX This does not run efficiently. If A includes B, then we end up
@@ -91,9 +132,13 @@ fn test_files(test_file_path: &str, should_pass: bool) {
             }
 
             let file_name = vec![entry.path()];
-            match ipdl_parser::compiler::compile(&include_dirs, file_name) {
-                Ok(()) => assert!(expected_result, "Expected test to fail, but it passed"),
-                Err(actual_error) => {
+            match ipdl_parser::compiler::check(&include_dirs, file_name) {
+                Ok(program) => {
+                    assert!(expected_result, "Expected test to fail, but it passed");
+                    check_expected_warnings(&entry.path(), &program);
+                }
+                Err(e) => {
+                    let actual_error = e.to_string();
                     assert!(
                         !expected_result,
                         "Expected test to pass, but it failed with \"{}\"",
@@ -134,11 +179,60 @@ fn test_files(test_file_path: &str, should_pass: bool) {
     }
 }
 
+fn compile_error(test_file_path: &str, file_name: &str) -> IpdlError {
+    let mut path: PathBuf = BASE_PATH.iter().collect();
+    path.push(test_file_path);
+    let include_dirs = vec![path.clone()];
+    path.push(file_name);
+    ipdl_parser::compiler::compile(&include_dirs, vec![path]).expect_err("Expected test to fail")
+}
+
+#[test]
+fn error_kinds() {
+    match compile_error(ERROR_PATH, "badProtocolInclude.ipdl") {
+        IpdlError::Io(_) => (),
+        e => panic!("Expected an I/O error, got {:?}", e),
+    }
+    match compile_error(ERROR_PATH, "IDONTEXIST.ipdl") {
+        IpdlError::Io(_) => (),
+        e => panic!("Expected an I/O error, got {:?}", e),
+    }
+    match compile_error(ERROR_PATH, "lex1.ipdl") {
+        IpdlError::Parse(_) => (),
+        e => panic!("Expected a parse error, got {:?}", e),
+    }
+    match compile_error(ERROR_PATH, "structRedecl.ipdl") {
+        IpdlError::TypeCheck(_) => (),
+        e => panic!("Expected a type error, got {:?}", e),
+    }
+}
+
 #[test]
 fn ok_tests() {
     test_files(OK_PATH, true);
 }
 
+// The files in ok/ are also fine when they are all checked in one run, so
+// the checks across translation units don't mix up unrelated files that
+// happen to use the same names.
+#[test]
+fn ok_tests_together() {
+    let mut path: PathBuf = BASE_PATH.iter().collect();
+    path.push(OK_PATH);
+    let mut file_names = fs::read_dir(&path)
+        .expect("Should have the test file directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|p| !p.is_dir())
+        .collect::<Vec<_>>();
+    file_names.sort();
+    if let Err(e) = ipdl_parser::compiler::check(&vec![path], file_names) {
+        panic!(
+            "Expected the tests to pass together, but they failed with \"{}\"",
+            e
+        );
+    }
+}
+
 #[test]
 fn error_tests() {
     test_files(ERROR_PATH, false);